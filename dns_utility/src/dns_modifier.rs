@@ -1,10 +1,13 @@
 // Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
 
 use std::io;
+use std::net::IpAddr;
 
 pub trait DnsModifier {
     fn type_name(&self) -> &'static str;
     fn subvert(&self) -> Result<(), String>;
     fn revert(&self) -> Result<(), String>;
     fn inspect(&self, stdout: &mut (dyn io::Write + Send)) -> Result<(), String>;
+    // The DNS servers the system was using before it was subverted, or is using now if it isn't.
+    fn original_nameservers(&self) -> Result<Vec<IpAddr>, String>;
 }
@@ -116,12 +116,15 @@ mod tests {
     use crate::fake_stream_holder::FakeStreamHolder;
     use std::cell::RefCell;
     use std::io;
+    use std::net::IpAddr;
+    use std::str::FromStr;
 
     pub struct DnsModifierMock {
         subvert_results: RefCell<Vec<Result<(), String>>>,
         revert_results: RefCell<Vec<Result<(), String>>>,
         inspect_to_stdout: RefCell<Vec<String>>,
         inspect_results: RefCell<Vec<Result<(), String>>>,
+        original_nameservers_results: RefCell<Vec<Result<Vec<IpAddr>, String>>>,
     }
 
     impl DnsModifier for DnsModifierMock {
@@ -141,6 +144,10 @@ mod tests {
             write!(stdout, "{}", self.inspect_to_stdout.borrow_mut().remove(0)).unwrap();
             self.inspect_results.borrow_mut().remove(0)
         }
+
+        fn original_nameservers(&self) -> Result<Vec<IpAddr>, String> {
+            self.original_nameservers_results.borrow_mut().remove(0)
+        }
     }
 
     impl DnsModifierMock {
@@ -150,6 +157,7 @@ mod tests {
                 revert_results: RefCell::new(vec![]),
                 inspect_to_stdout: RefCell::new(vec![]),
                 inspect_results: RefCell::new(vec![]),
+                original_nameservers_results: RefCell::new(vec![]),
             }
         }
 
@@ -172,6 +180,14 @@ mod tests {
             self.inspect_results.borrow_mut().push(result);
             self
         }
+
+        pub fn original_nameservers_result(
+            self,
+            result: Result<Vec<IpAddr>, String>,
+        ) -> DnsModifierMock {
+            self.original_nameservers_results.borrow_mut().push(result);
+            self
+        }
     }
 
     #[derive(Default)]
@@ -437,4 +453,20 @@ mod tests {
         assert_eq!(holder.stderr.get_string(), String::new());
         assert_eq!(holder.stdout.get_string(), String::from("reverted\n"));
     }
+
+    #[test]
+    fn dns_modifier_mock_gives_back_original_nameservers_in_order() {
+        let subject = DnsModifierMock::new()
+            .original_nameservers_result(Ok(vec![IpAddr::from_str("192.168.0.1").unwrap()]))
+            .original_nameservers_result(Err("No DNS servers".to_string()));
+
+        assert_eq!(
+            subject.original_nameservers(),
+            Ok(vec![IpAddr::from_str("192.168.0.1").unwrap()])
+        );
+        assert_eq!(
+            subject.original_nameservers(),
+            Err("No DNS servers".to_string())
+        );
+    }
 }
@@ -5,6 +5,8 @@ use libc;
 use regex::Regex;
 use std::collections::HashMap;
 use std::iter::FromIterator;
+use std::net::IpAddr;
+use std::str::FromStr;
 
 use core_foundation::array::CFArray;
 use core_foundation::base::FromVoid;
@@ -59,6 +61,21 @@ impl DnsModifier for DynamicStoreDnsModifier {
         writeln!(stdout, "{}", output).expect("write is broken");
         Ok(())
     }
+
+    fn original_nameservers(&self) -> Result<Vec<IpAddr>, String> {
+        let (_, dns_info) = self.get_dns_info(false)?;
+        let addresses = match dns_info
+            .get(SERVER_ADDRESSES_BAK)
+            .or_else(|| dns_info.get(SERVER_ADDRESSES))
+        {
+            None => return Err(String::from("This system has no DNS settings")),
+            Some(sa) => sa,
+        };
+        Ok(addresses
+            .iter()
+            .flat_map(|address| IpAddr::from_str(address))
+            .collect())
+    }
 }
 
 impl Default for DynamicStoreDnsModifier {
@@ -10,9 +10,11 @@ use std::io::Read;
 use std::io::Seek;
 use std::io::SeekFrom;
 use std::io::Write;
+use std::net::IpAddr;
 use std::ops::Add;
 use std::path::Path;
 use std::path::PathBuf;
+use std::str::FromStr;
 
 pub struct ResolvConfDnsModifier {
     root: PathBuf,
@@ -42,6 +44,11 @@ impl DnsModifier for ResolvConfDnsModifier {
         let (_, contents) = self.open_resolv_conf(false)?;
         self.inspect_contents(contents, stdout)
     }
+
+    fn original_nameservers(&self) -> Result<Vec<IpAddr>, String> {
+        let (_, contents) = self.open_resolv_conf(false)?;
+        self.original_nameservers_contents(&contents[..])
+    }
 }
 
 impl Default for ResolvConfDnsModifier {
@@ -160,6 +167,34 @@ impl ResolvConfDnsModifier {
         Ok(())
     }
 
+    // While DNS is subverted, the original nameservers are the ones subvert() commented out.
+    fn original_nameservers_contents(&self, contents: &str) -> Result<Vec<IpAddr>, String> {
+        let nameservers = match self.find_substratum_nameserver(contents)? {
+            Some(_) => self
+                .existing_nameservers(contents)
+                .into_iter()
+                .filter(|(nameserver, _)| nameserver.starts_with('#'))
+                .map(|(nameserver, _)| nameserver[1..].to_string())
+                .collect(),
+            None => self
+                .active_nameservers(contents)
+                .into_iter()
+                .map(|(nameserver, _)| nameserver)
+                .collect::<Vec<String>>(),
+        };
+        let ip_addrs: Vec<IpAddr> = nameservers
+            .into_iter()
+            .flat_map(|nameserver| IpAddr::from_str(&self.nameserver_line_to_ip(nameserver)))
+            .collect();
+        if ip_addrs.is_empty() {
+            Err(String::from(
+                "There do not appear to be any original DNS settings",
+            ))
+        } else {
+            Ok(ip_addrs)
+        }
+    }
+
     pub fn nameserver_line_to_ip(&self, nameserver_line: String) -> String {
         let regex = Regex::new(r"^\s*nameserver\s+([^\s#]*)").expect("Regex syntax error");
         let captures = regex
@@ -839,6 +874,62 @@ mod tests {
         assert_eq!(result.is_ok(), true);
     }
 
+    #[test]
+    fn original_nameservers_are_the_active_ones_if_dns_is_not_subverted() {
+        let root = make_root("original_nameservers_are_the_active_ones_if_dns_is_not_subverted");
+        make_resolv_conf(
+            &root,
+            "#comment\n#nameserver 1.1.1.1\nnameserver 8.8.8.8\nnameserver 2001:4860:4860::8888\n",
+        );
+        let mut subject = ResolvConfDnsModifier::new();
+        subject.root = root;
+
+        let result = subject.original_nameservers();
+
+        assert_eq!(
+            result,
+            Ok(vec![
+                IpAddr::from_str("8.8.8.8").unwrap(),
+                IpAddr::from_str("2001:4860:4860::8888").unwrap(),
+            ])
+        );
+    }
+
+    #[test]
+    fn original_nameservers_are_the_commented_ones_if_dns_is_subverted() {
+        let root = make_root("original_nameservers_are_the_commented_ones_if_dns_is_subverted");
+        make_resolv_conf (&root, "#comment\n## nameserver 1.1.1.1\n#nameserver 8.8.8.8\n#nameserver 9.9.9.9\nnameserver 127.0.0.1\n");
+        let mut subject = ResolvConfDnsModifier::new();
+        subject.root = root;
+
+        let result = subject.original_nameservers();
+
+        assert_eq!(
+            result,
+            Ok(vec![
+                IpAddr::from_str("8.8.8.8").unwrap(),
+                IpAddr::from_str("9.9.9.9").unwrap(),
+            ])
+        );
+    }
+
+    #[test]
+    fn original_nameservers_complains_if_there_are_none() {
+        let root = make_root("original_nameservers_complains_if_there_are_none");
+        make_resolv_conf(&root, "nameserver 127.0.0.1\n## nameserver 8.8.8.8\n");
+        let mut subject = ResolvConfDnsModifier::new();
+        subject.root = root;
+
+        let result = subject.original_nameservers();
+
+        assert_eq!(
+            result,
+            Err(String::from(
+                "There do not appear to be any original DNS settings"
+            ))
+        );
+    }
+
     fn make_root(test_name: &str) -> PathBuf {
         let cur_dir = env::current_dir().unwrap();
         let generated_dir = cur_dir.join(Path::new("generated"));
@@ -6,6 +6,8 @@ use crate::netsh::{Netsh, NetshCommand, NetshError};
 use std::collections::HashSet;
 use std::fmt::Debug;
 use std::io;
+use std::net::IpAddr;
+use std::str::FromStr;
 use winreg::enums::*;
 use winreg::RegKey;
 
@@ -90,6 +92,22 @@ impl DnsModifier for WinDnsModifier {
         write!(stdout, "{}", output).expect("write is broken");
         Ok(())
     }
+
+    fn original_nameservers(&self) -> Result<Vec<IpAddr>, String> {
+        let interfaces = self.find_interfaces_to_inspect()?;
+        let backup_opt = interfaces
+            .iter()
+            .flat_map(|interface| interface.get_value("NameServerBak"))
+            .find(|backup| !backup.is_empty());
+        let dns_server_list_csv = match backup_opt {
+            Some(backup) => backup,
+            None => self.find_dns_server_list(interfaces)?,
+        };
+        Ok(dns_server_list_csv
+            .split(',')
+            .flat_map(|dns_server| IpAddr::from_str(dns_server.trim()))
+            .collect())
+    }
 }
 
 impl Default for WinDnsModifier {
@@ -1742,6 +1760,34 @@ mod tests {
         );
     }
 
+    #[test]
+    fn original_nameservers_come_from_the_backup_if_dns_is_subverted() {
+        let subverted_interface = RegKeyMock::default()
+            .get_value_result("DefaultGateway", Ok("Common Gateway IP".to_string()))
+            .get_value_result(
+                "DhcpDefaultGateway",
+                Err(Error::from_raw_os_error(NOT_FOUND)),
+            )
+            .get_value_result("NameServer", Ok("127.0.0.1".to_string()))
+            .get_value_result("NameServerBak", Ok("8.8.8.8,8.8.8.9".to_string()));
+        let interfaces = RegKeyMock::default()
+            .enum_keys_result(vec![Ok("subverted_interface")])
+            .open_subkey_with_flags_result(Ok(Box::new(subverted_interface)));
+        let hive = RegKeyMock::default().open_subkey_with_flags_result(Ok(Box::new(interfaces)));
+        let mut subject = WinDnsModifier::default();
+        subject.hive = Box::new(hive);
+
+        let result = subject.original_nameservers();
+
+        assert_eq!(
+            result,
+            Ok(vec![
+                IpAddr::from_str("8.8.8.8").unwrap(),
+                IpAddr::from_str("8.8.8.9").unwrap(),
+            ])
+        );
+    }
+
     fn build_adapter_stubs(
         names: &[(&str, &str)],
     ) -> Result<Vec<Box<dyn AdapterWrapper>>, ipconfig::error::Error> {
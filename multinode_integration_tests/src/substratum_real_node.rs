@@ -173,6 +173,9 @@ impl NodeStartupConfig {
         args.push("trace".to_string());
         args.push("--data-directory".to_string());
         args.push(DATA_DIRECTORY.to_string());
+        // Test servers live on the private Docker network, which the default exit policy refuses.
+        args.push("--exit-deny".to_string());
+        args.push("none".to_string());
         if let EarningWalletInfo::Address(ref address) = self.earning_wallet_info {
             args.push("--earning-wallet".to_string());
             args.push(address.to_string());
@@ -1319,6 +1322,8 @@ mod tests {
                 "trace",
                 "--data-directory",
                 DATA_DIRECTORY,
+                "--exit-deny",
+                "none",
                 "--consuming-private-key",
                 "CCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCCC"
             ))
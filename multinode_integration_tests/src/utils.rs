@@ -4,6 +4,7 @@ use crate::substratum_node::SubstratumNode;
 use node_lib::neighborhood::node_record::NodeRecordInner;
 use node_lib::neighborhood::AccessibleGossipRecord;
 use node_lib::sub_lib::cryptde::{CryptData, PlainData};
+use node_lib::sub_lib::exit_policy::ExitPolicy;
 use std::collections::BTreeSet;
 use std::io::{ErrorKind, Read, Write};
 use std::net::TcpStream;
@@ -76,6 +77,7 @@ impl From<&dyn SubstratumNode> for AccessibleGossipRecord {
                 neighbors: BTreeSet::new(),
                 accepts_connections: substratum_node.accepts_connections(),
                 routes_data: substratum_node.routes_data(),
+                exit_policy: ExitPolicy::new(vec![], vec![]).summary(), // Real test Nodes run with --exit-deny none
                 version: 0,
             },
             node_addr_opt: Some(substratum_node.node_addr()),
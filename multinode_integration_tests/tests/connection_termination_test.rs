@@ -327,6 +327,10 @@ fn create_request_icp(
             target_port: server.socket_addr().port(),
             protocol: ProxyProtocol::HTTP,
             originator_public_key: originating_node.public_key().clone(),
+            fresh_return_route: false,
+            alpn_protocols: vec![],
+            route_refresh: false,
+            trace_id_opt: None,
         }),
         exit_node.public_key(),
     )
@@ -364,6 +368,10 @@ fn create_meaningless_icp(
             target_port: socket_addr.port(),
             protocol: ProxyProtocol::HTTP,
             originator_public_key: originating_node.public_key().clone(),
+            fresh_return_route: false,
+            alpn_protocols: vec![],
+            route_refresh: false,
+            trace_id_opt: None,
         }),
         exit_node.public_key(),
     )
@@ -438,6 +446,10 @@ fn create_client_drop_report(
         target_port: 80,
         protocol: ProxyProtocol::HTTP,
         originator_public_key: originating_node.public_key().clone(),
+        fresh_return_route: false,
+        alpn_protocols: vec![],
+        route_refresh: false,
+        trace_id_opt: None,
     });
 
     IncipientCoresPackage::new(
@@ -2,9 +2,11 @@
 
 const binaryBasePath = '../dist/static/binaries/'
 const recoverTimeout = 1000
+const defaultChainName = 'ropsten'
 
 module.exports = (() => {
   const childProcess = require('child_process')
+  const nodePath = require('path')
   const path = require('./wrappers/path_wrapper')
   const process = require('./wrappers/process_wrapper')
   const consoleWrapper = require('./wrappers/console_wrapper')
@@ -51,6 +53,19 @@ module.exports = (() => {
     return `${uid}:${gid}:${homeDir}`
   }
 
+  // Where the Node keeps its data when it isn't given --data-directory; this must agree with the Node's own default.
+  function getDataDirectory (chainName) {
+    const chain = chainName || defaultChainName
+    if (process.platform === 'win32') {
+      return nodePath.join(process.env.LOCALAPPDATA, 'Substratum', chain)
+    }
+    const homeDir = realUser().split(':').slice(2).join(':')
+    if (process.platform === 'darwin') {
+      return nodePath.join(homeDir, 'Library', 'Application Support', 'Substratum', chain)
+    }
+    return nodePath.join(homeDir, '.local', 'share', 'Substratum', chain)
+  }
+
   let startSubstratumNode, stopSubstratumNode
 
   function getBinaryPath () {
@@ -236,6 +251,7 @@ module.exports = (() => {
     recoverWallet: recoverWallet,
     generateWallet: generateWallet,
     getNodeConfiguration: getNodeConfiguration,
+    getDataDirectory: getDataDirectory,
     startSubstratumNode: startSubstratumNode,
     stopSubstratumNode: stopSubstratumNode
   }
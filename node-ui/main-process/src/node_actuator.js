@@ -97,10 +97,15 @@ module.exports = class NodeActuator {
     }
   }
 
+  connectToNode (additionalArguments) {
+    const chainName = additionalArguments ? additionalArguments.chainName : undefined
+    return uiInterface.connect(substratumNode.getDataDirectory(chainName))
+  }
+
   async startNode (additionalArguments) {
     if (this.substratumNodeProcess) {
       try {
-        await uiInterface.connect()
+        await this.connectToNode(additionalArguments)
       } catch (err) {
         return this.spawnSubstratumNodeProcess(additionalArguments)
       }
@@ -124,7 +129,7 @@ module.exports = class NodeActuator {
 
     if (await uiInterface.verifyNodeUp(NODE_STARTUP_TIMEOUT)) {
       try {
-        await uiInterface.connect()
+        await this.connectToNode(additionalArguments)
         await this.updateNodeDescriptor(await uiInterface.getNodeDescriptor())
       } catch (err) {
         dialog.showErrorBox('Error', 'Could not start node!')
@@ -25,6 +25,10 @@ module.exports = (() => {
     return handle(result)
   }
 
+  function getDataDirectory (chainName) {
+    return commandHelper.getDataDirectory(chainName)
+  }
+
   function handle (result) {
     if (result.status === 0) {
       return { success: true, result: result.stdout.toString('utf8').trim() }
@@ -66,6 +70,7 @@ module.exports = (() => {
   return {
     recoverWallet: recoverWallet,
     generateWallet: generateWallet,
+    getDataDirectory: getDataDirectory,
     start: start,
     stop: stop
   }
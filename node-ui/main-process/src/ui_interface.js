@@ -1,5 +1,7 @@
 // Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
 
+const fs = require('fs')
+const path = require('path')
 const webSocketWrapper = require('./wrappers/websocket_wrapper.js')
 
 module.exports = (() => {
@@ -7,6 +9,7 @@ module.exports = (() => {
   const UI_INTERFACE_URL = 'ws://127.0.0.1'
   const UI_PROTOCOL = 'SubstratumNode-UI'
   const CONNECT_TIMEOUT = 5000
+  const UI_SESSION_TOKEN_FILE = 'ui-session-token'
   let webSocket = null
   let getNodeDescriptorCallbackPair = null
   let setConsumingWalletPasswordCallbackPair = null
@@ -14,9 +17,14 @@ module.exports = (() => {
   let getNeighborhoodDotGraphCallbackPair = null
   let setGasPriceCallbackPair = null
 
-  function connect () {
+  /**
+   * connects with the session token the Node wrote into its data directory, without which the Node won't take orders
+   * @param dataDirectory
+   * @returns {Promise<boolean>}
+   */
+  function connect (dataDirectory) {
     return new Promise((resolve, reject) => {
-      const ws = createSocket(DEFAULT_UI_PORT)
+      const ws = createSocket(DEFAULT_UI_PORT, readSessionToken(dataDirectory))
       ws.onopen = () => {
         webSocket = ws
         resolve(true)
@@ -236,8 +244,20 @@ module.exports = (() => {
     })
   }
 
-  function createSocket (port) {
-    return webSocketWrapper.create(`${UI_INTERFACE_URL}:${port}`, UI_PROTOCOL, { handshakeTimeout: CONNECT_TIMEOUT })
+  function readSessionToken (dataDirectory) {
+    if (!dataDirectory) {
+      return null
+    }
+    try {
+      return fs.readFileSync(path.join(dataDirectory, UI_SESSION_TOKEN_FILE), 'utf8').trim()
+    } catch (error) {
+      return null
+    }
+  }
+
+  function createSocket (port, sessionToken) {
+    const query = sessionToken ? `/?token=${encodeURIComponent(sessionToken)}` : ''
+    return webSocketWrapper.create(`${UI_INTERFACE_URL}:${port}${query}`, UI_PROTOCOL, { handshakeTimeout: CONNECT_TIMEOUT })
   }
 
   return {
@@ -245,6 +265,7 @@ module.exports = (() => {
     UI_INTERFACE_URL: UI_INTERFACE_URL,
     UI_PROTOCOL: UI_PROTOCOL,
     CONNECT_TIMEOUT: CONNECT_TIMEOUT,
+    UI_SESSION_TOKEN_FILE: UI_SESSION_TOKEN_FILE,
     connect: connect,
     isConnected: isConnected,
     verifyNodeUp: verifyNodeUp,
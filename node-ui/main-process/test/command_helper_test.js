@@ -198,6 +198,20 @@ describe('CommandHelper', () => {
           })
         })
       })
+
+      describe('finding the data directory', () => {
+        beforeEach(() => {
+          process.env = { HOME: '/home/booga' }
+        })
+
+        it('looks where the Node puts it by default for the chain', () => {
+          assert.strictEqual(subject.getDataDirectory('mainnet'), '/home/booga/.local/share/Substratum/mainnet')
+        })
+
+        it('looks in the default chain\'s directory when no chain is named', () => {
+          assert.strictEqual(subject.getDataDirectory(), '/home/booga/.local/share/Substratum/ropsten')
+        })
+      })
     })
 
     describe('MacOS', () => {
@@ -236,6 +250,16 @@ describe('CommandHelper', () => {
           })
         })
       })
+
+      describe('finding the data directory', () => {
+        beforeEach(() => {
+          process.env = { SUDO_USER: 'booga' }
+        })
+
+        it('looks where the Node puts it by default for the chain', () => {
+          assert.strictEqual(subject.getDataDirectory('mainnet'), '/Users/booga/Library/Application Support/Substratum/mainnet')
+        })
+      })
     })
 
     describe('stopping', () => {
@@ -460,5 +484,15 @@ describe('CommandHelper', () => {
         })
       })
     })
+
+    describe('finding the data directory', () => {
+      beforeEach(() => {
+        process.env = { LOCALAPPDATA: '/Users/booga/AppData/Local' }
+      })
+
+      it('looks where the Node puts it by default for the chain', () => {
+        assert.strictEqual(subject.getDataDirectory('mainnet'), path.join('/Users/booga/AppData/Local', 'Substratum', 'mainnet'))
+      })
+    })
   })
 })
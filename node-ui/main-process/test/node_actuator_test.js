@@ -38,7 +38,7 @@ describe('NodeActuator', () => {
     mockChildProcess = td.replace('child_process')
     mockSudoPrompt = td.replace('sudo-prompt')
     mockConsole = td.replace('../src/wrappers/console_wrapper')
-    mockSubstratumNode = td.replace('../src/substratum_node', td.object(['generateWallet', 'recoverWallet']))
+    mockSubstratumNode = td.replace('../src/substratum_node', td.object(['generateWallet', 'recoverWallet', 'getDataDirectory']))
     mockDnsUtility = td.replace('../src/dns_utility')
     mockPsWrapper = td.replace('../src/wrappers/ps_wrapper')
     mockUiInterface = td.replace('../src/ui_interface')
@@ -58,7 +58,8 @@ describe('NodeActuator', () => {
       stdio: [0, 1, 2, 'ipc'],
       detached: true
     })).thenReturn(mockSubstratumNodeProcess)
-    td.when(mockUiInterface.connect()).thenResolve(true)
+    td.when(mockSubstratumNode.getDataDirectory(undefined)).thenReturn('/mock-data-dir')
+    td.when(mockUiInterface.connect('/mock-data-dir')).thenResolve(true)
     td.when(mockDnsUtility.getStatus()).thenReturn('reverted')
     td.when(mockDnsUtility.revert()).thenResolve('')
     td.when(mockDnsUtility.subvert()).thenResolve('')
@@ -150,12 +151,12 @@ describe('NodeActuator', () => {
       })
 
       it('connects the WebSocket', () => {
-        td.verify(mockUiInterface.connect())
+        td.verify(mockUiInterface.connect('/mock-data-dir'))
       })
 
       describe('but fails to connect via uiInterface', () => {
         beforeEach(async () => {
-          td.when(mockUiInterface.connect()).thenReject(':(')
+          td.when(mockUiInterface.connect('/mock-data-dir')).thenReject(':(')
           await subject.serving()
         })
 
@@ -168,7 +169,7 @@ describe('NodeActuator', () => {
         })
 
         it('connects the WebSocket', () => {
-          td.verify(mockUiInterface.connect().catch(() => {}))
+          td.verify(mockUiInterface.connect('/mock-data-dir').catch(() => {}))
         })
 
         it('does not try to get the node descriptor', () => {
@@ -197,7 +198,20 @@ describe('NodeActuator', () => {
       })
 
       it('connects the WebSocket', () => {
-        td.verify(mockUiInterface.connect())
+        td.verify(mockUiInterface.connect('/mock-data-dir'))
+      })
+    })
+
+    describe('to serving on a named chain, where the UI interface determines that the Node is up', () => {
+      beforeEach(async () => {
+        td.when(mockSubstratumNode.getDataDirectory('mainnet')).thenReturn('/mock-mainnet-dir')
+        td.when(mockUiInterface.verifyNodeUp(td.matchers.anything())).thenResolve(true)
+
+        await subject.serving({ chainName: 'mainnet' })
+      })
+
+      it('connects the WebSocket with the session token from that chain\'s data directory', () => {
+        td.verify(mockUiInterface.connect('/mock-mainnet-dir'))
       })
     })
 
@@ -217,7 +231,7 @@ describe('NodeActuator', () => {
       })
 
       it('does not connect the WebSocket', () => {
-        td.verify(mockUiInterface.connect(), { times: 0 })
+        td.verify(mockUiInterface.connect('/mock-data-dir'), { times: 0 })
       })
 
       it('shows an error dialog', () => {
@@ -4,11 +4,14 @@
 
 const td = require('testdouble')
 const assert = require('assert')
+const path = require('path')
 
 describe('Given a mock WebSocket', () => {
   let mockWebSocketWrapper
+  let mockFs
   let uiInterface
   beforeEach(() => {
+    mockFs = td.replace('fs')
     mockWebSocketWrapper = td.replace('../src/wrappers/websocket_wrapper')
     uiInterface = require('../src/ui_interface')
   })
@@ -46,6 +49,54 @@ describe('Given a mock WebSocket', () => {
       })
     })
 
+    describe('and the UiInterface connects after the Node has written its session token', () => {
+      let result
+      beforeEach(async () => {
+        td.when(mockFs.readFileSync(path.join('/data-dir', subject.UI_SESSION_TOKEN_FILE), 'utf8'))
+          .thenReturn('0123abcd\n')
+        const webSocketClient = td.object(['send', 'close', 'onopen', 'onmessage', 'onerror'])
+        td.when(mockWebSocketWrapper.create(`${subject.UI_INTERFACE_URL}:${subject.DEFAULT_UI_PORT}/?token=0123abcd`,
+          subject.UI_PROTOCOL, { handshakeTimeout: subject.CONNECT_TIMEOUT })).thenReturn(webSocketClient)
+
+        const connectPromise = subject.connect('/data-dir')
+        webSocketClient.onopen()
+        result = await connectPromise
+      })
+
+      afterEach(() => {
+        subject.shutdown()
+      })
+
+      it('presents the token in the URL it connects to', () => {
+        assert.strictEqual(result, true)
+        assert.strictEqual(subject.isConnected(), true)
+      })
+    })
+
+    describe('and the UiInterface connects when there is no session token to be found', () => {
+      let result
+      beforeEach(async () => {
+        td.when(mockFs.readFileSync(path.join('/data-dir', subject.UI_SESSION_TOKEN_FILE), 'utf8'))
+          .thenThrow(new Error('ENOENT'))
+        const webSocketClient = td.object(['send', 'close', 'onopen', 'onmessage', 'onerror'])
+        td.when(mockWebSocketWrapper.create(`${subject.UI_INTERFACE_URL}:${subject.DEFAULT_UI_PORT}`,
+          subject.UI_PROTOCOL, { handshakeTimeout: subject.CONNECT_TIMEOUT })).thenReturn(webSocketClient)
+
+        const connectPromise = subject.connect('/data-dir')
+        webSocketClient.onopen()
+        result = await connectPromise
+      })
+
+      afterEach(() => {
+        subject.shutdown()
+      })
+
+      it('connects without one, and may only ask questions', () => {
+        assert.strictEqual(result, true)
+        assert.strictEqual(subject.isConnected(), true)
+      })
+    })
+
     describe('and the UiInterface is connected', () => {
       let webSocketClient, connectPromise
       beforeEach(() => {
@@ -17,12 +17,17 @@ use crate::sub_lib::accountant::ReportExitServiceConsumedMessage;
 use crate::sub_lib::accountant::ReportExitServiceProvidedMessage;
 use crate::sub_lib::accountant::ReportRoutingServiceConsumedMessage;
 use crate::sub_lib::accountant::ReportRoutingServiceProvidedMessage;
+use crate::sub_lib::accountant::SetEarningWalletMsg;
 use crate::sub_lib::accountant::{AccountantConfig, GetFinancialStatisticsMessage};
 use crate::sub_lib::accountant::{AccountantSubs, FinancialStatisticsMessage};
 use crate::sub_lib::blockchain_bridge::ReportAccountsPayable;
 use crate::sub_lib::logger::Logger;
+use crate::sub_lib::metrics;
+use crate::sub_lib::metrics::{ACCOUNTANT_PAYABLE_BALANCE, ACCOUNTANT_RECEIVABLE_BALANCE};
 use crate::sub_lib::peer_actors::{BindMessage, StartMessage};
-use crate::sub_lib::ui_gateway::{UiCarrierMessage, UiMessage};
+use crate::sub_lib::privacy;
+use crate::sub_lib::set_earning_wallet_message::SetEarningWalletMessage;
+use crate::sub_lib::ui_gateway::{Setting, UiCarrierMessage, UiEvent, UiMessage};
 use crate::sub_lib::utils::NODE_MAILBOX_CAPACITY;
 use crate::sub_lib::wallet::Wallet;
 use actix::Actor;
@@ -88,6 +93,7 @@ pub struct Accountant {
     report_new_payments_sub: Option<Recipient<ReceivedPayments>>,
     report_sent_payments_sub: Option<Recipient<SentPayments>>,
     ui_carrier_message_sub: Option<Recipient<UiCarrierMessage>>,
+    set_earning_wallet_sub: Option<Recipient<SetEarningWalletMessage>>,
     logger: Logger,
 }
 
@@ -116,6 +122,7 @@ impl Handler<BindMessage> for Accountant {
         self.report_new_payments_sub = Some(msg.peer_actors.accountant.report_new_payments);
         self.report_sent_payments_sub = Some(msg.peer_actors.accountant.report_sent_payments);
         self.ui_carrier_message_sub = Some(msg.peer_actors.ui_gateway.ui_message_sub.clone());
+        self.set_earning_wallet_sub = Some(msg.peer_actors.neighborhood.set_earning_wallet_sub);
         ctx.set_mailbox_capacity(NODE_MAILBOX_CAPACITY);
 
         info!(self.logger, "Accountant bound");
@@ -129,6 +136,7 @@ impl Handler<StartMessage> for Accountant {
         self.scan_for_payables();
         self.scan_for_received_payments();
         self.scan_for_delinquencies();
+        self.pending_balances();
 
         ctx.run_interval(self.config.payable_scan_interval, |accountant, _ctx| {
             accountant.scan_for_payables();
@@ -139,6 +147,7 @@ impl Handler<StartMessage> for Accountant {
             |accountant, _ctx| {
                 accountant.scan_for_received_payments();
                 accountant.scan_for_delinquencies();
+                accountant.pending_balances();
             },
         );
     }
@@ -152,10 +161,22 @@ impl Handler<ReceivedPayments> for Accountant {
         received_payments: ReceivedPayments,
         _ctx: &mut Self::Context,
     ) -> Self::Result {
+        let events = received_payments
+            .payments
+            .iter()
+            .map(|transaction| UiEvent::PaymentConfirmed {
+                from: transaction.from.to_string(),
+                amount: transaction.gwei_amount,
+                block_number: transaction.block_number,
+            })
+            .collect::<Vec<UiEvent>>();
         self.receivable_dao.as_mut().more_money_received(
             self.persistent_configuration.as_ref(),
             received_payments.payments,
         );
+        events
+            .into_iter()
+            .for_each(|event| self.report_event(event));
     }
 }
 
@@ -167,7 +188,14 @@ impl Handler<SentPayments> for Accountant {
             .payments
             .iter()
             .for_each(|payment| match payment {
-                Ok(payment) => self.payable_dao.as_mut().payment_sent(payment),
+                Ok(payment) => {
+                    self.payable_dao.as_mut().payment_sent(payment);
+                    self.report_event(UiEvent::PaymentSent {
+                        to: payment.to.to_string(),
+                        amount: payment.amount,
+                        transaction: format!("{:#x}", payment.transaction),
+                    })
+                }
                 Err(e) => warning!(
                     self.logger,
                     "{} Please check your blockchain service URL configuration.",
@@ -187,7 +215,9 @@ impl Handler<ReportRoutingServiceProvidedMessage> for Accountant {
     ) -> Self::Result {
         debug!(
             self.logger,
-            "Charging routing of {} bytes to wallet {}", msg.payload_size, msg.paying_wallet
+            "Charging routing of {} bytes to wallet {}",
+            msg.payload_size,
+            privacy::wallet(&msg.paying_wallet)
         );
         self.record_service_provided(
             msg.service_rate,
@@ -210,7 +240,7 @@ impl Handler<ReportExitServiceProvidedMessage> for Accountant {
             self.logger,
             "Charging exit service for {} bytes to wallet {} at {} per service and {} per byte",
             msg.payload_size,
-            msg.paying_wallet,
+            privacy::wallet(&msg.paying_wallet),
             msg.service_rate,
             msg.byte_rate
         );
@@ -234,7 +264,7 @@ impl Handler<ReportRoutingServiceConsumedMessage> for Accountant {
         debug!(
             self.logger,
             "Accruing debt to wallet {} for consuming routing service {} bytes",
-            msg.earning_wallet,
+            privacy::wallet(&msg.earning_wallet),
             msg.payload_size
         );
         self.record_service_consumed(
@@ -257,7 +287,7 @@ impl Handler<ReportExitServiceConsumedMessage> for Accountant {
         debug!(
             self.logger,
             "Accruing debt to wallet {} for consuming exit service {} bytes",
-            msg.earning_wallet,
+            privacy::wallet(&msg.earning_wallet),
             msg.payload_size
         );
         self.record_service_consumed(
@@ -277,23 +307,13 @@ impl Handler<GetFinancialStatisticsMessage> for Accountant {
         msg: GetFinancialStatisticsMessage,
         _ctx: &mut Self::Context,
     ) -> Self::Result {
-        let pending_credit = self
-            .receivable_dao
-            .receivables()
-            .into_iter()
-            .map(|account| account.balance)
-            .sum();
-        let pending_debt = self
-            .payable_dao
-            .non_pending_payables()
-            .into_iter()
-            .map(|account| account.balance)
-            .sum();
+        let (pending_credit, pending_debt) = self.pending_balances();
         self.ui_carrier_message_sub
             .as_ref()
             .expect("UiGateway is unbound")
             .try_send(UiCarrierMessage {
                 client_id: msg.client_id,
+                context_id: Some(msg.context_id),
                 data: UiMessage::FinancialStatisticsResponse(FinancialStatisticsMessage {
                     pending_credit,
                     pending_debt,
@@ -303,6 +323,36 @@ impl Handler<GetFinancialStatisticsMessage> for Accountant {
     }
 }
 
+impl Handler<SetEarningWalletMsg> for Accountant {
+    type Result = ();
+
+    fn handle(&mut self, msg: SetEarningWalletMsg, _ctx: &mut Self::Context) -> Self::Result {
+        self.persistent_configuration
+            .change_earning_wallet_address(&msg.wallet.to_string());
+        info!(
+            self.logger,
+            "Earning wallet changed from {} to {}",
+            privacy::wallet(&self.earning_wallet),
+            privacy::wallet(&msg.wallet)
+        );
+        self.earning_wallet = msg.wallet.clone();
+        self.set_earning_wallet_sub
+            .as_ref()
+            .expect("Neighborhood is unbound")
+            .try_send(SetEarningWalletMessage { wallet: msg.wallet })
+            .expect("Neighborhood is dead");
+        self.ui_carrier_message_sub
+            .as_ref()
+            .expect("UiGateway is unbound")
+            .try_send(UiCarrierMessage {
+                client_id: msg.client_id,
+                context_id: Some(msg.context_id),
+                data: UiMessage::SetConfigurationResponse(Setting::EarningWallet, Ok(())),
+            })
+            .expect("UiGateway is dead");
+    }
+}
+
 impl Accountant {
     pub fn new(
         config: &BootstrapperConfig,
@@ -324,6 +374,7 @@ impl Accountant {
             report_new_payments_sub: None,
             report_sent_payments_sub: None,
             ui_carrier_message_sub: None,
+            set_earning_wallet_sub: None,
             logger: Logger::new("Accountant"),
         }
     }
@@ -347,9 +398,29 @@ impl Accountant {
             report_new_payments: addr.clone().recipient::<ReceivedPayments>(),
             report_sent_payments: addr.clone().recipient::<SentPayments>(),
             get_financial_statistics_sub: addr.clone().recipient::<GetFinancialStatisticsMessage>(),
+            set_earning_wallet_sub: addr.clone().recipient::<SetEarningWalletMsg>(),
         }
     }
 
+    // Keeps the balance gauges current whenever anybody looks at the balances
+    fn pending_balances(&self) -> (i64, i64) {
+        let pending_credit = self
+            .receivable_dao
+            .receivables()
+            .into_iter()
+            .map(|account| account.balance)
+            .sum();
+        let pending_debt = self
+            .payable_dao
+            .non_pending_payables()
+            .into_iter()
+            .map(|account| account.balance)
+            .sum();
+        metrics::set(&ACCOUNTANT_RECEIVABLE_BALANCE, &[], pending_credit);
+        metrics::set(&ACCOUNTANT_PAYABLE_BALANCE, &[], pending_debt);
+        (pending_credit, pending_debt)
+    }
+
     fn scan_for_payables(&mut self) {
         debug!(self.logger, "Scanning for payables");
         let future_logger = self.logger.clone();
@@ -405,11 +476,13 @@ impl Accountant {
                 let (balance, age) = Self::balance_and_age(&account);
                 info!(
                     self.logger,
+                    {wallet: privacy::wallet(&account.wallet)},
                     "Wallet {} (balance: {} SUB, age: {} sec) banned for delinquency",
-                    account.wallet,
+                    privacy::wallet(&account.wallet),
                     balance,
                     age.as_secs()
-                )
+                );
+                self.report_event(UiEvent::WalletBanned(account.wallet.to_string()))
             });
 
         self.receivable_dao
@@ -420,8 +493,9 @@ impl Accountant {
                 let (balance, age) = Self::balance_and_age(&account);
                 info!(
                     self.logger,
+                    {wallet: privacy::wallet(&account.wallet)},
                     "Wallet {} (balance: {} SUB, age: {} sec) is no longer delinquent: unbanned",
-                    account.wallet,
+                    privacy::wallet(&account.wallet),
                     balance,
                     age.as_secs()
                 )
@@ -432,7 +506,8 @@ impl Accountant {
         let future_logger = self.logger.clone();
         debug!(
             self.logger,
-            "Scanning for payments to {}", self.earning_wallet
+            "Scanning for payments to {}",
+            privacy::wallet(&self.earning_wallet)
         );
         let future_report_new_payments_sub = self.report_new_payments_sub.clone();
         let start_block = self.persistent_configuration.start_block();
@@ -478,6 +553,14 @@ impl Accountant {
         actix::spawn(future);
     }
 
+    // Nobody is listening for events until the UiGateway is bound
+    fn report_event(&self, event: UiEvent) {
+        if let Some(sub) = self.ui_carrier_message_sub.as_ref() {
+            sub.try_send(UiCarrierMessage::event(event))
+                .expect("UiGateway is dead")
+        }
+    }
+
     fn balance_and_age(account: &ReceivableAccount) -> (String, Duration) {
         let balance = format!("{}", (account.balance as f64) / 1_000_000_000.0);
         let age = account
@@ -532,7 +615,8 @@ impl Accountant {
         } else {
             info!(
                 self.logger,
-                "Not recording service provided for our wallet {}", wallet
+                "Not recording service provided for our wallet {}",
+                privacy::wallet(wallet)
             );
         }
     }
@@ -553,7 +637,8 @@ impl Accountant {
         } else {
             info!(
                 self.logger,
-                "Not recording service consumed to our wallet {}", wallet
+                "Not recording service consumed to our wallet {}",
+                privacy::wallet(wallet)
             );
         }
     }
@@ -717,7 +802,11 @@ pub mod tests {
         }
 
         fn receivables(&self) -> Vec<ReceivableAccount> {
-            self.receivables_results.borrow_mut().remove(0)
+            if self.receivables_results.borrow().is_empty() {
+                vec![]
+            } else {
+                self.receivables_results.borrow_mut().remove(0)
+            }
         }
 
         fn new_delinquencies(
@@ -1143,8 +1232,11 @@ pub mod tests {
 
         send_bind_message!(subject_subs, peer_actors);
 
-        addr.try_send(GetFinancialStatisticsMessage { client_id: 1234 })
-            .unwrap();
+        addr.try_send(GetFinancialStatisticsMessage {
+            client_id: 1234,
+            context_id: 4321,
+        })
+        .unwrap();
 
         System::current().stop();
         system.run();
@@ -1156,6 +1248,7 @@ pub mod tests {
             ui_gateway_recording.get_record::<UiCarrierMessage>(0),
             &UiCarrierMessage {
                 client_id: 1234,
+                context_id: Some(4321),
                 data: UiMessage::FinancialStatisticsResponse(FinancialStatisticsMessage {
                     pending_credit: 69_000_000_000,
                     pending_debt: 42_000_000_000,
@@ -1164,6 +1257,66 @@ pub mod tests {
         );
     }
 
+    #[test]
+    fn accountant_persists_a_new_earning_wallet_and_passes_it_on() {
+        let config = AccountantConfig {
+            payable_scan_interval: Duration::from_secs(10_000),
+            payment_received_scan_interval: Duration::from_secs(10_000),
+        };
+        let (ui_gateway, _, ui_gateway_recording_arc) = make_recorder();
+        let (neighborhood, _, neighborhood_recording_arc) = make_recorder();
+        let change_earning_wallet_address_params_arc = Arc::new(Mutex::new(vec![]));
+        let system = System::new("accountant_persists_a_new_earning_wallet_and_passes_it_on");
+        let persistent_configuration = PersistentConfigurationMock::new()
+            .change_earning_wallet_address_params(&change_earning_wallet_address_params_arc);
+        let subject = Accountant::new(
+            &bc_from_ac_plus_earning_wallet(config, make_wallet("old earning")),
+            Box::new(PayableDaoMock::new()),
+            Box::new(ReceivableDaoMock::new()),
+            Box::new(BannedDaoMock::new()),
+            Box::new(persistent_configuration),
+        );
+        let addr = subject.start();
+        let subject_subs = Accountant::make_subs_from(&addr);
+        let peer_actors = peer_actors_builder()
+            .ui_gateway(ui_gateway)
+            .neighborhood(neighborhood)
+            .build();
+        send_bind_message!(subject_subs, peer_actors);
+
+        addr.try_send(SetEarningWalletMsg {
+            client_id: 1234,
+            context_id: 4321,
+            wallet: make_wallet("new earning"),
+        })
+        .unwrap();
+
+        System::current().stop();
+        system.run();
+        let change_earning_wallet_address_params =
+            change_earning_wallet_address_params_arc.lock().unwrap();
+        assert_eq!(
+            *change_earning_wallet_address_params,
+            vec![make_wallet("new earning").to_string()]
+        );
+        let neighborhood_recording = neighborhood_recording_arc.lock().unwrap();
+        assert_eq!(
+            neighborhood_recording.get_record::<SetEarningWalletMessage>(0),
+            &SetEarningWalletMessage {
+                wallet: make_wallet("new earning")
+            }
+        );
+        let ui_gateway_recording = ui_gateway_recording_arc.lock().unwrap();
+        assert_eq!(
+            ui_gateway_recording.get_record::<UiCarrierMessage>(0),
+            &UiCarrierMessage {
+                client_id: 1234,
+                context_id: Some(4321),
+                data: UiMessage::SetConfigurationResponse(Setting::EarningWallet, Ok(())),
+            }
+        );
+    }
+
     #[test]
     fn accountant_payment_received_scan_timer_triggers_scanning_for_payments() {
         let paying_wallet = make_wallet("wallet0");
@@ -1791,6 +1944,108 @@ pub mod tests {
         tlh.exists_log_matching("INFO: Accountant: Wallet 0x00000000000000000077616c6c6574343536376e \\(balance: 4567 SUB, age: \\d+ sec\\) is no longer delinquent: unbanned");
     }
 
+    #[test]
+    fn scan_for_delinquencies_tells_uis_about_bans() {
+        let (ui_gateway, _, ui_gateway_recording_arc) = make_recorder();
+        let config = bc_from_ac_plus_earning_wallet(
+            AccountantConfig {
+                payable_scan_interval: Duration::from_secs(100),
+                payment_received_scan_interval: Duration::from_secs(1000),
+            },
+            make_wallet("mine"),
+        );
+        let newly_banned = make_receivable_account(1234, true);
+        let receivable_dao = ReceivableDaoMock::new()
+            .new_delinquencies_result(vec![newly_banned.clone()])
+            .paid_delinquencies_result(vec![make_receivable_account(3456, false)]);
+        let mut subject = Accountant::new(
+            &config,
+            Box::new(PayableDaoMock::new()),
+            Box::new(receivable_dao),
+            Box::new(BannedDaoMock::new()),
+            null_config(),
+        );
+        let system = System::new("scan_for_delinquencies_tells_uis_about_bans");
+        subject.ui_carrier_message_sub = Some(ui_gateway.start().recipient::<UiCarrierMessage>());
+
+        subject.scan_for_delinquencies();
+
+        System::current().stop();
+        system.run();
+        let ui_gateway_recording = ui_gateway_recording_arc.lock().unwrap();
+        assert_eq!(
+            ui_gateway_recording.get_record::<UiCarrierMessage>(0),
+            &UiCarrierMessage::event(UiEvent::WalletBanned(newly_banned.wallet.to_string()))
+        );
+        assert_eq!(ui_gateway_recording.len(), 1);
+    }
+
+    #[test]
+    fn accountant_tells_uis_about_payments_sent_and_received() {
+        let (ui_gateway, _, ui_gateway_recording_arc) = make_recorder();
+        let accountant = Accountant::new(
+            &bc_from_ac_plus_earning_wallet(
+                AccountantConfig {
+                    payable_scan_interval: Duration::from_secs(10_000),
+                    payment_received_scan_interval: Duration::from_secs(10_000),
+                },
+                make_wallet("earner"),
+            ),
+            Box::new(PayableDaoMock::new()),
+            Box::new(ReceivableDaoMock::new()),
+            Box::new(BannedDaoMock::new()),
+            null_config(),
+        );
+        let system = System::new("accountant_tells_uis_about_payments_sent_and_received");
+        let subject = accountant.start();
+        subject
+            .try_send(BindMessage {
+                peer_actors: peer_actors_builder().ui_gateway(ui_gateway).build(),
+            })
+            .unwrap();
+        let transaction_hash = H256::from("transaction_hash".keccak256());
+
+        subject
+            .try_send(SentPayments {
+                payments: vec![Ok(Payment::new(
+                    make_wallet("payee"),
+                    1234,
+                    transaction_hash,
+                ))],
+            })
+            .unwrap();
+        subject
+            .try_send(ReceivedPayments {
+                payments: vec![Transaction {
+                    block_number: 7,
+                    from: make_wallet("payer"),
+                    gwei_amount: 42,
+                }],
+            })
+            .unwrap();
+
+        System::current().stop();
+        system.run();
+        let ui_gateway_recording = ui_gateway_recording_arc.lock().unwrap();
+        assert_eq!(
+            ui_gateway_recording.get_record::<UiCarrierMessage>(0),
+            &UiCarrierMessage::event(UiEvent::PaymentSent {
+                to: make_wallet("payee").to_string(),
+                amount: 1234,
+                transaction: format!("{:#x}", transaction_hash),
+            })
+        );
+        assert_eq!(
+            ui_gateway_recording.get_record::<UiCarrierMessage>(1),
+            &UiCarrierMessage::event(UiEvent::PaymentConfirmed {
+                from: make_wallet("payer").to_string(),
+                amount: 42,
+                block_number: 7,
+            })
+        );
+        assert_eq!(ui_gateway_recording.len(), 2);
+    }
+
     #[test]
     fn report_routing_service_provided_message_is_received() {
         init_test_logging();
@@ -24,8 +24,10 @@ use crate::database::db_initializer::{DbInitializer, DbInitializerReal, DATABASE
 use crate::persistent_configuration::PersistentConfigurationReal;
 use crate::sub_lib::accountant::AccountantSubs;
 use crate::sub_lib::blockchain_bridge::BlockchainBridgeSubs;
+use crate::sub_lib::bypass::BypassList;
 use crate::sub_lib::cryptde::CryptDE;
 use crate::sub_lib::dispatcher::DispatcherSubs;
+use crate::sub_lib::entry_dns::EntryDnsMode;
 use crate::sub_lib::hopper::HopperConfig;
 use crate::sub_lib::hopper::HopperSubs;
 use crate::sub_lib::neighborhood::NeighborhoodSubs;
@@ -39,6 +41,7 @@ use crate::sub_lib::ui_gateway::UiGatewaySubs;
 use actix::Addr;
 use actix::Recipient;
 use actix::{Actor, Arbiter};
+use std::net::SocketAddr;
 use std::path::PathBuf;
 use std::sync::mpsc;
 use std::sync::mpsc::Sender;
@@ -87,16 +90,23 @@ impl ActorSystemFactoryReal {
             } else {
                 Some(0)
             },
+            config.route_path_count,
+            config.proxy_port_opt,
+            config.bypass_list.clone(),
+            config.bypass_dns_servers.clone(),
         );
         let proxy_client_subs = actor_factory.make_and_start_proxy_client(ProxyClientConfig {
             cryptde,
             dns_servers: config.dns_servers.clone(),
+            dns_protocol: config.dns_protocol.clone(),
             exit_service_rate: config
                 .neighborhood_config
                 .mode
                 .rate_pack()
                 .exit_service_rate,
             exit_byte_rate: config.neighborhood_config.mode.rate_pack().exit_byte_rate,
+            exit_policy: config.exit_policy.clone(),
+            exit_connection_pool: config.exit_connection_pool,
         });
         let hopper_subs = actor_factory.make_and_start_hopper(HopperConfig {
             cryptde,
@@ -167,6 +177,14 @@ impl ActorSystemFactoryReal {
         send_start_message!(peer_actors.neighborhood);
         send_start_message!(peer_actors.accountant);
 
+        //until this happens, the entry DNS server refuses what it can't answer with localhost
+        if config.entry_dns_mode != EntryDnsMode::Localhost {
+            config.entry_dns_tunnel.connect(
+                peer_actors.proxy_server.dns_query_from_entry_dns.clone(),
+                config.entry_dns_mode,
+            );
+        }
+
         //send out the stream handler pool subs (to be bound to listeners)
         tx.send(stream_handler_pool_subs).ok();
     }
@@ -179,6 +197,10 @@ pub trait ActorFactory: Send {
         cryptde: &'static dyn CryptDE,
         is_decentralized: bool,
         consuming_wallet_balance: Option<i64>,
+        route_path_count: usize,
+        proxy_port_opt: Option<u16>,
+        bypass_list: BypassList,
+        bypass_dns_servers: Vec<SocketAddr>,
     ) -> ProxyServerSubs;
     fn make_and_start_hopper(&self, config: HopperConfig) -> HopperSubs;
     fn make_and_start_neighborhood(
@@ -222,9 +244,18 @@ impl ActorFactory for ActorFactoryReal {
         cryptde: &'static dyn CryptDE,
         is_decentralized: bool,
         consuming_wallet_balance: Option<i64>,
+        route_path_count: usize,
+        proxy_port_opt: Option<u16>,
+        bypass_list: BypassList,
+        bypass_dns_servers: Vec<SocketAddr>,
     ) -> ProxyServerSubs {
         let addr: Addr<ProxyServer> = Arbiter::start(move |_| {
-            ProxyServer::new(cryptde, is_decentralized, consuming_wallet_balance)
+            let mut proxy_server =
+                ProxyServer::new(cryptde, is_decentralized, consuming_wallet_balance);
+            proxy_server.set_route_path_count(route_path_count);
+            proxy_server.set_proxy_port(proxy_port_opt);
+            proxy_server.set_bypass(bypass_list, bypass_dns_servers);
+            proxy_server
         });
         ProxyServer::make_subs_from(&addr)
     }
@@ -389,10 +420,12 @@ mod tests {
     use crate::database::db_initializer::test_utils::{ConnectionWrapperMock, DbInitializerMock};
     use crate::database::db_initializer::{ConnectionWrapper, InitializationError};
     use crate::neighborhood::gossip::Gossip;
+    use crate::server_initializer::{LogFormat, LogLevelSpec, LogRotation};
     use crate::stream_messages::AddStreamMsg;
     use crate::stream_messages::RemoveStreamMsg;
     use crate::sub_lib::accountant::ReportRoutingServiceConsumedMessage;
     use crate::sub_lib::accountant::ReportRoutingServiceProvidedMessage;
+    use crate::sub_lib::accountant::SetEarningWalletMsg;
     use crate::sub_lib::accountant::{AccountantConfig, GetFinancialStatisticsMessage};
     use crate::sub_lib::accountant::{
         ReportExitServiceConsumedMessage, ReportExitServiceProvidedMessage,
@@ -403,25 +436,34 @@ mod tests {
     use crate::sub_lib::crash_point::CrashPoint;
     use crate::sub_lib::cryptde::PlainData;
     use crate::sub_lib::dispatcher::{InboundClientData, StreamShutdownMsg};
+    use crate::sub_lib::entry_dns::EntryDnsTunnel;
+    use crate::sub_lib::exit_policy::{ExitPolicy, ExitRule};
     use crate::sub_lib::hopper::IncipientCoresPackage;
     use crate::sub_lib::hopper::{ExpiredCoresPackage, NoLookupIncipientCoresPackage};
-    use crate::sub_lib::neighborhood::{DispatcherNodeQueryMessage, NodeRecordMetadataMessage};
+    use crate::sub_lib::neighborhood::NeighborhoodViewRequest;
+    use crate::sub_lib::neighborhood::NodeRecordMetadataMessage;
+    use crate::sub_lib::neighborhood::{DisjointRouteQueryMessage, DispatcherNodeQueryMessage};
     use crate::sub_lib::neighborhood::{NeighborhoodConfig, NodeQueryMessage};
     use crate::sub_lib::neighborhood::{NeighborhoodDotGraphRequest, RouteQueryMessage};
     use crate::sub_lib::neighborhood::{NeighborhoodMode, RemoveNeighborMessage};
+    use crate::sub_lib::neighborhood::{SetNeighborhoodModeMsg, SetRatePackMsg};
     use crate::sub_lib::node_addr::NodeAddr;
     use crate::sub_lib::peer_actors::StartMessage;
     use crate::sub_lib::proxy_client::{
-        ClientResponsePayload, DnsResolveFailure, InboundServerData,
+        ClientResponsePayload, ConnectionPoolConfig, DnsAnswerPayload, DnsProtocol,
+        DnsResolveFailure, ExitPolicyRefusal, GetDnsCacheStatsMsg, InboundServerData,
+        SetDnsServersMsg,
     };
     use crate::sub_lib::proxy_server::{
-        AddReturnRouteMessage, AddRouteMessage, ClientRequestPayload,
+        AddReturnRouteMessage, AddRouteMessage, ClientRequestPayload, DnsQueryPayload,
+        InvalidateRoutesMessage, TunneledDnsQuery,
     };
     use crate::sub_lib::set_consuming_wallet_message::SetConsumingWalletMessage;
+    use crate::sub_lib::set_earning_wallet_message::SetEarningWalletMessage;
     use crate::sub_lib::stream_handler_pool::DispatcherNodeQueryResponse;
     use crate::sub_lib::stream_handler_pool::TransmitDataMsg;
     use crate::sub_lib::ui_gateway::UiGatewayConfig;
-    use crate::sub_lib::ui_gateway::{FromUiMessage, UiCarrierMessage};
+    use crate::sub_lib::ui_gateway::{FromUiMessage, UiCarrierMessage, UiDisconnectMessage};
     use crate::test_utils::rate_pack;
     use crate::test_utils::recorder::Recorder;
     use crate::test_utils::recorder::Recording;
@@ -481,12 +523,24 @@ mod tests {
             cryptde: &'a dyn CryptDE,
             is_decentralized: bool,
             consuming_wallet_balance: Option<i64>,
+            route_path_count: usize,
+            proxy_port_opt: Option<u16>,
+            bypass_list: BypassList,
+            bypass_dns_servers: Vec<SocketAddr>,
         ) -> ProxyServerSubs {
             self.parameters
                 .proxy_server_params
                 .lock()
                 .unwrap()
-                .get_or_insert((cryptde, is_decentralized, consuming_wallet_balance));
+                .get_or_insert((
+                    cryptde,
+                    is_decentralized,
+                    consuming_wallet_balance,
+                    route_path_count,
+                    proxy_port_opt,
+                    bypass_list,
+                    bypass_dns_servers,
+                ));
             let addr: Addr<Recorder> = ActorFactoryMock::start_recorder(&self.proxy_server);
             ProxyServerSubs {
                 bind: recipient!(addr, BindMessage),
@@ -497,10 +551,19 @@ mod tests {
                 dns_failure_from_hopper: addr
                     .clone()
                     .recipient::<ExpiredCoresPackage<DnsResolveFailure>>(),
+                exit_policy_refusal_from_hopper: addr
+                    .clone()
+                    .recipient::<ExpiredCoresPackage<ExitPolicyRefusal>>(),
+                dns_answer_from_hopper: addr
+                    .clone()
+                    .recipient::<ExpiredCoresPackage<DnsAnswerPayload>>(),
+                dns_query_from_entry_dns: recipient!(addr, TunneledDnsQuery),
                 add_return_route: recipient!(addr, AddReturnRouteMessage),
                 add_route: recipient!(addr, AddRouteMessage),
                 stream_shutdown_sub: recipient!(addr, StreamShutdownMsg),
                 set_consuming_wallet_sub: recipient!(addr, SetConsumingWalletMessage),
+                invalidate_routes: recipient!(addr, InvalidateRoutesMessage),
+                direct_server_data: recipient!(addr, InboundServerData),
             }
         }
 
@@ -537,6 +600,7 @@ mod tests {
                 start: recipient!(addr, StartMessage),
                 node_query: recipient!(addr, NodeQueryMessage),
                 route_query: recipient!(addr, RouteQueryMessage),
+                disjoint_route_query: recipient!(addr, DisjointRouteQueryMessage),
                 update_node_record_metadata: recipient!(addr, NodeRecordMetadataMessage),
                 from_hopper: addr.clone().recipient::<ExpiredCoresPackage<Gossip>>(),
                 dispatcher_node_query: recipient!(addr, DispatcherNodeQueryMessage),
@@ -544,6 +608,10 @@ mod tests {
                 stream_shutdown_sub: recipient!(addr, StreamShutdownMsg),
                 set_consuming_wallet_sub: recipient!(addr, SetConsumingWalletMessage),
                 from_ui_gateway: addr.clone().recipient::<NeighborhoodDotGraphRequest>(),
+                neighborhood_view_sub: recipient!(addr, NeighborhoodViewRequest),
+                set_rate_pack_sub: recipient!(addr, SetRatePackMsg),
+                set_neighborhood_mode_sub: recipient!(addr, SetNeighborhoodModeMsg),
+                set_earning_wallet_sub: recipient!(addr, SetEarningWalletMessage),
             }
         }
 
@@ -580,6 +648,7 @@ mod tests {
                 get_financial_statistics_sub: addr
                     .clone()
                     .recipient::<GetFinancialStatisticsMessage>(),
+                set_earning_wallet_sub: recipient!(addr, SetEarningWalletMsg),
             }
         }
 
@@ -594,6 +663,7 @@ mod tests {
                 bind: recipient!(addr, BindMessage),
                 ui_message_sub: recipient!(addr, UiCarrierMessage),
                 from_ui_message_sub: recipient!(addr, FromUiMessage),
+                ui_disconnect_sub: recipient!(addr, UiDisconnectMessage),
             }
         }
 
@@ -625,6 +695,12 @@ mod tests {
                     .recipient::<ExpiredCoresPackage<ClientRequestPayload>>(),
                 inbound_server_data: recipient!(addr, InboundServerData),
                 dns_resolve_failed: recipient!(addr, DnsResolveFailure),
+                exit_policy_refused: recipient!(addr, ExitPolicyRefusal),
+                dns_query_from_hopper: addr
+                    .clone()
+                    .recipient::<ExpiredCoresPackage<DnsQueryPayload>>(),
+                set_dns_servers_sub: recipient!(addr, SetDnsServersMsg),
+                dns_cache_stats_sub: recipient!(addr, GetDnsCacheStatsMsg),
             }
         }
 
@@ -664,7 +740,7 @@ mod tests {
     #[derive(Clone)]
     struct Parameters<'a> {
         proxy_client_params: Arc<Mutex<Option<(ProxyClientConfig)>>>,
-        proxy_server_params: Arc<Mutex<Option<(&'a dyn CryptDE, bool, Option<i64>)>>>,
+        proxy_server_params: Arc<Mutex<Option<ProxyServerParameters<'a>>>>,
         hopper_params: Arc<Mutex<Option<HopperConfig>>>,
         neighborhood_params: Arc<Mutex<Option<(&'a dyn CryptDE, BootstrapperConfig)>>>,
         accountant_params: Arc<Mutex<Option<(BootstrapperConfig, PathBuf)>>>,
@@ -672,6 +748,16 @@ mod tests {
         blockchain_bridge_params: Arc<Mutex<Option<BootstrapperConfig>>>,
     }
 
+    type ProxyServerParameters<'a> = (
+        &'a dyn CryptDE,
+        bool,
+        Option<i64>,
+        usize,
+        Option<u16>,
+        BypassList,
+        Vec<SocketAddr>,
+    );
+
     impl<'a> Parameters<'a> {
         pub fn new() -> Parameters<'a> {
             Parameters {
@@ -917,9 +1003,14 @@ mod tests {
         let actor_factory = ActorFactoryMock::new();
         let recordings = actor_factory.get_recordings();
         let config = BootstrapperConfig {
-            log_level: LevelFilter::Off,
+            log_level: LogLevelSpec::new(LevelFilter::Off),
+            log_format: LogFormat::Text,
+            log_rotation: LogRotation::default(),
+            privacy_logging: false,
+            stream_tracing: false,
             crash_point: CrashPoint::None,
             dns_servers: vec![],
+            dns_protocol: DnsProtocol::default(),
             neighborhood_config: NeighborhoodConfig {
                 mode: NeighborhoodMode::Standard(
                     NodeAddr::new(&IpAddr::V4(Ipv4Addr::new(1, 2, 3, 4)), &vec![]),
@@ -934,7 +1025,9 @@ mod tests {
             clandestine_discriminator_factories: Vec::new(),
             ui_gateway_config: UiGatewayConfig {
                 ui_port: 5335,
+                metrics_port_opt: Some(5336),
                 node_descriptor: String::from(""),
+                session_token: String::new(),
             },
             blockchain_bridge_config: BlockchainBridgeConfig {
                 blockchain_service_url: None,
@@ -948,6 +1041,14 @@ mod tests {
             data_directory: PathBuf::new(),
             cryptde_null_opt: None,
             real_user: RealUser::null(),
+            route_path_count: 1,
+            proxy_port_opt: None,
+            exit_policy: ExitPolicy::default(),
+            exit_connection_pool: None,
+            entry_dns_mode: EntryDnsMode::Localhost,
+            entry_dns_tunnel: EntryDnsTunnel::new(),
+            bypass_list: BypassList::default(),
+            bypass_dns_servers: vec![],
         };
         Bootstrapper::pub_initialize_cryptde_for_testing(&Some(cryptde().clone()));
         let subject = ActorSystemFactoryReal {};
@@ -976,9 +1077,14 @@ mod tests {
         let recordings = actor_factory.get_recordings();
         let parameters = actor_factory.make_parameters();
         let config = BootstrapperConfig {
-            log_level: LevelFilter::Off,
+            log_level: LogLevelSpec::new(LevelFilter::Off),
+            log_format: LogFormat::Text,
+            log_rotation: LogRotation::default(),
+            privacy_logging: false,
+            stream_tracing: false,
             crash_point: CrashPoint::None,
             dns_servers: vec![],
+            dns_protocol: DnsProtocol::Tls("dns.example.com".to_string()),
             neighborhood_config: NeighborhoodConfig {
                 mode: NeighborhoodMode::ZeroHop,
             },
@@ -989,7 +1095,9 @@ mod tests {
             clandestine_discriminator_factories: Vec::new(),
             ui_gateway_config: UiGatewayConfig {
                 ui_port: 5335,
+                metrics_port_opt: Some(5336),
                 node_descriptor: String::from("NODE-DESCRIPTOR"),
+                session_token: String::new(),
             },
             blockchain_bridge_config: BlockchainBridgeConfig {
                 blockchain_service_url: None,
@@ -1003,6 +1111,17 @@ mod tests {
             data_directory: PathBuf::new(),
             cryptde_null_opt: None,
             real_user: RealUser::null(),
+            route_path_count: 1,
+            proxy_port_opt: Some(8888),
+            exit_policy: ExitPolicy::default(),
+            exit_connection_pool: Some(ConnectionPoolConfig {
+                max_idle_per_server: 6,
+                idle_timeout: Duration::from_secs(5),
+            }),
+            entry_dns_mode: EntryDnsMode::Tunnel,
+            entry_dns_tunnel: EntryDnsTunnel::new(),
+            bypass_list: BypassList::new(vec![ExitRule::PrivateNetworks]),
+            bypass_dns_servers: vec![SocketAddr::from_str("192.168.1.1:53").unwrap()],
         };
         let (tx, rx) = mpsc::channel();
         let system = System::new("SubstratumNode");
@@ -1033,11 +1152,29 @@ mod tests {
         assert_eq!(proxy_client_config.exit_service_rate, 0);
         assert_eq!(proxy_client_config.exit_byte_rate, 0);
         assert_eq!(proxy_client_config.dns_servers, config.dns_servers);
-        let (actual_cryptde, actual_is_decentralized, consuming_wallet_balance) =
-            Parameters::get(parameters.proxy_server_params);
+        assert_eq!(proxy_client_config.dns_protocol, config.dns_protocol);
+        assert_eq!(proxy_client_config.exit_policy, config.exit_policy);
+        assert_eq!(
+            proxy_client_config.exit_connection_pool,
+            config.exit_connection_pool
+        );
+        let (
+            actual_cryptde,
+            actual_is_decentralized,
+            consuming_wallet_balance,
+            actual_route_path_count,
+            actual_proxy_port_opt,
+            actual_bypass_list,
+            actual_bypass_dns_servers,
+        ) = Parameters::get(parameters.proxy_server_params);
         check_cryptde(actual_cryptde);
         assert_eq!(actual_is_decentralized, false);
         assert_eq!(consuming_wallet_balance, Some(0));
+        assert_eq!(actual_route_path_count, config.route_path_count);
+        assert_eq!(actual_proxy_port_opt, Some(8888));
+        assert_eq!(actual_bypass_list, config.bypass_list);
+        assert_eq!(actual_bypass_dns_servers, config.bypass_dns_servers);
+        assert!(config.entry_dns_tunnel.recipient_opt().is_some());
         let (cryptde, neighborhood_config) = Parameters::get(parameters.neighborhood_params);
         check_cryptde(cryptde);
         assert_eq!(
@@ -1050,6 +1187,7 @@ mod tests {
         );
         let ui_gateway_config = Parameters::get(parameters.ui_gateway_params);
         assert_eq!(ui_gateway_config.ui_port, 5335);
+        assert_eq!(ui_gateway_config.metrics_port_opt, Some(5336));
         assert_eq!(ui_gateway_config.node_descriptor, "NODE-DESCRIPTOR");
         let bootstrapper_config = Parameters::get(parameters.blockchain_bridge_params);
         assert_eq!(
@@ -1074,9 +1212,14 @@ mod tests {
         let actor_factory = ActorFactoryMock::new();
         let parameters = actor_factory.make_parameters();
         let config = BootstrapperConfig {
-            log_level: LevelFilter::Off,
+            log_level: LogLevelSpec::new(LevelFilter::Off),
+            log_format: LogFormat::Text,
+            log_rotation: LogRotation::default(),
+            privacy_logging: false,
+            stream_tracing: false,
             crash_point: CrashPoint::None,
             dns_servers: vec![],
+            dns_protocol: DnsProtocol::default(),
             neighborhood_config: NeighborhoodConfig {
                 mode: NeighborhoodMode::Standard(
                     NodeAddr::new(&IpAddr::V4(Ipv4Addr::new(1, 2, 3, 4)), &vec![]),
@@ -1091,7 +1234,9 @@ mod tests {
             clandestine_discriminator_factories: Vec::new(),
             ui_gateway_config: UiGatewayConfig {
                 ui_port: 5335,
+                metrics_port_opt: Some(5336),
                 node_descriptor: String::from("NODE-DESCRIPTOR"),
+                session_token: String::new(),
             },
             blockchain_bridge_config: BlockchainBridgeConfig {
                 blockchain_service_url: None,
@@ -1105,6 +1250,14 @@ mod tests {
             data_directory: PathBuf::new(),
             cryptde_null_opt: None,
             real_user: RealUser::null(),
+            route_path_count: 1,
+            proxy_port_opt: None,
+            exit_policy: ExitPolicy::default(),
+            exit_connection_pool: None,
+            entry_dns_mode: EntryDnsMode::Localhost,
+            entry_dns_tunnel: EntryDnsTunnel::new(),
+            bypass_list: BypassList::default(),
+            bypass_dns_servers: vec![],
         };
         let (tx, _) = mpsc::channel();
         let system = System::new("SubstratumNode");
@@ -1118,8 +1271,10 @@ mod tests {
 
         System::current().stop();
         system.run();
-        let (_, _, consuming_wallet_balance) = Parameters::get(parameters.proxy_server_params);
+        let (_, _, consuming_wallet_balance, _, _, _, _) =
+            Parameters::get(parameters.proxy_server_params);
         assert_eq!(consuming_wallet_balance, None);
+        assert!(config.entry_dns_tunnel.recipient_opt().is_none());
     }
 
     fn check_bind_message(recording: &Arc<Mutex<Recording>>) {
@@ -13,8 +13,9 @@ use crate::sub_lib::blockchain_bridge::SetWalletPasswordMsg;
 use crate::sub_lib::blockchain_bridge::{BlockchainBridgeSubs, SetGasPriceMsg};
 use crate::sub_lib::logger::Logger;
 use crate::sub_lib::peer_actors::BindMessage;
+use crate::sub_lib::privacy;
 use crate::sub_lib::set_consuming_wallet_message::SetConsumingWalletMessage;
-use crate::sub_lib::ui_gateway::{UiCarrierMessage, UiMessage};
+use crate::sub_lib::ui_gateway::{UiCarrierMessage, UiEvent, UiMessage};
 use crate::sub_lib::wallet::Wallet;
 use actix::Context;
 use actix::Handler;
@@ -52,9 +53,10 @@ impl Handler<BindMessage> for BlockchainBridge {
                 .clone(),
         ]);
         match self.consuming_wallet.as_ref() {
-            Some(wallet) => debug!(
+            Some(privacy::wallet(wallet)) => debug!(
                 self.logger,
-                "Received BindMessage; consuming wallet address {}", wallet
+                "Received BindMessage; consuming wallet address {}",
+                privacy::wallet(wallet)
             ),
             None => debug!(
                 self.logger,
@@ -157,6 +159,7 @@ impl Handler<SetGasPriceMsg> for BlockchainBridge {
             .expect("UiGateway is unbound")
             .try_send(UiCarrierMessage {
                 client_id: msg.client_id,
+                context_id: Some(msg.context_id),
                 data: UiMessage::SetGasPriceResponse(gas_price_accepted),
             })
             .expect("UiGateway is dead");
@@ -168,14 +171,28 @@ impl Handler<SetWalletPasswordMsg> for BlockchainBridge {
 
     fn handle(&mut self, msg: SetWalletPasswordMsg, _ctx: &mut Self::Context) -> Self::Result {
         let password_accepted = self.accept_wallet_password(&msg.password);
-        self.ui_carrier_message_sub
+        let ui_carrier_message_sub = self
+            .ui_carrier_message_sub
             .as_ref()
-            .expect("UiGateway is unbound")
+            .expect("UiGateway is unbound");
+        ui_carrier_message_sub
             .try_send(UiCarrierMessage {
                 client_id: msg.client_id,
+                context_id: Some(msg.context_id),
                 data: UiMessage::SetWalletPasswordResponse(password_accepted),
             })
-            .expect("UiGateway is dead")
+            .expect("UiGateway is dead");
+        if password_accepted {
+            let consuming_wallet = self
+                .consuming_wallet
+                .as_ref()
+                .expect("Consuming wallet disappeared");
+            ui_carrier_message_sub
+                .try_send(UiCarrierMessage::event(UiEvent::WalletUnlocked(
+                    consuming_wallet.to_string(),
+                )))
+                .expect("UiGateway is dead")
+        }
     }
 }
 
@@ -244,7 +261,8 @@ impl BlockchainBridge {
                 self.consuming_wallet = Some(consuming_wallet);
                 debug!(
                     self.logger,
-                    "unlocked consuming wallet address {:?}", &self.consuming_wallet
+                    "unlocked consuming wallet address {:?}",
+                    privacy::data(&self.consuming_wallet)
                 );
                 true
             }
@@ -367,6 +385,7 @@ mod tests {
             .unwrap();
             addr.try_send(SetWalletPasswordMsg {
                 client_id: 42,
+                context_id: 4321,
                 password: password.to_string(),
             })
             .unwrap();
@@ -374,16 +393,23 @@ mod tests {
             system.run();
         });
 
-        ui_gateway_awaiter.await_message_count(1);
+        ui_gateway_awaiter.await_message_count(2);
 
         let ui_gateway_recording = ui_gateway_recording_arc.lock().unwrap();
         assert_eq!(
             ui_gateway_recording.get_record::<UiCarrierMessage>(0),
             &UiCarrierMessage {
                 client_id: 42,
+                context_id: Some(4321),
                 data: UiMessage::SetWalletPasswordResponse(true),
             }
         );
+        assert_eq!(
+            ui_gateway_recording.get_record::<UiCarrierMessage>(1),
+            &UiCarrierMessage::event(UiEvent::WalletUnlocked(
+                expected_wallet.as_ref().unwrap().to_string()
+            ))
+        );
         TestLogHandler::new().exists_log_containing(&format!(
             "unlocked consuming wallet address {:?}",
             &expected_wallet
@@ -437,6 +463,7 @@ mod tests {
             .unwrap();
             addr.try_send(SetWalletPasswordMsg {
                 client_id: 42,
+                context_id: 4321,
                 password: "ihatecheetos".to_string(),
             })
             .unwrap();
@@ -451,6 +478,7 @@ mod tests {
             ui_gateway_recording.get_record::<UiCarrierMessage>(0),
             &UiCarrierMessage {
                 client_id: 42,
+                context_id: Some(4321),
                 data: UiMessage::SetWalletPasswordResponse(false),
             }
         );
@@ -484,6 +512,7 @@ mod tests {
             .unwrap();
             addr.try_send(SetWalletPasswordMsg {
                 client_id: 42,
+                context_id: 4321,
                 password: "ilikecheetos".to_string(),
             })
             .unwrap();
@@ -498,6 +527,7 @@ mod tests {
             ui_gateway_recording.get_record::<UiCarrierMessage>(0),
             &UiCarrierMessage {
                 client_id: 42,
+                context_id: Some(4321),
                 data: UiMessage::SetWalletPasswordResponse(false),
             }
         );
@@ -529,6 +559,7 @@ mod tests {
             .unwrap();
             addr.try_send(SetWalletPasswordMsg {
                 client_id: 42,
+                context_id: 4321,
                 password: "ilikecheetos".to_string(),
             })
             .unwrap();
@@ -543,6 +574,7 @@ mod tests {
             ui_gateway_recording.get_record::<UiCarrierMessage>(0),
             &UiCarrierMessage {
                 client_id: 42,
+                context_id: Some(4321),
                 data: UiMessage::SetWalletPasswordResponse(false),
             }
         );
@@ -576,6 +608,7 @@ mod tests {
             .unwrap();
             addr.try_send(SetWalletPasswordMsg {
                 client_id: 42,
+                context_id: 4321,
                 password: "ilikecheetos".to_string(),
             })
             .unwrap();
@@ -590,6 +623,7 @@ mod tests {
             ui_gateway_recording.get_record::<UiCarrierMessage>(0),
             &UiCarrierMessage {
                 client_id: 42,
+                context_id: Some(4321),
                 data: UiMessage::SetWalletPasswordResponse(false),
             }
         );
@@ -677,6 +711,7 @@ mod tests {
             .unwrap();
             addr.try_send(SetGasPriceMsg {
                 client_id: 41,
+                context_id: 4321,
                 gas_price: "99".to_string(),
             })
             .unwrap();
@@ -692,6 +727,7 @@ mod tests {
             ui_gateway_recording.get_record::<UiCarrierMessage>(0),
             &UiCarrierMessage {
                 client_id: 41,
+                context_id: Some(4321),
                 data: UiMessage::SetGasPriceResponse(true),
             }
         );
@@ -722,6 +758,7 @@ mod tests {
             .unwrap();
             addr.try_send(SetGasPriceMsg {
                 client_id: 41,
+                context_id: 4321,
                 gas_price: "0xf".to_string(),
             })
             .unwrap();
@@ -737,6 +774,7 @@ mod tests {
             ui_gateway_recording.get_record::<UiCarrierMessage>(0),
             &UiCarrierMessage {
                 client_id: 41,
+                context_id: Some(4321),
                 data: UiMessage::SetGasPriceResponse(false),
             }
         );
@@ -2,6 +2,7 @@
 
 use crate::blockchain::raw_transaction::RawTransaction;
 use crate::sub_lib::logger::Logger;
+use crate::sub_lib::privacy;
 use crate::sub_lib::wallet::Wallet;
 use actix::Message;
 use futures::{future, Future};
@@ -210,7 +211,7 @@ impl BlockchainInterface for BlockchainInterfaceClandestine {
         error!(
             self.logger,
             "Could not get eth balance for {:?} since blockchain_service_url was not specified",
-            address
+            privacy::data(address)
         );
         Ok(0.into())
     }
@@ -219,7 +220,7 @@ impl BlockchainInterface for BlockchainInterfaceClandestine {
         error!(
             self.logger,
             "Could not get token balance for {:?} since blockchain_service_url was not specified",
-            address
+            privacy::data(address)
         );
         Ok(0.into())
     }
@@ -262,7 +263,7 @@ where
             self.logger,
             "Retrieving transactions from start block: {} for: {} chain_id: {} contract: {:#x}",
             start_block,
-            recipient,
+            privacy::wallet(recipient),
             self.chain_id,
             self.contract_address()
         );
@@ -325,8 +326,8 @@ where
             self.logger,
             "Sending transaction for {} Gwei to {} from {}: (chain_id: {} contract: {:#x})",
             amount,
-            recipient,
-            consuming_wallet,
+            privacy::wallet(recipient),
+            privacy::wallet(consuming_wallet),
             self.chain_id,
             self.contract_address()
         );
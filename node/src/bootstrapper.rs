@@ -17,23 +17,31 @@ use crate::node_configurator::node_configurator_standard::{
 use crate::node_configurator::{DirsWrapper, NodeConfigurator, RealDirsWrapper};
 use crate::persistent_configuration::{PersistentConfiguration, PersistentConfigurationReal};
 use crate::privilege_drop::{IdWrapper, IdWrapperReal};
-use crate::server_initializer::LoggerInitializerWrapper;
+use crate::server_initializer::{LogFormat, LogLevelSpec, LogRotation, LoggerInitializerWrapper};
 use crate::sub_lib::accountant;
 use crate::sub_lib::accountant::AccountantConfig;
 use crate::sub_lib::blockchain_bridge::BlockchainBridgeConfig;
+use crate::sub_lib::bypass::BypassList;
 use crate::sub_lib::crash_point::CrashPoint;
 use crate::sub_lib::cryptde::CryptDE;
 use crate::sub_lib::cryptde_null::CryptDENull;
 use crate::sub_lib::cryptde_real::CryptDEReal;
+use crate::sub_lib::entry_dns::{EntryDnsMode, EntryDnsTunnel};
+use crate::sub_lib::exit_policy::ExitPolicy;
 use crate::sub_lib::logger::Logger;
 use crate::sub_lib::main_tools::StdStreams;
 use crate::sub_lib::neighborhood::NodeDescriptor;
 use crate::sub_lib::neighborhood::{NeighborhoodConfig, NeighborhoodMode};
 use crate::sub_lib::node_addr::NodeAddr;
+use crate::sub_lib::privacy;
+use crate::sub_lib::proxy_client::ConnectionPoolConfig;
+use crate::sub_lib::proxy_client::DnsProtocol;
 use crate::sub_lib::socket_server::SocketServer;
+use crate::sub_lib::stream_trace::{self, STREAM_TRACES_FILE};
 use crate::sub_lib::ui_gateway::UiGatewayConfig;
 use crate::sub_lib::ui_gateway::DEFAULT_UI_PORT;
 use crate::sub_lib::wallet::Wallet;
+use crate::ui_gateway::session_token::{generate_session_token, write_session_token};
 use futures::try_ready;
 use itertools::Itertools;
 use log::LevelFilter;
@@ -41,7 +49,7 @@ use std::collections::HashMap;
 use std::env::var;
 use std::fmt::{Debug, Error, Formatter};
 use std::net::SocketAddr;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
 use std::time::Duration;
 use std::vec::Vec;
@@ -228,8 +236,13 @@ impl RealUser {
 #[derive(Clone, Debug)]
 pub struct BootstrapperConfig {
     // These fields can be set while privileged without penalty
-    pub log_level: LevelFilter,
+    pub log_level: LogLevelSpec,
+    pub log_format: LogFormat,
+    pub log_rotation: LogRotation,
+    pub privacy_logging: bool,
+    pub stream_tracing: bool,
     pub dns_servers: Vec<SocketAddr>,
+    pub dns_protocol: DnsProtocol,
     pub neighborhood_config: NeighborhoodConfig,
     pub accountant_config: AccountantConfig,
     pub crash_point: CrashPoint,
@@ -240,6 +253,14 @@ pub struct BootstrapperConfig {
     pub data_directory: PathBuf,
     pub cryptde_null_opt: Option<CryptDENull>,
     pub real_user: RealUser,
+    pub route_path_count: usize,
+    pub proxy_port_opt: Option<u16>,
+    pub exit_policy: ExitPolicy,
+    pub exit_connection_pool: Option<ConnectionPoolConfig>,
+    pub entry_dns_mode: EntryDnsMode,
+    pub entry_dns_tunnel: EntryDnsTunnel,
+    pub bypass_list: BypassList,
+    pub bypass_dns_servers: Vec<SocketAddr>,
 
     // These fields must be set without privilege: otherwise the database will be created as root
     pub clandestine_port_opt: Option<u16>,
@@ -257,8 +278,13 @@ impl BootstrapperConfig {
     pub fn new() -> BootstrapperConfig {
         BootstrapperConfig {
             // These fields can be set while privileged without penalty
-            log_level: LevelFilter::Off,
+            log_level: LogLevelSpec::new(LevelFilter::Off),
+            log_format: LogFormat::Text,
+            log_rotation: LogRotation::default(),
+            privacy_logging: false,
+            stream_tracing: false,
             dns_servers: vec![],
+            dns_protocol: DnsProtocol::default(),
             neighborhood_config: NeighborhoodConfig {
                 mode: NeighborhoodMode::ZeroHop,
             },
@@ -272,7 +298,9 @@ impl BootstrapperConfig {
             clandestine_discriminator_factories: vec![],
             ui_gateway_config: UiGatewayConfig {
                 ui_port: DEFAULT_UI_PORT,
+                metrics_port_opt: None,
                 node_descriptor: String::from(""),
+                session_token: String::new(),
             },
             blockchain_bridge_config: BlockchainBridgeConfig {
                 blockchain_service_url: None,
@@ -283,6 +311,14 @@ impl BootstrapperConfig {
             data_directory: PathBuf::new(),
             cryptde_null_opt: None,
             real_user: RealUser::null(),
+            route_path_count: 1,
+            proxy_port_opt: None,
+            exit_policy: ExitPolicy::default(),
+            exit_connection_pool: None,
+            entry_dns_mode: EntryDnsMode::default(),
+            entry_dns_tunnel: EntryDnsTunnel::new(),
+            bypass_list: BypassList::default(),
+            bypass_dns_servers: vec![],
 
             // These fields must be set without privilege: otherwise the database will be created as root
             clandestine_port_opt: None,
@@ -304,6 +340,7 @@ pub struct Bootstrapper {
     listener_handlers: FuturesUnordered<Box<dyn ListenerHandler<Item = (), Error = ()>>>,
     actor_system_factory: Box<dyn ActorSystemFactory>,
     logger_initializer: Box<dyn LoggerInitializerWrapper>,
+    entry_dns_tunnel: EntryDnsTunnel,
     config: BootstrapperConfig,
 }
 
@@ -328,11 +365,19 @@ impl SocketServer<BootstrapperConfig> for Bootstrapper {
 
     fn initialize_as_privileged(&mut self, args: &Vec<String>, streams: &mut StdStreams) {
         self.config = NodeConfiguratorStandardPrivileged {}.configure(args, streams);
+        self.config.entry_dns_tunnel = self.entry_dns_tunnel.clone();
+        self.entry_dns_tunnel.set_bypass(
+            self.config.bypass_list.clone(),
+            self.config.bypass_dns_servers.clone(),
+        );
 
+        privacy::set_privacy_logging(self.config.privacy_logging);
         self.logger_initializer.init(
             self.config.data_directory.clone(),
             &self.config.real_user,
-            self.config.log_level,
+            &self.config.log_level,
+            self.config.log_format,
+            self.config.log_rotation,
         );
         self.listener_handlers =
             FuturesUnordered::<Box<dyn ListenerHandler<Item = (), Error = ()>>>::new();
@@ -369,6 +414,11 @@ impl SocketServer<BootstrapperConfig> for Bootstrapper {
             streams,
             self.config.blockchain_bridge_config.chain_id,
         );
+        self.config.ui_gateway_config.session_token =
+            Bootstrapper::establish_ui_session_token(&self.config.data_directory);
+        if self.config.stream_tracing {
+            Bootstrapper::establish_stream_tracing(&self.config.data_directory);
+        }
         let stream_handler_pool_subs = self
             .actor_system_factory
             .make_and_start_actors(self.config.clone(), Box::new(ActorFactoryReal {}));
@@ -380,13 +430,17 @@ impl SocketServer<BootstrapperConfig> for Bootstrapper {
 }
 
 impl Bootstrapper {
-    pub fn new(logger_initializer: Box<dyn LoggerInitializerWrapper>) -> Bootstrapper {
+    pub fn new(
+        logger_initializer: Box<dyn LoggerInitializerWrapper>,
+        entry_dns_tunnel: EntryDnsTunnel,
+    ) -> Bootstrapper {
         Bootstrapper {
             listener_handler_factory: Box::new(ListenerHandlerFactoryReal::new()),
             listener_handlers:
                 FuturesUnordered::<Box<dyn ListenerHandler<Item = (), Error = ()>>>::new(),
             actor_system_factory: Box::new(ActorSystemFactoryReal {}),
             logger_initializer,
+            entry_dns_tunnel,
             config: BootstrapperConfig::new(),
         }
     }
@@ -431,6 +485,33 @@ impl Bootstrapper {
         descriptor
     }
 
+    fn establish_ui_session_token(data_directory: &Path) -> String {
+        let token = generate_session_token();
+        let path = write_session_token(data_directory, &token).unwrap_or_else(|e| {
+            panic!(
+                "Could not write UI session token into {:?}: {}",
+                data_directory, e
+            )
+        });
+        info!(
+            Logger::new("Bootstrapper"),
+            "UI session token written to {}",
+            path.display()
+        );
+        token
+    }
+
+    fn establish_stream_tracing(data_directory: &Path) {
+        let path = data_directory.join(STREAM_TRACES_FILE);
+        stream_trace::enable(&path)
+            .unwrap_or_else(|e| panic!("Could not start tracing streams: {}", e));
+        info!(
+            Logger::new("Bootstrapper"),
+            "Stream traces will be written to {}",
+            path.display()
+        );
+    }
+
     fn establish_clandestine_port(&mut self) {
         if let NeighborhoodMode::Standard(node_addr, neighbor_configs, rate_pack) =
             &self.config.neighborhood_config.mode
@@ -485,10 +566,12 @@ mod tests {
     use crate::node_test_utils::{extract_log, IdWrapperMock, MockDirsWrapper};
     use crate::persistent_configuration::{PersistentConfiguration, PersistentConfigurationReal};
     use crate::server_initializer::test_utils::LoggerInitializerWrapperMock;
+    use crate::server_initializer::LogRotationTrigger;
     use crate::stream_handler_pool::StreamHandlerPoolSubs;
     use crate::stream_messages::AddStreamMsg;
     use crate::sub_lib::cryptde::PlainData;
     use crate::sub_lib::cryptde::PublicKey;
+    use crate::sub_lib::exit_policy::ExitRule;
     use crate::sub_lib::neighborhood::{NeighborhoodMode, NodeDescriptor};
     use crate::sub_lib::node_addr::NodeAddr;
     use crate::sub_lib::stream_connector::ConnectionInfo;
@@ -504,6 +587,8 @@ mod tests {
         assert_contains, ensure_node_home_directory_exists, rate_pack, ArgsBuilder,
     };
     use crate::test_utils::{cryptde, FakeStreamHolder, DEFAULT_CHAIN_ID};
+    use crate::ui_gateway::session_token::UI_SESSION_TOKEN_FILE;
+    use actix::Actor;
     use actix::Recipient;
     use actix::System;
     use lazy_static::lazy_static;
@@ -800,13 +885,17 @@ mod tests {
         listener_handler_factory.add(Box::new(
             ListenerHandlerNull::new(vec![]).bind_port_result(Ok(())),
         ));
-        let mut subject = Bootstrapper::new(Box::new(logger_initializer));
+        let mut subject = Bootstrapper::new(Box::new(logger_initializer), EntryDnsTunnel::new());
         subject.listener_handler_factory = Box::new(listener_handler_factory);
         let args = ArgsBuilder::new()
             .param("--data-directory", data_dir.to_str().unwrap())
             .param("--dns-servers", "1.1.1.1")
             .param("--ip", "2.2.2.2")
-            .param("--real-user", "123:456:/home/booga");
+            .param("--real-user", "123:456:/home/booga")
+            .param("--log-level", "warn,Neighborhood=trace")
+            .param("--log-format", "json")
+            .param("--log-rotation-age", "day")
+            .param("--log-retention", "7");
 
         subject.initialize_as_privileged(&args.into(), &mut FakeStreamHolder::new().streams());
 
@@ -816,11 +905,125 @@ mod tests {
             vec![(
                 data_dir,
                 RealUser::new(Some(123), Some(456), Some("/home/booga".into())),
-                LevelFilter::Warn
+                LogLevelSpec {
+                    default: LevelFilter::Warn,
+                    loggers: vec![("Neighborhood".to_string(), LevelFilter::Trace)],
+                },
+                LogFormat::Json,
+                LogRotation {
+                    trigger: LogRotationTrigger::Daily,
+                    keep: 7,
+                },
             )]
         )
     }
 
+    #[test]
+    fn initialize_as_privileged_shares_the_entry_dns_tunnel_with_the_actors() {
+        let _lock = INITIALIZATION.lock();
+        let data_dir = ensure_node_home_directory_exists(
+            "bootstrapper",
+            "initialize_as_privileged_shares_the_entry_dns_tunnel_with_the_actors",
+        );
+        let _system =
+            System::new("initialize_as_privileged_shares_the_entry_dns_tunnel_with_the_actors");
+        let (proxy_server, _, _) = make_recorder();
+        let mut listener_handler_factory = ListenerHandlerFactoryMock::new();
+        listener_handler_factory.add(Box::new(
+            ListenerHandlerNull::new(vec![]).bind_port_result(Ok(())),
+        ));
+        listener_handler_factory.add(Box::new(
+            ListenerHandlerNull::new(vec![]).bind_port_result(Ok(())),
+        ));
+        let entry_dns_tunnel = EntryDnsTunnel::new();
+        let mut subject = Bootstrapper::new(
+            Box::new(LoggerInitializerWrapperMock::new()),
+            entry_dns_tunnel.clone(),
+        );
+        subject.listener_handler_factory = Box::new(listener_handler_factory);
+        let args = ArgsBuilder::new()
+            .param("--data-directory", data_dir.to_str().unwrap())
+            .param("--dns-servers", "1.1.1.1")
+            .param("--ip", "2.2.2.2");
+
+        subject.initialize_as_privileged(&args.into(), &mut FakeStreamHolder::new().streams());
+
+        subject
+            .config
+            .entry_dns_tunnel
+            .connect(proxy_server.start().recipient(), EntryDnsMode::Tunnel);
+        assert!(entry_dns_tunnel.recipient_opt().is_some());
+    }
+
+    #[test]
+    fn initialize_as_privileged_hands_the_bypass_list_to_entry_dns() {
+        let _lock = INITIALIZATION.lock();
+        let data_dir = ensure_node_home_directory_exists(
+            "bootstrapper",
+            "initialize_as_privileged_hands_the_bypass_list_to_entry_dns",
+        );
+        let mut listener_handler_factory = ListenerHandlerFactoryMock::new();
+        listener_handler_factory.add(Box::new(
+            ListenerHandlerNull::new(vec![]).bind_port_result(Ok(())),
+        ));
+        listener_handler_factory.add(Box::new(
+            ListenerHandlerNull::new(vec![]).bind_port_result(Ok(())),
+        ));
+        let entry_dns_tunnel = EntryDnsTunnel::new();
+        let mut subject = Bootstrapper::new(
+            Box::new(LoggerInitializerWrapperMock::new()),
+            entry_dns_tunnel.clone(),
+        );
+        subject.listener_handler_factory = Box::new(listener_handler_factory);
+        let args = ArgsBuilder::new()
+            .param("--data-directory", data_dir.to_str().unwrap())
+            .param("--dns-servers", "1.1.1.1")
+            .param("--ip", "2.2.2.2")
+            .param("--bypass", "intranet.example.com")
+            .param("--bypass-dns-servers", "192.168.1.1");
+
+        subject.initialize_as_privileged(&args.into(), &mut FakeStreamHolder::new().streams());
+
+        assert_eq!(
+            entry_dns_tunnel.bypass(),
+            (
+                BypassList::new(vec![ExitRule::Host("*.intranet.example.com".to_string())]),
+                vec![SocketAddr::from_str("192.168.1.1:53").unwrap()]
+            )
+        );
+    }
+
+    #[test]
+    fn initialize_as_privileged_turns_on_privacy_logging() {
+        let _lock = INITIALIZATION.lock();
+        let data_dir = ensure_node_home_directory_exists(
+            "bootstrapper",
+            "initialize_as_privileged_turns_on_privacy_logging",
+        );
+        let mut listener_handler_factory = ListenerHandlerFactoryMock::new();
+        listener_handler_factory.add(Box::new(
+            ListenerHandlerNull::new(vec![]).bind_port_result(Ok(())),
+        ));
+        listener_handler_factory.add(Box::new(
+            ListenerHandlerNull::new(vec![]).bind_port_result(Ok(())),
+        ));
+        let mut subject = Bootstrapper::new(
+            Box::new(LoggerInitializerWrapperMock::new()),
+            EntryDnsTunnel::new(),
+        );
+        subject.listener_handler_factory = Box::new(listener_handler_factory);
+        let args = ArgsBuilder::new()
+            .param("--data-directory", data_dir.to_str().unwrap())
+            .param("--dns-servers", "1.1.1.1")
+            .param("--ip", "2.2.2.2")
+            .param("--privacy-logging", "on");
+
+        subject.initialize_as_privileged(&args.into(), &mut FakeStreamHolder::new().streams());
+
+        assert!(privacy::privacy_logging());
+        privacy::set_privacy_logging(false);
+    }
+
     #[test]
     fn initialize_as_unprivileged_passes_node_descriptor_to_ui_config() {
         let _lock = INITIALIZATION.lock();
@@ -851,6 +1054,75 @@ mod tests {
         assert!(!config.ui_gateway_config.node_descriptor.is_empty());
     }
 
+    #[test]
+    fn initialize_as_unprivileged_writes_ui_session_token_for_ui_gateway() {
+        let _lock = INITIALIZATION.lock();
+        let data_dir = ensure_node_home_directory_exists(
+            "bootstrapper",
+            "initialize_as_unprivileged_writes_ui_session_token_for_ui_gateway",
+        );
+        let mut config = BootstrapperConfig::new();
+        config.clandestine_port_opt = Some(1234);
+        config.data_directory = data_dir.clone();
+        let mut subject = BootstrapperBuilder::new()
+            .add_listener_handler(Box::new(
+                ListenerHandlerNull::new(vec![]).bind_port_result(Ok(())),
+            ))
+            .config(config)
+            .build();
+
+        subject.initialize_as_unprivileged(
+            &vec![
+                "SubstratumNode".to_string(),
+                String::from("--data-directory"),
+                data_dir.to_str().unwrap().to_string(),
+            ],
+            &mut FakeStreamHolder::new().streams(),
+        );
+
+        let token = subject.config.ui_gateway_config.session_token;
+        assert_eq!(token.len(), 64);
+        assert_eq!(
+            std::fs::read_to_string(data_dir.join(UI_SESSION_TOKEN_FILE)).unwrap(),
+            token
+        );
+    }
+
+    #[test]
+    fn initialize_as_unprivileged_starts_stream_tracing_if_directed() {
+        let _lock = INITIALIZATION.lock();
+        let data_dir = ensure_node_home_directory_exists(
+            "bootstrapper",
+            "initialize_as_unprivileged_starts_stream_tracing_if_directed",
+        );
+        let mut config = BootstrapperConfig::new();
+        config.clandestine_port_opt = Some(1234);
+        config.data_directory = data_dir.clone();
+        config.stream_tracing = true;
+        let mut subject = BootstrapperBuilder::new()
+            .add_listener_handler(Box::new(
+                ListenerHandlerNull::new(vec![]).bind_port_result(Ok(())),
+            ))
+            .config(config)
+            .build();
+
+        subject.initialize_as_unprivileged(
+            &vec![
+                "SubstratumNode".to_string(),
+                String::from("--data-directory"),
+                data_dir.to_str().unwrap().to_string(),
+            ],
+            &mut FakeStreamHolder::new().streams(),
+        );
+
+        assert!(stream_trace::stream_tracing());
+        assert_eq!(
+            std::fs::read_to_string(data_dir.join(STREAM_TRACES_FILE)).unwrap(),
+            "[\n"
+        );
+        stream_trace::disable();
+    }
+
     #[test]
     fn initialize_as_unprivileged_sets_gas_price_on_blockchain_config() {
         let _lock = INITIALIZATION.lock();
@@ -1711,6 +1983,7 @@ mod tests {
                     Box<dyn ListenerHandler<Item = (), Error = ()>>,
                 >::new(),
                 logger_initializer: self.log_initializer_wrapper,
+                entry_dns_tunnel: EntryDnsTunnel::new(),
                 config: self.config,
             }
         }
@@ -1,9 +1,12 @@
 // Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
 use crate::stream_messages::{PoolBindMessage, RemovedStreamType};
 use crate::sub_lib::dispatcher::InboundClientData;
-use crate::sub_lib::dispatcher::{DispatcherSubs, StreamShutdownMsg};
+use crate::sub_lib::dispatcher::{DispatcherSubs, Endpoint, StreamShutdownMsg};
 use crate::sub_lib::logger::Logger;
+use crate::sub_lib::metrics;
+use crate::sub_lib::metrics::{DISPATCHER_BYTES_RECEIVED, DISPATCHER_BYTES_SENT};
 use crate::sub_lib::peer_actors::BindMessage;
+use crate::sub_lib::privacy;
 use crate::sub_lib::stream_handler_pool::TransmitDataMsg;
 use crate::sub_lib::utils::NODE_MAILBOX_CAPACITY;
 use actix::Actor;
@@ -11,6 +14,8 @@ use actix::Addr;
 use actix::Context;
 use actix::Handler;
 use actix::Recipient;
+use std::collections::HashMap;
+use std::net::SocketAddr;
 
 struct DispatcherOutSubs {
     to_proxy_server: Recipient<InboundClientData>,
@@ -22,6 +27,7 @@ struct DispatcherOutSubs {
 pub struct Dispatcher {
     subs: Option<DispatcherOutSubs>,
     to_stream: Option<Recipient<TransmitDataMsg>>,
+    ports_by_peer: HashMap<SocketAddr, u16>,
     logger: Logger,
 }
 
@@ -56,6 +62,14 @@ impl Handler<InboundClientData> for Dispatcher {
     type Result = ();
 
     fn handle(&mut self, msg: InboundClientData, _ctx: &mut Self::Context) {
+        if let Some(port) = msg.reception_port {
+            self.ports_by_peer.insert(msg.peer_addr, port);
+        }
+        metrics::increment(
+            &DISPATCHER_BYTES_RECEIVED,
+            &[("port", &Self::port_label(msg.reception_port))],
+            msg.data.len() as u64,
+        );
         if msg.is_clandestine {
             self.subs
                 .as_ref()
@@ -82,7 +96,16 @@ impl Handler<TransmitDataMsg> for Dispatcher {
             self.logger,
             "Relaying {} bytes to StreamHandlerPool for {:?}",
             msg.data.len(),
-            msg.endpoint
+            privacy::data(&msg.endpoint)
+        );
+        let port_opt = match &msg.endpoint {
+            Endpoint::Socket(peer_addr) => self.ports_by_peer.get(peer_addr).cloned(),
+            Endpoint::Key(_) => None,
+        };
+        metrics::increment(
+            &DISPATCHER_BYTES_SENT,
+            &[("port", &Self::port_label(port_opt))],
+            msg.data.len() as u64,
         );
         self.to_stream
             .as_ref()
@@ -105,6 +128,7 @@ impl Dispatcher {
         Dispatcher {
             subs: None,
             to_stream: None,
+            ports_by_peer: HashMap::new(),
             logger: Logger::new("Dispatcher"),
         }
     }
@@ -118,7 +142,17 @@ impl Dispatcher {
         }
     }
 
+    // Data for a peer we've never heard from, or addressed only by public key, may go out over
+    // any of several ports.
+    fn port_label(port_opt: Option<u16>) -> String {
+        match port_opt {
+            Some(port) => port.to_string(),
+            None => String::from("unknown"),
+        }
+    }
+
     fn handle_stream_shutdown_msg(&mut self, msg: StreamShutdownMsg) {
+        self.ports_by_peer.remove(&msg.peer_addr);
         let subs = self.subs.as_ref().expect("Dispatcher is unbound");
         match msg.stream_type {
             RemovedStreamType::Clandestine => subs
@@ -345,6 +379,57 @@ mod tests {
         assert_eq!(recording.len(), 1);
     }
 
+    #[test]
+    fn counts_bytes_in_and_out_by_the_port_the_peer_arrived_on() {
+        let system = System::new("counts_bytes_in_and_out_by_the_port_the_peer_arrived_on");
+        let subject = Dispatcher::new();
+        let subject_addr: Addr<Dispatcher> = subject.start();
+        let (stream_handler_pool, _, _) = make_recorder();
+        let peer_addr = SocketAddr::from_str("1.2.3.4:5678").unwrap();
+        let mut peer_actors = peer_actors_builder().build();
+        peer_actors.dispatcher = Dispatcher::make_subs_from(&subject_addr);
+        subject_addr
+            .try_send(PoolBindMessage {
+                dispatcher_subs: peer_actors.dispatcher.clone(),
+                stream_handler_pool_subs: make_stream_handler_pool_subs_from(Some(
+                    stream_handler_pool,
+                )),
+                neighborhood_subs: peer_actors.neighborhood.clone(),
+            })
+            .unwrap();
+        subject_addr.try_send(BindMessage { peer_actors }).unwrap();
+
+        subject_addr
+            .try_send(InboundClientData {
+                peer_addr,
+                reception_port: Some(47123),
+                sequence_number: Some(0),
+                last_data: false,
+                is_clandestine: false,
+                data: vec![1, 2, 3],
+            })
+            .unwrap();
+        subject_addr
+            .try_send(TransmitDataMsg {
+                endpoint: Endpoint::Socket(peer_addr),
+                last_data: false,
+                sequence_number: None,
+                data: vec![4, 5, 6, 7, 8],
+            })
+            .unwrap();
+
+        System::current().stop_with_code(0);
+        system.run();
+        assert_eq!(
+            metrics::value(&DISPATCHER_BYTES_RECEIVED, &[("port", "47123")]),
+            Some(3)
+        );
+        assert_eq!(
+            metrics::value(&DISPATCHER_BYTES_SENT, &[("port", "47123")]),
+            Some(5)
+        );
+    }
+
     #[test]
     fn handle_stream_shutdown_msg_routes_non_clandestine_to_proxy_server() {
         let system = System::new("test");
@@ -1,14 +1,54 @@
 // Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use crate::entry_dns::dns_tcp_connection::{DnsConnection, DnsTcpConnection};
+use crate::entry_dns::doh_connection::DohConnection;
+use crate::entry_dns::packet_facade::PacketFacade;
+use crate::sub_lib::bypass::BypassList;
+use crate::sub_lib::entry_dns::EntryDnsTunnel;
 use crate::sub_lib::logger::Logger;
 use crate::sub_lib::main_tools::StdStreams;
+use crate::sub_lib::privacy;
+use crate::sub_lib::proxy_client::DnsRecordData;
+use crate::sub_lib::proxy_server::{TunneledDnsAnswer, TunneledDnsQuery};
 use crate::sub_lib::socket_server::SocketServer;
+use crate::sub_lib::tokio_wrappers::ReadHalfWrapperReal;
+use crate::sub_lib::tokio_wrappers::TokioListenerWrapper;
+use crate::sub_lib::tokio_wrappers::TokioListenerWrapperReal;
+use crate::sub_lib::tokio_wrappers::WriteHalfWrapperReal;
+use actix::Recipient;
+use futures::sync::mpsc::{unbounded, UnboundedReceiver, UnboundedSender};
+use std::cmp::min;
+use std::collections::HashMap;
+use std::io;
+use std::io::Write;
 use std::net::IpAddr::V4;
 use std::net::Ipv4Addr;
+use std::net::Ipv6Addr;
 use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+use tokio::net::TcpStream;
 use tokio::prelude::Async;
+use tokio::prelude::AsyncRead;
 use tokio::prelude::Future;
+use tokio::prelude::Stream;
+use trust_dns::op::ResponseCode;
 
 const DNS_PORT: u16 = 53;
+// DNS over HTTPS, served in plain HTTP and to localhost only
+pub const DOH_PORT: u16 = 8053;
+const TCP_MESSAGE_LIMIT: usize = 65535;
+const MAX_TCP_CONNECTIONS: usize = 100;
+// Only checked when something else wakes the server up, so idle connections may outstay it a bit.
+const TCP_IDLE_TIMEOUT: Duration = Duration::from_secs(10);
+// Clients give up and ask again long before this; it only keeps lost answers from piling up.
+const TUNNELED_QUERY_TIMEOUT: Duration = Duration::from_secs(30);
+// An original DNS server that hasn't answered in this long is presumed down, and the query goes to
+// the next one.
+const FORWARDED_QUERY_FAILOVER: Duration = Duration::from_secs(2);
+// Every forwarded query has a socket of its own until it's answered.
+const MAX_FORWARDED_QUERIES: usize = 256;
+// How long to remember that there are no records of a type, since an empty answer carries no TTL.
+const EMPTY_ANSWER_TTL: u32 = 300;
+const MAX_CACHED_ANSWERS: usize = 1000;
 
 use crate::entry_dns::processing;
 use crate::sub_lib::udp_socket_wrapper::UdpSocketWrapperReal;
@@ -17,6 +57,98 @@ use crate::sub_lib::udp_socket_wrapper::UdpSocketWrapperTrait;
 pub struct DnsSocketServer {
     socket_wrapper: Box<dyn UdpSocketWrapperTrait>,
     buf: [u8; 65536],
+    tunnel: EntryDnsTunnel,
+    answers_tx: UnboundedSender<TunneledDnsAnswer>,
+    answers_rx: UnboundedReceiver<TunneledDnsAnswer>,
+    next_query_id: u32,
+    pending_queries: HashMap<u32, PendingQuery>,
+    answer_cache: HashMap<(String, u16), CachedAnswer>,
+    bypass_list: BypassList,
+    bypass_dns_servers: Vec<SocketAddr>,
+    upstream_socket_factory: Box<dyn UpstreamSocketFactory>,
+    preferred_dns_server: usize,
+    forwarded_queries: Vec<ForwardedQuery>,
+    tcp_listener: Box<dyn TokioListenerWrapper>,
+    tcp_listening: bool,
+    doh_listener: Box<dyn TokioListenerWrapper>,
+    doh_listening: bool,
+    next_connection_id: u64,
+    tcp_connections: HashMap<u64, Box<dyn DnsConnection>>,
+}
+
+// Where a response has to go: straight back over UDP, or onto one of the TCP connections.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum DnsClient {
+    Udp(SocketAddr),
+    Tcp(u64, SocketAddr),
+}
+
+impl DnsClient {
+    fn socket_addr(&self) -> SocketAddr {
+        match self {
+            DnsClient::Udp(socket_addr) => *socket_addr,
+            DnsClient::Tcp(_, socket_addr) => *socket_addr,
+        }
+    }
+
+    fn response_limit(&self, request: &mut [u8], length: usize) -> usize {
+        match self {
+            DnsClient::Udp(_) => processing::udp_response_limit(request, length),
+            DnsClient::Tcp(_, _) => min(request.len(), TCP_MESSAGE_LIMIT),
+        }
+    }
+}
+
+// A query for a destination on the bypass list, sent on to an original DNS server from a socket
+// of its own under a random transaction ID, so that an off-path attacker has to guess both the
+// port and the ID to slip in a forged answer. request keeps the client's transaction ID.
+struct ForwardedQuery {
+    client: DnsClient,
+    request: Vec<u8>,
+    transaction_id: u16,
+    socket: Box<dyn UdpSocketWrapperTrait>,
+    server_index: usize,
+    servers_left: usize,
+    sent_at: Instant,
+}
+
+trait UpstreamSocketFactory: Send {
+    fn make(&self, dns_server: SocketAddr) -> io::Result<Box<dyn UdpSocketWrapperTrait>>;
+}
+
+struct UpstreamSocketFactoryReal {}
+
+impl UpstreamSocketFactory for UpstreamSocketFactoryReal {
+    // Port 0 has the operating system pick a random ephemeral port.
+    fn make(&self, dns_server: SocketAddr) -> io::Result<Box<dyn UdpSocketWrapperTrait>> {
+        let local_ip = if dns_server.is_ipv4() {
+            V4(Ipv4Addr::UNSPECIFIED)
+        } else {
+            Ipv6Addr::UNSPECIFIED.into()
+        };
+        let mut socket = UdpSocketWrapperReal::new();
+        socket.bind(SocketAddr::new(local_ip, 0))?;
+        Ok(Box::new(socket))
+    }
+}
+
+struct PendingQuery {
+    client: DnsClient,
+    request: Vec<u8>,
+    cache_key: (String, u16),
+    sent_at: Instant,
+}
+
+struct CachedAnswer {
+    answer: TunneledDnsAnswer,
+    cached_at: Instant,
+    lifetime: u32,
+}
+
+impl CachedAnswer {
+    fn age(&self) -> u32 {
+        self.cached_at.elapsed().as_secs() as u32
+    }
 }
 
 impl Future for DnsSocketServer {
@@ -25,6 +157,14 @@ impl Future for DnsSocketServer {
 
     fn poll(&mut self) -> Result<Async<<Self as Future>::Item>, <Self as Future>::Error> {
         let logger = Logger::new("EntryDnsServer");
+        let tunnel_opt = self.tunnel.recipient_opt();
+        if tunnel_opt.is_some() {
+            while let Ok(Async::Ready(Some(answer))) = self.answers_rx.poll() {
+                self.handle_tunneled_answer(answer, &logger)?;
+            }
+        }
+        self.relay_forwarded_answers(&logger)?;
+        self.serve_tcp_connections(&tunnel_opt, &logger)?;
         loop {
             let mut buffer = self.buf;
             let (len, socket_addr) = match self.socket_wrapper.recv_from(&mut buffer) {
@@ -38,14 +178,13 @@ impl Future for DnsSocketServer {
                     return Err(());
                 }
             };
-            let response_length = processing::process(&mut buffer, len, &socket_addr, &logger);
-            if let Err(e) = self
-                .socket_wrapper
-                .send_to(&buffer[0..response_length], socket_addr)
-            {
-                error!(logger, "Unrecoverable error sending to UdpSocket: {}", e);
-                return Err(());
-            }
+            self.handle_request(
+                &mut buffer,
+                len,
+                DnsClient::Udp(socket_addr),
+                &tunnel_opt,
+                &logger,
+            )?;
         }
     }
 }
@@ -55,31 +194,522 @@ impl SocketServer<()> for DnsSocketServer {
         &()
     }
 
-    fn initialize_as_privileged(&mut self, _args: &Vec<String>, _streams: &mut StdStreams<'_>) {
+    fn initialize_as_privileged(&mut self, _args: &Vec<String>, streams: &mut StdStreams<'_>) {
         let socket_addr = SocketAddr::new(V4(Ipv4Addr::from(0)), DNS_PORT);
         // The following expect() will cause an appropriate panic if the port can't be opened
         self.socket_wrapper
             .bind(socket_addr)
             .unwrap_or_else(|_| panic!("Cannot bind socket to {:?}", socket_addr));
+        // Only truncated answers need TCP, so entry DNS can get along without it
+        match self.tcp_listener.bind(socket_addr) {
+            Ok(()) => self.tcp_listening = true,
+            Err(e) => writeln!(
+                streams.stderr,
+                "Entry DNS can't listen for TCP on {:?}, so truncated answers can't be retried: {}",
+                socket_addr, e
+            )
+            .expect("Could not write to stderr"),
+        }
+        let doh_addr = SocketAddr::new(V4(Ipv4Addr::LOCALHOST), DOH_PORT);
+        match self.doh_listener.bind(doh_addr) {
+            Ok(()) => self.doh_listening = true,
+            Err(e) => writeln!(
+                streams.stderr,
+                "Entry DNS can't listen for DNS over HTTPS on {:?}: {}",
+                doh_addr, e
+            )
+            .expect("Could not write to stderr"),
+        }
     }
 
     fn initialize_as_unprivileged(&mut self, _args: &Vec<String>, _streams: &mut StdStreams<'_>) {
         self.buf = [0; 65536];
+        let (bypass_list, bypass_dns_servers) = self.tunnel.bypass();
+        self.bypass_list = bypass_list;
+        self.bypass_dns_servers = bypass_dns_servers;
     }
 }
 
 impl DnsSocketServer {
-    pub fn new() -> DnsSocketServer {
+    pub fn new(tunnel: EntryDnsTunnel) -> DnsSocketServer {
+        let (answers_tx, answers_rx) = unbounded();
         DnsSocketServer {
             socket_wrapper: Box::new(UdpSocketWrapperReal::new()),
             buf: [0; 65536],
+            tunnel,
+            answers_tx,
+            answers_rx,
+            next_query_id: 0,
+            pending_queries: HashMap::new(),
+            answer_cache: HashMap::new(),
+            bypass_list: BypassList::default(),
+            bypass_dns_servers: vec![],
+            upstream_socket_factory: Box::new(UpstreamSocketFactoryReal {}),
+            preferred_dns_server: 0,
+            forwarded_queries: vec![],
+            tcp_listener: Box::new(TokioListenerWrapperReal::new()),
+            tcp_listening: false,
+            doh_listener: Box::new(TokioListenerWrapperReal::new()),
+            doh_listening: false,
+            next_connection_id: 0,
+            tcp_connections: HashMap::new(),
         }
     }
-}
 
-impl Default for DnsSocketServer {
-    fn default() -> Self {
-        Self::new()
+    fn handle_request(
+        &mut self,
+        buffer: &mut [u8],
+        len: usize,
+        client: DnsClient,
+        tunnel_opt: &Option<Recipient<TunneledDnsQuery>>,
+        logger: &Logger,
+    ) -> Result<(), ()> {
+        if self.is_bypassed(buffer, len) {
+            self.forward_query(&buffer[0..len], client, logger);
+            return Ok(());
+        }
+        if let Some(tunnel) = tunnel_opt {
+            if let Some((name, record_type)) =
+                processing::tunnelable_query(buffer, len, self.tunnel.resolves_addresses())
+            {
+                return self.tunnel_query(
+                    tunnel,
+                    &buffer[0..len],
+                    client,
+                    name,
+                    record_type,
+                    logger,
+                );
+            }
+        }
+        let limit = client.response_limit(buffer, len);
+        let response_length =
+            processing::process(&mut buffer[0..limit], len, &client.socket_addr(), logger);
+        self.respond(client, &buffer[0..response_length], logger)
+    }
+
+    fn respond(&mut self, client: DnsClient, response: &[u8], logger: &Logger) -> Result<(), ()> {
+        match client {
+            DnsClient::Udp(socket_addr) => {
+                if let Err(e) = self.socket_wrapper.send_to(response, socket_addr) {
+                    error!(logger, "Unrecoverable error sending to UdpSocket: {}", e);
+                    return Err(());
+                }
+            }
+            // The connection may have gone while the answer was on its way
+            DnsClient::Tcp(connection_id, _) => {
+                if let Some(connection) = self.tcp_connections.get_mut(&connection_id) {
+                    connection.queue_response(response);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn serve_tcp_connections(
+        &mut self,
+        tunnel_opt: &Option<Recipient<TunneledDnsQuery>>,
+        logger: &Logger,
+    ) -> Result<(), ()> {
+        if !self.tcp_listening && !self.doh_listening {
+            return Ok(());
+        }
+        self.accept_tcp_connections(logger);
+        let connection_ids = self.tcp_connections.keys().cloned().collect::<Vec<u64>>();
+        for connection_id in connection_ids {
+            let (client, requests) = match self.tcp_connections.get_mut(&connection_id) {
+                Some(connection) => (
+                    DnsClient::Tcp(connection_id, connection.peer_addr()),
+                    connection.read_requests(),
+                ),
+                None => continue,
+            };
+            let requests = match requests {
+                Ok(requests) => requests,
+                Err(()) => {
+                    debug!(
+                        logger,
+                        "TCP connection from {} broke",
+                        privacy::socket_addr(client.socket_addr())
+                    );
+                    self.tcp_connections.remove(&connection_id);
+                    continue;
+                }
+            };
+            for request in requests {
+                let mut buffer = self.buf;
+                buffer[0..request.len()].copy_from_slice(&request);
+                self.handle_request(&mut buffer, request.len(), client, tunnel_opt, logger)?;
+            }
+            let finished = match self.tcp_connections.get_mut(&connection_id) {
+                Some(connection) => connection.flush().is_err(),
+                None => false,
+            };
+            if finished {
+                self.tcp_connections.remove(&connection_id);
+            }
+        }
+        self.tcp_connections
+            .retain(|_, connection| !connection.is_idle_for(TCP_IDLE_TIMEOUT));
+        Ok(())
+    }
+
+    fn accept_tcp_connections(&mut self, logger: &Logger) {
+        while self.tcp_listening {
+            let (stream, peer_addr) = match Self::accept(self.tcp_listener.as_mut(), logger) {
+                Some(accepted) => accepted,
+                None => break,
+            };
+            let (reader, writer) = stream.split();
+            self.add_tcp_connection(
+                Box::new(DnsTcpConnection::new(
+                    peer_addr,
+                    Box::new(ReadHalfWrapperReal::new(reader)),
+                    Box::new(WriteHalfWrapperReal::new(writer)),
+                )),
+                logger,
+            );
+        }
+        while self.doh_listening {
+            let (stream, peer_addr) = match Self::accept(self.doh_listener.as_mut(), logger) {
+                Some(accepted) => accepted,
+                None => break,
+            };
+            let (reader, writer) = stream.split();
+            self.add_tcp_connection(
+                Box::new(DohConnection::new(
+                    peer_addr,
+                    Box::new(ReadHalfWrapperReal::new(reader)),
+                    Box::new(WriteHalfWrapperReal::new(writer)),
+                )),
+                logger,
+            );
+        }
+    }
+
+    fn accept(
+        listener: &mut dyn TokioListenerWrapper,
+        logger: &Logger,
+    ) -> Option<(TcpStream, SocketAddr)> {
+        match listener.poll_accept() {
+            Ok(Async::Ready(accepted)) => Some(accepted),
+            Ok(Async::NotReady) => None,
+            Err(e) => {
+                warning!(logger, "Could not accept TCP connection: {}", e);
+                None
+            }
+        }
+    }
+
+    fn add_tcp_connection(&mut self, connection: Box<dyn DnsConnection>, logger: &Logger) {
+        if self.tcp_connections.len() >= MAX_TCP_CONNECTIONS {
+            warning!(
+                logger,
+                "Too many TCP connections already; turning away {}",
+                privacy::socket_addr(connection.peer_addr())
+            );
+            return;
+        }
+        let connection_id = self.next_connection_id;
+        self.next_connection_id = self.next_connection_id.wrapping_add(1);
+        self.tcp_connections.insert(connection_id, connection);
+    }
+
+    // Reverse lookups are bypassed by the address they ask about, so network rules apply to them.
+    fn is_bypassed(&self, buf: &mut [u8], length: usize) -> bool {
+        if self.bypass_dns_servers.is_empty() {
+            return false;
+        }
+        match processing::query_name(buf, length) {
+            Some(name) => match processing::reverse_lookup_address(&name) {
+                Some(ip_addr) => self.bypass_list.bypasses(&ip_addr.to_string()),
+                None => self.bypass_list.bypasses(&name),
+            },
+            None => false,
+        }
+    }
+
+    // A name that resolves into a bypassed network is for a destination that doesn't go through
+    // the Substratum Network, so it's the original DNS servers that ought to answer for it.
+    fn answer_is_bypassed(&self, answer: &TunneledDnsAnswer) -> bool {
+        !self.bypass_dns_servers.is_empty()
+            && answer.records.iter().any(|record| match &record.data {
+                DnsRecordData::A(ip_addr) => self.bypass_list.bypasses(&ip_addr.to_string()),
+                DnsRecordData::Aaaa(ip_addr) => self.bypass_list.bypasses(&ip_addr.to_string()),
+                _ => false,
+            })
+    }
+
+    fn forward_query(&mut self, request: &[u8], client: DnsClient, logger: &Logger) {
+        if self.forwarded_queries.len() >= MAX_FORWARDED_QUERIES {
+            self.forwarded_queries.remove(0);
+        }
+        let server_count = self.bypass_dns_servers.len();
+        self.send_forwarded_query(
+            request.to_vec(),
+            client,
+            self.preferred_dns_server % server_count,
+            server_count - 1,
+            logger,
+        );
+    }
+
+    // Tries the original DNS servers in turn, starting at server_index, until one takes the query.
+    fn send_forwarded_query(
+        &mut self,
+        request: Vec<u8>,
+        client: DnsClient,
+        mut server_index: usize,
+        mut servers_left: usize,
+        logger: &Logger,
+    ) {
+        let server_count = self.bypass_dns_servers.len();
+        loop {
+            let dns_server = self.bypass_dns_servers[server_index];
+            let transaction_id = rand::random::<u16>();
+            let mut packet = request.clone();
+            let length = packet.len();
+            if !PacketFacade::new(&mut packet, length).set_transaction_id(transaction_id) {
+                return;
+            }
+            let sent = self
+                .upstream_socket_factory
+                .make(dns_server)
+                .and_then(|mut socket| {
+                    socket.send_to(&packet, dns_server)?;
+                    Ok(socket)
+                });
+            match sent {
+                Ok(socket) => {
+                    self.forwarded_queries.push(ForwardedQuery {
+                        client,
+                        request,
+                        transaction_id,
+                        socket,
+                        server_index,
+                        servers_left,
+                        sent_at: Instant::now(),
+                    });
+                    return;
+                }
+                Err(e) => {
+                    warning!(
+                        logger,
+                        "Couldn't forward bypassed DNS query to {}: {}",
+                        dns_server,
+                        e
+                    );
+                    server_index = (server_index + 1) % server_count;
+                    self.preferred_dns_server = server_index;
+                    if servers_left == 0 {
+                        return;
+                    }
+                    servers_left -= 1;
+                }
+            }
+        }
+    }
+
+    fn relay_forwarded_answers(&mut self, logger: &Logger) -> Result<(), ()> {
+        let mut index = 0;
+        while index < self.forwarded_queries.len() {
+            let mut buffer = self.buf;
+            let forwarded = &mut self.forwarded_queries[index];
+            let (len, dns_server) = match forwarded.socket.recv_from(&mut buffer) {
+                Ok(Async::Ready((len, dns_server))) => (len, dns_server),
+                Ok(Async::NotReady) => {
+                    index += 1;
+                    continue;
+                }
+                Err(e) => {
+                    warning!(
+                        logger,
+                        "Error receiving answer to bypassed DNS query: {}",
+                        e
+                    );
+                    index += 1;
+                    continue;
+                }
+            };
+            let mut facade = PacketFacade::new(&mut buffer, len);
+            if dns_server != self.bypass_dns_servers[forwarded.server_index]
+                || facade.get_transaction_id() != Some(forwarded.transaction_id)
+            {
+                debug!(
+                    logger,
+                    "Discarding unexpected {}-byte DNS answer from {}", len, dns_server
+                );
+                continue;
+            }
+            let mut forwarded = self.forwarded_queries.remove(index);
+            let length = forwarded.request.len();
+            let client_transaction_id =
+                PacketFacade::new(&mut forwarded.request, length).get_transaction_id();
+            if let Some(transaction_id) = client_transaction_id {
+                facade.set_transaction_id(transaction_id);
+            }
+            self.respond(forwarded.client, &buffer[0..len], logger)?;
+        }
+        self.fail_over_forwarded_queries(logger);
+        Ok(())
+    }
+
+    fn fail_over_forwarded_queries(&mut self, logger: &Logger) {
+        self.forwarded_queries
+            .retain(|forwarded| forwarded.sent_at.elapsed() < TUNNELED_QUERY_TIMEOUT);
+        let server_count = self.bypass_dns_servers.len();
+        let (overdue, waiting): (Vec<ForwardedQuery>, Vec<ForwardedQuery>) =
+            self.forwarded_queries.drain(..).partition(|forwarded| {
+                forwarded.servers_left > 0
+                    && forwarded.sent_at.elapsed() >= FORWARDED_QUERY_FAILOVER
+            });
+        self.forwarded_queries = waiting;
+        overdue.into_iter().for_each(|forwarded| {
+            let next_server_index = (forwarded.server_index + 1) % server_count;
+            warning!(
+                logger,
+                "Original DNS server {} isn't answering; trying {}",
+                self.bypass_dns_servers[forwarded.server_index],
+                self.bypass_dns_servers[next_server_index]
+            );
+            self.preferred_dns_server = next_server_index;
+            self.send_forwarded_query(
+                forwarded.request,
+                forwarded.client,
+                next_server_index,
+                forwarded.servers_left - 1,
+                logger,
+            );
+        });
+    }
+
+    fn tunnel_query(
+        &mut self,
+        tunnel: &Recipient<TunneledDnsQuery>,
+        request: &[u8],
+        client: DnsClient,
+        name: String,
+        record_type: u16,
+        logger: &Logger,
+    ) -> Result<(), ()> {
+        let cache_key = (name.to_lowercase(), record_type);
+        if let Some(answer) = self.cached_answer(&cache_key) {
+            if self.answer_is_bypassed(&answer) {
+                self.forward_query(request, client, logger);
+                return Ok(());
+            }
+            return self.send_tunneled_response(request, &answer, client, logger);
+        }
+        let query_id = self.next_query_id;
+        self.next_query_id = self.next_query_id.wrapping_add(1);
+        self.pending_queries
+            .retain(|_, pending| pending.sent_at.elapsed() < TUNNELED_QUERY_TIMEOUT);
+        match tunnel.try_send(TunneledDnsQuery {
+            query_id,
+            name,
+            record_type,
+            respond_to: self.answers_tx.clone(),
+        }) {
+            Ok(()) => {
+                self.pending_queries.insert(
+                    query_id,
+                    PendingQuery {
+                        client,
+                        request: request.to_vec(),
+                        cache_key,
+                        sent_at: Instant::now(),
+                    },
+                );
+                Ok(())
+            }
+            Err(_) => {
+                error!(logger, "ProxyServer is dead: can't tunnel DNS query");
+                let failure =
+                    TunneledDnsAnswer::new(query_id, ResponseCode::ServFail.low().into(), vec![]);
+                self.send_tunneled_response(request, &failure, client, logger)
+            }
+        }
+    }
+
+    fn handle_tunneled_answer(
+        &mut self,
+        answer: TunneledDnsAnswer,
+        logger: &Logger,
+    ) -> Result<(), ()> {
+        let pending = match self.pending_queries.remove(&answer.query_id) {
+            Some(pending) => pending,
+            None => {
+                debug!(
+                    logger,
+                    "Discarding answer to forgotten DNS query {}", answer.query_id
+                );
+                return Ok(());
+            }
+        };
+        self.cache_answer(pending.cache_key, &answer);
+        if self.answer_is_bypassed(&answer) {
+            self.forward_query(&pending.request, pending.client, logger);
+            return Ok(());
+        }
+        self.send_tunneled_response(&pending.request, &answer, pending.client, logger)
+    }
+
+    fn send_tunneled_response(
+        &mut self,
+        request: &[u8],
+        answer: &TunneledDnsAnswer,
+        client: DnsClient,
+        logger: &Logger,
+    ) -> Result<(), ()> {
+        let mut buffer = self.buf;
+        buffer[0..request.len()].copy_from_slice(request);
+        let limit = client.response_limit(&mut buffer, request.len());
+        let response_length =
+            processing::make_tunneled_response(&mut buffer[0..limit], request.len(), answer);
+        self.respond(client, &buffer[0..response_length], logger)
+    }
+
+    fn cached_answer(&mut self, cache_key: &(String, u16)) -> Option<TunneledDnsAnswer> {
+        let (mut answer, age) = match self.answer_cache.get(cache_key) {
+            Some(cached) if cached.age() < cached.lifetime => (cached.answer.clone(), cached.age()),
+            Some(_) => {
+                self.answer_cache.remove(cache_key);
+                return None;
+            }
+            None => return None,
+        };
+        answer
+            .records
+            .iter_mut()
+            .for_each(|record| record.ttl = record.ttl.saturating_sub(age));
+        Some(answer)
+    }
+
+    fn cache_answer(&mut self, cache_key: (String, u16), answer: &TunneledDnsAnswer) {
+        if answer.response_code != u16::from(ResponseCode::NoError.low()) {
+            return;
+        }
+        let lifetime = answer
+            .records
+            .iter()
+            .map(|record| record.ttl)
+            .min()
+            .unwrap_or(EMPTY_ANSWER_TTL);
+        if self.answer_cache.len() >= MAX_CACHED_ANSWERS {
+            self.answer_cache
+                .retain(|_, cached| cached.age() < cached.lifetime);
+            if self.answer_cache.len() >= MAX_CACHED_ANSWERS {
+                return;
+            }
+        }
+        self.answer_cache.insert(
+            cache_key,
+            CachedAnswer {
+                answer: answer.clone(),
+                cached_at: Instant::now(),
+                lifetime,
+            },
+        );
     }
 }
 
@@ -87,10 +717,19 @@ impl Default for DnsSocketServer {
 mod tests {
     use super::super::packet_facade::PacketFacade;
     use super::*;
+    use crate::sub_lib::proxy_client::{DnsRecord, DnsRecordData};
     use crate::sub_lib::udp_socket_wrapper::UdpSocketWrapperTrait;
+    use crate::sub_lib::utils::localhost;
+    use crate::test_utils::find_free_port;
     use crate::test_utils::logging::init_test_logging;
     use crate::test_utils::logging::TestLogHandler;
+    use crate::test_utils::recorder::make_recorder;
+    use crate::test_utils::tokio_wrapper_mocks::ReadHalfWrapperMock;
+    use crate::test_utils::tokio_wrapper_mocks::WriteHalfWrapperMock;
     use crate::test_utils::FakeStreamHolder;
+    use actix::{Actor, System};
+    use futures::executor;
+    use futures::future::lazy;
     use std::borrow::Borrow;
     use std::borrow::BorrowMut;
     use std::clone::Clone;
@@ -98,16 +737,21 @@ mod tests {
     use std::io;
     use std::io::Error;
     use std::io::ErrorKind;
+    use std::io::Read;
+    use std::net::TcpStream;
     use std::ops::DerefMut;
     use std::str::FromStr;
     use std::sync::Arc;
     use std::sync::Mutex;
+    use std::thread;
     use tokio;
-    use trust_dns::op::ResponseCode;
+    use trust_dns::op::OpCode;
+    use trust_dns::rr::{DNSClass, RecordType};
 
     struct UdpSocketWrapperMockGuts {
         log: Vec<String>,
-        buf: [u8; 12],
+        buf: Vec<u8>,
+        sent: Vec<Vec<u8>>,
     }
 
     #[derive(Clone)]
@@ -146,6 +790,7 @@ mod tests {
             let guts_ref = unwrapped_guts.borrow_mut();
             let guts: &mut UdpSocketWrapperMockGuts = guts_ref.deref_mut();
             guts.log.push(format!("send_to (buf, {:?})", addr));
+            guts.sent.push(buf.to_vec());
             UdpSocketWrapperMock::copy(&mut guts.buf, buf);
             self.send_to_results.lock().unwrap().borrow_mut().remove(0)
         }
@@ -153,29 +798,15 @@ mod tests {
 
     impl UdpSocketWrapperMock {
         fn new(buf: &[u8]) -> UdpSocketWrapperMock {
-            assert_eq!(
-                buf.len() <= 12,
-                true,
-                "Mock accepts buffer of up to 12 bytes, not {}",
-                buf.len()
-            );
-            let result = UdpSocketWrapperMock {
+            UdpSocketWrapperMock {
                 guts: Arc::new(Mutex::new(UdpSocketWrapperMockGuts {
                     log: Vec::new(),
-                    buf: [0; 12],
+                    buf: buf.to_vec(),
+                    sent: Vec::new(),
                 })),
                 recv_from_results: Arc::new(Mutex::new(vec![])),
                 send_to_results: Arc::new(Mutex::new(vec![])),
-            };
-            result
-                .guts
-                .lock()
-                .unwrap()
-                .borrow_mut()
-                .deref_mut()
-                .buf
-                .copy_from_slice(&buf);
-            result
+            }
         }
 
         fn copy(destination: &mut [u8], source: &[u8]) {
@@ -342,6 +973,755 @@ mod tests {
         );
     }
 
+    #[test]
+    fn also_listens_for_tcp_on_the_standard_dns_port() {
+        let socket_wrapper = make_socket_wrapper_mock();
+        let mut subject = make_instrumented_subject(socket_wrapper);
+        let bind_params_arc = Arc::new(Mutex::new(vec![]));
+        subject.tcp_listener = Box::new(TokioListenerWrapperMock {
+            bind_params: bind_params_arc.clone(),
+            bind_result: Some(Ok(())),
+        });
+
+        subject.initialize_as_privileged(&vec![], &mut FakeStreamHolder::new().streams());
+
+        assert_eq!(
+            *bind_params_arc.lock().unwrap(),
+            vec![SocketAddr::from_str("0.0.0.0:53").unwrap()]
+        );
+        assert_eq!(subject.tcp_listening, true);
+    }
+
+    #[test]
+    fn entry_dns_gets_along_without_tcp_if_it_must() {
+        let socket_wrapper = make_socket_wrapper_mock();
+        let mut subject = make_instrumented_subject(socket_wrapper);
+        subject.tcp_listener = Box::new(TokioListenerWrapperMock {
+            bind_params: Arc::new(Mutex::new(vec![])),
+            bind_result: Some(Err(Error::from(ErrorKind::AddrInUse))),
+        });
+        let mut holder = FakeStreamHolder::new();
+
+        subject.initialize_as_privileged(&vec![], &mut holder.streams());
+
+        assert_eq!(subject.tcp_listening, false);
+        assert_eq!(
+            holder.stderr.get_string(),
+            "Entry DNS can't listen for TCP on V4(0.0.0.0:53), so truncated answers can't be retried: address in use\n"
+        );
+    }
+
+    #[test]
+    fn also_serves_dns_over_https_on_localhost() {
+        let socket_wrapper = make_socket_wrapper_mock();
+        let mut subject = make_instrumented_subject(socket_wrapper);
+        let bind_params_arc = Arc::new(Mutex::new(vec![]));
+        subject.doh_listener = Box::new(TokioListenerWrapperMock {
+            bind_params: bind_params_arc.clone(),
+            bind_result: Some(Ok(())),
+        });
+
+        subject.initialize_as_privileged(&vec![], &mut FakeStreamHolder::new().streams());
+
+        assert_eq!(
+            *bind_params_arc.lock().unwrap(),
+            vec![SocketAddr::from_str("127.0.0.1:8053").unwrap()]
+        );
+        assert_eq!(subject.doh_listening, true);
+    }
+
+    #[test]
+    fn queries_over_doh_are_answered_over_the_same_connection() {
+        let request = make_request(RecordType::A);
+        let socket_wrapper = make_socket_wrapper_mock();
+        socket_wrapper
+            .recv_from_results
+            .lock()
+            .unwrap()
+            .push(Ok(Async::NotReady));
+        let poll_write_params_arc = Arc::new(Mutex::new(vec![]));
+        let mut subject = make_instrumented_subject(socket_wrapper.clone());
+        subject.doh_listening = true;
+        subject.tcp_connections.insert(
+            0,
+            Box::new(DohConnection::new(
+                SocketAddr::from_str("127.0.0.1:7890").unwrap(),
+                Box::new(
+                    ReadHalfWrapperMock::new()
+                        .poll_read_ok(
+                            format!(
+                                "GET /dns-query?dns={} HTTP/1.1\r\n\r\n",
+                                base64::encode_config(&request, base64::URL_SAFE_NO_PAD)
+                            )
+                            .into_bytes(),
+                        )
+                        .poll_read_result(vec![], Ok(Async::NotReady)),
+                ),
+                Box::new(
+                    WriteHalfWrapperMock::new()
+                        .poll_write_params(&poll_write_params_arc)
+                        .poll_write_ok(1000),
+                ),
+            )),
+        );
+
+        let result = poll_once(&mut subject);
+
+        assert_eq!(result, Ok(Async::NotReady));
+        assert!(socket_wrapper.guts.lock().unwrap().sent.is_empty());
+        let written = poll_write_params_arc.lock().unwrap()[0].clone();
+        let header_end = written
+            .windows(4)
+            .position(|window| window == b"\r\n\r\n")
+            .unwrap()
+            + 4;
+        assert_eq!(
+            String::from_utf8(written[0..header_end].to_vec()).unwrap(),
+            format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: application/dns-message\r\nContent-Length: {}\r\n\r\n",
+                request.len() + 27
+            )
+        );
+        let mut message = written[header_end..].to_vec();
+        let length = message.len();
+        let response = PacketFacade::new(&mut message, length);
+        assert_eq!(response.get_transaction_id(), Some(0x1234));
+        assert_eq!(
+            response.get_answers().unwrap()[0].get_rdata(),
+            &[127, 0, 0, 1][..]
+        );
+    }
+
+    #[test]
+    fn queries_over_tcp_are_answered_over_the_same_connection() {
+        let request = make_request(RecordType::A);
+        let socket_wrapper = make_socket_wrapper_mock();
+        socket_wrapper
+            .recv_from_results
+            .lock()
+            .unwrap()
+            .push(Ok(Async::NotReady));
+        let poll_write_params_arc = Arc::new(Mutex::new(vec![]));
+        let mut subject = make_instrumented_subject(socket_wrapper.clone());
+        add_tcp_connection(
+            &mut subject,
+            ReadHalfWrapperMock::new()
+                .poll_read_ok(with_length_prefix(&request))
+                .poll_read_result(vec![], Ok(Async::NotReady)),
+            WriteHalfWrapperMock::new()
+                .poll_write_params(&poll_write_params_arc)
+                .poll_write_ok(request.len() + 29),
+        );
+
+        let result = poll_once(&mut subject);
+
+        assert_eq!(result, Ok(Async::NotReady));
+        assert!(socket_wrapper.guts.lock().unwrap().sent.is_empty());
+        let mut written = poll_write_params_arc.lock().unwrap()[0].clone();
+        assert_eq!(written.len(), request.len() + 29);
+        assert_eq!(&written[0..2], &[0x00, (request.len() + 27) as u8][..]);
+        let length = written.len() - 2;
+        let response = PacketFacade::new(&mut written[2..], length);
+        assert_eq!(response.get_transaction_id(), Some(0x1234));
+        assert_eq!(response.is_truncated(), Some(false));
+        let answers = response.get_answers().unwrap();
+        assert_eq!(answers[0].get_rdata(), &[127, 0, 0, 1][..]);
+        assert_eq!(answers.len(), 1);
+        assert_eq!(subject.tcp_connections.len(), 1);
+    }
+
+    #[test]
+    fn big_answers_are_truncated_over_udp_but_not_over_tcp() {
+        let _system = System::new("big_answers_are_truncated_over_udp_but_not_over_tcp");
+        let (proxy_server, _, _) = make_recorder();
+        let tunnel = EntryDnsTunnel::new();
+        tunnel.connect(proxy_server.start().recipient(), EntryDnsMode::Tunnel);
+        let request = make_request(RecordType::TXT);
+        let socket_wrapper = Box::new(UdpSocketWrapperMock::new(&vec![0; 1300]));
+        socket_wrapper.guts.lock().unwrap().buf[0..request.len()].copy_from_slice(&request);
+        {
+            let mut recv_from_results = socket_wrapper.recv_from_results.lock().unwrap();
+            recv_from_results.push(Ok(Async::Ready((
+                request.len(),
+                SocketAddr::from_str("1.2.3.4:5678").unwrap(),
+            ))));
+            recv_from_results.push(Ok(Async::NotReady));
+            socket_wrapper
+                .send_to_results
+                .lock()
+                .unwrap()
+                .push(Ok(Async::Ready(512)));
+        }
+        let poll_write_params_arc = Arc::new(Mutex::new(vec![]));
+        let mut subject = make_tunneling_subject(socket_wrapper.clone(), tunnel);
+        add_tcp_connection(
+            &mut subject,
+            ReadHalfWrapperMock::new()
+                .poll_read_ok(with_length_prefix(&request))
+                .poll_read_result(vec![], Ok(Async::NotReady)),
+            WriteHalfWrapperMock::new()
+                .poll_write_params(&poll_write_params_arc)
+                .poll_write_ok(1271),
+        );
+        subject.cache_answer(
+            ("example.com".to_string(), RecordType::TXT.into()),
+            &TunneledDnsAnswer::new(
+                0,
+                0,
+                (0..10)
+                    .map(|_| DnsRecord {
+                        name: "example.com".to_string(),
+                        ttl: 300,
+                        data: DnsRecordData::Txt(vec![vec![b'x'; 100]]),
+                    })
+                    .collect(),
+            ),
+        );
+
+        let result = poll_once(&mut subject);
+
+        assert_eq!(result, Ok(Async::NotReady));
+        let mut udp_response = socket_wrapper.guts.lock().unwrap().sent[0].clone();
+        let udp_length = udp_response.len();
+        let udp_facade = PacketFacade::new(&mut udp_response, udp_length);
+        assert_eq!(udp_facade.is_truncated(), Some(true));
+        assert_eq!(udp_facade.get_answers().unwrap().len(), 3);
+        let mut tcp_response = poll_write_params_arc.lock().unwrap()[0].clone();
+        assert_eq!(tcp_response.len(), 1271);
+        let tcp_facade = PacketFacade::new(&mut tcp_response[2..], 1269);
+        assert_eq!(tcp_facade.is_truncated(), Some(false));
+        assert_eq!(tcp_facade.get_answers().unwrap().len(), 10);
+    }
+
+    #[test]
+    fn accepted_tcp_connections_are_served() {
+        let port = find_free_port();
+        let listen_addr = SocketAddr::new(localhost(), port);
+        let request = make_request(RecordType::AAAA);
+        let socket_wrapper = make_socket_wrapper_mock();
+        (0..100).for_each(|_| {
+            socket_wrapper
+                .recv_from_results
+                .lock()
+                .unwrap()
+                .push(Ok(Async::NotReady))
+        });
+        let mut subject = make_instrumented_subject(socket_wrapper);
+        let mut tcp_listener = TokioListenerWrapperReal::new();
+        thread::spawn(move || {
+            tokio::run(lazy(move || {
+                tcp_listener.bind(listen_addr).unwrap();
+                subject.tcp_listener = Box::new(tcp_listener);
+                subject.tcp_listening = true;
+                subject
+            }))
+        });
+        let mut stream = connect_with_retries(listen_addr);
+        stream
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+
+        stream.write_all(&with_length_prefix(&request)).unwrap();
+
+        let mut prefix = [0u8; 2];
+        stream.read_exact(&mut prefix).unwrap();
+        let length = ((prefix[0] as usize) << 8) | (prefix[1] as usize);
+        let mut response = vec![0u8; length];
+        stream.read_exact(&mut response).unwrap();
+        let facade = PacketFacade::new(&mut response, length);
+        let answers = facade.get_answers().unwrap();
+        assert_eq!(answers[0].get_resource_type(), u16::from(RecordType::AAAA));
+        assert_eq!(answers.len(), 1);
+    }
+
+    #[test]
+    fn tunnelable_queries_are_answered_through_the_proxy_server_then_from_the_cache() {
+        let system = System::new(
+            "tunnelable_queries_are_answered_through_the_proxy_server_then_from_the_cache",
+        );
+        let (proxy_server, _, proxy_server_recording_arc) = make_recorder();
+        let tunnel = EntryDnsTunnel::new();
+        tunnel.connect(proxy_server.start().recipient(), EntryDnsMode::Tunnel);
+        let request = make_request(RecordType::MX);
+        let socket_wrapper = Box::new(UdpSocketWrapperMock::new(&request));
+        let first_client = SocketAddr::from_str("1.2.3.4:5678").unwrap();
+        let second_client = SocketAddr::from_str("2.3.4.5:6789").unwrap();
+        {
+            let mut recv_from_results = socket_wrapper.recv_from_results.lock().unwrap();
+            recv_from_results.push(Ok(Async::Ready((request.len(), first_client))));
+            recv_from_results.push(Ok(Async::NotReady));
+            recv_from_results.push(Ok(Async::NotReady));
+            recv_from_results.push(Ok(Async::Ready((request.len(), second_client))));
+            recv_from_results.push(Ok(Async::NotReady));
+            let mut send_to_results = socket_wrapper.send_to_results.lock().unwrap();
+            send_to_results.push(Ok(Async::Ready(100)));
+            send_to_results.push(Ok(Async::Ready(100)));
+        }
+        let mut subject = make_tunneling_subject(socket_wrapper.clone(), tunnel);
+
+        let first_poll = poll_once(&mut subject);
+        System::current().stop();
+        system.run();
+        let query = {
+            let proxy_server_recording = proxy_server_recording_arc.lock().unwrap();
+            let query = proxy_server_recording.get_record::<TunneledDnsQuery>(0);
+            assert_eq!(query.name, "example.com".to_string());
+            assert_eq!(query.record_type, u16::from(RecordType::MX));
+            assert_eq!(proxy_server_recording.len(), 1);
+            (query.query_id, query.respond_to.clone())
+        };
+        query
+            .1
+            .unbounded_send(TunneledDnsAnswer::new(
+                query.0,
+                0,
+                vec![DnsRecord {
+                    name: "example.com".to_string(),
+                    ttl: 300,
+                    data: DnsRecordData::Mx(10, "mail.example.com".to_string()),
+                }],
+            ))
+            .unwrap();
+        let second_poll = poll_once(&mut subject);
+        socket_wrapper.guts.lock().unwrap().buf = request.clone();
+        let third_poll = poll_once(&mut subject);
+
+        assert_eq!(first_poll, Ok(Async::NotReady));
+        assert_eq!(second_poll, Ok(Async::NotReady));
+        assert_eq!(third_poll, Ok(Async::NotReady));
+        assert_eq!(subject.next_query_id, 1);
+        assert!(subject.pending_queries.is_empty());
+        let guts = socket_wrapper.guts.lock().unwrap();
+        assert_eq!(
+            guts.log
+                .iter()
+                .filter(|entry| entry.starts_with("send_to"))
+                .collect::<Vec<&String>>(),
+            vec![
+                "send_to (buf, V4(1.2.3.4:5678))",
+                "send_to (buf, V4(2.3.4.5:6789))"
+            ]
+        );
+        guts.sent.iter().for_each(|sent| {
+            let mut response = sent.clone();
+            let length = response.len();
+            let facade = PacketFacade::new(&mut response, length);
+            assert_eq!(facade.get_transaction_id(), Some(0x1234));
+            assert_eq!(facade.get_rcode(), Some(ResponseCode::NoError.low()));
+            let answers = facade.get_answers().unwrap();
+            assert_eq!(answers.len(), 1);
+            assert_eq!(answers[0].get_resource_type(), u16::from(RecordType::MX));
+            assert!(answers[0].get_time_to_live() > 295);
+            assert_eq!(
+                answers[0].get_rdata(),
+                &b"\x00\x0a\x04mail\x07example\x03com\x00"[..]
+            );
+        });
+    }
+
+    #[test]
+    fn address_queries_are_answered_with_localhost_even_when_tunneling() {
+        let _system =
+            System::new("address_queries_are_answered_with_localhost_even_when_tunneling");
+        let (proxy_server, _, _) = make_recorder();
+        let tunnel = EntryDnsTunnel::new();
+        tunnel.connect(proxy_server.start().recipient(), EntryDnsMode::Tunnel);
+        let request = make_request(RecordType::A);
+        let socket_wrapper = Box::new(UdpSocketWrapperMock::new(&request));
+        socket_wrapper
+            .recv_from_results
+            .lock()
+            .unwrap()
+            .push(Ok(Async::Ready((
+                request.len(),
+                SocketAddr::from_str("1.2.3.4:5678").unwrap(),
+            ))));
+        socket_wrapper
+            .recv_from_results
+            .lock()
+            .unwrap()
+            .push(Ok(Async::NotReady));
+        socket_wrapper
+            .send_to_results
+            .lock()
+            .unwrap()
+            .push(Ok(Async::Ready(100)));
+        let mut subject = make_tunneling_subject(socket_wrapper.clone(), tunnel);
+
+        let result = poll_once(&mut subject);
+
+        assert_eq!(result, Ok(Async::NotReady));
+        assert_eq!(subject.next_query_id, 0);
+        let mut response = socket_wrapper.guts.lock().unwrap().sent[0].clone();
+        let length = response.len();
+        let facade = PacketFacade::new(&mut response, length);
+        let answers = facade.get_answers().unwrap();
+        assert_eq!(answers[0].get_rdata(), &[127, 0, 0, 1][..]);
+    }
+
+    #[test]
+    fn initialize_as_unprivileged_picks_up_the_bypass_list_from_the_tunnel() {
+        let tunnel = EntryDnsTunnel::new();
+        tunnel.set_bypass(
+            BypassList::new(vec![BypassList::parse_rule("example.com").unwrap()]),
+            vec![SocketAddr::from_str("9.9.9.9:53").unwrap()],
+        );
+        let mut subject = make_tunneling_subject(make_socket_wrapper_mock(), tunnel);
+
+        subject.initialize_as_unprivileged(&vec![], &mut FakeStreamHolder::new().streams());
+
+        assert_eq!(
+            subject.bypass_list,
+            BypassList::new(vec![BypassList::parse_rule("example.com").unwrap()])
+        );
+        assert_eq!(
+            subject.bypass_dns_servers,
+            vec![SocketAddr::from_str("9.9.9.9:53").unwrap()]
+        );
+    }
+
+    #[test]
+    fn bypassed_queries_are_forwarded_to_the_original_dns_server_and_answered_from_there() {
+        let request = make_request(RecordType::A);
+        let client = SocketAddr::from_str("1.2.3.4:5678").unwrap();
+        let dns_server = SocketAddr::from_str("9.9.9.9:53").unwrap();
+        let socket_wrapper = Box::new(UdpSocketWrapperMock::new(&request));
+        {
+            let mut recv_from_results = socket_wrapper.recv_from_results.lock().unwrap();
+            recv_from_results.push(Ok(Async::Ready((request.len(), client))));
+            recv_from_results.push(Ok(Async::NotReady));
+            recv_from_results.push(Ok(Async::NotReady));
+            socket_wrapper
+                .send_to_results
+                .lock()
+                .unwrap()
+                .push(Ok(Async::Ready(request.len())));
+        }
+        // The mock echoes what's sent to it, so the "answer" is the forwarded query itself
+        let upstream_socket = make_upstream_socket_mock(&request, Ok(Async::Ready(request.len())));
+        upstream_socket
+            .recv_from_results
+            .lock()
+            .unwrap()
+            .push(Ok(Async::Ready((request.len(), dns_server))));
+        let socket_factory = UpstreamSocketFactoryMock::new(vec![upstream_socket.clone()]);
+        let make_params_arc = socket_factory.make_params.clone();
+        let mut subject = make_instrumented_subject(socket_wrapper.clone());
+        subject.bypass_list = BypassList::new(vec![BypassList::parse_rule("example.com").unwrap()]);
+        subject.bypass_dns_servers = vec![dns_server];
+        subject.upstream_socket_factory = Box::new(socket_factory);
+
+        let first_poll = poll_once(&mut subject);
+        let forwarded = upstream_socket.guts.lock().unwrap().sent[0].clone();
+        let transaction_id = subject.forwarded_queries[0].transaction_id;
+        let second_poll = poll_once(&mut subject);
+
+        assert_eq!(first_poll, Ok(Async::NotReady));
+        assert_eq!(second_poll, Ok(Async::NotReady));
+        assert_eq!(*make_params_arc.lock().unwrap(), vec![dns_server]);
+        assert_eq!(&forwarded[0..2], &transaction_id.to_be_bytes());
+        assert_eq!(&forwarded[2..], &request[2..]);
+        assert_eq!(
+            upstream_socket.guts.lock().unwrap().log[0],
+            "send_to (buf, V4(9.9.9.9:53))"
+        );
+        let guts = socket_wrapper.guts.lock().unwrap();
+        assert_eq!(guts.sent, vec![request.clone()]);
+        assert_eq!(
+            guts.log
+                .iter()
+                .filter(|entry| entry.starts_with("send_to"))
+                .collect::<Vec<&String>>(),
+            vec!["send_to (buf, V4(1.2.3.4:5678))"]
+        );
+        assert!(subject.forwarded_queries.is_empty());
+    }
+
+    #[test]
+    fn answers_from_unexpected_sources_or_with_unexpected_ids_are_discarded() {
+        let request = make_request(RecordType::A);
+        let socket_wrapper = make_socket_wrapper_mock();
+        socket_wrapper
+            .recv_from_results
+            .lock()
+            .unwrap()
+            .push(Ok(Async::NotReady));
+        let upstream_socket = Box::new(UdpSocketWrapperMock::new(&request));
+        {
+            let mut recv_from_results = upstream_socket.recv_from_results.lock().unwrap();
+            recv_from_results.push(Ok(Async::Ready((
+                request.len(),
+                SocketAddr::from_str("6.6.6.6:53").unwrap(),
+            ))));
+            recv_from_results.push(Ok(Async::Ready((
+                request.len(),
+                SocketAddr::from_str("9.9.9.9:53").unwrap(),
+            ))));
+            recv_from_results.push(Ok(Async::NotReady));
+        }
+        let mut subject = make_instrumented_subject(socket_wrapper.clone());
+        subject.bypass_list = BypassList::new(vec![BypassList::parse_rule("example.com").unwrap()]);
+        subject.bypass_dns_servers = vec![SocketAddr::from_str("9.9.9.9:53").unwrap()];
+        subject.forwarded_queries.push(ForwardedQuery {
+            client: DnsClient::Udp(SocketAddr::from_str("1.2.3.4:5678").unwrap()),
+            request: request.clone(),
+            transaction_id: 0x5678,
+            socket: upstream_socket,
+            server_index: 0,
+            servers_left: 0,
+            sent_at: Instant::now(),
+        });
+
+        let result = poll_once(&mut subject);
+
+        assert_eq!(result, Ok(Async::NotReady));
+        assert!(socket_wrapper.guts.lock().unwrap().sent.is_empty());
+        assert_eq!(subject.forwarded_queries.len(), 1);
+    }
+
+    #[test]
+    fn bypassed_queries_fail_over_to_the_next_dns_server_when_sending_fails() {
+        let request = make_request(RecordType::A);
+        let first_server = SocketAddr::from_str("9.9.9.9:53").unwrap();
+        let second_server = SocketAddr::from_str("8.8.8.8:53").unwrap();
+        let socket_wrapper = Box::new(UdpSocketWrapperMock::new(&request));
+        {
+            let mut recv_from_results = socket_wrapper.recv_from_results.lock().unwrap();
+            recv_from_results.push(Ok(Async::Ready((
+                request.len(),
+                SocketAddr::from_str("1.2.3.4:5678").unwrap(),
+            ))));
+            recv_from_results.push(Ok(Async::NotReady));
+        }
+        let first_socket =
+            make_upstream_socket_mock(&request, Err(Error::from(ErrorKind::ConnectionRefused)));
+        let second_socket = make_upstream_socket_mock(&request, Ok(Async::Ready(request.len())));
+        let socket_factory =
+            UpstreamSocketFactoryMock::new(vec![first_socket, second_socket.clone()]);
+        let make_params_arc = socket_factory.make_params.clone();
+        let mut subject = make_instrumented_subject(socket_wrapper);
+        subject.bypass_list = BypassList::new(vec![BypassList::parse_rule("example.com").unwrap()]);
+        subject.bypass_dns_servers = vec![first_server, second_server];
+        subject.upstream_socket_factory = Box::new(socket_factory);
+
+        let result = poll_once(&mut subject);
+
+        assert_eq!(result, Ok(Async::NotReady));
+        assert_eq!(
+            *make_params_arc.lock().unwrap(),
+            vec![first_server, second_server]
+        );
+        assert_eq!(second_socket.guts.lock().unwrap().sent.len(), 1);
+        assert_eq!(subject.preferred_dns_server, 1);
+        assert_eq!(subject.forwarded_queries.len(), 1);
+        assert_eq!(subject.forwarded_queries[0].server_index, 1);
+        assert_eq!(subject.forwarded_queries[0].servers_left, 0);
+    }
+
+    #[test]
+    fn bypassed_queries_fail_over_to_the_next_dns_server_when_the_first_is_silent() {
+        let request = make_request(RecordType::A);
+        let first_server = SocketAddr::from_str("9.9.9.9:53").unwrap();
+        let second_server = SocketAddr::from_str("8.8.8.8:53").unwrap();
+        let socket_wrapper = make_socket_wrapper_mock();
+        socket_wrapper
+            .recv_from_results
+            .lock()
+            .unwrap()
+            .push(Ok(Async::NotReady));
+        let silent_socket = Box::new(UdpSocketWrapperMock::new(&request));
+        silent_socket
+            .recv_from_results
+            .lock()
+            .unwrap()
+            .push(Ok(Async::NotReady));
+        let second_socket = make_upstream_socket_mock(&request, Ok(Async::Ready(request.len())));
+        let socket_factory = UpstreamSocketFactoryMock::new(vec![second_socket.clone()]);
+        let make_params_arc = socket_factory.make_params.clone();
+        let mut subject = make_instrumented_subject(socket_wrapper);
+        subject.bypass_list = BypassList::new(vec![BypassList::parse_rule("example.com").unwrap()]);
+        subject.bypass_dns_servers = vec![first_server, second_server];
+        subject.upstream_socket_factory = Box::new(socket_factory);
+        subject.forwarded_queries.push(ForwardedQuery {
+            client: DnsClient::Udp(SocketAddr::from_str("1.2.3.4:5678").unwrap()),
+            request: request.clone(),
+            transaction_id: 0x5678,
+            socket: silent_socket,
+            server_index: 0,
+            servers_left: 1,
+            sent_at: Instant::now() - FORWARDED_QUERY_FAILOVER,
+        });
+
+        let result = poll_once(&mut subject);
+
+        assert_eq!(result, Ok(Async::NotReady));
+        assert_eq!(*make_params_arc.lock().unwrap(), vec![second_server]);
+        let forwarded = second_socket.guts.lock().unwrap().sent[0].clone();
+        assert_eq!(&forwarded[2..], &request[2..]);
+        assert_eq!(subject.preferred_dns_server, 1);
+        assert_eq!(subject.forwarded_queries.len(), 1);
+        assert_eq!(subject.forwarded_queries[0].server_index, 1);
+        assert_eq!(subject.forwarded_queries[0].servers_left, 0);
+    }
+
+    #[test]
+    fn reverse_lookups_for_bypassed_networks_are_forwarded() {
+        let request = make_request_for("4.3.168.192.in-addr.arpa", RecordType::PTR);
+        let socket_wrapper = Box::new(UdpSocketWrapperMock::new(&request));
+        {
+            let mut recv_from_results = socket_wrapper.recv_from_results.lock().unwrap();
+            recv_from_results.push(Ok(Async::Ready((
+                request.len(),
+                SocketAddr::from_str("1.2.3.4:5678").unwrap(),
+            ))));
+            recv_from_results.push(Ok(Async::NotReady));
+        }
+        let upstream_socket = make_upstream_socket_mock(&request, Ok(Async::Ready(request.len())));
+        let mut subject = make_instrumented_subject(socket_wrapper);
+        subject.bypass_list =
+            BypassList::new(vec![BypassList::parse_rule("192.168.0.0/16").unwrap()]);
+        subject.bypass_dns_servers = vec![SocketAddr::from_str("9.9.9.9:53").unwrap()];
+        subject.upstream_socket_factory =
+            Box::new(UpstreamSocketFactoryMock::new(
+                vec![upstream_socket.clone()],
+            ));
+
+        let result = poll_once(&mut subject);
+
+        assert_eq!(result, Ok(Async::NotReady));
+        assert_eq!(upstream_socket.guts.lock().unwrap().sent.len(), 1);
+        assert_eq!(subject.forwarded_queries.len(), 1);
+    }
+
+    #[test]
+    fn names_that_resolve_into_bypassed_networks_are_forwarded() {
+        let _system = System::new("names_that_resolve_into_bypassed_networks_are_forwarded");
+        let (proxy_server, _, proxy_server_recording_arc) = make_recorder();
+        let tunnel = EntryDnsTunnel::new();
+        tunnel.connect(proxy_server.start().recipient(), EntryDnsMode::Tunnel);
+        let request = make_request(RecordType::CNAME);
+        let socket_wrapper = Box::new(UdpSocketWrapperMock::new(&request));
+        {
+            let mut recv_from_results = socket_wrapper.recv_from_results.lock().unwrap();
+            recv_from_results.push(Ok(Async::Ready((
+                request.len(),
+                SocketAddr::from_str("1.2.3.4:5678").unwrap(),
+            ))));
+            recv_from_results.push(Ok(Async::NotReady));
+        }
+        let upstream_socket = make_upstream_socket_mock(&request, Ok(Async::Ready(request.len())));
+        let mut subject = make_tunneling_subject(socket_wrapper.clone(), tunnel);
+        subject.bypass_list = BypassList::new(vec![BypassList::parse_rule("10.0.0.0/8").unwrap()]);
+        subject.bypass_dns_servers = vec![SocketAddr::from_str("9.9.9.9:53").unwrap()];
+        subject.upstream_socket_factory =
+            Box::new(UpstreamSocketFactoryMock::new(
+                vec![upstream_socket.clone()],
+            ));
+        subject.answer_cache.insert(
+            ("example.com".to_string(), u16::from(RecordType::CNAME)),
+            CachedAnswer {
+                answer: TunneledDnsAnswer::new(
+                    0,
+                    0,
+                    vec![
+                        DnsRecord {
+                            name: "example.com".to_string(),
+                            ttl: 300,
+                            data: DnsRecordData::Cname("intranet.example.com".to_string()),
+                        },
+                        DnsRecord {
+                            name: "intranet.example.com".to_string(),
+                            ttl: 300,
+                            data: DnsRecordData::A(Ipv4Addr::new(10, 1, 2, 3)),
+                        },
+                    ],
+                ),
+                cached_at: Instant::now(),
+                lifetime: 300,
+            },
+        );
+
+        let result = poll_once(&mut subject);
+
+        assert_eq!(result, Ok(Async::NotReady));
+        assert_eq!(upstream_socket.guts.lock().unwrap().sent.len(), 1);
+        assert!(socket_wrapper.guts.lock().unwrap().sent.is_empty());
+        assert_eq!(proxy_server_recording_arc.lock().unwrap().len(), 0);
+    }
+
+    struct UpstreamSocketFactoryMock {
+        make_params: Arc<Mutex<Vec<SocketAddr>>>,
+        sockets: Mutex<Vec<Box<UdpSocketWrapperMock>>>,
+    }
+
+    impl UpstreamSocketFactory for UpstreamSocketFactoryMock {
+        fn make(&self, dns_server: SocketAddr) -> io::Result<Box<dyn UdpSocketWrapperTrait>> {
+            self.make_params.lock().unwrap().push(dns_server);
+            Ok(self.sockets.lock().unwrap().remove(0))
+        }
+    }
+
+    impl UpstreamSocketFactoryMock {
+        fn new(sockets: Vec<Box<UdpSocketWrapperMock>>) -> UpstreamSocketFactoryMock {
+            UpstreamSocketFactoryMock {
+                make_params: Arc::new(Mutex::new(vec![])),
+                sockets: Mutex::new(sockets),
+            }
+        }
+    }
+
+    struct TokioListenerWrapperMock {
+        bind_params: Arc<Mutex<Vec<SocketAddr>>>,
+        bind_result: Option<io::Result<()>>,
+    }
+
+    impl TokioListenerWrapper for TokioListenerWrapperMock {
+        fn bind(&mut self, addr: SocketAddr) -> io::Result<()> {
+            self.bind_params.lock().unwrap().push(addr);
+            self.bind_result.take().unwrap()
+        }
+
+        fn poll_accept(&mut self) -> io::Result<Async<(tokio::net::TcpStream, SocketAddr)>> {
+            Ok(Async::NotReady)
+        }
+    }
+
+    fn add_tcp_connection(
+        subject: &mut DnsSocketServer,
+        reader: ReadHalfWrapperMock,
+        writer: WriteHalfWrapperMock,
+    ) {
+        subject.tcp_listening = true;
+        subject.tcp_connections.insert(
+            0,
+            Box::new(DnsTcpConnection::new(
+                SocketAddr::from_str("3.4.5.6:7890").unwrap(),
+                Box::new(reader),
+                Box::new(writer),
+            )),
+        );
+    }
+
+    fn with_length_prefix(message: &[u8]) -> Vec<u8> {
+        let mut result = (message.len() as u16).to_be_bytes().to_vec();
+        result.extend_from_slice(message);
+        result
+    }
+
+    fn connect_with_retries(socket_addr: SocketAddr) -> TcpStream {
+        let mut attempts = 0;
+        loop {
+            match TcpStream::connect(socket_addr) {
+                Ok(stream) => return stream,
+                Err(e) if attempts >= 50 => panic!("Couldn't connect to {}: {}", socket_addr, e),
+                Err(_) => {
+                    attempts += 1;
+                    thread::sleep(Duration::from_millis(100));
+                }
+            }
+        }
+    }
+
     fn make_socket_wrapper_mock() -> Box<UdpSocketWrapperMock> {
         Box::new(UdpSocketWrapperMock::new(&[
             0x12, 0x34, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
@@ -349,9 +1729,55 @@ mod tests {
     }
 
     fn make_instrumented_subject(socket_wrapper: Box<UdpSocketWrapperMock>) -> DnsSocketServer {
-        DnsSocketServer {
-            socket_wrapper,
-            buf: [0; 65536],
-        }
+        make_tunneling_subject(socket_wrapper, EntryDnsTunnel::new())
+    }
+
+    fn make_tunneling_subject(
+        socket_wrapper: Box<UdpSocketWrapperMock>,
+        tunnel: EntryDnsTunnel,
+    ) -> DnsSocketServer {
+        let mut subject = DnsSocketServer::new(tunnel);
+        subject.socket_wrapper = socket_wrapper;
+        subject.tcp_listener = Box::new(TokioListenerWrapperMock {
+            bind_params: Arc::new(Mutex::new(vec![])),
+            bind_result: Some(Ok(())),
+        });
+        subject.doh_listener = Box::new(TokioListenerWrapperMock {
+            bind_params: Arc::new(Mutex::new(vec![])),
+            bind_result: Some(Ok(())),
+        });
+        subject
+    }
+
+    fn make_upstream_socket_mock(
+        request: &[u8],
+        send_to_result: Result<Async<usize>, Error>,
+    ) -> Box<UdpSocketWrapperMock> {
+        let socket = Box::new(UdpSocketWrapperMock::new(&vec![0; request.len()]));
+        socket.send_to_results.lock().unwrap().push(send_to_result);
+        socket
+    }
+
+    fn make_request(record_type: RecordType) -> Vec<u8> {
+        make_request_for("example.com", record_type)
+    }
+
+    fn make_request_for(name: &str, record_type: RecordType) -> Vec<u8> {
+        let mut buf = [0u8; 100];
+        let length = {
+            let mut facade = PacketFacade::new(&mut buf, 12);
+            facade.set_transaction_id(0x1234);
+            facade.set_query(true);
+            facade.set_opcode(OpCode::Query.into());
+            facade.add_query(name, record_type.into(), DNSClass::IN.into());
+            facade.get_length()
+        };
+        buf[0..length].to_vec()
+    }
+
+    fn poll_once(subject: &mut DnsSocketServer) -> Result<Async<()>, ()> {
+        executor::spawn(lazy(|| Ok::<_, ()>(subject.poll())))
+            .wait_future()
+            .unwrap()
     }
 }
@@ -0,0 +1,209 @@
+// Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use crate::sub_lib::tokio_wrappers::ReadHalfWrapper;
+use crate::sub_lib::tokio_wrappers::WriteHalfWrapper;
+use std::net::SocketAddr;
+use std::time::Duration;
+use std::time::Instant;
+use tokio::prelude::Async;
+use tokio::prelude::AsyncRead;
+use tokio::prelude::AsyncWrite;
+
+// A connection to entry DNS over TCP, whatever the framing of the messages on it.
+pub trait DnsConnection: Send {
+    fn peer_addr(&self) -> SocketAddr;
+    // The requests that have arrived complete since the last call; Err if the connection broke.
+    fn read_requests(&mut self) -> Result<Vec<Vec<u8>>, ()>;
+    fn queue_response(&mut self, response: &[u8]);
+    // Err once the connection is finished with: either it broke, or the client has hung up and
+    // has been sent everything it was owed.
+    fn flush(&mut self) -> Result<(), ()>;
+    fn is_idle_for(&self, duration: Duration) -> bool;
+}
+
+// A client asking entry DNS questions over TCP, usually because a UDP answer came back truncated.
+// Messages in both directions go with two-byte length prefixes (RFC 7766).
+pub struct DnsTcpConnection {
+    peer_addr: SocketAddr,
+    reader: Box<dyn ReadHalfWrapper>,
+    writer: Box<dyn WriteHalfWrapper>,
+    incoming: Vec<u8>,
+    outgoing: Vec<u8>,
+    hung_up: bool,
+    last_active: Instant,
+}
+
+impl DnsConnection for DnsTcpConnection {
+    fn peer_addr(&self) -> SocketAddr {
+        self.peer_addr
+    }
+
+    fn read_requests(&mut self) -> Result<Vec<Vec<u8>>, ()> {
+        let mut buf = [0u8; 4096];
+        while !self.hung_up {
+            match self.reader.poll_read(&mut buf) {
+                Ok(Async::Ready(0)) => self.hung_up = true,
+                Ok(Async::Ready(len)) => {
+                    self.incoming.extend_from_slice(&buf[0..len]);
+                    self.last_active = Instant::now();
+                }
+                Ok(Async::NotReady) => break,
+                Err(_) => return Err(()),
+            }
+        }
+        let mut requests = vec![];
+        while self.incoming.len() >= 2 {
+            let length = ((self.incoming[0] as usize) << 8) | (self.incoming[1] as usize);
+            if self.incoming.len() < length + 2 {
+                break;
+            }
+            requests.push(self.incoming[2..(length + 2)].to_vec());
+            self.incoming.drain(0..(length + 2));
+        }
+        Ok(requests)
+    }
+
+    fn queue_response(&mut self, response: &[u8]) {
+        self.outgoing
+            .extend_from_slice(&(response.len() as u16).to_be_bytes());
+        self.outgoing.extend_from_slice(response);
+    }
+
+    fn flush(&mut self) -> Result<(), ()> {
+        while !self.outgoing.is_empty() {
+            match self.writer.poll_write(&self.outgoing) {
+                Ok(Async::Ready(0)) | Err(_) => return Err(()),
+                Ok(Async::Ready(len)) => {
+                    self.outgoing.drain(0..len);
+                    self.last_active = Instant::now();
+                }
+                Ok(Async::NotReady) => return Ok(()),
+            }
+        }
+        if self.hung_up {
+            Err(())
+        } else {
+            Ok(())
+        }
+    }
+
+    fn is_idle_for(&self, duration: Duration) -> bool {
+        self.outgoing.is_empty() && self.last_active.elapsed() >= duration
+    }
+}
+
+impl DnsTcpConnection {
+    pub fn new(
+        peer_addr: SocketAddr,
+        reader: Box<dyn ReadHalfWrapper>,
+        writer: Box<dyn WriteHalfWrapper>,
+    ) -> DnsTcpConnection {
+        DnsTcpConnection {
+            peer_addr,
+            reader,
+            writer,
+            incoming: vec![],
+            outgoing: vec![],
+            hung_up: false,
+            last_active: Instant::now(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::tokio_wrapper_mocks::ReadHalfWrapperMock;
+    use crate::test_utils::tokio_wrapper_mocks::WriteHalfWrapperMock;
+    use std::io;
+    use std::io::ErrorKind;
+    use std::str::FromStr;
+    use std::sync::Arc;
+    use std::sync::Mutex;
+
+    #[test]
+    fn requests_are_read_whole_however_they_arrive() {
+        let reader = ReadHalfWrapperMock::new()
+            .poll_read_ok(vec![0x00, 0x03, b'a'])
+            .poll_read_ok(vec![b'b', b'c', 0x00, 0x01, b'd', 0x00])
+            .poll_read_result(vec![], Ok(Async::NotReady))
+            .poll_read_ok(vec![0x02, b'e', b'f'])
+            .poll_read_result(vec![], Ok(Async::NotReady));
+        let mut subject = make_subject(reader, WriteHalfWrapperMock::new());
+
+        let first_result = subject.read_requests();
+        let second_result = subject.read_requests();
+
+        assert_eq!(first_result, Ok(vec![b"abc".to_vec(), b"d".to_vec()]));
+        assert_eq!(second_result, Ok(vec![b"ef".to_vec()]));
+    }
+
+    #[test]
+    fn broken_connections_are_reported() {
+        let reader = ReadHalfWrapperMock::new()
+            .poll_read_result(vec![], Err(io::Error::from(ErrorKind::ConnectionReset)));
+        let mut subject = make_subject(reader, WriteHalfWrapperMock::new());
+
+        let result = subject.read_requests();
+
+        assert_eq!(result, Err(()));
+    }
+
+    #[test]
+    fn responses_are_written_with_length_prefixes_as_the_connection_allows() {
+        let poll_write_params_arc = Arc::new(Mutex::new(vec![]));
+        let writer = WriteHalfWrapperMock::new()
+            .poll_write_params(&poll_write_params_arc)
+            .poll_write_ok(3)
+            .poll_write_result(Ok(Async::NotReady))
+            .poll_write_ok(3);
+        let mut subject = make_subject(ReadHalfWrapperMock::new(), writer);
+        subject.queue_response(b"abcd");
+
+        let first_result = subject.flush();
+        let second_result = subject.flush();
+
+        assert_eq!(first_result, Ok(()));
+        assert_eq!(second_result, Ok(()));
+        assert_eq!(
+            *poll_write_params_arc.lock().unwrap(),
+            vec![b"\x00\x04abcd".to_vec(), b"bcd".to_vec(), b"bcd".to_vec()]
+        );
+        assert!(subject.outgoing.is_empty());
+    }
+
+    #[test]
+    fn connections_are_finished_once_the_client_hangs_up_and_has_its_responses() {
+        let reader = ReadHalfWrapperMock::new()
+            .poll_read_ok(vec![0x00, 0x01, b'a'])
+            .poll_read_result(vec![], Ok(Async::Ready(0)));
+        let writer = WriteHalfWrapperMock::new().poll_write_ok(3);
+        let mut subject = make_subject(reader, writer);
+
+        let requests = subject.read_requests();
+        subject.queue_response(b"b");
+        let result = subject.flush();
+
+        assert_eq!(requests, Ok(vec![b"a".to_vec()]));
+        assert_eq!(result, Err(()));
+    }
+
+    #[test]
+    fn connections_with_responses_waiting_are_not_idle() {
+        let mut subject = make_subject(ReadHalfWrapperMock::new(), WriteHalfWrapperMock::new());
+
+        let idle_before = subject.is_idle_for(Duration::from_millis(0));
+        subject.queue_response(b"a");
+        let idle_after = subject.is_idle_for(Duration::from_millis(0));
+
+        assert_eq!(idle_before, true);
+        assert_eq!(idle_after, false);
+    }
+
+    fn make_subject(reader: ReadHalfWrapperMock, writer: WriteHalfWrapperMock) -> DnsTcpConnection {
+        DnsTcpConnection::new(
+            SocketAddr::from_str("1.2.3.4:5678").unwrap(),
+            Box::new(reader),
+            Box::new(writer),
+        )
+    }
+}
@@ -0,0 +1,394 @@
+// Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use crate::entry_dns::dns_tcp_connection::DnsConnection;
+use crate::sub_lib::tokio_wrappers::ReadHalfWrapper;
+use crate::sub_lib::tokio_wrappers::WriteHalfWrapper;
+use std::net::SocketAddr;
+use std::time::Duration;
+use std::time::Instant;
+use tokio::prelude::Async;
+use tokio::prelude::AsyncRead;
+use tokio::prelude::AsyncWrite;
+
+const DOH_PATH: &str = "/dns-query";
+const DOH_CONTENT_TYPE: &str = "application/dns-message";
+const MAX_HEADER_SIZE: usize = 8192;
+const MAX_MESSAGE_SIZE: usize = 65535;
+
+// A client asking entry DNS questions the way RFC 8484 has them asked: a GET with the query
+// base64url-encoded in the dns parameter, or a POST with the query as its body. Entry DNS only
+// listens for these on localhost, in plain HTTP. Requests are taken one at a time, so that the
+// responses go back in the order HTTP/1.1 requires.
+pub struct DohConnection {
+    peer_addr: SocketAddr,
+    reader: Box<dyn ReadHalfWrapper>,
+    writer: Box<dyn WriteHalfWrapper>,
+    incoming: Vec<u8>,
+    outgoing: Vec<u8>,
+    awaiting_response: bool,
+    hung_up: bool,
+    closing: bool,
+    last_active: Instant,
+}
+
+impl DnsConnection for DohConnection {
+    fn peer_addr(&self) -> SocketAddr {
+        self.peer_addr
+    }
+
+    fn read_requests(&mut self) -> Result<Vec<Vec<u8>>, ()> {
+        let mut buf = [0u8; 4096];
+        while !self.hung_up {
+            match self.reader.poll_read(&mut buf) {
+                Ok(Async::Ready(0)) => self.hung_up = true,
+                Ok(Async::Ready(len)) => {
+                    self.incoming.extend_from_slice(&buf[0..len]);
+                    self.last_active = Instant::now();
+                }
+                Ok(Async::NotReady) => break,
+                Err(_) => return Err(()),
+            }
+        }
+        if self.awaiting_response || self.closing {
+            return Ok(vec![]);
+        }
+        match self.parse_request() {
+            Ok(Some(request)) => {
+                self.awaiting_response = true;
+                Ok(vec![request])
+            }
+            Ok(None) => Ok(vec![]),
+            Err(status) => {
+                self.reject(status);
+                Ok(vec![])
+            }
+        }
+    }
+
+    fn queue_response(&mut self, response: &[u8]) {
+        self.outgoing.extend_from_slice(
+            format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: {}\r\nContent-Length: {}\r\n\r\n",
+                DOH_CONTENT_TYPE,
+                response.len()
+            )
+            .as_bytes(),
+        );
+        self.outgoing.extend_from_slice(response);
+        self.awaiting_response = false;
+    }
+
+    fn flush(&mut self) -> Result<(), ()> {
+        while !self.outgoing.is_empty() {
+            match self.writer.poll_write(&self.outgoing) {
+                Ok(Async::Ready(0)) | Err(_) => return Err(()),
+                Ok(Async::Ready(len)) => {
+                    self.outgoing.drain(0..len);
+                    self.last_active = Instant::now();
+                }
+                Ok(Async::NotReady) => return Ok(()),
+            }
+        }
+        if self.closing || (self.hung_up && !self.awaiting_response) {
+            Err(())
+        } else {
+            Ok(())
+        }
+    }
+
+    fn is_idle_for(&self, duration: Duration) -> bool {
+        self.outgoing.is_empty() && self.last_active.elapsed() >= duration
+    }
+}
+
+impl DohConnection {
+    pub fn new(
+        peer_addr: SocketAddr,
+        reader: Box<dyn ReadHalfWrapper>,
+        writer: Box<dyn WriteHalfWrapper>,
+    ) -> DohConnection {
+        DohConnection {
+            peer_addr,
+            reader,
+            writer,
+            incoming: vec![],
+            outgoing: vec![],
+            awaiting_response: false,
+            hung_up: false,
+            closing: false,
+            last_active: Instant::now(),
+        }
+    }
+
+    // Ok(None) until a whole request has arrived; Err with the HTTP status it deserves if it's
+    // no DNS query.
+    fn parse_request(&mut self) -> Result<Option<Vec<u8>>, u16> {
+        let header_end = match find(&self.incoming, b"\r\n\r\n") {
+            Some(position) => position + 4,
+            None if self.incoming.len() > MAX_HEADER_SIZE => return Err(431),
+            None => return Ok(None),
+        };
+        let header = String::from_utf8_lossy(&self.incoming[0..header_end]).to_string();
+        let request_line = header
+            .split("\r\n")
+            .next()
+            .unwrap_or("")
+            .split(' ')
+            .collect::<Vec<&str>>();
+        let (method, target) = match request_line.as_slice() {
+            [method, target, version] if version.starts_with("HTTP/1.") => (*method, *target),
+            _ => return Err(400),
+        };
+        let header_value = |name: &str| {
+            header
+                .split("\r\n")
+                .skip(1)
+                .filter_map(|line| {
+                    let mut parts = line.splitn(2, ':');
+                    match (parts.next(), parts.next()) {
+                        (Some(key), Some(value)) if key.trim().eq_ignore_ascii_case(name) => {
+                            Some(value.trim().to_string())
+                        }
+                        _ => None,
+                    }
+                })
+                .next()
+        };
+        let (path, query_opt) = match target.find('?') {
+            Some(position) => (&target[0..position], Some(&target[(position + 1)..])),
+            None => (target, None),
+        };
+        if path != DOH_PATH {
+            return Err(404);
+        }
+        match method {
+            "GET" => {
+                let encoded = query_opt
+                    .unwrap_or("")
+                    .split('&')
+                    .filter(|parameter| parameter.starts_with("dns="))
+                    .map(|parameter| &parameter[4..])
+                    .next()
+                    .ok_or(400u16)?;
+                let message =
+                    base64::decode_config(encoded, base64::URL_SAFE_NO_PAD).map_err(|_| 400u16)?;
+                if message.is_empty() || message.len() > MAX_MESSAGE_SIZE {
+                    return Err(400);
+                }
+                self.incoming.drain(0..header_end);
+                Ok(Some(message))
+            }
+            "POST" => {
+                match header_value("content-type") {
+                    Some(ref content_type) if content_type.as_str() == DOH_CONTENT_TYPE => (),
+                    _ => return Err(415),
+                }
+                let length = match header_value("content-length")
+                    .and_then(|value| value.parse::<usize>().ok())
+                {
+                    Some(length) if length > 0 && length <= MAX_MESSAGE_SIZE => length,
+                    Some(_) => return Err(413),
+                    None => return Err(411),
+                };
+                if self.incoming.len() < header_end + length {
+                    return Ok(None);
+                }
+                let message = self.incoming[header_end..(header_end + length)].to_vec();
+                self.incoming.drain(0..(header_end + length));
+                Ok(Some(message))
+            }
+            _ => Err(405),
+        }
+    }
+
+    // Anything the connection has sent after a bad request can't be trusted to be framed right,
+    // so the connection goes once the client has heard why.
+    fn reject(&mut self, status: u16) {
+        let reason = match status {
+            404 => "Not Found",
+            405 => "Method Not Allowed",
+            411 => "Length Required",
+            413 => "Payload Too Large",
+            415 => "Unsupported Media Type",
+            431 => "Request Header Fields Too Large",
+            _ => "Bad Request",
+        };
+        self.outgoing.extend_from_slice(
+            format!(
+                "HTTP/1.1 {} {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                status, reason
+            )
+            .as_bytes(),
+        );
+        self.incoming.clear();
+        self.closing = true;
+    }
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::tokio_wrapper_mocks::ReadHalfWrapperMock;
+    use crate::test_utils::tokio_wrapper_mocks::WriteHalfWrapperMock;
+    use std::str::FromStr;
+    use std::sync::Arc;
+    use std::sync::Mutex;
+
+    #[test]
+    fn get_requests_carry_their_queries_base64url_encoded() {
+        let reader = ReadHalfWrapperMock::new()
+            .poll_read_ok(b"GET /dns-query?ct&dns=AAEAAAAB".to_vec())
+            .poll_read_ok(b"AAAAAAAA HTTP/1.1\r\nHost: localhost\r\n\r\n".to_vec())
+            .poll_read_result(vec![], Ok(Async::NotReady));
+        let mut subject = make_subject(reader, WriteHalfWrapperMock::new());
+
+        let result = subject.read_requests();
+
+        assert_eq!(result, Ok(vec![vec![0, 1, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0]]));
+        assert!(subject.incoming.is_empty());
+    }
+
+    #[test]
+    fn post_requests_carry_their_queries_as_their_bodies_and_wait_for_all_of_it() {
+        let reader = ReadHalfWrapperMock::new()
+            .poll_read_ok(
+                b"POST /dns-query HTTP/1.1\r\ncontent-type: application/dns-message\r\nContent-Length: 4\r\n\r\nab"
+                    .to_vec(),
+            )
+            .poll_read_result(vec![], Ok(Async::NotReady))
+            .poll_read_ok(b"cd".to_vec())
+            .poll_read_result(vec![], Ok(Async::NotReady));
+        let mut subject = make_subject(reader, WriteHalfWrapperMock::new());
+
+        let first_result = subject.read_requests();
+        let second_result = subject.read_requests();
+
+        assert_eq!(first_result, Ok(vec![]));
+        assert_eq!(second_result, Ok(vec![b"abcd".to_vec()]));
+    }
+
+    #[test]
+    fn the_next_request_waits_for_the_response_to_the_last() {
+        let poll_write_params_arc = Arc::new(Mutex::new(vec![]));
+        let request = b"GET /dns-query?dns=YWI HTTP/1.1\r\n\r\n";
+        let reader = ReadHalfWrapperMock::new()
+            .poll_read_ok([&request[..], &request[..]].concat())
+            .poll_read_result(vec![], Ok(Async::NotReady))
+            .poll_read_result(vec![], Ok(Async::NotReady))
+            .poll_read_result(vec![], Ok(Async::NotReady));
+        let writer = WriteHalfWrapperMock::new()
+            .poll_write_params(&poll_write_params_arc)
+            .poll_write_ok(80);
+        let mut subject = make_subject(reader, writer);
+
+        let first_result = subject.read_requests();
+        let waiting_result = subject.read_requests();
+        subject.queue_response(b"xyz");
+        let flush_result = subject.flush();
+        let second_result = subject.read_requests();
+
+        assert_eq!(first_result, Ok(vec![b"ab".to_vec()]));
+        assert_eq!(waiting_result, Ok(vec![]));
+        assert_eq!(flush_result, Ok(()));
+        assert_eq!(second_result, Ok(vec![b"ab".to_vec()]));
+        assert_eq!(
+            poll_write_params_arc.lock().unwrap()[0],
+            b"HTTP/1.1 200 OK\r\nContent-Type: application/dns-message\r\nContent-Length: 3\r\n\r\nxyz"
+                .to_vec()
+        );
+    }
+
+    #[test]
+    fn requests_that_are_not_dns_queries_are_turned_away_and_the_connection_closed() {
+        let reject = |request: &[u8]| {
+            let poll_write_params_arc = Arc::new(Mutex::new(vec![]));
+            let reader = ReadHalfWrapperMock::new()
+                .poll_read_ok(request.to_vec())
+                .poll_read_result(vec![], Ok(Async::NotReady));
+            let writer = WriteHalfWrapperMock::new()
+                .poll_write_params(&poll_write_params_arc)
+                .poll_write_ok(200);
+            let mut subject = make_subject(reader, writer);
+
+            let requests = subject.read_requests();
+            let flush_result = subject.flush();
+
+            assert_eq!(requests, Ok(vec![]));
+            assert_eq!(flush_result, Err(()));
+            let written = poll_write_params_arc.lock().unwrap()[0].clone();
+            String::from_utf8(written)
+                .unwrap()
+                .split("\r\n")
+                .next()
+                .unwrap()
+                .to_string()
+        };
+
+        assert_eq!(
+            reject(b"GET /dns-query?dns=!!! HTTP/1.1\r\n\r\n"),
+            "HTTP/1.1 400 Bad Request"
+        );
+        assert_eq!(
+            reject(b"GET /dns-query HTTP/1.1\r\n\r\n"),
+            "HTTP/1.1 400 Bad Request"
+        );
+        assert_eq!(
+            reject(b"GET /index.html HTTP/1.1\r\n\r\n"),
+            "HTTP/1.1 404 Not Found"
+        );
+        assert_eq!(
+            reject(b"PUT /dns-query HTTP/1.1\r\n\r\n"),
+            "HTTP/1.1 405 Method Not Allowed"
+        );
+        assert_eq!(
+            reject(b"POST /dns-query HTTP/1.1\r\nContent-Type: application/dns-message\r\n\r\n"),
+            "HTTP/1.1 411 Length Required"
+        );
+        assert_eq!(
+            reject(b"POST /dns-query HTTP/1.1\r\nContent-Type: text/plain\r\nContent-Length: 2\r\n\r\nab"),
+            "HTTP/1.1 415 Unsupported Media Type"
+        );
+    }
+
+    #[test]
+    fn requests_with_endless_headers_are_turned_away() {
+        let poll_write_params_arc = Arc::new(Mutex::new(vec![]));
+        let reader = ReadHalfWrapperMock::new()
+            .poll_read_ok(b"GET /".to_vec())
+            .poll_read_ok(vec![b'a'; 4096])
+            .poll_read_ok(vec![b'a'; 4096])
+            .poll_read_result(vec![], Ok(Async::NotReady))
+            .poll_read_ok(vec![b'a'; 4096])
+            .poll_read_result(vec![], Ok(Async::NotReady));
+        let writer = WriteHalfWrapperMock::new()
+            .poll_write_params(&poll_write_params_arc)
+            .poll_write_ok(200);
+        let mut subject = make_subject(reader, writer);
+
+        let first_result = subject.read_requests();
+        let second_result = subject.read_requests();
+        let flush_result = subject.flush();
+
+        assert_eq!(first_result, Ok(vec![]));
+        assert_eq!(second_result, Ok(vec![]));
+        assert_eq!(flush_result, Err(()));
+        assert!(
+            String::from_utf8(poll_write_params_arc.lock().unwrap()[0].clone())
+                .unwrap()
+                .starts_with("HTTP/1.1 431 Request Header Fields Too Large\r\n")
+        );
+    }
+
+    fn make_subject(reader: ReadHalfWrapperMock, writer: WriteHalfWrapperMock) -> DohConnection {
+        DohConnection::new(
+            SocketAddr::from_str("127.0.0.1:5678").unwrap(),
+            Box::new(reader),
+            Box::new(writer),
+        )
+    }
+}
@@ -3,4 +3,6 @@
 #[macro_use]
 pub mod packet_facade; // public only so that it can be used by the integration test
 pub mod dns_socket_server;
+mod dns_tcp_connection;
+mod doh_connection;
 mod processing;
@@ -1,5 +1,6 @@
 // Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
 use std::cmp::max;
+use std::cmp::min;
 use std::ops::Add;
 use std::str::from_utf8;
 
@@ -166,6 +167,37 @@ impl ResourceRecord {
     }
 }
 
+pub const OPT_RECORD_TYPE: u16 = 41;
+const MAX_LABEL_LENGTH: usize = 63;
+const MAX_NAME_LENGTH: usize = 255;
+
+// The contents of an EDNS0 OPT pseudo-record (RFC 6891), which borrows the class and time-to-live
+// fields of a resource record for its own purposes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Edns {
+    pub udp_payload_size: u16,
+    pub extended_rcode: u8,
+    pub version: u8,
+    pub dnssec_ok: bool,
+}
+
+impl Edns {
+    fn from_record(record: &ResourceRecord) -> Edns {
+        Edns {
+            udp_payload_size: record.rclass,
+            extended_rcode: (record.time_to_live >> 24) as u8,
+            version: (record.time_to_live >> 16) as u8,
+            dnssec_ok: record.time_to_live & 0x8000 != 0,
+        }
+    }
+
+    fn time_to_live(&self) -> u32 {
+        (u32::from(self.extended_rcode) << 24)
+            | (u32::from(self.version) << 16)
+            | if self.dnssec_ok { 0x8000 } else { 0x0000 }
+    }
+}
+
 #[derive(Debug)]
 pub struct PacketFacade<'a> {
     buf: &'a mut [u8],
@@ -174,6 +206,7 @@ pub struct PacketFacade<'a> {
 
 impl<'a> PacketFacade<'a> {
     pub fn new(buf: &mut [u8], length: usize) -> PacketFacade<'_> {
+        let length = min(length, buf.len());
         PacketFacade { buf, length }
     }
 
@@ -336,7 +369,8 @@ impl<'a> PacketFacade<'a> {
             return false;
         };
         self.establish_high_water(offset + 4);
-        let count = try_flg!(PacketFacade::u16_from(self.buf, 4, self.length)) + 1;
+        let count =
+            try_flg!(try_flg!(PacketFacade::u16_from(self.buf, 4, self.length)).checked_add(1));
         PacketFacade::u16_to(count, self.buf, 4);
         true
     }
@@ -381,7 +415,8 @@ impl<'a> PacketFacade<'a> {
             time_to_live,
             rdata
         ));
-        let count = try_flg!(PacketFacade::u16_from(self.buf, 6, self.length)) + 1;
+        let count =
+            try_flg!(try_flg!(PacketFacade::u16_from(self.buf, 6, self.length)).checked_add(1));
         PacketFacade::u16_to(count, self.buf, 6);
         true
     }
@@ -424,7 +459,8 @@ impl<'a> PacketFacade<'a> {
             time_to_live,
             rdata
         ));
-        let count = try_flg!(PacketFacade::u16_from(self.buf, 8, self.length)) + 1;
+        let count =
+            try_flg!(try_flg!(PacketFacade::u16_from(self.buf, 8, self.length)).checked_add(1));
         PacketFacade::u16_to(count, self.buf, 8);
         true
     }
@@ -467,15 +503,52 @@ impl<'a> PacketFacade<'a> {
             time_to_live,
             rdata
         ));
-        let count = try_flg!(PacketFacade::u16_from(self.buf, 10, self.length)) + 1;
+        let count =
+            try_flg!(try_flg!(PacketFacade::u16_from(self.buf, 10, self.length)).checked_add(1));
         PacketFacade::u16_to(count, self.buf, 10);
         true
     }
 
+    // The OPT pseudo-records in the additional section; a well-formed packet has no more than one.
+    pub fn get_edns(&self) -> Option<Vec<Edns>> {
+        let mut result = vec![];
+        for record in try_opt!(self.get_additionals()) {
+            if record.get_resource_type() != OPT_RECORD_TYPE {
+                continue;
+            }
+            if !record.get_name().is_empty() {
+                return None;
+            }
+            result.push(Edns::from_record(&record));
+        }
+        Some(result)
+    }
+
+    pub fn add_edns(&mut self, edns: &Edns) -> bool {
+        self.add_additional(
+            "",
+            OPT_RECORD_TYPE,
+            edns.udp_payload_size,
+            edns.time_to_live(),
+            &[],
+        )
+    }
+
     pub fn get_length(&self) -> usize {
         self.length
     }
 
+    // Keeps the queries but drops every resource record after them, including any that came in
+    // with a request.
+    pub fn clear_resource_records(&mut self) -> bool {
+        let queries_end = try_flg!(self.find_queries_end());
+        PacketFacade::u16_to(0x0000, &mut self.buf, 6);
+        PacketFacade::u16_to(0x0000, &mut self.buf, 8);
+        PacketFacade::u16_to(0x0000, &mut self.buf, 10);
+        self.length = queries_end;
+        true
+    }
+
     pub fn clear(&mut self) {
         PacketFacade::u16_to(0x0000, &mut self.buf, 4);
         PacketFacade::u16_to(0x0000, &mut self.buf, 6);
@@ -560,30 +633,54 @@ impl<'a> PacketFacade<'a> {
         Some(rdata_end - start)
     }
 
+    // A name ends with an empty label or with a compression pointer (RFC 1035 4.1.4); the two
+    // label types in between are reserved.
     fn find_string_seq_end(buf: &[u8], offset: usize, buflen: usize) -> Option<usize> {
         let mut local_offset = offset;
         loop {
-            if local_offset > buflen {
+            if local_offset >= buflen {
                 return None;
             }
             let length = buf[local_offset] as usize;
-            if length == 0x00 {
-                return Some(local_offset + 1);
+            match length & 0xC0 {
+                0x00 if length == 0x00 => return Some(local_offset + 1),
+                0x00 => local_offset += length + 1,
+                0xC0 if local_offset + 2 <= buflen => return Some(local_offset + 2),
+                _ => return None,
             }
-            local_offset += length + 1;
         }
     }
 
+    // Compression pointers are followed only if they point backward, past where the current run of
+    // labels began, so that no packet can send us around in circles.
     fn extract_string_seq(buf: &[u8], offset: usize, buflen: usize) -> Option<(String, usize)> {
         let mut local_offset = offset;
+        let mut run_start = offset;
+        let mut end_opt = None;
         let mut result = String::from("");
         loop {
             if local_offset >= buflen {
                 return None;
             }
             let length = buf[local_offset] as usize;
-            if length == 0x00 {
-                return Some((result, local_offset + 1));
+            match length & 0xC0 {
+                0x00 if length == 0x00 => {
+                    return Some((result, end_opt.unwrap_or(local_offset + 1)));
+                }
+                0x00 => (),
+                0xC0 => {
+                    let pointer =
+                        try_opt!(PacketFacade::u16_from(buf, local_offset, buflen)) & 0x3FFF;
+                    let pointer = pointer as usize;
+                    if pointer >= run_start {
+                        return None;
+                    }
+                    end_opt = end_opt.or(Some(local_offset + 2));
+                    run_start = pointer;
+                    local_offset = pointer;
+                    continue;
+                }
+                _ => return None,
             }
             if !result.is_empty() {
                 result = result.add(".");
@@ -596,15 +693,25 @@ impl<'a> PacketFacade<'a> {
                 Ok(s) => s,
                 Err(_) => return None,
             });
+            if result.len() > MAX_NAME_LENGTH {
+                return None;
+            }
             local_offset = end;
         }
     }
 
     fn add_string_seq(buf: &mut [u8], offset: usize, string: &str) -> Option<usize> {
         let mut local_offset = offset;
+        let string = string.trim_end_matches('.');
+        if string.len() > MAX_NAME_LENGTH {
+            return None;
+        }
         if !string.is_empty() {
             for part in string.split('.') {
                 let bytes = part.as_bytes();
+                if bytes.is_empty() || bytes.len() > MAX_LABEL_LENGTH {
+                    return None;
+                }
                 if (local_offset + bytes.len() + 1) > buf.len() {
                     return None;
                 }
@@ -626,6 +733,8 @@ impl<'a> PacketFacade<'a> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use rand::rngs::SmallRng;
+    use rand::{Rng, SeedableRng};
 
     #[test]
     fn query_complains_when_name_length_busts_length_limit() {
@@ -1801,6 +1910,182 @@ mod tests {
         assert_eq!(additionals.len(), 2);
     }
 
+    #[test]
+    fn names_can_be_compressed_with_pointers_to_earlier_names() {
+        let mut buf: [u8; 100] = [0; 100];
+        let length = {
+            let mut adder = ByteHandle::new(&mut buf, 12);
+            adder.add_bytes(b"\x07example\x03com\x00");
+            adder.add_bytes(b"\x03www\xC0\x0C");
+            adder.get_offset()
+        };
+
+        let extracted = PacketFacade::extract_string_seq(&buf, 25, length);
+        let end = PacketFacade::find_string_seq_end(&buf, 25, length);
+
+        assert_eq!(extracted, Some(("www.example.com".to_string(), 31)));
+        assert_eq!(end, Some(31));
+    }
+
+    #[test]
+    fn pointers_that_do_not_point_backward_are_rejected() {
+        let check = |bytes: &[u8]| {
+            let mut buf: [u8; 100] = [0; 100];
+            let length = {
+                let mut adder = ByteHandle::new(&mut buf, 12);
+                adder.add_bytes(bytes);
+                adder.get_offset()
+            };
+            PacketFacade::extract_string_seq(&buf, 12, length)
+        };
+
+        assert_eq!(check(b"\xC0\x0C"), None);
+        assert_eq!(check(b"\xC0\x20\x00"), None);
+        assert_eq!(check(b"\x01a\xC0\x0C"), None);
+        assert_eq!(check(b"\x01a\xC0"), None);
+    }
+
+    #[test]
+    fn reserved_label_types_are_rejected() {
+        let mut buf: [u8; 100] = [0; 100];
+        let length = {
+            let mut adder = ByteHandle::new(&mut buf, 12);
+            adder.add_bytes(b"\x41a\x00");
+            adder.get_offset()
+        };
+
+        assert_eq!(PacketFacade::extract_string_seq(&buf, 12, length), None);
+        assert_eq!(PacketFacade::find_string_seq_end(&buf, 12, length), None);
+    }
+
+    #[test]
+    fn names_that_run_off_the_end_of_the_packet_are_rejected() {
+        let mut buf: [u8; 14] = [0; 14];
+        buf[12] = 0x01;
+        buf[13] = b'a';
+
+        assert_eq!(PacketFacade::extract_string_seq(&buf, 12, 14), None);
+        assert_eq!(PacketFacade::find_string_seq_end(&buf, 12, 14), None);
+    }
+
+    #[test]
+    fn names_that_cannot_be_encoded_are_not_added() {
+        let mut buf: [u8; 500] = [0; 500];
+        let long_label = "a".repeat(64);
+        let long_name = vec!["abcdefghi"; 26].join(".");
+
+        assert_eq!(
+            PacketFacade::add_string_seq(&mut buf, 12, &long_label),
+            None
+        );
+        assert_eq!(PacketFacade::add_string_seq(&mut buf, 12, &long_name), None);
+        assert_eq!(PacketFacade::add_string_seq(&mut buf, 12, "a..b"), None);
+        assert_eq!(PacketFacade::add_string_seq(&mut buf, 12, "a.b."), Some(17));
+        assert_eq!(&buf[12..17], b"\x01a\x01b\x00");
+    }
+
+    #[test]
+    fn can_add_and_find_edns() {
+        let mut buf: [u8; 100] = [0; 100];
+        let mut subject = PacketFacade::new(&mut buf, 12);
+        subject.add_query("example.com", 1, 1);
+        let edns = Edns {
+            udp_payload_size: 4096,
+            extended_rcode: 1,
+            version: 0,
+            dnssec_ok: true,
+        };
+
+        let result = subject.add_edns(&edns);
+
+        assert_eq!(result, true);
+        assert_eq!(subject.get_edns(), Some(vec![edns]));
+        let additionals = subject.get_additionals().unwrap();
+        assert_eq!(additionals[0].get_name(), "");
+        assert_eq!(additionals[0].get_resource_type(), OPT_RECORD_TYPE);
+        assert_eq!(additionals[0].get_resource_class(), 4096);
+        assert_eq!(additionals[0].get_time_to_live(), 0x01008000);
+        assert_eq!(additionals[0].get_rdata(), u8vec(&vec![]));
+        assert_eq!(subject.get_length(), 40);
+    }
+
+    #[test]
+    fn opt_records_with_names_are_malformed() {
+        let mut buf: [u8; 100] = [0; 100];
+        let mut subject = PacketFacade::new(&mut buf, 12);
+        subject.add_additional("example.com", OPT_RECORD_TYPE, 512, 0, &[]);
+
+        assert_eq!(subject.get_edns(), None);
+    }
+
+    #[test]
+    fn clearing_resource_records_keeps_the_queries() {
+        let mut buf: [u8; 100] = [0; 100];
+        let mut subject = PacketFacade::new(&mut buf, 12);
+        subject.add_query("a.b", 1, 1);
+        subject.add_answer("a.b", 1, 1, 3600, &[1, 2, 3, 4]);
+        subject.add_authority("b", 2, 1, 3600, &[0]);
+        subject.add_additional("", OPT_RECORD_TYPE, 512, 0, &[]);
+
+        let result = subject.clear_resource_records();
+
+        assert_eq!(result, true);
+        assert_eq!(subject.get_length(), 21);
+        assert_eq!(subject.get_queries().unwrap()[0].get_query_name(), "a.b");
+        assert_eq!(subject.get_answers().unwrap().len(), 0);
+        assert_eq!(subject.get_authorities().unwrap().len(), 0);
+        assert_eq!(subject.get_additionals().unwrap().len(), 0);
+    }
+
+    #[test]
+    fn parsing_survives_random_and_mangled_packets() {
+        let mut rng = SmallRng::seed_from_u64(0x0053_0053);
+        let mut template: [u8; 512] = [0; 512];
+        let template_length = {
+            let mut facade = PacketFacade::new(&mut template, 12);
+            facade.set_transaction_id(0x1234);
+            facade.add_query("www.example.com", 1, 1);
+            facade.add_answer("www.example.com", 5, 1, 300, b"\x03cdn\xC0\x10");
+            facade.add_answer("cdn.example.com", 1, 1, 300, &[1, 2, 3, 4]);
+            facade.add_authority("example.com", 2, 1, 300, b"\x02ns\xC0\x10");
+            facade.add_edns(&Edns {
+                udp_payload_size: 4096,
+                extended_rcode: 0,
+                version: 0,
+                dnssec_ok: false,
+            });
+            facade.get_length()
+        };
+        for round in 0..20_000 {
+            let mut buf: [u8; 512] = [0; 512];
+            let length = if round % 2 == 0 {
+                let length = rng.gen_range(0, buf.len());
+                rng.fill(&mut buf[0..length]);
+                length
+            } else {
+                buf.copy_from_slice(&template);
+                for _ in 0..rng.gen_range(1, 8) {
+                    buf[rng.gen_range(0, template_length)] = rng.gen();
+                }
+                rng.gen_range(0, template_length + 1)
+            };
+
+            let subject = PacketFacade::new(&mut buf, length);
+
+            subject.get_transaction_id();
+            subject.is_query();
+            subject.get_opcode();
+            subject.is_truncated();
+            subject.get_rcode();
+            subject.get_queries();
+            subject.get_answers();
+            subject.get_authorities();
+            subject.get_additionals();
+            subject.get_edns();
+            assert!(subject.get_length() <= length);
+        }
+    }
+
     fn u8vec(buf: &[u8]) -> &[u8] {
         buf
     }
@@ -1,12 +1,17 @@
 // Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use super::packet_facade::Edns;
 use super::packet_facade::PacketFacade;
 use super::packet_facade::Query;
 use super::packet_facade::ResourceRecord;
 use crate::sub_lib::logger::Logger;
+use crate::sub_lib::privacy;
+use crate::sub_lib::proxy_client::DnsRecordData;
+use crate::sub_lib::proxy_server::TunneledDnsAnswer;
+use std::cmp::{max, min};
 use std::convert::From;
 use std::convert::TryFrom;
 use std::net::SocketAddr;
-use std::net::{Ipv4Addr, Ipv6Addr};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
 use std::time::Instant;
 use trust_dns::op::OpCode;
 use trust_dns::op::ResponseCode;
@@ -15,6 +20,11 @@ use trust_dns::rr::RecordType;
 
 const HEADER_BYTES: usize = 12;
 const UNKNOWN: &str = "<unknown>";
+const MIN_UDP_PAYLOAD_SIZE: usize = 512;
+// What we tell EDNS0 clients we can take, and the most we'll send them over UDP: enough for nearly
+// every answer without risking IP fragmentation.
+const EDNS_UDP_PAYLOAD_SIZE: u16 = 1232;
+const EDNS_VERSION: u8 = 0;
 
 pub fn process(buf: &mut [u8], length: usize, addr: &SocketAddr, logger: &Logger) -> usize {
     let mut facade = PacketFacade::new(buf, length);
@@ -36,18 +46,26 @@ pub fn process(buf: &mut [u8], length: usize, addr: &SocketAddr, logger: &Logger
     response_size
 }
 
-fn make_response(mut facade: &mut PacketFacade) -> usize {
+fn make_response(facade: &mut PacketFacade) -> usize {
+    let edns_opt = match request_edns(facade) {
+        Some(edns_opt) => edns_opt,
+        None => return make_format_error(facade),
+    };
     match facade.get_opcode() {
         None => return make_format_error(facade),
         Some(opcode) if opcode == u8::from(OpCode::Query) => (),
-        Some(_) => return make_not_implemented_error(facade),
+        Some(_) => return make_edns_error(facade, ResponseCode::NotImp.into(), edns_opt),
+    }
+    if edns_opt.map_or(false, |edns| edns.version > EDNS_VERSION) {
+        return make_edns_error(facade, ResponseCode::BADVERS.into(), edns_opt);
     }
     if !(facade.set_query(false)
         && facade.set_authoritative_answer(false)
         && facade.set_truncated(false)
         && facade.set_recursion_available(true)
         && facade.set_authenticated_data(false)
-        && facade.set_checking_disabled(false))
+        && facade.set_checking_disabled(false)
+        && facade.set_rcode(ResponseCode::NoError.low()))
     {
         return make_format_error(facade);
     }
@@ -55,33 +73,250 @@ fn make_response(mut facade: &mut PacketFacade) -> usize {
         None => return make_format_error(facade),
         Some(q) => q,
     };
+    if !facade.clear_resource_records() {
+        return make_format_error(facade);
+    }
     for query in queries {
-        if query.get_query_class() != u16::from(DNSClass::IN) {
-            return make_not_implemented_error(facade);
-        }
-
-        let resource_type = query.get_query_type();
-        match RecordType::from(resource_type) {
-            RecordType::A => facade.add_answer(
-                &query.get_query_name(),
-                resource_type,
-                DNSClass::IN.into(),
-                3600,
-                &Ipv4Addr::LOCALHOST.octets(),
-            ),
-            RecordType::AAAA => facade.add_answer(
+        let answers = match local_answers(&query) {
+            Ok(answers) => answers,
+            Err(response_code) => return make_edns_error(facade, response_code.into(), edns_opt),
+        };
+        for (record_type, rdata) in answers {
+            if !facade.add_answer(
                 &query.get_query_name(),
-                resource_type,
+                record_type.into(),
                 DNSClass::IN.into(),
                 3600,
-                &Ipv6Addr::LOCALHOST.octets(),
-            ),
-            _ => return make_not_implemented_error(&mut facade),
-        };
+                &rdata,
+            ) {
+                return finish_response(facade, edns_opt, true);
+            }
+        }
+    }
+    finish_response(facade, edns_opt, false)
+}
+
+// Every name resolves to localhost, so there are addresses for anyone who asks in the Internet
+// class, and an empty answer (NODATA) for every other type and class.
+fn local_answers(query: &Query) -> Result<Vec<(RecordType, Vec<u8>)>, ResponseCode> {
+    let ipv4 = (RecordType::A, Ipv4Addr::LOCALHOST.octets().to_vec());
+    let ipv6 = (RecordType::AAAA, Ipv6Addr::LOCALHOST.octets().to_vec());
+    let internet_class = match DNSClass::from_u16(query.get_query_class()) {
+        Ok(DNSClass::IN) | Ok(DNSClass::ANY) => true,
+        _ => false,
+    };
+    // RecordType::from() doesn't know IXFR, so zone transfers are spotted by number
+    let zone_transfer = [RecordType::AXFR, RecordType::IXFR]
+        .iter()
+        .any(|record_type| u16::from(*record_type) == query.get_query_type());
+    if zone_transfer {
+        return Err(ResponseCode::NotImp);
+    }
+    match RecordType::from(query.get_query_type()) {
+        RecordType::OPT => Err(ResponseCode::FormErr),
+        _ if !internet_class => Ok(vec![]),
+        RecordType::A => Ok(vec![ipv4]),
+        RecordType::AAAA => Ok(vec![ipv6]),
+        RecordType::ANY => Ok(vec![ipv4, ipv6]),
+        _ => Ok(vec![]),
+    }
+}
+
+// None if the request's additional section can't be read or holds more than one OPT record.
+fn request_edns(facade: &PacketFacade) -> Option<Option<Edns>> {
+    match facade.get_edns()?.as_slice() {
+        [] => Some(None),
+        [edns] => Some(Some(*edns)),
+        _ => None,
+    }
+}
+
+fn response_edns(extended_rcode: u8) -> Edns {
+    Edns {
+        udp_payload_size: EDNS_UDP_PAYLOAD_SIZE,
+        extended_rcode,
+        version: EDNS_VERSION,
+        dnssec_ok: false,
+    }
+}
+
+// A client that sent an OPT record gets one back. If that leaves no room for the answers, they
+// have to go instead, and the client will ask again over TCP.
+fn finish_response(facade: &mut PacketFacade, edns_opt: Option<Edns>, truncated: bool) -> usize {
+    if truncated {
+        facade.set_truncated(true);
+    }
+    if edns_opt.is_some() && !facade.add_edns(&response_edns(0)) {
+        facade.set_truncated(true);
+        facade.clear_resource_records();
+        facade.add_edns(&response_edns(0));
     }
     facade.get_length()
 }
 
+// The most a UDP response to the request in buf may hold: 512 bytes, unless the client has said
+// through EDNS0 that it can take more.
+pub fn udp_response_limit(buf: &mut [u8], length: usize) -> usize {
+    let buflen = buf.len();
+    let facade = PacketFacade::new(buf, length);
+    let payload_size = match request_edns(&facade) {
+        Some(Some(edns)) => min(edns.udp_payload_size, EDNS_UDP_PAYLOAD_SIZE) as usize,
+        _ => MIN_UDP_PAYLOAD_SIZE,
+    };
+    min(buflen, max(max(payload_size, MIN_UDP_PAYLOAD_SIZE), length))
+}
+
+// A request an exit Node can answer when entry DNS runs in tunnel or resolve mode: a single
+// Internet-class query for a type of record that localhost can't stand in for. In resolve mode,
+// addresses are looked up too, for clients that don't send everything through the proxy ports.
+pub fn tunnelable_query(
+    buf: &mut [u8],
+    length: usize,
+    resolve_addresses: bool,
+) -> Option<(String, u16)> {
+    let facade = PacketFacade::new(buf, length);
+    if facade.get_opcode()? != u8::from(OpCode::Query) {
+        return None;
+    }
+    let queries = facade.get_queries()?;
+    if queries.len() != 1 || queries[0].get_query_class() != u16::from(DNSClass::IN) {
+        return None;
+    }
+    match RecordType::from(queries[0].get_query_type()) {
+        RecordType::CNAME | RecordType::MX | RecordType::TXT => Some((
+            queries[0].get_query_name().to_string(),
+            queries[0].get_query_type(),
+        )),
+        RecordType::A | RecordType::AAAA if resolve_addresses => Some((
+            queries[0].get_query_name().to_string(),
+            queries[0].get_query_type(),
+        )),
+        _ => None,
+    }
+}
+
+// The name a single query asks about, whatever the type of record; used to pick out the queries
+// for destinations on the bypass list.
+pub fn query_name(buf: &mut [u8], length: usize) -> Option<String> {
+    let facade = PacketFacade::new(buf, length);
+    if facade.get_opcode()? != u8::from(OpCode::Query) {
+        return None;
+    }
+    match facade.get_queries()?.as_slice() {
+        [query] => Some(query.get_query_name().to_string()),
+        _ => None,
+    }
+}
+
+// The address a reverse lookup (PTR) name like 4.3.2.1.in-addr.arpa asks about, if it names a
+// whole one.
+pub fn reverse_lookup_address(name: &str) -> Option<IpAddr> {
+    let name = name.trim_end_matches('.').to_lowercase();
+    if name.ends_with(".in-addr.arpa") {
+        let labels: Vec<&str> = name[..name.len() - ".in-addr.arpa".len()]
+            .split('.')
+            .collect();
+        if labels.len() != 4 {
+            return None;
+        }
+        let mut octets = [0u8; 4];
+        for (octet, label) in octets.iter_mut().zip(labels.iter().rev()) {
+            *octet = label.parse().ok()?;
+        }
+        Some(IpAddr::from(octets))
+    } else if name.ends_with(".ip6.arpa") {
+        let nibbles: Vec<&str> = name[..name.len() - ".ip6.arpa".len()].split('.').collect();
+        if nibbles.len() != 32 {
+            return None;
+        }
+        let mut octets = [0u8; 16];
+        for (index, nibble) in nibbles.iter().rev().enumerate() {
+            if nibble.len() != 1 {
+                return None;
+            }
+            let value = u8::from_str_radix(nibble, 16).ok()?;
+            octets[index / 2] |= if index % 2 == 0 { value << 4 } else { value };
+        }
+        Some(IpAddr::from(octets))
+    } else {
+        None
+    }
+}
+
+// Turns the request in buf into the response an exit Node's answer calls for.
+pub fn make_tunneled_response(buf: &mut [u8], length: usize, answer: &TunneledDnsAnswer) -> usize {
+    let mut facade = PacketFacade::new(buf, length);
+    let edns_opt = match request_edns(&facade) {
+        Some(edns_opt) => edns_opt,
+        None => return make_format_error(&mut facade),
+    };
+    let response_code = (answer.response_code & 0x0F) as u8;
+    if response_code != ResponseCode::NoError.low() {
+        return make_edns_error(&mut facade, response_code.into(), edns_opt);
+    }
+    if !(facade.set_query(false)
+        && facade.set_authoritative_answer(false)
+        && facade.set_truncated(false)
+        && facade.set_recursion_available(true)
+        && facade.set_authenticated_data(false)
+        && facade.set_checking_disabled(false)
+        && facade.set_rcode(response_code)
+        && facade.clear_resource_records())
+    {
+        return make_format_error(&mut facade);
+    }
+    for record in &answer.records {
+        let (record_type, rdata) = record_type_and_rdata(&record.data);
+        if !facade.add_answer(
+            &record.name,
+            record_type.into(),
+            DNSClass::IN.into(),
+            record.ttl,
+            &rdata,
+        ) {
+            return finish_response(&mut facade, edns_opt, true);
+        }
+    }
+    finish_response(&mut facade, edns_opt, false)
+}
+
+fn record_type_and_rdata(data: &DnsRecordData) -> (RecordType, Vec<u8>) {
+    match data {
+        DnsRecordData::A(ip_addr) => (RecordType::A, ip_addr.octets().to_vec()),
+        DnsRecordData::Aaaa(ip_addr) => (RecordType::AAAA, ip_addr.octets().to_vec()),
+        DnsRecordData::Cname(name) => (RecordType::CNAME, name_rdata(name)),
+        DnsRecordData::Mx(preference, exchange) => {
+            let mut rdata = preference.to_be_bytes().to_vec();
+            rdata.extend(name_rdata(exchange));
+            (RecordType::MX, rdata)
+        }
+        DnsRecordData::Txt(strings) => {
+            let mut rdata = vec![];
+            for string in strings {
+                if string.is_empty() {
+                    rdata.push(0);
+                }
+                for chunk in string.chunks(255) {
+                    rdata.push(chunk.len() as u8);
+                    rdata.extend_from_slice(chunk);
+                }
+            }
+            (RecordType::TXT, rdata)
+        }
+    }
+}
+
+// Uncompressed, since there's nothing in the response to point at that we can vouch for.
+fn name_rdata(name: &str) -> Vec<u8> {
+    let mut rdata = vec![];
+    for label in name.split('.').filter(|label| !label.is_empty()) {
+        rdata.push(label.len() as u8);
+        rdata.extend_from_slice(label.as_bytes());
+    }
+    rdata.push(0);
+    rdata
+}
+
 fn display(opcode: u8) -> &'static str {
     match OpCode::from_u8(opcode) {
         Ok(OpCode::Notify) => "Notify",
@@ -96,8 +331,18 @@ fn make_format_error(facade: &mut PacketFacade<'_>) -> usize {
     make_error(facade, ResponseCode::FormErr.low())
 }
 
-fn make_not_implemented_error(facade: &mut PacketFacade<'_>) -> usize {
-    make_error(facade, ResponseCode::NotImp.low())
+// The high bits of an extended response code travel in the OPT record, so clients that didn't
+// send one can't be told them.
+fn make_edns_error(
+    facade: &mut PacketFacade<'_>,
+    response_code: u16,
+    edns_opt: Option<Edns>,
+) -> usize {
+    make_error(facade, (response_code & 0x000F) as u8);
+    if edns_opt.is_some() {
+        facade.add_edns(&response_edns((response_code >> 4) as u8));
+    }
+    facade.get_length()
 }
 
 fn make_error(facade: &mut PacketFacade<'_>, response_code: u8) -> usize {
@@ -156,11 +401,11 @@ fn write_log(from: &RequestRecord, to: &ResponseRecord, addr: &SocketAddr, logge
             logger,
             "{}ns: {} {} ({}) -> {} ({})",
             to.latency_ns,
-            addr,
+            privacy::socket_addr(*addr),
             display(from.opcode),
-            &query_list,
+            privacy::data(&query_list),
             ResponseCode::from(0, to.rcode),
-            &answer_list
+            privacy::data(&answer_list)
         );
     }
 }
@@ -181,11 +426,15 @@ struct ResponseRecord {
 mod tests {
     use super::*;
     use crate::sub_lib::logger::Logger;
+    use crate::sub_lib::proxy_client::DnsRecord;
     use crate::test_utils::logging::init_test_logging;
     use crate::test_utils::logging::TestLogHandler;
+    use rand::rngs::SmallRng;
+    use rand::{Rng, SeedableRng};
     use std::net::Ipv4Addr;
     use std::net::SocketAddr;
     use std::net::SocketAddrV4;
+    use std::str::FromStr;
     use std::time::Instant;
 
     #[test]
@@ -235,18 +484,52 @@ mod tests {
     }
 
     #[test]
-    fn returns_not_implemented_error_if_query_type_is_other_than_a_or_aaaa() {
-        let mut buf: [u8; 500] = [0; 500];
-        let req_length = {
-            let mut facade = PacketFacade::new(&mut buf, 500);
-            facade.set_transaction_id(0x1234);
-            facade.set_query(true);
-            facade.set_recursion_available(false);
-            facade.set_recursion_desired(false);
-            facade.set_opcode(OpCode::Query.into());
-            facade.add_query("name", RecordType::NS.into(), DNSClass::IN.into());
-            facade.get_length()
+    fn other_query_types_and_classes_get_empty_answers() {
+        let no_data = |record_type: RecordType, class: DNSClass| {
+            let mut buf: [u8; 500] = [0; 500];
+            let req_length = make_request(&mut buf, record_type, class, None);
+            let addr = SocketAddr::from(SocketAddrV4::new(Ipv4Addr::new(1, 2, 3, 4), 53));
+
+            let rsp_length = process(&mut buf, req_length, &addr, &Logger::new(""));
+
+            let response = PacketFacade::new(&mut buf, rsp_length);
+            assert_eq!(response.get_transaction_id(), Some(0x1234));
+            assert_eq!(response.is_query(), Some(false));
+            assert_eq!(response.get_rcode(), Some(ResponseCode::NoError.low()));
+            assert_eq!(response.get_queries().unwrap().len(), 1);
+            assert_eq!(response.get_answers().unwrap().len(), 0);
+            assert_eq!(response.get_additionals().unwrap().len(), 0);
         };
+
+        no_data(RecordType::NS, DNSClass::IN);
+        no_data(RecordType::MX, DNSClass::IN);
+        no_data(RecordType::TXT, DNSClass::IN);
+        no_data(RecordType::SOA, DNSClass::IN);
+        no_data(RecordType::Unknown(64), DNSClass::IN); // SVCB
+        no_data(RecordType::Unknown(65), DNSClass::IN); // HTTPS
+        no_data(RecordType::A, DNSClass::CH);
+        no_data(RecordType::TXT, DNSClass::CH);
+    }
+
+    #[test]
+    fn any_queries_get_both_addresses() {
+        let mut buf: [u8; 500] = [0; 500];
+        let req_length = make_request(&mut buf, RecordType::ANY, DNSClass::IN, None);
+        let addr = SocketAddr::from(SocketAddrV4::new(Ipv4Addr::new(1, 2, 3, 4), 53));
+
+        let rsp_length = process(&mut buf, req_length, &addr, &Logger::new(""));
+
+        let response = PacketFacade::new(&mut buf, rsp_length);
+        let answers = response.get_answers().unwrap();
+        assert_eq!(answers[0].get_resource_type(), u16::from(RecordType::A));
+        assert_eq!(answers[1].get_resource_type(), u16::from(RecordType::AAAA));
+        assert_eq!(answers.len(), 2);
+    }
+
+    #[test]
+    fn returns_not_implemented_error_for_zone_transfers() {
+        let mut buf: [u8; 500] = [0; 500];
+        let req_length = make_request(&mut buf, RecordType::AXFR, DNSClass::IN, None);
         let addr = SocketAddr::from(SocketAddrV4::new(Ipv4Addr::new(101, 102, 103, 104), 53));
 
         let rsp_length = process(&mut buf, req_length, &addr, &Logger::new(""));
@@ -256,26 +539,206 @@ mod tests {
     }
 
     #[test]
-    fn returns_not_implemented_error_if_query_class_is_other_than_in() {
+    fn edns_requests_get_our_opt_record_in_place_of_theirs() {
+        let mut buf: [u8; 500] = [0; 500];
+        let req_length = make_request(
+            &mut buf,
+            RecordType::A,
+            DNSClass::IN,
+            Some(Edns {
+                udp_payload_size: 4096,
+                extended_rcode: 0,
+                version: 0,
+                dnssec_ok: true,
+            }),
+        );
+        let addr = SocketAddr::from(SocketAddrV4::new(Ipv4Addr::new(1, 2, 3, 4), 53));
+
+        let rsp_length = process(&mut buf, req_length, &addr, &Logger::new(""));
+
+        let response = PacketFacade::new(&mut buf, rsp_length);
+        assert_eq!(response.get_rcode(), Some(ResponseCode::NoError.low()));
+        let answers = response.get_answers().unwrap();
+        assert_eq!(answers[0].get_name(), "example.com");
+        assert_eq!(answers[0].get_rdata(), &[127, 0, 0, 1][..]);
+        assert_eq!(answers.len(), 1);
+        assert_eq!(
+            response.get_edns(),
+            Some(vec![Edns {
+                udp_payload_size: 1232,
+                extended_rcode: 0,
+                version: 0,
+                dnssec_ok: false,
+            }])
+        );
+        assert_eq!(response.get_additionals().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn edns_requests_of_unknown_versions_get_bad_version_errors() {
+        let mut buf: [u8; 500] = [0; 500];
+        let req_length = make_request(
+            &mut buf,
+            RecordType::A,
+            DNSClass::IN,
+            Some(Edns {
+                udp_payload_size: 4096,
+                extended_rcode: 0,
+                version: 1,
+                dnssec_ok: false,
+            }),
+        );
+        let addr = SocketAddr::from(SocketAddrV4::new(Ipv4Addr::new(1, 2, 3, 4), 53));
+
+        let rsp_length = process(&mut buf, req_length, &addr, &Logger::new(""));
+
+        let response = PacketFacade::new(&mut buf, rsp_length);
+        let edns = response.get_edns().unwrap();
+        assert_eq!(
+            (u16::from(edns[0].extended_rcode) << 4) | u16::from(response.get_rcode().unwrap()),
+            u16::from(ResponseCode::BADVERS)
+        );
+        assert_eq!(edns[0].version, 0);
+        assert_eq!(response.get_answers().unwrap().len(), 0);
+    }
+
+    #[test]
+    fn requests_with_two_opt_records_get_format_errors() {
         let mut buf: [u8; 500] = [0; 500];
+        let edns = Edns {
+            udp_payload_size: 4096,
+            extended_rcode: 0,
+            version: 0,
+            dnssec_ok: false,
+        };
         let req_length = {
-            let mut facade = PacketFacade::new(&mut buf, 500);
-            facade.set_transaction_id(0x1234);
-            facade.set_query(true);
-            facade.set_recursion_available(false);
-            facade.set_recursion_desired(false);
-            facade.set_opcode(OpCode::Query.into());
-            facade.add_query("name", RecordType::A.into(), DNSClass::CH.into());
+            let length = make_request(&mut buf, RecordType::A, DNSClass::IN, Some(edns));
+            let mut facade = PacketFacade::new(&mut buf, length);
+            facade.add_edns(&edns);
             facade.get_length()
         };
-        let addr = SocketAddr::from(SocketAddrV4::new(Ipv4Addr::new(101, 102, 103, 104), 53));
+        let addr = SocketAddr::from(SocketAddrV4::new(Ipv4Addr::new(1, 2, 3, 4), 53));
 
         let rsp_length = process(&mut buf, req_length, &addr, &Logger::new(""));
 
-        check_not_implemented_error_message(&mut buf, 0x1234, OpCode::Query.into());
+        check_format_error_message(&mut buf, 0x1234);
         assert_eq!(rsp_length, HEADER_BYTES);
     }
 
+    #[test]
+    fn udp_response_limit_depends_on_edns() {
+        let limit = |edns_opt: Option<Edns>, buflen: usize| {
+            let mut buf = vec![0u8; buflen];
+            let req_length = make_request(&mut buf, RecordType::A, DNSClass::IN, edns_opt);
+            udp_response_limit(&mut buf, req_length)
+        };
+        let edns = |udp_payload_size: u16| {
+            Some(Edns {
+                udp_payload_size,
+                extended_rcode: 0,
+                version: 0,
+                dnssec_ok: false,
+            })
+        };
+
+        assert_eq!(limit(None, 65536), 512);
+        assert_eq!(limit(edns(256), 65536), 512);
+        assert_eq!(limit(edns(1000), 65536), 1000);
+        assert_eq!(limit(edns(4096), 65536), 1232);
+        assert_eq!(limit(edns(4096), 600), 600);
+    }
+
+    #[test]
+    fn tunneled_answers_too_big_for_the_response_are_truncated() {
+        let mut buf: [u8; 512] = [0; 512];
+        let req_length = make_request(&mut buf, RecordType::TXT, DNSClass::IN, None);
+        let answer = TunneledDnsAnswer::new(
+            7,
+            0,
+            (0..10)
+                .map(|_| DnsRecord {
+                    name: "example.com".to_string(),
+                    ttl: 60,
+                    data: DnsRecordData::Txt(vec![vec![b'x'; 100]]),
+                })
+                .collect(),
+        );
+
+        let rsp_length = make_tunneled_response(&mut buf, req_length, &answer);
+
+        let response = PacketFacade::new(&mut buf, rsp_length);
+        assert_eq!(response.is_truncated(), Some(true));
+        assert_eq!(response.get_rcode(), Some(ResponseCode::NoError.low()));
+        assert_eq!(response.get_answers().unwrap().len(), 3);
+        assert!(rsp_length <= 512);
+    }
+
+    #[test]
+    fn truncated_edns_responses_keep_the_opt_record() {
+        let mut buf: [u8; 512] = [0; 512];
+        let req_length = make_request(
+            &mut buf,
+            RecordType::TXT,
+            DNSClass::IN,
+            Some(Edns {
+                udp_payload_size: 512,
+                extended_rcode: 0,
+                version: 0,
+                dnssec_ok: false,
+            }),
+        );
+        let answer = TunneledDnsAnswer::new(
+            7,
+            0,
+            vec![DnsRecord {
+                name: "example.com".to_string(),
+                ttl: 60,
+                data: DnsRecordData::Txt(vec![vec![b'x'; 230]; 2]),
+            }],
+        );
+
+        let rsp_length = make_tunneled_response(&mut buf, req_length, &answer);
+
+        let response = PacketFacade::new(&mut buf, rsp_length);
+        assert_eq!(response.is_truncated(), Some(true));
+        assert_eq!(response.get_answers().unwrap().len(), 0);
+        assert_eq!(response.get_edns().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn processing_survives_mangled_requests() {
+        let mut rng = SmallRng::seed_from_u64(0x0035_0035);
+        let mut template: [u8; 512] = [0; 512];
+        let template_length = make_request(
+            &mut template,
+            RecordType::A,
+            DNSClass::IN,
+            Some(Edns {
+                udp_payload_size: 4096,
+                extended_rcode: 0,
+                version: 0,
+                dnssec_ok: false,
+            }),
+        );
+        let addr = SocketAddr::from(SocketAddrV4::new(Ipv4Addr::new(1, 2, 3, 4), 53));
+        let logger = Logger::new("processing_survives_mangled_requests");
+        for _ in 0..20_000 {
+            let mut buf: [u8; 512] = [0; 512];
+            buf.copy_from_slice(&template);
+            for _ in 0..rng.gen_range(1, 6) {
+                buf[rng.gen_range(0, template_length)] = rng.gen();
+            }
+            let length = rng.gen_range(0, template_length + 1);
+            tunnelable_query(&mut buf, length, true);
+            query_name(&mut buf, length);
+            let limit = udp_response_limit(&mut buf, length);
+
+            let rsp_length = process(&mut buf[0..limit], length, &addr, &logger);
+
+            assert!(rsp_length <= limit);
+        }
+    }
+
     #[test]
     fn two_queries_are_answered() {
         init_test_logging();
@@ -355,6 +818,198 @@ mod tests {
         );
     }
 
+    #[test]
+    fn only_single_queries_for_other_record_types_are_tunnelable() {
+        let tunnelable_in = |resolve_addresses: bool, queries: Vec<(RecordType, DNSClass)>| {
+            let mut buf: [u8; 500] = [0; 500];
+            let req_length = {
+                let mut facade = PacketFacade::new(&mut buf, HEADER_BYTES);
+                facade.set_transaction_id(0x1234);
+                facade.set_query(true);
+                facade.set_opcode(OpCode::Query.into());
+                queries.into_iter().for_each(|(record_type, class)| {
+                    facade.add_query("Example.com", record_type.into(), class.into());
+                });
+                facade.get_length()
+            };
+            tunnelable_query(&mut buf, req_length, resolve_addresses)
+        };
+        let tunnelable = |queries| tunnelable_in(false, queries);
+
+        assert_eq!(
+            tunnelable(vec![(RecordType::MX, DNSClass::IN)]),
+            Some(("Example.com".to_string(), RecordType::MX.into()))
+        );
+        assert_eq!(
+            tunnelable(vec![(RecordType::TXT, DNSClass::IN)]),
+            Some(("Example.com".to_string(), RecordType::TXT.into()))
+        );
+        assert_eq!(
+            tunnelable(vec![(RecordType::CNAME, DNSClass::IN)]),
+            Some(("Example.com".to_string(), RecordType::CNAME.into()))
+        );
+        assert_eq!(tunnelable(vec![(RecordType::A, DNSClass::IN)]), None);
+        assert_eq!(tunnelable(vec![(RecordType::AAAA, DNSClass::IN)]), None);
+        assert_eq!(tunnelable(vec![(RecordType::MX, DNSClass::CH)]), None);
+        assert_eq!(
+            tunnelable_in(true, vec![(RecordType::A, DNSClass::IN)]),
+            Some(("Example.com".to_string(), RecordType::A.into()))
+        );
+        assert_eq!(
+            tunnelable_in(true, vec![(RecordType::AAAA, DNSClass::IN)]),
+            Some(("Example.com".to_string(), RecordType::AAAA.into()))
+        );
+        assert_eq!(
+            tunnelable_in(true, vec![(RecordType::A, DNSClass::CH)]),
+            None
+        );
+        assert_eq!(
+            tunnelable(vec![
+                (RecordType::MX, DNSClass::IN),
+                (RecordType::TXT, DNSClass::IN)
+            ]),
+            None
+        );
+    }
+
+    #[test]
+    fn query_name_finds_the_name_in_single_queries_of_any_type() {
+        let query_name_of = |names: Vec<&str>, opcode: OpCode| {
+            let mut buf: [u8; 500] = [0; 500];
+            let req_length = {
+                let mut facade = PacketFacade::new(&mut buf, HEADER_BYTES);
+                facade.set_transaction_id(0x1234);
+                facade.set_query(true);
+                facade.set_opcode(opcode.into());
+                names.into_iter().for_each(|name| {
+                    facade.add_query(name, RecordType::A.into(), DNSClass::IN.into());
+                });
+                facade.get_length()
+            };
+            query_name(&mut buf, req_length)
+        };
+
+        assert_eq!(
+            query_name_of(vec!["Intranet.example.com"], OpCode::Query),
+            Some("Intranet.example.com".to_string())
+        );
+        assert_eq!(
+            query_name_of(vec!["intranet.example.com", "example.com"], OpCode::Query),
+            None
+        );
+        assert_eq!(query_name_of(vec![], OpCode::Query), None);
+        assert_eq!(
+            query_name_of(vec!["intranet.example.com"], OpCode::Update),
+            None
+        );
+    }
+
+    #[test]
+    fn reverse_lookup_address_reads_addresses_out_of_ptr_names() {
+        assert_eq!(
+            reverse_lookup_address("4.3.168.192.in-addr.arpa."),
+            Some(IpAddr::from([192, 168, 3, 4]))
+        );
+        assert_eq!(
+            reverse_lookup_address(
+                "b.a.9.8.7.6.5.0.4.0.0.0.3.0.0.0.2.0.0.0.1.0.0.0.0.0.0.0.1.2.3.4.IP6.ARPA"
+            ),
+            Some(IpAddr::from(
+                Ipv6Addr::from_str("4321:0:1:2:3:4:567:89ab").unwrap()
+            ))
+        );
+        assert_eq!(reverse_lookup_address("168.192.in-addr.arpa"), None);
+        assert_eq!(reverse_lookup_address("4.3.168.256.in-addr.arpa"), None);
+        assert_eq!(reverse_lookup_address("1.2.ip6.arpa"), None);
+        assert_eq!(reverse_lookup_address("example.com"), None);
+    }
+
+    #[test]
+    fn tunneled_answers_are_written_into_the_response() {
+        let mut buf: [u8; 500] = [0; 500];
+        let req_length = {
+            let mut facade = PacketFacade::new(&mut buf, HEADER_BYTES);
+            facade.set_transaction_id(0x4321);
+            facade.set_query(true);
+            facade.set_recursion_desired(true);
+            facade.set_opcode(OpCode::Query.into());
+            facade.add_query("example.com", RecordType::MX.into(), DNSClass::IN.into());
+            facade.get_length()
+        };
+        let answer = TunneledDnsAnswer::new(
+            7,
+            0,
+            vec![
+                DnsRecord {
+                    name: "example.com".to_string(),
+                    ttl: 300,
+                    data: DnsRecordData::Mx(10, "mail.example.com.".to_string()),
+                },
+                DnsRecord {
+                    name: "example.com".to_string(),
+                    ttl: 60,
+                    data: DnsRecordData::Txt(vec![b"v=spf1 -all".to_vec(), vec![]]),
+                },
+                DnsRecord {
+                    name: "www.example.com".to_string(),
+                    ttl: 30,
+                    data: DnsRecordData::Cname("example.com".to_string()),
+                },
+            ],
+        );
+
+        let rsp_length = make_tunneled_response(&mut buf, req_length, &answer);
+
+        let response = PacketFacade::new(&mut buf, rsp_length);
+        assert_eq!(response.get_transaction_id(), Some(0x4321));
+        assert_eq!(response.is_query(), Some(false));
+        assert_eq!(response.is_recursion_desired(), Some(true));
+        assert_eq!(response.is_recursion_available(), Some(true));
+        assert_eq!(response.is_truncated(), Some(false));
+        assert_eq!(response.get_rcode(), Some(ResponseCode::NoError.low()));
+        assert_eq!(
+            response.get_queries().unwrap()[0].get_query_name(),
+            "example.com"
+        );
+        let answers = response.get_answers().unwrap();
+        assert_eq!(answers.len(), 3);
+        assert_eq!(answers[0].get_name(), "example.com");
+        assert_eq!(answers[0].get_resource_type(), u16::from(RecordType::MX));
+        assert_eq!(answers[0].get_resource_class(), u16::from(DNSClass::IN));
+        assert_eq!(answers[0].get_time_to_live(), 300);
+        assert_eq!(
+            answers[0].get_rdata(),
+            &b"\x00\x0a\x04mail\x07example\x03com\x00"[..]
+        );
+        assert_eq!(answers[1].get_resource_type(), u16::from(RecordType::TXT));
+        assert_eq!(answers[1].get_time_to_live(), 60);
+        assert_eq!(answers[1].get_rdata(), &b"\x0bv=spf1 -all\x00"[..]);
+        assert_eq!(answers[2].get_name(), "www.example.com");
+        assert_eq!(answers[2].get_resource_type(), u16::from(RecordType::CNAME));
+        assert_eq!(answers[2].get_rdata(), &b"\x07example\x03com\x00"[..]);
+    }
+
+    #[test]
+    fn tunneled_failures_are_reported_with_their_response_codes() {
+        let mut buf: [u8; 500] = [0; 500];
+        let req_length = {
+            let mut facade = PacketFacade::new(&mut buf, HEADER_BYTES);
+            facade.set_transaction_id(0x1234);
+            facade.set_query(true);
+            facade.set_opcode(OpCode::Query.into());
+            facade.add_query("nowhere.com", RecordType::TXT.into(), DNSClass::IN.into());
+            facade.get_length()
+        };
+        let answer = TunneledDnsAnswer::new(7, ResponseCode::NXDomain.low().into(), vec![]);
+
+        let rsp_length = make_tunneled_response(&mut buf, req_length, &answer);
+
+        let response = PacketFacade::new(&mut buf, rsp_length);
+        assert_eq!(response.get_transaction_id(), Some(0x1234));
+        assert_eq!(response.get_rcode(), Some(ResponseCode::NXDomain.low()));
+        assert_eq!(rsp_length, HEADER_BYTES);
+    }
+
     #[test]
     fn write_log_produces_correct_text() {
         init_test_logging();
@@ -538,6 +1193,23 @@ mod tests {
         );
     }
 
+    fn make_request(
+        buf: &mut [u8],
+        record_type: RecordType,
+        class: DNSClass,
+        edns_opt: Option<Edns>,
+    ) -> usize {
+        let mut facade = PacketFacade::new(buf, HEADER_BYTES);
+        facade.set_transaction_id(0x1234);
+        facade.set_query(true);
+        facade.set_opcode(OpCode::Query.into());
+        facade.add_query("example.com", record_type.into(), class.into());
+        if let Some(edns) = edns_opt {
+            facade.add_edns(&edns);
+        }
+        facade.get_length()
+    }
+
     fn check_format_error_message(mut buf: &mut [u8], transaction_id: u16) {
         let facade = PacketFacade::new(&mut buf, HEADER_BYTES);
         assert_eq!(facade.get_transaction_id(), Some(transaction_id));
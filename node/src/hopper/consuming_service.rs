@@ -7,6 +7,7 @@ use crate::sub_lib::dispatcher::{Endpoint, InboundClientData};
 use crate::sub_lib::hopper::{IncipientCoresPackage, NoLookupIncipientCoresPackage};
 use crate::sub_lib::logger::Logger;
 use crate::sub_lib::stream_handler_pool::TransmitDataMsg;
+use crate::sub_lib::stream_trace::{self, SpanKind};
 use actix::Recipient;
 use std::borrow::Borrow;
 use std::net::{IpAddr, Ipv4Addr, SocketAddr};
@@ -71,6 +72,7 @@ impl ConsumingService {
             "Instructed to send IncipientCoresPackage with {}-byte payload",
             incipient_cores_package.payload.len()
         );
+        let trace_id_opt = incipient_cores_package.trace_id_opt;
         match LiveCoresPackage::from_incipient(incipient_cores_package, self.cryptde.borrow()) {
             Ok((live_package, next_hop)) => {
                 let encrypted_package =
@@ -89,6 +91,9 @@ impl ConsumingService {
             }
             Err(e) => error!(self.logger, "{}", e),
         };
+        if let Some(trace_id) = trace_id_opt {
+            stream_trace::close_span(trace_id, SpanKind::HopperTransmit);
+        }
     }
 
     fn zero_hop(&self, encrypted_package: CryptData) {
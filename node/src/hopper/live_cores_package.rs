@@ -256,11 +256,11 @@ mod tests {
         let (subject, next_stop) = LiveCoresPackage::from_incipient(incipient, cryptde).unwrap();
 
         assert_eq!(
-            LiveHop {
-                public_key: key34.clone(),
-                payer: Some(paying_wallet.as_payer(&key12, &contract_address)),
-                component: Component::Hopper
-            },
+            LiveHop::new(
+                &key34,
+                Some(paying_wallet.as_payer(&key12, &contract_address)),
+                Component::Hopper
+            ),
             next_stop
         );
         route.shift(cryptde).unwrap();
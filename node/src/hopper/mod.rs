@@ -2,6 +2,7 @@
 
 mod consuming_service;
 pub mod live_cores_package;
+mod replay_cache;
 mod routing_service;
 
 use crate::hopper::routing_service::RoutingServiceSubs;
@@ -94,7 +95,7 @@ impl Handler<InboundClientData> for Hopper {
 
     fn handle(&mut self, msg: InboundClientData, _ctx: &mut Self::Context) -> Self::Result {
         self.routing_service
-            .as_ref()
+            .as_mut()
             .expect("Hopper unbound: no RoutingService")
             .route(msg);
     }
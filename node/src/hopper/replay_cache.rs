@@ -0,0 +1,187 @@
+// Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use crate::sub_lib::cryptde::CryptData;
+use crate::sub_lib::hop::LiveHop;
+use crate::sub_lib::ttl_hashmap::TtlHashMap;
+use std::collections::VecDeque;
+use std::time::Duration;
+
+pub const REPLAY_CACHE_TTL: Duration = Duration::from_secs(120);
+pub const REPLAY_CACHE_CAPACITY: usize = 100_000;
+
+// Routes are reused for every package in a stream, so a hop's stamp alone identifies a Route,
+// not a package. The digest of the encrypted payload is what tells one package on that Route
+// from another.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+struct ReplayKey {
+    nonce: u64,
+    timestamp: u64,
+    payload_digest: [u8; sha1::DIGEST_LENGTH],
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Admission {
+    Fresh,
+    Replayed,
+    Untracked,
+}
+
+// Hop timestamps aren't checked against our own clock, which may not agree with the originating
+// Node's; a package replayed after its first sighting has expired gets through as fresh.
+pub struct ReplayCache {
+    seen: TtlHashMap<ReplayKey, ()>,
+    arrivals: VecDeque<ReplayKey>,
+    capacity: usize,
+}
+
+impl ReplayCache {
+    pub fn new(ttl: Duration, capacity: usize) -> ReplayCache {
+        ReplayCache {
+            seen: TtlHashMap::new(ttl),
+            arrivals: VecDeque::new(),
+            capacity,
+        }
+    }
+
+    pub fn admit(&mut self, hop: &LiveHop, payload: &CryptData) -> Admission {
+        if !hop.is_stamped() {
+            return Admission::Untracked;
+        }
+        let key = ReplayKey {
+            nonce: hop.nonce,
+            timestamp: hop.timestamp,
+            payload_digest: Self::digest(payload),
+        };
+        if self.seen.get(&key).is_some() {
+            return Admission::Replayed;
+        }
+        // Refusing everything once the cache is full would hand an adversary an easy way to
+        // stop us routing, so the oldest sighting makes room instead. Sightings that have
+        // already expired are still in the arrival order, so that never outgrows the capacity.
+        if self.arrivals.len() >= self.capacity {
+            if let Some(oldest) = self.arrivals.pop_front() {
+                let _ = self.seen.remove(&oldest);
+            }
+        }
+        self.seen.insert(key.clone(), ());
+        self.arrivals.push_back(key);
+        Admission::Fresh
+    }
+
+    fn digest(payload: &CryptData) -> [u8; sha1::DIGEST_LENGTH] {
+        let mut hash = sha1::Sha1::new();
+        hash.update(payload.as_slice());
+        hash.digest().bytes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sub_lib::cryptde::PublicKey;
+    use crate::sub_lib::dispatcher::Component;
+    use std::thread;
+
+    fn make_hop() -> LiveHop {
+        LiveHop::new(&PublicKey::new(&[1, 2, 3, 4]), None, Component::Hopper)
+    }
+
+    #[test]
+    fn first_sighting_is_fresh_and_second_is_a_replay() {
+        let mut subject = ReplayCache::new(Duration::from_secs(10), 10);
+        let hop = make_hop();
+        let payload = CryptData::new(&[5, 6, 7, 8]);
+
+        let first = subject.admit(&hop, &payload);
+        let second = subject.admit(&hop, &payload);
+
+        assert_eq!(first, Admission::Fresh);
+        assert_eq!(second, Admission::Replayed);
+    }
+
+    #[test]
+    fn different_payloads_on_the_same_hop_are_not_replays() {
+        let mut subject = ReplayCache::new(Duration::from_secs(10), 10);
+        let hop = make_hop();
+
+        let first = subject.admit(&hop, &CryptData::new(&[5, 6, 7, 8]));
+        let second = subject.admit(&hop, &CryptData::new(&[8, 7, 6, 5]));
+
+        assert_eq!(first, Admission::Fresh);
+        assert_eq!(second, Admission::Fresh);
+    }
+
+    #[test]
+    fn the_same_payload_on_different_hops_is_not_a_replay() {
+        let mut subject = ReplayCache::new(Duration::from_secs(10), 10);
+        let payload = CryptData::new(&[5, 6, 7, 8]);
+
+        let first = subject.admit(&make_hop(), &payload);
+        let second = subject.admit(&make_hop(), &payload);
+
+        assert_eq!(first, Admission::Fresh);
+        assert_eq!(second, Admission::Fresh);
+    }
+
+    #[test]
+    fn unstamped_hops_are_not_tracked() {
+        let mut subject = ReplayCache::new(Duration::from_secs(10), 10);
+        let mut hop = make_hop();
+        hop.nonce = 0;
+        hop.timestamp = 0;
+        let payload = CryptData::new(&[5, 6, 7, 8]);
+
+        let first = subject.admit(&hop, &payload);
+        let second = subject.admit(&hop, &payload);
+
+        assert_eq!(first, Admission::Untracked);
+        assert_eq!(second, Admission::Untracked);
+    }
+
+    #[test]
+    fn full_cache_forgets_its_oldest_sighting_to_make_room() {
+        let mut subject = ReplayCache::new(Duration::from_secs(10), 2);
+        let payload = CryptData::new(&[5, 6, 7, 8]);
+        let oldest_hop = make_hop();
+        let older_hop = make_hop();
+        let hop = make_hop();
+        subject.admit(&oldest_hop, &payload);
+        subject.admit(&older_hop, &payload);
+
+        let first = subject.admit(&hop, &payload);
+        let second = subject.admit(&hop, &payload);
+        let older = subject.admit(&older_hop, &payload);
+        let oldest = subject.admit(&oldest_hop, &payload);
+
+        assert_eq!(first, Admission::Fresh);
+        assert_eq!(second, Admission::Replayed);
+        assert_eq!(older, Admission::Replayed);
+        assert_eq!(oldest, Admission::Fresh);
+        assert_eq!(subject.seen.len(), 2);
+    }
+
+    #[test]
+    fn expired_sightings_do_not_pile_up_in_a_cache_that_never_fills() {
+        let mut subject = ReplayCache::new(Duration::from_millis(10), 2);
+        let payload = CryptData::new(&[5, 6, 7, 8]);
+        subject.admit(&make_hop(), &payload);
+        subject.admit(&make_hop(), &payload);
+        thread::sleep(Duration::from_millis(20));
+
+        subject.admit(&make_hop(), &payload);
+
+        assert_eq!(subject.seen.len(), 1);
+        assert_eq!(subject.arrivals.len(), 2);
+    }
+
+    #[test]
+    fn replays_are_forgotten_after_the_ttl_expires() {
+        let mut subject = ReplayCache::new(Duration::from_millis(10), 10);
+        let hop = make_hop();
+        let payload = CryptData::new(&[5, 6, 7, 8]);
+        subject.admit(&hop, &payload);
+
+        thread::sleep(Duration::from_millis(20));
+
+        assert_eq!(subject.admit(&hop, &payload), Admission::Fresh);
+    }
+}
@@ -1,5 +1,6 @@
 // Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
 use super::live_cores_package::LiveCoresPackage;
+use super::replay_cache::{Admission, ReplayCache, REPLAY_CACHE_CAPACITY, REPLAY_CACHE_TTL};
 use crate::blockchain::payer::Payer;
 use crate::sub_lib::accountant::ReportRoutingServiceProvidedMessage;
 use crate::sub_lib::cryptde::{decodex, encodex, CryptDE, CryptData, CryptdecError};
@@ -7,10 +8,14 @@ use crate::sub_lib::dispatcher::{Component, Endpoint, InboundClientData};
 use crate::sub_lib::hop::LiveHop;
 use crate::sub_lib::hopper::{ExpiredCoresPackage, HopperSubs, MessageType};
 use crate::sub_lib::logger::Logger;
+use crate::sub_lib::metrics;
+use crate::sub_lib::metrics::HOPPER_PACKAGES;
 use crate::sub_lib::neighborhood::NeighborhoodSubs;
+use crate::sub_lib::privacy;
 use crate::sub_lib::proxy_client::ProxyClientSubs;
 use crate::sub_lib::proxy_server::ProxyServerSubs;
 use crate::sub_lib::stream_handler_pool::TransmitDataMsg;
+use crate::sub_lib::wallet::Wallet;
 use actix::Recipient;
 use std::borrow::Borrow;
 use std::net::SocketAddr;
@@ -31,6 +36,7 @@ pub struct RoutingService {
     per_routing_byte: u64,
     logger: Logger,
     is_decentralized: bool,
+    replay_cache: ReplayCache,
 }
 
 impl RoutingService {
@@ -48,16 +54,17 @@ impl RoutingService {
             per_routing_byte,
             logger: Logger::new("RoutingService"),
             is_decentralized,
+            replay_cache: ReplayCache::new(REPLAY_CACHE_TTL, REPLAY_CACHE_CAPACITY),
         }
     }
 
-    pub fn route(&self, ibcd: InboundClientData) {
+    pub fn route(&mut self, ibcd: InboundClientData) {
         let data_size = ibcd.data.len();
         debug!(
             self.logger,
             "Instructed to route {} bytes of InboundClientData ({}) from Dispatcher",
             data_size,
-            ibcd.peer_addr
+            privacy::socket_addr(ibcd.peer_addr)
         );
         let peer_addr = ibcd.peer_addr;
         let last_data = ibcd.last_data;
@@ -71,9 +78,10 @@ impl RoutingService {
                         self.logger,
                         "Couldn't decode CORES package in {}-byte buffer from {}: {}",
                         ibcd.data.len(),
-                        ibcd.peer_addr,
+                        privacy::socket_addr(ibcd.peer_addr),
                         e
                     );
+                    count_package("dropped");
                     return;
                 }
             };
@@ -85,10 +93,24 @@ impl RoutingService {
                     self.logger,
                     "Invalid {}-byte CORES package: {:?}", data_size, e
                 );
+                count_package("dropped");
                 return;
             }
         };
 
+        if self.replay_cache.admit(&next_hop, &live_package.payload) == Admission::Replayed {
+            warning!(
+                self.logger,
+                "Malefactor detected at {}: replayed {}-byte CORES package (hop nonce {}, timestamp {}); dropping",
+                privacy::socket_addr(peer_addr),
+                data_size,
+                next_hop.nonce,
+                next_hop.timestamp
+            );
+            count_package("dropped");
+            return;
+        }
+
         self.route_data(peer_addr, next_hop, live_package, last_data, &ibcd_but_data);
     }
 
@@ -105,7 +127,7 @@ impl RoutingService {
                 self.logger,
                 "Routing LiveCoresPackage with {}-byte payload to {}",
                 live_package.payload.len(),
-                next_hop.public_key
+                privacy::key(&next_hop.public_key)
             );
             self.route_data_externally(live_package, next_hop.payer, last_data);
         } else {
@@ -140,10 +162,11 @@ impl RoutingService {
                     if payer.is_delinquent() {
                         warning!(self.logger,
                         "Node with consuming wallet {} is delinquent; electing not to route {}-byte payload to {:?}",
-                        payer.wallet,
+                        privacy::wallet(&payer.wallet),
                         payload_size,
                         next_hop.component,
                     );
+                        count_package("dropped");
                         return;
                     }
                 }
@@ -166,6 +189,7 @@ impl RoutingService {
             Ok(x) => x,
             Err(e) => {
                 error!(self.logger, "bad zero-hop route: {:?}", e);
+                count_package("dropped");
                 return;
             }
         };
@@ -202,6 +226,7 @@ impl RoutingService {
                         self.logger,
                         "Couldn't expire CORES package with {}-byte payload: {:?}", data_len, e
                     );
+                    count_package("dropped");
                     return;
                 }
             };
@@ -210,7 +235,7 @@ impl RoutingService {
             "Forwarding ExpiredCoresPackage to {:?}",
             component
         );
-        match (component, expired_package.payload) {
+        let consumed = match (component, expired_package.payload) {
             (Component::ProxyClient, MessageType::ClientRequest(client_request)) => {
                 if !self.is_decentralized || payer_owns_secret_key {
                     self.routing_service_subs
@@ -223,61 +248,129 @@ impl RoutingService {
                             client_request,
                             expired_package.payload_len,
                         ))
-                        .expect("ProxyClient is dead")
+                        .expect("ProxyClient is dead");
+                    true
                 } else {
-                    let payload_len = &expired_package.payload_len;
-                    let address = match &expired_package.paying_wallet {
-                        Some(wallet) => format!("{} ", wallet),
-                        None => String::from(""),
-                    };
-                    warning!(
-                        self.logger,
-                            "Refusing to route Expired CORES package with {}-byte payload without proof of {}paying wallet ownership.",
-                        payload_len, address
+                    self.refuse_unproven_paying_wallet(
+                        expired_package.payload_len,
+                        &expired_package.paying_wallet,
                     );
+                    false
                 }
             }
-            (Component::ProxyServer, MessageType::ClientResponse(client_reponse)) => self
-                .routing_service_subs
-                .proxy_server_subs
-                .from_hopper
-                .try_send(ExpiredCoresPackage::new(
-                    expired_package.immediate_neighbor,
-                    expired_package.paying_wallet,
-                    expired_package.remaining_route,
-                    client_reponse,
-                    expired_package.payload_len,
-                ))
-                .expect("ProxyServer is dead"),
-            (Component::ProxyServer, MessageType::DnsResolveFailed(dns_resolve_failure)) => self
-                .routing_service_subs
-                .proxy_server_subs
-                .dns_failure_from_hopper
-                .try_send(ExpiredCoresPackage::new(
-                    expired_package.immediate_neighbor,
-                    expired_package.paying_wallet,
-                    expired_package.remaining_route,
-                    dns_resolve_failure,
-                    expired_package.payload_len,
-                ))
-                .expect("ProxyServer is dead"),
-            (Component::Neighborhood, MessageType::Gossip(gossip)) => self
-                .routing_service_subs
-                .neighborhood_subs
-                .from_hopper
-                .try_send(ExpiredCoresPackage::new(
-                    expired_package.immediate_neighbor,
-                    expired_package.paying_wallet,
-                    expired_package.remaining_route,
-                    gossip,
-                    expired_package.payload_len,
-                ))
-                .expect("Neighborhood is dead"),
-            (destination, payload) => error!(
-                self.logger,
-                "Attempt to send invalid combination {:?} to {:?}", payload, destination
-            ),
+            (Component::ProxyClient, MessageType::DnsQuery(dns_query)) => {
+                if !self.is_decentralized || payer_owns_secret_key {
+                    self.routing_service_subs
+                        .proxy_client_subs
+                        .dns_query_from_hopper
+                        .try_send(ExpiredCoresPackage::new(
+                            expired_package.immediate_neighbor,
+                            expired_package.paying_wallet,
+                            expired_package.remaining_route,
+                            dns_query,
+                            expired_package.payload_len,
+                        ))
+                        .expect("ProxyClient is dead");
+                    true
+                } else {
+                    self.refuse_unproven_paying_wallet(
+                        expired_package.payload_len,
+                        &expired_package.paying_wallet,
+                    );
+                    false
+                }
+            }
+            (Component::ProxyServer, MessageType::ClientResponse(client_reponse)) => {
+                self.routing_service_subs
+                    .proxy_server_subs
+                    .from_hopper
+                    .try_send(ExpiredCoresPackage::new(
+                        expired_package.immediate_neighbor,
+                        expired_package.paying_wallet,
+                        expired_package.remaining_route,
+                        client_reponse,
+                        expired_package.payload_len,
+                    ))
+                    .expect("ProxyServer is dead");
+                true
+            }
+            (Component::ProxyServer, MessageType::DnsResolveFailed(dns_resolve_failure)) => {
+                self.routing_service_subs
+                    .proxy_server_subs
+                    .dns_failure_from_hopper
+                    .try_send(ExpiredCoresPackage::new(
+                        expired_package.immediate_neighbor,
+                        expired_package.paying_wallet,
+                        expired_package.remaining_route,
+                        dns_resolve_failure,
+                        expired_package.payload_len,
+                    ))
+                    .expect("ProxyServer is dead");
+                true
+            }
+            (Component::ProxyServer, MessageType::ExitPolicyRefused(exit_policy_refusal)) => {
+                self.routing_service_subs
+                    .proxy_server_subs
+                    .exit_policy_refusal_from_hopper
+                    .try_send(ExpiredCoresPackage::new(
+                        expired_package.immediate_neighbor,
+                        expired_package.paying_wallet,
+                        expired_package.remaining_route,
+                        exit_policy_refusal,
+                        expired_package.payload_len,
+                    ))
+                    .expect("ProxyServer is dead");
+                true
+            }
+            (Component::ProxyServer, MessageType::DnsAnswer(dns_answer)) => {
+                self.routing_service_subs
+                    .proxy_server_subs
+                    .dns_answer_from_hopper
+                    .try_send(ExpiredCoresPackage::new(
+                        expired_package.immediate_neighbor,
+                        expired_package.paying_wallet,
+                        expired_package.remaining_route,
+                        dns_answer,
+                        expired_package.payload_len,
+                    ))
+                    .expect("ProxyServer is dead");
+                true
+            }
+            (Component::Neighborhood, MessageType::Gossip(gossip)) => {
+                self.routing_service_subs
+                    .neighborhood_subs
+                    .from_hopper
+                    .try_send(ExpiredCoresPackage::new(
+                        expired_package.immediate_neighbor,
+                        expired_package.paying_wallet,
+                        expired_package.remaining_route,
+                        gossip,
+                        expired_package.payload_len,
+                    ))
+                    .expect("Neighborhood is dead");
+                true
+            }
+            (destination, payload) => {
+                error!(
+                    self.logger,
+                    "Attempt to send invalid combination {:?} to {:?}", payload, destination
+                );
+                false
+            }
+        };
+        count_package(if consumed { "consumed" } else { "dropped" });
+    }
+
+    fn refuse_unproven_paying_wallet(&self, payload_len: usize, paying_wallet: &Option<Wallet>) {
+        let address = match paying_wallet {
+            Some(wallet) => format!("{} ", privacy::wallet(wallet)),
+            None => String::from(""),
         };
+        warning!(
+            self.logger,
+            "Refusing to route Expired CORES package with {}-byte payload without proof of {}paying wallet ownership.",
+            payload_len, address
+        );
     }
 
     fn route_data_externally(
@@ -292,16 +385,18 @@ impl RoutingService {
                 if !payer.owns_secret_key(&self.cryptde.digest()) {
                     warning!(self.logger,
                         "Refusing to route Live CORES package with {}-byte payload without proof of {} paying wallet ownership.",
-                        payload_size, payer.wallet
+                        payload_size, privacy::wallet(&payer.wallet)
                     );
+                    count_package("dropped");
                     return;
                 }
                 if payer.is_delinquent() {
                     warning!(self.logger,
                         "Node with consuming wallet {} is delinquent; electing not to route {}-byte payload further",
-                        payer.wallet,
+                        privacy::wallet(&payer.wallet),
                         payload_size,
                     );
+                    count_package("dropped");
                     return;
                 }
                 match self.routing_service_subs.to_accountant_routing.try_send(
@@ -324,6 +419,7 @@ impl RoutingService {
                     "Refusing to route Live CORES package with {}-byte payload without paying wallet",
                     payload_size
                 );
+                count_package("dropped");
                 return;
             }
         }
@@ -332,6 +428,7 @@ impl RoutingService {
             Ok(m) => m,
             Err(e) => {
                 error!(self.logger, "{:?}", e);
+                count_package("dropped");
                 return;
             }
         };
@@ -345,6 +442,7 @@ impl RoutingService {
             .to_dispatcher
             .try_send(transmit_msg)
             .expect("Dispatcher is dead");
+        count_package("routed");
     }
 
     fn to_transmit_data_msg(
@@ -381,6 +479,10 @@ impl RoutingService {
     }
 }
 
+fn count_package(disposition: &str) {
+    metrics::increment(&HOPPER_PACKAGES, &[("disposition", disposition)], 1);
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -391,8 +493,11 @@ mod tests {
     use crate::sub_lib::cryptde::{encodex, PlainData, PublicKey};
     use crate::sub_lib::cryptde_null::CryptDENull;
     use crate::sub_lib::hopper::{IncipientCoresPackage, MessageType, MessageType::ClientRequest};
-    use crate::sub_lib::proxy_client::{ClientResponsePayload, DnsResolveFailure};
-    use crate::sub_lib::proxy_server::ClientRequestPayload;
+    use crate::sub_lib::proxy_client::{
+        ClientResponsePayload, DnsAnswerPayload, DnsRecord, DnsRecordData, DnsResolveFailure,
+        ExitPolicyRefusal,
+    };
+    use crate::sub_lib::proxy_server::{ClientRequestPayload, DnsQueryPayload};
     use crate::sub_lib::route::{Route, RouteSegment};
     use crate::sub_lib::wallet::Wallet;
     use crate::test_utils::environment_guard::EnvironmentGuard;
@@ -436,7 +541,7 @@ mod tests {
 
         let system = System::new("dns_resolution_failures_are_reported_to_the_proxy_server");
         let peer_actors = peer_actors_builder().proxy_server(proxy_server).build();
-        let subject = RoutingService::new(
+        let mut subject = RoutingService::new(
             cryptde,
             RoutingServiceSubs {
                 proxy_client_subs: peer_actors.proxy_client,
@@ -462,6 +567,177 @@ mod tests {
         assert_eq!(dns_resolve_failure, message.payload);
     }
 
+    #[test]
+    fn exit_policy_refusals_are_reported_to_the_proxy_server() {
+        init_test_logging();
+        let cryptde = cryptde();
+        let route = route_to_proxy_server(&cryptde.public_key(), cryptde);
+        let stream_key = make_meaningless_stream_key();
+        let exit_policy_refusal = ExitPolicyRefusal::new(stream_key, "Not allowed".to_string());
+        let lcp = LiveCoresPackage::new(
+            route,
+            encodex(
+                cryptde,
+                &cryptde.public_key(),
+                &MessageType::ExitPolicyRefused(exit_policy_refusal.clone()),
+            )
+            .unwrap(),
+        );
+        let data_enc = encodex(cryptde, &cryptde.public_key(), &lcp).unwrap();
+        let inbound_client_data = InboundClientData {
+            peer_addr: SocketAddr::from_str("1.2.3.4:5678").unwrap(),
+            reception_port: None,
+            sequence_number: None,
+            last_data: false,
+            is_clandestine: false,
+            data: data_enc.into(),
+        };
+        let (proxy_server, proxy_server_awaiter, proxy_server_recording) = make_recorder();
+
+        let system = System::new("exit_policy_refusals_are_reported_to_the_proxy_server");
+        let peer_actors = peer_actors_builder().proxy_server(proxy_server).build();
+        let mut subject = RoutingService::new(
+            cryptde,
+            RoutingServiceSubs {
+                proxy_client_subs: peer_actors.proxy_client,
+                proxy_server_subs: peer_actors.proxy_server,
+                neighborhood_subs: peer_actors.neighborhood,
+                hopper_subs: peer_actors.hopper,
+                to_dispatcher: peer_actors.dispatcher.from_dispatcher_client,
+                to_accountant_routing: peer_actors.accountant.report_routing_service_provided,
+            },
+            100,
+            200,
+            false,
+        );
+
+        subject.route(inbound_client_data);
+
+        System::current().stop();
+        system.run();
+
+        proxy_server_awaiter.await_message_count(1);
+        let recordings = proxy_server_recording.lock().unwrap();
+        let message = recordings.get_record::<ExpiredCoresPackage<ExitPolicyRefusal>>(0);
+        assert_eq!(exit_policy_refusal, message.payload);
+    }
+
+    #[test]
+    fn dns_answers_are_reported_to_the_proxy_server() {
+        let cryptde = cryptde();
+        let route = route_to_proxy_server(&cryptde.public_key(), cryptde);
+        let dns_answer = DnsAnswerPayload::new(
+            make_meaningless_stream_key(),
+            0,
+            vec![DnsRecord {
+                name: "example.com".to_string(),
+                ttl: 300,
+                data: DnsRecordData::Mx(10, "mail.example.com".to_string()),
+            }],
+        );
+        let lcp = LiveCoresPackage::new(
+            route,
+            encodex(
+                cryptde,
+                &cryptde.public_key(),
+                &MessageType::DnsAnswer(dns_answer.clone()),
+            )
+            .unwrap(),
+        );
+        let data_enc = encodex(cryptde, &cryptde.public_key(), &lcp).unwrap();
+        let inbound_client_data = InboundClientData {
+            peer_addr: SocketAddr::from_str("1.2.3.4:5678").unwrap(),
+            reception_port: None,
+            sequence_number: None,
+            last_data: false,
+            is_clandestine: false,
+            data: data_enc.into(),
+        };
+        let (proxy_server, proxy_server_awaiter, proxy_server_recording) = make_recorder();
+
+        let system = System::new("dns_answers_are_reported_to_the_proxy_server");
+        let peer_actors = peer_actors_builder().proxy_server(proxy_server).build();
+        let mut subject = RoutingService::new(
+            cryptde,
+            RoutingServiceSubs {
+                proxy_client_subs: peer_actors.proxy_client,
+                proxy_server_subs: peer_actors.proxy_server,
+                neighborhood_subs: peer_actors.neighborhood,
+                hopper_subs: peer_actors.hopper,
+                to_dispatcher: peer_actors.dispatcher.from_dispatcher_client,
+                to_accountant_routing: peer_actors.accountant.report_routing_service_provided,
+            },
+            100,
+            200,
+            false,
+        );
+
+        subject.route(inbound_client_data);
+
+        System::current().stop();
+        system.run();
+
+        proxy_server_awaiter.await_message_count(1);
+        let recordings = proxy_server_recording.lock().unwrap();
+        let message = recordings.get_record::<ExpiredCoresPackage<DnsAnswerPayload>>(0);
+        assert_eq!(dns_answer, message.payload);
+    }
+
+    #[test]
+    fn dns_queries_are_delivered_to_the_proxy_client() {
+        let _eg = EnvironmentGuard::new();
+        BAN_CACHE.clear();
+        let cryptde = cryptde();
+        let (component, _, component_recording_arc) = make_recorder();
+        let route = route_to_proxy_client(&cryptde.public_key(), cryptde);
+        let dns_query = DnsQueryPayload {
+            version: DnsQueryPayload::version(),
+            stream_key: make_meaningless_stream_key(),
+            name: "example.com".to_string(),
+            record_type: 15,
+            originator_public_key: cryptde.public_key().clone(),
+        };
+        let lcp = LiveCoresPackage::new(
+            route,
+            encodex::<MessageType>(cryptde, &cryptde.public_key(), &dns_query.clone().into())
+                .unwrap(),
+        );
+        let data_enc = encodex(cryptde, &cryptde.public_key(), &lcp).unwrap();
+        let inbound_client_data = InboundClientData {
+            peer_addr: SocketAddr::from_str("1.2.3.4:5678").unwrap(),
+            reception_port: None,
+            sequence_number: None,
+            last_data: false,
+            is_clandestine: false,
+            data: data_enc.into(),
+        };
+
+        let system = System::new("dns_queries_are_delivered_to_the_proxy_client");
+        let peer_actors = peer_actors_builder().proxy_client(component).build();
+        let mut subject = RoutingService::new(
+            cryptde,
+            RoutingServiceSubs {
+                proxy_client_subs: peer_actors.proxy_client,
+                proxy_server_subs: peer_actors.proxy_server,
+                neighborhood_subs: peer_actors.neighborhood,
+                hopper_subs: peer_actors.hopper,
+                to_dispatcher: peer_actors.dispatcher.from_dispatcher_client,
+                to_accountant_routing: peer_actors.accountant.report_routing_service_provided,
+            },
+            0,
+            0,
+            false,
+        );
+
+        subject.route(inbound_client_data);
+
+        System::current().stop();
+        system.run();
+        let component_recording = component_recording_arc.lock().unwrap();
+        let record = component_recording.get_record::<ExpiredCoresPackage<DnsQueryPayload>>(0);
+        assert_eq!(record.payload, dns_query);
+    }
+
     #[test]
     fn logs_and_ignores_message_that_cannot_be_decoded() {
         init_test_logging();
@@ -481,7 +757,7 @@ mod tests {
             data: data_enc.into(),
         };
         let peer_actors = peer_actors_builder().build();
-        let subject = RoutingService::new(
+        let mut subject = RoutingService::new(
             cryptde,
             RoutingServiceSubs {
                 proxy_client_subs: peer_actors.proxy_client,
@@ -527,7 +803,7 @@ mod tests {
             data: data_enc.into(),
         };
         let peer_actors = peer_actors_builder().build();
-        let subject = RoutingService::new(
+        let mut subject = RoutingService::new(
             cryptde,
             RoutingServiceSubs {
                 proxy_client_subs: peer_actors.proxy_client,
@@ -573,7 +849,7 @@ mod tests {
 
         let system = System::new("converts_live_message_to_expired_for_proxy_client");
         let peer_actors = peer_actors_builder().proxy_client(component).build();
-        let subject = RoutingService::new(
+        let mut subject = RoutingService::new(
             cryptde,
             RoutingServiceSubs {
                 proxy_client_subs: peer_actors.proxy_client,
@@ -631,7 +907,7 @@ mod tests {
 
         let system = System::new("converts_live_message_to_expired_for_proxy_server");
         let peer_actors = peer_actors_builder().proxy_server(component).build();
-        let subject = RoutingService::new(
+        let mut subject = RoutingService::new(
             cryptde,
             RoutingServiceSubs {
                 proxy_client_subs: peer_actors.proxy_client,
@@ -699,7 +975,7 @@ mod tests {
 
         let system = System::new("converts_live_message_to_expired_for_neighborhood");
         let peer_actors = peer_actors_builder().neighborhood(component).build();
-        let subject = RoutingService::new(
+        let mut subject = RoutingService::new(
             cryptde,
             RoutingServiceSubs {
                 proxy_client_subs: peer_actors.proxy_client,
@@ -770,7 +1046,7 @@ mod tests {
             .dispatcher(dispatcher)
             .accountant(accountant)
             .build();
-        let subject = RoutingService::new(
+        let mut subject = RoutingService::new(
             cryptde,
             RoutingServiceSubs {
                 proxy_client_subs: peer_actors.proxy_client,
@@ -855,7 +1131,7 @@ mod tests {
             "reprocesses_inbound_client_data_meant_for_this_node_and_destined_for_hopper",
         );
         let peer_actors = peer_actors_builder().hopper(hopper).build();
-        let subject = RoutingService::new(
+        let mut subject = RoutingService::new(
             cryptde,
             RoutingServiceSubs {
                 proxy_client_subs: peer_actors.proxy_client,
@@ -940,7 +1216,7 @@ mod tests {
             .dispatcher(dispatcher)
             .accountant(accountant)
             .build();
-        let subject = RoutingService::new(
+        let mut subject = RoutingService::new(
             cryptde,
             RoutingServiceSubs {
                 proxy_client_subs: peer_actors.proxy_client,
@@ -1034,7 +1310,7 @@ mod tests {
             .dispatcher(dispatcher)
             .accountant(accountant)
             .build();
-        let subject = RoutingService::new(
+        let mut subject = RoutingService::new(
             cryptde,
             RoutingServiceSubs {
                 proxy_client_subs: peer_actors.proxy_client,
@@ -1194,7 +1470,7 @@ mod tests {
             .dispatcher(dispatcher)
             .accountant(accountant)
             .build();
-        let subject = RoutingService::new(
+        let mut subject = RoutingService::new(
             cryptde,
             RoutingServiceSubs {
                 proxy_client_subs: peer_actors.proxy_client,
@@ -1261,7 +1537,7 @@ mod tests {
             .dispatcher(dispatcher)
             .accountant(accountant)
             .build();
-        let subject = RoutingService::new(
+        let mut subject = RoutingService::new(
             cryptde,
             RoutingServiceSubs {
                 proxy_client_subs: peer_actors.proxy_client,
@@ -1310,7 +1586,7 @@ mod tests {
             .neighborhood(neighborhood)
             .dispatcher(dispatcher)
             .build();
-        let subject = RoutingService::new(
+        let mut subject = RoutingService::new(
             cryptde(),
             RoutingServiceSubs {
                 proxy_client_subs: peer_actors.proxy_client,
@@ -1364,7 +1640,7 @@ mod tests {
             .neighborhood(neighborhood)
             .dispatcher(dispatcher)
             .build();
-        let subject = RoutingService::new(
+        let mut subject = RoutingService::new(
             cryptde,
             RoutingServiceSubs {
                 proxy_client_subs: peer_actors.proxy_client,
@@ -1392,6 +1668,66 @@ mod tests {
         assert_eq!(dispatcher_recording_arc.lock().unwrap().len(), 0);
     }
 
+    #[test]
+    fn route_drops_and_logs_replayed_cores_package() {
+        let _eg = EnvironmentGuard::new();
+        BAN_CACHE.clear();
+        init_test_logging();
+        let cryptde = cryptde();
+        let (component, _, component_recording_arc) = make_recorder();
+        let route = route_to_proxy_client(&cryptde.public_key(), cryptde);
+        let payload = make_request_payload(0, cryptde);
+        let lcp = LiveCoresPackage::new(
+            route,
+            encodex::<MessageType>(cryptde, &cryptde.public_key(), &payload.clone().into())
+                .unwrap(),
+        );
+        let data_enc = encodex(cryptde, &cryptde.public_key(), &lcp).unwrap();
+        let inbound_client_data = InboundClientData {
+            peer_addr: SocketAddr::from_str("1.2.3.4:5678").unwrap(),
+            reception_port: None,
+            sequence_number: None,
+            last_data: true,
+            is_clandestine: false,
+            data: data_enc.into(),
+        };
+        let system = System::new("route_drops_and_logs_replayed_cores_package");
+        let peer_actors = peer_actors_builder().proxy_client(component).build();
+        let mut subject = RoutingService::new(
+            cryptde,
+            RoutingServiceSubs {
+                proxy_client_subs: peer_actors.proxy_client,
+                proxy_server_subs: peer_actors.proxy_server,
+                neighborhood_subs: peer_actors.neighborhood,
+                hopper_subs: peer_actors.hopper,
+                to_dispatcher: peer_actors.dispatcher.from_dispatcher_client,
+                to_accountant_routing: peer_actors.accountant.report_routing_service_provided,
+            },
+            0,
+            0,
+            false,
+        );
+        let count = |disposition: &str| {
+            metrics::value(&HOPPER_PACKAGES, &[("disposition", disposition)]).unwrap_or(0)
+        };
+        let consumed_before = count("consumed");
+        let dropped_before = count("dropped");
+
+        subject.route(inbound_client_data.clone());
+        subject.route(inbound_client_data);
+
+        System::current().stop();
+        system.run();
+        let component_recording = component_recording_arc.lock().unwrap();
+        assert_eq!(component_recording.len(), 1);
+        // Other tests count packages too, so only the increase is ours
+        assert!(count("consumed") > consumed_before);
+        assert!(count("dropped") > dropped_before);
+        TestLogHandler::new().exists_log_matching(
+            "WARN: RoutingService: Malefactor detected at 1\\.2\\.3\\.4:5678: replayed \\d+-byte CORES package \\(hop nonce \\d+, timestamp \\d+\\); dropping",
+        );
+    }
+
     #[test]
     fn route_data_around_again_logs_and_ignores_bad_lcp() {
         init_test_logging();
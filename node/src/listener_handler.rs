@@ -2,6 +2,7 @@
 use crate::bootstrapper::PortConfiguration;
 use crate::stream_messages::AddStreamMsg;
 use crate::sub_lib::logger::Logger;
+use crate::sub_lib::privacy;
 use crate::sub_lib::stream_connector::StreamConnector;
 use crate::sub_lib::stream_connector::StreamConnectorReal;
 use crate::sub_lib::tokio_wrappers::TokioListenerWrapper;
@@ -76,7 +77,7 @@ impl Future for ListenerHandlerReal {
                                 error!(
                                     self.logger,
                                     "Connection from {} was closed before it could be accepted",
-                                    socket_addr
+                                    privacy::socket_addr(socket_addr)
                                 );
                                 return Ok(Async::NotReady);
                             }
@@ -2,7 +2,7 @@
 
 use crate::sub_lib::logger::Logger;
 use clap::{App, ArgMatches};
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::fmt::Debug;
 use std::fs::File;
 use std::io::{ErrorKind, Read};
@@ -282,8 +282,29 @@ impl VirtualCommandLine for ConfigFileVcl {
     }
 }
 
+// How one table of a configuration file turns into command-line arguments
+pub struct ConfigFileSection {
+    pub name: &'static str,
+    pub settings: ConfigFileSettings,
+}
+
+pub enum ConfigFileSettings {
+    // Each key stands for the argument paired with it
+    Each(&'static [(&'static str, &'static str)]),
+    // Every key must be present, and their values are joined in this order into one argument
+    Joined {
+        arg: &'static str,
+        separator: &'static str,
+        keys: &'static [&'static str],
+    },
+}
+
 impl ConfigFileVcl {
-    pub fn new(file_path: &PathBuf, user_specified: bool) -> ConfigFileVcl {
+    pub fn new(
+        file_path: &PathBuf,
+        user_specified: bool,
+        sections: &[ConfigFileSection],
+    ) -> ConfigFileVcl {
         let logger = Logger::new("Bootstrapper");
         let mut file: File = match File::open(file_path) {
             Err(e) => {
@@ -316,31 +337,205 @@ impl ConfigFileVcl {
             ),
             Ok(table) => table,
         };
-        let vcl_args: Vec<Box<dyn VclArg>> = table
-            .keys()
-            .map(|key| {
-                let name = format!("--{}", key);
-                let value = match table.get(key).expect("value disappeared") {
-                    Value::Table(_) => Self::complain_about_data_elements(file_path),
-                    Value::Array(_) => Self::complain_about_data_elements(file_path),
-                    Value::Datetime(_) => Self::complain_about_data_elements(file_path),
-                    Value::String(v) => v.as_str().to_string(),
-                    v => v.to_string(),
-                };
-                let result: Box<dyn VclArg> =
-                    Box::new(NameValueVclArg::new(&name, &value.to_string()));
-                result
-            })
-            .collect();
+        let mut reader = ConfigFileReader::new(file_path, &contents);
+        for (key, value) in table.iter() {
+            match value {
+                Value::Table(settings) => reader.read_section(key, settings, sections),
+                value => reader.read_setting(None, key, key, value),
+            }
+        }
 
-        ConfigFileVcl { vcl_args }
+        ConfigFileVcl {
+            vcl_args: reader.vcl_args,
+        }
     }
+}
 
-    fn complain_about_data_elements(file_path: &PathBuf) -> ! {
-        panic!("Configuration file at {:?} contains unsupported Datetime or non-scalar configuration values", file_path)
+struct ConfigFileReader<'a> {
+    file_path: &'a PathBuf,
+    contents: &'a str,
+    vcl_args: Vec<Box<dyn VclArg>>,
+    lines_setting_args: HashMap<String, usize>,
+}
+
+impl<'a> ConfigFileReader<'a> {
+    fn new(file_path: &'a PathBuf, contents: &'a str) -> ConfigFileReader<'a> {
+        ConfigFileReader {
+            file_path,
+            contents,
+            vcl_args: vec![],
+            lines_setting_args: HashMap::new(),
+        }
+    }
+
+    fn read_section(&mut self, name: &str, settings: &Table, sections: &[ConfigFileSection]) {
+        let section = match sections.iter().find(|section| section.name == name) {
+            Some(section) => section,
+            None => self.complain(
+                self.locate_section(name),
+                &format!(
+                    "there is no section [{}]; the sections are {}",
+                    name,
+                    sections
+                        .iter()
+                        .map(|section| format!("[{}]", section.name))
+                        .collect::<Vec<String>>()
+                        .join(", ")
+                ),
+            ),
+        };
+        match &section.settings {
+            ConfigFileSettings::Each(pairs) => {
+                for (key, value) in settings.iter() {
+                    match pairs
+                        .iter()
+                        .find(|(candidate, _)| *candidate == key.as_str())
+                    {
+                        Some((_, arg)) => self.read_setting(Some(name), key, arg, value),
+                        None => self.complain_about_key(
+                            name,
+                            key,
+                            &pairs.iter().map(|(key, _)| *key).collect::<Vec<&str>>(),
+                        ),
+                    }
+                }
+            }
+            ConfigFileSettings::Joined {
+                arg,
+                separator,
+                keys,
+            } => {
+                if let Some(key) = settings.keys().find(|key| !keys.contains(&key.as_str())) {
+                    self.complain_about_key(name, key, keys)
+                }
+                let values = keys
+                    .iter()
+                    .map(|key| match settings.get(*key) {
+                        Some(value) => self.scalar(Some(name), key, value),
+                        None => self.complain(
+                            self.locate_section(name),
+                            &format!("[{}] needs '{}' as well", name, key),
+                        ),
+                    })
+                    .collect::<Vec<String>>();
+                self.add(self.locate_section(name), arg, values.join(separator));
+            }
+        }
+    }
+
+    // A list becomes one comma-separated value, as it would be written on the command line
+    fn read_setting(&mut self, section_opt: Option<&str>, key: &str, arg: &str, value: &Value) {
+        let value = match value {
+            Value::Array(elements) => elements
+                .iter()
+                .map(|element| self.scalar(section_opt, key, element))
+                .collect::<Vec<String>>()
+                .join(","),
+            value => self.scalar(section_opt, key, value),
+        };
+        self.add(self.locate_key(section_opt, key), arg, value);
+    }
+
+    fn scalar(&self, section_opt: Option<&str>, key: &str, value: &Value) -> String {
+        match value {
+            Value::String(v) => v.to_string(),
+            // Every switch that takes a value is an on/off switch
+            Value::Boolean(true) => "on".to_string(),
+            Value::Boolean(false) => "off".to_string(),
+            Value::Integer(_) | Value::Float(_) => value.to_string(),
+            Value::Datetime(_) => self.complain(
+                self.locate_key(section_opt, key),
+                &format!("'{}' is a date or time, which no setting takes", key),
+            ),
+            Value::Array(_) | Value::Table(_) => self.complain(
+                self.locate_key(section_opt, key),
+                &format!(
+                    "'{}' must be a single value or a list of single values",
+                    key
+                ),
+            ),
+        }
+    }
+
+    fn add(&mut self, position: (usize, usize), arg: &str, value: String) {
+        if let Some(line) = self.lines_setting_args.get(arg) {
+            self.complain(position, &format!("--{} is also set on line {}", arg, line))
+        }
+        self.lines_setting_args.insert(arg.to_string(), position.0);
+        self.vcl_args.push(Box::new(NameValueVclArg::new(
+            &format!("--{}", arg),
+            &value,
+        )));
+    }
+
+    fn complain_about_key(&self, section: &str, key: &str, keys: &[&str]) -> ! {
+        self.complain(
+            self.locate_key(Some(section), key),
+            &format!(
+                "[{}] has no setting '{}'; its settings are {}",
+                section,
+                key,
+                keys.join(", ")
+            ),
+        )
+    }
+
+    fn complain(&self, (line, column): (usize, usize), problem: &str) -> ! {
+        panic!(
+            "Configuration file at {:?}, line {} column {}: {}",
+            self.file_path, line, column, problem
+        )
+    }
+
+    // toml doesn't say where in the file it found each value, so we look for the line that set it
+    fn locate_key(&self, section_opt: Option<&str>, key: &str) -> (usize, usize) {
+        let mut current_section: Option<&str> = None;
+        for (index, line) in self.contents.lines().enumerate() {
+            let trimmed = line.trim_start();
+            if let Some(header) = section_header(trimmed) {
+                current_section = Some(header);
+            } else if current_section == section_opt && sets_key(trimmed, key) {
+                return (index + 1, line.len() - trimmed.len() + 1);
+            }
+        }
+        match section_opt {
+            Some(section) => self.locate_section(section),
+            None => (1, 1),
+        }
+    }
+
+    fn locate_section(&self, name: &str) -> (usize, usize) {
+        for (index, line) in self.contents.lines().enumerate() {
+            let trimmed = line.trim_start();
+            if section_header(trimmed) == Some(name) {
+                return (index + 1, line.len() - trimmed.len() + 1);
+            }
+        }
+        // Written as an inline table, such as rate_pack = { ... }
+        self.locate_key(None, name)
+    }
+}
+
+fn section_header(line: &str) -> Option<&str> {
+    if line.starts_with('[') && !line.starts_with("[[") {
+        line[1..]
+            .split(']')
+            .next()
+            .map(|name| name.trim().trim_matches('"'))
+    } else {
+        None
     }
 }
 
+fn sets_key(line: &str, key: &str) -> bool {
+    let unquoted = line.trim_start_matches('"');
+    unquoted.starts_with(key)
+        && unquoted[key.len()..]
+            .trim_start_matches('"')
+            .trim_start()
+            .starts_with('=')
+}
+
 #[cfg(test)]
 pub(crate) mod tests {
     use super::*;
@@ -770,13 +965,13 @@ pub(crate) mod tests {
                 .unwrap();
         }
 
-        let subject = ConfigFileVcl::new(&file_path, true);
+        let subject = ConfigFileVcl::new(&file_path, true, &[]);
 
         assert_eq!(
             vec![
                 "".to_string(),
                 "--boolean-arg".to_string(),
-                "true".to_string(),
+                "on".to_string(),
                 "--numeric-arg".to_string(),
                 "47".to_string(),
                 "--string-arg".to_string(),
@@ -804,7 +999,7 @@ pub(crate) mod tests {
         let mut file_path = home_dir.clone();
         file_path.push("config.toml");
 
-        let subject = ConfigFileVcl::new(&file_path, false);
+        let subject = ConfigFileVcl::new(&file_path, false, &[]);
 
         assert_eq!(vec!["".to_string()], subject.args());
         assert!(subject.vcl_args().is_empty());
@@ -823,7 +1018,7 @@ pub(crate) mod tests {
         let mut file_path = home_dir.clone();
         file_path.push("config.toml");
 
-        ConfigFileVcl::new(&file_path, true);
+        ConfigFileVcl::new(&file_path, true, &[]);
     }
 
     #[test]
@@ -842,7 +1037,7 @@ pub(crate) mod tests {
             toml_file.write_all(&mut buf).unwrap();
         }
 
-        ConfigFileVcl::new(&file_path, true);
+        ConfigFileVcl::new(&file_path, true, &[]);
     }
 
     #[test]
@@ -861,57 +1056,184 @@ pub(crate) mod tests {
             toml_file.write_all(b"][=blah..[\n").unwrap();
         }
 
-        ConfigFileVcl::new(&file_path, true);
+        ConfigFileVcl::new(&file_path, true, &[]);
+    }
+
+    const TEST_SECTIONS: &[ConfigFileSection] = &[
+        ConfigFileSection {
+            name: "network",
+            settings: ConfigFileSettings::Each(&[
+                ("servers", "server-list"),
+                ("port", "numeric-arg"),
+            ]),
+        },
+        ConfigFileSection {
+            name: "rates",
+            settings: ConfigFileSettings::Joined {
+                arg: "rate-arg",
+                separator: "|",
+                keys: &["first", "second"],
+            },
+        },
+    ];
+
+    fn write_config_file(test_name: &str, contents: &str) -> PathBuf {
+        let home_dir = ensure_node_home_directory_exists("multi_config", test_name);
+        let file_path = home_dir.join("config.toml");
+        let mut toml_file = File::create(&file_path).unwrap();
+        toml_file.write_all(contents.as_bytes()).unwrap();
+        file_path
     }
 
     #[test]
-    #[should_panic(expected = "contains unsupported Datetime or non-scalar configuration values")]
-    fn config_file_vcl_handles_datetime_element() {
-        let home_dir = ensure_node_home_directory_exists(
-            "multi_config",
-            "config_file_vcl_handles_datetime_element",
+    fn config_file_vcl_turns_sections_and_arrays_into_arguments() {
+        let file_path = write_config_file(
+            "config_file_vcl_turns_sections_and_arrays_into_arguments",
+            "string-arg = \"booga\"\nlist-arg = [1, 2, 3]\n\n[network]\nservers = [\"1.1.1.1\", \"8.8.8.8\"]\nport = 47\n\n[rates]\nsecond = 2\nfirst = 1\n",
         );
-        let mut file_path = home_dir.clone();
-        file_path.push("config.toml");
-        {
-            let mut toml_file = File::create(&file_path).unwrap();
-            toml_file.write_all(b"datetime = 12:34:56\n").unwrap();
-        }
 
-        ConfigFileVcl::new(&file_path, true);
+        let subject = ConfigFileVcl::new(&file_path, true, TEST_SECTIONS);
+
+        assert_eq!(
+            subject.args(),
+            vec![
+                "".to_string(),
+                "--list-arg".to_string(),
+                "1,2,3".to_string(),
+                "--numeric-arg".to_string(),
+                "47".to_string(),
+                "--server-list".to_string(),
+                "1.1.1.1,8.8.8.8".to_string(),
+                "--rate-arg".to_string(),
+                "1|2".to_string(),
+                "--string-arg".to_string(),
+                "booga".to_string(),
+            ]
+        );
     }
 
     #[test]
-    #[should_panic(expected = "contains unsupported Datetime or non-scalar configuration values")]
-    fn config_file_vcl_handles_array_element() {
-        let home_dir = ensure_node_home_directory_exists(
-            "multi_config",
-            "config_file_vcl_handles_array_element",
+    fn config_file_vcl_keeps_the_precedence_of_its_place_in_the_multi_config() {
+        let file_path = write_config_file(
+            "config_file_vcl_keeps_the_precedence_of_its_place_in_the_multi_config",
+            "[network]\nport = 47\nservers = [\"1.1.1.1\", \"8.8.8.8\"]\n",
         );
-        let mut file_path = home_dir.clone();
-        file_path.push("config.toml");
-        {
-            let mut toml_file = File::create(&file_path).unwrap();
-            toml_file.write_all(b"array = [1, 2, 3]\n").unwrap();
-        }
+        let schema = App::new("test")
+            .arg(
+                Arg::with_name("numeric-arg")
+                    .long("numeric-arg")
+                    .takes_value(true),
+            )
+            .arg(
+                Arg::with_name("server-list")
+                    .long("server-list")
+                    .takes_value(true)
+                    .use_delimiter(true),
+            );
+        let vcls: Vec<Box<dyn VirtualCommandLine>> = vec![
+            Box::new(ConfigFileVcl::new(&file_path, true, TEST_SECTIONS)),
+            Box::new(CommandLineVcl::new(vec![
+                String::new(),
+                "--numeric-arg".to_string(),
+                "10".to_string(),
+            ])),
+        ];
 
-        ConfigFileVcl::new(&file_path, true);
+        let subject = MultiConfig::new(&schema, vcls);
+
+        assert_eq!(value_m!(subject, "numeric-arg", u64), Some(10));
+        assert_eq!(
+            values_m!(subject, "server-list", String),
+            vec!["1.1.1.1".to_string(), "8.8.8.8".to_string()]
+        );
     }
 
     #[test]
-    #[should_panic(expected = "contains unsupported Datetime or non-scalar configuration values")]
-    fn config_file_vcl_handles_table_element() {
-        let home_dir = ensure_node_home_directory_exists(
-            "multi_config",
-            "config_file_vcl_handles_table_element",
+    #[should_panic(
+        expected = "line 3 column 3: there is no section [netwrok]; the sections are [network], [rates]"
+    )]
+    fn config_file_vcl_complains_about_unknown_section() {
+        let file_path = write_config_file(
+            "config_file_vcl_complains_about_unknown_section",
+            "string-arg = \"booga\"\n\n  [netwrok]\nport = 47\n",
         );
-        let mut file_path = home_dir.clone();
-        file_path.push("config.toml");
-        {
-            let mut toml_file = File::create(&file_path).unwrap();
-            toml_file.write_all(b"[table]\nooga = \"booga\"").unwrap();
-        }
 
-        ConfigFileVcl::new(&file_path, true);
+        ConfigFileVcl::new(&file_path, true, TEST_SECTIONS);
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "line 3 column 5: [network] has no setting 'sevrers'; its settings are servers, port"
+    )]
+    fn config_file_vcl_complains_about_unknown_setting_in_section() {
+        let file_path = write_config_file(
+            "config_file_vcl_complains_about_unknown_setting_in_section",
+            "[network]\nport = 47\n    sevrers = [\"1.1.1.1\"]\n",
+        );
+
+        ConfigFileVcl::new(&file_path, true, TEST_SECTIONS);
+    }
+
+    #[test]
+    #[should_panic(expected = "line 2 column 1: [rates] needs 'second' as well")]
+    fn config_file_vcl_complains_about_incomplete_joined_section() {
+        let file_path = write_config_file(
+            "config_file_vcl_complains_about_incomplete_joined_section",
+            "string-arg = \"booga\"\n[rates]\nfirst = 1\n",
+        );
+
+        ConfigFileVcl::new(&file_path, true, TEST_SECTIONS);
+    }
+
+    #[test]
+    #[should_panic(expected = "line 1 column 1: --numeric-arg is also set on line 3")]
+    fn config_file_vcl_complains_about_argument_set_twice() {
+        let file_path = write_config_file(
+            "config_file_vcl_complains_about_argument_set_twice",
+            "numeric-arg = 46\n[network]\nport = 47\n",
+        );
+
+        ConfigFileVcl::new(&file_path, true, TEST_SECTIONS);
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "line 2 column 1: 'datetime' is a date or time, which no setting takes"
+    )]
+    fn config_file_vcl_complains_about_datetime_element() {
+        let file_path = write_config_file(
+            "config_file_vcl_complains_about_datetime_element",
+            "string-arg = \"booga\"\ndatetime = 12:34:56\n",
+        );
+
+        ConfigFileVcl::new(&file_path, true, TEST_SECTIONS);
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "line 2 column 1: 'servers' must be a single value or a list of single values"
+    )]
+    fn config_file_vcl_complains_about_nested_array_element() {
+        let file_path = write_config_file(
+            "config_file_vcl_complains_about_nested_array_element",
+            "[network]\nservers = [[1, 2], [3]]\n",
+        );
+
+        ConfigFileVcl::new(&file_path, true, TEST_SECTIONS);
+    }
+
+    #[test]
+    fn config_file_vcl_finds_inline_tables() {
+        let file_path = write_config_file(
+            "config_file_vcl_finds_inline_tables",
+            "rates = { first = 1, second = 2 }\n",
+        );
+
+        let subject = ConfigFileVcl::new(&file_path, true, TEST_SECTIONS);
+
+        assert_eq!(
+            subject.args(),
+            vec!["".to_string(), "--rate-arg".to_string(), "1|2".to_string()]
+        );
     }
 }
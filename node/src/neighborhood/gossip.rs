@@ -610,9 +610,9 @@ mod tests {
             "\n\tinner: NodeRecordInner {\n\t\tpublic_key: AQIDBA,\n\t\tnode_addr_opt: Some(1.2.3.4:[1234]),\n\t\tearning_wallet: Wallet { kind: Address(0x546900db8d6e0937497133d1ae6fdf5f4b75bcd0) },\n\t\trate_pack: RatePack { routing_byte_rate: 1235, routing_service_rate: 1236, exit_byte_rate: 1237, exit_service_rate: 1238 },\n\t\tneighbors: [],\n\t\tversion: 2,\n\t},",
             "\n\tnode_addr_opt: Some(1.2.3.4:[1234]),",
             "\n\tsigned_data:
-Length: 246 (0xf6) bytes
-0000:   a8 6c 64 61  74 61 5f 76  65 72 73 69  6f 6e 83 00   .ldata_version..
-0010:   10 00 6a 70  75 62 6c 69  63 5f 6b 65  79 44 01 02   ..jpublic_keyD..
+Length: 280 (0x118) bytes
+0000:   a9 6c 64 61  74 61 5f 76  65 72 73 69  6f 6e 83 00   .ldata_version..
+0010:   10 01 6a 70  75 62 6c 69  63 5f 6b 65  79 44 01 02   ..jpublic_keyD..
 0020:   03 04 6e 65  61 72 6e 69  6e 67 5f 77  61 6c 6c 65   ..nearning_walle
 0030:   74 a1 67 61  64 64 72 65  73 73 94 18  54 18 69 00   t.gaddress..T.i.
 0040:   18 db 18 8d  18 6e 09 18  37 18 49 18  71 18 33 18   .....n..7.I.q.3.
@@ -625,12 +625,14 @@ Length: 246 (0xf6) bytes
 00b0:   5f 73 65 72  76 69 63 65  5f 72 61 74  65 19 04 d6   _service_rate...
 00c0:   69 6e 65 69  67 68 62 6f  72 73 80 73  61 63 63 65   ineighbors.sacce
 00d0:   70 74 73 5f  63 6f 6e 6e  65 63 74 69  6f 6e 73 f5   pts_connections.
-00e0:   6b 72 6f 75  74 65 73 5f  64 61 74 61  f5 67 76 65   kroutes_data.gve
-00f0:   72 73 69 6f  6e 02                                   rsion.",
+00e0:   6b 72 6f 75  74 65 73 5f  64 61 74 61  f5 6b 65 78   kroutes_data.kex
+00f0:   69 74 5f 70  6f 6c 69 63  79 a2 65 61  6c 6c 6f 77   it_policy.eallow
+0100:   80 64 64 65  6e 79 81 67  70 72 69 76  61 74 65 67   .ddeny.gprivateg
+0110:   76 65 72 73  69 6f 6e 02                             version.",
             "\n\tsignature:
 Length: 24 (0x18) bytes
-0000:   01 02 03 04  67 5b 38 53  63 59 ad a8  3d e1 d5 96   ....g[8ScY..=...
-0010:   89 59 88 be  09 86 64 ae                             .Y....d.",
+0000:   01 02 03 04  69 c6 4e c8  65 aa da 75  7e 30 7b f8   ....i.N.e..u~0{.
+0010:   a5 db bd bd  f6 0e 8c 50                             .......P",
         );
 
         assert_eq!(result, expected);
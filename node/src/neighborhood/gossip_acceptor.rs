@@ -7,6 +7,7 @@ use crate::neighborhood::AccessibleGossipRecord;
 use crate::sub_lib::cryptde::{CryptDE, PublicKey};
 use crate::sub_lib::logger::Logger;
 use crate::sub_lib::node_addr::NodeAddr;
+use crate::sub_lib::privacy;
 use std::collections::HashSet;
 use std::net::{IpAddr, SocketAddr};
 
@@ -142,10 +143,10 @@ impl GossipHandler for DebutHandler {
                 .ip_addr();
             debug!(self.logger,
                 "DebutHandler is commissioning Pass of {} at {} to more appropriate neighbor {} at {}",
-                source_key,
-                gossip_source,
-                preferred_key,
-                preferred_ip,
+                privacy::key(&source_key),
+                privacy::socket_addr(gossip_source),
+                privacy::key(&preferred_key),
+                privacy::ip(preferred_ip),
             );
             return GossipAcceptanceResult::Reply(
                 Self::make_pass_gossip(database, preferred_key),
@@ -164,7 +165,7 @@ impl GossipHandler for DebutHandler {
             None => {
                 debug!(self.logger,
                     "Neighbor count at maximum, but no non-common neighbors. DebutHandler is reluctantly ignoring debut from {} at {}",
-                    source_key, source_node_addr
+                    privacy::key(&source_key), privacy::ip(&source_node_addr)
                 );
                 return GossipAcceptanceResult::Ignored;
             }
@@ -181,10 +182,10 @@ impl GossipHandler for DebutHandler {
         debug!(
             self.logger,
             "DebutHandler is commissioning Pass of {} at {} to {} at {}",
-            source_key,
-            source_node_addr.ip_addr(),
-            lcn_key,
-            lcn_ip_str
+            privacy::key(&source_key),
+            privacy::ip(source_node_addr.ip_addr()),
+            privacy::key(&lcn_key),
+            privacy::ip(&lcn_ip_str)
         );
         GossipAcceptanceResult::Reply(
             Self::make_pass_gossip(database, lcn_key),
@@ -269,15 +270,19 @@ impl DebutHandler {
                 let root_mut = database.root_mut();
                 root_mut.increment_version();
                 root_mut.regenerate_signed_gossip(cryptde);
-                trace!(self.logger, "Current database: {}", database.to_dot_graph());
+                trace!(
+                    self.logger,
+                    "Current database: {}",
+                    privacy::data(database.to_dot_graph())
+                );
                 if Self::should_not_make_introduction(&debuting_agr) {
                     let ip_addr_str = match &debuting_agr.node_addr_opt {
                         Some(node_addr) => node_addr.ip_addr().to_string(),
                         None => "?.?.?.?".to_string(),
                     };
                     debug!(self.logger, "Node {} at {} is responding to first introduction: sending update Gossip instead of further introduction",
-                                              debuting_agr.inner.public_key,
-                                              ip_addr_str);
+                                              privacy::key(&debuting_agr.inner.public_key),
+                                              privacy::ip(&ip_addr_str));
                     Ok(GossipAcceptanceResult::Accepted)
                 } else {
                     match self.make_introduction(database, &debuting_agr, gossip_source) {
@@ -292,8 +297,8 @@ impl DebutHandler {
                             debug!(
                                 self.logger,
                                 "DebutHandler can't make an introduction, but is accepting {} at {} and broadcasting change",
-                                &debut_node_key,
-                                gossip_source,
+                                privacy::key(&debut_node_key),
+                                privacy::socket_addr(gossip_source),
                             );
                             Ok(GossipAcceptanceResult::Accepted)
                         }
@@ -328,10 +333,10 @@ impl DebutHandler {
             debug!(
                 self.logger,
                 "DebutHandler commissioning Introduction of {} at {} to {} at {}",
-                lcn_key,
-                lcn_node_addr_str,
-                &debuting_agr.inner.public_key,
-                debut_node_addr
+                privacy::key(&lcn_key),
+                privacy::ip(&lcn_node_addr_str),
+                privacy::key(&debuting_agr.inner.public_key),
+                privacy::ip(&debut_node_addr)
             );
             Some((
                 GossipBuilder::new(database)
@@ -716,7 +721,7 @@ impl IntroductionHandler {
                     debug!(
                         self.logger,
                         "Updating obsolete introducer {} from version {} to version {}",
-                        introducer_key,
+                        privacy::key(introducer_key),
                         existing_introducer_ref.version(),
                         introducer.inner.version
                     );
@@ -725,7 +730,7 @@ impl IntroductionHandler {
                     debug!(
                         self.logger,
                         "Preserving existing introducer {} at version {}",
-                        introducer_key,
+                        privacy::key(introducer_key),
                         existing_introducer_ref.version()
                     );
                     return Ok(false);
@@ -735,7 +740,8 @@ impl IntroductionHandler {
                 let new_introducer = NodeRecord::from(introducer);
                 debug!(
                     self.logger,
-                    "Adding introducer {} to database", introducer_key
+                    "Adding introducer {} to database",
+                    privacy::key(introducer_key)
                 );
                 database
                     .add_node(new_introducer)
@@ -749,7 +755,11 @@ impl IntroductionHandler {
             database.root_mut().increment_version();
             database.root_mut().regenerate_signed_gossip(cryptde);
         }
-        trace!(self.logger, "Current database: {}", database.to_dot_graph());
+        trace!(
+            self.logger,
+            "Current database: {}",
+            privacy::data(database.to_dot_graph())
+        );
         Ok(true)
     }
 }
@@ -843,7 +853,11 @@ impl GossipHandler for StandardGossipHandler {
         // If no Nodes need updating, return ::Ignored and don't change the database.
         // Otherwise, return ::Accepted.
         if db_changed {
-            trace!(self.logger, "Current database: {}", database.to_dot_graph());
+            trace!(
+                self.logger,
+                "Current database: {}",
+                privacy::data(database.to_dot_graph())
+            );
             GossipAcceptanceResult::Accepted
         } else {
             debug!(
@@ -29,25 +29,39 @@ use crate::sub_lib::dispatcher::{Component, StreamShutdownMsg};
 use crate::sub_lib::hopper::{ExpiredCoresPackage, NoLookupIncipientCoresPackage};
 use crate::sub_lib::hopper::{IncipientCoresPackage, MessageType};
 use crate::sub_lib::logger::Logger;
+use crate::sub_lib::metrics;
+use crate::sub_lib::metrics::{
+    NEIGHBORHOOD_GOSSIP_RECEIVED, NEIGHBORHOOD_GOSSIP_SENT, NEIGHBORHOOD_NODES,
+};
+use crate::sub_lib::neighborhood::DisjointRouteQueryMessage;
 use crate::sub_lib::neighborhood::DispatcherNodeQueryMessage;
 use crate::sub_lib::neighborhood::ExpectedService;
 use crate::sub_lib::neighborhood::ExpectedServices;
 use crate::sub_lib::neighborhood::NeighborhoodDotGraphRequest;
+use crate::sub_lib::neighborhood::NeighborhoodMode;
 use crate::sub_lib::neighborhood::NeighborhoodSubs;
+use crate::sub_lib::neighborhood::NeighborhoodView;
+use crate::sub_lib::neighborhood::NeighborhoodViewRequest;
 use crate::sub_lib::neighborhood::NodeDescriptor;
 use crate::sub_lib::neighborhood::NodeQueryMessage;
 use crate::sub_lib::neighborhood::NodeQueryResponseMetadata;
 use crate::sub_lib::neighborhood::NodeRecordMetadataMessage;
+use crate::sub_lib::neighborhood::NodeRecordView;
 use crate::sub_lib::neighborhood::RemoveNeighborMessage;
 use crate::sub_lib::neighborhood::RouteQueryMessage;
 use crate::sub_lib::neighborhood::RouteQueryResponse;
+use crate::sub_lib::neighborhood::{SetNeighborhoodModeMsg, SetRatePackMsg};
+use crate::sub_lib::neighborhood::{DEFAULT_RATE_PACK, ZERO_RATE_PACK};
 use crate::sub_lib::node_addr::NodeAddr;
 use crate::sub_lib::peer_actors::{BindMessage, StartMessage};
+use crate::sub_lib::privacy;
+use crate::sub_lib::proxy_server::InvalidateRoutesMessage;
 use crate::sub_lib::route::Route;
 use crate::sub_lib::route::RouteSegment;
 use crate::sub_lib::set_consuming_wallet_message::SetConsumingWalletMessage;
+use crate::sub_lib::set_earning_wallet_message::SetEarningWalletMessage;
 use crate::sub_lib::stream_handler_pool::DispatcherNodeQueryResponse;
-use crate::sub_lib::ui_gateway::{UiCarrierMessage, UiMessage};
+use crate::sub_lib::ui_gateway::{Setting, UiCarrierMessage, UiEvent, UiMessage};
 use crate::sub_lib::utils::{node_descriptor_delimiter, NODE_MAILBOX_CAPACITY};
 use crate::sub_lib::wallet::Wallet;
 use actix::Actor;
@@ -63,6 +77,8 @@ use gossip_producer::GossipProducerReal;
 use neighborhood_database::NeighborhoodDatabase;
 use node_record::NodeRecord;
 use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::collections::HashSet;
 use std::convert::TryFrom;
 use std::net::SocketAddr;
 
@@ -70,13 +86,15 @@ pub struct Neighborhood {
     cryptde: &'static dyn CryptDE,
     hopper: Option<Recipient<IncipientCoresPackage>>,
     hopper_no_lookup: Option<Recipient<NoLookupIncipientCoresPackage>>,
-    dot_graph_recipient: Option<Recipient<UiCarrierMessage>>,
+    ui_carrier_message_sub: Option<Recipient<UiCarrierMessage>>,
+    invalidate_routes_recipient: Option<Recipient<InvalidateRoutesMessage>>,
     gossip_acceptor: Box<dyn GossipAcceptor>,
     gossip_producer: Box<dyn GossipProducer>,
     neighborhood_database: NeighborhoodDatabase,
     consuming_wallet_opt: Option<Wallet>,
     next_return_route_id: u32,
     initial_neighbors: Vec<String>,
+    mode: NeighborhoodMode,
     logger: Logger,
     chain_id: u8,
 }
@@ -92,7 +110,8 @@ impl Handler<BindMessage> for Neighborhood {
         ctx.set_mailbox_capacity(NODE_MAILBOX_CAPACITY);
         self.hopper = Some(msg.peer_actors.hopper.from_hopper_client);
         self.hopper_no_lookup = Some(msg.peer_actors.hopper.from_hopper_client_no_lookup);
-        self.dot_graph_recipient = Some(msg.peer_actors.ui_gateway.ui_message_sub)
+        self.ui_carrier_message_sub = Some(msg.peer_actors.ui_gateway.ui_message_sub);
+        self.invalidate_routes_recipient = Some(msg.peer_actors.proxy_server.invalidate_routes);
     }
 }
 
@@ -108,6 +127,11 @@ impl Handler<StartMessage> for Neighborhood {
     type Result = ();
 
     fn handle(&mut self, _msg: StartMessage, _ctx: &mut Self::Context) -> Self::Result {
+        metrics::set(
+            &NEIGHBORHOOD_NODES,
+            &[],
+            self.neighborhood_database.keys().len() as i64,
+        );
         if self.initial_neighbors.is_empty() {
             info!(self.logger, "Empty. No Nodes to report to; continuing");
             return;
@@ -142,10 +166,10 @@ impl Handler<StartMessage> for Neighborhood {
                 trace!(
                     self.logger,
                     "Sent Gossip: {}",
-                    gossip.to_dot_graph(
+                    privacy::data(gossip.to_dot_graph(
                         self.neighborhood_database.root(),
                         (&node_descriptor.public_key, &node_descriptor.node_addr_opt),
-                    )
+                    ))
                 );
             } else {
                 panic!(
@@ -239,19 +263,61 @@ impl Handler<RouteQueryMessage> for Neighborhood {
                 debug!(
                     self.logger,
                     "Processed {} into {}-hop response",
-                    msg_str,
+                    privacy::data(&msg_str),
                     response.route.hops.len(),
                 );
                 Some(response)
             }
             Err(msg) => {
-                error!(self.logger, "Unsatisfied route query: {}", msg);
+                error!(
+                    self.logger,
+                    "Unsatisfied route query: {}",
+                    privacy::data(&msg)
+                );
+                self.report_event(UiEvent::RouteFailed(msg));
                 None
             }
         })
     }
 }
 
+impl Handler<DisjointRouteQueryMessage> for Neighborhood {
+    type Result = MessageResult<DisjointRouteQueryMessage>;
+
+    fn handle(
+        &mut self,
+        msg: DisjointRouteQueryMessage,
+        _ctx: &mut Self::Context,
+    ) -> <Self as Handler<DisjointRouteQueryMessage>>::Result {
+        let msg_str = format!("{:?}", msg);
+        let result = if msg.minimum_hop_count == 0 {
+            Ok(vec![self.zero_hop_route_response()])
+        } else {
+            self.make_disjoint_round_trip_routes(msg)
+        };
+        MessageResult(match result {
+            Ok(responses) => {
+                debug!(
+                    self.logger,
+                    "Processed {} into {} disjoint route(s)",
+                    privacy::data(&msg_str),
+                    responses.len(),
+                );
+                responses
+            }
+            Err(msg) => {
+                error!(
+                    self.logger,
+                    "Unsatisfied disjoint route query: {}",
+                    privacy::data(&msg)
+                );
+                self.report_event(UiEvent::RouteFailed(msg));
+                vec![]
+            }
+        })
+    }
+}
+
 impl Handler<ExpiredCoresPackage<Gossip>> for Neighborhood {
     type Result = ();
 
@@ -271,14 +337,18 @@ impl Handler<RemoveNeighborMessage> for Neighborhood {
 
     fn handle(&mut self, msg: RemoveNeighborMessage, _ctx: &mut Self::Context) -> Self::Result {
         let public_key = &msg.public_key;
+        let views_before = self.neighborhood_database.to_views();
         match self.neighborhood_database.remove_neighbor(public_key) {
             Err(s) => error!(self.logger, "{}", s),
             Ok(db_changed) => {
                 if db_changed {
+                    self.report_database_changes(views_before);
+                    self.invalidate_routes_through(public_key);
                     self.gossip_to_neighbors();
                     info!(
                         self.logger,
-                        "removed neighbor by public key: {}", public_key
+                        "removed neighbor by public key: {}",
+                        privacy::key(public_key)
                     )
                 }
             }
@@ -320,16 +390,132 @@ impl Handler<NeighborhoodDotGraphRequest> for Neighborhood {
             self.logger,
             "acknowledge request for neighborhood dot graph."
         );
-        self.dot_graph_recipient
+        self.ui_carrier_message_sub
             .as_ref()
-            .expect("DOT graph recipient is unbound")
+            .expect("UiGateway is unbound")
             .try_send(UiCarrierMessage {
                 client_id: msg.client_id,
+                context_id: Some(msg.context_id),
                 data: UiMessage::NeighborhoodDotGraphResponse(
                     self.neighborhood_database.to_dot_graph().clone(),
                 ),
             })
-            .expect("DOT graph recipient is dead")
+            .expect("UiGateway is dead")
+    }
+}
+
+impl Handler<NeighborhoodViewRequest> for Neighborhood {
+    type Result = ();
+
+    fn handle(&mut self, msg: NeighborhoodViewRequest, _ctx: &mut Self::Context) -> Self::Result {
+        let view = NeighborhoodView {
+            root_key: self.neighborhood_database.root().public_key().to_string(),
+            earning_wallet: self
+                .neighborhood_database
+                .root()
+                .earning_wallet()
+                .to_string(),
+            consuming_wallet: self
+                .consuming_wallet_opt
+                .as_ref()
+                .map(|wallet| wallet.to_string()),
+            nodes: self
+                .neighborhood_database
+                .to_views()
+                .into_iter()
+                .map(|(_, view)| view)
+                .collect(),
+        };
+        self.ui_carrier_message_sub
+            .as_ref()
+            .expect("UiGateway is unbound")
+            .try_send(UiCarrierMessage {
+                client_id: msg.client_id,
+                context_id: Some(msg.context_id),
+                data: UiMessage::NeighborhoodResponse(view),
+            })
+            .expect("UiGateway is dead")
+    }
+}
+
+impl Handler<SetRatePackMsg> for Neighborhood {
+    type Result = ();
+
+    fn handle(&mut self, msg: SetRatePackMsg, _ctx: &mut Self::Context) -> Self::Result {
+        let result = if self.mode.routes_data() {
+            let views_before = self.neighborhood_database.to_views();
+            if self
+                .neighborhood_database
+                .root_mut()
+                .set_rate_pack(msg.rate_pack.clone())
+            {
+                self.mode = match &self.mode {
+                    NeighborhoodMode::Standard(node_addr, neighbor_configs, _) => {
+                        NeighborhoodMode::Standard(
+                            node_addr.clone(),
+                            neighbor_configs.clone(),
+                            msg.rate_pack.clone(),
+                        )
+                    }
+                    NeighborhoodMode::OriginateOnly(neighbor_configs, _) => {
+                        NeighborhoodMode::OriginateOnly(neighbor_configs.clone(), msg.rate_pack)
+                    }
+                    other => other.clone(),
+                };
+                self.republish_root(views_before);
+            }
+            Ok(())
+        } else {
+            Err(format!(
+                "Node has no rate pack to change in --neighborhood-mode {}",
+                self.mode_name()
+            ))
+        };
+        self.report_setting_result(msg.client_id, msg.context_id, Setting::RatePack, result);
+    }
+}
+
+impl Handler<SetNeighborhoodModeMsg> for Neighborhood {
+    type Result = ();
+
+    fn handle(&mut self, msg: SetNeighborhoodModeMsg, _ctx: &mut Self::Context) -> Self::Result {
+        let result = match self.mode_from_name(&msg.mode) {
+            Err(e) => Err(e),
+            Ok(mode) => {
+                let views_before = self.neighborhood_database.to_views();
+                let root = self.neighborhood_database.root_mut();
+                let accepts_connections_changed =
+                    root.set_accepts_connections(mode.accepts_connections());
+                let routes_data_changed = root.set_routes_data(mode.routes_data());
+                let rate_pack_changed = root.set_rate_pack(mode.rate_pack().clone());
+                self.mode = mode;
+                if accepts_connections_changed || routes_data_changed || rate_pack_changed {
+                    self.republish_root(views_before);
+                }
+                Ok(())
+            }
+        };
+        self.report_setting_result(
+            msg.client_id,
+            msg.context_id,
+            Setting::NeighborhoodMode,
+            result,
+        );
+    }
+}
+
+impl Handler<SetEarningWalletMessage> for Neighborhood {
+    type Result = ();
+
+    fn handle(&mut self, msg: SetEarningWalletMessage, _ctx: &mut Self::Context) -> Self::Result {
+        let views_before = self.neighborhood_database.to_views();
+        if self
+            .neighborhood_database
+            .root_mut()
+            .set_earning_wallet(msg.wallet)
+        {
+            self.republish_root(views_before);
+        }
     }
 }
 
@@ -381,24 +567,34 @@ impl Neighborhood {
         }
         let gossip_acceptor: Box<dyn GossipAcceptor> = Box::new(GossipAcceptorReal::new(cryptde));
         let gossip_producer = Box::new(GossipProducerReal::new());
-        let neighborhood_database = NeighborhoodDatabase::new(
+        let mut neighborhood_database = NeighborhoodDatabase::new(
             &cryptde.public_key(),
             neighborhood_config.mode.clone(),
             config.earning_wallet.clone(),
             cryptde,
         );
+        if neighborhood_database
+            .root_mut()
+            .set_exit_policy(&config.exit_policy)
+        {
+            neighborhood_database
+                .root_mut()
+                .regenerate_signed_gossip(cryptde);
+        }
 
         Neighborhood {
             cryptde,
             hopper: None,
             hopper_no_lookup: None,
-            dot_graph_recipient: None,
+            ui_carrier_message_sub: None,
+            invalidate_routes_recipient: None,
             gossip_acceptor,
             gossip_producer,
             neighborhood_database,
             consuming_wallet_opt: config.consuming_wallet.clone(),
             next_return_route_id: 0,
             initial_neighbors: neighborhood_config.mode.neighbor_configs().clone(),
+            mode: neighborhood_config.mode.clone(),
             logger: Logger::new("Neighborhood"),
             chain_id: config.blockchain_bridge_config.chain_id,
         }
@@ -410,6 +606,7 @@ impl Neighborhood {
             start: addr.clone().recipient::<StartMessage>(),
             node_query: addr.clone().recipient::<NodeQueryMessage>(),
             route_query: addr.clone().recipient::<RouteQueryMessage>(),
+            disjoint_route_query: addr.clone().recipient::<DisjointRouteQueryMessage>(),
             update_node_record_metadata: addr.clone().recipient::<NodeRecordMetadataMessage>(),
             from_hopper: addr.clone().recipient::<ExpiredCoresPackage<Gossip>>(),
             dispatcher_node_query: addr.clone().recipient::<DispatcherNodeQueryMessage>(),
@@ -417,6 +614,10 @@ impl Neighborhood {
             stream_shutdown_sub: addr.clone().recipient::<StreamShutdownMsg>(),
             set_consuming_wallet_sub: addr.clone().recipient::<SetConsumingWalletMessage>(),
             from_ui_gateway: addr.clone().recipient::<NeighborhoodDotGraphRequest>(),
+            neighborhood_view_sub: addr.clone().recipient::<NeighborhoodViewRequest>(),
+            set_rate_pack_sub: addr.clone().recipient::<SetRatePackMsg>(),
+            set_neighborhood_mode_sub: addr.clone().recipient::<SetNeighborhoodModeMsg>(),
+            set_earning_wallet_sub: addr.clone().recipient::<SetEarningWalletMessage>(),
         }
     }
 
@@ -428,11 +629,12 @@ impl Neighborhood {
         trace!(
             self.logger,
             "Received Gossip: {}",
-            incoming_gossip.to_dot_graph(source, self.neighborhood_database.root())
+            privacy::data(incoming_gossip.to_dot_graph(source, self.neighborhood_database.root()))
         );
     }
 
     fn handle_gossip(&mut self, incoming_gossip: Gossip, gossip_source: SocketAddr) {
+        metrics::increment(&NEIGHBORHOOD_GOSSIP_RECEIVED, &[], 1);
         info!(
             self.logger,
             "Processing Gossip about {} Nodes",
@@ -450,7 +652,8 @@ impl Neighborhood {
             // TODO: Instead of ignoring non-deserializable Gossip, ban the Node that sent it
             error!(
                 self.logger,
-                "Received non-deserializable Gossip from {}", gossip_source
+                "Received non-deserializable Gossip from {}",
+                privacy::socket_addr(gossip_source)
             );
             self.announce_gossip_handling_completion(record_count);
             return;
@@ -467,7 +670,8 @@ impl Neighborhood {
             // TODO: Instead of ignoring badly-signed Gossip, ban the Node that sent it
             error!(
                 self.logger,
-                "Received Gossip with invalid signature from {}", gossip_source
+                "Received Gossip with invalid signature from {}",
+                privacy::socket_addr(gossip_source)
             );
             self.announce_gossip_handling_completion(record_count);
             return;
@@ -480,20 +684,26 @@ impl Neighborhood {
     fn handle_agrs(&mut self, agrs: Vec<AccessibleGossipRecord>, gossip_source: SocketAddr) {
         let ignored_node_name = self.gossip_source_name(&agrs, gossip_source);
         let gossip_record_count = agrs.len();
+        let views_before = self.neighborhood_database.to_views();
         let acceptance_result =
             self.gossip_acceptor
                 .handle(&mut self.neighborhood_database, agrs, gossip_source);
+        self.report_database_changes(views_before);
         match acceptance_result {
             GossipAcceptanceResult::Accepted => self.gossip_to_neighbors(),
             GossipAcceptanceResult::Reply(next_debut, relay_target, relay_node_addr) => {
                 self.handle_gossip_reply(next_debut, relay_target, relay_node_addr)
             }
             GossipAcceptanceResult::Ignored => {
-                trace!(self.logger, "Gossip from {} ignored", gossip_source);
+                trace!(
+                    self.logger,
+                    "Gossip from {} ignored",
+                    privacy::socket_addr(gossip_source)
+                );
                 self.handle_gossip_ignored(ignored_node_name, gossip_record_count)
             }
             GossipAcceptanceResult::Ban(reason) => {
-                warning!(self.logger, "Malefactor detected at {}, but malefactor bans not yet implemented; ignoring: {}", gossip_source, reason
+                warning!(self.logger, "Malefactor detected at {}, but malefactor bans not yet implemented; ignoring: {}", privacy::socket_addr(gossip_source), privacy::data(&reason)
             );
                 self.handle_gossip_ignored(ignored_node_name, gossip_record_count);
             }
@@ -523,26 +733,42 @@ impl Neighborhood {
                     .expect("Key magically disappeared");
             info!(
                 self.logger,
-                "Sending update Gossip about {} Nodes to Node {}", gossip_len, neighbor
+                {public_key: privacy::key(neighbor)},
+                "Sending update Gossip about {} Nodes to Node {}",
+                gossip_len,
+                privacy::key(neighbor)
             );
             self.hopper
                 .as_ref()
                 .expect("unbound hopper")
                 .try_send(package)
                 .expect("hopper is dead");
+            metrics::increment(&NEIGHBORHOOD_GOSSIP_SENT, &[], 1);
             trace!(
                 self.logger,
                 "Sent Gossip: {}",
-                gossip.to_dot_graph(
-                    self.neighborhood_database.root(),
-                    self.neighborhood_database
-                        .node_by_key(*neighbor)
-                        .expect("Node magically disappeared"),
+                privacy::data(
+                    gossip.to_dot_graph(
+                        self.neighborhood_database.root(),
+                        self.neighborhood_database
+                            .node_by_key(*neighbor)
+                            .expect("Node magically disappeared"),
+                    )
                 )
             );
         });
     }
 
+    fn invalidate_routes_through(&self, public_key: &PublicKey) {
+        self.invalidate_routes_recipient
+            .as_ref()
+            .expect("unbound ProxyServer")
+            .try_send(InvalidateRoutesMessage {
+                public_key: public_key.clone(),
+            })
+            .expect("ProxyServer is dead");
+    }
+
     fn create_single_hop_route(&self, destination: &PublicKey) -> Route {
         Route::one_way(
             RouteSegment::new(
@@ -593,19 +819,112 @@ impl Neighborhood {
             msg.minimum_hop_count,
             msg.target_component,
             RouteDirection::Over,
+            (
+                msg.target_hostname_opt.as_ref().map(String::as_str),
+                msg.target_port_opt,
+            ),
         )?;
-        debug!(self.logger, "Route over: {:?}", over);
+        debug!(self.logger, "Route over: {:?}", privacy::data(&over));
         let back = self.make_route_segment(
             over.keys.last().expect("Empty segment"),
             Some(&self.cryptde.public_key()),
             msg.minimum_hop_count,
             msg.return_component_opt.expect("No return component"),
             RouteDirection::Back,
+            (None, None),
         )?;
-        debug!(self.logger, "Route back: {:?}", back);
+        debug!(self.logger, "Route back: {:?}", privacy::data(&back));
         self.compose_route_query_response(over, back)
     }
 
+    // Every route shares the exit Node of the most desirable over route, so that the exit can
+    // put the stream back together; only the relays in between are kept disjoint.
+    fn make_disjoint_round_trip_routes(
+        &mut self,
+        msg: DisjointRouteQueryMessage,
+    ) -> Result<Vec<RouteQueryResponse>, String> {
+        let minimum_hop_count = msg.minimum_hop_count;
+        let destination = (
+            msg.target_hostname_opt.as_ref().map(String::as_str),
+            msg.target_port_opt,
+        );
+        let origin_key = self.cryptde.public_key().clone();
+        let segment_pairs: Vec<(RouteSegment, RouteSegment)> = {
+            let over_seqs = self.complete_routes(
+                vec![&origin_key],
+                msg.target_key_opt.as_ref(),
+                minimum_hop_count,
+                RouteDirection::Over,
+            );
+            if over_seqs.is_empty() {
+                return Err(format!(
+                    "Couldn't find any routes: at least {}-hop from {} to ProxyClient at Unknown",
+                    minimum_hop_count, origin_key
+                ));
+            }
+            let mut over_seqs = self.routes_to_willing_exits(over_seqs, destination);
+            if over_seqs.is_empty() {
+                return Err(Self::no_willing_exit_message(
+                    minimum_hop_count,
+                    &origin_key,
+                    destination,
+                ));
+            }
+            self.sort_routes_by_desirable_exit_nodes(over_seqs.as_mut());
+            let exit_key = (*over_seqs[0].last().expect("Empty route")).clone();
+            let over_seqs: Vec<Vec<&PublicKey>> = over_seqs
+                .into_iter()
+                .filter(|seq| seq.last() == Some(&&exit_key))
+                .collect();
+            let back_seqs = self.complete_routes(
+                vec![&exit_key],
+                Some(&origin_key),
+                minimum_hop_count,
+                RouteDirection::Back,
+            );
+            Self::pick_disjoint_routes(over_seqs, msg.path_count)
+                .into_iter()
+                .zip(Self::pick_disjoint_routes(back_seqs, msg.path_count).into_iter())
+                .map(|(over, back)| {
+                    (
+                        RouteSegment::new(over, Component::ProxyClient),
+                        RouteSegment::new(back, Component::ProxyServer),
+                    )
+                })
+                .collect()
+        };
+        if segment_pairs.is_empty() {
+            return Err(format!(
+                "Couldn't find any routes: at least {}-hop from exit back to {}",
+                minimum_hop_count, origin_key
+            ));
+        }
+        segment_pairs
+            .into_iter()
+            .map(|(over, back)| self.compose_route_query_response(over, back))
+            .collect()
+    }
+
+    fn pick_disjoint_routes<'a>(
+        node_seqs: Vec<Vec<&'a PublicKey>>,
+        path_count: usize,
+    ) -> Vec<Vec<&'a PublicKey>> {
+        let mut relays_in_use: HashSet<&PublicKey> = HashSet::new();
+        let mut chosen = vec![];
+        for node_seq in node_seqs {
+            if chosen.len() >= path_count {
+                break;
+            }
+            let relays = &node_seq[1..(node_seq.len() - 1)];
+            if relays.iter().any(|key| relays_in_use.contains(key)) {
+                continue;
+            }
+            relays_in_use.extend(relays.iter());
+            chosen.push(node_seq);
+        }
+        chosen
+    }
+
     fn compose_route_query_response(
         &mut self,
         over: RouteSegment,
@@ -658,11 +977,23 @@ impl Neighborhood {
         minimum_hop_count: usize,
         target_component: Component,
         direction: RouteDirection,
+        destination: (Option<&str>, Option<u16>),
     ) -> Result<RouteSegment, String> {
-        let mut node_seqs =
-            self.complete_routes(vec![origin], target, minimum_hop_count, direction);
+        let node_seqs = self.complete_routes(vec![origin], target, minimum_hop_count, direction);
+        let found_any_routes = !node_seqs.is_empty();
+        let mut node_seqs = match direction {
+            RouteDirection::Over => self.routes_to_willing_exits(node_seqs, destination),
+            RouteDirection::Back => node_seqs,
+        };
 
         if node_seqs.is_empty() {
+            if found_any_routes {
+                return Err(Self::no_willing_exit_message(
+                    minimum_hop_count,
+                    origin,
+                    destination,
+                ));
+            }
             let target_str = match target {
                 Some(t) => format!(" {}", t),
                 None => String::from("Unknown"),
@@ -681,6 +1012,43 @@ impl Neighborhood {
         }
     }
 
+    // Drops the routes whose exit Node advertises an exit policy that would certainly refuse
+    // to connect to the destination.
+    fn routes_to_willing_exits<'a>(
+        &self,
+        node_seqs: Vec<Vec<&'a PublicKey>>,
+        destination: (Option<&str>, Option<u16>),
+    ) -> Vec<Vec<&'a PublicKey>> {
+        let (hostname_opt, port_opt) = destination;
+        node_seqs
+            .into_iter()
+            .filter(|node_seq| {
+                let exit_key = node_seq.last().expect("Empty route");
+                match self.neighborhood_database.node_by_key(exit_key) {
+                    Some(exit_node) => exit_node.exit_policy().may_accept(hostname_opt, port_opt),
+                    None => false,
+                }
+            })
+            .collect()
+    }
+
+    fn no_willing_exit_message(
+        minimum_hop_count: usize,
+        origin: &PublicKey,
+        destination: (Option<&str>, Option<u16>),
+    ) -> String {
+        let destination_str = match destination {
+            (Some(hostname), Some(port)) => format!("{}:{}", hostname, port),
+            (Some(hostname), None) => hostname.to_string(),
+            (None, Some(port)) => format!("port {}", port),
+            (None, None) => "its destination".to_string(),
+        };
+        format!(
+            "Couldn't find any routes: no exit Node at least {}-hop from {} has an exit policy that accepts {}",
+            minimum_hop_count, origin, destination_str
+        )
+    }
+
     fn sort_routes_by_desirable_exit_nodes(&self, node_seqs: &mut Vec<Vec<&PublicKey>>) {
         if node_seqs.is_empty() {
             panic!("Unable to sort routes by desirable exit nodes: Missing routes.");
@@ -904,13 +1272,14 @@ impl Neighborhood {
             .expect("No-lookup Hopper is unbound")
             .try_send(package)
             .expect("Hopper is dead");
+        metrics::increment(&NEIGHBORHOOD_GOSSIP_SENT, &[], 1);
         trace!(
             self.logger,
             "Sent Gossip: {}",
-            gossip.to_dot_graph(
+            privacy::data(gossip.to_dot_graph(
                 self.neighborhood_database.root(),
                 (&target_key, &Some(target_node_addr)),
-            )
+            ))
         );
     }
 
@@ -937,7 +1306,7 @@ impl Neighborhood {
         }
         let neighbor_key = match self.neighborhood_database.node_by_ip(&msg.peer_addr.ip()) {
             None => {
-                warning!(self.logger, "Received shutdown notification for stream to {}, but no Node with that IP is in the database - ignoring", msg.peer_addr.ip());
+                warning!(self.logger, "Received shutdown notification for stream to {}, but no Node with that IP is in the database - ignoring", privacy::ip(msg.peer_addr.ip()));
                 return;
             }
             Some(n) => (n.public_key().clone()),
@@ -945,20 +1314,147 @@ impl Neighborhood {
         self.remove_neighbor(&neighbor_key, &msg.peer_addr);
     }
 
+    fn report_database_changes(&self, views_before: BTreeMap<PublicKey, NodeRecordView>) {
+        let views_after = self.neighborhood_database.to_views();
+        metrics::set(&NEIGHBORHOOD_NODES, &[], views_after.len() as i64);
+        let root_key = self.neighborhood_database.root().public_key();
+        let neighbors = |views: &BTreeMap<PublicKey, NodeRecordView>| {
+            views
+                .get(root_key)
+                .map(|view| {
+                    view.half_neighbors
+                        .iter()
+                        .cloned()
+                        .collect::<HashSet<String>>()
+                })
+                .unwrap_or_default()
+        };
+        let neighbors_before = neighbors(&views_before);
+        let neighbors_after = neighbors(&views_after);
+        neighbors_after
+            .difference(&neighbors_before)
+            .for_each(|key| self.report_event(UiEvent::NeighborAdded(key.clone())));
+        neighbors_before
+            .difference(&neighbors_after)
+            .for_each(|key| self.report_event(UiEvent::NeighborRemoved(key.clone())));
+        let updated = views_after
+            .iter()
+            .filter(|(key, view)| views_before.get(*key) != Some(*view))
+            .map(|(_, view)| view.clone())
+            .collect::<Vec<NodeRecordView>>();
+        let removed = views_before
+            .keys()
+            .filter(|key| !views_after.contains_key(*key))
+            .map(|key| key.to_string())
+            .collect::<Vec<String>>();
+        if !updated.is_empty() || !removed.is_empty() {
+            self.report_event(UiEvent::NeighborhoodChanged { updated, removed });
+        }
+    }
+
+    // Lets the rest of the Neighborhood and any UIs know that the root NodeRecord has changed
+    fn republish_root(&mut self, views_before: BTreeMap<PublicKey, NodeRecordView>) {
+        self.neighborhood_database.root_mut().increment_version();
+        self.gossip_to_neighbors();
+        self.report_database_changes(views_before);
+    }
+
+    // A zero-hop Node has no Neighborhood to tell about a mode change, and a decentralized Node has
+    // no way to become zero-hop without being restarted.
+    fn mode_from_name(&self, name: &str) -> Result<NeighborhoodMode, String> {
+        let neighbor_configs = self.mode.neighbor_configs().clone();
+        let rate_pack = match self.neighborhood_database.root().rate_pack() {
+            rate_pack if rate_pack == &ZERO_RATE_PACK => DEFAULT_RATE_PACK,
+            rate_pack => rate_pack.clone(),
+        };
+        if self.mode.is_zero_hop() || name == "zero-hop" {
+            return Err(
+                "Node cannot switch into or out of --neighborhood-mode zero-hop while running"
+                    .to_string(),
+            );
+        }
+        match name {
+            "standard" => match self.neighborhood_database.root().node_addr_opt() {
+                Some(node_addr) => Ok(NeighborhoodMode::Standard(
+                    node_addr,
+                    neighbor_configs,
+                    rate_pack,
+                )),
+                None => Err(
+                    "Node cannot run as --neighborhood-mode standard without --ip specified"
+                        .to_string(),
+                ),
+            },
+            "originate-only" if neighbor_configs.is_empty() => Err(
+                "Node cannot run as --neighborhood-mode originate-only without --neighbors specified"
+                    .to_string(),
+            ),
+            "originate-only" => Ok(NeighborhoodMode::OriginateOnly(neighbor_configs, rate_pack)),
+            "consume-only" if neighbor_configs.is_empty() => Err(
+                "Node cannot run as --neighborhood-mode consume-only without --neighbors specified"
+                    .to_string(),
+            ),
+            "consume-only" => Ok(NeighborhoodMode::ConsumeOnly(neighbor_configs)),
+            _ => Err(format!("Invalid neighborhood mode '{}'", name)),
+        }
+    }
+
+    fn mode_name(&self) -> &'static str {
+        match self.mode {
+            NeighborhoodMode::Standard(_, _, _) => "standard",
+            NeighborhoodMode::ZeroHop => "zero-hop",
+            NeighborhoodMode::OriginateOnly(_, _) => "originate-only",
+            NeighborhoodMode::ConsumeOnly(_) => "consume-only",
+        }
+    }
+
+    fn report_setting_result(
+        &self,
+        client_id: u64,
+        context_id: u64,
+        setting: Setting,
+        result: Result<(), String>,
+    ) {
+        if let Err(e) = &result {
+            warning!(self.logger, "Refused to change {:?}: {}", setting, e);
+        }
+        self.ui_carrier_message_sub
+            .as_ref()
+            .expect("UiGateway is unbound")
+            .try_send(UiCarrierMessage {
+                client_id,
+                context_id: Some(context_id),
+                data: UiMessage::SetConfigurationResponse(setting, result),
+            })
+            .expect("UiGateway is dead")
+    }
+
+    // Events are only for UIs, so there's nothing to do if the UiGateway isn't around to hear them
+    fn report_event(&self, event: UiEvent) {
+        if let Some(sub) = self.ui_carrier_message_sub.as_ref() {
+            sub.try_send(UiCarrierMessage::event(event))
+                .expect("UiGateway is dead")
+        }
+    }
+
     fn remove_neighbor(&mut self, neighbor_key: &PublicKey, peer_addr: &SocketAddr) {
+        let views_before = self.neighborhood_database.to_views();
         match self.neighborhood_database.remove_neighbor(neighbor_key) {
             Err(_) => panic!("Node suddenly disappeared"),
             Ok(true) => {
                 debug!(
                     self.logger,
+                    {public_key: privacy::key(neighbor_key)},
                     "Received shutdown notification for {} at {}: removing neighborship",
-                    neighbor_key,
-                    peer_addr.ip()
+                    privacy::key(neighbor_key),
+                    privacy::ip(peer_addr.ip())
                 );
+                self.report_database_changes(views_before);
+                self.invalidate_routes_through(neighbor_key);
                 self.gossip_to_neighbors()
             }
             Ok(false) => {
-                debug!(self.logger, "Received shutdown notification for {} at {}, but that Node is no neighbor - ignoring", neighbor_key, peer_addr.ip());
+                debug!(self.logger, "Received shutdown notification for {} at {}, but that Node is no neighbor - ignoring", privacy::key(neighbor_key), privacy::ip(peer_addr.ip()));
             }
         };
     }
@@ -990,6 +1486,7 @@ mod tests {
     use crate::sub_lib::cryptde::{decodex, encodex, CryptData};
     use crate::sub_lib::cryptde_null::CryptDENull;
     use crate::sub_lib::dispatcher::Endpoint;
+    use crate::sub_lib::exit_policy::{ExitPolicy, ExitRule};
     use crate::sub_lib::hop::LiveHop;
     use crate::sub_lib::hopper::MessageType;
     use crate::sub_lib::neighborhood::{ExpectedServices, NeighborhoodMode};
@@ -2413,6 +2910,50 @@ mod tests {
         tlh.exists_log_containing(&format!("Sent Gossip: digraph db {{ \"src\" [label=\"Gossip From:\\n{}\\n5.5.5.5\"]; \"dest\" [label=\"Gossip To:\\nAgMEBQ\\n2.3.4.5\"]; \"src\" -> \"dest\" [arrowhead=empty]; }}", &key_as_str[..8]));
     }
 
+    #[test]
+    fn neighborhood_logs_no_keys_ips_or_dot_graphs_in_privacy_logging_mode() {
+        init_test_logging();
+        privacy::set_privacy_logging(true);
+        let subject_node = make_global_cryptde_node_record(5555, true);
+        let neighbor = make_node_record(1000, true);
+        let mut subject = neighborhood_from_nodes(&subject_node, Some(&neighbor));
+        let full_neighbor = make_node_record(7193, true);
+        subject
+            .neighborhood_database
+            .add_node(full_neighbor.clone())
+            .unwrap();
+        subject
+            .neighborhood_database
+            .add_arbitrary_full_neighbor(subject_node.public_key(), full_neighbor.public_key());
+        subject.gossip_acceptor =
+            Box::new(GossipAcceptorMock::new().handle_result(GossipAcceptanceResult::Accepted));
+        subject.gossip_producer =
+            Box::new(GossipProducerMock::new().produce_result(Gossip::new(vec![])));
+        let (hopper, _, _) = make_recorder();
+        let peer_actors = peer_actors_builder().hopper(hopper).build();
+        let system =
+            System::new("neighborhood_logs_no_keys_ips_or_dot_graphs_in_privacy_logging_mode");
+        subject.hopper = Some(peer_actors.hopper.from_hopper_client);
+
+        subject.handle_gossip(
+            Gossip::new(vec![]),
+            SocketAddr::from_str("7.1.9.3:7193").unwrap(),
+        );
+
+        System::current().stop();
+        system.run();
+        let key_token = privacy::key(full_neighbor.public_key()).to_string();
+        privacy::set_privacy_logging(false);
+        let tlh = TestLogHandler::new();
+        tlh.exists_log_containing(&format!(
+            "INFO: Neighborhood: Sending update Gossip about 0 Nodes to Node {}",
+            key_token
+        ));
+        tlh.exists_log_containing("Sent Gossip: <redacted data>");
+        tlh.exists_no_log_containing(&full_neighbor.public_key().to_string());
+        tlh.exists_no_log_containing("7.1.9.3");
+    }
+
     #[test]
     fn neighborhood_sends_only_relay_gossip_when_gossip_acceptor_relays() {
         let subject_node = make_global_cryptde_node_record(5555, true); // 9e7p7un06eHs6frl5A
@@ -2764,6 +3305,8 @@ mod tests {
             target_component: Component::ProxyClient,
             minimum_hop_count: 3,
             return_component_opt: None,
+            target_hostname_opt: None,
+            target_port_opt: None,
         };
         let unsuccessful_three_hop_route = addr.send(three_hop_route_request);
         let public_key_query = addr.send(NodeQueryMessage::PublicKey(a.public_key().clone()));
@@ -2781,57 +3324,457 @@ mod tests {
         assert_eq!(None, failed_ip_address_query.wait().unwrap());
     }
 
-    fn node_record_to_neighbor_config(
-        node_record_ref: &NodeRecord,
-        cryptde: &dyn CryptDE,
-    ) -> String {
-        NodeDescriptor::from((
-            &node_record_ref.public_key().clone(),
-            &node_record_ref.node_addr_opt().unwrap().clone(),
-        ))
-        .to_string(cryptde, DEFAULT_CHAIN_ID)
-    }
-
     #[test]
-    fn neighborhood_sends_node_query_response_with_none_when_initially_configured_with_no_data() {
-        let cryptde = cryptde();
-        let (recorder, awaiter, recording_arc) = make_recorder();
-        thread::spawn(move || {
-            let system = System::new("responds_with_none_when_initially_configured_with_no_data");
-
-            let addr: Addr<Recorder> = recorder.start();
-            let recipient: Recipient<DispatcherNodeQueryResponse> =
-                addr.recipient::<DispatcherNodeQueryResponse>();
-
-            let subject = make_standard_subject();
-            let addr: Addr<Neighborhood> = subject.start();
-            let sub: Recipient<DispatcherNodeQueryMessage> =
-                addr.recipient::<DispatcherNodeQueryMessage>();
-
-            sub.try_send(DispatcherNodeQueryMessage {
-                query: NodeQueryMessage::PublicKey(PublicKey::new(&b"booga"[..])),
-                context: TransmitDataMsg {
-                    endpoint: Endpoint::Key(cryptde.public_key().clone()),
-                    last_data: false,
-                    sequence_number: None,
-                    data: Vec::new(),
-                },
-                recipient,
-            })
-            .unwrap();
+    fn neighborhood_tells_uis_about_removed_neighbors_and_failed_routes() {
+        let system =
+            System::new("neighborhood_tells_uis_about_removed_neighbors_and_failed_routes");
+        let (ui_gateway, _, ui_gateway_recording_arc) = make_recorder();
+        let mut subject = make_standard_subject();
+        let n = &subject.neighborhood_database.root().clone();
+        let a = &make_node_record(3456, true);
+        subject.neighborhood_database.add_node(a.clone()).unwrap();
+        subject
+            .neighborhood_database
+            .add_arbitrary_half_neighbor(n.public_key(), a.public_key());
+        let addr: Addr<Neighborhood> = subject.start();
+        let peer_actors = peer_actors_builder().ui_gateway(ui_gateway).build();
+        addr.try_send(BindMessage { peer_actors }).unwrap();
 
-            system.run();
-        });
+        addr.try_send(RemoveNeighborMessage {
+            public_key: a.public_key().clone(),
+        })
+        .unwrap();
+        let route_request = addr.send(RouteQueryMessage::data_indefinite_route_request(3));
 
-        awaiter.await_message_count(1);
-        let recording = recording_arc.lock().unwrap();
-        assert_eq!(recording.len(), 1);
-        let message = recording.get_record::<DispatcherNodeQueryResponse>(0);
-        assert_eq!(message.result, None);
+        System::current().stop_with_code(0);
+        system.run();
+        assert_eq!(None, route_request.wait().unwrap());
+        let ui_gateway_recording = ui_gateway_recording_arc.lock().unwrap();
+        assert_eq!(
+            ui_gateway_recording.get_record::<UiCarrierMessage>(0),
+            &UiCarrierMessage::event(UiEvent::NeighborRemoved(a.public_key().to_string()))
+        );
+        match &ui_gateway_recording.get_record::<UiCarrierMessage>(1).data {
+            UiMessage::Event(UiEvent::NeighborhoodChanged { updated, removed }) => {
+                assert_eq!(updated.len(), 1);
+                assert_eq!(updated[0].public_key, n.public_key().to_string());
+                assert_eq!(updated[0].half_neighbors, Vec::<String>::new());
+                assert_eq!(removed, &Vec::<String>::new());
+            }
+            x => panic!("Expected a NeighborhoodChanged event, got {:?}", x),
+        }
+        match &ui_gateway_recording.get_record::<UiCarrierMessage>(2).data {
+            UiMessage::Event(UiEvent::RouteFailed(_)) => (),
+            x => panic!("Expected a RouteFailed event, got {:?}", x),
+        }
+        assert_eq!(ui_gateway_recording.len(), 3);
     }
 
     #[test]
-    fn neighborhood_sends_node_query_response_with_none_when_key_query_matches_no_configured_data()
+    fn neighborhood_tells_uis_about_database_changes() {
+        let system = System::new("neighborhood_tells_uis_about_database_changes");
+        let (ui_gateway, _, ui_gateway_recording_arc) = make_recorder();
+        let mut subject = make_standard_subject();
+        subject.ui_carrier_message_sub = Some(ui_gateway.start().recipient::<UiCarrierMessage>());
+        let root_key = subject.neighborhood_database.root().public_key().clone();
+        let staying = make_node_record(3456, true);
+        let leaving = make_node_record(4567, true);
+        let arriving = make_node_record(5678, true);
+        let gone = make_node_record(6789, true);
+        {
+            let db = &mut subject.neighborhood_database;
+            db.add_node(staying.clone()).unwrap();
+            db.add_node(leaving.clone()).unwrap();
+            db.add_node(arriving.clone()).unwrap();
+            db.add_arbitrary_half_neighbor(&root_key, staying.public_key());
+            db.add_arbitrary_half_neighbor(&root_key, leaving.public_key());
+        }
+        let mut views_before = subject.neighborhood_database.to_views();
+        views_before.insert(
+            gone.public_key().clone(),
+            gone.to_view(&subject.neighborhood_database),
+        );
+        subject
+            .neighborhood_database
+            .add_arbitrary_half_neighbor(&root_key, arriving.public_key());
+        subject
+            .neighborhood_database
+            .root_mut()
+            .remove_half_neighbor_key(leaving.public_key());
+
+        subject.report_database_changes(views_before);
+
+        System::current().stop();
+        system.run();
+        let root_view = subject
+            .neighborhood_database
+            .root()
+            .to_view(&subject.neighborhood_database);
+        let ui_gateway_recording = ui_gateway_recording_arc.lock().unwrap();
+        assert_eq!(
+            ui_gateway_recording.get_record::<UiCarrierMessage>(0),
+            &UiCarrierMessage::event(UiEvent::NeighborAdded(arriving.public_key().to_string()))
+        );
+        assert_eq!(
+            ui_gateway_recording.get_record::<UiCarrierMessage>(1),
+            &UiCarrierMessage::event(UiEvent::NeighborRemoved(leaving.public_key().to_string()))
+        );
+        assert_eq!(
+            ui_gateway_recording.get_record::<UiCarrierMessage>(2),
+            &UiCarrierMessage::event(UiEvent::NeighborhoodChanged {
+                updated: vec![root_view],
+                removed: vec![gone.public_key().to_string()],
+            })
+        );
+        assert_eq!(ui_gateway_recording.len(), 3);
+    }
+
+    #[test]
+    fn neighborhood_view_request_is_answered_with_the_whole_database() {
+        let system = System::new("neighborhood_view_request_is_answered_with_the_whole_database");
+        let (ui_gateway, _, ui_gateway_recording_arc) = make_recorder();
+        let mut subject = make_standard_subject();
+        let root = subject.neighborhood_database.root().clone();
+        let neighbor = make_node_record(3456, true);
+        subject
+            .neighborhood_database
+            .add_node(neighbor.clone())
+            .unwrap();
+        subject
+            .neighborhood_database
+            .add_arbitrary_full_neighbor(root.public_key(), neighbor.public_key());
+        let expected_nodes = subject
+            .neighborhood_database
+            .to_views()
+            .into_iter()
+            .map(|(_, view)| view)
+            .collect::<Vec<NodeRecordView>>();
+        let expected_consuming_wallet = subject
+            .consuming_wallet_opt
+            .as_ref()
+            .map(|wallet| wallet.to_string());
+        let addr: Addr<Neighborhood> = subject.start();
+        let peer_actors = peer_actors_builder().ui_gateway(ui_gateway).build();
+        addr.try_send(BindMessage { peer_actors }).unwrap();
+
+        addr.try_send(NeighborhoodViewRequest {
+            client_id: 1234,
+            context_id: 4321,
+        })
+        .unwrap();
+
+        System::current().stop();
+        system.run();
+        let ui_gateway_recording = ui_gateway_recording_arc.lock().unwrap();
+        assert_eq!(
+            ui_gateway_recording.get_record::<UiCarrierMessage>(0),
+            &UiCarrierMessage {
+                client_id: 1234,
+                context_id: Some(4321),
+                data: UiMessage::NeighborhoodResponse(NeighborhoodView {
+                    root_key: root.public_key().to_string(),
+                    earning_wallet: root.earning_wallet().to_string(),
+                    consuming_wallet: expected_consuming_wallet,
+                    nodes: expected_nodes,
+                }),
+            }
+        );
+        assert_eq!(ui_gateway_recording.len(), 1);
+    }
+
+    fn make_subject_with_one_neighbor() -> (NodeRecord, Neighborhood) {
+        let mut subject = make_standard_subject();
+        let root_key = subject.neighborhood_database.root().public_key().clone();
+        let neighbor = make_node_record(3456, true);
+        subject
+            .neighborhood_database
+            .add_node(neighbor.clone())
+            .unwrap();
+        subject
+            .neighborhood_database
+            .add_arbitrary_full_neighbor(&root_key, neighbor.public_key());
+        (neighbor, subject)
+    }
+
+    #[test]
+    fn set_rate_pack_changes_the_root_gossips_and_acknowledges() {
+        let system = System::new("set_rate_pack_changes_the_root_gossips_and_acknowledges");
+        let (hopper, _, hopper_recording_arc) = make_recorder();
+        let (ui_gateway, _, ui_gateway_recording_arc) = make_recorder();
+        let (_, subject) = make_subject_with_one_neighbor();
+        let version_before = subject.neighborhood_database.root().version();
+        let addr: Addr<Neighborhood> = subject.start();
+        let peer_actors = peer_actors_builder()
+            .hopper(hopper)
+            .ui_gateway(ui_gateway)
+            .build();
+        addr.try_send(BindMessage { peer_actors }).unwrap();
+
+        addr.try_send(SetRatePackMsg {
+            client_id: 1234,
+            context_id: 4321,
+            rate_pack: rate_pack(500),
+        })
+        .unwrap();
+
+        let database_future = addr.send(NeighborhoodDatabaseMessage {});
+        System::current().stop();
+        system.run();
+        let database = database_future.wait().unwrap();
+        assert_eq!(database.root().rate_pack(), &rate_pack(500));
+        assert_eq!(database.root().version(), version_before + 1);
+        let hopper_recording = hopper_recording_arc.lock().unwrap();
+        assert_eq!(hopper_recording.len(), 1);
+        let ui_gateway_recording = ui_gateway_recording_arc.lock().unwrap();
+        assert_eq!(
+            ui_gateway_recording.get_record::<UiCarrierMessage>(0),
+            &UiCarrierMessage::event(UiEvent::NeighborhoodChanged {
+                updated: vec![database.root().to_view(&database)],
+                removed: vec![],
+            })
+        );
+        assert_eq!(
+            ui_gateway_recording.get_record::<UiCarrierMessage>(1),
+            &UiCarrierMessage {
+                client_id: 1234,
+                context_id: Some(4321),
+                data: UiMessage::SetConfigurationResponse(Setting::RatePack, Ok(())),
+            }
+        );
+        assert_eq!(ui_gateway_recording.len(), 2);
+    }
+
+    #[test]
+    fn set_rate_pack_is_refused_by_a_consume_only_node() {
+        let system = System::new("set_rate_pack_is_refused_by_a_consume_only_node");
+        let (ui_gateway, _, ui_gateway_recording_arc) = make_recorder();
+        let mut subject = make_standard_subject();
+        subject.mode = NeighborhoodMode::ConsumeOnly(vec!["neighbor".to_string()]);
+        let addr: Addr<Neighborhood> = subject.start();
+        let peer_actors = peer_actors_builder().ui_gateway(ui_gateway).build();
+        addr.try_send(BindMessage { peer_actors }).unwrap();
+
+        addr.try_send(SetRatePackMsg {
+            client_id: 1234,
+            context_id: 4321,
+            rate_pack: rate_pack(500),
+        })
+        .unwrap();
+
+        System::current().stop();
+        system.run();
+        let ui_gateway_recording = ui_gateway_recording_arc.lock().unwrap();
+        assert_eq!(
+            ui_gateway_recording.get_record::<UiCarrierMessage>(0),
+            &UiCarrierMessage {
+                client_id: 1234,
+                context_id: Some(4321),
+                data: UiMessage::SetConfigurationResponse(
+                    Setting::RatePack,
+                    Err(
+                        "Node has no rate pack to change in --neighborhood-mode consume-only"
+                            .to_string()
+                    )
+                ),
+            }
+        );
+        assert_eq!(ui_gateway_recording.len(), 1);
+    }
+
+    #[test]
+    fn set_neighborhood_mode_to_consume_only_stops_routing_and_gossips() {
+        let system = System::new("set_neighborhood_mode_to_consume_only_stops_routing_and_gossips");
+        let (hopper, _, hopper_recording_arc) = make_recorder();
+        let (ui_gateway, _, ui_gateway_recording_arc) = make_recorder();
+        let (_, subject) = make_subject_with_one_neighbor();
+        let addr: Addr<Neighborhood> = subject.start();
+        let peer_actors = peer_actors_builder()
+            .hopper(hopper)
+            .ui_gateway(ui_gateway)
+            .build();
+        addr.try_send(BindMessage { peer_actors }).unwrap();
+
+        addr.try_send(SetNeighborhoodModeMsg {
+            client_id: 1234,
+            context_id: 4321,
+            mode: "consume-only".to_string(),
+        })
+        .unwrap();
+
+        let database_future = addr.send(NeighborhoodDatabaseMessage {});
+        System::current().stop();
+        system.run();
+        let database = database_future.wait().unwrap();
+        assert!(!database.root().accepts_connections());
+        assert!(!database.root().routes_data());
+        assert_eq!(database.root().rate_pack(), &ZERO_RATE_PACK);
+        let hopper_recording = hopper_recording_arc.lock().unwrap();
+        assert_eq!(hopper_recording.len(), 1);
+        let ui_gateway_recording = ui_gateway_recording_arc.lock().unwrap();
+        assert_eq!(
+            ui_gateway_recording.get_record::<UiCarrierMessage>(1),
+            &UiCarrierMessage {
+                client_id: 1234,
+                context_id: Some(4321),
+                data: UiMessage::SetConfigurationResponse(Setting::NeighborhoodMode, Ok(())),
+            }
+        );
+        assert_eq!(ui_gateway_recording.len(), 2);
+    }
+
+    #[test]
+    fn mode_from_name_keeps_neighbors_and_rate_pack_where_it_can() {
+        let mut subject = make_standard_subject();
+        let neighbor_configs = subject.mode.neighbor_configs().clone();
+        let node_addr = subject
+            .neighborhood_database
+            .root()
+            .node_addr_opt()
+            .unwrap();
+        let root_rate_pack = subject.neighborhood_database.root().rate_pack().clone();
+
+        assert_eq!(
+            subject.mode_from_name("originate-only"),
+            Ok(NeighborhoodMode::OriginateOnly(
+                neighbor_configs.clone(),
+                root_rate_pack
+            ))
+        );
+        assert_eq!(
+            subject.mode_from_name("consume-only"),
+            Ok(NeighborhoodMode::ConsumeOnly(neighbor_configs.clone()))
+        );
+        subject
+            .neighborhood_database
+            .root_mut()
+            .set_rate_pack(ZERO_RATE_PACK);
+        assert_eq!(
+            subject.mode_from_name("standard"),
+            Ok(NeighborhoodMode::Standard(
+                node_addr,
+                neighbor_configs,
+                DEFAULT_RATE_PACK
+            ))
+        );
+    }
+
+    #[test]
+    fn mode_from_name_refuses_modes_the_node_cannot_switch_to() {
+        let subject = make_standard_subject();
+        let zero_hop_subject =
+            neighborhood_from_nodes(&make_global_cryptde_node_record(1234, true), None);
+        let mut no_ip_subject = make_standard_subject();
+        no_ip_subject.neighborhood_database = NeighborhoodDatabase::new(
+            &cryptde().public_key(),
+            NeighborhoodMode::ConsumeOnly(vec![]),
+            make_wallet("earning"),
+            cryptde(),
+        );
+        no_ip_subject.mode = NeighborhoodMode::ConsumeOnly(vec![]);
+
+        assert_eq!(
+            subject.mode_from_name("zero-hop"),
+            Err(
+                "Node cannot switch into or out of --neighborhood-mode zero-hop while running"
+                    .to_string()
+            )
+        );
+        assert_eq!(
+            zero_hop_subject.mode_from_name("standard"),
+            Err(
+                "Node cannot switch into or out of --neighborhood-mode zero-hop while running"
+                    .to_string()
+            )
+        );
+        assert_eq!(
+            no_ip_subject.mode_from_name("standard"),
+            Err(
+                "Node cannot run as --neighborhood-mode standard without --ip specified"
+                    .to_string()
+            )
+        );
+        assert_eq!(
+            no_ip_subject.mode_from_name("originate-only"),
+            Err("Node cannot run as --neighborhood-mode originate-only without --neighbors specified".to_string())
+        );
+    }
+
+    #[test]
+    fn set_earning_wallet_changes_the_root_and_gossips() {
+        let system = System::new("set_earning_wallet_changes_the_root_and_gossips");
+        let (hopper, _, hopper_recording_arc) = make_recorder();
+        let (_, subject) = make_subject_with_one_neighbor();
+        let addr: Addr<Neighborhood> = subject.start();
+        let peer_actors = peer_actors_builder().hopper(hopper).build();
+        addr.try_send(BindMessage { peer_actors }).unwrap();
+        let gossip_sent_before = metrics::value(&NEIGHBORHOOD_GOSSIP_SENT, &[]).unwrap_or(0);
+
+        addr.try_send(SetEarningWalletMessage {
+            wallet: make_wallet("new earning"),
+        })
+        .unwrap();
+
+        let database_future = addr.send(NeighborhoodDatabaseMessage {});
+        System::current().stop();
+        system.run();
+        let database = database_future.wait().unwrap();
+        assert_eq!(database.root().earning_wallet(), make_wallet("new earning"));
+        let hopper_recording = hopper_recording_arc.lock().unwrap();
+        assert_eq!(hopper_recording.len(), 1);
+        assert!(metrics::value(&NEIGHBORHOOD_GOSSIP_SENT, &[]).unwrap() > gossip_sent_before);
+    }
+
+    fn node_record_to_neighbor_config(
+        node_record_ref: &NodeRecord,
+        cryptde: &dyn CryptDE,
+    ) -> String {
+        NodeDescriptor::from((
+            &node_record_ref.public_key().clone(),
+            &node_record_ref.node_addr_opt().unwrap().clone(),
+        ))
+        .to_string(cryptde, DEFAULT_CHAIN_ID)
+    }
+
+    #[test]
+    fn neighborhood_sends_node_query_response_with_none_when_initially_configured_with_no_data() {
+        let cryptde = cryptde();
+        let (recorder, awaiter, recording_arc) = make_recorder();
+        thread::spawn(move || {
+            let system = System::new("responds_with_none_when_initially_configured_with_no_data");
+
+            let addr: Addr<Recorder> = recorder.start();
+            let recipient: Recipient<DispatcherNodeQueryResponse> =
+                addr.recipient::<DispatcherNodeQueryResponse>();
+
+            let subject = make_standard_subject();
+            let addr: Addr<Neighborhood> = subject.start();
+            let sub: Recipient<DispatcherNodeQueryMessage> =
+                addr.recipient::<DispatcherNodeQueryMessage>();
+
+            sub.try_send(DispatcherNodeQueryMessage {
+                query: NodeQueryMessage::PublicKey(PublicKey::new(&b"booga"[..])),
+                context: TransmitDataMsg {
+                    endpoint: Endpoint::Key(cryptde.public_key().clone()),
+                    last_data: false,
+                    sequence_number: None,
+                    data: Vec::new(),
+                },
+                recipient,
+            })
+            .unwrap();
+
+            system.run();
+        });
+
+        awaiter.await_message_count(1);
+        let recording = recording_arc.lock().unwrap();
+        assert_eq!(recording.len(), 1);
+        let message = recording.get_record::<DispatcherNodeQueryResponse>(0);
+        assert_eq!(message.result, None);
+    }
+
+    #[test]
+    fn neighborhood_sends_node_query_response_with_none_when_key_query_matches_no_configured_data()
     {
         let cryptde = cryptde();
         let earning_wallet = make_wallet("earning");
@@ -3108,7 +4051,7 @@ mod tests {
                 None,
             );
             let mut subject = Neighborhood::new(cryptde, &config);
-            subject.dot_graph_recipient = Some(recipient);
+            subject.ui_carrier_message_sub = Some(recipient);
             subject
                 .neighborhood_database
                 .add_node(another_node_record_a)
@@ -3117,8 +4060,11 @@ mod tests {
             let sub: Recipient<NeighborhoodDotGraphRequest> =
                 addr.recipient::<NeighborhoodDotGraphRequest>();
 
-            sub.try_send(NeighborhoodDotGraphRequest { client_id: 0 })
-                .unwrap();
+            sub.try_send(NeighborhoodDotGraphRequest {
+                client_id: 0,
+                context_id: 4321,
+            })
+            .unwrap();
 
             system.run();
         });
@@ -3171,12 +4117,9 @@ mod tests {
 
         let minimum_hop_count = 2;
 
-        let result = subject.make_round_trip_route(RouteQueryMessage {
-            target_key_opt: None,
-            target_component: Component::ProxyClient,
-            minimum_hop_count,
-            return_component_opt: Some(Component::ProxyServer),
-        });
+        let result = subject.make_round_trip_route(
+            RouteQueryMessage::data_indefinite_route_request(minimum_hop_count),
+        );
 
         assert_eq!(
             Err(format!(
@@ -3216,12 +4159,9 @@ mod tests {
 
         let minimum_hop_count = 2;
 
-        let result = subject.make_round_trip_route(RouteQueryMessage {
-            target_key_opt: None,
-            target_component: Component::ProxyClient,
-            minimum_hop_count,
-            return_component_opt: Some(Component::ProxyServer),
-        });
+        let result = subject.make_round_trip_route(
+            RouteQueryMessage::data_indefinite_route_request(minimum_hop_count),
+        );
 
         let next_door_neighbor_cryptde =
             CryptDENull::from(&next_door_neighbor.public_key(), DEFAULT_CHAIN_ID);
@@ -3256,6 +4196,295 @@ mod tests {
         assert_eq!(expected_public_keys, actual_keys);
     }
 
+    #[test]
+    fn make_round_trip_route_avoids_exit_nodes_whose_exit_policy_refuses_the_destination() {
+        let relay = make_node_record(3, true);
+        let mut refusing_exit = make_node_record(4, false);
+        refusing_exit.set_exit_policy(&ExitPolicy::new(vec![], vec![ExitRule::Ports(25, 25)]));
+        refusing_exit.resign();
+        let willing_exit = make_node_record(5, false);
+        let subject_node = make_global_cryptde_node_record(666, true);
+        let mut subject = neighborhood_from_nodes(&subject_node, Some(&relay));
+        {
+            let db = &mut subject.neighborhood_database;
+            db.add_node(relay.clone()).unwrap();
+            db.add_node(refusing_exit.clone()).unwrap();
+            db.add_node(willing_exit.clone()).unwrap();
+            db.add_arbitrary_full_neighbor(subject_node.public_key(), relay.public_key());
+            db.add_arbitrary_full_neighbor(relay.public_key(), refusing_exit.public_key());
+            db.add_arbitrary_full_neighbor(relay.public_key(), willing_exit.public_key());
+        }
+
+        (0..4).for_each(|_| {
+            let result = subject
+                .make_round_trip_route(RouteQueryMessage::data_indefinite_route_request_for(
+                    2,
+                    Some("mail.server.com".to_string()),
+                    25,
+                ))
+                .unwrap();
+
+            match result.expected_services {
+                ExpectedServices::RoundTrip(over, _, _) => assert_eq!(
+                    over[2],
+                    ExpectedService::Exit(
+                        willing_exit.public_key().clone(),
+                        willing_exit.earning_wallet(),
+                        willing_exit.rate_pack().clone()
+                    )
+                ),
+                x => panic!("Expected RoundTrip, got {:?}", x),
+            }
+        });
+    }
+
+    #[test]
+    fn make_round_trip_route_complains_when_every_exit_node_refuses_the_destination() {
+        let relay = make_node_record(3, true);
+        let mut refusing_exit = make_node_record(4, false);
+        refusing_exit.set_exit_policy(&ExitPolicy::new(
+            vec![],
+            vec![ExitRule::Host("*.server.com".to_string())],
+        ));
+        refusing_exit.resign();
+        let subject_node = make_global_cryptde_node_record(666, true);
+        let mut subject = neighborhood_from_nodes(&subject_node, Some(&relay));
+        {
+            let db = &mut subject.neighborhood_database;
+            db.add_node(relay.clone()).unwrap();
+            db.add_node(refusing_exit.clone()).unwrap();
+            db.add_arbitrary_full_neighbor(subject_node.public_key(), relay.public_key());
+            db.add_arbitrary_full_neighbor(relay.public_key(), refusing_exit.public_key());
+        }
+
+        let result =
+            subject.make_round_trip_route(RouteQueryMessage::data_indefinite_route_request_for(
+                2,
+                Some("www.server.com".to_string()),
+                443,
+            ));
+
+        assert_eq!(
+            result,
+            Err(format!(
+                "Couldn't find any routes: no exit Node at least 2-hop from {} has an exit policy that accepts www.server.com:443",
+                cryptde().public_key()
+            ))
+        );
+    }
+
+    #[test]
+    fn make_disjoint_round_trip_routes_avoids_exit_nodes_whose_exit_policy_refuses_the_destination()
+    {
+        let relay = make_node_record(3, true);
+        let mut refusing_exit = make_node_record(4, false);
+        refusing_exit.set_exit_policy(&ExitPolicy::new(vec![ExitRule::Ports(443, 443)], vec![]));
+        refusing_exit.resign();
+        let subject_node = make_global_cryptde_node_record(666, true);
+        let mut subject = neighborhood_from_nodes(&subject_node, Some(&relay));
+        {
+            let db = &mut subject.neighborhood_database;
+            db.add_node(relay.clone()).unwrap();
+            db.add_node(refusing_exit.clone()).unwrap();
+            db.add_arbitrary_full_neighbor(subject_node.public_key(), relay.public_key());
+            db.add_arbitrary_full_neighbor(relay.public_key(), refusing_exit.public_key());
+        }
+
+        let result = subject.make_disjoint_round_trip_routes(
+            DisjointRouteQueryMessage::data_indefinite_route_request_for(2, 3, None, 80),
+        );
+
+        assert_eq!(
+            result,
+            Err(format!(
+                "Couldn't find any routes: no exit Node at least 2-hop from {} has an exit policy that accepts port 80",
+                cryptde().public_key()
+            ))
+        );
+    }
+
+    #[test]
+    fn neighborhood_advertises_the_configured_exit_policy() {
+        let cryptde = cryptde();
+        let exit_policy = ExitPolicy::new(vec![], vec![ExitRule::Ports(25, 25)]);
+        let mut config = BootstrapperConfig::new();
+        config.neighborhood_config = NeighborhoodConfig {
+            mode: NeighborhoodMode::ZeroHop,
+        };
+        config.exit_policy = exit_policy.clone();
+
+        let subject = Neighborhood::new(cryptde, &config);
+
+        let root = subject.neighborhood_database.root();
+        assert_eq!(root.exit_policy(), &exit_policy.summary());
+        let signed_inner: NodeRecordInner =
+            serde_cbor::de::from_slice(root.signed_gossip().as_slice()).unwrap();
+        assert_eq!(signed_inner.exit_policy, exit_policy.summary());
+        assert!(cryptde.verify_signature(
+            root.signed_gossip(),
+            root.signature(),
+            cryptde.public_key()
+        ));
+    }
+
+    #[test]
+    fn make_disjoint_round_trip_routes_finds_relay_disjoint_routes_to_one_exit() {
+        let relay_a = make_node_record(3, true);
+        let relay_b = make_node_record(4, true);
+        let exit_node = make_node_record(5, false);
+        let subject_node = make_global_cryptde_node_record(666, true);
+        let mut subject = neighborhood_from_nodes(&subject_node, Some(&relay_a));
+        subject
+            .neighborhood_database
+            .add_node(relay_a.clone())
+            .unwrap();
+        subject
+            .neighborhood_database
+            .add_node(relay_b.clone())
+            .unwrap();
+        subject
+            .neighborhood_database
+            .add_node(exit_node.clone())
+            .unwrap();
+        subject
+            .neighborhood_database
+            .add_arbitrary_full_neighbor(subject_node.public_key(), relay_a.public_key());
+        subject
+            .neighborhood_database
+            .add_arbitrary_full_neighbor(subject_node.public_key(), relay_b.public_key());
+        subject
+            .neighborhood_database
+            .add_arbitrary_full_neighbor(relay_a.public_key(), exit_node.public_key());
+        subject
+            .neighborhood_database
+            .add_arbitrary_full_neighbor(relay_b.public_key(), exit_node.public_key());
+
+        let result = subject
+            .make_disjoint_round_trip_routes(
+                DisjointRouteQueryMessage::data_indefinite_route_request(2, 3),
+            )
+            .unwrap();
+
+        let services: Vec<(Vec<ExpectedService>, Vec<ExpectedService>, u32)> = result
+            .into_iter()
+            .map(|response| match response.expected_services {
+                ExpectedServices::RoundTrip(over, back, return_route_id) => {
+                    (over, back, return_route_id)
+                }
+                x => panic!("Expected RoundTrip, got {:?}", x),
+            })
+            .collect();
+        assert_eq!(services.len(), 2);
+        let relay_key = |service: &ExpectedService| match service {
+            ExpectedService::Routing(key, _, _) => key.clone(),
+            x => panic!("Expected Routing, got {:?}", x),
+        };
+        let exit_key = |service: &ExpectedService| match service {
+            ExpectedService::Exit(key, _, _) => key.clone(),
+            x => panic!("Expected Exit, got {:?}", x),
+        };
+        assert_eq!(exit_key(&services[0].0[2]), exit_node.public_key().clone());
+        assert_eq!(exit_key(&services[1].0[2]), exit_node.public_key().clone());
+        assert_ne!(relay_key(&services[0].0[1]), relay_key(&services[1].0[1]));
+        assert_ne!(relay_key(&services[0].1[1]), relay_key(&services[1].1[1]));
+        assert_ne!(services[0].2, services[1].2);
+    }
+
+    #[test]
+    fn make_disjoint_round_trip_routes_can_be_pinned_to_an_exit() {
+        let relay_a = make_node_record(3, true);
+        let relay_b = make_node_record(4, true);
+        let exit_a = make_node_record(5, false);
+        let exit_b = make_node_record(6, false);
+        let subject_node = make_global_cryptde_node_record(666, true);
+        let mut subject = neighborhood_from_nodes(&subject_node, Some(&relay_a));
+        {
+            let db = &mut subject.neighborhood_database;
+            db.add_node(relay_a.clone()).unwrap();
+            db.add_node(relay_b.clone()).unwrap();
+            db.add_node(exit_a.clone()).unwrap();
+            db.add_node(exit_b.clone()).unwrap();
+            db.add_arbitrary_full_neighbor(subject_node.public_key(), relay_a.public_key());
+            db.add_arbitrary_full_neighbor(subject_node.public_key(), relay_b.public_key());
+            db.add_arbitrary_full_neighbor(relay_a.public_key(), exit_a.public_key());
+            db.add_arbitrary_full_neighbor(relay_a.public_key(), exit_b.public_key());
+            db.add_arbitrary_full_neighbor(relay_b.public_key(), exit_a.public_key());
+            db.add_arbitrary_full_neighbor(relay_b.public_key(), exit_b.public_key());
+        }
+
+        (0..4).for_each(|_| {
+            let result = subject
+                .make_disjoint_round_trip_routes(DisjointRouteQueryMessage {
+                    target_key_opt: Some(exit_b.public_key().clone()),
+                    ..DisjointRouteQueryMessage::data_indefinite_route_request(2, 2)
+                })
+                .unwrap();
+
+            assert_eq!(result.len(), 2);
+            result
+                .into_iter()
+                .for_each(|response| match response.expected_services {
+                    ExpectedServices::RoundTrip(over, _, _) => assert_eq!(
+                        over[2],
+                        ExpectedService::Exit(
+                            exit_b.public_key().clone(),
+                            exit_b.earning_wallet(),
+                            exit_b.rate_pack().clone()
+                        )
+                    ),
+                    x => panic!("Expected RoundTrip, got {:?}", x),
+                });
+        });
+    }
+
+    #[test]
+    fn make_disjoint_round_trip_routes_settles_for_fewer_routes_when_relays_must_be_shared() {
+        let relay = make_node_record(3, true);
+        let exit_node = make_node_record(5, false);
+        let subject_node = make_global_cryptde_node_record(666, true);
+        let mut subject = neighborhood_from_nodes(&subject_node, Some(&relay));
+        subject
+            .neighborhood_database
+            .add_node(relay.clone())
+            .unwrap();
+        subject
+            .neighborhood_database
+            .add_node(exit_node.clone())
+            .unwrap();
+        subject
+            .neighborhood_database
+            .add_arbitrary_full_neighbor(subject_node.public_key(), relay.public_key());
+        subject
+            .neighborhood_database
+            .add_arbitrary_full_neighbor(relay.public_key(), exit_node.public_key());
+
+        let result = subject
+            .make_disjoint_round_trip_routes(
+                DisjointRouteQueryMessage::data_indefinite_route_request(2, 3),
+            )
+            .unwrap();
+
+        assert_eq!(result.len(), 1);
+    }
+
+    #[test]
+    fn make_disjoint_round_trip_routes_complains_when_there_are_no_routes() {
+        let subject_node = make_global_cryptde_node_record(666, true);
+        let mut subject = neighborhood_from_nodes(&subject_node, None);
+
+        let result = subject.make_disjoint_round_trip_routes(
+            DisjointRouteQueryMessage::data_indefinite_route_request(2, 3),
+        );
+
+        assert_eq!(
+            result,
+            Err(format!(
+                "Couldn't find any routes: at least 2-hop from {} to ProxyClient at Unknown",
+                cryptde().public_key()
+            ))
+        );
+    }
+
     #[test]
     #[should_panic(
         expected = "Neighborhood should never get ShutdownStreamMsg about non-clandestine stream"
@@ -3364,6 +4593,7 @@ mod tests {
     fn handle_stream_shutdown_handles_existing_socket_addr() {
         init_test_logging();
         let (hopper, _, hopper_recording_arc) = make_recorder();
+        let (proxy_server, _, proxy_server_recording_arc) = make_recorder();
         let system = System::new("test");
         let gossip_neighbor_node = make_node_record(2456, true);
         let shutdown_neighbor_node = make_node_record(3123, true);
@@ -3390,8 +4620,12 @@ mod tests {
             subject_node.public_key(),
             shutdown_neighbor_node.public_key(),
         );
-        let peer_actors = peer_actors_builder().hopper(hopper).build();
+        let peer_actors = peer_actors_builder()
+            .hopper(hopper)
+            .proxy_server(proxy_server)
+            .build();
         subject.hopper = Some(peer_actors.hopper.from_hopper_client);
+        subject.invalidate_routes_recipient = Some(peer_actors.proxy_server.invalidate_routes);
 
         subject.handle_stream_shutdown_msg(StreamShutdownMsg {
             peer_addr: shutdown_neighbor_node_socket_addr,
@@ -3412,6 +4646,13 @@ mod tests {
         );
         let hopper_recording = hopper_recording_arc.lock().unwrap();
         assert_eq!(hopper_recording.len(), 1);
+        let proxy_server_recording = proxy_server_recording_arc.lock().unwrap();
+        assert_eq!(
+            proxy_server_recording.get_record::<InvalidateRoutesMessage>(0),
+            &InvalidateRoutesMessage {
+                public_key: shutdown_neighbor_node.public_key().clone(),
+            }
+        );
         TestLogHandler::new().exists_log_containing(&format!(
             "DEBUG: Neighborhood: Received shutdown notification for {} at {}: removing neighborship",
             shutdown_neighbor_node.public_key(),
@@ -7,9 +7,11 @@ use crate::neighborhood::node_record::{NodeRecord, NodeRecordError};
 use crate::sub_lib::cryptde::CryptDE;
 use crate::sub_lib::cryptde::PublicKey;
 use crate::sub_lib::neighborhood::NeighborhoodMode;
+use crate::sub_lib::neighborhood::NodeRecordView;
 use crate::sub_lib::node_addr::NodeAddr;
 use crate::sub_lib::wallet::Wallet;
 use itertools::Itertools;
+use std::collections::BTreeMap;
 use std::collections::HashMap;
 use std::collections::HashSet;
 use std::fmt::Debug;
@@ -198,6 +200,13 @@ impl NeighborhoodDatabase {
         render_dot_graph(renderables)
     }
 
+    pub fn to_views(&self) -> BTreeMap<PublicKey, NodeRecordView> {
+        self.by_public_key
+            .iter()
+            .map(|(key, node_record)| (key.clone(), node_record.to_view(self)))
+            .collect()
+    }
+
     fn to_dot_renderables(&self) -> Vec<Box<dyn DotRenderable>> {
         let mut mentioned: HashSet<PublicKey> = HashSet::new();
         let mut present: HashSet<PublicKey> = HashSet::new();
@@ -705,6 +714,40 @@ mod tests {
         assert_string_contains(&result, "\"BAUGBw\" -> \"AQIDBA\";");
     }
 
+    #[test]
+    fn database_can_be_viewed_node_by_node() {
+        let this_node = make_node_record(1234, true);
+        let node_one = make_node_record(2345, true);
+        let node_two = make_node_record(3456, false);
+        let mut subject = db_from_node(&this_node);
+        subject.add_node(node_one.clone()).unwrap();
+        subject.add_node(node_two.clone()).unwrap();
+        subject.add_arbitrary_full_neighbor(this_node.public_key(), node_one.public_key());
+        subject.add_arbitrary_half_neighbor(node_two.public_key(), node_one.public_key());
+
+        let result = subject.to_views();
+
+        assert_eq!(
+            result.keys().collect::<Vec<&PublicKey>>(),
+            vec![
+                this_node.public_key(),
+                node_one.public_key(),
+                node_two.public_key()
+            ]
+        );
+        assert_eq!(
+            result.get(this_node.public_key()),
+            Some(&subject.root().to_view(&subject))
+        );
+        let node_one_view = result.get(node_one.public_key()).unwrap();
+        assert_eq!(node_one_view.half_neighbors, vec!["AQIDBA".to_string()]);
+        assert_eq!(node_one_view.full_neighbors, vec!["AQIDBA".to_string()]);
+        let node_two_view = result.get(node_two.public_key()).unwrap();
+        assert_eq!(node_two_view.node_addr, None);
+        assert_eq!(node_two_view.half_neighbors, vec!["AgMEBQ".to_string()]);
+        assert_eq!(node_two_view.full_neighbors, Vec::<String>::new());
+    }
+
     #[test]
     fn remove_neighbor_returns_error_when_given_nonexistent_node_key() {
         let this_node = make_node_record(123, true);
@@ -5,7 +5,9 @@ use crate::neighborhood::neighborhood_database::{NeighborhoodDatabase, Neighborh
 use crate::neighborhood::{regenerate_signed_gossip, AccessibleGossipRecord};
 use crate::sub_lib::cryptde::{CryptDE, CryptData, PlainData, PublicKey};
 use crate::sub_lib::data_version::DataVersion;
+use crate::sub_lib::exit_policy::{ExitPolicy, ExitPolicySummary};
 use crate::sub_lib::neighborhood::NodeDescriptor;
+use crate::sub_lib::neighborhood::NodeRecordView;
 use crate::sub_lib::neighborhood::RatePack;
 use crate::sub_lib::node_addr::NodeAddr;
 use crate::sub_lib::wallet::Wallet;
@@ -24,12 +26,15 @@ pub struct NodeRecordInner {
     pub neighbors: BTreeSet<PublicKey>,
     pub accepts_connections: bool,
     pub routes_data: bool,
+    // Added in 1.1; records from older Nodes get the default policy.
+    #[serde(default)]
+    pub exit_policy: ExitPolicySummary,
     pub version: u32,
 }
 
 impl NodeRecordInner {
     pub fn data_version() -> DataVersion {
-        DataVersion::new(1, 0).expect("Internal Error")
+        DataVersion::new(1, 1).expect("Internal Error")
     }
 }
 
@@ -84,6 +89,7 @@ impl NodeRecord {
                 rate_pack,
                 accepts_connections,
                 routes_data,
+                exit_policy: ExitPolicySummary::default(),
                 neighbors: BTreeSet::new(),
                 version,
             },
@@ -238,6 +244,47 @@ impl NodeRecord {
         &self.inner.rate_pack
     }
 
+    pub fn exit_policy(&self) -> &ExitPolicySummary {
+        &self.inner.exit_policy
+    }
+
+    pub fn set_exit_policy(&mut self, exit_policy: &ExitPolicy) -> bool {
+        let summary = exit_policy.summary();
+        if self.inner.exit_policy == summary {
+            false
+        } else {
+            self.inner.exit_policy = summary;
+            true
+        }
+    }
+
+    pub fn set_rate_pack(&mut self, rate_pack: RatePack) -> bool {
+        if self.inner.rate_pack == rate_pack {
+            false
+        } else {
+            self.inner.rate_pack = rate_pack;
+            true
+        }
+    }
+
+    pub fn set_accepts_connections(&mut self, accepts_connections: bool) -> bool {
+        if self.inner.accepts_connections == accepts_connections {
+            false
+        } else {
+            self.inner.accepts_connections = accepts_connections;
+            true
+        }
+    }
+
+    pub fn set_routes_data(&mut self, routes_data: bool) -> bool {
+        if self.inner.routes_data == routes_data {
+            false
+        } else {
+            self.inner.routes_data = routes_data;
+            true
+        }
+    }
+
     pub fn is_desirable(&self) -> bool {
         self.metadata.desirable
     }
@@ -246,6 +293,31 @@ impl NodeRecord {
         self.metadata.desirable = is_desirable
     }
 
+    pub fn to_view(&self, db: &NeighborhoodDatabase) -> NodeRecordView {
+        let sorted_strings = |keys: HashSet<&PublicKey>| {
+            keys.into_iter()
+                .collect::<BTreeSet<&PublicKey>>()
+                .into_iter()
+                .map(|k| k.to_string())
+                .collect::<Vec<String>>()
+        };
+        let rate_pack = self.rate_pack();
+        NodeRecordView {
+            public_key: self.public_key().to_string(),
+            version: self.version(),
+            routing_byte_rate: rate_pack.routing_byte_rate,
+            routing_service_rate: rate_pack.routing_service_rate,
+            exit_byte_rate: rate_pack.exit_byte_rate,
+            exit_service_rate: rate_pack.exit_service_rate,
+            accepts_connections: self.accepts_connections(),
+            routes_data: self.routes_data(),
+            desirable: self.is_desirable(),
+            node_addr: self.node_addr_opt().map(|node_addr| node_addr.to_string()),
+            half_neighbors: sorted_strings(self.half_neighbor_keys()),
+            full_neighbors: sorted_strings(self.full_neighbor_keys(db)),
+        }
+    }
+
     pub fn update(&mut self, agr: AccessibleGossipRecord) -> Result<(), String> {
         if &agr.inner.public_key != self.public_key() {
             return Err("Updating a NodeRecord must not change its public key".to_string());
@@ -322,6 +394,7 @@ mod tests {
     use crate::neighborhood::neighborhood_test_utils::db_from_node;
     use crate::neighborhood::neighborhood_test_utils::make_node_record;
     use crate::sub_lib::cryptde_null::CryptDENull;
+    use crate::sub_lib::exit_policy::ExitRule;
     use crate::sub_lib::neighborhood::ZERO_RATE_PACK;
     use crate::test_utils::{assert_contains, cryptde, make_wallet, rate_pack, DEFAULT_CHAIN_ID};
     use std::net::IpAddr;
@@ -342,6 +415,44 @@ mod tests {
         assert_eq!(expected_node_record, actual_node_record);
     }
 
+    #[test]
+    fn to_view_describes_the_node_record() {
+        let root = make_node_record(1234, true);
+        let full_neighbor = make_node_record(2345, false);
+        let half_neighbor = make_node_record(3456, false);
+        let mut db = db_from_node(&root);
+        db.add_node(full_neighbor.clone()).unwrap();
+        db.add_node(half_neighbor.clone()).unwrap();
+        db.add_arbitrary_full_neighbor(root.public_key(), full_neighbor.public_key());
+        db.add_arbitrary_half_neighbor(root.public_key(), half_neighbor.public_key());
+        db.root_mut().set_desirable(false);
+        let subject = db.root();
+
+        let result = subject.to_view(&db);
+
+        let rate_pack = subject.rate_pack();
+        assert_eq!(
+            result,
+            NodeRecordView {
+                public_key: subject.public_key().to_string(),
+                version: subject.version(),
+                routing_byte_rate: rate_pack.routing_byte_rate,
+                routing_service_rate: rate_pack.routing_service_rate,
+                exit_byte_rate: rate_pack.exit_byte_rate,
+                exit_service_rate: rate_pack.exit_service_rate,
+                accepts_connections: subject.accepts_connections(),
+                routes_data: subject.routes_data(),
+                desirable: false,
+                node_addr: Some("1.2.3.4:1234".to_string()),
+                half_neighbors: vec![
+                    full_neighbor.public_key().to_string(),
+                    half_neighbor.public_key().to_string(),
+                ],
+                full_neighbors: vec![full_neighbor.public_key().to_string()],
+            }
+        );
+    }
+
     #[test]
     fn set_node_addr_works_once_but_not_twice() {
         let mut subject = make_node_record(1234, false);
@@ -663,6 +774,16 @@ mod tests {
             cryptde(),
         );
         mod_signature.signature = CryptData::new(&[]);
+        let mut mod_exit_policy = NodeRecord::new(
+            &PublicKey::new(&b"poke"[..]),
+            earning_wallet.clone(),
+            rate_pack(100),
+            true,
+            true,
+            0,
+            cryptde(),
+        );
+        mod_exit_policy.set_exit_policy(&ExitPolicy::new(vec![], vec![]));
         let mod_version = NodeRecord::new(
             &PublicKey::new(&b"poke"[..]),
             earning_wallet.clone(),
@@ -684,6 +805,7 @@ mod tests {
         assert_ne!(exemplar, mod_routes_data);
         assert_ne!(exemplar, mod_signed_gossip);
         assert_ne!(exemplar, mod_signature);
+        assert_ne!(exemplar, mod_exit_policy);
         assert_ne!(exemplar, mod_version);
     }
 
@@ -749,6 +871,122 @@ mod tests {
         );
     }
 
+    #[test]
+    fn set_exit_policy_reports_whether_the_policy_changes() {
+        let mut this_node = make_node_record(1234, true);
+        assert_eq!(this_node.exit_policy(), &ExitPolicy::default().summary());
+        let new_policy = ExitPolicy::new(vec![ExitRule::Ports(80, 80)], vec![]);
+
+        assert!(this_node.set_exit_policy(&new_policy));
+        assert!(!this_node.set_exit_policy(&new_policy));
+
+        assert_eq!(this_node.exit_policy(), &new_policy.summary());
+    }
+
+    #[test]
+    fn rate_pack_and_mode_flag_setters_report_whether_anything_changes() {
+        let mut this_node = make_node_record(1234, true);
+        let new_rate_pack = RatePack {
+            routing_byte_rate: 1,
+            routing_service_rate: 2,
+            exit_byte_rate: 3,
+            exit_service_rate: 4,
+        };
+
+        assert!(this_node.set_rate_pack(new_rate_pack.clone()));
+        assert!(!this_node.set_rate_pack(new_rate_pack.clone()));
+        assert!(this_node.set_accepts_connections(false));
+        assert!(!this_node.set_accepts_connections(false));
+        assert!(this_node.set_routes_data(false));
+        assert!(!this_node.set_routes_data(false));
+
+        assert_eq!(this_node.rate_pack(), &new_rate_pack);
+        assert!(!this_node.accepts_connections());
+        assert!(!this_node.routes_data());
+    }
+
+    #[test]
+    fn inner_from_an_older_node_gets_the_default_exit_policy() {
+        #[derive(Serialize)]
+        struct OldNodeRecordInner {
+            data_version: DataVersion,
+            public_key: PublicKey,
+            earning_wallet: Wallet,
+            rate_pack: RatePack,
+            neighbors: BTreeSet<PublicKey>,
+            accepts_connections: bool,
+            routes_data: bool,
+            version: u32,
+        }
+        let old_inner = OldNodeRecordInner {
+            data_version: DataVersion::new(1, 0).unwrap(),
+            public_key: PublicKey::new(&b"poke"[..]),
+            earning_wallet: make_wallet("wallet"),
+            rate_pack: rate_pack(100),
+            neighbors: BTreeSet::new(),
+            accepts_connections: true,
+            routes_data: true,
+            version: 3,
+        };
+
+        let result: NodeRecordInner =
+            serde_cbor::de::from_slice(&serde_cbor::ser::to_vec(&old_inner).unwrap()).unwrap();
+
+        assert_eq!(result.data_version, DataVersion::new(1, 0).unwrap());
+        assert_eq!(result.exit_policy, ExitPolicy::default().summary());
+        assert_eq!(result.version, 3);
+    }
+
+    #[test]
+    fn inner_with_an_exit_policy_entry_it_does_not_understand_is_still_readable() {
+        #[derive(Serialize)]
+        struct NewerExitPolicySummary {
+            allowed_ports: Vec<serde_cbor::Value>,
+            denies_private_networks: bool,
+            denied_countries: Vec<String>,
+        }
+        #[derive(Serialize)]
+        struct NewerNodeRecordInner {
+            data_version: DataVersion,
+            public_key: PublicKey,
+            earning_wallet: Wallet,
+            rate_pack: RatePack,
+            neighbors: BTreeSet<PublicKey>,
+            accepts_connections: bool,
+            routes_data: bool,
+            exit_policy: NewerExitPolicySummary,
+            version: u32,
+        }
+        let newer_inner = NewerNodeRecordInner {
+            data_version: DataVersion::new(1, 2).unwrap(),
+            public_key: PublicKey::new(&b"poke"[..]),
+            earning_wallet: make_wallet("wallet"),
+            rate_pack: rate_pack(100),
+            neighbors: BTreeSet::new(),
+            accepts_connections: true,
+            routes_data: true,
+            exit_policy: NewerExitPolicySummary {
+                allowed_ports: vec![
+                    serde_cbor::Value::Text("quic".to_string()),
+                    serde_cbor::Value::Array(vec![
+                        serde_cbor::Value::Integer(443),
+                        serde_cbor::Value::Integer(443),
+                    ]),
+                ],
+                denies_private_networks: true,
+                denied_countries: vec!["XX".to_string()],
+            },
+            version: 3,
+        };
+
+        let result: NodeRecordInner =
+            serde_cbor::de::from_slice(&serde_cbor::ser::to_vec(&newer_inner).unwrap()).unwrap();
+
+        assert_eq!(result.exit_policy.allowed_ports, vec![(443, 443)]);
+        assert_eq!(result.exit_policy.denies_private_networks, true);
+        assert_eq!(result.version, 3);
+    }
+
     #[test]
     fn set_desirable_when_no_change_from_default() {
         let mut this_node = make_node_record(5432, true);
@@ -2,20 +2,27 @@
 
 use crate::blockchain::blockchain_interface::DEFAULT_GAS_PRICE;
 use crate::bootstrapper::BootstrapperConfig;
+use crate::multi_config::{ConfigFileSection, ConfigFileSettings};
 use crate::node_configurator;
 use crate::node_configurator::{
     app_head, chain_arg, common_validators, config_file_arg, data_directory_arg,
     earning_wallet_arg, initialize_database, real_user_arg, wallet_password_arg, NodeConfigurator,
 };
+use crate::server_initializer::{DEFAULT_LOG_RETENTION, DEFAULT_LOG_ROTATION_MEGABYTES};
 use crate::sub_lib::crash_point::CrashPoint;
+use crate::sub_lib::exit_policy::{NO_EXIT_RULES, PRIVATE_NETWORKS_RULE};
 use crate::sub_lib::main_tools::StdStreams;
-use crate::sub_lib::ui_gateway::DEFAULT_UI_PORT;
+use crate::sub_lib::proxy_server::MAX_ROUTE_PATH_COUNT;
+use crate::sub_lib::ui_gateway::{DEFAULT_METRICS_PORT, DEFAULT_UI_PORT};
 use clap::{App, Arg};
 use indoc::indoc;
 use lazy_static::lazy_static;
 
 pub const LOWEST_USABLE_INSECURE_PORT: u16 = 1025;
 pub const HIGHEST_USABLE_PORT: u16 = 65535;
+pub const LOG_LEVELS: [&str; 6] = ["off", "error", "warn", "info", "debug", "trace"];
+pub const NEIGHBORHOOD_MODES: [&str; 4] =
+    ["zero-hop", "originate-only", "consume-only", "standard"];
 
 pub struct NodeConfiguratorStandardPrivileged {}
 
@@ -64,12 +71,24 @@ impl NodeConfiguratorStandardUnprivileged {
 
 lazy_static! {
     static ref DEFAULT_UI_PORT_VALUE: String = DEFAULT_UI_PORT.to_string();
+    static ref DEFAULT_METRICS_PORT_VALUE: String = DEFAULT_METRICS_PORT.to_string();
+    static ref DEFAULT_LOG_RETENTION_VALUE: String = DEFAULT_LOG_RETENTION.to_string();
     static ref DEFAULT_CRASH_POINT_VALUE: String = format!("{}", CrashPoint::None);
     static ref UI_PORT_HELP: String = format!(
         "The port at which user interfaces will connect to the Node. Best to accept the default unless \
         you know what you're doing. Must be between {} and {}.",
         LOWEST_USABLE_INSECURE_PORT, HIGHEST_USABLE_PORT
     );
+    static ref METRICS_PORT_HELP: String = format!(
+        "The localhost port at which the Node serves its metrics in Prometheus text format, at the \
+        path /metrics, when --metrics is on. Must be between {} and {}.",
+        LOWEST_USABLE_INSECURE_PORT, HIGHEST_USABLE_PORT
+    );
+    static ref LOG_ROTATION_SIZE_HELP: String = format!(
+        "The size in megabytes the Node's logfile may grow to before it's zipped up and a new one is \
+         started. Can't be combined with --log-rotation-age. [default: {}]",
+        DEFAULT_LOG_ROTATION_MEGABYTES
+    );
     static ref CLANDESTINE_PORT_HELP: String = format!(
         "The port this Node will advertise to other Nodes at which clandestine traffic will be \
          received. If you don't specify a clandestine port, the Node will choose an unused \
@@ -89,9 +108,61 @@ const BLOCKCHAIN_SERVICE_HELP: &str =
     "The Ethereum client you wish to use to provide Blockchain \
      exit services from your SubstratumNode (e.g. http://localhost:8545, \
      https://ropsten.infura.io/v3/YOUR-PROJECT-ID, https://mainnet.infura.io/v3/YOUR-PROJECT-ID).";
+const BYPASS_HELP: &str =
+    "Destinations your Node should leave off the Substratum Network entirely, such as intranet sites, \
+     printers, or anything else that only makes sense from your own network, as a comma-separated list \
+     of rules. A rule can be a domain, which covers the domain and all its subdomains \
+     (intranet.example.com); a network in CIDR notation (10.0.0.0/8) or a single IP address; or private, \
+     which covers loopback, link-local, and private-network addresses. Names on the list are looked up \
+     with the DNS servers your machine used before dns_utility subverted its DNS, and your Node connects \
+     to them directly, so the sites see your real IP address.";
+const BYPASS_DNS_SERVERS_HELP: &str =
+    "IP addresses of the DNS servers that should look up names on the --bypass list. If you don't \
+     supply any, your Node uses the DNS servers your machine used before dns_utility subverted its DNS. \
+     Meaningless without --bypass.";
 const DNS_SERVERS_HELP: &str =
     "IP addresses of DNS Servers for host name look-up while providing exit \
      services for other SubstratumNodes (e.g. 1.0.0.1,1.1.1.1,8.8.8.8,9.9.9.9, etc.)";
+const DNS_PROTOCOL_HELP: &str =
+    "How your Node sends its look-ups to the --dns-servers: udp is plain DNS on port 53, which anyone \
+     watching your network can read; tls is DNS-over-TLS on port 853 and https is DNS-over-HTTPS on \
+     port 443, both of which are encrypted. The encrypted protocols require --dns-tls-name.";
+const DNS_TLS_NAME_HELP: &str =
+    "The hostname on the certificates presented by the --dns-servers when --dns-protocol is tls or https \
+     (e.g. cloudflare-dns.com for 1.1.1.1 and 1.0.0.1, dns.google for 8.8.8.8 and 8.8.4.4).";
+const ENTRY_DNS_HELP: &str =
+    "How your Node answers the DNS queries your computer sends it once dns_utility has subverted your \
+     DNS. With localhost, every host name resolves to your own machine, so that your browsing goes through \
+     your Node, and queries for anything other than addresses are refused. With tunnel, host names still \
+     resolve to your own machine, but queries for other kinds of records (CNAME, MX, TXT) are looked up \
+     for you by an exit Node, so that software that needs them works without giving your location away \
+     to a DNS server. With resolve, addresses are looked up by an exit Node too, so that software that can't \
+     go through your Node still reaches the real hosts; browsers then have to use --proxy-port, which resolve \
+     requires. Whichever you choose, the same queries can be sent the DNS-over-HTTPS way to \
+     http://127.0.0.1:8053/dns-query. That endpoint is plain HTTP, without TLS, so software that will only \
+     use https:// needs something in front of it to terminate TLS.";
+const EXIT_ALLOW_HELP: &str =
+    "The destinations your Node is willing to connect to when it acts as an exit Node for other Nodes, \
+     as a comma-separated list of rules. If you supply any, your Node will refuse to connect anywhere \
+     they don't cover. A rule can be a network in CIDR notation (203.0.113.0/24) or a single IP address; \
+     a port or range of ports (port:443 or port:8000-8099); a hostname, with an optional leading \
+     wildcard (*.example.com covers example.com and all its subdomains); or private, which covers \
+     loopback, link-local, private-network, multicast, broadcast and NAT64 addresses. --exit-deny always wins over --exit-allow.";
+const EXIT_DENY_HELP: &str =
+    "The destinations your Node will refuse to connect to when it acts as an exit Node for other Nodes, \
+     as a comma-separated list of rules in the same form as --exit-allow. The default, private, keeps \
+     other Nodes away from your own machine, your local network, and your cloud provider's metadata \
+     service. If you supply this parameter, include private in the list unless you really mean to let \
+     them in; use none to deny nothing at all.";
+const EXIT_POOL_SIZE_HELP: &str =
+    "How many idle connections to each HTTP server your Node keeps open when it acts as an exit Node, \
+     so that later requests from other Nodes to the same server don't have to wait for a new connection. \
+     Only plaintext HTTP/1.1 connections that neither the browser nor the server has asked to close \
+     are kept. The default, 0, keeps none. Must be between 0 and 64.";
+const EXIT_POOL_IDLE_TIMEOUT_HELP: &str =
+    "How many seconds a connection kept open by --exit-pool-size may sit idle before your Node closes \
+     it. Keep this shorter than the keep-alive timeouts of the servers themselves, or they may close \
+     connections your Node still expects to use. Must be between 1 and 300.";
 const EARNING_WALLET_HELP: &str =
     "An Ethereum wallet address. Addresses must begin with 0x followed by 40 hexadecimal digits \
      (case-insensitive). If you already have a derivation-path earning wallet, don't supply this. \
@@ -107,7 +178,23 @@ const LOG_LEVEL_HELP: &str =
      a level lower than the default unless you're doing testing or forensics: a Node at the 'trace' log level \
      generates a lot of log traffic. This will both consume your disk space and degrade your Node's performance. \
      You should probably not specify a level higher than the default unless you have security concerns about \
-     persistent logs being kept on your computer: if your Node crashes, it's good to know why.";
+     persistent logs being kept on your computer: if your Node crashes, it's good to know why. \
+     To hear more or less from particular components, follow the level with their names and levels, \
+     separated by commas: for example, 'warn,Neighborhood=trace,Hopper=debug'. A name is matched as a prefix, \
+     so Neighborhood=trace also turns up NeighborhoodDatabase; name that one too if you want it left alone.";
+const LOG_FORMAT_HELP: &str =
+    "The form of the lines in the Node's logfile. 'text' lines are meant for people to read; 'json' \
+     lines are single JSON objects with timestamp, thread, logger, level and message fields, plus \
+     whatever structured fields the message carries, such as stream_key, public_key or wallet.";
+const LOG_ROTATION_AGE_HELP: &str =
+    "Start a new logfile every hour or every day, zipping up the old one, instead of waiting for the \
+     logfile to reach a certain size. Can't be combined with --log-rotation-size.";
+const LOG_RETENTION_HELP: &str =
+    "How many zipped-up old logfiles to keep. When rotation would make one more than this, the oldest \
+     is deleted. Together with the rotation age, this also limits how far back the logs go.";
+const METRICS_HELP: &str =
+    "Whether to serve the counters and gauges the Node keeps about its traffic, in Prometheus text \
+     format, on localhost at the --metrics-port. Off unless you ask for it.";
 const NEIGHBORS_HELP: &str = "One or more Node descriptors for running Nodes in the Substratum \
      Network to which you'd like your Node to connect on startup. A Node descriptor looks like \
      this:\n\ngBviQbjOS3e5ReFQCvIhUM3i02d1zPleo1iXg/EN6zQ:86.75.30.9:5542 (initial ':' for testnet) and\n\
@@ -135,6 +222,34 @@ const NEIGHBORHOOD_MODE_HELP: &str = "This configures the way the Node relates t
      standard means that your Node will operate fully unconstrained, both originating and accepting \
      connections, both consuming and providing services, and when you operate behind a router, it \
      requires that you forward your clandestine port through that router to your Node's machine.";
+const PRIVACY_LOGGING_HELP: &str =
+    "Whether to keep hostnames, IP addresses, wallet addresses and public keys out of the Node's logfile. \
+     With 'on', each one is logged as a token such as host#1a2b3c4d instead: the same value always gets \
+     the same token until the Node is restarted, so you can still follow it through the log, but the \
+     token can't be traced back to the value. The contents of the data passing through are left out \
+     entirely.";
+const PROXY_PORT_HELP: &str =
+    "A port on which your Node will act as an explicit HTTP proxy, so that you can point your browser's \
+     proxy settings at it instead of redirecting your machine's DNS to your Node. Plain requests for \
+     http:// URLs and CONNECT requests to any port are sent over the Substratum Network like any other \
+     traffic. Must be between 1025 and 65535, and must not be your clandestine, UI, metrics or \
+     DNS-over-HTTPS port. If you don't supply this, your Node won't listen for proxy requests.";
+const RATE_PACK_HELP: &str =
+    "The rates your Node will charge for routing and exit services, as four whole numbers separated by '|': \
+     the routing byte rate, the routing service rate, the exit byte rate and the exit service rate. Your \
+     neighbors learn them through Gossip. If you don't supply this, your Node will charge the default rates.";
+const ROUTE_PATHS_HELP: &str =
+    "The number of relay-disjoint routes over which each of your streams should be spread, from 1 to 8. \
+     Each request goes out over the next route in turn, so no single relay Node sees the whole stream, \
+     and the exit Node spreads its responses back the same way. Spreading a stream costs more routing \
+     service, and if your Neighborhood can't find that many routes, you'll get as many as it can find. \
+     --route-paths is meaningless in --neighborhood-mode zero-hop.";
+const STREAM_TRACING_HELP: &str =
+    "Whether to time each stream from your browser as it passes through your Node: how long the route \
+     took to find, how long the Hopper took to send the first request on, how long the first response \
+     took to come back and how long the stream stayed open. With 'on', UIs can ask for the timings of \
+     recent streams, and every finished stream is appended to stream-traces.json in the data directory, \
+     in a format that chrome://tracing and Perfetto can open.";
 const WALLET_PASSWORD_HELP: &str =
     "A password or phrase to decrypt your consuming wallet or a keystore file. Can be changed \
      later and still produce the same addresses.";
@@ -169,6 +284,84 @@ const HELP_TEXT: &str = indoc!(
         3. Create the port forwarding entries in the router."
 );
 
+// Tables that a configuration file can use to group its settings, rather than naming each one as it
+// would be named on the command line
+pub const CONFIG_FILE_SECTIONS: &[ConfigFileSection] = &[
+    ConfigFileSection {
+        name: "neighborhood",
+        settings: ConfigFileSettings::Each(&[
+            ("mode", "neighborhood-mode"),
+            ("neighbors", "neighbors"),
+            ("ip", "ip"),
+            ("clandestine_port", "clandestine-port"),
+            ("route_paths", "route-paths"),
+        ]),
+    },
+    ConfigFileSection {
+        name: "rate_pack",
+        settings: ConfigFileSettings::Joined {
+            arg: "rate-pack",
+            separator: "|",
+            keys: &[
+                "routing_byte_rate",
+                "routing_service_rate",
+                "exit_byte_rate",
+                "exit_service_rate",
+            ],
+        },
+    },
+    ConfigFileSection {
+        name: "blockchain",
+        settings: ConfigFileSettings::Each(&[
+            ("service_url", "blockchain-service-url"),
+            ("chain", "chain"),
+            ("gas_price", "gas-price"),
+            ("earning_wallet", "earning-wallet"),
+            ("consuming_private_key", "consuming-private-key"),
+        ]),
+    },
+    ConfigFileSection {
+        name: "dns",
+        settings: ConfigFileSettings::Each(&[
+            ("servers", "dns-servers"),
+            ("protocol", "dns-protocol"),
+            ("tls_name", "dns-tls-name"),
+            ("entry", "entry-dns"),
+            ("bypass", "bypass"),
+            ("bypass_servers", "bypass-dns-servers"),
+        ]),
+    },
+    ConfigFileSection {
+        name: "exit_policy",
+        settings: ConfigFileSettings::Each(&[
+            ("allow", "exit-allow"),
+            ("deny", "exit-deny"),
+            ("pool_size", "exit-pool-size"),
+            ("pool_idle_timeout", "exit-pool-idle-timeout"),
+        ]),
+    },
+    ConfigFileSection {
+        name: "log",
+        settings: ConfigFileSettings::Each(&[
+            ("level", "log-level"),
+            ("format", "log-format"),
+            ("rotation_size", "log-rotation-size"),
+            ("rotation_age", "log-rotation-age"),
+            ("retention", "log-retention"),
+            ("privacy", "privacy-logging"),
+        ]),
+    },
+    ConfigFileSection {
+        name: "ui",
+        settings: ConfigFileSettings::Each(&[
+            ("port", "ui-port"),
+            ("proxy_port", "proxy-port"),
+            ("metrics", "metrics"),
+            ("metrics_port", "metrics-port"),
+        ]),
+    },
+];
+
 fn app() -> App<'static, 'static> {
     app_head()
         .after_help(HELP_TEXT)
@@ -180,6 +373,24 @@ fn app() -> App<'static, 'static> {
                 .takes_value(true)
                 .help(BLOCKCHAIN_SERVICE_HELP),
         )
+        .arg(
+            Arg::with_name("bypass")
+                .long("bypass")
+                .value_name("BYPASS-RULES")
+                .takes_value(true)
+                .use_delimiter(true)
+                .validator(validators::validate_bypass_rule)
+                .help(BYPASS_HELP),
+        )
+        .arg(
+            Arg::with_name("bypass-dns-servers")
+                .long("bypass-dns-servers")
+                .value_name("DNS-SERVERS")
+                .takes_value(true)
+                .use_delimiter(true)
+                .validator(validators::validate_ip_address)
+                .help(BYPASS_DNS_SERVERS_HELP),
+        )
         .arg(
             Arg::with_name("clandestine-port")
                 .long("clandestine-port")
@@ -217,11 +428,75 @@ fn app() -> App<'static, 'static> {
                 .validator(validators::validate_ip_address)
                 .help(DNS_SERVERS_HELP),
         )
+        .arg(
+            Arg::with_name("dns-protocol")
+                .long("dns-protocol")
+                .value_name("DNS-PROTOCOL")
+                .takes_value(true)
+                .default_value("udp")
+                .possible_values(&["udp", "tls", "https"])
+                .help(DNS_PROTOCOL_HELP),
+        )
+        .arg(
+            Arg::with_name("dns-tls-name")
+                .long("dns-tls-name")
+                .value_name("DNS-TLS-NAME")
+                .takes_value(true)
+                .required_ifs(&[("dns-protocol", "tls"), ("dns-protocol", "https")])
+                .help(DNS_TLS_NAME_HELP),
+        )
         .arg(earning_wallet_arg(
             EARNING_WALLET_HELP,
             common_validators::validate_ethereum_address,
         ))
         .arg(chain_arg())
+        .arg(
+            Arg::with_name("entry-dns")
+                .long("entry-dns")
+                .value_name("ENTRY-DNS")
+                .takes_value(true)
+                .default_value("localhost")
+                .possible_values(&["localhost", "tunnel", "resolve"])
+                .requires_if("resolve", "proxy-port")
+                .help(ENTRY_DNS_HELP),
+        )
+        .arg(
+            Arg::with_name("exit-allow")
+                .long("exit-allow")
+                .value_name("EXIT-RULES")
+                .takes_value(true)
+                .use_delimiter(true)
+                .validator(validators::validate_exit_rule)
+                .help(EXIT_ALLOW_HELP),
+        )
+        .arg(
+            Arg::with_name("exit-deny")
+                .long("exit-deny")
+                .value_name("EXIT-RULES")
+                .takes_value(true)
+                .use_delimiter(true)
+                .default_value(PRIVATE_NETWORKS_RULE)
+                .validator(validators::validate_exit_rule)
+                .help(EXIT_DENY_HELP),
+        )
+        .arg(
+            Arg::with_name("exit-pool-idle-timeout")
+                .long("exit-pool-idle-timeout")
+                .value_name("SECONDS")
+                .takes_value(true)
+                .default_value("4")
+                .validator(validators::validate_exit_pool_idle_timeout)
+                .help(EXIT_POOL_IDLE_TIMEOUT_HELP),
+        )
+        .arg(
+            Arg::with_name("exit-pool-size")
+                .long("exit-pool-size")
+                .value_name("EXIT-POOL-SIZE")
+                .takes_value(true)
+                .default_value("0")
+                .validator(validators::validate_exit_pool_size)
+                .help(EXIT_POOL_SIZE_HELP),
+        )
         .arg(
             Arg::with_name("fake-public-key")
                 .long("fake-public-key")
@@ -252,17 +527,69 @@ fn app() -> App<'static, 'static> {
                 .long("log-level")
                 .value_name("FILTER")
                 .takes_value(true)
-                .possible_values(&["off", "error", "warn", "info", "debug", "trace"])
                 .default_value("warn")
-                .case_insensitive(true)
+                .validator(validators::validate_log_level)
                 .help(LOG_LEVEL_HELP),
         )
+        .arg(
+            Arg::with_name("log-format")
+                .long("log-format")
+                .value_name("LOG-FORMAT")
+                .takes_value(true)
+                .possible_values(&["text", "json"])
+                .default_value("text")
+                .help(LOG_FORMAT_HELP),
+        )
+        .arg(
+            Arg::with_name("log-retention")
+                .long("log-retention")
+                .value_name("COUNT")
+                .takes_value(true)
+                .default_value(&DEFAULT_LOG_RETENTION_VALUE)
+                .validator(validators::validate_log_retention)
+                .help(LOG_RETENTION_HELP),
+        )
+        .arg(
+            Arg::with_name("log-rotation-age")
+                .long("log-rotation-age")
+                .value_name("AGE")
+                .takes_value(true)
+                .possible_values(&["hour", "day"])
+                .conflicts_with("log-rotation-size")
+                .help(LOG_ROTATION_AGE_HELP),
+        )
+        .arg(
+            Arg::with_name("log-rotation-size")
+                .long("log-rotation-size")
+                .value_name("MEGABYTES")
+                .takes_value(true)
+                .validator(validators::validate_log_rotation_size)
+                .help(&LOG_ROTATION_SIZE_HELP),
+        )
+        .arg(
+            Arg::with_name("metrics")
+                .long("metrics")
+                .value_name("ON-OFF")
+                .takes_value(true)
+                .possible_values(&["on", "off"])
+                .default_value("off")
+                .help(METRICS_HELP),
+        )
+        .arg(
+            Arg::with_name("metrics-port")
+                .long("metrics-port")
+                .value_name("METRICS-PORT")
+                .takes_value(true)
+                .default_value(&DEFAULT_METRICS_PORT_VALUE)
+                .validator(validators::validate_ui_port)
+                .help(&METRICS_PORT_HELP),
+        )
         .arg(
             Arg::with_name("neighborhood-mode")
                 .long("neighborhood-mode")
                 .value_name("NEIGHBORHOOD-MODE")
                 .takes_value(true)
-                .possible_values(&["zero-hop", "originate-only", "consume-only", "standard"])
+                .possible_values(&NEIGHBORHOOD_MODES)
                 .default_value("standard")
                 .case_insensitive(true)
                 .help(NEIGHBORHOOD_MODE_HELP),
@@ -275,7 +602,50 @@ fn app() -> App<'static, 'static> {
                 .use_delimiter(true)
                 .help(NEIGHBORS_HELP),
         )
+        .arg(
+            Arg::with_name("privacy-logging")
+                .long("privacy-logging")
+                .value_name("ON-OFF")
+                .takes_value(true)
+                .possible_values(&["on", "off"])
+                .default_value("off")
+                .help(PRIVACY_LOGGING_HELP),
+        )
+        .arg(
+            Arg::with_name("proxy-port")
+                .long("proxy-port")
+                .value_name("PROXY-PORT")
+                .takes_value(true)
+                .validator(validators::validate_proxy_port)
+                .help(PROXY_PORT_HELP),
+        )
+        .arg(
+            Arg::with_name("rate-pack")
+                .long("rate-pack")
+                .value_name("RATE-PACK")
+                .takes_value(true)
+                .validator(validators::validate_rate_pack)
+                .help(RATE_PACK_HELP),
+        )
         .arg(real_user_arg())
+        .arg(
+            Arg::with_name("route-paths")
+                .long("route-paths")
+                .value_name("ROUTE-PATHS")
+                .takes_value(true)
+                .default_value("1")
+                .validator(validators::validate_route_paths)
+                .help(ROUTE_PATHS_HELP),
+        )
+        .arg(
+            Arg::with_name("stream-tracing")
+                .long("stream-tracing")
+                .value_name("ON-OFF")
+                .takes_value(true)
+                .possible_values(&["on", "off"])
+                .default_value("off")
+                .help(STREAM_TRACING_HELP),
+        )
         .arg(
             Arg::with_name("ui-port")
                 .long("ui-port")
@@ -294,12 +664,12 @@ mod standard {
     use std::net::SocketAddr;
 
     use clap::{value_t, values_t};
-    use log::LevelFilter;
 
     use crate::blockchain::bip32::Bip32ECKeyPair;
     use crate::blockchain::bip39::{Bip39, Bip39Error};
     use crate::blockchain::blockchain_interface::chain_id_from_name;
     use crate::bootstrapper::PortConfiguration;
+    use crate::entry_dns::dns_socket_server::DOH_PORT;
     use crate::http_request_start_finder::HttpRequestDiscriminatorFactory;
     use crate::multi_config::{CommandLineVcl, ConfigFileVcl, EnvironmentVcl, MultiConfig};
     use crate::node_configurator::{
@@ -307,16 +677,25 @@ mod standard {
         request_wallet_decryption_password,
     };
     use crate::persistent_configuration::{PersistentConfiguration, HTTP_PORT, TLS_PORT};
+    use crate::server_initializer::{LogFormat, LogLevelSpec, LogRotation, LogRotationTrigger};
     use crate::sub_lib::accountant::DEFAULT_EARNING_WALLET;
+    use crate::sub_lib::bypass::BypassList;
     use crate::sub_lib::cryptde::{PlainData, PublicKey};
     use crate::sub_lib::cryptde_null::CryptDENull;
-    use crate::sub_lib::neighborhood::{NeighborhoodConfig, NeighborhoodMode, DEFAULT_RATE_PACK};
+    use crate::sub_lib::entry_dns::EntryDnsMode;
+    use crate::sub_lib::exit_policy::{ExitPolicy, ExitRule};
+    use crate::sub_lib::neighborhood::{
+        NeighborhoodConfig, NeighborhoodMode, RatePack, DEFAULT_RATE_PACK,
+    };
     use crate::sub_lib::node_addr::NodeAddr;
+    use crate::sub_lib::proxy_client::{ConnectionPoolConfig, DnsProtocol};
     use crate::sub_lib::wallet::Wallet;
     use crate::tls_discriminator_factory::TlsDiscriminatorFactory;
+    use dns_utility_lib::dns_modifier_factory::{DnsModifierFactory, DnsModifierFactoryReal};
     use rustc_hex::{FromHex, ToHex};
     use std::convert::TryInto;
     use std::str::FromStr;
+    use std::time::Duration;
 
     pub fn make_service_mode_multi_config<'a>(app: &'a App, args: &Vec<String>) -> MultiConfig<'a> {
         let (config_file_path, user_specified) = determine_config_file_path(app, args);
@@ -325,7 +704,11 @@ mod standard {
             vec![
                 Box::new(CommandLineVcl::new(args.clone())),
                 Box::new(EnvironmentVcl::new(&app)),
-                Box::new(ConfigFileVcl::new(&config_file_path, user_specified)),
+                Box::new(ConfigFileVcl::new(
+                    &config_file_path,
+                    user_specified,
+                    CONFIG_FILE_SECTIONS,
+                )),
             ],
         )
     }
@@ -368,22 +751,77 @@ mod standard {
         config.data_directory = data_directory;
         config.blockchain_bridge_config.chain_id = chain_id;
 
+        config.dns_protocol = dns_protocol(multi_config);
+        let dns_port = config.dns_protocol.default_port();
         config.dns_servers = values_m!(multi_config, "dns-servers", IpAddr)
             .into_iter()
-            .map(|ip| SocketAddr::from((ip, 53)))
+            .map(|ip| SocketAddr::from((ip, dns_port)))
             .collect();
 
         config.log_level =
-            value_m!(multi_config, "log-level", LevelFilter).expect("Internal Error");
+            value_m!(multi_config, "log-level", LogLevelSpec).expect("Internal Error");
+        config.log_format =
+            value_m!(multi_config, "log-format", LogFormat).expect("Internal Error");
+        config.log_rotation = log_rotation(multi_config);
+        config.privacy_logging =
+            value_m!(multi_config, "privacy-logging", String).expect("Internal Error") == "on";
+        config.stream_tracing =
+            value_m!(multi_config, "stream-tracing", String).expect("Internal Error") == "on";
 
         config.neighborhood_config = make_neighborhood_config(multi_config);
 
         config.ui_gateway_config.ui_port =
             value_m!(multi_config, "ui-port", u16).expect("Internal Error");
 
+        config.ui_gateway_config.metrics_port_opt =
+            if value_m!(multi_config, "metrics", String).expect("Internal Error") == "on" {
+                value_m!(multi_config, "metrics-port", u16)
+            } else {
+                None
+            };
+
         config.crash_point =
             value_m!(multi_config, "crash-point", CrashPoint).expect("Internal Error");
 
+        config.route_path_count =
+            value_m!(multi_config, "route-paths", usize).expect("Internal Error");
+
+        config.exit_policy = ExitPolicy::new(
+            exit_rules(values_m!(multi_config, "exit-allow", String)),
+            exit_rules(values_m!(multi_config, "exit-deny", String)),
+        );
+
+        config.exit_connection_pool = exit_connection_pool(multi_config);
+
+        config.entry_dns_mode = entry_dns_mode(multi_config);
+
+        config.bypass_list = BypassList::new(
+            values_m!(multi_config, "bypass", String)
+                .into_iter()
+                .map(|value| BypassList::parse_rule(&value).expect("Internal Error"))
+                .collect(),
+        );
+        config.bypass_dns_servers = bypass_dns_servers(multi_config, &config.bypass_list);
+
+        config.proxy_port_opt = value_m!(multi_config, "proxy-port", u16);
+        if let Some(proxy_port) = config.proxy_port_opt {
+            let mut other_ports = vec![
+                ("--ui-port", config.ui_gateway_config.ui_port),
+                ("the DNS-over-HTTPS port", DOH_PORT),
+            ];
+            if let Some(metrics_port) = config.ui_gateway_config.metrics_port_opt {
+                other_ports.push(("--metrics-port", metrics_port));
+            }
+            check_proxy_port(proxy_port, &other_ports);
+            config.port_configurations.insert(
+                proxy_port,
+                PortConfiguration::new(
+                    vec![Box::new(HttpRequestDiscriminatorFactory::new())],
+                    false,
+                ),
+            );
+        }
+
         match value_m!(multi_config, "fake-public-key", String) {
             None => (),
             Some(public_key_str) => {
@@ -398,6 +836,103 @@ mod standard {
         }
     }
 
+    fn dns_protocol(multi_config: &MultiConfig) -> DnsProtocol {
+        let tls_name_opt = value_m!(multi_config, "dns-tls-name", String);
+        match value_m!(multi_config, "dns-protocol", String)
+            .expect("Internal Error")
+            .as_str()
+        {
+            "tls" => DnsProtocol::Tls(tls_name_opt.expect("Internal Error")),
+            "https" => DnsProtocol::Https(tls_name_opt.expect("Internal Error")),
+            _ => DnsProtocol::Udp,
+        }
+    }
+
+    fn log_rotation(multi_config: &MultiConfig) -> LogRotation {
+        let trigger = match value_m!(multi_config, "log-rotation-age", String) {
+            Some(ref age) if age == "hour" => LogRotationTrigger::Hourly,
+            Some(_) => LogRotationTrigger::Daily,
+            None => LogRotationTrigger::Size(
+                value_m!(multi_config, "log-rotation-size", u64)
+                    .unwrap_or(DEFAULT_LOG_ROTATION_MEGABYTES)
+                    * 1_000_000,
+            ),
+        };
+        LogRotation {
+            trigger,
+            keep: value_m!(multi_config, "log-retention", usize).expect("Internal Error"),
+        }
+    }
+
+    fn exit_connection_pool(multi_config: &MultiConfig) -> Option<ConnectionPoolConfig> {
+        match value_m!(multi_config, "exit-pool-size", usize).expect("Internal Error") {
+            0 => None,
+            max_idle_per_server => Some(ConnectionPoolConfig {
+                max_idle_per_server,
+                idle_timeout: Duration::from_secs(
+                    value_m!(multi_config, "exit-pool-idle-timeout", u64).expect("Internal Error"),
+                ),
+            }),
+        }
+    }
+
+    fn entry_dns_mode(multi_config: &MultiConfig) -> EntryDnsMode {
+        match value_m!(multi_config, "entry-dns", String)
+            .expect("Internal Error")
+            .as_str()
+        {
+            "tunnel" => EntryDnsMode::Tunnel,
+            "resolve" => EntryDnsMode::Resolve,
+            _ => EntryDnsMode::Localhost,
+        }
+    }
+
+    fn bypass_dns_servers(multi_config: &MultiConfig, bypass_list: &BypassList) -> Vec<SocketAddr> {
+        let explicit_ip_addrs = values_m!(multi_config, "bypass-dns-servers", IpAddr);
+        let ip_addrs = if !explicit_ip_addrs.is_empty() {
+            explicit_ip_addrs
+        } else if bypass_list.is_empty() {
+            vec![]
+        } else {
+            original_nameservers()
+        };
+        ip_addrs
+            .into_iter()
+            .map(|ip| SocketAddr::from((ip, 53)))
+            .collect()
+    }
+
+    fn original_nameservers() -> Vec<IpAddr> {
+        let dns_modifier = DnsModifierFactoryReal::new()
+            .make()
+            .expect("Can't find this system's DNS settings for --bypass: please supply --bypass-dns-servers");
+        dns_modifier.original_nameservers().unwrap_or_else(|e| {
+            panic!(
+                "Can't find this system's original DNS servers to use for --bypass ({}): please supply --bypass-dns-servers",
+                e
+            )
+        })
+    }
+
+    // The clandestine port may come from the database, so it's checked later, in
+    // unprivileged_parse_args.
+    fn check_proxy_port(proxy_port: u16, other_ports: &[(&str, u16)]) {
+        if let Some((name, _)) = other_ports.iter().find(|(_, port)| *port == proxy_port) {
+            panic!(
+                "--proxy-port {} cannot be the same as {}: please choose another port",
+                proxy_port, name
+            )
+        }
+    }
+
+    fn exit_rules(values: Vec<String>) -> Vec<ExitRule> {
+        values
+            .into_iter()
+            .filter(|value| value != NO_EXIT_RULES)
+            .map(|value| ExitRule::from_str(&value).expect("Internal Error"))
+            .collect()
+    }
+
     pub fn unprivileged_parse_args(
         multi_config: &MultiConfig,
         unprivileged_config: &mut BootstrapperConfig,
@@ -405,6 +940,12 @@ mod standard {
         persistent_config: &dyn PersistentConfiguration,
     ) {
         unprivileged_config.clandestine_port_opt = value_m!(multi_config, "clandestine-port", u16);
+        if let Some(proxy_port) = value_m!(multi_config, "proxy-port", u16) {
+            let clandestine_port = unprivileged_config
+                .clandestine_port_opt
+                .unwrap_or_else(|| persistent_config.clandestine_port());
+            check_proxy_port(proxy_port, &[("--clandestine-port", clandestine_port)]);
+        }
         unprivileged_config.blockchain_bridge_config.gas_price =
             value_m!(multi_config, "gas-price", u64);
         get_wallets(
@@ -492,12 +1033,13 @@ mod standard {
 
     pub fn make_neighborhood_config(multi_config: &MultiConfig) -> NeighborhoodConfig {
         let neighbor_configs = values_m!(multi_config, "neighbors", String);
+        let rate_pack = value_m!(multi_config, "rate-pack", RatePack).unwrap_or(DEFAULT_RATE_PACK);
         match value_m! (multi_config, "neighborhood-mode", String) {
             Some (ref s) if s == "standard" => NeighborhoodConfig {
                 mode: NeighborhoodMode::Standard (
                 NodeAddr::new (&value_m! (multi_config, "ip", IpAddr).expect ("Node cannot run as --neighborhood_mode standard without --ip specified"), &vec![]),
                 neighbor_configs,
-                DEFAULT_RATE_PACK,
+                rate_pack,
             )},
             Some (ref s) if s == "originate-only" => {
                 if neighbor_configs.is_empty () {
@@ -506,7 +1048,7 @@ mod standard {
                 NeighborhoodConfig {
                     mode: NeighborhoodMode::OriginateOnly (
                     neighbor_configs,
-                    DEFAULT_RATE_PACK,
+                    rate_pack,
                 )}
             },
             Some (ref s) if s == "consume-only" => {
@@ -642,8 +1184,60 @@ mod standard {
     }
 }
 
+// The command line's rules, applied to settings that UIs change while the Node runs
+pub mod reconfiguration {
+    use super::*;
+    use crate::server_initializer::LogLevelSpec;
+    use crate::sub_lib::neighborhood::RatePack;
+    use crate::sub_lib::wallet::Wallet;
+    use std::net::IpAddr;
+    use std::str::FromStr;
+
+    pub fn rate_pack(value: &str) -> Result<RatePack, String> {
+        RatePack::from_str(value)
+    }
+
+    pub fn neighborhood_mode(value: &str) -> Result<String, String> {
+        let mode = value.to_lowercase();
+        if NEIGHBORHOOD_MODES.contains(&mode.as_str()) {
+            Ok(mode)
+        } else {
+            Err(format!(
+                "Invalid neighborhood mode '{}': choose from {}",
+                value,
+                NEIGHBORHOOD_MODES.join(", ")
+            ))
+        }
+    }
+
+    pub fn log_level(value: &str) -> Result<LogLevelSpec, String> {
+        LogLevelSpec::from_str(value)
+    }
+
+    pub fn dns_servers(value: &str) -> Result<Vec<IpAddr>, String> {
+        value
+            .split(',')
+            .map(|address| {
+                validators::validate_ip_address(address.trim().to_string())
+                    .map(|_| IpAddr::from_str(address.trim()).expect("Validation failed"))
+                    .map_err(|address| format!("Invalid DNS server IP address '{}'", address))
+            })
+            .collect()
+    }
+
+    pub fn earning_wallet(value: &str) -> Result<Wallet, String> {
+        common_validators::validate_ethereum_address(value.to_string())
+            .map(|_| Wallet::from_str(value).expect("Validation failed"))
+            .map_err(|address| format!("Invalid earning wallet address '{}'", address))
+    }
+}
+
 mod validators {
     use super::*;
+    use crate::server_initializer::LogLevelSpec;
+    use crate::sub_lib::bypass::BypassList;
+    use crate::sub_lib::exit_policy::ExitRule;
+    use crate::sub_lib::neighborhood::RatePack;
     use regex::Regex;
     use std::net::IpAddr;
     use std::str::FromStr;
@@ -663,6 +1257,73 @@ mod validators {
         }
     }
 
+    pub fn validate_exit_rule(rule: String) -> Result<(), String> {
+        if rule == NO_EXIT_RULES {
+            return Ok(());
+        }
+        match ExitRule::from_str(&rule) {
+            Ok(_) => Ok(()),
+            Err(_) => Err(rule),
+        }
+    }
+
+    pub fn validate_bypass_rule(rule: String) -> Result<(), String> {
+        match BypassList::parse_rule(&rule) {
+            Ok(_) => Ok(()),
+            Err(_) => Err(rule),
+        }
+    }
+
+    pub fn validate_rate_pack(rate_pack: String) -> Result<(), String> {
+        RatePack::from_str(&rate_pack).map(|_| ())
+    }
+
+    pub fn validate_log_level(spec: String) -> Result<(), String> {
+        LogLevelSpec::from_str(&spec).map(|_| ())
+    }
+
+    pub fn validate_log_rotation_size(megabytes: String) -> Result<(), String> {
+        match megabytes.parse::<u64>() {
+            Ok(size) if size >= 1 => Ok(()),
+            _ => Err(megabytes),
+        }
+    }
+
+    pub fn validate_log_retention(count: String) -> Result<(), String> {
+        match count.parse::<usize>() {
+            Ok(count) if count >= 1 => Ok(()),
+            _ => Err(count),
+        }
+    }
+
+    pub fn validate_route_paths(route_paths: String) -> Result<(), String> {
+        match route_paths.parse::<usize>() {
+            Ok(count) if count >= 1 && count <= MAX_ROUTE_PATH_COUNT => Ok(()),
+            _ => Err(route_paths),
+        }
+    }
+
+    pub fn validate_exit_pool_size(size: String) -> Result<(), String> {
+        match size.parse::<usize>() {
+            Ok(count) if count <= 64 => Ok(()),
+            _ => Err(size),
+        }
+    }
+
+    pub fn validate_exit_pool_idle_timeout(seconds: String) -> Result<(), String> {
+        match seconds.parse::<u64>() {
+            Ok(timeout) if timeout >= 1 && timeout <= 300 => Ok(()),
+            _ => Err(seconds),
+        }
+    }
+
+    pub fn validate_proxy_port(proxy_port: String) -> Result<(), String> {
+        match proxy_port.parse::<u16>() {
+            Ok(port) if port >= LOWEST_USABLE_INSECURE_PORT => Ok(()),
+            _ => Err(proxy_port),
+        }
+    }
+
     pub fn validate_clandestine_port(clandestine_port: String) -> Result<(), String> {
         match clandestine_port.parse::<u16>() {
             Ok(clandestine_port) if clandestine_port >= LOWEST_USABLE_INSECURE_PORT => Ok(()),
@@ -706,12 +1367,17 @@ mod tests {
         CommandLineVcl, ConfigFileVcl, MultiConfig, NameValueVclArg, VclArg, VirtualCommandLine,
     };
     use crate::persistent_configuration::PersistentConfigurationReal;
+    use crate::server_initializer::{LogFormat, LogLevelSpec, LogRotation, LogRotationTrigger};
     use crate::sub_lib::accountant::DEFAULT_EARNING_WALLET;
+    use crate::sub_lib::bypass::BypassList;
     use crate::sub_lib::crash_point::CrashPoint;
     use crate::sub_lib::cryptde::{CryptDE, PlainData, PublicKey};
     use crate::sub_lib::cryptde_null::CryptDENull;
+    use crate::sub_lib::entry_dns::EntryDnsMode;
+    use crate::sub_lib::exit_policy::{ExitPolicy, ExitRule};
     use crate::sub_lib::neighborhood::{NeighborhoodConfig, NeighborhoodMode, DEFAULT_RATE_PACK};
     use crate::sub_lib::node_addr::NodeAddr;
+    use crate::sub_lib::proxy_client::{ConnectionPoolConfig, DnsProtocol};
     use crate::sub_lib::wallet::Wallet;
     use crate::test_utils::environment_guard::EnvironmentGuard;
     use crate::test_utils::persistent_configuration_mock::PersistentConfigurationMock;
@@ -730,6 +1396,7 @@ mod tests {
     use std::path::PathBuf;
     use std::str::FromStr;
     use std::sync::{Arc, Mutex};
+    use std::time::Duration;
 
     fn make_default_cli_params() -> ArgsBuilder {
         ArgsBuilder::new()
@@ -737,6 +1404,69 @@ mod tests {
             .param("--ip", "1.2.3.4")
     }
 
+    #[test]
+    fn reconfiguration_accepts_what_the_command_line_accepts() {
+        assert_eq!(
+            reconfiguration::rate_pack("1|2|3|4"),
+            Ok(crate::sub_lib::neighborhood::RatePack {
+                routing_byte_rate: 1,
+                routing_service_rate: 2,
+                exit_byte_rate: 3,
+                exit_service_rate: 4,
+            })
+        );
+        assert_eq!(
+            reconfiguration::neighborhood_mode("Originate-Only"),
+            Ok("originate-only".to_string())
+        );
+        assert_eq!(
+            reconfiguration::log_level("DEBUG,Hopper=trace"),
+            Ok(LogLevelSpec {
+                default: log::LevelFilter::Debug,
+                loggers: vec![("Hopper".to_string(), log::LevelFilter::Trace)],
+            })
+        );
+        assert_eq!(
+            reconfiguration::dns_servers("1.1.1.1, 8.8.8.8"),
+            Ok(vec![
+                IpAddr::from_str("1.1.1.1").unwrap(),
+                IpAddr::from_str("8.8.8.8").unwrap()
+            ])
+        );
+        assert_eq!(
+            reconfiguration::earning_wallet("0xbDfeFf9A1f4A1bdF483d680046344316019C58CF"),
+            Ok(Wallet::from_str("0xbDfeFf9A1f4A1bdF483d680046344316019C58CF").unwrap())
+        );
+    }
+
+    #[test]
+    fn reconfiguration_refuses_what_the_command_line_refuses() {
+        assert!(reconfiguration::rate_pack("1|2|3").is_err());
+        assert_eq!(
+            reconfiguration::neighborhood_mode("sometimes"),
+            Err("Invalid neighborhood mode 'sometimes': choose from zero-hop, originate-only, consume-only, standard".to_string())
+        );
+        assert_eq!(
+            reconfiguration::log_level("loud"),
+            Err(
+                "Invalid log level 'loud': choose from off, error, warn, info, debug, trace"
+                    .to_string()
+            )
+        );
+        assert_eq!(
+            reconfiguration::dns_servers("1.1.1.1,booga"),
+            Err("Invalid DNS server IP address 'booga'".to_string())
+        );
+        assert_eq!(
+            reconfiguration::dns_servers(""),
+            Err("Invalid DNS server IP address ''".to_string())
+        );
+        assert_eq!(
+            reconfiguration::earning_wallet("m/44'/60'/0'/0/0"),
+            Err("Invalid earning wallet address 'm/44'/60'/0'/0/0'".to_string())
+        );
+    }
+
     #[test]
     fn validate_private_key_requires_a_key_that_is_64_characters_long() {
         let result = validators::validate_private_key(String::from("42"));
@@ -810,50 +1540,222 @@ mod tests {
     }
 
     #[test]
-    fn validate_clandestine_port_rejects_badly_formatted_port_number() {
-        let result = validators::validate_clandestine_port(String::from("booga"));
-
-        assert_eq!(Err(String::from("booga")), result);
+    fn validate_exit_rule_accepts_well_formed_rules_and_none() {
+        vec![
+            "10.0.0.0/8",
+            "1.2.3.4",
+            "port:25",
+            "port:6660-6669",
+            "*.example.com",
+            "private",
+            "none",
+        ]
+        .into_iter()
+        .for_each(|rule| {
+            assert_eq!(
+                validators::validate_exit_rule(String::from(rule)),
+                Ok(()),
+                "{}",
+                rule
+            )
+        });
     }
 
     #[test]
-    fn validate_clandestine_port_rejects_port_number_too_low() {
-        let result = validators::validate_clandestine_port(String::from("1024"));
-
-        assert_eq!(Err(String::from("1024")), result);
+    fn validate_exit_rule_rejects_malformed_rules() {
+        assert_eq!(
+            validators::validate_exit_rule(String::from("10.0.0.0/33")),
+            Err(String::from("10.0.0.0/33"))
+        );
+        assert_eq!(
+            validators::validate_exit_rule(String::from("port:booga")),
+            Err(String::from("port:booga"))
+        );
     }
 
     #[test]
-    fn validate_clandestine_port_rejects_port_number_too_high() {
-        let result = validators::validate_clandestine_port(String::from("65536"));
-
-        assert_eq!(Err(String::from("65536")), result);
+    fn validate_bypass_rule_rejects_port_rules() {
+        assert_eq!(
+            validators::validate_bypass_rule(String::from("intranet.example.com")),
+            Ok(())
+        );
+        assert_eq!(
+            validators::validate_bypass_rule(String::from("port:22")),
+            Err(String::from("port:22"))
+        );
     }
 
     #[test]
-    fn validate_clandestine_port_accepts_port_if_provided() {
-        let result = validators::validate_clandestine_port(String::from("4567"));
-
-        assert!(result.is_ok());
-        assert_eq!(Ok(()), result);
+    fn validate_exit_pool_size_accepts_0_through_64() {
+        assert_eq!(
+            validators::validate_exit_pool_size(String::from("0")),
+            Ok(())
+        );
+        assert_eq!(
+            validators::validate_exit_pool_size(String::from("64")),
+            Ok(())
+        );
+        assert_eq!(
+            validators::validate_exit_pool_size(String::from("65")),
+            Err(String::from("65"))
+        );
+        assert_eq!(
+            validators::validate_exit_pool_size(String::from("booga")),
+            Err(String::from("booga"))
+        );
     }
 
     #[test]
-    fn validate_gas_price_zero() {
-        let result = validators::validate_gas_price("0".to_string());
-
-        assert!(result.is_err());
-        assert_eq!(Err(String::from("0")), result);
+    fn validate_exit_pool_idle_timeout_accepts_1_through_300() {
+        assert_eq!(
+            validators::validate_exit_pool_idle_timeout(String::from("1")),
+            Ok(())
+        );
+        assert_eq!(
+            validators::validate_exit_pool_idle_timeout(String::from("300")),
+            Ok(())
+        );
+        assert_eq!(
+            validators::validate_exit_pool_idle_timeout(String::from("0")),
+            Err(String::from("0"))
+        );
+        assert_eq!(
+            validators::validate_exit_pool_idle_timeout(String::from("301")),
+            Err(String::from("301"))
+        );
     }
 
     #[test]
-    fn validate_gas_price_normal_ropsten() {
-        let result = validators::validate_gas_price("2".to_string());
-
-        assert!(result.is_ok());
-        assert_eq!(Ok(()), result);
-    }
-
+    fn validate_proxy_port_rejects_privileged_ports() {
+        assert_eq!(
+            validators::validate_proxy_port(String::from("1025")),
+            Ok(())
+        );
+        assert_eq!(
+            validators::validate_proxy_port(String::from("8888")),
+            Ok(())
+        );
+        assert_eq!(
+            validators::validate_proxy_port(String::from("443")),
+            Err(String::from("443"))
+        );
+        assert_eq!(
+            validators::validate_proxy_port(String::from("65536")),
+            Err(String::from("65536"))
+        );
+    }
+
+    #[test]
+    fn validate_route_paths_rejects_out_of_range_counts() {
+        assert_eq!(
+            validators::validate_route_paths(String::from("0")),
+            Err(String::from("0"))
+        );
+        assert_eq!(
+            validators::validate_route_paths(String::from("9")),
+            Err(String::from("9"))
+        );
+        assert_eq!(
+            validators::validate_route_paths(String::from("booga")),
+            Err(String::from("booga"))
+        );
+    }
+
+    #[test]
+    fn validate_route_paths_accepts_counts_in_range() {
+        assert_eq!(validators::validate_route_paths(String::from("1")), Ok(()));
+        assert_eq!(validators::validate_route_paths(String::from("8")), Ok(()));
+    }
+
+    #[test]
+    fn validate_log_level_accepts_levels_for_particular_loggers() {
+        assert_eq!(
+            validators::validate_log_level(String::from("info,ProxyServer=debug")),
+            Ok(())
+        );
+        assert_eq!(
+            validators::validate_log_level(String::from("info,ProxyServer")),
+            Err("More than one default log level in 'info,ProxyServer'".to_string())
+        );
+    }
+
+    #[test]
+    fn validate_log_rotation_size_rejects_zero_and_garbage() {
+        assert_eq!(
+            validators::validate_log_rotation_size(String::from("0")),
+            Err(String::from("0"))
+        );
+        assert_eq!(
+            validators::validate_log_rotation_size(String::from("big")),
+            Err(String::from("big"))
+        );
+        assert_eq!(
+            validators::validate_log_rotation_size(String::from("25")),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn validate_log_retention_rejects_zero_and_garbage() {
+        assert_eq!(
+            validators::validate_log_retention(String::from("0")),
+            Err(String::from("0"))
+        );
+        assert_eq!(
+            validators::validate_log_retention(String::from("-1")),
+            Err(String::from("-1"))
+        );
+        assert_eq!(
+            validators::validate_log_retention(String::from("10")),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn validate_clandestine_port_rejects_badly_formatted_port_number() {
+        let result = validators::validate_clandestine_port(String::from("booga"));
+
+        assert_eq!(Err(String::from("booga")), result);
+    }
+
+    #[test]
+    fn validate_clandestine_port_rejects_port_number_too_low() {
+        let result = validators::validate_clandestine_port(String::from("1024"));
+
+        assert_eq!(Err(String::from("1024")), result);
+    }
+
+    #[test]
+    fn validate_clandestine_port_rejects_port_number_too_high() {
+        let result = validators::validate_clandestine_port(String::from("65536"));
+
+        assert_eq!(Err(String::from("65536")), result);
+    }
+
+    #[test]
+    fn validate_clandestine_port_accepts_port_if_provided() {
+        let result = validators::validate_clandestine_port(String::from("4567"));
+
+        assert!(result.is_ok());
+        assert_eq!(Ok(()), result);
+    }
+
+    #[test]
+    fn validate_gas_price_zero() {
+        let result = validators::validate_gas_price("0".to_string());
+
+        assert!(result.is_err());
+        assert_eq!(Err(String::from("0")), result);
+    }
+
+    #[test]
+    fn validate_gas_price_normal_ropsten() {
+        let result = validators::validate_gas_price("2".to_string());
+
+        assert!(result.is_ok());
+        assert_eq!(Ok(()), result);
+    }
+
     #[test]
     fn validate_gas_price_normal_mainnet() {
         let result = validators::validate_gas_price("20".to_string());
@@ -923,6 +1825,147 @@ mod tests {
         );
     }
 
+    #[test]
+    fn make_neighborhood_config_standard_takes_rate_pack() {
+        let multi_config = MultiConfig::new(
+            &app(),
+            vec![Box::new(CommandLineVcl::new(
+                ArgsBuilder::new()
+                    .param("--neighborhood-mode", "standard")
+                    .param("--ip", "1.2.3.4")
+                    .param("--rate-pack", "1|2|3|4")
+                    .into(),
+            ))],
+        );
+
+        let result = standard::make_neighborhood_config(&multi_config);
+
+        assert_eq!(
+            result.mode.rate_pack(),
+            &RatePack {
+                routing_byte_rate: 1,
+                routing_service_rate: 2,
+                exit_byte_rate: 3,
+                exit_service_rate: 4,
+            }
+        );
+    }
+
+    #[test]
+    fn validate_rate_pack_requires_four_whole_numbers() {
+        assert_eq!(
+            validators::validate_rate_pack("1|2|3|4".to_string()),
+            Ok(())
+        );
+        assert!(validators::validate_rate_pack("1|2|3".to_string()).is_err());
+        assert!(validators::validate_rate_pack("1|2|3|x".to_string()).is_err());
+    }
+
+    #[test]
+    fn structured_config_file_is_read_like_the_command_line() {
+        let home_dir = ensure_node_home_directory_exists(
+            "node_configurator",
+            "structured_config_file_is_read_like_the_command_line",
+        );
+        let config_file_path = home_dir.join("config.toml");
+        {
+            let mut config_file = File::create(&config_file_path).unwrap();
+            config_file
+                .write_all(
+                    indoc!(
+                        r#"
+                        [neighborhood]
+                        mode = "standard"
+                        ip = "1.2.3.4"
+                        neighbors = ["QmlsbA:1.2.3.4:1234;2345", "VGVk:2.3.4.5:3456;4567"]
+
+                        [rate_pack]
+                        routing_byte_rate = 1
+                        routing_service_rate = 2
+                        exit_byte_rate = 3
+                        exit_service_rate = 4
+
+                        [dns]
+                        servers = ["1.1.1.1", "8.8.8.8"]
+                        bypass = ["intranet.example.com", "10.0.0.0/8"]
+                        bypass_servers = ["10.0.0.53"]
+
+                        [log]
+                        privacy = true
+
+                        [ui]
+                        port = 5335
+                        proxy_port = 8080
+                        metrics = true
+                        metrics_port = 5336
+                    "#
+                    )
+                    .as_bytes(),
+                )
+                .unwrap();
+        }
+        let args = ArgsBuilder::new().param("--data-directory", home_dir.to_str().unwrap());
+        let mut config = BootstrapperConfig::new();
+        let multi_config = MultiConfig::new(
+            &app(),
+            vec![
+                Box::new(CommandLineVcl::new(args.into())),
+                Box::new(ConfigFileVcl::new(
+                    &config_file_path,
+                    true,
+                    CONFIG_FILE_SECTIONS,
+                )),
+            ],
+        );
+
+        standard::privileged_parse_args(
+            &multi_config,
+            &mut config,
+            &mut FakeStreamHolder::new().streams(),
+        );
+
+        assert_eq!(
+            config.neighborhood_config,
+            NeighborhoodConfig {
+                mode: NeighborhoodMode::Standard(
+                    NodeAddr::new(&IpAddr::from_str("1.2.3.4").unwrap(), &vec![]),
+                    vec![
+                        "QmlsbA:1.2.3.4:1234;2345".to_string(),
+                        "VGVk:2.3.4.5:3456;4567".to_string()
+                    ],
+                    RatePack {
+                        routing_byte_rate: 1,
+                        routing_service_rate: 2,
+                        exit_byte_rate: 3,
+                        exit_service_rate: 4,
+                    }
+                )
+            }
+        );
+        assert_eq!(
+            config.dns_servers,
+            vec![
+                SocketAddr::from_str("1.1.1.1:53").unwrap(),
+                SocketAddr::from_str("8.8.8.8:53").unwrap()
+            ]
+        );
+        assert_eq!(
+            config.bypass_list,
+            BypassList::new(vec![
+                ExitRule::Host("*.intranet.example.com".to_string()),
+                ExitRule::Network(IpAddr::from_str("10.0.0.0").unwrap(), 8),
+            ])
+        );
+        assert_eq!(
+            config.bypass_dns_servers,
+            vec![SocketAddr::from_str("10.0.0.53:53").unwrap()]
+        );
+        assert!(config.privacy_logging);
+        assert_eq!(config.ui_gateway_config.ui_port, 5335);
+        assert_eq!(config.proxy_port_opt, Some(8080));
+        assert_eq!(config.ui_gateway_config.metrics_port_opt, Some(5336));
+    }
+
     #[test]
     #[should_panic(
         expected = "Node cannot run as --neighborhood_mode standard without --ip specified"
@@ -1158,7 +2201,11 @@ mod tests {
             &app(),
             vec![
                 Box::new(CommandLineVcl::new(args.into())),
-                Box::new(ConfigFileVcl::new(&config_file_path, false)),
+                Box::new(ConfigFileVcl::new(
+                    &config_file_path,
+                    false,
+                    CONFIG_FILE_SECTIONS,
+                )),
             ],
         );
 
@@ -1199,6 +2246,54 @@ mod tests {
         );
     }
 
+    #[test]
+    fn privileged_parse_args_takes_log_settings_from_config_file() {
+        let home_dir = ensure_node_home_directory_exists(
+            "node_configurator",
+            "privileged_parse_args_takes_log_settings_from_config_file",
+        );
+        let config_file_path = home_dir.join("config.toml");
+        {
+            let mut config_file = File::create(&config_file_path).unwrap();
+            writeln!(
+                config_file,
+                "log-format = \"json\"\nlog-rotation-age = \"hour\"\nlog-retention = 24\nprivacy-logging = \"on\""
+            )
+            .unwrap();
+        }
+        let args = ArgsBuilder::new()
+            .param("--data-directory", home_dir.to_str().unwrap())
+            .param("--ip", "1.2.3.4");
+        let mut config = BootstrapperConfig::new();
+        let multi_config = MultiConfig::new(
+            &app(),
+            vec![
+                Box::new(CommandLineVcl::new(args.into())),
+                Box::new(ConfigFileVcl::new(
+                    &config_file_path,
+                    false,
+                    CONFIG_FILE_SECTIONS,
+                )),
+            ],
+        );
+
+        standard::privileged_parse_args(
+            &multi_config,
+            &mut config,
+            &mut FakeStreamHolder::new().streams(),
+        );
+
+        assert_eq!(config.log_format, LogFormat::Json);
+        assert_eq!(
+            config.log_rotation,
+            LogRotation {
+                trigger: LogRotationTrigger::Hourly,
+                keep: 24,
+            }
+        );
+        assert!(config.privacy_logging);
+    }
+
     #[test]
     fn privileged_parse_args_creates_configurations() {
         let home_dir = ensure_node_home_directory_exists(
@@ -1208,6 +2303,8 @@ mod tests {
         let args = ArgsBuilder::new()
             .param("--config-file", "specified_config.toml")
             .param("--dns-servers", "12.34.56.78,23.45.67.89")
+            .param("--dns-protocol", "tls")
+            .param("--dns-tls-name", "dns.example.com")
             .param(
                 "--neighbors",
                 "QmlsbA:1.2.3.4:1234;2345,VGVk:2.3.4.5:3456;4567",
@@ -1215,9 +2312,14 @@ mod tests {
             .param("--ip", "34.56.78.90")
             .param("--clandestine-port", "1234")
             .param("--ui-port", "5335")
+            .param("--metrics", "on")
+            .param("--metrics-port", "5336")
             .param("--data-directory", home_dir.to_str().unwrap())
             .param("--blockchain-service-url", "http://127.0.0.1:8545")
-            .param("--log-level", "trace")
+            .param("--log-level", "trace,Dispatcher=warn")
+            .param("--log-format", "json")
+            .param("--log-rotation-size", "25")
+            .param("--log-retention", "10")
             .param("--fake-public-key", "AQIDBA")
             .param("--wallet-password", "secret-wallet-password")
             .param(
@@ -1228,7 +2330,11 @@ mod tests {
                 "--consuming-private-key",
                 "ABCDEF01ABCDEF01ABCDEF01ABCDEF01ABCDEF01ABCDEF01ABCDEF01ABCDEF01",
             )
-            .param("--real-user", "999:999:/home/booga");
+            .param("--real-user", "999:999:/home/booga")
+            .param("--route-paths", "3")
+            .param("--stream-tracing", "on")
+            .param("--exit-allow", "port:80,port:443")
+            .param("--exit-deny", "private,*.example.com");
         let mut config = BootstrapperConfig::new();
         let vcls: Vec<Box<dyn VirtualCommandLine>> =
             vec![Box::new(CommandLineVcl::new(args.into()))];
@@ -1247,10 +2353,14 @@ mod tests {
         assert_eq!(
             config.dns_servers,
             vec!(
-                SocketAddr::from_str("12.34.56.78:53").unwrap(),
-                SocketAddr::from_str("23.45.67.89:53").unwrap()
+                SocketAddr::from_str("12.34.56.78:853").unwrap(),
+                SocketAddr::from_str("23.45.67.89:853").unwrap()
             ),
         );
+        assert_eq!(
+            config.dns_protocol,
+            DnsProtocol::Tls("dns.example.com".to_string())
+        );
         assert_eq!(
             config.neighborhood_config.mode.neighbor_configs(),
             &vec!(
@@ -1268,6 +2378,22 @@ mod tests {
             IpAddr::V4(Ipv4Addr::new(34, 56, 78, 90)),
         );
         assert_eq!(config.ui_gateway_config.ui_port, 5335);
+        assert_eq!(config.ui_gateway_config.metrics_port_opt, Some(5336));
+        assert_eq!(
+            config.log_level,
+            LogLevelSpec {
+                default: log::LevelFilter::Trace,
+                loggers: vec![("Dispatcher".to_string(), log::LevelFilter::Warn)],
+            }
+        );
+        assert_eq!(config.log_format, LogFormat::Json);
+        assert_eq!(
+            config.log_rotation,
+            LogRotation {
+                trigger: LogRotationTrigger::Size(25_000_000),
+                keep: 10,
+            }
+        );
         let expected_port_list: Vec<u16> = vec![];
         assert_eq!(
             config
@@ -1291,6 +2417,18 @@ mod tests {
             config.real_user,
             RealUser::new(Some(999), Some(999), Some(PathBuf::from("/home/booga")))
         );
+        assert_eq!(config.route_path_count, 3);
+        assert!(config.stream_tracing);
+        assert_eq!(
+            config.exit_policy,
+            ExitPolicy::new(
+                vec![ExitRule::Ports(80, 80), ExitRule::Ports(443, 443)],
+                vec![
+                    ExitRule::PrivateNetworks,
+                    ExitRule::Host("*.example.com".to_string())
+                ],
+            )
+        );
     }
 
     #[test]
@@ -1391,6 +2529,10 @@ mod tests {
                 SocketAddr::from_str("23.45.67.89:53").unwrap()
             )
         );
+        assert_eq!(config.dns_protocol, DnsProtocol::Udp);
+        assert_eq!(config.exit_connection_pool, None);
+        assert_eq!(config.entry_dns_mode, EntryDnsMode::Localhost);
+        assert_eq!(config.proxy_port_opt, None);
         assert_eq!(config.crash_point, CrashPoint::None);
         assert_eq!(
             config
@@ -1402,8 +2544,295 @@ mod tests {
             IpAddr::from_str("1.2.3.4").unwrap()
         );
         assert_eq!(config.ui_gateway_config.ui_port, 5333);
+        assert_eq!(config.ui_gateway_config.metrics_port_opt, None);
+        assert_eq!(config.log_level, LogLevelSpec::new(log::LevelFilter::Warn));
+        assert_eq!(config.log_format, LogFormat::Text);
+        assert_eq!(config.log_rotation, LogRotation::default());
+        assert!(!config.privacy_logging);
+        assert!(!config.stream_tracing);
         assert!(config.cryptde_null_opt.is_none());
         assert_eq!(config.real_user, RealUser::null().populate());
+        assert_eq!(config.route_path_count, 1);
+        assert_eq!(config.exit_policy, ExitPolicy::default());
+        assert_eq!(config.bypass_list, BypassList::default());
+        assert_eq!(config.bypass_dns_servers, vec![]);
+    }
+
+    #[test]
+    fn privileged_parse_args_sends_dns_over_https_to_port_443() {
+        let args = ArgsBuilder::new()
+            .param("--dns-servers", "1.1.1.1")
+            .param("--dns-protocol", "https")
+            .param("--dns-tls-name", "cloudflare-dns.com")
+            .param("--ip", "1.2.3.4");
+        let mut config = BootstrapperConfig::new();
+        let vcls: Vec<Box<dyn VirtualCommandLine>> =
+            vec![Box::new(CommandLineVcl::new(args.into()))];
+        let multi_config = MultiConfig::new(&app(), vcls);
+
+        standard::privileged_parse_args(
+            &multi_config,
+            &mut config,
+            &mut FakeStreamHolder::new().streams(),
+        );
+
+        assert_eq!(
+            config.dns_servers,
+            vec!(SocketAddr::from_str("1.1.1.1:443").unwrap())
+        );
+        assert_eq!(
+            config.dns_protocol,
+            DnsProtocol::Https("cloudflare-dns.com".to_string())
+        );
+    }
+
+    #[test]
+    fn privileged_parse_args_configures_exit_connection_pool() {
+        let args = ArgsBuilder::new()
+            .param("--dns-servers", "1.1.1.1")
+            .param("--exit-pool-size", "8")
+            .param("--exit-pool-idle-timeout", "10")
+            .param("--ip", "1.2.3.4");
+        let mut config = BootstrapperConfig::new();
+        let vcls: Vec<Box<dyn VirtualCommandLine>> =
+            vec![Box::new(CommandLineVcl::new(args.into()))];
+        let multi_config = MultiConfig::new(&app(), vcls);
+
+        standard::privileged_parse_args(
+            &multi_config,
+            &mut config,
+            &mut FakeStreamHolder::new().streams(),
+        );
+
+        assert_eq!(
+            config.exit_connection_pool,
+            Some(ConnectionPoolConfig {
+                max_idle_per_server: 8,
+                idle_timeout: Duration::from_secs(10),
+            })
+        );
+    }
+
+    #[test]
+    fn privileged_parse_args_configures_bypass_list() {
+        let args = ArgsBuilder::new()
+            .param("--dns-servers", "1.1.1.1")
+            .param("--bypass", "intranet.example.com,10.0.0.0/8")
+            .param("--bypass-dns-servers", "10.0.0.53,10.0.1.53")
+            .param("--ip", "1.2.3.4");
+        let mut config = BootstrapperConfig::new();
+        let vcls: Vec<Box<dyn VirtualCommandLine>> =
+            vec![Box::new(CommandLineVcl::new(args.into()))];
+        let multi_config = MultiConfig::new(&app(), vcls);
+
+        standard::privileged_parse_args(
+            &multi_config,
+            &mut config,
+            &mut FakeStreamHolder::new().streams(),
+        );
+
+        assert_eq!(
+            config.bypass_list,
+            BypassList::new(vec![
+                ExitRule::Host("*.intranet.example.com".to_string()),
+                ExitRule::Network(IpAddr::from_str("10.0.0.0").unwrap(), 8),
+            ])
+        );
+        assert_eq!(
+            config.bypass_dns_servers,
+            vec![
+                SocketAddr::from_str("10.0.0.53:53").unwrap(),
+                SocketAddr::from_str("10.0.1.53:53").unwrap(),
+            ]
+        );
+    }
+
+    #[test]
+    fn privileged_parse_args_configures_entry_dns_tunnel() {
+        let args = ArgsBuilder::new()
+            .param("--dns-servers", "1.1.1.1")
+            .param("--entry-dns", "tunnel")
+            .param("--ip", "1.2.3.4");
+        let mut config = BootstrapperConfig::new();
+        let vcls: Vec<Box<dyn VirtualCommandLine>> =
+            vec![Box::new(CommandLineVcl::new(args.into()))];
+        let multi_config = MultiConfig::new(&app(), vcls);
+
+        standard::privileged_parse_args(
+            &multi_config,
+            &mut config,
+            &mut FakeStreamHolder::new().streams(),
+        );
+
+        assert_eq!(config.entry_dns_mode, EntryDnsMode::Tunnel);
+    }
+
+    #[test]
+    fn privileged_parse_args_configures_entry_dns_resolution() {
+        let args = ArgsBuilder::new()
+            .param("--dns-servers", "1.1.1.1")
+            .param("--entry-dns", "resolve")
+            .param("--proxy-port", "8888")
+            .param("--ip", "1.2.3.4");
+        let mut config = BootstrapperConfig::new();
+        let vcls: Vec<Box<dyn VirtualCommandLine>> =
+            vec![Box::new(CommandLineVcl::new(args.into()))];
+        let multi_config = MultiConfig::new(&app(), vcls);
+
+        standard::privileged_parse_args(
+            &multi_config,
+            &mut config,
+            &mut FakeStreamHolder::new().streams(),
+        );
+
+        assert_eq!(config.entry_dns_mode, EntryDnsMode::Resolve);
+        assert_eq!(config.proxy_port_opt, Some(8888));
+    }
+
+    #[test]
+    fn privileged_parse_args_listens_for_proxy_requests_on_the_proxy_port() {
+        let args = ArgsBuilder::new()
+            .param("--dns-servers", "1.1.1.1")
+            .param("--proxy-port", "8888")
+            .param("--ip", "1.2.3.4");
+        let mut config = BootstrapperConfig::new();
+        let vcls: Vec<Box<dyn VirtualCommandLine>> =
+            vec![Box::new(CommandLineVcl::new(args.into()))];
+        let multi_config = MultiConfig::new(&app(), vcls);
+
+        standard::privileged_parse_args(
+            &multi_config,
+            &mut config,
+            &mut FakeStreamHolder::new().streams(),
+        );
+
+        assert_eq!(config.proxy_port_opt, Some(8888));
+        let port_configuration = config.port_configurations.get(&8888).unwrap();
+        assert_eq!(port_configuration.discriminator_factories.len(), 1);
+        assert_eq!(port_configuration.is_clandestine, false);
+    }
+
+    #[test]
+    #[should_panic(expected = "--proxy-port 5333 cannot be the same as --ui-port")]
+    fn privileged_parse_args_rejects_a_proxy_port_that_is_the_ui_port() {
+        let args = ArgsBuilder::new()
+            .param("--dns-servers", "1.1.1.1")
+            .param("--ui-port", "5333")
+            .param("--proxy-port", "5333");
+        let vcls: Vec<Box<dyn VirtualCommandLine>> =
+            vec![Box::new(CommandLineVcl::new(args.into()))];
+        let multi_config = MultiConfig::new(&app(), vcls);
+
+        standard::privileged_parse_args(
+            &multi_config,
+            &mut BootstrapperConfig::new(),
+            &mut FakeStreamHolder::new().streams(),
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "--proxy-port 5334 cannot be the same as --metrics-port")]
+    fn privileged_parse_args_rejects_a_proxy_port_that_is_the_metrics_port() {
+        let args = ArgsBuilder::new()
+            .param("--dns-servers", "1.1.1.1")
+            .param("--metrics", "on")
+            .param("--proxy-port", "5334");
+        let vcls: Vec<Box<dyn VirtualCommandLine>> =
+            vec![Box::new(CommandLineVcl::new(args.into()))];
+        let multi_config = MultiConfig::new(&app(), vcls);
+
+        standard::privileged_parse_args(
+            &multi_config,
+            &mut BootstrapperConfig::new(),
+            &mut FakeStreamHolder::new().streams(),
+        );
+    }
+
+    #[test]
+    fn privileged_parse_args_lets_the_proxy_port_have_the_metrics_port_when_metrics_are_off() {
+        let args = ArgsBuilder::new()
+            .param("--dns-servers", "1.1.1.1")
+            .param("--proxy-port", "5334");
+        let mut config = BootstrapperConfig::new();
+        let vcls: Vec<Box<dyn VirtualCommandLine>> =
+            vec![Box::new(CommandLineVcl::new(args.into()))];
+        let multi_config = MultiConfig::new(&app(), vcls);
+
+        standard::privileged_parse_args(
+            &multi_config,
+            &mut config,
+            &mut FakeStreamHolder::new().streams(),
+        );
+
+        assert_eq!(config.proxy_port_opt, Some(5334));
+        assert_eq!(config.ui_gateway_config.metrics_port_opt, None);
+    }
+
+    #[test]
+    #[should_panic(expected = "--proxy-port 8053 cannot be the same as the DNS-over-HTTPS port")]
+    fn privileged_parse_args_rejects_a_proxy_port_that_is_the_doh_port() {
+        let args = ArgsBuilder::new()
+            .param("--dns-servers", "1.1.1.1")
+            .param("--proxy-port", "8053");
+        let vcls: Vec<Box<dyn VirtualCommandLine>> =
+            vec![Box::new(CommandLineVcl::new(args.into()))];
+        let multi_config = MultiConfig::new(&app(), vcls);
+
+        standard::privileged_parse_args(
+            &multi_config,
+            &mut BootstrapperConfig::new(),
+            &mut FakeStreamHolder::new().streams(),
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "--proxy-port 4321 cannot be the same as --clandestine-port")]
+    fn unprivileged_parse_args_rejects_a_proxy_port_that_is_the_clandestine_port() {
+        let args = ArgsBuilder::new()
+            .param("--dns-servers", "1.1.1.1")
+            .param("--clandestine-port", "4321")
+            .param("--proxy-port", "4321");
+        let vcls: Vec<Box<dyn VirtualCommandLine>> =
+            vec![Box::new(CommandLineVcl::new(args.into()))];
+        let multi_config = MultiConfig::new(&app(), vcls);
+
+        standard::unprivileged_parse_args(
+            &multi_config,
+            &mut BootstrapperConfig::new(),
+            &mut FakeStreamHolder::new().streams(),
+            &PersistentConfigurationMock::new(),
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "--proxy-port 4321 cannot be the same as --clandestine-port")]
+    fn unprivileged_parse_args_rejects_a_proxy_port_that_is_the_persisted_clandestine_port() {
+        let args = ArgsBuilder::new()
+            .param("--dns-servers", "1.1.1.1")
+            .param("--proxy-port", "4321");
+        let vcls: Vec<Box<dyn VirtualCommandLine>> =
+            vec![Box::new(CommandLineVcl::new(args.into()))];
+        let multi_config = MultiConfig::new(&app(), vcls);
+
+        standard::unprivileged_parse_args(
+            &multi_config,
+            &mut BootstrapperConfig::new(),
+            &mut FakeStreamHolder::new().streams(),
+            &PersistentConfigurationMock::new().clandestine_port_result(4321),
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "MissingRequiredArgument")]
+    fn encrypted_dns_protocol_requires_dns_tls_name() {
+        let args = ArgsBuilder::new()
+            .param("--dns-servers", "1.1.1.1")
+            .param("--dns-protocol", "tls")
+            .param("--ip", "1.2.3.4");
+        let vcls: Vec<Box<dyn VirtualCommandLine>> =
+            vec![Box::new(CommandLineVcl::new(args.into()))];
+
+        MultiConfig::new(&app(), vcls);
     }
 
     #[test]
@@ -33,6 +33,7 @@ pub trait PersistentConfiguration: Send {
     fn earning_wallet_from_address(&self) -> Option<Wallet>;
     fn earning_wallet_address(&self) -> Option<String>;
     fn set_earning_wallet_address(&self, address: &str);
+    fn change_earning_wallet_address(&self, address: &str);
     fn start_block(&self) -> u64;
     fn set_start_block_transactionally(&self, tx: &Transaction, value: u64) -> Result<(), String>;
 }
@@ -278,6 +279,18 @@ impl PersistentConfiguration for PersistentConfigurationReal {
         }
     }
 
+    // Unlike set_earning_wallet_address, this replaces any address that's already there
+    fn change_earning_wallet_address(&self, address: &str) {
+        match Wallet::from_str(address) {
+            Ok(_) => (),
+            Err(_) => panic!("Invalid earning wallet address '{}'", address),
+        }
+        match self.dao.set_string("earning_wallet_address", address) {
+            Ok(_) => (),
+            Err(e) => panic!("Error changing earning wallet address: {:?}", e),
+        }
+    }
+
     fn start_block(&self) -> u64 {
         self.dao.get_u64("start_block").unwrap_or_else(|e| {
             panic!(
@@ -1244,6 +1257,39 @@ mod tests {
         assert_eq!(set_string_params.len(), 0);
     }
 
+    #[test]
+    fn change_earning_wallet_address_overwrites_existing_address() {
+        let set_string_params_arc = Arc::new(Mutex::new(vec![]));
+        let config_dao: Box<dyn ConfigDao> = Box::new(
+            ConfigDaoMock::new()
+                .get_string_result(Ok("0xcafedeadbeefbabefacecafedeadbeefbabeface".to_string()))
+                .set_string_params(&set_string_params_arc)
+                .set_string_result(Ok(())),
+        );
+        let subject = PersistentConfigurationReal::new(config_dao);
+
+        subject.change_earning_wallet_address("0xbabefacecafedeadbeefbabefacecafedeadbeef");
+
+        let set_string_params = set_string_params_arc.lock().unwrap();
+        assert_eq!(
+            *set_string_params,
+            vec![(
+                "earning_wallet_address".to_string(),
+                "0xbabefacecafedeadbeefbabefacecafedeadbeef".to_string()
+            )]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid earning wallet address 'booga'")]
+    fn change_earning_wallet_address_bad_address() {
+        let config_dao: Box<dyn ConfigDao> =
+            Box::new(ConfigDaoMock::new().set_string_result(Ok(())));
+        let subject = PersistentConfigurationReal::new(config_dao);
+
+        subject.change_earning_wallet_address("booga");
+    }
+
     #[test]
     #[should_panic(expected = "Database is corrupt: error retrieving one: TypeError")]
     fn handle_config_pair_result_handles_first_error() {
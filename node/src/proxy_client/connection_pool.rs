@@ -0,0 +1,883 @@
+// Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use crate::http_request_start_finder::HttpRequestStartFinder;
+use crate::proxy_server::http_protocol_pack::HttpProtocolPack;
+use crate::sub_lib::cryptde::PublicKey;
+use crate::sub_lib::framer::Framer;
+use crate::sub_lib::http_packet_framer::HttpPacketFramer;
+use crate::sub_lib::http_response_start_finder::HttpResponseStartFinder;
+use crate::sub_lib::logger::Logger;
+use crate::sub_lib::metrics;
+use crate::sub_lib::metrics::PROXY_CLIENT_POOLED_CONNECTIONS;
+use crate::sub_lib::metrics::PROXY_CLIENT_POOL_IDLE_CONNECTIONS;
+use crate::sub_lib::privacy;
+use crate::sub_lib::proxy_client::ConnectionPoolConfig;
+use crate::sub_lib::stream_connector::ConnectionInfo;
+use crate::sub_lib::tokio_wrappers::ReadHalfWrapper;
+use crate::sub_lib::tokio_wrappers::WriteHalfWrapper;
+use futures::task;
+use futures::task::Task;
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+use std::io::ErrorKind;
+use std::io::Read;
+use std::io::Write;
+use std::net::IpAddr;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Instant;
+use tokio::prelude::Async;
+use tokio::prelude::AsyncRead;
+use tokio::prelude::AsyncWrite;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ConnectionPoolStats {
+    pub reused: u64,
+    pub missed: u64,
+    pub parked: u64,
+    pub discarded: u64,
+    pub idle: usize,
+}
+
+impl fmt::Display for ConnectionPoolStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} reused, {} missed, {} parked, {} discarded, {} idle",
+            self.reused, self.missed, self.parked, self.discarded, self.idle
+        )
+    }
+}
+
+struct IdleConnection {
+    reader: Box<dyn ReadHalfWrapper>,
+    writer: Box<dyn WriteHalfWrapper>,
+    local_addr: SocketAddr,
+    parked_at: Instant,
+}
+
+// Connections are never shared between consumers: one consumer's requests must not be able to
+// ride on, or learn anything from, a connection another consumer's traffic opened.
+type PoolKey = (PublicKey, SocketAddr);
+
+struct ConnectionPoolInner {
+    config: ConnectionPoolConfig,
+    idle: HashMap<PoolKey, Vec<IdleConnection>>,
+    stats: ConnectionPoolStats,
+}
+
+// Idle connections to HTTP servers, kept by the exit Node so that a new stream to a server it
+// has recently talked to on behalf of the same consumer can skip the TCP handshake. Only
+// connections whose last request and response both left them fit for another one are ever
+// parked here.
+#[derive(Clone)]
+pub struct ConnectionPool {
+    inner: Arc<Mutex<ConnectionPoolInner>>,
+    logger: Logger,
+}
+
+impl ConnectionPool {
+    pub fn new(config: ConnectionPoolConfig) -> ConnectionPool {
+        ConnectionPool {
+            inner: Arc::new(Mutex::new(ConnectionPoolInner {
+                config,
+                idle: HashMap::new(),
+                stats: ConnectionPoolStats::default(),
+            })),
+            logger: Logger::new("ConnectionPool"),
+        }
+    }
+
+    pub fn stats(&self) -> ConnectionPoolStats {
+        let inner = self.inner.lock().expect("Connection pool is poisoned");
+        inner.stats()
+    }
+
+    pub fn take(
+        &self,
+        consumer: &PublicKey,
+        ip_addrs: &[IpAddr],
+        port: u16,
+    ) -> Option<ConnectionInfo> {
+        self.take_at(consumer, ip_addrs, port, Instant::now())
+    }
+
+    // Wraps a connection so that it can find its way back here when the stream using it is done
+    pub fn lease(&self, consumer: &PublicKey, connection_info: ConnectionInfo) -> ConnectionInfo {
+        let lease = Arc::new(Mutex::new(Lease::new(
+            self.clone(),
+            consumer.clone(),
+            connection_info.local_addr,
+            connection_info.peer_addr,
+        )));
+        ConnectionInfo {
+            reader: Box::new(LeasedReadHalf {
+                reader: Some(connection_info.reader),
+                lease: lease.clone(),
+            }),
+            writer: Box::new(LeasedWriteHalf {
+                writer: Some(connection_info.writer),
+                lease,
+            }),
+            local_addr: connection_info.local_addr,
+            peer_addr: connection_info.peer_addr,
+        }
+    }
+
+    fn take_at(
+        &self,
+        consumer: &PublicKey,
+        ip_addrs: &[IpAddr],
+        port: u16,
+        now: Instant,
+    ) -> Option<ConnectionInfo> {
+        let mut inner = self.inner.lock().expect("Connection pool is poisoned");
+        inner.discard_expired(now);
+        let mut found = None;
+        for ip_addr in ip_addrs {
+            let peer_addr = SocketAddr::new(*ip_addr, port);
+            while let Some(mut connection) = inner
+                .idle
+                .get_mut(&(consumer.clone(), peer_addr))
+                .and_then(|idle| idle.pop())
+            {
+                if is_still_open(&mut connection.reader) {
+                    found = Some((peer_addr, connection));
+                    break;
+                }
+                inner.stats.discarded += 1;
+                count_connections("discarded", 1);
+            }
+            if found.is_some() {
+                break;
+            }
+        }
+        inner.idle.retain(|_, idle| !idle.is_empty());
+        inner.count_idle();
+        match found {
+            Some((peer_addr, connection)) => {
+                inner.stats.reused += 1;
+                count_connections("reused", 1);
+                debug!(
+                    self.logger,
+                    "Reusing idle connection to {}: {}",
+                    privacy::socket_addr(peer_addr),
+                    inner.stats()
+                );
+                Some(ConnectionInfo {
+                    reader: connection.reader,
+                    writer: connection.writer,
+                    local_addr: connection.local_addr,
+                    peer_addr,
+                })
+            }
+            None => {
+                inner.stats.missed += 1;
+                count_connections("missed", 1);
+                None
+            }
+        }
+    }
+
+    pub fn park(
+        &self,
+        consumer: PublicKey,
+        peer_addr: SocketAddr,
+        local_addr: SocketAddr,
+        reader: Box<dyn ReadHalfWrapper>,
+        writer: Box<dyn WriteHalfWrapper>,
+    ) {
+        self.park_at(
+            consumer,
+            peer_addr,
+            local_addr,
+            reader,
+            writer,
+            Instant::now(),
+        )
+    }
+
+    fn park_at(
+        &self,
+        consumer: PublicKey,
+        peer_addr: SocketAddr,
+        local_addr: SocketAddr,
+        reader: Box<dyn ReadHalfWrapper>,
+        writer: Box<dyn WriteHalfWrapper>,
+        now: Instant,
+    ) {
+        let mut inner = self.inner.lock().expect("Connection pool is poisoned");
+        inner.discard_expired(now);
+        let max_idle = inner.config.max_idle_per_server;
+        let idle = inner
+            .idle
+            .entry((consumer, peer_addr))
+            .or_insert_with(Vec::new);
+        idle.push(IdleConnection {
+            reader,
+            writer,
+            local_addr,
+            parked_at: now,
+        });
+        let overflow = idle.len().saturating_sub(max_idle);
+        idle.drain(0..overflow);
+        inner.idle.retain(|_, idle| !idle.is_empty());
+        inner.stats.parked += 1;
+        inner.stats.discarded += overflow as u64;
+        count_connections("parked", 1);
+        count_connections("discarded", overflow as u64);
+        inner.count_idle();
+        debug!(
+            self.logger,
+            "Parked idle connection to {}: {}",
+            privacy::socket_addr(peer_addr),
+            inner.stats()
+        );
+    }
+}
+
+impl ConnectionPoolInner {
+    fn stats(&self) -> ConnectionPoolStats {
+        ConnectionPoolStats {
+            idle: self.idle.values().map(|idle| idle.len()).sum(),
+            ..self.stats
+        }
+    }
+
+    // Swept across every server, not just the one being asked about, so that connections to
+    // servers nobody visits again don't sit open forever.
+    fn discard_expired(&mut self, now: Instant) {
+        let idle_timeout = self.config.idle_timeout;
+        let mut discarded = 0;
+        for idle in self.idle.values_mut() {
+            let before = idle.len();
+            idle.retain(|connection| connection.parked_at + idle_timeout > now);
+            discarded += before - idle.len();
+        }
+        self.idle.retain(|_, idle| !idle.is_empty());
+        self.stats.discarded += discarded as u64;
+        count_connections("discarded", discarded as u64);
+    }
+
+    fn count_idle(&self) {
+        metrics::set(
+            &PROXY_CLIENT_POOL_IDLE_CONNECTIONS,
+            &[],
+            self.stats().idle as i64,
+        );
+    }
+}
+
+fn count_connections(outcome: &str, amount: u64) {
+    if amount > 0 {
+        metrics::increment(
+            &PROXY_CLIENT_POOLED_CONNECTIONS,
+            &[("outcome", outcome)],
+            amount,
+        );
+    }
+}
+
+// A non-blocking probe of an idle connection: a server with nothing to say leaves the read
+// NotReady. End-of-stream or an error means the server has closed its end, and bytes nobody
+// asked for mean the connection can't be trusted with another request either.
+fn is_still_open(reader: &mut Box<dyn ReadHalfWrapper>) -> bool {
+    let mut buf = [0u8; 1];
+    match reader.poll_read(&mut buf) {
+        Ok(Async::NotReady) => true,
+        _ => false,
+    }
+}
+
+#[derive(Default)]
+struct MessageTally {
+    messages: usize,
+    bytes: usize,
+    framed_bytes: usize,
+}
+
+impl MessageTally {
+    // Counts the complete messages in the data, and says whether they all leave the connection
+    // fit for reuse
+    fn add(
+        &mut self,
+        framer: &mut HttpPacketFramer,
+        data: &[u8],
+        keeps_connection_alive: fn(&[u8]) -> bool,
+    ) -> bool {
+        self.bytes += data.len();
+        framer.add_data(data);
+        while let Some(frame) = framer.take_frame() {
+            if !keeps_connection_alive(&frame.chunk) {
+                return false;
+            }
+            self.messages += 1;
+            self.framed_bytes += frame.chunk.len();
+        }
+        true
+    }
+
+    fn is_between_messages(&self) -> bool {
+        self.bytes == self.framed_bytes
+    }
+}
+
+// What the two halves of a leased connection know about the traffic that has crossed it
+struct Lease {
+    pool: ConnectionPool,
+    consumer: PublicKey,
+    local_addr: SocketAddr,
+    peer_addr: SocketAddr,
+    reusable: bool,
+    request_framer: HttpPacketFramer,
+    response_framer: HttpPacketFramer,
+    requests: MessageTally,
+    responses: MessageTally,
+    released: bool,
+    returned_writer: Option<Box<dyn WriteHalfWrapper>>,
+    reader_task: Option<Task>,
+}
+
+impl Lease {
+    fn new(
+        pool: ConnectionPool,
+        consumer: PublicKey,
+        local_addr: SocketAddr,
+        peer_addr: SocketAddr,
+    ) -> Lease {
+        Lease {
+            pool,
+            consumer,
+            local_addr,
+            peer_addr,
+            reusable: true,
+            request_framer: HttpPacketFramer::new(Box::new(HttpRequestStartFinder {})),
+            response_framer: HttpPacketFramer::new(Box::new(HttpResponseStartFinder {})),
+            requests: MessageTally::default(),
+            responses: MessageTally::default(),
+            released: false,
+            returned_writer: None,
+            reader_task: None,
+        }
+    }
+
+    fn request_written(&mut self, data: &[u8]) {
+        if self.reusable {
+            self.reusable = self.requests.add(
+                &mut self.request_framer,
+                data,
+                HttpProtocolPack::keeps_connection_alive,
+            );
+        }
+    }
+
+    fn response_read(&mut self, data: &[u8]) {
+        if self.reusable {
+            self.reusable = self.responses.add(
+                &mut self.response_framer,
+                data,
+                HttpProtocolPack::response_keeps_connection_alive,
+            );
+        }
+    }
+
+    // Every request sent has been answered, and nothing is half-written or half-read
+    fn is_idle(&self) -> bool {
+        self.reusable
+            && self.requests.messages > 0
+            && self.requests.messages == self.responses.messages
+            && self.requests.is_between_messages()
+            && self.responses.is_between_messages()
+    }
+}
+
+pub struct LeasedReadHalf {
+    reader: Option<Box<dyn ReadHalfWrapper>>,
+    lease: Arc<Mutex<Lease>>,
+}
+
+impl Read for LeasedReadHalf {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, io::Error> {
+        match self.reader.as_mut() {
+            Some(reader) => reader.read(buf),
+            None => Ok(0),
+        }
+    }
+}
+
+// Once the write half has been released, the read half returns the connection to the pool and
+// reports end-of-stream, so that the StreamReader using it goes away quietly.
+impl AsyncRead for LeasedReadHalf {
+    fn poll_read(&mut self, buf: &mut [u8]) -> Result<Async<usize>, io::Error> {
+        let mut lease = self.lease.lock().expect("Connection lease is poisoned");
+        if lease.released {
+            if let (Some(reader), Some(writer)) = (self.reader.take(), lease.returned_writer.take())
+            {
+                let consumer = lease.consumer.clone();
+                lease
+                    .pool
+                    .park(consumer, lease.peer_addr, lease.local_addr, reader, writer);
+            }
+            return Ok(Async::Ready(0));
+        }
+        let reader = match self.reader.as_mut() {
+            Some(reader) => reader,
+            None => return Ok(Async::Ready(0)),
+        };
+        match reader.poll_read(buf) {
+            Ok(Async::Ready(0)) => {
+                lease.reusable = false;
+                Ok(Async::Ready(0))
+            }
+            Ok(Async::Ready(len)) => {
+                lease.response_read(&buf[0..len]);
+                Ok(Async::Ready(len))
+            }
+            Ok(Async::NotReady) => {
+                lease.reader_task = Some(task::current());
+                Ok(Async::NotReady)
+            }
+            Err(e) => {
+                lease.reusable = false;
+                Err(e)
+            }
+        }
+    }
+}
+
+impl ReadHalfWrapper for LeasedReadHalf {}
+
+pub struct LeasedWriteHalf {
+    writer: Option<Box<dyn WriteHalfWrapper>>,
+    lease: Arc<Mutex<Lease>>,
+}
+
+impl LeasedWriteHalf {
+    fn writer_mut(&mut self) -> Result<&mut Box<dyn WriteHalfWrapper>, io::Error> {
+        self.writer
+            .as_mut()
+            .ok_or_else(|| io::Error::from(ErrorKind::NotConnected))
+    }
+}
+
+impl Write for LeasedWriteHalf {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, io::Error> {
+        let len = self.writer_mut()?.write(buf)?;
+        let mut lease = self.lease.lock().expect("Connection lease is poisoned");
+        lease.request_written(&buf[0..len]);
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> Result<(), io::Error> {
+        self.writer_mut()?.flush()
+    }
+}
+
+// Shutting down a connection that is idle releases it instead: the write half is handed to the
+// read half, which parks both in the pool.
+impl AsyncWrite for LeasedWriteHalf {
+    fn poll_write(&mut self, buf: &[u8]) -> Result<Async<usize>, io::Error> {
+        let result = self.writer_mut()?.poll_write(buf);
+        let mut lease = self.lease.lock().expect("Connection lease is poisoned");
+        match &result {
+            Ok(Async::Ready(len)) => lease.request_written(&buf[0..*len]),
+            Ok(Async::NotReady) => (),
+            Err(_) => lease.reusable = false,
+        }
+        result
+    }
+
+    fn shutdown(&mut self) -> Result<Async<()>, io::Error> {
+        {
+            let mut lease = self.lease.lock().expect("Connection lease is poisoned");
+            if lease.released {
+                return Ok(Async::Ready(()));
+            }
+            if lease.is_idle() {
+                lease.returned_writer = self.writer.take();
+                lease.released = true;
+                if let Some(reader_task) = lease.reader_task.take() {
+                    reader_task.notify();
+                }
+                return Ok(Async::Ready(()));
+            }
+            lease.reusable = false;
+        }
+        self.writer_mut()?.shutdown()
+    }
+}
+
+impl WriteHalfWrapper for LeasedWriteHalf {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::logging::init_test_logging;
+    use crate::test_utils::logging::TestLogHandler;
+    use crate::test_utils::tokio_wrapper_mocks::ReadHalfWrapperMock;
+    use crate::test_utils::tokio_wrapper_mocks::WriteHalfWrapperMock;
+    use std::str::FromStr;
+    use std::time::Duration;
+
+    const REQUEST: &[u8] = b"GET /index.html HTTP/1.1\r\nHost: server.com\r\n\r\n";
+    const RESPONSE: &[u8] = b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nbooga";
+
+    fn make_pool(max_idle_per_server: usize) -> ConnectionPool {
+        ConnectionPool::new(ConnectionPoolConfig {
+            max_idle_per_server,
+            idle_timeout: Duration::from_secs(4),
+        })
+    }
+
+    fn consumer() -> PublicKey {
+        PublicKey::new(b"consumer")
+    }
+
+    fn make_connection(
+        peer_addr: &str,
+        reader: ReadHalfWrapperMock,
+        writer: WriteHalfWrapperMock,
+    ) -> ConnectionInfo {
+        ConnectionInfo {
+            reader: Box::new(reader),
+            writer: Box::new(writer),
+            local_addr: SocketAddr::from_str("192.168.0.1:5555").unwrap(),
+            peer_addr: SocketAddr::from_str(peer_addr).unwrap(),
+        }
+    }
+
+    fn park_idle_connection(subject: &ConnectionPool, peer_addr: &str, now: Instant) {
+        park_connection(
+            subject,
+            consumer(),
+            peer_addr,
+            ReadHalfWrapperMock::new().poll_read_result(vec![], Ok(Async::NotReady)),
+            now,
+        );
+    }
+
+    fn park_connection(
+        subject: &ConnectionPool,
+        consumer: PublicKey,
+        peer_addr: &str,
+        reader: ReadHalfWrapperMock,
+        now: Instant,
+    ) {
+        subject.park_at(
+            consumer,
+            SocketAddr::from_str(peer_addr).unwrap(),
+            SocketAddr::from_str("192.168.0.1:5555").unwrap(),
+            Box::new(reader),
+            Box::new(WriteHalfWrapperMock::new()),
+            now,
+        );
+    }
+
+    fn exchange(connection: &mut ConnectionInfo, request: &[u8]) -> Result<Async<()>, io::Error> {
+        let mut buf = [0u8; 1024];
+        assert_eq!(
+            connection.writer.poll_write(request).unwrap(),
+            Async::Ready(request.len())
+        );
+        connection.reader.poll_read(&mut buf).unwrap();
+        connection.writer.shutdown()
+    }
+
+    #[test]
+    fn connection_pool_stats_display_properly() {
+        let stats = ConnectionPoolStats {
+            reused: 5,
+            missed: 4,
+            parked: 3,
+            discarded: 2,
+            idle: 1,
+        };
+
+        assert_eq!(
+            format!("{}", stats),
+            "5 reused, 4 missed, 3 parked, 2 discarded, 1 idle"
+        );
+    }
+
+    #[test]
+    fn take_finds_idle_connection_to_any_of_the_addresses() {
+        let subject = make_pool(2);
+        let now = Instant::now();
+        park_idle_connection(&subject, "2.3.4.5:80", now);
+
+        let miss = subject.take_at(
+            &consumer(),
+            &[IpAddr::from_str("2.3.4.5").unwrap()],
+            8080,
+            now,
+        );
+        let hit = subject.take_at(
+            &consumer(),
+            &[
+                IpAddr::from_str("1.2.3.4").unwrap(),
+                IpAddr::from_str("2.3.4.5").unwrap(),
+            ],
+            80,
+            now,
+        );
+        let empty = subject.take_at(
+            &consumer(),
+            &[IpAddr::from_str("2.3.4.5").unwrap()],
+            80,
+            now,
+        );
+
+        assert_eq!(miss.is_none(), true);
+        assert_eq!(
+            hit.map(|connection| connection.peer_addr),
+            Some(SocketAddr::from_str("2.3.4.5:80").unwrap())
+        );
+        assert_eq!(empty.is_none(), true);
+        assert_eq!(
+            subject.stats(),
+            ConnectionPoolStats {
+                reused: 1,
+                missed: 2,
+                parked: 1,
+                discarded: 0,
+                idle: 0,
+            }
+        );
+    }
+
+    #[test]
+    fn take_discards_connections_that_have_been_idle_too_long() {
+        let subject = make_pool(2);
+        let then = Instant::now();
+        park_idle_connection(&subject, "2.3.4.5:80", then);
+
+        let result = subject.take_at(
+            &consumer(),
+            &[IpAddr::from_str("2.3.4.5").unwrap()],
+            80,
+            then + Duration::from_secs(4),
+        );
+
+        assert_eq!(result.is_none(), true);
+        assert_eq!(subject.stats().discarded, 1);
+        assert_eq!(subject.stats().idle, 0);
+    }
+
+    #[test]
+    fn take_discards_expired_connections_to_other_servers_too() {
+        let subject = make_pool(2);
+        let then = Instant::now();
+        park_idle_connection(&subject, "3.4.5.6:80", then);
+        park_idle_connection(&subject, "2.3.4.5:80", then + Duration::from_secs(2));
+
+        let result = subject.take_at(
+            &consumer(),
+            &[IpAddr::from_str("2.3.4.5").unwrap()],
+            80,
+            then + Duration::from_secs(4),
+        );
+
+        assert_eq!(result.is_some(), true);
+        assert_eq!(subject.stats().discarded, 1);
+        assert_eq!(subject.inner.lock().unwrap().idle.is_empty(), true);
+    }
+
+    #[test]
+    fn take_does_not_hand_one_consumers_connection_to_another() {
+        let subject = make_pool(2);
+        let now = Instant::now();
+        park_idle_connection(&subject, "2.3.4.5:80", now);
+
+        let result = subject.take_at(
+            &PublicKey::new(b"someone else"),
+            &[IpAddr::from_str("2.3.4.5").unwrap()],
+            80,
+            now,
+        );
+
+        assert_eq!(result.is_none(), true);
+        assert_eq!(subject.stats().idle, 1);
+    }
+
+    #[test]
+    fn take_discards_connections_the_server_has_closed_or_written_to() {
+        let subject = make_pool(4);
+        let now = Instant::now();
+        let closed = ReadHalfWrapperMock::new().poll_read_ok(vec![]);
+        let chatty = ReadHalfWrapperMock::new().poll_read_ok(b"HTTP/1.1 408".to_vec());
+        let broken = ReadHalfWrapperMock::new()
+            .poll_read_result(vec![], Err(io::Error::from(ErrorKind::ConnectionReset)));
+        park_idle_connection(&subject, "2.3.4.5:80", now);
+        park_connection(&subject, consumer(), "2.3.4.5:80", closed, now);
+        park_connection(&subject, consumer(), "2.3.4.5:80", chatty, now);
+        park_connection(&subject, consumer(), "2.3.4.5:80", broken, now);
+
+        let result = subject.take_at(
+            &consumer(),
+            &[IpAddr::from_str("2.3.4.5").unwrap()],
+            80,
+            now,
+        );
+
+        assert_eq!(result.is_some(), true);
+        assert_eq!(
+            subject.stats(),
+            ConnectionPoolStats {
+                reused: 1,
+                missed: 0,
+                parked: 4,
+                discarded: 3,
+                idle: 0,
+            }
+        );
+    }
+
+    #[test]
+    fn park_discards_the_oldest_connection_when_a_server_has_too_many() {
+        let subject = make_pool(2);
+        let now = Instant::now();
+
+        park_idle_connection(&subject, "2.3.4.5:80", now);
+        park_idle_connection(&subject, "2.3.4.5:80", now + Duration::from_secs(1));
+        park_idle_connection(&subject, "3.4.5.6:80", now + Duration::from_secs(1));
+        park_idle_connection(&subject, "2.3.4.5:80", now + Duration::from_secs(2));
+
+        assert_eq!(
+            subject.stats(),
+            ConnectionPoolStats {
+                reused: 0,
+                missed: 0,
+                parked: 4,
+                discarded: 1,
+                idle: 3,
+            }
+        );
+        let inner = subject.inner.lock().unwrap();
+        let parked_at: Vec<Instant> = inner.idle
+            [&(consumer(), SocketAddr::from_str("2.3.4.5:80").unwrap())]
+            .iter()
+            .map(|connection| connection.parked_at)
+            .collect();
+        assert_eq!(
+            parked_at,
+            vec![now + Duration::from_secs(1), now + Duration::from_secs(2)]
+        );
+    }
+
+    #[test]
+    fn pool_activity_is_counted_in_the_metrics_registry() {
+        let subject = make_pool(1);
+        let now = Instant::now();
+        let connections = |outcome: &str| {
+            metrics::value(&PROXY_CLIENT_POOLED_CONNECTIONS, &[("outcome", outcome)]).unwrap_or(0)
+        };
+        let (reused_before, missed_before, parked_before, discarded_before) = (
+            connections("reused"),
+            connections("missed"),
+            connections("parked"),
+            connections("discarded"),
+        );
+
+        park_idle_connection(&subject, "2.3.4.5:80", now);
+        park_idle_connection(&subject, "2.3.4.5:80", now);
+        let _ = subject.take_at(
+            &consumer(),
+            &[IpAddr::from_str("2.3.4.5").unwrap()],
+            80,
+            now,
+        );
+        let _ = subject.take_at(
+            &consumer(),
+            &[IpAddr::from_str("2.3.4.5").unwrap()],
+            80,
+            now,
+        );
+
+        // The registry is shared with every other test
+        assert!(connections("reused") > reused_before);
+        assert!(connections("missed") > missed_before);
+        assert!(connections("parked") >= parked_before + 2);
+        assert!(connections("discarded") > discarded_before);
+        assert!(metrics::value(&PROXY_CLIENT_POOL_IDLE_CONNECTIONS, &[]).is_some());
+    }
+
+    #[test]
+    fn leased_connection_returns_to_the_pool_after_a_complete_exchange() {
+        init_test_logging();
+        let subject = make_pool(2);
+        let reader = ReadHalfWrapperMock::new()
+            .poll_read_ok(RESPONSE.to_vec())
+            .poll_read_result(vec![], Ok(Async::NotReady));
+        let writer = WriteHalfWrapperMock::new().poll_write_ok(REQUEST.len());
+        let mut connection =
+            subject.lease(&consumer(), make_connection("2.3.4.5:80", reader, writer));
+
+        let shutdown_result = exchange(&mut connection, REQUEST);
+        let mut buf = [0u8; 1024];
+        let read_result = connection.reader.poll_read(&mut buf).unwrap();
+
+        assert_eq!(shutdown_result.unwrap(), Async::Ready(()));
+        assert_eq!(read_result, Async::Ready(0));
+        assert_eq!(subject.stats().parked, 1);
+        assert_eq!(subject.stats().idle, 1);
+        let reused = subject.take(&consumer(), &[IpAddr::from_str("2.3.4.5").unwrap()], 80);
+        assert_eq!(
+            reused.map(|connection| connection.local_addr),
+            Some(SocketAddr::from_str("192.168.0.1:5555").unwrap())
+        );
+        TestLogHandler::new().exists_log_containing(
+            "DEBUG: ConnectionPool: Parked idle connection to 2.3.4.5:80: 0 reused, 0 missed, 1 parked, 0 discarded, 1 idle",
+        );
+    }
+
+    #[test]
+    fn leased_connection_is_really_shut_down_when_the_browser_asked_to_close_it() {
+        let request = b"GET /index.html HTTP/1.1\r\nHost: server.com\r\nConnection: close\r\n\r\n";
+        let subject = make_pool(2);
+        let reader = ReadHalfWrapperMock::new().poll_read_ok(RESPONSE.to_vec());
+        let writer = WriteHalfWrapperMock::new()
+            .poll_write_ok(request.len())
+            .shutdown_ok();
+        let mut connection =
+            subject.lease(&consumer(), make_connection("2.3.4.5:80", reader, writer));
+
+        let shutdown_result = exchange(&mut connection, request);
+
+        assert_eq!(shutdown_result.unwrap(), Async::Ready(()));
+        assert_eq!(subject.stats().parked, 0);
+    }
+
+    #[test]
+    fn leased_connection_is_really_shut_down_while_a_response_is_outstanding() {
+        let subject = make_pool(2);
+        let reader = ReadHalfWrapperMock::new().poll_read_ok(RESPONSE[0..20].to_vec());
+        let writer = WriteHalfWrapperMock::new()
+            .poll_write_ok(REQUEST.len())
+            .shutdown_ok();
+        let mut connection =
+            subject.lease(&consumer(), make_connection("2.3.4.5:80", reader, writer));
+
+        let shutdown_result = exchange(&mut connection, REQUEST);
+
+        assert_eq!(shutdown_result.unwrap(), Async::Ready(()));
+        assert_eq!(subject.stats().parked, 0);
+    }
+
+    #[test]
+    fn leased_connection_is_really_shut_down_when_the_server_would_close_it() {
+        let subject = make_pool(2);
+        let response = b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\nConnection: close\r\n\r\nbooga";
+        let reader = ReadHalfWrapperMock::new().poll_read_ok(response.to_vec());
+        let writer = WriteHalfWrapperMock::new()
+            .poll_write_ok(REQUEST.len())
+            .shutdown_ok();
+        let mut connection =
+            subject.lease(&consumer(), make_connection("2.3.4.5:80", reader, writer));
+
+        let shutdown_result = exchange(&mut connection, REQUEST);
+
+        assert_eq!(shutdown_result.unwrap(), Async::Ready(()));
+        assert_eq!(subject.stats().parked, 0);
+    }
+}
@@ -2,25 +2,33 @@
 
 use crate::proxy_client::resolver_wrapper::ResolverWrapper;
 use crate::proxy_client::resolver_wrapper::ResolverWrapperFactory;
+use crate::proxy_client::resolver_wrapper::WrappedLookupFuture;
 use crate::proxy_client::resolver_wrapper::WrappedLookupIpFuture;
 use futures::future;
 use futures::sync::mpsc::unbounded;
 use futures::sync::mpsc::SendError;
+use futures::Future;
 use std::cell::RefCell;
 use std::net::IpAddr;
 use std::sync::Arc;
 use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tokio::timer::Delay;
 use trust_dns::rr::{Name, Record};
 use trust_dns_proto::op::Query;
 use trust_dns_resolver::config::ResolverConfig;
 use trust_dns_resolver::config::ResolverOpts;
 use trust_dns_resolver::error::ResolveError;
 use trust_dns_resolver::lookup::Lookup;
+use trust_dns_resolver::lookup_ip::LookupIp;
 use trust_dns_resolver::proto::rr::RData;
+use trust_dns_resolver::proto::rr::RecordType;
 
 pub struct ResolverWrapperMock {
     lookup_ip_results: RefCell<Vec<Box<WrappedLookupIpFuture>>>,
     lookup_ip_parameters: Arc<Mutex<Vec<String>>>,
+    lookup_results: RefCell<Vec<Box<WrappedLookupFuture>>>,
+    lookup_parameters: Arc<Mutex<Vec<(String, RecordType)>>>,
 }
 
 impl ResolverWrapper for ResolverWrapperMock {
@@ -31,6 +39,14 @@ impl ResolverWrapper for ResolverWrapperMock {
             .push(host.to_string());
         self.lookup_ip_results.borrow_mut().remove(0)
     }
+
+    fn lookup(&self, name: &str, record_type: RecordType) -> Box<WrappedLookupFuture> {
+        self.lookup_parameters
+            .lock()
+            .unwrap()
+            .push((name.to_string(), record_type));
+        self.lookup_results.borrow_mut().remove(0)
+    }
 }
 
 impl ResolverWrapperMock {
@@ -38,10 +54,34 @@ impl ResolverWrapperMock {
         ResolverWrapperMock {
             lookup_ip_results: RefCell::new(vec![]),
             lookup_ip_parameters: Arc::new(Mutex::new(vec![])),
+            lookup_results: RefCell::new(vec![]),
+            lookup_parameters: Arc::new(Mutex::new(vec![])),
         }
     }
 
     pub fn lookup_ip_success(self, ip_addrs: Vec<IpAddr>) -> ResolverWrapperMock {
+        let lookup_ip = Self::make_lookup_ip(ip_addrs);
+        self.lookup_ip_results
+            .borrow_mut()
+            .push(Box::new(future::ok(lookup_ip)));
+        self
+    }
+
+    // For a lookup that's still going when something else happens
+    pub fn lookup_ip_delayed_success(
+        self,
+        ip_addrs: Vec<IpAddr>,
+        delay: Duration,
+    ) -> ResolverWrapperMock {
+        let lookup_ip = Self::make_lookup_ip(ip_addrs);
+        self.lookup_ip_results.borrow_mut().push(Box::new(
+            Delay::new(Instant::now() + delay)
+                .then(move |_| Ok::<LookupIp, ResolveError>(lookup_ip)),
+        ));
+        self
+    }
+
+    fn make_lookup_ip(ip_addrs: Vec<IpAddr>) -> LookupIp {
         let records: Vec<Record> = ip_addrs
             .into_iter()
             .map(|ip_addr| match ip_addr {
@@ -54,11 +94,7 @@ impl ResolverWrapperMock {
             })
             .collect();
 
-        let lookup_ip = Lookup::new_with_max_ttl(Query::default(), Arc::new(records)).into();
-        self.lookup_ip_results
-            .borrow_mut()
-            .push(Box::new(future::ok(lookup_ip)));
-        self
+        Lookup::new_with_max_ttl(Query::default(), Arc::new(records)).into()
     }
 
     pub fn lookup_ip_failure(self, error: ResolveError) -> ResolverWrapperMock {
@@ -75,6 +111,29 @@ impl ResolverWrapperMock {
         self.lookup_ip_parameters = parameters.clone();
         self
     }
+
+    pub fn lookup_success(self, records: Vec<Record>) -> ResolverWrapperMock {
+        let lookup = Lookup::new_with_max_ttl(Query::default(), Arc::new(records));
+        self.lookup_results
+            .borrow_mut()
+            .push(Box::new(future::ok(lookup)));
+        self
+    }
+
+    pub fn lookup_failure(self, error: ResolveError) -> ResolverWrapperMock {
+        self.lookup_results
+            .borrow_mut()
+            .push(Box::new(future::err(error)));
+        self
+    }
+
+    pub fn lookup_parameters(
+        mut self,
+        parameters: &Arc<Mutex<Vec<(String, RecordType)>>>,
+    ) -> ResolverWrapperMock {
+        self.lookup_parameters = parameters.clone();
+        self
+    }
 }
 
 pub struct ResolverWrapperFactoryMock {
@@ -1,33 +1,48 @@
 // Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
 
+mod connection_pool;
 #[cfg(test)]
-mod local_test_utils;
-mod resolver_wrapper;
+pub mod local_test_utils;
+pub mod resolver_wrapper;
 mod stream_establisher;
 mod stream_handler_pool;
-mod stream_reader;
-mod stream_writer;
+pub mod stream_reader;
+pub mod stream_writer;
 
+use crate::proxy_client::connection_pool::ConnectionPool;
+use crate::proxy_client::resolver_wrapper::CachingResolverWrapper;
+use crate::proxy_client::resolver_wrapper::DnsCache;
+use crate::proxy_client::resolver_wrapper::ResolverWrapper;
 use crate::proxy_client::resolver_wrapper::ResolverWrapperFactory;
 use crate::proxy_client::resolver_wrapper::ResolverWrapperFactoryReal;
+use crate::proxy_client::resolver_wrapper::SharedResolverWrapper;
 use crate::proxy_client::stream_handler_pool::StreamHandlerPool;
 use crate::proxy_client::stream_handler_pool::StreamHandlerPoolFactory;
 use crate::proxy_client::stream_handler_pool::StreamHandlerPoolFactoryReal;
 use crate::sub_lib::accountant::ReportExitServiceProvidedMessage;
 use crate::sub_lib::cryptde::CryptDE;
 use crate::sub_lib::cryptde::PublicKey;
+use crate::sub_lib::exit_policy::ExitPolicy;
 use crate::sub_lib::hopper::MessageType;
 use crate::sub_lib::hopper::{ExpiredCoresPackage, IncipientCoresPackage};
 use crate::sub_lib::logger::Logger;
 use crate::sub_lib::peer_actors::BindMessage;
+use crate::sub_lib::privacy;
+use crate::sub_lib::proxy_client::DnsProtocol;
+use crate::sub_lib::proxy_client::GetDnsCacheStatsMsg;
 use crate::sub_lib::proxy_client::InboundServerData;
 use crate::sub_lib::proxy_client::ProxyClientConfig;
 use crate::sub_lib::proxy_client::ProxyClientSubs;
-use crate::sub_lib::proxy_client::{ClientResponsePayload, DnsResolveFailure};
+use crate::sub_lib::proxy_client::SetDnsServersMsg;
+use crate::sub_lib::proxy_client::{ClientResponsePayload, DnsResolveFailure, ExitPolicyRefusal};
+use crate::sub_lib::proxy_client::{DnsAnswerPayload, DnsRecord, DnsRecordData};
 use crate::sub_lib::proxy_server::ClientRequestPayload;
+use crate::sub_lib::proxy_server::DnsQueryPayload;
+use crate::sub_lib::proxy_server::MAX_ROUTE_PATH_COUNT;
 use crate::sub_lib::route::Route;
 use crate::sub_lib::sequence_buffer::SequencedPacket;
 use crate::sub_lib::stream_key::StreamKey;
+use crate::sub_lib::ui_gateway::{Setting, UiCarrierMessage, UiMessage};
 use crate::sub_lib::utils::NODE_MAILBOX_CAPACITY;
 use crate::sub_lib::wallet::Wallet;
 use actix::Actor;
@@ -38,14 +53,29 @@ use actix::Recipient;
 use pretty_hex::PrettyHex;
 use std::collections::HashMap;
 use std::net::SocketAddr;
+use tokio::prelude::future;
+use tokio::prelude::Future;
 use trust_dns_resolver::config::NameServerConfig;
 use trust_dns_resolver::config::Protocol;
 use trust_dns_resolver::config::ResolverConfig;
 use trust_dns_resolver::config::ResolverOpts;
+use trust_dns_resolver::error::ResolveError;
+use trust_dns_resolver::error::ResolveErrorKind;
+use trust_dns_resolver::lookup::Lookup;
+use trust_dns_resolver::proto::op::ResponseCode;
+use trust_dns_resolver::proto::rr::{Name, RData, RecordType};
+
+// A multipath stream's requests reach us over several routes, and its responses are spread back
+// over the return routes those requests carried. This caps how many we remember per stream.
+pub const MAX_RETURN_ROUTES_PER_STREAM: usize = MAX_ROUTE_PATH_COUNT;
 
 pub struct ProxyClient {
     dns_servers: Vec<SocketAddr>,
+    dns_protocol: DnsProtocol,
+    dns_cache: DnsCache,
     resolver_wrapper_factory: Box<dyn ResolverWrapperFactory>,
+    resolver_opt: Option<SharedResolverWrapper>,
+    ui_carrier_message_sub: Option<Recipient<UiCarrierMessage>>,
     stream_handler_pool_factory: Box<dyn StreamHandlerPoolFactory>,
     cryptde: &'static dyn CryptDE,
     to_hopper: Option<Recipient<IncipientCoresPackage>>,
@@ -54,6 +84,8 @@ pub struct ProxyClient {
     stream_contexts: HashMap<StreamKey, StreamContext>,
     exit_service_rate: u64,
     exit_byte_rate: u64,
+    exit_policy: ExitPolicy,
+    connection_pool_opt: Option<ConnectionPool>,
     logger: Logger,
 }
 
@@ -69,28 +101,65 @@ impl Handler<BindMessage> for ProxyClient {
         ctx.set_mailbox_capacity(NODE_MAILBOX_CAPACITY);
         self.to_hopper = Some(msg.peer_actors.hopper.from_hopper_client);
         self.to_accountant = Some(msg.peer_actors.accountant.report_exit_service_provided);
-        let mut config = ResolverConfig::new();
-        for dns_server_ref in &self.dns_servers {
-            info!(self.logger, "Adding DNS server: {}", dns_server_ref.ip());
-            config.add_name_server(NameServerConfig {
-                socket_addr: *dns_server_ref,
-                protocol: Protocol::Udp,
-                tls_dns_name: None,
-            })
-        }
-        let opts = ResolverOpts::default();
-        let resolver = self.resolver_wrapper_factory.make(config, opts);
+        self.ui_carrier_message_sub = Some(msg.peer_actors.ui_gateway.ui_message_sub);
+        let resolver = SharedResolverWrapper::new(self.make_resolver());
+        self.resolver_opt = Some(resolver.clone());
         self.pool = Some(self.stream_handler_pool_factory.make(
-            resolver,
+            Box::new(resolver),
             self.cryptde,
             self.to_accountant.clone().expect("Accountant is unbound"),
             msg.peer_actors.proxy_client.clone(),
             self.exit_service_rate,
             self.exit_byte_rate,
+            self.exit_policy.clone(),
+            self.connection_pool_opt.clone(),
         ));
     }
 }
 
+impl Handler<SetDnsServersMsg> for ProxyClient {
+    type Result = ();
+
+    fn handle(&mut self, msg: SetDnsServersMsg, _ctx: &mut Self::Context) -> Self::Result {
+        let port = self.dns_protocol.default_port();
+        self.dns_servers = msg
+            .dns_servers
+            .iter()
+            .map(|ip_addr| SocketAddr::new(*ip_addr, port))
+            .collect();
+        let resolver = self.make_resolver();
+        self.resolver_opt
+            .as_ref()
+            .expect("Resolver is unbound")
+            .replace(resolver);
+        self.ui_carrier_message_sub
+            .as_ref()
+            .expect("UiGateway is unbound")
+            .try_send(UiCarrierMessage {
+                client_id: msg.client_id,
+                context_id: Some(msg.context_id),
+                data: UiMessage::SetConfigurationResponse(Setting::DnsServers, Ok(())),
+            })
+            .expect("UiGateway is dead");
+    }
+}
+
+impl Handler<GetDnsCacheStatsMsg> for ProxyClient {
+    type Result = ();
+
+    fn handle(&mut self, msg: GetDnsCacheStatsMsg, _ctx: &mut Self::Context) -> Self::Result {
+        self.ui_carrier_message_sub
+            .as_ref()
+            .expect("UiGateway is unbound")
+            .try_send(UiCarrierMessage {
+                client_id: msg.client_id,
+                context_id: Some(msg.context_id),
+                data: UiMessage::DnsCacheStatsResponse(self.dns_cache.stats()),
+            })
+            .expect("UiGateway is dead");
+    }
+}
+
 impl Handler<ExpiredCoresPackage<ClientRequestPayload>> for ProxyClient {
     type Result = ();
 
@@ -104,11 +173,6 @@ impl Handler<ExpiredCoresPackage<ClientRequestPayload>> for ProxyClient {
         if paying_wallet.is_some() || &payload.originator_public_key == self.cryptde.public_key() {
             let pool = self.pool.as_mut().expect("StreamHandlerPool unbound");
             let return_route = msg.remaining_route;
-            let latest_stream_context = StreamContext {
-                return_route,
-                payload_destination_key: payload.originator_public_key.clone(),
-                paying_wallet: paying_wallet.clone(),
-            };
             debug!(
                 self.logger,
                 "Received ClientRequestPayload: stream {}, sequence {}, length {}",
@@ -116,8 +180,33 @@ impl Handler<ExpiredCoresPackage<ClientRequestPayload>> for ProxyClient {
                 payload.sequenced_packet.sequence_number,
                 payload.sequenced_packet.data.len()
             );
-            self.stream_contexts
-                .insert(payload.stream_key, latest_stream_context);
+            match self.stream_contexts.get_mut(&payload.stream_key) {
+                Some(stream_context) => {
+                    if payload.fresh_return_route {
+                        debug!(
+                            self.logger,
+                            "Originator rerouted stream {}; forgetting old return routes",
+                            payload.stream_key
+                        );
+                        stream_context.replace_return_routes(return_route);
+                    } else {
+                        stream_context.add_return_route(return_route);
+                    }
+                    stream_context.payload_destination_key = payload.originator_public_key.clone();
+                    stream_context.paying_wallet = paying_wallet.clone();
+                }
+                None => {
+                    self.stream_contexts.insert(
+                        payload.stream_key,
+                        StreamContext {
+                            return_route,
+                            alternate_return_routes: vec![],
+                            payload_destination_key: payload.originator_public_key.clone(),
+                            paying_wallet: paying_wallet.clone(),
+                        },
+                    );
+                }
+            }
             pool.process_package(payload, paying_wallet);
         } else {
             warning!(self.logger, "Refusing to provide exit services for CORES package with {}-byte payload without paying wallet", payload.sequenced_packet.data.len());
@@ -141,11 +230,11 @@ impl Handler<InboundServerData> for ProxyClient {
                     self.logger,
                     "Received InboundServerData{} from {}: stream {}, sequence {}, length {}; but no such known stream - ignoring\n{}",
                     if msg_last_data {" (last_data)"} else {""},
-                    msg_source,
+                    privacy::socket_addr(msg_source),
                     msg_stream_key,
                     msg_sequence_number,
                     msg_data_len,
-                    msg.data.hex_dump().to_string(),
+                    privacy::data(msg.data.hex_dump().to_string()),
                 );
                 return;
             }
@@ -154,6 +243,9 @@ impl Handler<InboundServerData> for ProxyClient {
             return;
         };
         self.report_response_exit_to_accountant(&stream_context, msg_data_len);
+        if let Some(stream_context) = self.stream_contexts.get_mut(&msg_stream_key) {
+            stream_context.rotate_return_routes();
+        }
         if msg_last_data {
             debug!(
                 self.logger,
@@ -169,35 +261,117 @@ impl Handler<DnsResolveFailure> for ProxyClient {
 
     fn handle(&mut self, msg: DnsResolveFailure, _ctx: &mut Self::Context) -> Self::Result {
         let stream_key = msg.stream_key;
-        let stream_context_opt = self.stream_contexts.get(&stream_key);
-        match stream_context_opt {
-            Some(stream_context) => {
-                let package = IncipientCoresPackage::new(
-                    self.cryptde,
-                    stream_context.return_route.clone(),
-                    MessageType::DnsResolveFailed(msg),
-                    &stream_context.payload_destination_key,
-                )
-                .expect("Failed to create IncipientCoresPackage");
-                self.to_hopper
-                    .as_ref()
-                    .expect("Hopper is unbound")
-                    .try_send(package)
-                    .expect("Hopper is dead");
-                debug!(
-                    self.logger,
-                    "Removing stream key {} for DnsResolveFailure", stream_key
-                );
-                self.stream_contexts.remove(&stream_key);
-            }
-            None => error!(
+        if !self.send_failure_to_originator(
+            stream_key,
+            MessageType::DnsResolveFailed(msg),
+            "DnsResolveFailure",
+        ) {
+            error!(
                 self.logger,
-                "DNS resolution for nonexistent stream ({:?}) failed.", msg.stream_key
-            ),
+                "DNS resolution for nonexistent stream ({:?}) failed.", stream_key
+            )
         }
     }
 }
 
+impl Handler<ExitPolicyRefusal> for ProxyClient {
+    type Result = ();
+
+    fn handle(&mut self, msg: ExitPolicyRefusal, _ctx: &mut Self::Context) -> Self::Result {
+        let stream_key = msg.stream_key;
+        info!(
+            self.logger,
+            "Refusing stream {}: {}",
+            stream_key,
+            privacy::data(&msg.reason)
+        );
+        if !self.send_failure_to_originator(
+            stream_key,
+            MessageType::ExitPolicyRefused(msg),
+            "ExitPolicyRefusal",
+        ) {
+            error!(
+                self.logger,
+                "Exit policy refused nonexistent stream ({:?}).", stream_key
+            )
+        }
+    }
+}
+
+impl Handler<ExpiredCoresPackage<DnsQueryPayload>> for ProxyClient {
+    type Result = ();
+
+    fn handle(
+        &mut self,
+        msg: ExpiredCoresPackage<DnsQueryPayload>,
+        _ctx: &mut Self::Context,
+    ) -> Self::Result {
+        let payload = msg.payload;
+        let paying_wallet = msg.paying_wallet;
+        if paying_wallet.is_none() && &payload.originator_public_key != self.cryptde.public_key() {
+            warning!(
+                self.logger,
+                "Refusing to look up {} for CORES package without paying wallet",
+                privacy::host(&payload.name)
+            );
+            return;
+        }
+        let record_type = RecordType::from(payload.record_type);
+        debug!(
+            self.logger,
+            "Looking up {} record for {}: query {}",
+            record_type,
+            privacy::host(&payload.name),
+            payload.stream_key
+        );
+        let lookup = ProxyClient::answer_dns_query(
+            self.resolver_opt.clone().expect("Resolver unbound"),
+            payload.stream_key,
+            payload.name.clone(),
+            record_type,
+        );
+        let cryptde = self.cryptde;
+        let to_hopper = self.to_hopper.clone().expect("Hopper unbound");
+        let to_accountant = self.to_accountant.clone().expect("Accountant unbound");
+        let exit_service_rate = self.exit_service_rate;
+        let exit_byte_rate = self.exit_byte_rate;
+        let logger = self.logger.clone();
+        let return_route = msg.remaining_route;
+        actix::spawn(lookup.and_then(move |answer| {
+            let icp = match IncipientCoresPackage::new(
+                cryptde,
+                return_route,
+                answer.into(),
+                &payload.originator_public_key,
+            ) {
+                Ok(icp) => icp,
+                Err(err) => {
+                    error!(
+                        logger,
+                        "Could not create CORES package for DNS answer to query {}: {} - ignoring",
+                        payload.stream_key,
+                        err
+                    );
+                    return Ok(());
+                }
+            };
+            let payload_size = icp.payload.len();
+            to_hopper.try_send(icp).expect("Hopper is dead");
+            if let Some(paying_wallet) = paying_wallet {
+                to_accountant
+                    .try_send(ReportExitServiceProvidedMessage {
+                        paying_wallet,
+                        payload_size,
+                        service_rate: exit_service_rate,
+                        byte_rate: exit_byte_rate,
+                    })
+                    .expect("Accountant is dead");
+            }
+            Ok(())
+        }));
+    }
+}
+
 impl ProxyClient {
     pub fn new(config: ProxyClientConfig) -> ProxyClient {
         if config.dns_servers.is_empty() {
@@ -205,7 +379,11 @@ impl ProxyClient {
         }
         ProxyClient {
             dns_servers: config.dns_servers,
+            dns_protocol: config.dns_protocol,
+            dns_cache: DnsCache::default(),
             resolver_wrapper_factory: Box::new(ResolverWrapperFactoryReal {}),
+            resolver_opt: None,
+            ui_carrier_message_sub: None,
             stream_handler_pool_factory: Box::new(StreamHandlerPoolFactoryReal {}),
             cryptde: config.cryptde,
             to_hopper: None,
@@ -214,6 +392,8 @@ impl ProxyClient {
             stream_contexts: HashMap::new(),
             exit_service_rate: config.exit_service_rate,
             exit_byte_rate: config.exit_byte_rate,
+            exit_policy: config.exit_policy,
+            connection_pool_opt: config.exit_connection_pool.map(ConnectionPool::new),
             logger: Logger::new("ProxyClient"),
         }
     }
@@ -226,7 +406,162 @@ impl ProxyClient {
                 .recipient::<ExpiredCoresPackage<ClientRequestPayload>>(),
             inbound_server_data: addr.clone().recipient::<InboundServerData>(),
             dns_resolve_failed: addr.clone().recipient::<DnsResolveFailure>(),
+            exit_policy_refused: addr.clone().recipient::<ExitPolicyRefusal>(),
+            dns_query_from_hopper: addr
+                .clone()
+                .recipient::<ExpiredCoresPackage<DnsQueryPayload>>(),
+            set_dns_servers_sub: addr.clone().recipient::<SetDnsServersMsg>(),
+            dns_cache_stats_sub: addr.clone().recipient::<GetDnsCacheStatsMsg>(),
+        }
+    }
+
+    // Both Bind and a change of DNS servers from a UI build a resolver this way; either way it
+    // shares the same cache.
+    fn make_resolver(&self) -> Box<dyn ResolverWrapper> {
+        let mut config = ResolverConfig::new();
+        let protocol = match self.dns_protocol {
+            DnsProtocol::Udp => Protocol::Udp,
+            DnsProtocol::Tls(_) => Protocol::Tls,
+            DnsProtocol::Https(_) => Protocol::Https,
+        };
+        for dns_server_ref in &self.dns_servers {
+            info!(
+                self.logger,
+                "Adding DNS server: {} ({:?})",
+                dns_server_ref.ip(),
+                self.dns_protocol
+            );
+            config.add_name_server(NameServerConfig {
+                socket_addr: *dns_server_ref,
+                protocol,
+                tls_dns_name: self.dns_protocol.tls_dns_name(),
+            })
         }
+        let opts = ResolverOpts::default();
+        Box::new(CachingResolverWrapper::new(
+            self.resolver_wrapper_factory.make(config, opts),
+            self.dns_cache.clone(),
+        ))
+    }
+
+    // trust-dns reports a name that doesn't exist just as it does a name without records of the
+    // type asked for, so whether the name exists at all is asked separately before answering
+    // NXDOMAIN. Servers that won't answer that say nothing about the name, which leaves it be.
+    fn answer_dns_query(
+        resolver: SharedResolverWrapper,
+        stream_key: StreamKey,
+        name: String,
+        record_type: RecordType,
+    ) -> Box<dyn Future<Item = DnsAnswerPayload, Error = ()> + Send> {
+        Box::new(
+            resolver
+                .lookup(&name, record_type)
+                .then(move |result| match result {
+                    Err(ref e) if ProxyClient::is_no_records_found(e) => {
+                        Box::new(resolver.lookup(&name, RecordType::ANY).then(move |result| {
+                            let response_code = match result {
+                                Err(ref e) if ProxyClient::is_no_records_found(e) => {
+                                    ResponseCode::NXDomain
+                                }
+                                _ => ResponseCode::NoError,
+                            };
+                            Ok(DnsAnswerPayload::new(
+                                stream_key,
+                                u16::from(response_code),
+                                vec![],
+                            ))
+                        }))
+                            as Box<dyn Future<Item = DnsAnswerPayload, Error = ()> + Send>
+                    }
+                    result => Box::new(future::ok(ProxyClient::dns_answer_from_lookup(
+                        stream_key, result,
+                    ))),
+                }),
+        )
+    }
+
+    fn is_no_records_found(error: &ResolveError) -> bool {
+        match error.kind() {
+            ResolveErrorKind::NoRecordsFound { .. } => true,
+            _ => false,
+        }
+    }
+
+    // A name with no records of the requested type comes back from the resolver as an error, but
+    // it's no failure: the answer is simply empty.
+    fn dns_answer_from_lookup(
+        stream_key: StreamKey,
+        result: Result<Lookup, ResolveError>,
+    ) -> DnsAnswerPayload {
+        match result {
+            Ok(lookup) => DnsAnswerPayload::new(
+                stream_key,
+                u16::from(ResponseCode::NoError),
+                lookup
+                    .record_iter()
+                    .filter_map(|record| {
+                        let data = match record.rdata() {
+                            RData::A(ip_addr) => DnsRecordData::A(*ip_addr),
+                            RData::AAAA(ip_addr) => DnsRecordData::Aaaa(*ip_addr),
+                            RData::CNAME(name) => DnsRecordData::Cname(Self::plain_name(name)),
+                            RData::MX(mx) => {
+                                DnsRecordData::Mx(mx.preference(), Self::plain_name(mx.exchange()))
+                            }
+                            RData::TXT(txt) => DnsRecordData::Txt(
+                                txt.iter().map(|string| string.to_vec()).collect(),
+                            ),
+                            _ => return None,
+                        };
+                        Some(DnsRecord {
+                            name: Self::plain_name(record.name()),
+                            ttl: record.ttl(),
+                            data,
+                        })
+                    })
+                    .collect(),
+            ),
+            Err(ref e) => match e.kind() {
+                ResolveErrorKind::NoRecordsFound { .. } => {
+                    DnsAnswerPayload::new(stream_key, u16::from(ResponseCode::NoError), vec![])
+                }
+                _ => DnsAnswerPayload::new(stream_key, u16::from(ResponseCode::ServFail), vec![]),
+            },
+        }
+    }
+
+    fn plain_name(name: &Name) -> String {
+        name.to_utf8().trim_end_matches('.').to_string()
+    }
+
+    // Returns false if there's no stream to send it back over.
+    fn send_failure_to_originator(
+        &mut self,
+        stream_key: StreamKey,
+        payload: MessageType,
+        message_name: &str,
+    ) -> bool {
+        let stream_context = match self.stream_contexts.get(&stream_key) {
+            Some(stream_context) => stream_context,
+            None => return false,
+        };
+        let package = IncipientCoresPackage::new(
+            self.cryptde,
+            stream_context.return_route.clone(),
+            payload,
+            &stream_context.payload_destination_key,
+        )
+        .expect("Failed to create IncipientCoresPackage");
+        self.to_hopper
+            .as_ref()
+            .expect("Hopper is unbound")
+            .try_send(package)
+            .expect("Hopper is dead");
+        debug!(
+            self.logger,
+            "Removing stream key {} for {}", stream_key, message_name
+        );
+        self.stream_contexts.remove(&stream_key);
+        true
     }
 
     fn send_response_to_hopper(
@@ -262,7 +597,7 @@ impl ProxyClient {
         ) {
             Ok(icp) => icp,
             Err(err) => {
-                error!(self.logger, "Could not create CORES package for {}-byte response from {}, seq {}: {} - ignoring", msg_data_len, msg_source, msg_sequence_number, err);
+                error!(self.logger, "Could not create CORES package for {}-byte response from {}, seq {}: {} - ignoring", msg_data_len, privacy::socket_addr(msg_source), msg_sequence_number, err);
                 return Err(());
             }
         };
@@ -302,10 +637,42 @@ impl ProxyClient {
 
 struct StreamContext {
     return_route: Route,
+    alternate_return_routes: Vec<Route>,
     payload_destination_key: PublicKey,
     paying_wallet: Option<Wallet>,
 }
 
+impl StreamContext {
+    // Every package that comes back over a given path carries fresh garbage at the tail of its
+    // Route, but the head hop is the same each time; that's how we tell paths apart.
+    fn add_return_route(&mut self, return_route: Route) {
+        let head = return_route.hops.first().cloned();
+        self.alternate_return_routes
+            .retain(|route| route.hops.first() != head.as_ref());
+        let previous = std::mem::replace(&mut self.return_route, return_route);
+        if previous.hops.first() != head.as_ref() {
+            self.alternate_return_routes.push(previous);
+        }
+        while self.alternate_return_routes.len() >= MAX_RETURN_ROUTES_PER_STREAM {
+            self.alternate_return_routes.remove(0);
+        }
+    }
+
+    fn replace_return_routes(&mut self, return_route: Route) {
+        self.return_route = return_route;
+        self.alternate_return_routes.clear();
+    }
+
+    fn rotate_return_routes(&mut self) {
+        if self.alternate_return_routes.is_empty() {
+            return;
+        }
+        let next = self.alternate_return_routes.remove(0);
+        let previous = std::mem::replace(&mut self.return_route, next);
+        self.alternate_return_routes.push(previous);
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -317,8 +684,11 @@ mod tests {
     use crate::sub_lib::accountant::ReportExitServiceProvidedMessage;
     use crate::sub_lib::cryptde::CryptData;
     use crate::sub_lib::cryptde::PublicKey;
+    use crate::sub_lib::exit_policy::ExitRule;
     use crate::sub_lib::hopper::MessageType;
     use crate::sub_lib::proxy_client::ClientResponsePayload;
+    use crate::sub_lib::proxy_client::ConnectionPoolConfig;
+    use crate::sub_lib::proxy_client::DnsCacheStats;
     use crate::sub_lib::proxy_server::ClientRequestPayload;
     use crate::sub_lib::proxy_server::ProxyProtocol;
     use crate::sub_lib::route::Route;
@@ -333,11 +703,17 @@ mod tests {
     use actix::System;
     use std::cell::RefCell;
     use std::net::IpAddr;
+    use std::net::Ipv4Addr;
     use std::net::SocketAddr;
     use std::str::FromStr;
     use std::sync::Arc;
     use std::sync::Mutex;
     use std::thread;
+    use std::time::Duration;
+    use tokio::prelude::Future;
+    use trust_dns_resolver::proto::op::Query;
+    use trust_dns_resolver::proto::rr::rdata::{MX, NULL};
+    use trust_dns_resolver::proto::rr::Record;
 
     fn dnss() -> Vec<SocketAddr> {
         vec![SocketAddr::from_str("8.8.8.8:53").unwrap()]
@@ -382,6 +758,8 @@ mod tests {
                     ProxyClientSubs,
                     u64,
                     u64,
+                    ExitPolicy,
+                    Option<ConnectionPool>,
                 )>,
             >,
         >,
@@ -397,6 +775,8 @@ mod tests {
             proxy_client_subs: ProxyClientSubs,
             exit_service_rate: u64,
             exit_byte_rate: u64,
+            exit_policy: ExitPolicy,
+            connection_pool_opt: Option<ConnectionPool>,
         ) -> Box<dyn StreamHandlerPool> {
             self.make_parameters.lock().unwrap().push((
                 resolver,
@@ -405,6 +785,8 @@ mod tests {
                 proxy_client_subs,
                 exit_service_rate,
                 exit_byte_rate,
+                exit_policy,
+                connection_pool_opt,
             ));
             self.make_results.borrow_mut().remove(0)
         }
@@ -429,6 +811,8 @@ mod tests {
                         ProxyClientSubs,
                         u64,
                         u64,
+                        ExitPolicy,
+                        Option<ConnectionPool>,
                     )>,
                 >,
             >,
@@ -454,15 +838,21 @@ mod tests {
         ProxyClient::new(ProxyClientConfig {
             cryptde: cryptde(),
             dns_servers: vec![],
+            dns_protocol: DnsProtocol::Udp,
             exit_service_rate: 100,
             exit_byte_rate: 200,
+            exit_policy: ExitPolicy::default(),
+            exit_connection_pool: None,
         });
     }
 
     #[test]
     fn bind_operates_properly() {
         let system = System::new("bind_initializes_resolver_wrapper_properly");
-        let resolver_wrapper = ResolverWrapperMock::new();
+        let lookup_ip_parameters = Arc::new(Mutex::new(vec![]));
+        let resolver_wrapper = ResolverWrapperMock::new()
+            .lookup_ip_success(vec![IpAddr::from_str("1.2.3.4").unwrap()])
+            .lookup_ip_parameters(&lookup_ip_parameters);
         let mut resolver_wrapper_new_parameters_arc: Arc<
             Mutex<Vec<(ResolverConfig, ResolverOpts)>>,
         > = Arc::new(Mutex::new(vec![]));
@@ -481,8 +871,14 @@ mod tests {
                 SocketAddr::from_str("4.3.2.1:4321").unwrap(),
                 SocketAddr::from_str("5.4.3.2:5432").unwrap(),
             ],
+            dns_protocol: DnsProtocol::Udp,
             exit_service_rate: 100,
             exit_byte_rate: 200,
+            exit_policy: ExitPolicy::new(vec![], vec![ExitRule::Ports(25, 25)]),
+            exit_connection_pool: Some(ConnectionPoolConfig {
+                max_idle_per_server: 4,
+                idle_timeout: Duration::from_secs(4),
+            }),
         });
         subject.resolver_wrapper_factory = Box::new(resolver_wrapper_factory);
         subject.stream_handler_pool_factory = Box::new(pool_factory);
@@ -515,6 +911,252 @@ mod tests {
         );
         assert_eq!(opts, ResolverOpts::default());
         assert_eq!(resolver_wrapper_new_parameters.is_empty(), true);
+        let pool_factory_make_parameters = pool_factory_make_parameters.lock().unwrap();
+        let (
+            resolver,
+            _,
+            _,
+            _,
+            exit_service_rate,
+            exit_byte_rate,
+            exit_policy,
+            connection_pool_opt,
+        ) = &pool_factory_make_parameters[0];
+        assert_eq!(*exit_service_rate, 100);
+        assert_eq!(*exit_byte_rate, 200);
+        assert_eq!(
+            exit_policy,
+            &ExitPolicy::new(vec![], vec![ExitRule::Ports(25, 25)])
+        );
+        assert_eq!(connection_pool_opt.is_some(), true);
+        // The pool's resolver is behind the cache: the second look-up never reaches the mock
+        assert_eq!(resolver.lookup_ip("booga.com").wait().is_ok(), true);
+        assert_eq!(resolver.lookup_ip("booga.com").wait().is_ok(), true);
+        assert_eq!(
+            *lookup_ip_parameters.lock().unwrap(),
+            vec!["booga.com".to_string()]
+        );
+    }
+
+    #[test]
+    fn bind_configures_encrypted_dns_servers() {
+        let system = System::new("bind_configures_encrypted_dns_servers");
+        let mut resolver_wrapper_new_parameters_arc: Arc<
+            Mutex<Vec<(ResolverConfig, ResolverOpts)>>,
+        > = Arc::new(Mutex::new(vec![]));
+        let resolver_wrapper_factory = ResolverWrapperFactoryMock::new()
+            .new_parameters(&mut resolver_wrapper_new_parameters_arc)
+            .new_result(Box::new(ResolverWrapperMock::new()));
+        let pool_factory =
+            StreamHandlerPoolFactoryMock::new().make_result(Box::new(StreamHandlerPoolMock::new()));
+        let peer_actors = peer_actors_builder().build();
+        let mut subject = ProxyClient::new(ProxyClientConfig {
+            cryptde: cryptde(),
+            dns_servers: vec![SocketAddr::from_str("1.1.1.1:853").unwrap()],
+            dns_protocol: DnsProtocol::Tls("cloudflare-dns.com".to_string()),
+            exit_service_rate: 100,
+            exit_byte_rate: 200,
+            exit_policy: ExitPolicy::default(),
+            exit_connection_pool: None,
+        });
+        subject.resolver_wrapper_factory = Box::new(resolver_wrapper_factory);
+        subject.stream_handler_pool_factory = Box::new(pool_factory);
+        let subject_addr: Addr<ProxyClient> = subject.start();
+
+        subject_addr.try_send(BindMessage { peer_actors }).unwrap();
+
+        System::current().stop_with_code(0);
+        system.run();
+
+        let mut resolver_wrapper_new_parameters =
+            resolver_wrapper_new_parameters_arc.lock().unwrap();
+        let (config, _) = resolver_wrapper_new_parameters.remove(0);
+        assert_eq!(
+            config.name_servers(),
+            &[NameServerConfig {
+                socket_addr: SocketAddr::from_str("1.1.1.1:853").unwrap(),
+                protocol: Protocol::Tls,
+                tls_dns_name: Some("cloudflare-dns.com".to_string()),
+            }]
+        );
+    }
+
+    #[test]
+    fn bind_configures_dns_over_https_servers() {
+        let system = System::new("bind_configures_dns_over_https_servers");
+        let mut resolver_wrapper_new_parameters_arc: Arc<
+            Mutex<Vec<(ResolverConfig, ResolverOpts)>>,
+        > = Arc::new(Mutex::new(vec![]));
+        let resolver_wrapper_factory = ResolverWrapperFactoryMock::new()
+            .new_parameters(&mut resolver_wrapper_new_parameters_arc)
+            .new_result(Box::new(ResolverWrapperMock::new()));
+        let pool_factory =
+            StreamHandlerPoolFactoryMock::new().make_result(Box::new(StreamHandlerPoolMock::new()));
+        let peer_actors = peer_actors_builder().build();
+        let mut subject = ProxyClient::new(ProxyClientConfig {
+            cryptde: cryptde(),
+            dns_servers: vec![SocketAddr::from_str("1.1.1.1:443").unwrap()],
+            dns_protocol: DnsProtocol::Https("cloudflare-dns.com".to_string()),
+            exit_service_rate: 100,
+            exit_byte_rate: 200,
+            exit_policy: ExitPolicy::default(),
+            exit_connection_pool: None,
+        });
+        subject.resolver_wrapper_factory = Box::new(resolver_wrapper_factory);
+        subject.stream_handler_pool_factory = Box::new(pool_factory);
+        let subject_addr: Addr<ProxyClient> = subject.start();
+
+        subject_addr.try_send(BindMessage { peer_actors }).unwrap();
+
+        System::current().stop_with_code(0);
+        system.run();
+
+        let mut resolver_wrapper_new_parameters =
+            resolver_wrapper_new_parameters_arc.lock().unwrap();
+        let (config, _) = resolver_wrapper_new_parameters.remove(0);
+        assert_eq!(
+            config.name_servers(),
+            &[NameServerConfig {
+                socket_addr: SocketAddr::from_str("1.1.1.1:443").unwrap(),
+                protocol: Protocol::Https,
+                tls_dns_name: Some("cloudflare-dns.com".to_string()),
+            }]
+        );
+    }
+
+    #[test]
+    fn set_dns_servers_replaces_the_resolver_and_acknowledges() {
+        let system = System::new("set_dns_servers_replaces_the_resolver_and_acknowledges");
+        let old_lookup_ip_parameters = Arc::new(Mutex::new(vec![]));
+        let new_lookup_ip_parameters = Arc::new(Mutex::new(vec![]));
+        let old_resolver_wrapper =
+            ResolverWrapperMock::new().lookup_ip_parameters(&old_lookup_ip_parameters);
+        let new_resolver_wrapper = ResolverWrapperMock::new()
+            .lookup_ip_success(vec![IpAddr::from_str("1.2.3.4").unwrap()])
+            .lookup_ip_parameters(&new_lookup_ip_parameters);
+        let mut resolver_wrapper_new_parameters_arc: Arc<
+            Mutex<Vec<(ResolverConfig, ResolverOpts)>>,
+        > = Arc::new(Mutex::new(vec![]));
+        let resolver_wrapper_factory = ResolverWrapperFactoryMock::new()
+            .new_parameters(&mut resolver_wrapper_new_parameters_arc)
+            .new_result(Box::new(old_resolver_wrapper))
+            .new_result(Box::new(new_resolver_wrapper));
+        let mut pool_factory_make_parameters = Arc::new(Mutex::new(vec![]));
+        let pool_factory = StreamHandlerPoolFactoryMock::new()
+            .make_parameters(&mut pool_factory_make_parameters)
+            .make_result(Box::new(StreamHandlerPoolMock::new()));
+        let (ui_gateway, _, ui_gateway_recording_arc) = make_recorder();
+        let peer_actors = peer_actors_builder().ui_gateway(ui_gateway).build();
+        let mut subject = ProxyClient::new(ProxyClientConfig {
+            cryptde: cryptde(),
+            dns_servers: vec![SocketAddr::from_str("1.1.1.1:853").unwrap()],
+            dns_protocol: DnsProtocol::Tls("dns.example.com".to_string()),
+            exit_service_rate: 100,
+            exit_byte_rate: 200,
+            exit_policy: ExitPolicy::default(),
+            exit_connection_pool: None,
+        });
+        subject.resolver_wrapper_factory = Box::new(resolver_wrapper_factory);
+        subject.stream_handler_pool_factory = Box::new(pool_factory);
+        let subject_addr: Addr<ProxyClient> = subject.start();
+        subject_addr.try_send(BindMessage { peer_actors }).unwrap();
+
+        subject_addr
+            .try_send(SetDnsServersMsg {
+                client_id: 1234,
+                context_id: 4321,
+                dns_servers: vec![
+                    IpAddr::from_str("9.9.9.9").unwrap(),
+                    IpAddr::from_str("8.8.8.8").unwrap(),
+                ],
+            })
+            .unwrap();
+
+        System::current().stop_with_code(0);
+        system.run();
+        let resolver_wrapper_new_parameters = resolver_wrapper_new_parameters_arc.lock().unwrap();
+        let (config, _) = &resolver_wrapper_new_parameters[1];
+        assert_eq!(
+            config.name_servers(),
+            &[
+                NameServerConfig {
+                    socket_addr: SocketAddr::from_str("9.9.9.9:853").unwrap(),
+                    protocol: Protocol::Tls,
+                    tls_dns_name: Some("dns.example.com".to_string()),
+                },
+                NameServerConfig {
+                    socket_addr: SocketAddr::from_str("8.8.8.8:853").unwrap(),
+                    protocol: Protocol::Tls,
+                    tls_dns_name: Some("dns.example.com".to_string()),
+                },
+            ]
+        );
+        assert_eq!(resolver_wrapper_new_parameters.len(), 2);
+        let pool_factory_make_parameters = pool_factory_make_parameters.lock().unwrap();
+        let resolver = &pool_factory_make_parameters[0].0;
+        assert_eq!(resolver.lookup_ip("booga.com").wait().is_ok(), true);
+        assert_eq!(old_lookup_ip_parameters.lock().unwrap().is_empty(), true);
+        assert_eq!(
+            *new_lookup_ip_parameters.lock().unwrap(),
+            vec!["booga.com".to_string()]
+        );
+        let ui_gateway_recording = ui_gateway_recording_arc.lock().unwrap();
+        assert_eq!(
+            ui_gateway_recording.get_record::<UiCarrierMessage>(0),
+            &UiCarrierMessage {
+                client_id: 1234,
+                context_id: Some(4321),
+                data: UiMessage::SetConfigurationResponse(Setting::DnsServers, Ok(())),
+            }
+        );
+    }
+
+    #[test]
+    fn get_dns_cache_stats_answers_the_ui_that_asked() {
+        let system = System::new("get_dns_cache_stats_answers_the_ui_that_asked");
+        let (ui_gateway, _, ui_gateway_recording_arc) = make_recorder();
+        let peer_actors = peer_actors_builder().ui_gateway(ui_gateway).build();
+        let mut subject = ProxyClient::new(ProxyClientConfig {
+            cryptde: cryptde(),
+            dns_servers: dnss(),
+            dns_protocol: DnsProtocol::Udp,
+            exit_service_rate: 100,
+            exit_byte_rate: 200,
+            exit_policy: ExitPolicy::default(),
+            exit_connection_pool: None,
+        });
+        subject.resolver_wrapper_factory = Box::new(
+            ResolverWrapperFactoryMock::new().new_result(Box::new(ResolverWrapperMock::new())),
+        );
+        subject.stream_handler_pool_factory = Box::new(
+            StreamHandlerPoolFactoryMock::new().make_result(Box::new(StreamHandlerPoolMock::new())),
+        );
+        let subject_addr: Addr<ProxyClient> = subject.start();
+        subject_addr.try_send(BindMessage { peer_actors }).unwrap();
+
+        subject_addr
+            .try_send(GetDnsCacheStatsMsg {
+                client_id: 1234,
+                context_id: 4321,
+            })
+            .unwrap();
+
+        System::current().stop_with_code(0);
+        system.run();
+        let ui_gateway_recording = ui_gateway_recording_arc.lock().unwrap();
+        assert_eq!(
+            ui_gateway_recording.get_record::<UiCarrierMessage>(0),
+            &UiCarrierMessage {
+                client_id: 1234,
+                context_id: Some(4321),
+                data: UiMessage::DnsCacheStatsResponse(DnsCacheStats {
+                    hits: 0,
+                    negative_hits: 0,
+                    misses: 0,
+                    entries: 0,
+                }),
+            }
+        );
     }
 
     #[test]
@@ -532,6 +1174,9 @@ mod tests {
             target_port: 1234,
             protocol: ProxyProtocol::HTTP,
             originator_public_key: PublicKey::new(&b"originator_public_key"[..]),
+            fresh_return_route: false,
+            alpn_protocols: vec![],
+            trace_id_opt: None,
         };
         let cryptde = cryptde();
         let package = ExpiredCoresPackage::new(
@@ -545,8 +1190,11 @@ mod tests {
         let subject = ProxyClient::new(ProxyClientConfig {
             cryptde,
             dns_servers: dnss(),
+            dns_protocol: DnsProtocol::Udp,
             exit_service_rate: 100,
             exit_byte_rate: 200,
+            exit_policy: ExitPolicy::default(),
+            exit_connection_pool: None,
         });
         let subject_addr: Addr<ProxyClient> = subject.start();
 
@@ -567,8 +1215,11 @@ mod tests {
             let subject = ProxyClient::new(ProxyClientConfig {
                 cryptde,
                 dns_servers: vec![SocketAddr::from_str("1.1.1.1:53").unwrap()],
+                dns_protocol: DnsProtocol::Udp,
                 exit_service_rate: 0,
                 exit_byte_rate: 0,
+                exit_policy: ExitPolicy::default(),
+                exit_connection_pool: None,
             });
             let subject_addr = subject.start();
             let subject_subs = ProxyClient::make_subs_from(&subject_addr);
@@ -606,13 +1257,17 @@ mod tests {
             let mut subject = ProxyClient::new(ProxyClientConfig {
                 cryptde,
                 dns_servers: vec![SocketAddr::from_str("1.1.1.1:53").unwrap()],
+                dns_protocol: DnsProtocol::Udp,
                 exit_service_rate: 0,
                 exit_byte_rate: 0,
+                exit_policy: ExitPolicy::default(),
+                exit_connection_pool: None,
             });
             subject.stream_contexts.insert(
                 stream_key_inner,
                 StreamContext {
                     return_route: return_route_inner,
+                    alternate_return_routes: vec![],
                     payload_destination_key: originator_key_inner,
                     paying_wallet: None,
                 },
@@ -655,6 +1310,81 @@ mod tests {
         );
     }
 
+    #[test]
+    fn forwards_exit_policy_refusal_to_hopper() {
+        init_test_logging();
+        let cryptde = cryptde();
+        let (hopper, hopper_awaiter, hopper_recording_arc) = make_recorder();
+        let stream_key = make_meaningless_stream_key();
+        let return_route = make_meaningless_route();
+        let originator_key = make_meaningless_public_key();
+        let stream_key_inner = stream_key.clone();
+        let return_route_inner = return_route.clone();
+        let originator_key_inner = originator_key.clone();
+        let refusal = ExitPolicyRefusal::new(
+            stream_key,
+            "localhost port 80 is refused by the exit Node's exit policy".to_string(),
+        );
+        let refusal_inner = refusal.clone();
+        thread::spawn(move || {
+            let system = System::new("forwards_exit_policy_refusal_to_hopper");
+            let peer_actors = peer_actors_builder().hopper(hopper).build();
+            let mut subject = ProxyClient::new(ProxyClientConfig {
+                cryptde,
+                dns_servers: vec![SocketAddr::from_str("1.1.1.1:53").unwrap()],
+                dns_protocol: DnsProtocol::Udp,
+                exit_service_rate: 0,
+                exit_byte_rate: 0,
+                exit_policy: ExitPolicy::default(),
+                exit_connection_pool: None,
+            });
+            subject.stream_contexts.insert(
+                stream_key_inner,
+                StreamContext {
+                    return_route: return_route_inner,
+                    alternate_return_routes: vec![],
+                    payload_destination_key: originator_key_inner,
+                    paying_wallet: None,
+                },
+            );
+            let subject_addr = subject.start();
+            let subject_subs = ProxyClient::make_subs_from(&subject_addr);
+
+            send_bind_message!(subject_subs, peer_actors);
+
+            subject_subs
+                .exit_policy_refused
+                .try_send(refusal_inner.clone())
+                .unwrap();
+
+            subject_subs
+                .exit_policy_refused
+                .try_send(refusal_inner)
+                .unwrap();
+
+            system.run();
+        });
+
+        hopper_awaiter.await_message_count(1);
+
+        let message_type: MessageType = refusal.into();
+        assert_eq!(
+            &IncipientCoresPackage::new(cryptde, return_route, message_type, &originator_key)
+                .unwrap(),
+            hopper_recording_arc
+                .lock()
+                .unwrap()
+                .get_record::<IncipientCoresPackage>(0)
+        );
+        TestLogHandler::new().await_log_containing(
+            &format!(
+                "ERROR: ProxyClient: Exit policy refused nonexistent stream ({:?}).",
+                stream_key
+            ),
+            1000,
+        );
+    }
+
     #[test]
     fn data_from_hopper_is_relayed_to_stream_handler_pool() {
         let cryptde = cryptde();
@@ -670,6 +1400,9 @@ mod tests {
             target_port: 0,
             protocol: ProxyProtocol::HTTP,
             originator_public_key: PublicKey::new(&b"originator"[..]),
+            fresh_return_route: false,
+            alpn_protocols: vec![],
+            trace_id_opt: None,
         };
         let package = ExpiredCoresPackage::new(
             SocketAddr::from_str("1.2.3.4:1234").unwrap(),
@@ -694,8 +1427,11 @@ mod tests {
         let mut subject = ProxyClient::new(ProxyClientConfig {
             cryptde,
             dns_servers: dnss(),
+            dns_protocol: DnsProtocol::Udp,
             exit_service_rate: 100,
             exit_byte_rate: 200,
+            exit_policy: ExitPolicy::default(),
+            exit_connection_pool: None,
         });
         subject.resolver_wrapper_factory = Box::new(resolver_factory);
         subject.stream_handler_pool_factory = Box::new(pool_factory);
@@ -710,6 +1446,212 @@ mod tests {
         assert_eq!(parameter, (request, Some(make_wallet("consuming")),));
     }
 
+    #[test]
+    fn dns_query_from_hopper_is_looked_up_and_answered_over_its_return_route() {
+        let cryptde = cryptde();
+        let (hopper, hopper_awaiter, hopper_recording_arc) = make_recorder();
+        let (accountant, accountant_awaiter, accountant_recording_arc) = make_recorder();
+        let originator_key = PublicKey::new(&b"originator"[..]);
+        let return_route = make_meaningless_route();
+        let stream_key = make_meaningless_stream_key();
+        let query = DnsQueryPayload {
+            version: DnsQueryPayload::version(),
+            stream_key,
+            name: "example.com".to_string(),
+            record_type: u16::from(RecordType::MX),
+            originator_public_key: originator_key.clone(),
+        };
+        let package = ExpiredCoresPackage::new(
+            SocketAddr::from_str("1.2.3.4:1234").unwrap(),
+            Some(make_wallet("consuming")),
+            return_route.clone(),
+            query,
+            0,
+        );
+        let lookup_parameters = Arc::new(Mutex::new(vec![]));
+        let lookup_parameters_inner = lookup_parameters.clone();
+        thread::spawn(move || {
+            let system = System::new(
+                "dns_query_from_hopper_is_looked_up_and_answered_over_its_return_route",
+            );
+            let peer_actors = peer_actors_builder()
+                .hopper(hopper)
+                .accountant(accountant)
+                .build();
+            let resolver = ResolverWrapperMock::new()
+                .lookup_success(vec![
+                    Record::from_rdata(
+                        Name::from_str("example.com").unwrap(),
+                        300,
+                        RData::MX(MX::new(10, Name::from_str("mail.example.com.").unwrap())),
+                    ),
+                    Record::from_rdata(
+                        Name::from_str("example.com").unwrap(),
+                        300,
+                        RData::NULL(NULL::new()),
+                    ),
+                ])
+                .lookup_parameters(&lookup_parameters_inner);
+            let resolver_factory = ResolverWrapperFactoryMock::new().new_result(Box::new(resolver));
+            let pool_factory = StreamHandlerPoolFactoryMock::new()
+                .make_result(Box::new(StreamHandlerPoolMock::new()));
+            let mut subject = ProxyClient::new(ProxyClientConfig {
+                cryptde,
+                dns_servers: dnss(),
+                dns_protocol: DnsProtocol::Udp,
+                exit_service_rate: 100,
+                exit_byte_rate: 200,
+                exit_policy: ExitPolicy::default(),
+                exit_connection_pool: None,
+            });
+            subject.resolver_wrapper_factory = Box::new(resolver_factory);
+            subject.stream_handler_pool_factory = Box::new(pool_factory);
+            let subject_addr: Addr<ProxyClient> = subject.start();
+            subject_addr.try_send(BindMessage { peer_actors }).unwrap();
+
+            subject_addr.try_send(package).unwrap();
+
+            system.run();
+        });
+
+        hopper_awaiter.await_message_count(1);
+        let expected_answer = DnsAnswerPayload::new(
+            stream_key,
+            0,
+            vec![DnsRecord {
+                name: "example.com".to_string(),
+                ttl: 300,
+                data: DnsRecordData::Mx(10, "mail.example.com".to_string()),
+            }],
+        );
+        let expected_package = IncipientCoresPackage::new(
+            cryptde,
+            return_route,
+            expected_answer.into(),
+            &originator_key,
+        )
+        .unwrap();
+        assert_eq!(
+            hopper_recording_arc
+                .lock()
+                .unwrap()
+                .get_record::<IncipientCoresPackage>(0),
+            &expected_package
+        );
+        accountant_awaiter.await_message_count(1);
+        assert_eq!(
+            accountant_recording_arc
+                .lock()
+                .unwrap()
+                .get_record::<ReportExitServiceProvidedMessage>(0),
+            &ReportExitServiceProvidedMessage {
+                paying_wallet: make_wallet("consuming"),
+                payload_size: expected_package.payload.len(),
+                service_rate: 100,
+                byte_rate: 200,
+            }
+        );
+        assert_eq!(
+            *lookup_parameters.lock().unwrap(),
+            vec![("example.com".to_string(), RecordType::MX)]
+        );
+    }
+
+    #[test]
+    fn dns_answer_from_lookup_tells_empty_answers_from_failures() {
+        let no_records = ResolveError::from(ResolveErrorKind::NoRecordsFound {
+            query: Query::default(),
+            valid_until: None,
+        });
+        let io_failure = ResolveError::from(ResolveErrorKind::Io);
+
+        let stream_key = make_meaningless_stream_key();
+
+        let empty_answer = ProxyClient::dns_answer_from_lookup(stream_key, Err(no_records));
+        let failed_answer = ProxyClient::dns_answer_from_lookup(stream_key, Err(io_failure));
+
+        assert_eq!(
+            empty_answer,
+            DnsAnswerPayload::new(stream_key, u16::from(ResponseCode::NoError), vec![])
+        );
+        assert_eq!(
+            failed_answer,
+            DnsAnswerPayload::new(stream_key, u16::from(ResponseCode::ServFail), vec![])
+        );
+    }
+
+    #[test]
+    fn dns_query_for_a_name_that_does_not_exist_is_answered_with_nxdomain() {
+        let no_records = || {
+            ResolveError::from(ResolveErrorKind::NoRecordsFound {
+                query: Query::default(),
+                valid_until: None,
+            })
+        };
+        let lookup_parameters = Arc::new(Mutex::new(vec![]));
+        let resolver = ResolverWrapperMock::new()
+            .lookup_failure(no_records())
+            .lookup_failure(no_records())
+            .lookup_parameters(&lookup_parameters);
+        let stream_key = make_meaningless_stream_key();
+
+        let result = ProxyClient::answer_dns_query(
+            SharedResolverWrapper::new(Box::new(resolver)),
+            stream_key,
+            "nowhere.example".to_string(),
+            RecordType::MX,
+        )
+        .wait();
+
+        assert_eq!(
+            result,
+            Ok(DnsAnswerPayload::new(
+                stream_key,
+                u16::from(ResponseCode::NXDomain),
+                vec![]
+            ))
+        );
+        assert_eq!(
+            *lookup_parameters.lock().unwrap(),
+            vec![
+                ("nowhere.example".to_string(), RecordType::MX),
+                ("nowhere.example".to_string(), RecordType::ANY)
+            ]
+        );
+    }
+
+    #[test]
+    fn dns_query_for_a_name_without_records_of_its_type_is_answered_with_no_data() {
+        let resolver = ResolverWrapperMock::new()
+            .lookup_failure(ResolveError::from(ResolveErrorKind::NoRecordsFound {
+                query: Query::default(),
+                valid_until: None,
+            }))
+            .lookup_success(vec![Record::from_rdata(
+                Name::from_str("example.com").unwrap(),
+                300,
+                RData::A(Ipv4Addr::new(1, 2, 3, 4)),
+            )]);
+        let stream_key = make_meaningless_stream_key();
+
+        let result = ProxyClient::answer_dns_query(
+            SharedResolverWrapper::new(Box::new(resolver)),
+            stream_key,
+            "example.com".to_string(),
+            RecordType::MX,
+        )
+        .wait();
+
+        assert_eq!(
+            result,
+            Ok(DnsAnswerPayload::new(
+                stream_key,
+                u16::from(ResponseCode::NoError),
+                vec![]
+            ))
+        );
+    }
+
     #[test]
     fn refuse_to_provide_exit_services_with_no_paying_wallet() {
         init_test_logging();
@@ -726,6 +1668,9 @@ mod tests {
             target_port: 0,
             protocol: ProxyProtocol::HTTP,
             originator_public_key: PublicKey::new(&b"originator"[..]),
+            fresh_return_route: false,
+            alpn_protocols: vec![],
+            trace_id_opt: None,
         };
         let package = ExpiredCoresPackage::new(
             SocketAddr::from_str("1.2.3.4:1234").unwrap(),
@@ -750,8 +1695,11 @@ mod tests {
         let mut subject = ProxyClient::new(ProxyClientConfig {
             cryptde,
             dns_servers: dnss(),
+            dns_protocol: DnsProtocol::Udp,
             exit_service_rate: rate_pack_exit(100),
             exit_byte_rate: rate_pack_exit_byte(100),
+            exit_policy: ExitPolicy::default(),
+            exit_connection_pool: None,
         });
         subject.resolver_wrapper_factory = Box::new(resolver_factory);
         subject.stream_handler_pool_factory = Box::new(pool_factory);
@@ -781,6 +1729,9 @@ mod tests {
             target_port: 0,
             protocol: ProxyProtocol::HTTP,
             originator_public_key: cryptde.public_key().clone(),
+            fresh_return_route: false,
+            alpn_protocols: vec![],
+            trace_id_opt: None,
         };
         let package = ExpiredCoresPackage::new(
             SocketAddr::from_str("1.2.3.4:1234").unwrap(),
@@ -805,8 +1756,11 @@ mod tests {
         let mut subject = ProxyClient::new(ProxyClientConfig {
             cryptde,
             dns_servers: dnss(),
+            dns_protocol: DnsProtocol::Udp,
             exit_service_rate: rate_pack_exit(100),
             exit_byte_rate: rate_pack_exit_byte(100),
+            exit_policy: ExitPolicy::default(),
+            exit_connection_pool: None,
         });
         subject.resolver_wrapper_factory = Box::new(resolver_factory);
         subject.stream_handler_pool_factory = Box::new(pool_factory);
@@ -832,13 +1786,17 @@ mod tests {
         let mut subject = ProxyClient::new(ProxyClientConfig {
             cryptde: cryptde(),
             dns_servers: vec![SocketAddr::from_str("8.7.6.5:4321").unwrap()],
+            dns_protocol: DnsProtocol::Udp,
             exit_service_rate: 100,
             exit_byte_rate: 200,
+            exit_policy: ExitPolicy::default(),
+            exit_connection_pool: None,
         });
         subject.stream_contexts.insert(
             stream_key.clone(),
             StreamContext {
                 return_route: make_meaningless_route(),
+                alternate_return_routes: vec![],
                 payload_destination_key: PublicKey::new(&b"abcd"[..]),
                 paying_wallet: Some(make_wallet("paying")),
             },
@@ -964,13 +1922,17 @@ mod tests {
         let mut subject = ProxyClient::new(ProxyClientConfig {
             cryptde: cryptde(),
             dns_servers: vec![SocketAddr::from_str("8.7.6.5:4321").unwrap()],
+            dns_protocol: DnsProtocol::Udp,
             exit_service_rate: 100,
             exit_byte_rate: 200,
+            exit_policy: ExitPolicy::default(),
+            exit_connection_pool: None,
         });
         subject.stream_contexts.insert(
             stream_key.clone(),
             StreamContext {
                 return_route: make_meaningless_route(),
+                alternate_return_routes: vec![],
                 payload_destination_key: PublicKey::new(&b"abcd"[..]),
                 paying_wallet: None,
             },
@@ -1014,13 +1976,17 @@ mod tests {
         let mut subject = ProxyClient::new(ProxyClientConfig {
             cryptde: cryptde(),
             dns_servers: vec![SocketAddr::from_str("8.7.6.5:4321").unwrap()],
+            dns_protocol: DnsProtocol::Udp,
             exit_service_rate: 100,
             exit_byte_rate: 200,
+            exit_policy: ExitPolicy::default(),
+            exit_connection_pool: None,
         });
         subject.stream_contexts.insert(
             stream_key.clone(),
             StreamContext {
                 return_route: make_meaningless_route(),
+                alternate_return_routes: vec![],
                 payload_destination_key: PublicKey::new(&[]),
                 paying_wallet: Some(make_wallet("consuming")),
             },
@@ -1062,8 +2028,11 @@ mod tests {
         let mut subject = ProxyClient::new(ProxyClientConfig {
             cryptde,
             dns_servers: vec![SocketAddr::from_str("8.7.6.5:4321").unwrap()],
+            dns_protocol: DnsProtocol::Udp,
             exit_service_rate: 100,
             exit_byte_rate: 200,
+            exit_policy: ExitPolicy::default(),
+            exit_connection_pool: None,
         });
         let mut process_package_params_arc = Arc::new(Mutex::new(vec![]));
         let pool = StreamHandlerPoolMock::new()
@@ -1078,6 +2047,7 @@ mod tests {
             stream_key.clone(),
             StreamContext {
                 return_route: old_return_route,
+                alternate_return_routes: vec![],
                 payload_destination_key: originator_public_key.clone(),
                 paying_wallet: Some(make_wallet("consuming")),
             },
@@ -1101,6 +2071,9 @@ mod tests {
             target_port: 0,
             protocol: ProxyProtocol::HTTP,
             originator_public_key: originator_public_key.clone(),
+            fresh_return_route: false,
+            alpn_protocols: vec![],
+            trace_id_opt: None,
         };
 
         subject_addr
@@ -1160,4 +2133,81 @@ mod tests {
             }
         )
     }
+
+    fn make_stream_context(return_route: Route) -> StreamContext {
+        StreamContext {
+            return_route,
+            alternate_return_routes: vec![],
+            payload_destination_key: PublicKey::new(&[4, 3, 2, 1]),
+            paying_wallet: None,
+        }
+    }
+
+    fn make_route_with_head(head: u8, garbage: u8) -> Route {
+        Route {
+            hops: vec![CryptData::new(&[head]), CryptData::new(&[garbage])],
+        }
+    }
+
+    #[test]
+    fn return_routes_over_different_paths_are_kept_and_taken_in_turn() {
+        let mut subject = make_stream_context(make_route_with_head(1, 0));
+        subject.add_return_route(make_route_with_head(2, 0));
+
+        let mut heads = vec![];
+        for _ in 0..3 {
+            heads.push(subject.return_route.hops[0].clone());
+            subject.rotate_return_routes();
+        }
+
+        assert_eq!(
+            heads,
+            vec![
+                CryptData::new(&[2]),
+                CryptData::new(&[1]),
+                CryptData::new(&[2])
+            ]
+        );
+    }
+
+    #[test]
+    fn return_route_over_a_known_path_replaces_the_old_one() {
+        let mut subject = make_stream_context(make_route_with_head(1, 0));
+        subject.add_return_route(make_route_with_head(2, 0));
+
+        subject.add_return_route(make_route_with_head(1, 9));
+
+        assert_eq!(subject.return_route, make_route_with_head(1, 9));
+        assert_eq!(
+            subject.alternate_return_routes,
+            vec![make_route_with_head(2, 0)]
+        );
+    }
+
+    #[test]
+    fn fresh_return_route_replaces_all_the_old_ones() {
+        let mut subject = make_stream_context(make_route_with_head(1, 0));
+        subject.add_return_route(make_route_with_head(2, 0));
+
+        subject.replace_return_routes(make_route_with_head(3, 0));
+        subject.rotate_return_routes();
+
+        assert_eq!(subject.return_route, make_route_with_head(3, 0));
+        assert!(subject.alternate_return_routes.is_empty());
+    }
+
+    #[test]
+    fn return_routes_are_limited_per_stream() {
+        let mut subject = make_stream_context(make_route_with_head(0, 0));
+
+        for head in 1..20 {
+            subject.add_return_route(make_route_with_head(head, 0));
+        }
+
+        assert_eq!(
+            subject.alternate_return_routes.len(),
+            MAX_RETURN_ROUTES_PER_STREAM - 1
+        );
+        assert_eq!(subject.return_route, make_route_with_head(19, 0));
+    }
 }
@@ -1,15 +1,37 @@
 // Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use crate::sub_lib::logger::Logger;
+use crate::sub_lib::metrics;
+use crate::sub_lib::metrics::PROXY_CLIENT_DNS_CACHE_ENTRIES;
+use crate::sub_lib::metrics::PROXY_CLIENT_DNS_LOOKUPS;
+use crate::sub_lib::privacy;
+use crate::sub_lib::proxy_client::DnsCacheStats;
+use std::collections::BTreeSet;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+use std::time::Instant;
+use tokio::prelude::future;
 use tokio::prelude::Future;
 use trust_dns_resolver::config::ResolverConfig;
 use trust_dns_resolver::config::ResolverOpts;
 use trust_dns_resolver::error::ResolveError;
+use trust_dns_resolver::error::ResolveErrorKind;
+use trust_dns_resolver::lookup::Lookup;
 use trust_dns_resolver::lookup_ip::LookupIp;
+use trust_dns_resolver::proto::rr::RecordType;
 use trust_dns_resolver::AsyncResolver;
 
+pub const DNS_CACHE_CAPACITY: usize = 10_000;
+// How long to remember that a name doesn't exist when the upstream server didn't say
+pub const DEFAULT_NEGATIVE_TTL: Duration = Duration::from_secs(60);
+
 pub type WrappedLookupIpFuture = dyn Future<Item = LookupIp, Error = ResolveError> + Send;
+pub type WrappedLookupFuture = dyn Future<Item = Lookup, Error = ResolveError> + Send;
 
 pub trait ResolverWrapper: Send {
     fn lookup_ip(&self, host: &str) -> Box<WrappedLookupIpFuture>;
+    fn lookup(&self, name: &str, record_type: RecordType) -> Box<WrappedLookupFuture>;
 }
 
 pub trait ResolverWrapperFactory {
@@ -24,6 +46,10 @@ impl ResolverWrapper for ResolverWrapperReal {
     fn lookup_ip(&self, host: &str) -> Box<WrappedLookupIpFuture> {
         Box::new(self.delegate.lookup_ip(host))
     }
+
+    fn lookup(&self, name: &str, record_type: RecordType) -> Box<WrappedLookupFuture> {
+        Box::new(self.delegate.lookup(name, record_type))
+    }
 }
 
 pub struct ResolverWrapperFactoryReal;
@@ -36,3 +62,639 @@ impl ResolverWrapperFactory for ResolverWrapperFactoryReal {
         Box::new(ResolverWrapperReal { delegate })
     }
 }
+
+#[derive(Clone, Debug)]
+enum CachedAnswer {
+    Found(LookupIp),
+    NotFound(ResolveError),
+}
+
+struct DnsCacheInner {
+    entries: HashMap<String, (CachedAnswer, Instant)>,
+    // The same names, soonest to expire first, so that making room never means searching
+    expiries: BTreeSet<(Instant, String)>,
+    capacity: usize,
+    stats: DnsCacheStats,
+}
+
+// Shared by every stream the ProxyClient opens, so that only the first lookup of a name goes
+// upstream until the answer's TTL runs out.
+#[derive(Clone)]
+pub struct DnsCache {
+    inner: Arc<Mutex<DnsCacheInner>>,
+}
+
+impl Default for DnsCache {
+    fn default() -> Self {
+        DnsCache::new(DNS_CACHE_CAPACITY)
+    }
+}
+
+impl DnsCache {
+    pub fn new(capacity: usize) -> DnsCache {
+        DnsCache {
+            inner: Arc::new(Mutex::new(DnsCacheInner {
+                entries: HashMap::new(),
+                expiries: BTreeSet::new(),
+                capacity,
+                stats: DnsCacheStats::default(),
+            })),
+        }
+    }
+
+    pub fn stats(&self) -> DnsCacheStats {
+        let inner = self.inner.lock().expect("DNS cache is poisoned");
+        DnsCacheStats {
+            entries: inner.entries.len(),
+            ..inner.stats
+        }
+    }
+
+    fn answer(&self, host: &str, now: Instant) -> Option<Result<LookupIp, ResolveError>> {
+        let mut inner = self.inner.lock().expect("DNS cache is poisoned");
+        let key = Self::key(host);
+        let answer_opt = match inner.entries.get(&key).cloned() {
+            Some((answer, valid_until)) if valid_until > now => Some(answer),
+            Some(_) => {
+                inner.remove(&key);
+                None
+            }
+            None => None,
+        };
+        match answer_opt {
+            Some(CachedAnswer::Found(lookup_ip)) => {
+                inner.stats.hits += 1;
+                Some(Ok(lookup_ip))
+            }
+            Some(CachedAnswer::NotFound(error)) => {
+                inner.stats.hits += 1;
+                inner.stats.negative_hits += 1;
+                Some(Err(error))
+            }
+            None => {
+                inner.stats.misses += 1;
+                None
+            }
+        }
+    }
+
+    fn store(&self, host: &str, result: &Result<LookupIp, ResolveError>, now: Instant) {
+        let (answer, valid_until) = match result {
+            Ok(lookup_ip) => (
+                CachedAnswer::Found(lookup_ip.clone()),
+                lookup_ip.valid_until(),
+            ),
+            Err(error) => match error.kind() {
+                ResolveErrorKind::NoRecordsFound { valid_until, .. } => (
+                    CachedAnswer::NotFound(error.clone()),
+                    valid_until.unwrap_or_else(|| now + DEFAULT_NEGATIVE_TTL),
+                ),
+                // Timeouts and I/O trouble say nothing about the name; try again next time
+                _ => return,
+            },
+        };
+        if valid_until <= now {
+            return;
+        }
+        let mut inner = self.inner.lock().expect("DNS cache is poisoned");
+        let key = Self::key(host);
+        if !inner.entries.contains_key(&key) {
+            inner.make_room();
+        }
+        if inner.capacity > 0 {
+            inner.insert(key, answer, valid_until);
+        }
+    }
+
+    fn key(host: &str) -> String {
+        host.trim_end_matches('.').to_lowercase()
+    }
+}
+
+impl DnsCacheInner {
+    fn insert(&mut self, key: String, answer: CachedAnswer, valid_until: Instant) {
+        if let Some((_, old_valid_until)) = self.entries.insert(key.clone(), (answer, valid_until))
+        {
+            self.expiries.remove(&(old_valid_until, key.clone()));
+        }
+        self.expiries.insert((valid_until, key));
+        self.count_entries();
+    }
+
+    fn remove(&mut self, key: &str) {
+        if let Some((_, valid_until)) = self.entries.remove(key) {
+            self.expiries.remove(&(valid_until, key.to_string()));
+        }
+        self.count_entries();
+    }
+
+    // Expired entries go first, since they expire soonest of all
+    fn make_room(&mut self) {
+        while self.entries.len() >= self.capacity {
+            let soonest = match self.expiries.iter().next() {
+                Some(soonest) => soonest.clone(),
+                None => break,
+            };
+            self.remove(&soonest.1);
+        }
+    }
+
+    fn count_entries(&self) {
+        metrics::set(
+            &PROXY_CLIENT_DNS_CACHE_ENTRIES,
+            &[],
+            self.entries.len() as i64,
+        );
+    }
+}
+
+pub struct CachingResolverWrapper {
+    delegate: Box<dyn ResolverWrapper>,
+    cache: DnsCache,
+    logger: Logger,
+}
+
+impl ResolverWrapper for CachingResolverWrapper {
+    fn lookup_ip(&self, host: &str) -> Box<WrappedLookupIpFuture> {
+        if let Some(result) = self.cache.answer(host, Instant::now()) {
+            debug!(
+                self.logger,
+                "Answered look-up of {} from DNS cache: {}",
+                privacy::host(host),
+                self.cache.stats()
+            );
+            count_lookup(if result.is_ok() {
+                "cached"
+            } else {
+                "cached_nonexistent"
+            });
+            return Box::new(future::result(result));
+        }
+        let cache = self.cache.clone();
+        let logger = self.logger.clone();
+        let host = host.to_string();
+        Box::new(self.delegate.lookup_ip(&host).then(move |result| {
+            cache.store(&host, &result, Instant::now());
+            count_lookup(if result.is_ok() { "resolved" } else { "failed" });
+            debug!(
+                logger,
+                "Sent look-up of {} upstream: {}",
+                privacy::host(&host),
+                cache.stats()
+            );
+            result
+        }))
+    }
+
+    // Only address look-ups are cached here; the entry Node caches what it asks for by other
+    // record types.
+    fn lookup(&self, name: &str, record_type: RecordType) -> Box<WrappedLookupFuture> {
+        Box::new(self.delegate.lookup(name, record_type).then(|result| {
+            count_lookup(if result.is_ok() { "resolved" } else { "failed" });
+            result
+        }))
+    }
+}
+
+impl CachingResolverWrapper {
+    pub fn new(delegate: Box<dyn ResolverWrapper>, cache: DnsCache) -> CachingResolverWrapper {
+        CachingResolverWrapper {
+            delegate,
+            cache,
+            logger: Logger::new("DnsCache"),
+        }
+    }
+}
+
+fn count_lookup(result: &str) {
+    metrics::increment(&PROXY_CLIENT_DNS_LOOKUPS, &[("result", result)], 1);
+}
+
+// Lets the ProxyClient and its StreamHandlerPool share one resolver, and therefore one
+// connection to the upstream DNS servers.
+#[derive(Clone)]
+pub struct SharedResolverWrapper {
+    delegate: Arc<Mutex<Box<dyn ResolverWrapper>>>,
+}
+
+impl ResolverWrapper for SharedResolverWrapper {
+    fn lookup_ip(&self, host: &str) -> Box<WrappedLookupIpFuture> {
+        self.delegate
+            .lock()
+            .expect("Resolver is poisoned")
+            .lookup_ip(host)
+    }
+
+    fn lookup(&self, name: &str, record_type: RecordType) -> Box<WrappedLookupFuture> {
+        self.delegate
+            .lock()
+            .expect("Resolver is poisoned")
+            .lookup(name, record_type)
+    }
+}
+
+impl SharedResolverWrapper {
+    pub fn new(delegate: Box<dyn ResolverWrapper>) -> SharedResolverWrapper {
+        SharedResolverWrapper {
+            delegate: Arc::new(Mutex::new(delegate)),
+        }
+    }
+
+    // Every clone starts using the new delegate for its next lookup
+    pub fn replace(&self, delegate: Box<dyn ResolverWrapper>) {
+        *self.delegate.lock().expect("Resolver is poisoned") = delegate;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proxy_client::local_test_utils::ResolverWrapperMock;
+    use crate::test_utils::logging::init_test_logging;
+    use crate::test_utils::logging::TestLogHandler;
+    use actix::System;
+    use std::net::IpAddr;
+    use std::net::SocketAddr;
+    use std::net::UdpSocket;
+    use std::str::FromStr;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+    use trust_dns_proto::op::{Message, MessageType, Query, ResponseCode};
+    use trust_dns_proto::rr::{Name, RData, Record, RecordType};
+    use trust_dns_resolver::config::{NameServerConfig, Protocol};
+    use trust_dns_resolver::lookup::Lookup;
+
+    fn lookup_ip(ip_addr: &str, valid_until: Instant) -> LookupIp {
+        let ip_addr = IpAddr::from_str(ip_addr).unwrap();
+        let record = match ip_addr {
+            IpAddr::V4(ip_addr) => Record::from_rdata(Name::root(), 0, RData::A(ip_addr)),
+            IpAddr::V6(ip_addr) => Record::from_rdata(Name::root(), 0, RData::AAAA(ip_addr)),
+        };
+        Lookup::new_with_deadline(Query::default(), Arc::new(vec![record]), valid_until).into()
+    }
+
+    fn no_records_found(valid_until: Option<Instant>) -> ResolveError {
+        ResolveError::from(ResolveErrorKind::NoRecordsFound {
+            query: Query::default(),
+            valid_until,
+        })
+    }
+
+    fn ips(lookup_ip: &LookupIp) -> Vec<IpAddr> {
+        lookup_ip.iter().collect()
+    }
+
+    #[test]
+    fn second_lookup_is_answered_from_the_cache() {
+        init_test_logging();
+        let lookup_ip_parameters = Arc::new(Mutex::new(vec![]));
+        let delegate = ResolverWrapperMock::new()
+            .lookup_ip_success(vec![IpAddr::from_str("1.2.3.4").unwrap()])
+            .lookup_ip_parameters(&lookup_ip_parameters);
+        let cache = DnsCache::default();
+        let subject = CachingResolverWrapper::new(Box::new(delegate), cache.clone());
+        let lookups = |result: &str| {
+            metrics::value(&PROXY_CLIENT_DNS_LOOKUPS, &[("result", result)]).unwrap_or(0)
+        };
+        let (cached_before, resolved_before) = (lookups("cached"), lookups("resolved"));
+
+        let first = subject.lookup_ip("www.example.com.").wait().unwrap();
+        let second = subject.lookup_ip("WWW.Example.com").wait().unwrap();
+
+        assert_eq!(ips(&first), vec![IpAddr::from_str("1.2.3.4").unwrap()]);
+        assert_eq!(ips(&second), ips(&first));
+        assert_eq!(
+            *lookup_ip_parameters.lock().unwrap(),
+            vec!["www.example.com.".to_string()]
+        );
+        assert_eq!(
+            cache.stats(),
+            DnsCacheStats {
+                hits: 1,
+                negative_hits: 0,
+                misses: 1,
+                entries: 1,
+            }
+        );
+        TestLogHandler::new().exists_log_containing(
+            "DEBUG: DnsCache: Answered look-up of WWW.Example.com from DNS cache: 1 hits (0 negative), 1 misses, 1 entries",
+        );
+        // The registry is shared with every other test
+        assert!(lookups("cached") > cached_before);
+        assert!(lookups("resolved") > resolved_before);
+    }
+
+    #[test]
+    fn answers_are_forgotten_when_their_ttl_runs_out() {
+        let subject = DnsCache::new(10);
+        let now = Instant::now();
+        let answer = lookup_ip("1.2.3.4", now + Duration::from_secs(300));
+
+        subject.store("www.example.com", &Ok(answer), now);
+
+        assert_eq!(
+            subject
+                .answer("www.example.com", now + Duration::from_secs(299))
+                .map(|result| ips(&result.unwrap())),
+            Some(vec![IpAddr::from_str("1.2.3.4").unwrap()])
+        );
+        assert_eq!(
+            subject
+                .answer("www.example.com", now + Duration::from_secs(300))
+                .is_none(),
+            true
+        );
+        assert_eq!(subject.stats().entries, 0);
+    }
+
+    #[test]
+    fn nonexistent_names_are_cached_until_the_servers_deadline() {
+        let subject = DnsCache::new(10);
+        let now = Instant::now();
+        let error = no_records_found(Some(now + Duration::from_secs(10)));
+
+        subject.store("nowhere.example.com", &Err(error), now);
+
+        match subject.answer("nowhere.example.com", now + Duration::from_secs(9)) {
+            Some(Err(ref e)) => match e.kind() {
+                ResolveErrorKind::NoRecordsFound { .. } => (),
+                _ => panic!("Expected NoRecordsFound, got {:?}", e),
+            },
+            other => panic!("Expected cached NoRecordsFound, got {:?}", other),
+        }
+        assert_eq!(
+            subject
+                .answer("nowhere.example.com", now + Duration::from_secs(10))
+                .is_none(),
+            true
+        );
+        assert_eq!(
+            subject.stats(),
+            DnsCacheStats {
+                hits: 1,
+                negative_hits: 1,
+                misses: 1,
+                entries: 0,
+            }
+        );
+    }
+
+    #[test]
+    fn nonexistent_names_without_a_deadline_get_the_default_negative_ttl() {
+        let subject = DnsCache::new(10);
+        let now = Instant::now();
+
+        subject.store("nowhere.example.com", &Err(no_records_found(None)), now);
+
+        assert_eq!(
+            subject
+                .answer(
+                    "nowhere.example.com",
+                    now + DEFAULT_NEGATIVE_TTL - Duration::from_secs(1)
+                )
+                .is_some(),
+            true
+        );
+        assert_eq!(
+            subject
+                .answer("nowhere.example.com", now + DEFAULT_NEGATIVE_TTL)
+                .is_none(),
+            true
+        );
+    }
+
+    #[test]
+    fn failures_that_say_nothing_about_the_name_are_not_cached() {
+        let lookup_ip_parameters = Arc::new(Mutex::new(vec![]));
+        let delegate = ResolverWrapperMock::new()
+            .lookup_ip_failure(ResolveError::from(ResolveErrorKind::Timeout))
+            .lookup_ip_success(vec![IpAddr::from_str("1.2.3.4").unwrap()])
+            .lookup_ip_parameters(&lookup_ip_parameters);
+        let cache = DnsCache::default();
+        let subject = CachingResolverWrapper::new(Box::new(delegate), cache.clone());
+
+        let first = subject.lookup_ip("www.example.com").wait();
+        let second = subject.lookup_ip("www.example.com").wait().unwrap();
+
+        assert_eq!(first.is_err(), true);
+        assert_eq!(ips(&second), vec![IpAddr::from_str("1.2.3.4").unwrap()]);
+        assert_eq!(lookup_ip_parameters.lock().unwrap().len(), 2);
+        assert_eq!(cache.stats().misses, 2);
+    }
+
+    #[test]
+    fn full_cache_drops_expired_entries_and_then_the_ones_expiring_soonest() {
+        let subject = DnsCache::new(2);
+        let now = Instant::now();
+        subject.store(
+            "expired.com",
+            &Ok(lookup_ip("1.1.1.1", now + Duration::from_secs(1))),
+            now,
+        );
+        subject.store(
+            "long.com",
+            &Ok(lookup_ip("2.2.2.2", now + Duration::from_secs(100))),
+            now,
+        );
+        let later = now + Duration::from_secs(2);
+
+        subject.store(
+            "short.com",
+            &Ok(lookup_ip("3.3.3.3", later + Duration::from_secs(10))),
+            later,
+        );
+        subject.store(
+            "new.com",
+            &Ok(lookup_ip("4.4.4.4", later + Duration::from_secs(50))),
+            later,
+        );
+
+        assert_eq!(subject.stats().entries, 2);
+        assert_eq!(subject.answer("short.com", later).is_none(), true);
+        assert_eq!(subject.answer("long.com", later).is_some(), true);
+        assert_eq!(subject.answer("new.com", later).is_some(), true);
+    }
+
+    #[test]
+    fn storing_a_name_again_moves_it_to_its_new_place_in_line() {
+        let subject = DnsCache::new(2);
+        let now = Instant::now();
+        let store = |host: &str, secs: u64| {
+            subject.store(
+                host,
+                &Ok(lookup_ip("1.1.1.1", now + Duration::from_secs(secs))),
+                now,
+            )
+        };
+
+        store("renewed.com", 10);
+        store("renewed.com", 100);
+        store("other.com", 50);
+        store("new.com", 60);
+
+        assert_eq!(subject.stats().entries, 2);
+        assert_eq!(subject.answer("renewed.com", now).is_some(), true);
+        assert_eq!(subject.answer("other.com", now).is_none(), true);
+        assert_eq!(subject.answer("new.com", now).is_some(), true);
+        let inner = subject.inner.lock().unwrap();
+        assert_eq!(inner.expiries.len(), 2);
+    }
+
+    #[test]
+    fn shared_resolver_sends_every_clones_lookups_to_one_delegate() {
+        let lookup_ip_parameters = Arc::new(Mutex::new(vec![]));
+        let lookup_parameters = Arc::new(Mutex::new(vec![]));
+        let delegate = ResolverWrapperMock::new()
+            .lookup_ip_success(vec![IpAddr::from_str("1.2.3.4").unwrap()])
+            .lookup_ip_parameters(&lookup_ip_parameters)
+            .lookup_success(vec![])
+            .lookup_parameters(&lookup_parameters);
+        let subject = SharedResolverWrapper::new(Box::new(delegate));
+        let clone = subject.clone();
+
+        let ip_result = subject.lookup_ip("first.com").wait().unwrap();
+        let result = clone.lookup("second.com", RecordType::MX).wait().unwrap();
+
+        assert_eq!(ips(&ip_result), vec![IpAddr::from_str("1.2.3.4").unwrap()]);
+        assert_eq!(result.record_iter().count(), 0);
+        assert_eq!(
+            *lookup_ip_parameters.lock().unwrap(),
+            vec!["first.com".to_string()]
+        );
+        assert_eq!(
+            *lookup_parameters.lock().unwrap(),
+            vec![("second.com".to_string(), RecordType::MX)]
+        );
+    }
+
+    #[test]
+    fn shared_resolver_sends_lookups_to_a_replacement_delegate() {
+        let old_parameters = Arc::new(Mutex::new(vec![]));
+        let new_parameters = Arc::new(Mutex::new(vec![]));
+        let old_delegate = ResolverWrapperMock::new().lookup_ip_parameters(&old_parameters);
+        let new_delegate = ResolverWrapperMock::new()
+            .lookup_ip_success(vec![IpAddr::from_str("5.6.7.8").unwrap()])
+            .lookup_ip_parameters(&new_parameters);
+        let subject = SharedResolverWrapper::new(Box::new(old_delegate));
+        let clone = subject.clone();
+
+        subject.replace(Box::new(new_delegate));
+
+        let ip_result = clone.lookup_ip("replaced.com").wait().unwrap();
+        assert_eq!(ips(&ip_result), vec![IpAddr::from_str("5.6.7.8").unwrap()]);
+        assert_eq!(old_parameters.lock().unwrap().is_empty(), true);
+        assert_eq!(
+            *new_parameters.lock().unwrap(),
+            vec!["replaced.com".to_string()]
+        );
+    }
+
+    // Answers A queries for www.example.com with 1.2.3.4 and everything else with NXDOMAIN
+    fn start_stand_in_dns_server() -> (SocketAddr, Arc<AtomicUsize>) {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let server_addr = socket.local_addr().unwrap();
+        let query_count = Arc::new(AtomicUsize::new(0));
+        let query_count_inner = query_count.clone();
+        thread::spawn(move || {
+            let mut buf = [0u8; 512];
+            loop {
+                let (len, client_addr) = match socket.recv_from(&mut buf) {
+                    Ok(pair) => pair,
+                    Err(_) => return,
+                };
+                query_count_inner.fetch_add(1, Ordering::SeqCst);
+                let request = Message::from_vec(&buf[..len]).unwrap();
+                let mut response = Message::new();
+                response
+                    .set_id(request.id())
+                    .set_message_type(MessageType::Response)
+                    .set_recursion_desired(request.recursion_desired())
+                    .set_recursion_available(true);
+                for query in request.queries() {
+                    response.add_query(query.clone());
+                    if query.name().to_ascii() == "www.example.com."
+                        && query.query_type() == RecordType::A
+                    {
+                        response.add_answer(Record::from_rdata(
+                            query.name().clone(),
+                            300,
+                            RData::A(std::net::Ipv4Addr::new(1, 2, 3, 4)),
+                        ));
+                    } else if query.name().to_ascii() != "www.example.com." {
+                        response.set_response_code(ResponseCode::NXDomain);
+                    }
+                }
+                socket
+                    .send_to(&response.to_vec().unwrap(), client_addr)
+                    .unwrap();
+            }
+        });
+        (server_addr, query_count)
+    }
+
+    #[test]
+    fn real_resolver_behind_cache_asks_the_server_only_once_per_name() {
+        let (server_addr, query_count) = start_stand_in_dns_server();
+        let mut config = ResolverConfig::new();
+        config.add_name_server(NameServerConfig {
+            socket_addr: server_addr,
+            protocol: Protocol::Udp,
+            tls_dns_name: None,
+        });
+        let cache = DnsCache::default();
+        let results = Arc::new(Mutex::new(vec![]));
+        let results_inner = results.clone();
+        let query_count_inner = query_count.clone();
+        let cache_inner = cache.clone();
+        let system = System::new("real_resolver_behind_cache_asks_the_server_only_once_per_name");
+
+        actix::spawn(future::lazy(move || {
+            let subject = CachingResolverWrapper::new(
+                ResolverWrapperFactoryReal {}.make(config, ResolverOpts::default()),
+                cache_inner,
+            );
+            let both_names = |subject: &CachingResolverWrapper| {
+                subject
+                    .lookup_ip("www.example.com")
+                    .then(|result| Ok::<_, ()>(result))
+                    .join(
+                        subject
+                            .lookup_ip("missing.example.com")
+                            .then(|result| Ok::<_, ()>(result)),
+                    )
+            };
+            both_names(&subject).and_then(move |first_results| {
+                let queries_after_first_round = query_count_inner.load(Ordering::SeqCst);
+                both_names(&subject).map(move |second_results| {
+                    results_inner.lock().unwrap().push((
+                        first_results,
+                        second_results,
+                        queries_after_first_round,
+                    ));
+                    System::current().stop();
+                })
+            })
+        }));
+        system.run();
+
+        let mut results = results.lock().unwrap();
+        let ((first_found, first_missing), (second_found, second_missing), queries) =
+            results.remove(0);
+        let expected_ips = vec![IpAddr::from_str("1.2.3.4").unwrap()];
+        assert_eq!(ips(&first_found.unwrap()), expected_ips);
+        assert_eq!(ips(&second_found.unwrap()), expected_ips);
+        assert_eq!(first_missing.is_err(), true);
+        assert_eq!(second_missing.is_err(), true);
+        assert_eq!(queries > 0, true);
+        assert_eq!(query_count.load(Ordering::SeqCst), queries);
+        assert_eq!(
+            cache.stats(),
+            DnsCacheStats {
+                hits: 2,
+                negative_hits: 1,
+                misses: 2,
+                entries: 2,
+            }
+        );
+    }
+}
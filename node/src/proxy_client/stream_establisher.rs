@@ -1,15 +1,22 @@
 // Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
 
+use crate::proxy_client::connection_pool::ConnectionPool;
 use crate::proxy_client::stream_reader::StreamReader;
 use crate::proxy_client::stream_writer::StreamWriter;
+use crate::proxy_server::http_protocol_pack::HttpProtocolPack;
 use crate::sub_lib::channel_wrappers::FuturesChannelFactory;
 use crate::sub_lib::channel_wrappers::FuturesChannelFactoryReal;
 use crate::sub_lib::channel_wrappers::SenderWrapper;
 use crate::sub_lib::cryptde::CryptDE;
 use crate::sub_lib::logger::Logger;
+use crate::sub_lib::metrics;
+use crate::sub_lib::metrics::PROXY_CLIENT_STREAMS_ESTABLISHED;
+use crate::sub_lib::privacy;
 use crate::sub_lib::proxy_client::{InboundServerData, ProxyClientSubs};
 use crate::sub_lib::proxy_server::ClientRequestPayload;
+use crate::sub_lib::proxy_server::ProxyProtocol;
 use crate::sub_lib::sequence_buffer::SequencedPacket;
+use crate::sub_lib::sequencer::Sequencer;
 use crate::sub_lib::stream_connector::StreamConnector;
 use crate::sub_lib::stream_connector::StreamConnectorReal;
 use crate::sub_lib::stream_key::StreamKey;
@@ -19,16 +26,35 @@ use std::io;
 use std::net::IpAddr;
 use std::net::SocketAddr;
 use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex};
 use tokio;
 
+// Where the numbering of a new connection to a stream's server picks up: at zero for a new stream,
+// or where the old connection left off for a stream that has been reconnected.
+#[derive(Clone)]
+pub struct StreamSequencing {
+    pub first_request_sequence_number: u64,
+    pub response_sequencer: Arc<Mutex<Sequencer>>,
+}
+
+impl Default for StreamSequencing {
+    fn default() -> Self {
+        StreamSequencing {
+            first_request_sequence_number: 0,
+            response_sequencer: Arc::new(Mutex::new(Sequencer::new())),
+        }
+    }
+}
+
 pub struct StreamEstablisher {
     pub cryptde: &'static dyn CryptDE,
     pub stream_adder_tx: Sender<(StreamKey, Box<dyn SenderWrapper<SequencedPacket>>)>,
-    pub stream_killer_tx: Sender<(StreamKey, u64)>,
+    pub stream_killer_tx: Sender<(StreamKey, Arc<Mutex<Sequencer>>)>,
     pub stream_connector: Box<dyn StreamConnector>,
     pub proxy_client_sub: Recipient<InboundServerData>,
     pub logger: Logger,
     pub channel_factory: Box<dyn FuturesChannelFactory<SequencedPacket>>,
+    pub connection_pool_opt: Option<ConnectionPool>,
 }
 
 impl Clone for StreamEstablisher {
@@ -41,6 +67,7 @@ impl Clone for StreamEstablisher {
             proxy_client_sub: self.proxy_client_sub.clone(),
             logger: self.logger.clone(),
             channel_factory: Box::new(FuturesChannelFactoryReal {}),
+            connection_pool_opt: self.connection_pool_opt.clone(),
         }
     }
 }
@@ -51,40 +78,82 @@ impl StreamEstablisher {
         payload: &ClientRequestPayload,
         ip_addrs: Vec<IpAddr>,
         target_hostname: String,
+        sequencing: StreamSequencing,
     ) -> io::Result<Box<dyn SenderWrapper<SequencedPacket>>> {
-        let connection_info = self.stream_connector.connect_one(
-            ip_addrs,
-            &target_hostname,
-            payload.target_port,
-            &self.logger,
-        )?;
+        let connection_info = match self.connection_pool_for(payload) {
+            Some(pool) => {
+                let consumer = &payload.originator_public_key;
+                // An idle connection may turn out to have been closed by the server just as the
+                // request goes out, so only a request that can safely be sent again is risked
+                // on one.
+                let idle_opt = if HttpProtocolPack::is_idempotent(&payload.sequenced_packet.data) {
+                    pool.take(consumer, &ip_addrs, payload.target_port)
+                } else {
+                    None
+                };
+                let connection_info = match idle_opt {
+                    Some(connection_info) => connection_info,
+                    None => self.stream_connector.connect_one(
+                        ip_addrs,
+                        &target_hostname,
+                        payload.target_port,
+                        &self.logger,
+                    )?,
+                };
+                pool.lease(consumer, connection_info)
+            }
+            None => self.stream_connector.connect_one(
+                ip_addrs,
+                &target_hostname,
+                payload.target_port,
+                &self.logger,
+            )?,
+        };
 
         self.spawn_stream_reader(
             &payload.clone(),
             connection_info.reader,
             connection_info.peer_addr,
+            sequencing.response_sequencer,
         )?;
 
         let (tx_to_write, rx_to_write) = self.channel_factory.make(connection_info.peer_addr);
-        let stream_writer = StreamWriter::new(
+        let stream_writer = StreamWriter::starting_at(
             connection_info.writer,
             connection_info.peer_addr,
             rx_to_write,
             payload.stream_key,
+            sequencing.first_request_sequence_number,
         );
         tokio::spawn(stream_writer);
 
         self.stream_adder_tx
             .send((payload.stream_key, tx_to_write.clone()))
             .expect("StreamHandlerPool died");
+        count_established_stream(payload);
         Ok(tx_to_write)
     }
 
+    // Only plaintext HTTP/1.1 streams whose first request doesn't ask for the connection to be
+    // closed may share connections with other streams.
+    fn connection_pool_for(&self, payload: &ClientRequestPayload) -> Option<ConnectionPool> {
+        match &self.connection_pool_opt {
+            Some(pool)
+                if payload.protocol == ProxyProtocol::HTTP
+                    && HttpProtocolPack::keeps_connection_alive(&payload.sequenced_packet.data) =>
+            {
+                Some(pool.clone())
+            }
+            _ => None,
+        }
+    }
+
     fn spawn_stream_reader(
         &self,
         payload: &ClientRequestPayload,
         read_stream: Box<dyn ReadHalfWrapper>,
         peer_addr: SocketAddr,
+        sequencer: Arc<Mutex<Sequencer>>,
     ) -> io::Result<()> {
         let stream_reader = StreamReader::new(
             payload.stream_key,
@@ -92,13 +161,38 @@ impl StreamEstablisher {
             read_stream,
             self.stream_killer_tx.clone(),
             peer_addr,
+            sequencer,
+        );
+        debug!(
+            self.logger,
+            "Spawning StreamReader for {}",
+            privacy::socket_addr(peer_addr)
         );
-        debug!(self.logger, "Spawning StreamReader for {}", peer_addr);
         tokio::spawn(stream_reader);
         Ok(())
     }
 }
 
+// Browsers may offer any ALPN names at all; only the two they actually use get labels of their own
+fn count_established_stream(payload: &ClientRequestPayload) {
+    let protocol = match payload.protocol {
+        ProxyProtocol::HTTP => "http",
+        ProxyProtocol::TLS => "tls",
+        ProxyProtocol::Raw => "raw",
+    };
+    let alpn = match payload.alpn_protocols.first() {
+        None => "none",
+        Some(offered) if offered == "h2" => "h2",
+        Some(offered) if offered == "http/1.1" => "http/1.1",
+        Some(_) => "other",
+    };
+    metrics::increment(
+        &PROXY_CLIENT_STREAMS_ESTABLISHED,
+        &[("protocol", protocol), ("alpn", alpn)],
+        1,
+    );
+}
+
 pub trait StreamEstablisherFactory: Send {
     fn make(&self) -> StreamEstablisher;
 }
@@ -106,9 +200,10 @@ pub trait StreamEstablisherFactory: Send {
 pub struct StreamEstablisherFactoryReal {
     pub cryptde: &'static dyn CryptDE,
     pub stream_adder_tx: Sender<(StreamKey, Box<dyn SenderWrapper<SequencedPacket>>)>,
-    pub stream_killer_tx: Sender<(StreamKey, u64)>,
+    pub stream_killer_tx: Sender<(StreamKey, Arc<Mutex<Sequencer>>)>,
     pub proxy_client_subs: ProxyClientSubs,
     pub logger: Logger,
+    pub connection_pool_opt: Option<ConnectionPool>,
 }
 
 impl StreamEstablisherFactory for StreamEstablisherFactoryReal {
@@ -121,6 +216,7 @@ impl StreamEstablisherFactory for StreamEstablisherFactoryReal {
             proxy_client_sub: self.proxy_client_subs.inbound_server_data.clone(),
             logger: self.logger.clone(),
             channel_factory: Box::new(FuturesChannelFactoryReal {}),
+            connection_pool_opt: self.connection_pool_opt.clone(),
         }
     }
 }
@@ -128,6 +224,7 @@ impl StreamEstablisherFactory for StreamEstablisherFactoryReal {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::sub_lib::proxy_client::ConnectionPoolConfig;
     use crate::sub_lib::proxy_server::ProxyProtocol;
     use crate::test_utils::cryptde;
     use crate::test_utils::make_meaningless_stream_key;
@@ -135,6 +232,7 @@ mod tests {
     use crate::test_utils::recorder::peer_actors_builder;
     use crate::test_utils::stream_connector_mock::StreamConnectorMock;
     use crate::test_utils::tokio_wrapper_mocks::ReadHalfWrapperMock;
+    use crate::test_utils::tokio_wrapper_mocks::WriteHalfWrapperMock;
     use actix::System;
     use futures::future::lazy;
     use std::io::ErrorKind;
@@ -142,6 +240,7 @@ mod tests {
     use std::str::FromStr;
     use std::sync::mpsc;
     use std::thread;
+    use std::time::Duration;
     use tokio::prelude::Async;
 
     #[test]
@@ -178,6 +277,7 @@ mod tests {
                 proxy_client_sub,
                 logger: Logger::new("ProxyClient"),
                 channel_factory: Box::new(FuturesChannelFactoryReal {}),
+                connection_pool_opt: None,
             };
             subject
                 .spawn_stream_reader(
@@ -193,9 +293,13 @@ mod tests {
                         target_port: 0,
                         protocol: ProxyProtocol::HTTP,
                         originator_public_key: subject.cryptde.public_key().clone(),
+                        fresh_return_route: false,
+                        alpn_protocols: vec![],
+                        trace_id_opt: None,
                     },
                     read_stream,
                     SocketAddr::from_str("1.2.3.4:5678").unwrap(),
+                    Arc::new(Mutex::new(Sequencer::starting_at(5))),
                 )
                 .expect("spawn_stream_reader () failed");
 
@@ -219,10 +323,162 @@ mod tests {
             InboundServerData {
                 stream_key: make_meaningless_stream_key(),
                 last_data: false,
-                sequence_number: 0,
+                sequence_number: 5,
                 source: SocketAddr::from_str("1.2.3.4:5678").unwrap(),
                 data: b"I'm a stream establisher test not a framer test".to_vec()
             }
         );
     }
+
+    fn establish_stream_with_pool(
+        test_name: &'static str,
+        pool: ConnectionPool,
+        stream_connector: StreamConnectorMock,
+        data: &[u8],
+    ) {
+        let (proxy_client, _, _) = make_recorder();
+        let (sub_tx, sub_rx) = mpsc::channel();
+        thread::spawn(move || {
+            let system = System::new(test_name);
+            let peer_actors = peer_actors_builder().proxy_client(proxy_client).build();
+            sub_tx
+                .send(peer_actors.proxy_client.inbound_server_data)
+                .expect("Unable to send inbound_server_data sub from proxy_client to test");
+            system.run();
+        });
+        let (stream_adder_tx, stream_adder_rx) = mpsc::channel();
+        let (result_tx, result_rx) = mpsc::channel();
+        let data = data.to_vec();
+        let test_future = lazy(move || {
+            let proxy_client_sub = sub_rx.recv().unwrap();
+            let (stream_killer_tx, _) = mpsc::channel();
+            let mut subject = StreamEstablisher {
+                cryptde: cryptde(),
+                stream_adder_tx,
+                stream_killer_tx,
+                stream_connector: Box::new(stream_connector),
+                proxy_client_sub,
+                logger: Logger::new("ProxyClient"),
+                channel_factory: Box::new(FuturesChannelFactoryReal {}),
+                connection_pool_opt: Some(pool),
+            };
+            let result = subject.establish_stream(
+                &ClientRequestPayload {
+                    version: ClientRequestPayload::version(),
+                    stream_key: make_meaningless_stream_key(),
+                    sequenced_packet: SequencedPacket {
+                        data,
+                        sequence_number: 0,
+                        last_data: false,
+                    },
+                    target_hostname: Some("server.com".to_string()),
+                    target_port: 80,
+                    protocol: ProxyProtocol::HTTP,
+                    originator_public_key: subject.cryptde.public_key().clone(),
+                    fresh_return_route: false,
+                    alpn_protocols: vec![],
+                    trace_id_opt: None,
+                },
+                vec![IpAddr::from_str("2.3.4.5").unwrap()],
+                "server.com".to_string(),
+                StreamSequencing::default(),
+            );
+            result_tx.send(result.is_ok()).unwrap();
+            Ok(())
+        });
+
+        thread::spawn(move || {
+            tokio::run(test_future);
+        });
+
+        assert_eq!(result_rx.recv().unwrap(), true);
+        let (stream_key, _) = stream_adder_rx.recv().unwrap();
+        assert_eq!(stream_key, make_meaningless_stream_key());
+    }
+
+    fn make_pool_with_idle_connection() -> ConnectionPool {
+        let pool = ConnectionPool::new(ConnectionPoolConfig {
+            max_idle_per_server: 2,
+            idle_timeout: Duration::from_secs(4),
+        });
+        pool.park(
+            cryptde().public_key().clone(),
+            SocketAddr::from_str("2.3.4.5:80").unwrap(),
+            SocketAddr::from_str("192.168.0.1:5555").unwrap(),
+            Box::new(
+                ReadHalfWrapperMock::new()
+                    .poll_read_result(vec![], Ok(Async::NotReady))
+                    .poll_read_result(vec![], Ok(Async::NotReady)),
+            ),
+            Box::new(WriteHalfWrapperMock::new()),
+        );
+        pool
+    }
+
+    #[test]
+    fn establish_stream_reuses_an_idle_connection_from_the_pool() {
+        let pool = make_pool_with_idle_connection();
+
+        establish_stream_with_pool(
+            "establish_stream_reuses_an_idle_connection_from_the_pool",
+            pool.clone(),
+            StreamConnectorMock::new(), // panics if asked to connect
+            b"GET /index.html HTTP/1.1\r\nHost: server.com\r\n\r\n",
+        );
+
+        assert_eq!(pool.stats().reused, 1);
+        assert_eq!(pool.stats().idle, 0);
+    }
+
+    #[test]
+    fn establish_stream_does_not_risk_a_non_idempotent_request_on_an_idle_connection() {
+        let pool = make_pool_with_idle_connection();
+        let stream_connector = StreamConnectorMock::new().with_connection(
+            SocketAddr::from_str("192.168.0.1:6666").unwrap(),
+            SocketAddr::from_str("2.3.4.5:80").unwrap(),
+            ReadHalfWrapperMock::new().poll_read_result(vec![], Ok(Async::NotReady)),
+            WriteHalfWrapperMock::new(),
+        );
+
+        establish_stream_with_pool(
+            "establish_stream_does_not_risk_a_non_idempotent_request_on_an_idle_connection",
+            pool.clone(),
+            stream_connector,
+            b"POST /form HTTP/1.1\r\nHost: server.com\r\nContent-Length: 0\r\n\r\n",
+        );
+
+        assert_eq!(pool.stats().reused, 0);
+        assert_eq!(pool.stats().idle, 1);
+    }
+
+    #[test]
+    fn established_streams_are_counted_by_the_first_alpn_protocol_offered() {
+        let established = |alpn: &str| {
+            metrics::value(
+                &PROXY_CLIENT_STREAMS_ESTABLISHED,
+                &[("protocol", "tls"), ("alpn", alpn)],
+            )
+            .unwrap_or(0)
+        };
+        let payload = |alpn_protocols: Vec<&str>| ClientRequestPayload {
+            version: ClientRequestPayload::version(),
+            stream_key: make_meaningless_stream_key(),
+            sequenced_packet: SequencedPacket::new(vec![], 0, false),
+            target_hostname: Some("server.com".to_string()),
+            target_port: 443,
+            protocol: ProxyProtocol::TLS,
+            originator_public_key: cryptde().public_key().clone(),
+            fresh_return_route: false,
+            alpn_protocols: alpn_protocols.into_iter().map(String::from).collect(),
+            trace_id_opt: None,
+        };
+        let (h2_before, other_before) = (established("h2"), established("other"));
+
+        count_established_stream(&payload(vec!["h2", "http/1.1"]));
+        count_established_stream(&payload(vec!["spdy/3.1", "h2"]));
+        count_established_stream(&payload(vec!["acme-tls/1"]));
+
+        assert!(established("h2") >= h2_before + 1);
+        assert!(established("other") >= other_before + 2);
+    }
 }
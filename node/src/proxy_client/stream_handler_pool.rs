@@ -1,18 +1,27 @@
 // Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
 #![allow(proc_macro_derive_resolution_fallback)]
 
+use crate::proxy_client::connection_pool::ConnectionPool;
 use crate::proxy_client::resolver_wrapper::ResolverWrapper;
 use crate::proxy_client::stream_establisher::StreamEstablisherFactoryReal;
-use crate::proxy_client::stream_establisher::{StreamEstablisher, StreamEstablisherFactory};
+use crate::proxy_client::stream_establisher::{
+    StreamEstablisher, StreamEstablisherFactory, StreamSequencing,
+};
+use crate::proxy_server::http_protocol_pack::HttpProtocolPack;
+use crate::proxy_server::protocol_pack::{from_protocol, ServerConnectionFailure};
 use crate::sub_lib::accountant::ReportExitServiceProvidedMessage;
 use crate::sub_lib::channel_wrappers::SenderWrapper;
 use crate::sub_lib::cryptde::CryptDE;
+use crate::sub_lib::exit_policy::ExitPolicy;
 use crate::sub_lib::logger::Logger;
+use crate::sub_lib::privacy;
 use crate::sub_lib::proxy_client::{error_socket_addr, ProxyClientSubs};
-use crate::sub_lib::proxy_client::{DnsResolveFailure, InboundServerData};
-use crate::sub_lib::proxy_server::ClientRequestPayload;
+use crate::sub_lib::proxy_client::{DnsResolveFailure, ExitPolicyRefusal, InboundServerData};
+use crate::sub_lib::proxy_server::{ClientRequestPayload, ProxyProtocol};
 use crate::sub_lib::sequence_buffer::SequencedPacket;
+use crate::sub_lib::sequencer::Sequencer;
 use crate::sub_lib::stream_key::StreamKey;
+use crate::sub_lib::stream_trace::{self, SpanKind};
 use crate::sub_lib::wallet::Wallet;
 use actix::Recipient;
 use futures::future;
@@ -25,8 +34,11 @@ use std::sync::mpsc;
 use std::sync::mpsc::Receiver;
 use std::sync::Arc;
 use std::sync::Mutex;
+use std::time::Duration;
+use std::time::Instant;
 use tokio::prelude::future::FutureResult;
 use tokio::prelude::future::{err, ok};
+use tokio::timer::Delay;
 use trust_dns_resolver::error::ResolveError;
 use trust_dns_resolver::lookup_ip::LookupIp;
 
@@ -37,18 +49,33 @@ pub trait StreamHandlerPool {
 pub struct StreamHandlerPoolReal {
     inner: Arc<Mutex<StreamHandlerPoolRealInner>>,
     stream_adder_rx: Receiver<(StreamKey, Box<dyn SenderWrapper<SequencedPacket>>)>,
-    stream_killer_rx: Receiver<(StreamKey, u64)>,
+    stream_killer_rx: Receiver<(StreamKey, Arc<Mutex<Sequencer>>)>,
 }
 
 struct StreamHandlerPoolRealInner {
     accountant_sub: Recipient<ReportExitServiceProvidedMessage>,
     proxy_client_subs: ProxyClientSubs,
     stream_writer_channels: HashMap<StreamKey, Box<dyn SenderWrapper<SequencedPacket>>>,
+    response_sequencers: HashMap<StreamKey, Arc<Mutex<Sequencer>>>,
+    pending_streams: HashMap<StreamKey, PendingStream>,
+    next_establishment_id: u64,
+    pending_stream_timeout: Duration,
     resolver: Box<dyn ResolverWrapper>,
     logger: Logger,
     establisher_factory: Box<dyn StreamEstablisherFactory>,
     exit_service_rate: u64,
     exit_byte_rate: u64,
+    exit_policy: ExitPolicy,
+}
+
+// A stream whose first packet is still waiting for its DNS lookup and connection. Packets that
+// arrive for it in the meantime wait here instead of setting up connections of their own. A stream
+// that's given up stays here, marked, until its establishment comes to an end, so that the
+// establishment knows not to connect or, if it already has, to drop the connection.
+struct PendingStream {
+    establishment_id: u64,
+    given_up: bool,
+    queued_packets: Vec<(ClientRequestPayload, Option<Wallet>)>,
 }
 
 impl StreamHandlerPool for StreamHandlerPoolReal {
@@ -58,8 +85,47 @@ impl StreamHandlerPool for StreamHandlerPoolReal {
     }
 }
 
-type StreamEstablisherResult =
-    Box<dyn Future<Item = Box<dyn SenderWrapper<SequencedPacket> + 'static>, Error = String>>;
+// How many more times an idempotent request is sent to its server on a new connection after
+// the first attempt fails.
+pub const IDEMPOTENT_REQUEST_RETRIES: usize = 1;
+
+// How many packets may wait behind a stream that's still being established, and how long they may
+// wait, before the stream is given up for lost.
+pub const PENDING_STREAM_PACKET_LIMIT: usize = 64;
+pub const PENDING_STREAM_TIMEOUT_MS: u64 = 30_000;
+
+type StreamEstablisherResult = Box<
+    dyn Future<Item = Box<dyn SenderWrapper<SequencedPacket> + 'static>, Error = StreamFailure>,
+>;
+
+#[derive(Clone, Debug, PartialEq)]
+enum StreamFailure {
+    // Either the originator has already been told about these some other way (for example,
+    // with a DnsResolveFailure) or there's nothing useful to tell it.
+    Unreported(String),
+    Connection(ServerConnectionFailure, String),
+    Refused(String),
+    // The stream was given up while it was being established, and the originator told so then.
+    Abandoned(String),
+}
+
+impl StreamFailure {
+    fn from_io_error(error: io::Error) -> StreamFailure {
+        StreamFailure::Connection(
+            ServerConnectionFailure::from(error.kind()),
+            format!("Could not establish stream: {:?}", error),
+        )
+    }
+
+    fn message(&self) -> &str {
+        match self {
+            StreamFailure::Unreported(message) => message,
+            StreamFailure::Connection(_, message) => message,
+            StreamFailure::Refused(message) => message,
+            StreamFailure::Abandoned(message) => message,
+        }
+    }
+}
 
 impl StreamHandlerPoolReal {
     pub fn new(
@@ -69,6 +135,8 @@ impl StreamHandlerPoolReal {
         proxy_client_subs: ProxyClientSubs,
         exit_service_rate: u64,
         exit_byte_rate: u64,
+        exit_policy: ExitPolicy,
+        connection_pool_opt: Option<ConnectionPool>,
     ) -> StreamHandlerPoolReal {
         let (stream_killer_tx, stream_killer_rx) = mpsc::channel();
         let (stream_adder_tx, stream_adder_rx) = mpsc::channel();
@@ -80,14 +148,20 @@ impl StreamHandlerPoolReal {
                     stream_killer_tx,
                     proxy_client_subs: proxy_client_subs.clone(),
                     logger: Logger::new("ProxyClient"),
+                    connection_pool_opt,
                 }),
                 accountant_sub,
                 proxy_client_subs,
                 stream_writer_channels: HashMap::new(),
+                response_sequencers: HashMap::new(),
+                pending_streams: HashMap::new(),
+                next_establishment_id: 0,
+                pending_stream_timeout: Duration::from_millis(PENDING_STREAM_TIMEOUT_MS),
                 resolver,
                 logger: Logger::new("ProxyClient"),
                 exit_service_rate,
                 exit_byte_rate,
+                exit_policy,
             })),
             stream_adder_rx,
             stream_killer_rx,
@@ -104,65 +178,540 @@ impl StreamHandlerPoolReal {
         match Self::find_stream_with_key(&stream_key, &inner_arc) {
             Some(sender_wrapper) => {
                 let source = sender_wrapper.peer_addr();
-                let future =
-                    Self::write_and_tend(sender_wrapper, payload, paying_wallet, inner_arc)
-                        .map_err(move |error| {
-                            Self::clean_up_bad_stream(inner_arc_1, &stream_key, source, error)
-                        });
-                actix::spawn(future);
+                if Self::is_retriable(&payload) {
+                    let retry_payload = payload.clone();
+                    let retry_wallet = paying_wallet.clone();
+                    let future =
+                        Self::write_and_tend(sender_wrapper, payload, paying_wallet, inner_arc)
+                            .or_else(move |error| {
+                                Self::reconnect_and_write(
+                                    retry_payload,
+                                    retry_wallet,
+                                    inner_arc_1,
+                                    source,
+                                    error,
+                                )
+                            });
+                    actix::spawn(future);
+                } else {
+                    let response = Self::connection_lost_response(&payload);
+                    let future =
+                        Self::write_and_tend(sender_wrapper, payload, paying_wallet, inner_arc)
+                            .map_err(move |error| {
+                                Self::clean_up_bad_stream(
+                                    inner_arc_1,
+                                    &stream_key,
+                                    source,
+                                    error,
+                                    response,
+                                )
+                            });
+                    actix::spawn(future);
+                }
             }
             None => {
-                if payload.sequenced_packet.data.is_empty() {
+                if Self::is_pending(&stream_key, &inner_arc) {
+                    Self::queue_behind_pending_stream(payload, paying_wallet, &inner_arc)
+                } else if payload.sequenced_packet.data.is_empty() {
                     debug!(
                         Self::make_logger_copy(&inner_arc_1),
                         "Empty request payload received for nonexistent stream {:?} - ignoring",
                         payload.stream_key
                     )
                 } else {
-                    let future = Self::make_stream_with_key(&payload, inner_arc_1.clone())
-                        .and_then(move |sender_wrapper| {
-                            Self::write_and_tend(sender_wrapper, payload, paying_wallet, inner_arc)
-                        })
-                        .map_err(move |error| {
-                            // TODO: This ends up sending an empty response back to the browser and terminating
-                            // the stream. User deserves better than that. Send back a response from the
-                            // proper ServerImpersonator describing the error.
-                            Self::clean_up_bad_stream(
-                                inner_arc_1,
-                                &stream_key,
-                                error_socket_addr(),
-                                error,
-                            );
-                        });
+                    let retries = if Self::is_retriable(&payload) {
+                        IDEMPOTENT_REQUEST_RETRIES
+                    } else {
+                        0
+                    };
+                    let future = Self::start_establishment(
+                        payload,
+                        paying_wallet,
+                        inner_arc,
+                        retries,
+                        StreamSequencing::default(),
+                    );
                     actix::spawn(future);
                 }
             }
         };
     }
 
+    fn is_pending(
+        stream_key: &StreamKey,
+        inner_arc: &Arc<Mutex<StreamHandlerPoolRealInner>>,
+    ) -> bool {
+        inner_arc
+            .lock()
+            .expect("Stream handler pool is poisoned")
+            .pending_streams
+            .contains_key(stream_key)
+    }
+
+    fn queue_behind_pending_stream(
+        payload: ClientRequestPayload,
+        paying_wallet: Option<Wallet>,
+        inner_arc: &Arc<Mutex<StreamHandlerPoolRealInner>>,
+    ) {
+        let stream_key = payload.stream_key;
+        let establishment_id = {
+            let mut inner = inner_arc.lock().expect("Stream handler pool is poisoned");
+            let logger = inner.logger.clone();
+            let pending = inner
+                .pending_streams
+                .get_mut(&stream_key)
+                .expect("Pending stream disappeared");
+            if pending.given_up {
+                debug!(
+                    logger,
+                    "Discarding packet {} for stream {:?}, which was given up",
+                    payload.sequenced_packet.sequence_number,
+                    stream_key
+                );
+                return;
+            } else if pending.queued_packets.len() < PENDING_STREAM_PACKET_LIMIT {
+                debug!(
+                    logger,
+                    "Queueing packet {} for stream {:?} until it's established",
+                    payload.sequenced_packet.sequence_number,
+                    stream_key
+                );
+                pending.queued_packets.push((payload, paying_wallet));
+                return;
+            }
+            pending.establishment_id
+        };
+        Self::give_up_pending_stream(
+            &stream_key,
+            establishment_id,
+            format!(
+                "More than {} packets arrived for stream {:?} while it was being established",
+                PENDING_STREAM_PACKET_LIMIT, stream_key
+            ),
+            inner_arc,
+        );
+    }
+
+    // The originator is told the stream is gone right away; the establishment finds out when it
+    // next looks.
+    fn give_up_pending_stream(
+        stream_key: &StreamKey,
+        establishment_id: u64,
+        error: String,
+        inner_arc: &Arc<Mutex<StreamHandlerPoolRealInner>>,
+    ) {
+        {
+            let mut inner = inner_arc.lock().expect("Stream handler pool is poisoned");
+            match inner.pending_streams.get_mut(stream_key) {
+                Some(pending) => {
+                    if pending.establishment_id != establishment_id || pending.given_up {
+                        return;
+                    }
+                    pending.given_up = true;
+                    pending.queued_packets.clear();
+                }
+                None => return,
+            }
+        }
+        Self::clean_up_bad_stream(
+            inner_arc.clone(),
+            stream_key,
+            error_socket_addr(),
+            error,
+            vec![],
+        );
+    }
+
+    fn establishment_is_live(
+        stream_key: &StreamKey,
+        establishment_id: u64,
+        inner_arc: &Arc<Mutex<StreamHandlerPoolRealInner>>,
+    ) -> Result<(), StreamFailure> {
+        match inner_arc
+            .lock()
+            .expect("Stream handler pool is poisoned")
+            .pending_streams
+            .get(stream_key)
+        {
+            Some(pending) if pending.establishment_id == establishment_id && !pending.given_up => {
+                Ok(())
+            }
+            _ => Err(StreamFailure::Abandoned(format!(
+                "Stream {:?} was given up while it was being established",
+                stream_key
+            ))),
+        }
+    }
+
+    fn start_establishment(
+        payload: ClientRequestPayload,
+        paying_wallet: Option<Wallet>,
+        inner_arc: Arc<Mutex<StreamHandlerPoolRealInner>>,
+        retries: usize,
+        sequencing: StreamSequencing,
+    ) -> Box<dyn Future<Item = (), Error = ()>> {
+        let establishment_id = {
+            let mut inner = inner_arc.lock().expect("Stream handler pool is poisoned");
+            inner
+                .response_sequencers
+                .insert(payload.stream_key, sequencing.response_sequencer.clone());
+            inner.next_establishment_id += 1;
+            let establishment_id = inner.next_establishment_id;
+            inner.pending_streams.insert(
+                payload.stream_key,
+                PendingStream {
+                    establishment_id,
+                    given_up: false,
+                    queued_packets: vec![],
+                },
+            );
+            establishment_id
+        };
+        Self::start_pending_stream_timer(payload.stream_key, establishment_id, inner_arc.clone());
+        Self::establish_and_write(
+            payload,
+            paying_wallet,
+            inner_arc,
+            retries,
+            establishment_id,
+            sequencing,
+        )
+    }
+
+    fn start_pending_stream_timer(
+        stream_key: StreamKey,
+        establishment_id: u64,
+        inner_arc: Arc<Mutex<StreamHandlerPoolRealInner>>,
+    ) {
+        let timeout = inner_arc
+            .lock()
+            .expect("Stream handler pool is poisoned")
+            .pending_stream_timeout;
+        actix::spawn(Delay::new(Instant::now() + timeout).then(move |_| {
+            Self::give_up_pending_stream(
+                &stream_key,
+                establishment_id,
+                format!(
+                    "Stream {:?} was still not established after {:?}",
+                    stream_key, timeout
+                ),
+                &inner_arc,
+            );
+            Ok(())
+        }));
+    }
+
+    // Once the stream is established, the packets that waited for it follow its first packet
+    // in the order they arrived.
+    fn release_queued_packets(
+        stream_key: StreamKey,
+        establishment_id: u64,
+        sender_wrapper: Box<dyn SenderWrapper<SequencedPacket>>,
+        inner_arc: Arc<Mutex<StreamHandlerPoolRealInner>>,
+    ) {
+        let queued_packets = {
+            let mut inner = inner_arc.lock().expect("Stream handler pool is poisoned");
+            match Self::take_pending_stream(&mut inner, &stream_key, establishment_id) {
+                Some(ref pending) if pending.given_up => {
+                    Self::drop_given_up_connection(&mut inner, stream_key, pending);
+                    return;
+                }
+                Some(pending) => pending.queued_packets,
+                None => return,
+            }
+        };
+        if queued_packets.is_empty() {
+            return;
+        }
+        debug!(
+            Self::make_logger_copy(&inner_arc),
+            "Releasing {} packets queued for stream {:?}",
+            queued_packets.len(),
+            stream_key
+        );
+        let source = sender_wrapper.peer_addr();
+        let writes: Vec<_> = queued_packets
+            .into_iter()
+            .map(|(payload, paying_wallet)| {
+                Self::write_and_tend(
+                    sender_wrapper.clone(),
+                    payload,
+                    paying_wallet,
+                    inner_arc.clone(),
+                )
+            })
+            .collect();
+        actix::spawn(future::join_all(writes).map(|_| ()).map_err(move |error| {
+            Self::clean_up_bad_stream(inner_arc, &stream_key, source, error, vec![])
+        }));
+    }
+
+    // The connection may not have been registered yet; if not, the marker goes back for
+    // add_new_streams to find.
+    fn drop_given_up_connection(
+        inner: &mut StreamHandlerPoolRealInner,
+        stream_key: StreamKey,
+        pending: &PendingStream,
+    ) {
+        match inner.stream_writer_channels.remove(&stream_key) {
+            Some(_) => debug!(
+                inner.logger,
+                "Dropping connection for stream {:?}, which was given up while it was being established",
+                stream_key
+            ),
+            None => {
+                inner.pending_streams.insert(
+                    stream_key,
+                    PendingStream {
+                        establishment_id: pending.establishment_id,
+                        given_up: true,
+                        queued_packets: vec![],
+                    },
+                );
+            }
+        }
+    }
+
+    fn forget_pending_stream(
+        stream_key: &StreamKey,
+        establishment_id: u64,
+        inner_arc: &Arc<Mutex<StreamHandlerPoolRealInner>>,
+    ) {
+        let mut inner = inner_arc.lock().expect("Stream handler pool is poisoned");
+        if let Some(pending) = Self::take_pending_stream(&mut inner, stream_key, establishment_id) {
+            if !pending.queued_packets.is_empty() {
+                debug!(
+                    inner.logger,
+                    "Discarding {} packets queued for stream {:?}",
+                    pending.queued_packets.len(),
+                    stream_key
+                );
+            }
+        }
+    }
+
+    // A stream given up while it was pending may have been started again since; only the
+    // establishment that created the entry may take it.
+    fn take_pending_stream(
+        inner: &mut StreamHandlerPoolRealInner,
+        stream_key: &StreamKey,
+        establishment_id: u64,
+    ) -> Option<PendingStream> {
+        let is_ours = match inner.pending_streams.get(stream_key) {
+            Some(pending) => pending.establishment_id == establishment_id,
+            None => false,
+        };
+        if is_ours {
+            inner.pending_streams.remove(stream_key)
+        } else {
+            None
+        }
+    }
+
+    fn is_retriable(payload: &ClientRequestPayload) -> bool {
+        payload.protocol == ProxyProtocol::HTTP
+            && HttpProtocolPack::is_idempotent(&payload.sequenced_packet.data)
+    }
+
+    // Connection failures on an existing stream are passed on to the browser the way the server
+    // would have passed them on, rather than as an empty response.
+    fn connection_lost_response(payload: &ClientRequestPayload) -> Vec<u8> {
+        from_protocol(payload.protocol)
+            .server_impersonator()
+            .server_connection_failure_response(
+                payload.target_hostname.clone(),
+                ServerConnectionFailure::Reset,
+            )
+    }
+
+    // The server has gone away from under an existing stream; an idempotent request can be sent
+    // again on a new connection without the browser ever knowing. The new connection carries on
+    // the stream's numbering in both directions.
+    fn reconnect_and_write(
+        payload: ClientRequestPayload,
+        paying_wallet: Option<Wallet>,
+        inner_arc: Arc<Mutex<StreamHandlerPoolRealInner>>,
+        source: SocketAddr,
+        error: String,
+    ) -> Box<dyn Future<Item = (), Error = ()>> {
+        let sequencing = {
+            let mut inner = inner_arc.lock().expect("Stream handler pool is poisoned");
+            warning!(
+                inner.logger,
+                "Lost connection to {} for stream {:?} ({}); retrying idempotent request on a new connection",
+                privacy::socket_addr(source),
+                payload.stream_key,
+                error
+            );
+            inner.stream_writer_channels.remove(&payload.stream_key);
+            let response_sequencer = match inner.response_sequencers.get(&payload.stream_key) {
+                Some(sequencer) => sequencer.lock().expect("Sequencer is poisoned").clone(),
+                None => Sequencer::new(),
+            };
+            StreamSequencing {
+                first_request_sequence_number: payload.sequenced_packet.sequence_number,
+                response_sequencer: Arc::new(Mutex::new(response_sequencer)),
+            }
+        };
+        Self::start_establishment(
+            payload,
+            paying_wallet,
+            inner_arc,
+            IDEMPOTENT_REQUEST_RETRIES.saturating_sub(1),
+            sequencing,
+        )
+    }
+
+    fn establish_and_write(
+        payload: ClientRequestPayload,
+        paying_wallet: Option<Wallet>,
+        inner_arc: Arc<Mutex<StreamHandlerPoolRealInner>>,
+        retries_left: usize,
+        establishment_id: u64,
+        sequencing: StreamSequencing,
+    ) -> Box<dyn Future<Item = (), Error = ()>> {
+        let retry_payload = payload.clone();
+        let retry_wallet = paying_wallet.clone();
+        let retry_sequencing = sequencing.clone();
+        let inner_arc_1 = inner_arc.clone();
+        if let Some(trace_id) = payload.trace_id_opt {
+            stream_trace::open_span(trace_id, SpanKind::ServerConnect);
+        }
+        Box::new(
+            Self::make_stream_with_key(&payload, establishment_id, sequencing, inner_arc.clone())
+                .and_then(move |sender_wrapper| {
+                    if let Some(trace_id) = payload.trace_id_opt {
+                        stream_trace::close_span(trace_id, SpanKind::ServerConnect);
+                    }
+                    let stream_key = payload.stream_key;
+                    let queue_sender_wrapper = sender_wrapper.clone();
+                    Self::write_and_tend(sender_wrapper, payload, paying_wallet, inner_arc.clone())
+                        .map_err(|error| {
+                            StreamFailure::Connection(ServerConnectionFailure::Reset, error)
+                        })
+                        .map(move |_| {
+                            Self::release_queued_packets(
+                                stream_key,
+                                establishment_id,
+                                queue_sender_wrapper,
+                                inner_arc,
+                            )
+                        })
+                })
+                .or_else(move |failure| -> Box<dyn Future<Item = (), Error = ()>> {
+                    if let (StreamFailure::Connection(connection_failure, message), true) =
+                        (&failure, retries_left > 0)
+                    {
+                        warning!(
+                            Self::make_logger_copy(&inner_arc_1),
+                            "{} for stream {:?} ({}); retrying idempotent request",
+                            connection_failure,
+                            retry_payload.stream_key,
+                            message
+                        );
+                        Self::establish_and_write(
+                            retry_payload,
+                            retry_wallet,
+                            inner_arc_1,
+                            retries_left - 1,
+                            establishment_id,
+                            retry_sequencing,
+                        )
+                    } else {
+                        Self::forget_pending_stream(
+                            &retry_payload.stream_key,
+                            establishment_id,
+                            &inner_arc_1,
+                        );
+                        if let StreamFailure::Abandoned(message) = failure {
+                            debug!(Self::make_logger_copy(&inner_arc_1), "{}", message);
+                        } else {
+                            Self::answer_failure(&retry_payload, inner_arc_1, failure);
+                        }
+                        Box::new(future::err(()))
+                    }
+                }),
+        )
+    }
+
+    fn answer_failure(
+        payload: &ClientRequestPayload,
+        inner_arc: Arc<Mutex<StreamHandlerPoolRealInner>>,
+        failure: StreamFailure,
+    ) {
+        let response = match &failure {
+            StreamFailure::Unreported(_) | StreamFailure::Abandoned(_) => vec![],
+            // The refusal is the whole answer: the originating Node retires the stream when it
+            // arrives, so no empty last packet follows it.
+            StreamFailure::Refused(reason) => {
+                let mut inner = inner_arc.lock().expect("Stream handler pool is poisoned");
+                Self::forget_bad_stream(&mut inner, &payload.stream_key, reason);
+                inner
+                    .proxy_client_subs
+                    .exit_policy_refused
+                    .try_send(ExitPolicyRefusal::new(payload.stream_key, reason.clone()))
+                    .expect("ProxyClient is dead");
+                return;
+            }
+            StreamFailure::Connection(connection_failure, _) => from_protocol(payload.protocol)
+                .server_impersonator()
+                .server_connection_failure_response(
+                    payload.target_hostname.clone(),
+                    *connection_failure,
+                ),
+        };
+        Self::clean_up_bad_stream(
+            inner_arc,
+            &payload.stream_key,
+            error_socket_addr(),
+            failure.message().to_string(),
+            response,
+        );
+    }
+
     fn clean_up_bad_stream(
         inner_arc: Arc<Mutex<StreamHandlerPoolRealInner>>,
         stream_key: &StreamKey,
         source: SocketAddr,
         error: String,
+        response: Vec<u8>,
     ) {
         let mut inner = inner_arc.lock().expect("Stream handler pool was poisoned");
+        let sequence_number = Self::forget_bad_stream(&mut inner, stream_key, &error);
+        Self::send_terminating_package(
+            stream_key,
+            source,
+            sequence_number,
+            response,
+            &inner.proxy_client_subs.inbound_server_data,
+        );
+    }
+
+    // Returns the sequence number the stream's next response would have had
+    fn forget_bad_stream(
+        inner: &mut StreamHandlerPoolRealInner,
+        stream_key: &StreamKey,
+        error: &str,
+    ) -> u64 {
         error!(
             inner.logger,
-            "Couldn't process request from CORES package: {}", error
+            "Couldn't process request from CORES package: {}",
+            privacy::data(error)
         );
         if let Some(sender_wrapper) = inner.stream_writer_channels.remove(stream_key) {
             debug!(
                 inner.logger,
                 "Removing stream writer for {}",
-                sender_wrapper.peer_addr()
+                privacy::socket_addr(sender_wrapper.peer_addr())
             );
         }
-        Self::send_terminating_package(
-            stream_key,
-            source,
-            &inner.proxy_client_subs.inbound_server_data,
-        );
+        match inner.response_sequencers.remove(stream_key) {
+            Some(sequencer) => sequencer
+                .lock()
+                .expect("Sequencer is poisoned")
+                .next_sequence_number(),
+            None => 0,
+        }
     }
 
     fn write_and_tend(
@@ -183,7 +732,7 @@ impl StreamHandlerPoolReal {
                         inner.logger,
                         "Removing StreamWriter {:?} to {}",
                         stream_key,
-                        channel.peer_addr()
+                        privacy::socket_addr(channel.peer_addr())
                     ),
                     None => debug!(
                         inner.logger,
@@ -215,15 +764,15 @@ impl StreamHandlerPoolReal {
 
     fn make_stream_with_key(
         payload: &ClientRequestPayload,
+        establishment_id: u64,
+        sequencing: StreamSequencing,
         inner_arc: Arc<Mutex<StreamHandlerPoolRealInner>>,
     ) -> StreamEstablisherResult {
-        // TODO: Figure out what to do if a flurry of requests for a particular stream key
-        // come flooding in so densely that several of them arrive in the time it takes to
-        // resolve the first one and add it to the stream_writers map.
         let logger = Self::make_logger_copy(&inner_arc);
         debug!(
             logger,
-            "No stream to {:?} exists; resolving host", &payload.target_hostname
+            "No stream to {:?} exists; resolving host",
+            privacy::data(&payload.target_hostname)
         );
 
         match payload.target_hostname {
@@ -233,8 +782,16 @@ impl StreamHandlerPoolReal {
                     socket_addr,
                     inner_arc,
                     target_hostname.to_string(),
+                    establishment_id,
+                    sequencing,
+                ),
+                Err(_) => Self::lookup_dns(
+                    inner_arc,
+                    target_hostname.to_string(),
+                    payload.clone(),
+                    establishment_id,
+                    sequencing,
                 ),
-                Err(_) => Self::lookup_dns(inner_arc, target_hostname.to_string(), payload.clone()),
             },
             None => {
                 error!(
@@ -244,8 +801,10 @@ impl StreamHandlerPoolReal {
                 );
                 Box::new(future::err::<
                     Box<dyn SenderWrapper<SequencedPacket> + 'static>,
-                    String,
-                >("No hostname provided".to_string()))
+                    StreamFailure,
+                >(StreamFailure::Unreported(
+                    "No hostname provided".to_string(),
+                )))
             }
         }
     }
@@ -271,20 +830,37 @@ impl StreamHandlerPoolReal {
         ip_addr: IpAddr,
         inner_arc: Arc<Mutex<StreamHandlerPoolRealInner>>,
         target_hostname: String,
+        establishment_id: u64,
+        sequencing: StreamSequencing,
     ) -> StreamEstablisherResult {
+        let ip_addrs = {
+            let inner = inner_arc.lock().expect("Stream handler pool is poisoned");
+            match Self::apply_exit_policy(
+                &inner.exit_policy,
+                &target_hostname,
+                vec![ip_addr],
+                payload.target_port,
+                &inner.logger,
+            ) {
+                Ok(ip_addrs) => ip_addrs,
+                Err(failure) => return Box::new(future::err(failure)),
+            }
+        };
         let mut stream_establisher = StreamHandlerPoolReal::make_establisher(inner_arc.clone());
-        Box::new(
-            future::lazy(move || {
-                stream_establisher.establish_stream(&payload, vec![ip_addr], target_hostname)
-            })
-            .map_err(|io_error| format!("Could not establish stream: {:?}", io_error)),
-        )
+        Box::new(future::lazy(move || {
+            Self::establishment_is_live(&payload.stream_key, establishment_id, &inner_arc)?;
+            stream_establisher
+                .establish_stream(&payload, ip_addrs, target_hostname, sequencing)
+                .map_err(StreamFailure::from_io_error)
+        }))
     }
 
     fn lookup_dns(
         inner_arc: Arc<Mutex<StreamHandlerPoolRealInner>>,
         target_hostname: String,
         payload: ClientRequestPayload,
+        establishment_id: u64,
+        sequencing: StreamSequencing,
     ) -> StreamEstablisherResult {
         let fqdn = Self::make_fqdn(&target_hostname);
         let dns_resolve_failed_sub = inner_arc
@@ -296,6 +872,12 @@ impl StreamHandlerPoolReal {
         let mut establisher = StreamHandlerPoolReal::make_establisher(inner_arc.clone());
         let stream_key = payload.stream_key;
         let logger = StreamHandlerPoolReal::make_logger_copy(&inner_arc);
+        let exit_policy = inner_arc
+            .lock()
+            .expect("Stream handler pool is poisoned")
+            .exit_policy
+            .clone();
+        let inner_arc_1 = inner_arc.clone();
         Box::new(
             inner_arc
                 .lock()
@@ -309,15 +891,18 @@ impl StreamHandlerPoolReal {
                     err
                 })
                 .then(move |lookup_result| {
+                    // The stream may have been given up while its host was being looked up
+                    Self::establishment_is_live(&stream_key, establishment_id, &inner_arc_1)?;
                     Self::handle_lookup_ip(
                         target_hostname.to_string(),
                         &payload,
                         lookup_result,
+                        &exit_policy,
                         logger,
                         &mut establisher,
+                        sequencing,
                     )
-                })
-                .map_err(|io_error| format!("Could not establish stream: {:?}", io_error)),
+                }),
         )
     }
 
@@ -325,24 +910,78 @@ impl StreamHandlerPoolReal {
         target_hostname: String,
         payload: &ClientRequestPayload,
         lookup_result: Result<LookupIp, ResolveError>,
+        exit_policy: &ExitPolicy,
         logger: Logger,
         establisher: &mut StreamEstablisher,
-    ) -> io::Result<Box<dyn SenderWrapper<SequencedPacket>>> {
+        sequencing: StreamSequencing,
+    ) -> Result<Box<dyn SenderWrapper<SequencedPacket>>, StreamFailure> {
         let ip_addrs: Vec<IpAddr> = match lookup_result {
             Err(e) => {
                 error!(
                     logger,
-                    "Could not find IP address for host {}: {}", target_hostname, e
+                    "Could not find IP address for host {}: {}",
+                    privacy::host(&target_hostname),
+                    privacy::data(&e)
                 );
-                return Err(io::Error::from(e));
+                return Err(StreamFailure::Unreported(format!(
+                    "Could not establish stream: {:?}",
+                    io::Error::from(e)
+                )));
             }
             Ok(lookup_ip) => lookup_ip.iter().map(|x| x).collect(),
         };
         debug!(
             logger,
-            "Found IP addresses for {}: {:?}", target_hostname, &ip_addrs
+            "Found IP addresses for {}: {:?}",
+            privacy::host(&target_hostname),
+            privacy::data(&ip_addrs)
         );
-        establisher.establish_stream(&payload, ip_addrs, target_hostname)
+        let ip_addrs = Self::apply_exit_policy(
+            exit_policy,
+            &target_hostname,
+            ip_addrs,
+            payload.target_port,
+            &logger,
+        )?;
+        establisher
+            .establish_stream(&payload, ip_addrs, target_hostname, sequencing)
+            .map_err(StreamFailure::from_io_error)
+    }
+
+    // Checked against resolved addresses rather than just the hostname, so that a name that
+    // resolves to somewhere forbidden can't be used to sneak past the policy. The originator is
+    // told only that its target was refused: what the name resolved to here is none of its
+    // business.
+    fn apply_exit_policy(
+        exit_policy: &ExitPolicy,
+        target_hostname: &str,
+        ip_addrs: Vec<IpAddr>,
+        target_port: u16,
+        logger: &Logger,
+    ) -> Result<Vec<IpAddr>, StreamFailure> {
+        let mut refusals = vec![];
+        let permitted: Vec<IpAddr> = ip_addrs
+            .into_iter()
+            .filter(
+                |ip_addr| match exit_policy.check(target_hostname, *ip_addr, target_port) {
+                    Ok(()) => true,
+                    Err(reason) => {
+                        refusals.push(reason);
+                        false
+                    }
+                },
+            )
+            .collect();
+        match (permitted.is_empty(), refusals.first()) {
+            (true, Some(reason)) => {
+                warning!(logger, "Refusing to connect: {}", privacy::data(reason));
+                Err(StreamFailure::Refused(format!(
+                    "{} port {} is refused by the exit Node's exit policy",
+                    target_hostname, target_port
+                )))
+            }
+            _ => Ok(permitted),
+        }
     }
 
     fn make_fqdn(target_hostname: &str) -> String {
@@ -381,15 +1020,17 @@ impl StreamHandlerPoolReal {
     fn send_terminating_package(
         stream_key: &StreamKey,
         source: SocketAddr,
+        sequence_number: u64,
+        data: Vec<u8>,
         proxy_client_sub: &Recipient<InboundServerData>,
     ) {
         proxy_client_sub
             .try_send(InboundServerData {
                 stream_key: *stream_key,
                 last_data: true,
-                sequence_number: 0,
+                sequence_number,
                 source,
-                data: vec![],
+                data,
             })
             .expect("ProxyClient is dead");
     }
@@ -401,7 +1042,23 @@ impl StreamHandlerPoolReal {
 
     fn clean_up_dead_streams(&self) {
         let mut inner = self.inner.lock().expect("Stream handler pool is poisoned");
-        while let Ok((stream_key, sequence_number)) = self.stream_killer_rx.try_recv() {
+        while let Ok((stream_key, sequencer)) = self.stream_killer_rx.try_recv() {
+            let superseded = match inner.response_sequencers.get(&stream_key) {
+                Some(current) => !Arc::ptr_eq(current, &sequencer),
+                None => false,
+            };
+            if superseded {
+                debug!(
+                    inner.logger,
+                    "Old connection for stream {:?} closed after it was reconnected", stream_key
+                );
+                continue;
+            }
+            inner.response_sequencers.remove(&stream_key);
+            let sequence_number = sequencer
+                .lock()
+                .expect("Sequencer is poisoned")
+                .next_sequence_number();
             match inner.stream_writer_channels.remove(&stream_key) {
                 Some(writer_channel) => {
                     inner
@@ -418,7 +1075,7 @@ impl StreamHandlerPoolReal {
                     debug!(
                         inner.logger,
                         "Killed StreamWriter to {} and sent server-drop report",
-                        writer_channel.peer_addr()
+                        privacy::socket_addr(writer_channel.peer_addr())
                     )
                 }
                 None => debug!(
@@ -429,16 +1086,37 @@ impl StreamHandlerPoolReal {
         }
     }
 
+    // Takes the marker off a given-up stream, if that's what it is.
+    fn is_given_up(inner: &mut StreamHandlerPoolRealInner, stream_key: &StreamKey) -> bool {
+        let given_up = match inner.pending_streams.get(stream_key) {
+            Some(pending) => pending.given_up,
+            None => false,
+        };
+        if given_up {
+            inner.pending_streams.remove(stream_key);
+        }
+        given_up
+    }
+
     fn add_new_streams(&self) {
         let mut inner = self.inner.lock().expect("Stream handler pool is poisoned");
         loop {
             match self.stream_adder_rx.try_recv() {
                 Err(_) => break,
                 Ok((stream_key, stream_writer_channel)) => {
+                    if Self::is_given_up(&mut inner, &stream_key) {
+                        debug!(
+                            inner.logger,
+                            "Dropping connection to {} for stream {:?}, which was given up while it was being established",
+                            privacy::socket_addr(stream_writer_channel.peer_addr()),
+                            stream_key
+                        );
+                        continue;
+                    }
                     debug!(
                         inner.logger,
                         "Persisting StreamWriter to {} under key {:?}",
-                        stream_writer_channel.peer_addr(),
+                        privacy::socket_addr(stream_writer_channel.peer_addr()),
                         stream_key
                     );
                     inner
@@ -459,6 +1137,8 @@ pub trait StreamHandlerPoolFactory {
         proxy_client_subs: ProxyClientSubs,
         exit_service_rate: u64,
         exit_byte_rate: u64,
+        exit_policy: ExitPolicy,
+        connection_pool_opt: Option<ConnectionPool>,
     ) -> Box<dyn StreamHandlerPool>;
 }
 
@@ -473,6 +1153,8 @@ impl StreamHandlerPoolFactory for StreamHandlerPoolFactoryReal {
         proxy_client_subs: ProxyClientSubs,
         exit_service_rate: u64,
         exit_byte_rate: u64,
+        exit_policy: ExitPolicy,
+        connection_pool_opt: Option<ConnectionPool>,
     ) -> Box<dyn StreamHandlerPool> {
         Box::new(StreamHandlerPoolReal::new(
             resolver,
@@ -481,6 +1163,8 @@ impl StreamHandlerPoolFactory for StreamHandlerPoolFactoryReal {
             proxy_client_subs,
             exit_service_rate,
             exit_byte_rate,
+            exit_policy,
+            connection_pool_opt,
         ))
     }
 }
@@ -492,6 +1176,7 @@ mod tests {
     use crate::proxy_client::local_test_utils::make_send_error;
     use crate::proxy_client::local_test_utils::ResolverWrapperMock;
     use crate::proxy_client::stream_establisher::StreamEstablisher;
+    use crate::proxy_server::server_impersonator_http::ServerImpersonatorHttp;
     use crate::sub_lib::channel_wrappers::FuturesChannelFactoryReal;
     use crate::sub_lib::cryptde::PublicKey;
     use crate::sub_lib::hopper::ExpiredCoresPackage;
@@ -570,11 +1255,16 @@ mod tests {
                 proxy_client_sub: peer_actors.proxy_client.inbound_server_data.clone(),
                 logger: logger.clone(),
                 channel_factory: Box::new(FuturesChannelFactoryMock::default()),
+                connection_pool_opt: None,
             };
             let inner = StreamHandlerPoolRealInner {
                 accountant_sub: peer_actors.accountant.report_exit_service_provided.clone(),
                 proxy_client_subs: peer_actors.proxy_client.clone(),
                 stream_writer_channels: HashMap::new(),
+                response_sequencers: HashMap::new(),
+                pending_streams: HashMap::new(),
+                next_establishment_id: 0,
+                pending_stream_timeout: Duration::from_millis(PENDING_STREAM_TIMEOUT_MS),
                 resolver: Box::new(resolver_mock),
                 logger,
                 establisher_factory: Box::new(StreamEstablisherFactoryMock {
@@ -582,6 +1272,7 @@ mod tests {
                 }),
                 exit_service_rate: Default::default(),
                 exit_byte_rate: Default::default(),
+                exit_policy: ExitPolicy::default(),
             };
             let payload = ClientRequestPayload {
                 version: ClientRequestPayload::version(),
@@ -591,6 +1282,9 @@ mod tests {
                 target_port: HTTP_PORT,
                 protocol: ProxyProtocol::HTTP,
                 originator_public_key: cryptde.public_key().clone(),
+                fresh_return_route: false,
+                alpn_protocols: vec![],
+                trace_id_opt: None,
             };
 
             StreamHandlerPoolReal::process_package(payload, None, Arc::new(Mutex::new(inner)));
@@ -625,6 +1319,9 @@ mod tests {
             target_port: HTTP_PORT,
             protocol: ProxyProtocol::HTTP,
             originator_public_key: PublicKey::new(&b"men's souls"[..]),
+            fresh_return_route: false,
+            alpn_protocols: vec![],
+            trace_id_opt: None,
         };
         let write_parameters = Arc::new(Mutex::new(vec![]));
         let tx_to_write = Box::new(
@@ -650,6 +1347,8 @@ mod tests {
                 peer_actors.proxy_client.clone(),
                 100,
                 200,
+                ExitPolicy::default(),
+                None,
             );
             subject
                 .inner
@@ -688,6 +1387,9 @@ mod tests {
                 target_port: HTTP_PORT,
                 protocol: ProxyProtocol::HTTP,
                 originator_public_key: originator_key,
+                fresh_return_route: false,
+                alpn_protocols: vec![],
+                trace_id_opt: None,
             };
             let package = ExpiredCoresPackage::new(
                 SocketAddr::from_str("1.2.3.4:1234").unwrap(),
@@ -712,6 +1414,8 @@ mod tests {
                 peer_actors.proxy_client.clone(),
                 100,
                 200,
+                ExitPolicy::default(),
+                None,
             );
             subject
                 .inner
@@ -737,18 +1441,18 @@ mod tests {
     }
 
     #[test]
-    fn when_hostname_is_ip_establish_stream_without_dns_lookup() {
+    fn connecting_to_the_server_is_timed_for_a_traced_stream() {
         let cryptde = cryptde();
-        let lookup_ip_parameters = Arc::new(Mutex::new(vec![]));
-        let expected_lookup_ip_parameters = lookup_ip_parameters.clone();
-        let write_parameters = Arc::new(Mutex::new(vec![]));
-        let expected_write_parameters = write_parameters.clone();
-        let (proxy_client, proxy_client_awaiter, proxy_client_recording_arc) = make_recorder();
+        let stream_key = make_meaningless_stream_key();
+        stream_trace::enable_without_export();
+        let trace_id = stream_trace::begin(&stream_key).unwrap();
+        stream_trace::disable();
+        let (proxy_client, proxy_client_awaiter, _) = make_recorder();
         thread::spawn(move || {
             let peer_actors = peer_actors_builder().proxy_client(proxy_client).build();
             let client_request_payload = ClientRequestPayload {
                 version: ClientRequestPayload::version(),
-                stream_key: make_meaningless_stream_key(),
+                stream_key,
                 sequenced_packet: SequencedPacket {
                     data: b"These are the times".to_vec(),
                     sequence_number: 0,
@@ -758,6 +1462,9 @@ mod tests {
                 target_port: HTTP_PORT,
                 protocol: ProxyProtocol::HTTP,
                 originator_public_key: PublicKey::new(&b"men's souls"[..]),
+                fresh_return_route: false,
+                alpn_protocols: vec![],
+                trace_id_opt: Some(trace_id),
             };
             let package = ExpiredCoresPackage::new(
                 SocketAddr::from_str("1.2.3.4:1234").unwrap(),
@@ -766,12 +1473,6 @@ mod tests {
                 client_request_payload.into(),
                 0,
             );
-            let resolver = ResolverWrapperMock::new()
-                .lookup_ip_parameters(&lookup_ip_parameters)
-                .lookup_ip_success(vec![
-                    IpAddr::from_str("2.3.4.5").unwrap(),
-                    IpAddr::from_str("3.4.5.6").unwrap(),
-                ]);
             let peer_addr = SocketAddr::from_str("3.4.5.6:80").unwrap();
             let first_read_result = b"HTTP/1.1 200 OK\r\n\r\n";
             let reader = ReadHalfWrapperMock {
@@ -784,17 +1485,19 @@ mod tests {
                 ],
             };
             let writer = WriteHalfWrapperMock {
-                poll_write_params: write_parameters,
+                poll_write_params: Arc::new(Mutex::new(vec![])),
                 poll_write_results: vec![Ok(Async::Ready(first_read_result.len()))],
                 shutdown_results: Arc::new(Mutex::new(vec![])),
             };
             let mut subject = StreamHandlerPoolReal::new(
-                Box::new(resolver),
+                Box::new(ResolverWrapperMock::new()),
                 cryptde,
                 peer_actors.accountant.report_exit_service_provided.clone(),
                 peer_actors.proxy_client.clone(),
                 100,
                 200,
+                ExitPolicy::default(),
+                None,
             );
             let (stream_killer_tx, stream_killer_rx) = mpsc::channel();
             subject.stream_killer_rx = stream_killer_rx;
@@ -814,8 +1517,8 @@ mod tests {
                     proxy_client_sub: inner.proxy_client_subs.inbound_server_data.clone(),
                     logger: inner.logger.clone(),
                     channel_factory: Box::new(FuturesChannelFactoryReal {}),
+                    connection_pool_opt: None,
                 };
-
                 inner.establisher_factory = Box::new(StreamEstablisherFactoryMock {
                     make_results: RefCell::new(vec![establisher]),
                 });
@@ -825,29 +1528,19 @@ mod tests {
         });
 
         proxy_client_awaiter.await_message_count(1);
-        assert_eq!(
-            expected_lookup_ip_parameters.lock().unwrap().deref(),
-            &(vec![] as Vec<String>)
-        );
-        assert_eq!(
-            expected_write_parameters.lock().unwrap().remove(0),
-            b"These are the times".to_vec()
-        );
-        let proxy_client_recording = proxy_client_recording_arc.lock().unwrap();
-        assert_eq!(
-            proxy_client_recording.get_record::<InboundServerData>(0),
-            &InboundServerData {
-                stream_key: make_meaningless_stream_key(),
-                last_data: false,
-                sequence_number: 0,
-                source: SocketAddr::from_str("3.4.5.6:80").unwrap(),
-                data: b"HTTP/1.1 200 OK\r\n\r\n".to_vec(),
-            }
+        let view = stream_trace::views()
+            .into_iter()
+            .find(|view| view.trace_id == trace_id.to_string())
+            .unwrap();
+        assert!(
+            view.spans.iter().any(|span| span.name == "server_connect"),
+            "{:?}",
+            view
         );
     }
 
     #[test]
-    fn ip_is_parsed_even_without_port() {
+    fn when_hostname_is_ip_establish_stream_without_dns_lookup() {
         let cryptde = cryptde();
         let lookup_ip_parameters = Arc::new(Mutex::new(vec![]));
         let expected_lookup_ip_parameters = lookup_ip_parameters.clone();
@@ -864,10 +1557,13 @@ mod tests {
                     sequence_number: 0,
                     last_data: false,
                 },
-                target_hostname: Some(String::from("3.4.5.6")),
+                target_hostname: Some(String::from("3.4.5.6:80")),
                 target_port: HTTP_PORT,
                 protocol: ProxyProtocol::HTTP,
                 originator_public_key: PublicKey::new(&b"men's souls"[..]),
+                fresh_return_route: false,
+                alpn_protocols: vec![],
+                trace_id_opt: None,
             };
             let package = ExpiredCoresPackage::new(
                 SocketAddr::from_str("1.2.3.4:1234").unwrap(),
@@ -905,6 +1601,8 @@ mod tests {
                 peer_actors.proxy_client.clone(),
                 100,
                 200,
+                ExitPolicy::default(),
+                None,
             );
             let (stream_killer_tx, stream_killer_rx) = mpsc::channel();
             subject.stream_killer_rx = stream_killer_rx;
@@ -924,6 +1622,7 @@ mod tests {
                     proxy_client_sub: inner.proxy_client_subs.inbound_server_data.clone(),
                     logger: inner.logger.clone(),
                     channel_factory: Box::new(FuturesChannelFactoryReal {}),
+                    connection_pool_opt: None,
                 };
 
                 inner.establisher_factory = Box::new(StreamEstablisherFactoryMock {
@@ -957,25 +1656,283 @@ mod tests {
     }
 
     #[test]
-    fn missing_hostname_for_nonexistent_stream_generates_log_and_termination_message() {
-        init_test_logging();
+    fn exit_policy_refuses_literal_ip_address_without_connecting() {
         let cryptde = cryptde();
+        let stream_key = make_meaningless_stream_key();
         let (proxy_client, proxy_client_awaiter, proxy_client_recording_arc) = make_recorder();
-        let originator_key = PublicKey::new(&b"men's souls"[..]);
         thread::spawn(move || {
             let peer_actors = peer_actors_builder().proxy_client(proxy_client).build();
             let client_request_payload = ClientRequestPayload {
                 version: ClientRequestPayload::version(),
-                stream_key: make_meaningless_stream_key(),
+                stream_key,
                 sequenced_packet: SequencedPacket {
-                    data: b"These are the times".to_vec(),
+                    data: b"GET / HTTP/1.1\r\n\r\n".to_vec(),
                     sequence_number: 0,
                     last_data: false,
                 },
-                target_hostname: None,
-                target_port: HTTP_PORT,
+                target_hostname: Some(String::from("127.0.0.1:8080")),
+                target_port: 8080,
                 protocol: ProxyProtocol::HTTP,
-                originator_public_key: originator_key,
+                originator_public_key: PublicKey::new(&b"men's souls"[..]),
+                fresh_return_route: false,
+                alpn_protocols: vec![],
+                trace_id_opt: None,
+            };
+            let package = ExpiredCoresPackage::new(
+                SocketAddr::from_str("1.2.3.4:1234").unwrap(),
+                Some(make_wallet("consuming")),
+                make_meaningless_route(),
+                client_request_payload.into(),
+                0,
+            );
+            let subject = StreamHandlerPoolReal::new(
+                Box::new(ResolverWrapperMock::new()),
+                cryptde,
+                peer_actors.accountant.report_exit_service_provided.clone(),
+                peer_actors.proxy_client.clone(),
+                100,
+                200,
+                ExitPolicy::default(),
+                None,
+            );
+            subject.inner.lock().unwrap().establisher_factory =
+                Box::new(StreamEstablisherFactoryMock {
+                    make_results: RefCell::new(vec![]),
+                });
+
+            run_process_package_in_actix(subject, package);
+        });
+
+        proxy_client_awaiter.await_message_count(1);
+        let proxy_client_recording = proxy_client_recording_arc.lock().unwrap();
+        assert_eq!(
+            proxy_client_recording.get_record::<ExitPolicyRefusal>(0),
+            &ExitPolicyRefusal::new(
+                stream_key,
+                "127.0.0.1:8080 port 8080 is refused by the exit Node's exit policy".to_string()
+            )
+        );
+        // The stream is forgotten before the refusal goes out, so nothing can come after it
+        assert_eq!(proxy_client_recording.len(), 1);
+    }
+
+    #[test]
+    fn exit_policy_is_applied_to_resolved_addresses() {
+        let cryptde = cryptde();
+        let stream_key = make_meaningless_stream_key();
+        let (proxy_client, proxy_client_awaiter, proxy_client_recording_arc) = make_recorder();
+        thread::spawn(move || {
+            let peer_actors = peer_actors_builder().proxy_client(proxy_client).build();
+            let client_request_payload = ClientRequestPayload {
+                version: ClientRequestPayload::version(),
+                stream_key,
+                sequenced_packet: SequencedPacket {
+                    data: b"GET / HTTP/1.1\r\n\r\n".to_vec(),
+                    sequence_number: 0,
+                    last_data: false,
+                },
+                target_hostname: Some(String::from("innocent.com")),
+                target_port: HTTP_PORT,
+                protocol: ProxyProtocol::HTTP,
+                originator_public_key: PublicKey::new(&b"men's souls"[..]),
+                fresh_return_route: false,
+                alpn_protocols: vec![],
+                trace_id_opt: None,
+            };
+            let package = ExpiredCoresPackage::new(
+                SocketAddr::from_str("1.2.3.4:1234").unwrap(),
+                Some(make_wallet("consuming")),
+                make_meaningless_route(),
+                client_request_payload.into(),
+                0,
+            );
+            let resolver = ResolverWrapperMock::new().lookup_ip_success(vec![
+                IpAddr::from_str("169.254.169.254").unwrap(),
+                IpAddr::from_str("10.0.0.1").unwrap(),
+            ]);
+            let subject = StreamHandlerPoolReal::new(
+                Box::new(resolver),
+                cryptde,
+                peer_actors.accountant.report_exit_service_provided.clone(),
+                peer_actors.proxy_client.clone(),
+                100,
+                200,
+                ExitPolicy::default(),
+                None,
+            );
+            let (stream_adder_tx, _stream_adder_rx) = mpsc::channel();
+            let (stream_killer_tx, _stream_killer_rx) = mpsc::channel();
+            {
+                let mut inner = subject.inner.lock().unwrap();
+                let establisher = StreamEstablisher {
+                    cryptde,
+                    stream_adder_tx,
+                    stream_killer_tx,
+                    stream_connector: Box::new(StreamConnectorMock::new()),
+                    proxy_client_sub: inner.proxy_client_subs.inbound_server_data.clone(),
+                    logger: inner.logger.clone(),
+                    channel_factory: Box::new(FuturesChannelFactoryReal {}),
+                    connection_pool_opt: None,
+                };
+                inner.establisher_factory = Box::new(StreamEstablisherFactoryMock {
+                    make_results: RefCell::new(vec![establisher]),
+                });
+            }
+
+            run_process_package_in_actix(subject, package);
+        });
+
+        proxy_client_awaiter.await_message_count(1);
+        let proxy_client_recording = proxy_client_recording_arc.lock().unwrap();
+        assert_eq!(
+            proxy_client_recording.get_record::<ExitPolicyRefusal>(0),
+            &ExitPolicyRefusal::new(
+                stream_key,
+                "innocent.com port 80 is refused by the exit Node's exit policy".to_string()
+            )
+        );
+        assert_eq!(proxy_client_recording.len(), 1);
+    }
+
+    #[test]
+    fn ip_is_parsed_even_without_port() {
+        let cryptde = cryptde();
+        let lookup_ip_parameters = Arc::new(Mutex::new(vec![]));
+        let expected_lookup_ip_parameters = lookup_ip_parameters.clone();
+        let write_parameters = Arc::new(Mutex::new(vec![]));
+        let expected_write_parameters = write_parameters.clone();
+        let (proxy_client, proxy_client_awaiter, proxy_client_recording_arc) = make_recorder();
+        thread::spawn(move || {
+            let peer_actors = peer_actors_builder().proxy_client(proxy_client).build();
+            let client_request_payload = ClientRequestPayload {
+                version: ClientRequestPayload::version(),
+                stream_key: make_meaningless_stream_key(),
+                sequenced_packet: SequencedPacket {
+                    data: b"These are the times".to_vec(),
+                    sequence_number: 0,
+                    last_data: false,
+                },
+                target_hostname: Some(String::from("3.4.5.6")),
+                target_port: HTTP_PORT,
+                protocol: ProxyProtocol::HTTP,
+                originator_public_key: PublicKey::new(&b"men's souls"[..]),
+                fresh_return_route: false,
+                alpn_protocols: vec![],
+                trace_id_opt: None,
+            };
+            let package = ExpiredCoresPackage::new(
+                SocketAddr::from_str("1.2.3.4:1234").unwrap(),
+                Some(make_wallet("consuming")),
+                make_meaningless_route(),
+                client_request_payload.into(),
+                0,
+            );
+            let resolver = ResolverWrapperMock::new()
+                .lookup_ip_parameters(&lookup_ip_parameters)
+                .lookup_ip_success(vec![
+                    IpAddr::from_str("2.3.4.5").unwrap(),
+                    IpAddr::from_str("3.4.5.6").unwrap(),
+                ]);
+            let peer_addr = SocketAddr::from_str("3.4.5.6:80").unwrap();
+            let first_read_result = b"HTTP/1.1 200 OK\r\n\r\n";
+            let reader = ReadHalfWrapperMock {
+                poll_read_results: vec![
+                    (
+                        first_read_result.to_vec(),
+                        Ok(Async::Ready(first_read_result.len())),
+                    ),
+                    (vec![], Err(Error::from(ErrorKind::ConnectionAborted))),
+                ],
+            };
+            let writer = WriteHalfWrapperMock {
+                poll_write_params: write_parameters,
+                poll_write_results: vec![Ok(Async::Ready(first_read_result.len()))],
+                shutdown_results: Arc::new(Mutex::new(vec![])),
+            };
+            let mut subject = StreamHandlerPoolReal::new(
+                Box::new(resolver),
+                cryptde,
+                peer_actors.accountant.report_exit_service_provided.clone(),
+                peer_actors.proxy_client.clone(),
+                100,
+                200,
+                ExitPolicy::default(),
+                None,
+            );
+            let (stream_killer_tx, stream_killer_rx) = mpsc::channel();
+            subject.stream_killer_rx = stream_killer_rx;
+            let (stream_adder_tx, _stream_adder_rx) = mpsc::channel();
+            {
+                let mut inner = subject.inner.lock().unwrap();
+                let establisher = StreamEstablisher {
+                    cryptde,
+                    stream_adder_tx,
+                    stream_killer_tx,
+                    stream_connector: Box::new(StreamConnectorMock::new().with_connection(
+                        peer_addr.clone(),
+                        peer_addr.clone(),
+                        reader,
+                        writer,
+                    )),
+                    proxy_client_sub: inner.proxy_client_subs.inbound_server_data.clone(),
+                    logger: inner.logger.clone(),
+                    channel_factory: Box::new(FuturesChannelFactoryReal {}),
+                    connection_pool_opt: None,
+                };
+
+                inner.establisher_factory = Box::new(StreamEstablisherFactoryMock {
+                    make_results: RefCell::new(vec![establisher]),
+                });
+            }
+
+            run_process_package_in_actix(subject, package);
+        });
+
+        proxy_client_awaiter.await_message_count(1);
+        assert_eq!(
+            expected_lookup_ip_parameters.lock().unwrap().deref(),
+            &(vec![] as Vec<String>)
+        );
+        assert_eq!(
+            expected_write_parameters.lock().unwrap().remove(0),
+            b"These are the times".to_vec()
+        );
+        let proxy_client_recording = proxy_client_recording_arc.lock().unwrap();
+        assert_eq!(
+            proxy_client_recording.get_record::<InboundServerData>(0),
+            &InboundServerData {
+                stream_key: make_meaningless_stream_key(),
+                last_data: false,
+                sequence_number: 0,
+                source: SocketAddr::from_str("3.4.5.6:80").unwrap(),
+                data: b"HTTP/1.1 200 OK\r\n\r\n".to_vec(),
+            }
+        );
+    }
+
+    #[test]
+    fn missing_hostname_for_nonexistent_stream_generates_log_and_termination_message() {
+        init_test_logging();
+        let cryptde = cryptde();
+        let (proxy_client, proxy_client_awaiter, proxy_client_recording_arc) = make_recorder();
+        let originator_key = PublicKey::new(&b"men's souls"[..]);
+        thread::spawn(move || {
+            let peer_actors = peer_actors_builder().proxy_client(proxy_client).build();
+            let client_request_payload = ClientRequestPayload {
+                version: ClientRequestPayload::version(),
+                stream_key: make_meaningless_stream_key(),
+                sequenced_packet: SequencedPacket {
+                    data: b"These are the times".to_vec(),
+                    sequence_number: 0,
+                    last_data: false,
+                },
+                target_hostname: None,
+                target_port: HTTP_PORT,
+                protocol: ProxyProtocol::HTTP,
+                originator_public_key: originator_key,
+                fresh_return_route: false,
+                alpn_protocols: vec![],
+                trace_id_opt: None,
             };
             let package = ExpiredCoresPackage::new(
                 SocketAddr::from_str("1.2.3.4:1234").unwrap(),
@@ -993,7 +1950,517 @@ mod tests {
                 peer_actors.proxy_client.clone(),
                 100,
                 200,
+                ExitPolicy::default(),
+                None,
+            );
+
+            run_process_package_in_actix(subject, package);
+        });
+
+        proxy_client_awaiter.await_message_count(1);
+        let proxy_client_recording = proxy_client_recording_arc.lock().unwrap();
+        assert_eq!(
+            proxy_client_recording.get_record::<InboundServerData>(0),
+            &InboundServerData {
+                stream_key: make_meaningless_stream_key(),
+                last_data: true,
+                sequence_number: 0,
+                source: error_socket_addr(),
+                data: vec![],
+            }
+        );
+        TestLogHandler::new().exists_log_containing(
+            format!(
+                "ERROR: ProxyClient: Cannot open new stream with key {:?}: no hostname supplied",
+                make_meaningless_stream_key()
+            )
+            .as_str(),
+        );
+    }
+
+    #[test]
+    fn nonexistent_connection_springs_into_being_and_is_persisted_to_handle_transaction() {
+        let cryptde = cryptde();
+        let lookup_ip_parameters = Arc::new(Mutex::new(vec![]));
+        let expected_lookup_ip_parameters = lookup_ip_parameters.clone();
+        let write_parameters = Arc::new(Mutex::new(vec![]));
+        let expected_write_parameters = write_parameters.clone();
+        let (proxy_client, proxy_client_awaiter, proxy_client_recording_arc) = make_recorder();
+        thread::spawn(move || {
+            let peer_actors = peer_actors_builder().proxy_client(proxy_client).build();
+            let client_request_payload = ClientRequestPayload {
+                version: ClientRequestPayload::version(),
+                stream_key: make_meaningless_stream_key(),
+                sequenced_packet: SequencedPacket {
+                    data: b"These are the times".to_vec(),
+                    sequence_number: 0,
+                    last_data: false,
+                },
+                target_hostname: Some(String::from("that.try")),
+                target_port: HTTP_PORT,
+                protocol: ProxyProtocol::HTTP,
+                originator_public_key: PublicKey::new(&b"men's souls"[..]),
+                fresh_return_route: false,
+                alpn_protocols: vec![],
+                trace_id_opt: None,
+            };
+            let package = ExpiredCoresPackage::new(
+                SocketAddr::from_str("1.2.3.4:1234").unwrap(),
+                Some(make_wallet("consuming")),
+                make_meaningless_route(),
+                client_request_payload.into(),
+                0,
+            );
+            let resolver = ResolverWrapperMock::new()
+                .lookup_ip_parameters(&lookup_ip_parameters)
+                .lookup_ip_success(vec![
+                    IpAddr::from_str("2.3.4.5").unwrap(),
+                    IpAddr::from_str("3.4.5.6").unwrap(),
+                ]);
+            let peer_addr = SocketAddr::from_str("3.4.5.6:80").unwrap();
+            let first_read_result = b"HTTP/1.1 200 OK\r\n\r\n";
+            let reader = ReadHalfWrapperMock {
+                poll_read_results: vec![
+                    (
+                        first_read_result.to_vec(),
+                        Ok(Async::Ready(first_read_result.len())),
+                    ),
+                    (vec![], Err(Error::from(ErrorKind::ConnectionAborted))),
+                ],
+            };
+            let writer = WriteHalfWrapperMock {
+                poll_write_params: write_parameters,
+                poll_write_results: vec![Ok(Async::Ready(first_read_result.len()))],
+                shutdown_results: Arc::new(Mutex::new(vec![])),
+            };
+            let mut subject = StreamHandlerPoolReal::new(
+                Box::new(resolver),
+                cryptde,
+                peer_actors.accountant.report_exit_service_provided.clone(),
+                peer_actors.proxy_client.clone(),
+                100,
+                200,
+                ExitPolicy::default(),
+                None,
+            );
+            let (stream_killer_tx, stream_killer_rx) = mpsc::channel();
+            subject.stream_killer_rx = stream_killer_rx;
+            let (stream_adder_tx, _stream_adder_rx) = mpsc::channel();
+            {
+                let mut inner = subject.inner.lock().unwrap();
+                let establisher = StreamEstablisher {
+                    cryptde,
+                    stream_adder_tx,
+                    stream_killer_tx,
+                    stream_connector: Box::new(StreamConnectorMock::new().with_connection(
+                        peer_addr.clone(),
+                        peer_addr.clone(),
+                        reader,
+                        writer,
+                    )),
+                    proxy_client_sub: inner.proxy_client_subs.inbound_server_data.clone(),
+                    logger: inner.logger.clone(),
+                    channel_factory: Box::new(FuturesChannelFactoryReal {}),
+                    connection_pool_opt: None,
+                };
+
+                inner.establisher_factory = Box::new(StreamEstablisherFactoryMock {
+                    make_results: RefCell::new(vec![establisher]),
+                });
+            }
+
+            run_process_package_in_actix(subject, package);
+        });
+
+        proxy_client_awaiter.await_message_count(1);
+        assert_eq!(
+            expected_lookup_ip_parameters.lock().unwrap().deref(),
+            &vec!["that.try.".to_string()]
+        );
+        assert_eq!(
+            expected_write_parameters.lock().unwrap().remove(0),
+            b"These are the times".to_vec()
+        );
+        let proxy_client_recording = proxy_client_recording_arc.lock().unwrap();
+        assert_eq!(
+            proxy_client_recording.get_record::<InboundServerData>(0),
+            &InboundServerData {
+                stream_key: make_meaningless_stream_key(),
+                last_data: false,
+                sequence_number: 0,
+                source: SocketAddr::from_str("3.4.5.6:80").unwrap(),
+                data: b"HTTP/1.1 200 OK\r\n\r\n".to_vec(),
+            }
+        );
+    }
+
+    fn make_payload(stream_key: StreamKey, sequence_number: u64) -> ClientRequestPayload {
+        ClientRequestPayload {
+            version: ClientRequestPayload::version(),
+            stream_key,
+            sequenced_packet: SequencedPacket {
+                data: b"These are the times".to_vec(),
+                sequence_number,
+                last_data: false,
+            },
+            target_hostname: Some(String::from("that.try")),
+            target_port: HTTP_PORT,
+            protocol: ProxyProtocol::HTTP,
+            originator_public_key: PublicKey::new(&b"men's souls"[..]),
+            fresh_return_route: false,
+            alpn_protocols: vec![],
+            trace_id_opt: None,
+        }
+    }
+
+    #[test]
+    fn packets_arriving_while_a_stream_is_being_established_wait_for_it() {
+        init_test_logging();
+        let cryptde = cryptde();
+        let stream_key = make_meaningless_stream_key();
+        let lookup_ip_parameters = Arc::new(Mutex::new(vec![]));
+        let lookup_ip_parameters_inner = lookup_ip_parameters.clone();
+        let unbounded_send_params = Arc::new(Mutex::new(vec![]));
+        let unbounded_send_params_inner = unbounded_send_params.clone();
+        thread::spawn(move || {
+            let peer_actors = peer_actors_builder().build();
+            // Only one lookup result and one establisher: a second establishment would panic
+            let resolver = ResolverWrapperMock::new()
+                .lookup_ip_parameters(&lookup_ip_parameters_inner)
+                .lookup_ip_success(vec![IpAddr::from_str("3.4.5.6").unwrap()]);
+            let mut subject = StreamHandlerPoolReal::new(
+                Box::new(resolver),
+                cryptde,
+                peer_actors.accountant.report_exit_service_provided.clone(),
+                peer_actors.proxy_client.clone(),
+                100,
+                200,
+                ExitPolicy::default(),
+                None,
+            );
+            let (stream_killer_tx, stream_killer_rx) = mpsc::channel();
+            subject.stream_killer_rx = stream_killer_rx;
+            let (stream_adder_tx, _stream_adder_rx) = mpsc::channel();
+            let peer_addr = SocketAddr::from_str("3.4.5.6:80").unwrap();
+            let reader = ReadHalfWrapperMock {
+                poll_read_results: vec![(vec![], Ok(Async::NotReady))],
+            };
+            let writer = WriteHalfWrapperMock {
+                poll_write_params: Arc::new(Mutex::new(vec![])),
+                poll_write_results: vec![],
+                shutdown_results: Arc::new(Mutex::new(vec![])),
+            };
+            let establisher = StreamEstablisher {
+                cryptde,
+                stream_adder_tx,
+                stream_killer_tx,
+                stream_connector: Box::new(
+                    StreamConnectorMock::new()
+                        .with_connection(peer_addr, peer_addr, reader, writer),
+                ),
+                proxy_client_sub: peer_actors.proxy_client.inbound_server_data.clone(),
+                logger: subject.inner.lock().unwrap().logger.clone(),
+                channel_factory: Box::new(FuturesChannelFactoryMock {
+                    results: vec![(
+                        Box::new(
+                            SenderWrapperMock::new(peer_addr)
+                                .unbounded_send_params(&unbounded_send_params_inner),
+                        ),
+                        Box::new(ReceiverWrapperMock {
+                            poll_results: vec![Ok(Async::NotReady)],
+                        }),
+                    )],
+                }),
+                connection_pool_opt: None,
+            };
+            subject.inner.lock().unwrap().establisher_factory =
+                Box::new(StreamEstablisherFactoryMock {
+                    make_results: RefCell::new(vec![establisher]),
+                });
+
+            actix::run(move || {
+                for sequence_number in 0..3 {
+                    subject.process_package(
+                        make_payload(stream_key, sequence_number),
+                        Some(make_wallet("consuming")),
+                    );
+                }
+                ok(())
+            });
+        });
+
+        await_messages(3, &unbounded_send_params);
+        assert_eq!(
+            *lookup_ip_parameters.lock().unwrap(),
+            vec!["that.try.".to_string()]
+        );
+        let sequence_numbers: Vec<u64> = unbounded_send_params
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|packet: &SequencedPacket| packet.sequence_number)
+            .collect();
+        assert_eq!(sequence_numbers, vec![0, 1, 2]);
+        TestLogHandler::new().exists_log_containing(&format!(
+            "DEBUG: ProxyClient: Releasing 2 packets queued for stream {:?}",
+            stream_key
+        ));
+    }
+
+    #[test]
+    fn pending_stream_is_given_up_when_too_many_packets_wait_for_it() {
+        init_test_logging();
+        let stream_key = make_meaningless_stream_key();
+        let (proxy_client, proxy_client_awaiter, proxy_client_recording_arc) = make_recorder();
+        let (pending_tx, pending_rx) = mpsc::channel();
+        thread::spawn(move || {
+            let peer_actors = peer_actors_builder().proxy_client(proxy_client).build();
+            let subject = StreamHandlerPoolReal::new(
+                Box::new(ResolverWrapperMock::new()),
+                cryptde(),
+                peer_actors.accountant.report_exit_service_provided.clone(),
+                peer_actors.proxy_client.clone(),
+                100,
+                200,
+                ExitPolicy::default(),
+                None,
+            );
+            subject.inner.lock().unwrap().pending_streams.insert(
+                stream_key,
+                PendingStream {
+                    establishment_id: 1,
+                    given_up: false,
+                    queued_packets: (0..PENDING_STREAM_PACKET_LIMIT)
+                        .map(|n| (make_payload(stream_key, n as u64 + 1), None))
+                        .collect(),
+                },
+            );
+            let inner_arc = subject.inner.clone();
+
+            actix::run(move || {
+                subject.process_package(
+                    make_payload(stream_key, PENDING_STREAM_PACKET_LIMIT as u64 + 1),
+                    None,
+                );
+                subject.process_package(
+                    make_payload(stream_key, PENDING_STREAM_PACKET_LIMIT as u64 + 2),
+                    None,
+                );
+                let inner = inner_arc.lock().unwrap();
+                let pending = &inner.pending_streams[&stream_key];
+                pending_tx
+                    .send((pending.given_up, pending.queued_packets.len()))
+                    .unwrap();
+                ok(())
+            });
+        });
+
+        proxy_client_awaiter.await_message_count(1);
+        assert_eq!(pending_rx.recv().unwrap(), (true, 0));
+        let proxy_client_recording = proxy_client_recording_arc.lock().unwrap();
+        assert_eq!(
+            proxy_client_recording.get_record::<InboundServerData>(0),
+            &InboundServerData {
+                stream_key,
+                last_data: true,
+                sequence_number: 0,
+                source: error_socket_addr(),
+                data: vec![],
+            }
+        );
+        assert_eq!(proxy_client_recording.len(), 1);
+        let tlh = TestLogHandler::new();
+        tlh.exists_log_containing(&format!(
+            "ERROR: ProxyClient: Couldn't process request from CORES package: More than {} packets arrived for stream {:?} while it was being established",
+            PENDING_STREAM_PACKET_LIMIT,
+            stream_key
+        ));
+        tlh.exists_log_containing(&format!(
+            "DEBUG: ProxyClient: Discarding packet {} for stream {:?}, which was given up",
+            PENDING_STREAM_PACKET_LIMIT + 2,
+            stream_key
+        ));
+    }
+
+    #[test]
+    fn pending_stream_is_given_up_when_it_takes_too_long_to_establish_and_never_connects() {
+        init_test_logging();
+        let cryptde = cryptde();
+        let stream_key = make_meaningless_stream_key();
+        let (proxy_client, proxy_client_awaiter, proxy_client_recording_arc) = make_recorder();
+        thread::spawn(move || {
+            let peer_actors = peer_actors_builder().proxy_client(proxy_client).build();
+            let resolver = ResolverWrapperMock::new().lookup_ip_delayed_success(
+                vec![IpAddr::from_str("3.4.5.6").unwrap()],
+                Duration::from_millis(200),
+            );
+            let subject = StreamHandlerPoolReal::new(
+                Box::new(resolver),
+                cryptde,
+                peer_actors.accountant.report_exit_service_provided.clone(),
+                peer_actors.proxy_client.clone(),
+                100,
+                200,
+                ExitPolicy::default(),
+                None,
+            );
+            // With no connections to make, the establisher would fail loudly if it were used
+            let establisher = StreamEstablisher {
+                cryptde,
+                stream_adder_tx: mpsc::channel().0,
+                stream_killer_tx: mpsc::channel().0,
+                stream_connector: Box::new(StreamConnectorMock::new()),
+                proxy_client_sub: peer_actors.proxy_client.inbound_server_data.clone(),
+                logger: subject.inner.lock().unwrap().logger.clone(),
+                channel_factory: Box::new(FuturesChannelFactoryMock::default()),
+                connection_pool_opt: None,
+            };
+            {
+                let mut inner = subject.inner.lock().unwrap();
+                inner.establisher_factory = Box::new(StreamEstablisherFactoryMock {
+                    make_results: RefCell::new(vec![establisher]),
+                });
+                inner.pending_stream_timeout = Duration::from_millis(50);
+            }
+
+            actix::run(move || {
+                subject.process_package(make_payload(stream_key, 0), None);
+                ok(())
+            });
+        });
+
+        proxy_client_awaiter.await_message_count(1);
+        let tlh = TestLogHandler::new();
+        tlh.await_log_containing(
+            &format!(
+                "DEBUG: ProxyClient: Stream {:?} was given up while it was being established",
+                stream_key
+            ),
+            1000,
+        );
+        tlh.exists_log_containing(&format!(
+            "ERROR: ProxyClient: Couldn't process request from CORES package: Stream {:?} was still not established after 50ms",
+            stream_key
+        ));
+        let proxy_client_recording = proxy_client_recording_arc.lock().unwrap();
+        assert_eq!(proxy_client_recording.len(), 1);
+        let result = proxy_client_recording.get_record::<InboundServerData>(0);
+        assert_eq!(result.last_data, true);
+        assert_eq!(result.source, error_socket_addr());
+    }
+
+    #[test]
+    fn connection_made_for_a_given_up_stream_is_dropped_instead_of_registered() {
+        let _system =
+            System::new("connection_made_for_a_given_up_stream_is_dropped_instead_of_registered");
+        let peer_actors = peer_actors_builder().build();
+        let mut subject = StreamHandlerPoolReal::new(
+            Box::new(ResolverWrapperMock::new()),
+            cryptde(),
+            peer_actors.accountant.report_exit_service_provided.clone(),
+            peer_actors.proxy_client.clone(),
+            100,
+            200,
+            ExitPolicy::default(),
+            None,
+        );
+        let stream_key = make_meaningless_stream_key();
+        let (stream_adder_tx, stream_adder_rx) = mpsc::channel();
+        subject.stream_adder_rx = stream_adder_rx;
+        subject.inner.lock().unwrap().pending_streams.insert(
+            stream_key,
+            PendingStream {
+                establishment_id: 1,
+                given_up: true,
+                queued_packets: vec![],
+            },
+        );
+        stream_adder_tx
+            .send((
+                stream_key,
+                Box::new(SenderWrapperMock::new(
+                    SocketAddr::from_str("3.4.5.6:80").unwrap(),
+                )) as Box<dyn SenderWrapper<SequencedPacket>>,
+            ))
+            .unwrap();
+
+        subject.add_new_streams();
+
+        let inner = subject.inner.lock().unwrap();
+        assert!(inner.stream_writer_channels.is_empty());
+        assert!(inner.pending_streams.is_empty());
+    }
+
+    #[test]
+    fn failing_to_make_a_connection_sends_an_error_response() {
+        let cryptde = cryptde();
+        let stream_key = make_meaningless_stream_key();
+        let lookup_ip_parameters = Arc::new(Mutex::new(vec![]));
+        let (proxy_client, proxy_client_awaiter, proxy_client_recording_arc) = make_recorder();
+        let originator_key = PublicKey::new(&b"men's souls"[..]);
+        thread::spawn(move || {
+            let peer_actors = peer_actors_builder().proxy_client(proxy_client).build();
+            let client_request_payload = ClientRequestPayload {
+                version: ClientRequestPayload::version(),
+                stream_key,
+                sequenced_packet: SequencedPacket {
+                    data: b"These are the times".to_vec(),
+                    sequence_number: 0,
+                    last_data: false,
+                },
+                target_hostname: Some(String::from("that.try")),
+                target_port: HTTP_PORT,
+                protocol: ProxyProtocol::HTTP,
+                originator_public_key: originator_key,
+                fresh_return_route: false,
+                alpn_protocols: vec![],
+                trace_id_opt: None,
+            };
+            let package = ExpiredCoresPackage::new(
+                SocketAddr::from_str("1.2.3.4:1234").unwrap(),
+                Some(make_wallet("consuming")),
+                make_meaningless_route(),
+                client_request_payload.into(),
+                0,
+            );
+            let resolver = ResolverWrapperMock::new()
+                .lookup_ip_parameters(&lookup_ip_parameters)
+                .lookup_ip_success(vec![
+                    IpAddr::from_str("2.3.4.5").unwrap(),
+                    IpAddr::from_str("3.4.5.6").unwrap(),
+                ]);
+            let proxy_client_sub = peer_actors.proxy_client.inbound_server_data.clone();
+            let mut subject = StreamHandlerPoolReal::new(
+                Box::new(resolver),
+                cryptde,
+                peer_actors.accountant.report_exit_service_provided.clone(),
+                peer_actors.proxy_client.clone(),
+                100,
+                200,
+                ExitPolicy::default(),
+                None,
             );
+            let (stream_killer_tx, stream_killer_rx) = mpsc::channel();
+            subject.stream_killer_rx = stream_killer_rx;
+            let (stream_adder_tx, _stream_adder_rx) = mpsc::channel();
+            let establisher = StreamEstablisher {
+                cryptde,
+                stream_adder_tx,
+                stream_killer_tx,
+                stream_connector: Box::new(
+                    StreamConnectorMock::new()
+                        .connect_pair_result(Err(Error::from(ErrorKind::Other))),
+                ),
+                proxy_client_sub,
+                logger: subject.inner.lock().unwrap().logger.clone(),
+                channel_factory: Box::new(FuturesChannelFactoryReal {}),
+                connection_pool_opt: None,
+            };
+
+            subject.inner.lock().unwrap().establisher_factory =
+                Box::new(StreamEstablisherFactoryMock {
+                    make_results: RefCell::new(vec![establisher]),
+                });
 
             run_process_package_in_actix(subject, package);
         });
@@ -1003,27 +2470,22 @@ mod tests {
         assert_eq!(
             proxy_client_recording.get_record::<InboundServerData>(0),
             &InboundServerData {
-                stream_key: make_meaningless_stream_key(),
+                stream_key,
                 last_data: true,
                 sequence_number: 0,
                 source: error_socket_addr(),
-                data: vec![],
+                data: ServerImpersonatorHttp {}.server_connection_failure_response(
+                    Some("that.try".to_string()),
+                    ServerConnectionFailure::Other
+                ),
             }
         );
-        TestLogHandler::new().exists_log_containing(
-            format!(
-                "ERROR: ProxyClient: Cannot open new stream with key {:?}: no hostname supplied",
-                make_meaningless_stream_key()
-            )
-            .as_str(),
-        );
     }
 
     #[test]
-    fn nonexistent_connection_springs_into_being_and_is_persisted_to_handle_transaction() {
+    fn idempotent_request_is_retried_on_a_new_connection_when_the_first_is_refused() {
         let cryptde = cryptde();
-        let lookup_ip_parameters = Arc::new(Mutex::new(vec![]));
-        let expected_lookup_ip_parameters = lookup_ip_parameters.clone();
+        let stream_key = make_meaningless_stream_key();
         let write_parameters = Arc::new(Mutex::new(vec![]));
         let expected_write_parameters = write_parameters.clone();
         let (proxy_client, proxy_client_awaiter, proxy_client_recording_arc) = make_recorder();
@@ -1031,16 +2493,19 @@ mod tests {
             let peer_actors = peer_actors_builder().proxy_client(proxy_client).build();
             let client_request_payload = ClientRequestPayload {
                 version: ClientRequestPayload::version(),
-                stream_key: make_meaningless_stream_key(),
+                stream_key,
                 sequenced_packet: SequencedPacket {
-                    data: b"These are the times".to_vec(),
+                    data: b"GET /index.html HTTP/1.1\r\n\r\n".to_vec(),
                     sequence_number: 0,
                     last_data: false,
                 },
-                target_hostname: Some(String::from("that.try")),
+                target_hostname: Some(String::from("3.4.5.6")),
                 target_port: HTTP_PORT,
                 protocol: ProxyProtocol::HTTP,
                 originator_public_key: PublicKey::new(&b"men's souls"[..]),
+                fresh_return_route: false,
+                alpn_protocols: vec![],
+                trace_id_opt: None,
             };
             let package = ExpiredCoresPackage::new(
                 SocketAddr::from_str("1.2.3.4:1234").unwrap(),
@@ -1049,58 +2514,62 @@ mod tests {
                 client_request_payload.into(),
                 0,
             );
-            let resolver = ResolverWrapperMock::new()
-                .lookup_ip_parameters(&lookup_ip_parameters)
-                .lookup_ip_success(vec![
-                    IpAddr::from_str("2.3.4.5").unwrap(),
-                    IpAddr::from_str("3.4.5.6").unwrap(),
-                ]);
             let peer_addr = SocketAddr::from_str("3.4.5.6:80").unwrap();
-            let first_read_result = b"HTTP/1.1 200 OK\r\n\r\n";
+            let response = b"HTTP/1.1 200 OK\r\n\r\n";
             let reader = ReadHalfWrapperMock {
                 poll_read_results: vec![
-                    (
-                        first_read_result.to_vec(),
-                        Ok(Async::Ready(first_read_result.len())),
-                    ),
+                    (response.to_vec(), Ok(Async::Ready(response.len()))),
                     (vec![], Err(Error::from(ErrorKind::ConnectionAborted))),
                 ],
             };
             let writer = WriteHalfWrapperMock {
                 poll_write_params: write_parameters,
-                poll_write_results: vec![Ok(Async::Ready(first_read_result.len()))],
+                poll_write_results: vec![Ok(Async::Ready(response.len()))],
                 shutdown_results: Arc::new(Mutex::new(vec![])),
             };
             let mut subject = StreamHandlerPoolReal::new(
-                Box::new(resolver),
+                Box::new(ResolverWrapperMock::new()),
                 cryptde,
                 peer_actors.accountant.report_exit_service_provided.clone(),
                 peer_actors.proxy_client.clone(),
                 100,
                 200,
+                ExitPolicy::default(),
+                None,
             );
             let (stream_killer_tx, stream_killer_rx) = mpsc::channel();
             subject.stream_killer_rx = stream_killer_rx;
             let (stream_adder_tx, _stream_adder_rx) = mpsc::channel();
             {
                 let mut inner = subject.inner.lock().unwrap();
-                let establisher = StreamEstablisher {
+                let refused_establisher = StreamEstablisher {
+                    cryptde,
+                    stream_adder_tx: stream_adder_tx.clone(),
+                    stream_killer_tx: stream_killer_tx.clone(),
+                    stream_connector: Box::new(
+                        StreamConnectorMock::new()
+                            .connect_pair_result(Err(Error::from(ErrorKind::ConnectionRefused))),
+                    ),
+                    proxy_client_sub: inner.proxy_client_subs.inbound_server_data.clone(),
+                    logger: inner.logger.clone(),
+                    channel_factory: Box::new(FuturesChannelFactoryReal {}),
+                    connection_pool_opt: None,
+                };
+                let successful_establisher = StreamEstablisher {
                     cryptde,
                     stream_adder_tx,
                     stream_killer_tx,
-                    stream_connector: Box::new(StreamConnectorMock::new().with_connection(
-                        peer_addr.clone(),
-                        peer_addr.clone(),
-                        reader,
-                        writer,
-                    )),
+                    stream_connector: Box::new(
+                        StreamConnectorMock::new()
+                            .with_connection(peer_addr, peer_addr, reader, writer),
+                    ),
                     proxy_client_sub: inner.proxy_client_subs.inbound_server_data.clone(),
                     logger: inner.logger.clone(),
                     channel_factory: Box::new(FuturesChannelFactoryReal {}),
+                    connection_pool_opt: None,
                 };
-
                 inner.establisher_factory = Box::new(StreamEstablisherFactoryMock {
-                    make_results: RefCell::new(vec![establisher]),
+                    make_results: RefCell::new(vec![refused_establisher, successful_establisher]),
                 });
             }
 
@@ -1108,19 +2577,15 @@ mod tests {
         });
 
         proxy_client_awaiter.await_message_count(1);
-        assert_eq!(
-            expected_lookup_ip_parameters.lock().unwrap().deref(),
-            &vec!["that.try.".to_string()]
-        );
         assert_eq!(
             expected_write_parameters.lock().unwrap().remove(0),
-            b"These are the times".to_vec()
+            b"GET /index.html HTTP/1.1\r\n\r\n".to_vec()
         );
         let proxy_client_recording = proxy_client_recording_arc.lock().unwrap();
         assert_eq!(
             proxy_client_recording.get_record::<InboundServerData>(0),
             &InboundServerData {
-                stream_key: make_meaningless_stream_key(),
+                stream_key,
                 last_data: false,
                 sequence_number: 0,
                 source: SocketAddr::from_str("3.4.5.6:80").unwrap(),
@@ -1130,26 +2595,30 @@ mod tests {
     }
 
     #[test]
-    fn failing_to_make_a_connection_sends_an_error_response() {
+    fn idempotent_request_partway_through_a_stream_is_retried_where_the_stream_left_off() {
         let cryptde = cryptde();
         let stream_key = make_meaningless_stream_key();
-        let lookup_ip_parameters = Arc::new(Mutex::new(vec![]));
+        let write_parameters = Arc::new(Mutex::new(vec![]));
+        let expected_write_parameters = write_parameters.clone();
         let (proxy_client, proxy_client_awaiter, proxy_client_recording_arc) = make_recorder();
-        let originator_key = PublicKey::new(&b"men's souls"[..]);
         thread::spawn(move || {
             let peer_actors = peer_actors_builder().proxy_client(proxy_client).build();
+            let sequenced_packet = SequencedPacket {
+                data: b"GET /second.html HTTP/1.1\r\n\r\n".to_vec(),
+                sequence_number: 2,
+                last_data: false,
+            };
             let client_request_payload = ClientRequestPayload {
                 version: ClientRequestPayload::version(),
                 stream_key,
-                sequenced_packet: SequencedPacket {
-                    data: b"These are the times".to_vec(),
-                    sequence_number: 0,
-                    last_data: false,
-                },
-                target_hostname: Some(String::from("that.try")),
+                sequenced_packet: sequenced_packet.clone(),
+                target_hostname: Some(String::from("3.4.5.6")),
                 target_port: HTTP_PORT,
                 protocol: ProxyProtocol::HTTP,
-                originator_public_key: originator_key,
+                originator_public_key: PublicKey::new(&b"men's souls"[..]),
+                fresh_return_route: false,
+                alpn_protocols: vec![],
+                trace_id_opt: None,
             };
             let package = ExpiredCoresPackage::new(
                 SocketAddr::from_str("1.2.3.4:1234").unwrap(),
@@ -1158,55 +2627,77 @@ mod tests {
                 client_request_payload.into(),
                 0,
             );
-            let resolver = ResolverWrapperMock::new()
-                .lookup_ip_parameters(&lookup_ip_parameters)
-                .lookup_ip_success(vec![
-                    IpAddr::from_str("2.3.4.5").unwrap(),
-                    IpAddr::from_str("3.4.5.6").unwrap(),
-                ]);
-            let proxy_client_sub = peer_actors.proxy_client.inbound_server_data.clone();
-            let mut subject = StreamHandlerPoolReal::new(
-                Box::new(resolver),
+            let peer_addr = SocketAddr::from_str("3.4.5.6:80").unwrap();
+            let response = b"HTTP/1.1 200 OK\r\n\r\n";
+            let reader = ReadHalfWrapperMock {
+                poll_read_results: vec![
+                    (response.to_vec(), Ok(Async::Ready(response.len()))),
+                    (vec![], Ok(Async::NotReady)),
+                ],
+            };
+            let writer = WriteHalfWrapperMock {
+                poll_write_params: write_parameters,
+                poll_write_results: vec![Ok(Async::Ready(sequenced_packet.data.len()))],
+                shutdown_results: Arc::new(Mutex::new(vec![])),
+            };
+            let subject = StreamHandlerPoolReal::new(
+                Box::new(ResolverWrapperMock::new()),
                 cryptde,
                 peer_actors.accountant.report_exit_service_provided.clone(),
                 peer_actors.proxy_client.clone(),
                 100,
                 200,
+                ExitPolicy::default(),
+                None,
             );
-            let (stream_killer_tx, stream_killer_rx) = mpsc::channel();
-            subject.stream_killer_rx = stream_killer_rx;
-            let (stream_adder_tx, _stream_adder_rx) = mpsc::channel();
-            let establisher = StreamEstablisher {
-                cryptde,
-                stream_adder_tx,
-                stream_killer_tx,
-                stream_connector: Box::new(
-                    StreamConnectorMock::new()
-                        .connect_pair_result(Err(Error::from(ErrorKind::Other))),
-                ),
-                proxy_client_sub,
-                logger: subject.inner.lock().unwrap().logger.clone(),
-                channel_factory: Box::new(FuturesChannelFactoryReal {}),
-            };
-
-            subject.inner.lock().unwrap().establisher_factory =
-                Box::new(StreamEstablisherFactoryMock {
+            {
+                let mut inner = subject.inner.lock().unwrap();
+                inner.stream_writer_channels.insert(
+                    stream_key,
+                    Box::new(
+                        SenderWrapperMock::new(peer_addr)
+                            .unbounded_send_result(make_send_error(sequenced_packet)),
+                    ),
+                );
+                inner
+                    .response_sequencers
+                    .insert(stream_key, Arc::new(Mutex::new(Sequencer::starting_at(3))));
+                let (stream_adder_tx, _stream_adder_rx) = mpsc::channel();
+                let establisher = StreamEstablisher {
+                    cryptde,
+                    stream_adder_tx,
+                    stream_killer_tx: mpsc::channel().0,
+                    stream_connector: Box::new(
+                        StreamConnectorMock::new()
+                            .with_connection(peer_addr, peer_addr, reader, writer),
+                    ),
+                    proxy_client_sub: inner.proxy_client_subs.inbound_server_data.clone(),
+                    logger: inner.logger.clone(),
+                    channel_factory: Box::new(FuturesChannelFactoryReal {}),
+                    connection_pool_opt: None,
+                };
+                inner.establisher_factory = Box::new(StreamEstablisherFactoryMock {
                     make_results: RefCell::new(vec![establisher]),
                 });
+            }
 
             run_process_package_in_actix(subject, package);
         });
 
         proxy_client_awaiter.await_message_count(1);
+        assert_eq!(
+            expected_write_parameters.lock().unwrap().remove(0),
+            b"GET /second.html HTTP/1.1\r\n\r\n".to_vec()
+        );
         let proxy_client_recording = proxy_client_recording_arc.lock().unwrap();
         assert_eq!(
             proxy_client_recording.get_record::<InboundServerData>(0),
             &InboundServerData {
                 stream_key,
-                last_data: true,
-                sequence_number: 0,
-                source: error_socket_addr(),
-                data: vec![],
+                last_data: false,
+                sequence_number: 3,
+                source: SocketAddr::from_str("3.4.5.6:80").unwrap(),
+                data: b"HTTP/1.1 200 OK\r\n\r\n".to_vec(),
             }
         );
     }
@@ -1237,6 +2728,9 @@ mod tests {
                 target_port: HTTP_PORT,
                 protocol: ProxyProtocol::HTTP,
                 originator_public_key: PublicKey::new(&b"men's souls"[..]),
+                fresh_return_route: false,
+                alpn_protocols: vec![],
+                trace_id_opt: None,
             };
 
             let package = ExpiredCoresPackage::new(
@@ -1273,6 +2767,8 @@ mod tests {
                 peer_actors.proxy_client.clone(),
                 100,
                 200,
+                ExitPolicy::default(),
+                None,
             );
 
             let peer_addr = SocketAddr::from_str("3.4.5.6:80").unwrap();
@@ -1304,6 +2800,7 @@ mod tests {
                             }),
                         )],
                     }),
+                    connection_pool_opt: None,
                 };
 
                 inner.establisher_factory = Box::new(StreamEstablisherFactoryMock {
@@ -1322,7 +2819,10 @@ mod tests {
                 last_data: true,
                 sequence_number: 0,
                 source: error_socket_addr(),
-                data: vec![],
+                data: ServerImpersonatorHttp {}.server_connection_failure_response(
+                    Some("that.try".to_string()),
+                    ServerConnectionFailure::Reset
+                ),
             }
         );
     }
@@ -1347,6 +2847,9 @@ mod tests {
                 target_port: HTTP_PORT,
                 protocol: ProxyProtocol::HTTP,
                 originator_public_key: originator_key,
+                fresh_return_route: false,
+                alpn_protocols: vec![],
+                trace_id_opt: None,
             };
             let package = ExpiredCoresPackage::new(
                 SocketAddr::from_str("1.2.3.4:1234").unwrap(),
@@ -1367,6 +2870,8 @@ mod tests {
                 peer_actors.proxy_client.clone(),
                 100,
                 200,
+                ExitPolicy::default(),
+                None,
             );
             run_process_package_in_actix(subject, package);
         });
@@ -1393,7 +2898,7 @@ mod tests {
         init_test_logging();
         let cryptde = cryptde();
         let stream_key = make_meaningless_stream_key();
-        let (proxy_client, _, _) = make_recorder();
+        let (proxy_client, proxy_client_awaiter, proxy_client_recording_arc) = make_recorder();
         let (hopper, _, _) = make_recorder();
         let (accountant, _, _) = make_recorder();
         let sequenced_packet = SequencedPacket {
@@ -1409,6 +2914,9 @@ mod tests {
             target_port: HTTP_PORT,
             protocol: ProxyProtocol::HTTP,
             originator_public_key: PublicKey::new(&b"men's souls"[..]),
+            fresh_return_route: false,
+            alpn_protocols: vec![],
+            trace_id_opt: None,
         };
         let package = ExpiredCoresPackage::new(
             SocketAddr::from_str("1.2.3.4:1234").unwrap(),
@@ -1436,6 +2944,8 @@ mod tests {
                 peer_actors.proxy_client.clone(),
                 100,
                 200,
+                ExitPolicy::default(),
+                None,
             );
             subject
                 .inner
@@ -1452,6 +2962,21 @@ mod tests {
 
         let tlh = TestLogHandler::new();
         tlh.await_log_containing("Removing stream writer for 1.2.3.4:5678", 1000);
+        proxy_client_awaiter.await_message_count(1);
+        let proxy_client_recording = proxy_client_recording_arc.lock().unwrap();
+        assert_eq!(
+            proxy_client_recording.get_record::<InboundServerData>(0),
+            &InboundServerData {
+                stream_key,
+                last_data: true,
+                sequence_number: 0,
+                source: SocketAddr::from_str("1.2.3.4:5678").unwrap(),
+                data: ServerImpersonatorHttp {}.server_connection_failure_response(
+                    Some("that.try".to_string()),
+                    ServerConnectionFailure::Reset
+                ),
+            }
+        );
     }
 
     #[test]
@@ -1478,6 +3003,9 @@ mod tests {
                 target_port: HTTP_PORT,
                 protocol: ProxyProtocol::HTTP,
                 originator_public_key: PublicKey::new(&b"booga"[..]),
+                fresh_return_route: false,
+                alpn_protocols: vec![],
+                trace_id_opt: None,
             };
             let package = ExpiredCoresPackage::new(
                 SocketAddr::from_str("1.2.3.4:1234").unwrap(),
@@ -1494,6 +3022,8 @@ mod tests {
                 peer_actors.proxy_client.clone(),
                 100,
                 200,
+                ExitPolicy::default(),
+                None,
             );
 
             subject.inner.lock().unwrap().establisher_factory =
@@ -1530,6 +3060,8 @@ mod tests {
             peer_actors.proxy_client,
             0,
             0,
+            ExitPolicy::default(),
+            None,
         );
         let (stream_killer_tx, stream_killer_rx) = mpsc::channel();
         subject.stream_killer_rx = stream_killer_rx;
@@ -1541,7 +3073,9 @@ mod tests {
                 .stream_writer_channels
                 .insert(stream_key, Box::new(SenderWrapperMock::new(peer_addr)));
         }
-        stream_killer_tx.send((stream_key, 47)).unwrap();
+        stream_killer_tx
+            .send((stream_key, Arc::new(Mutex::new(Sequencer::starting_at(47)))))
+            .unwrap();
 
         subject.clean_up_dead_streams();
 
@@ -1573,11 +3107,55 @@ mod tests {
             peer_actors.proxy_client,
             0,
             0,
+            ExitPolicy::default(),
+            None,
         );
         let (stream_killer_tx, stream_killer_rx) = mpsc::channel();
         subject.stream_killer_rx = stream_killer_rx;
         let stream_key = make_meaningless_stream_key();
-        stream_killer_tx.send((stream_key, 47)).unwrap();
+        stream_killer_tx
+            .send((stream_key, Arc::new(Mutex::new(Sequencer::starting_at(47)))))
+            .unwrap();
+
+        subject.clean_up_dead_streams();
+
+        System::current().stop_with_code(0);
+        system.run();
+        let proxy_client_recording = proxy_client_recording_arc.lock().unwrap();
+        assert_eq!(proxy_client_recording.len(), 0);
+    }
+
+    #[test]
+    fn clean_up_dead_streams_ignores_the_old_connection_of_a_reconnected_stream() {
+        let system = System::new("test");
+        let (proxy_client, _, proxy_client_recording_arc) = make_recorder();
+        let peer_actors = peer_actors_builder().proxy_client(proxy_client).build();
+        let mut subject = StreamHandlerPoolReal::new(
+            Box::new(ResolverWrapperMock::new()),
+            cryptde(),
+            peer_actors.accountant.report_exit_service_provided,
+            peer_actors.proxy_client,
+            0,
+            0,
+            ExitPolicy::default(),
+            None,
+        );
+        let (stream_killer_tx, stream_killer_rx) = mpsc::channel();
+        subject.stream_killer_rx = stream_killer_rx;
+        let stream_key = make_meaningless_stream_key();
+        let peer_addr = SocketAddr::from_str("1.2.3.4:5678").unwrap();
+        {
+            let mut inner = subject.inner.lock().unwrap();
+            inner
+                .stream_writer_channels
+                .insert(stream_key, Box::new(SenderWrapperMock::new(peer_addr)));
+            inner
+                .response_sequencers
+                .insert(stream_key, Arc::new(Mutex::new(Sequencer::starting_at(47))));
+        }
+        stream_killer_tx
+            .send((stream_key, Arc::new(Mutex::new(Sequencer::starting_at(47)))))
+            .unwrap();
 
         subject.clean_up_dead_streams();
 
@@ -1585,5 +3163,8 @@ mod tests {
         system.run();
         let proxy_client_recording = proxy_client_recording_arc.lock().unwrap();
         assert_eq!(proxy_client_recording.len(), 0);
+        let inner = subject.inner.lock().unwrap();
+        assert!(inner.stream_writer_channels.contains_key(&stream_key));
+        assert!(inner.response_sequencers.contains_key(&stream_key));
     }
 }
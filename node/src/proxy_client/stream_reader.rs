@@ -1,5 +1,6 @@
 // Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
 use crate::sub_lib::logger::Logger;
+use crate::sub_lib::privacy;
 use crate::sub_lib::proxy_client::InboundServerData;
 use crate::sub_lib::sequencer::Sequencer;
 use crate::sub_lib::stream_key::StreamKey;
@@ -9,6 +10,7 @@ use crate::sub_lib::utils::indicates_dead_stream;
 use actix::Recipient;
 use std::net::SocketAddr;
 use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex};
 use tokio::prelude::Async;
 use tokio::prelude::Future;
 
@@ -16,10 +18,10 @@ pub struct StreamReader {
     stream_key: StreamKey,
     proxy_client_sub: Recipient<InboundServerData>,
     stream: Box<dyn ReadHalfWrapper>,
-    stream_killer: Sender<(StreamKey, u64)>,
+    stream_killer: Sender<(StreamKey, Arc<Mutex<Sequencer>>)>,
     peer_addr: SocketAddr,
     logger: Logger,
-    sequencer: Sequencer,
+    sequencer: Arc<Mutex<Sequencer>>,
 }
 
 impl Future for StreamReader {
@@ -35,7 +37,8 @@ impl Future for StreamReader {
                     // see RETURN VALUE section of recv man page (Unix)
                     debug!(
                         self.logger,
-                        "Stream from {} was closed: (0-byte read)", self.peer_addr
+                        "Stream from {} was closed: (0-byte read)",
+                        privacy::socket_addr(self.peer_addr)
                     );
                     self.shutdown();
                     return Ok(Async::Ready(()));
@@ -46,8 +49,8 @@ impl Future for StreamReader {
                             self.logger,
                             "Read {}-byte chunk from {}: {}",
                             len,
-                            self.peer_addr,
-                            utils::to_string(&Vec::from(&buf[0..len]))
+                            privacy::socket_addr(self.peer_addr),
+                            privacy::data(utils::to_string(&Vec::from(&buf[0..len])))
                         );
                     }
                     let stream_key = self.stream_key;
@@ -57,7 +60,9 @@ impl Future for StreamReader {
                     if indicates_dead_stream(e.kind()) {
                         debug!(
                             self.logger,
-                            "Stream from {} was closed: {}", self.peer_addr, e
+                            "Stream from {} was closed: {}",
+                            privacy::socket_addr(self.peer_addr),
+                            e
                         );
                         self.shutdown();
                         return Err(());
@@ -66,7 +71,7 @@ impl Future for StreamReader {
                         warning!(
                             self.logger,
                             "Continuing after read error on stream from {}: {}",
-                            self.peer_addr,
+                            privacy::socket_addr(self.peer_addr),
                             e
                         );
                     }
@@ -81,8 +86,9 @@ impl StreamReader {
         stream_key: StreamKey,
         proxy_client_sub: Recipient<InboundServerData>,
         stream: Box<dyn ReadHalfWrapper>,
-        stream_killer: Sender<(StreamKey, u64)>,
+        stream_killer: Sender<(StreamKey, Arc<Mutex<Sequencer>>)>,
         peer_addr: SocketAddr,
+        sequencer: Arc<Mutex<Sequencer>>,
     ) -> StreamReader {
         StreamReader {
             stream_key,
@@ -91,14 +97,16 @@ impl StreamReader {
             stream_killer,
             peer_addr,
             logger: Logger::new(&format!("StreamReader for {:?}/{}", stream_key, peer_addr)[..]),
-            sequencer: Sequencer::new(),
+            sequencer,
         }
     }
 
+    // The sequencer goes along so that the StreamHandlerPool can number the report of the
+    // server's hangup, and can tell this reader from one that has replaced it.
     fn shutdown(&mut self) {
         let _ = self
             .stream_killer
-            .send((self.stream_key, self.sequencer.next_sequence_number()));
+            .send((self.stream_key, self.sequencer.clone()));
     }
 
     fn send_inbound_server_data(&mut self, stream_key: StreamKey, data: Vec<u8>, last_data: bool) {
@@ -106,7 +114,11 @@ impl StreamReader {
             .try_send(InboundServerData {
                 stream_key,
                 last_data,
-                sequence_number: self.sequencer.next_sequence_number(),
+                sequence_number: self
+                    .sequencer
+                    .lock()
+                    .expect("Sequencer is poisoned")
+                    .next_sequence_number(),
                 source: self.peer_addr,
                 data,
             })
@@ -178,7 +190,7 @@ mod tests {
             stream_killer,
             peer_addr: SocketAddr::from_str("8.7.4.3:50").unwrap(),
             logger: Logger::new("test"),
-            sequencer: Sequencer::new(),
+            sequencer: Arc::new(Mutex::new(Sequencer::new())),
         };
 
         let _res = subject.poll();
@@ -215,8 +227,9 @@ mod tests {
                 data: b"4 File not found\r\n\r\nHTTP/1.1 503 Server error\r\n\r\n".to_vec()
             },
         );
-        let stream_killer_parameters = stream_killer_params.try_recv().unwrap();
-        assert_eq!(stream_killer_parameters, (make_meaningless_stream_key(), 3));
+        let (stream_key, sequencer) = stream_killer_params.try_recv().unwrap();
+        assert_eq!(stream_key, make_meaningless_stream_key());
+        assert_eq!(sequencer.lock().unwrap().next_sequence_number(), 3);
     }
 
     #[test]
@@ -258,7 +271,7 @@ mod tests {
             stream_killer,
             peer_addr: SocketAddr::from_str("5.7.9.0:95").unwrap(),
             logger: Logger::new("test"),
-            sequencer: Sequencer::new(),
+            sequencer: Arc::new(Mutex::new(Sequencer::new())),
         };
 
         let result = subject.poll();
@@ -297,10 +310,11 @@ mod tests {
             }
         );
 
-        let kill_stream_msg = stream_killer_params
+        let (stream_key, sequencer) = stream_killer_params
             .try_recv()
             .expect("stream was not killed");
-        assert_eq!(kill_stream_msg, (make_meaningless_stream_key(), 3));
+        assert_eq!(stream_key, make_meaningless_stream_key());
+        assert_eq!(sequencer.lock().unwrap().next_sequence_number(), 3);
         assert!(stream_killer_params.try_recv().is_err());
     }
 
@@ -325,7 +339,7 @@ mod tests {
             stream_killer,
             peer_addr: SocketAddr::from_str("5.3.4.3:654").unwrap(),
             logger: Logger::new("test"),
-            sequencer,
+            sequencer: Arc::new(Mutex::new(sequencer)),
         };
         System::current().stop_with_code(0);
         system.run();
@@ -333,7 +347,9 @@ mod tests {
         let result = subject.poll();
 
         assert_eq!(result, Ok(Async::Ready(())));
-        assert_eq!(kill_stream_params.try_recv().unwrap(), (stream_key, 2));
+        let (killed_stream_key, sequencer) = kill_stream_params.try_recv().unwrap();
+        assert_eq!(killed_stream_key, stream_key);
+        assert_eq!(sequencer.lock().unwrap().next_sequence_number(), 2);
         TestLogHandler::new()
             .exists_log_containing("Stream from 5.3.4.3:654 was closed: (0-byte read)");
     }
@@ -373,7 +389,7 @@ mod tests {
             stream_killer,
             peer_addr: SocketAddr::from_str("6.5.4.1:8325").unwrap(),
             logger: Logger::new("test"),
-            sequencer: Sequencer::new(),
+            sequencer: Arc::new(Mutex::new(Sequencer::new())),
         };
 
         let result = subject.poll();
@@ -1,6 +1,7 @@
 // Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
 use crate::sub_lib::channel_wrappers::ReceiverWrapper;
 use crate::sub_lib::logger::Logger;
+use crate::sub_lib::privacy;
 use crate::sub_lib::sequence_buffer::SequenceBuffer;
 use crate::sub_lib::sequence_buffer::SequencedPacket;
 use crate::sub_lib::stream_key::StreamKey;
@@ -45,6 +46,18 @@ impl StreamWriter {
         peer_addr: SocketAddr,
         rx_to_write: Box<dyn ReceiverWrapper<SequencedPacket>>,
         stream_key: StreamKey,
+    ) -> StreamWriter {
+        StreamWriter::starting_at(stream, peer_addr, rx_to_write, stream_key, 0)
+    }
+
+    // For a stream reconnected to its server, which picks up with the packet that couldn't be
+    // written to the old connection
+    pub fn starting_at(
+        stream: Box<dyn WriteHalfWrapper>,
+        peer_addr: SocketAddr,
+        rx_to_write: Box<dyn ReceiverWrapper<SequencedPacket>>,
+        stream_key: StreamKey,
+        first_sequence_number: u64,
     ) -> StreamWriter {
         let name = format!("StreamWriter for {:?}/{}", stream_key, peer_addr);
         let logger = Logger::new(&name[..]);
@@ -52,7 +65,7 @@ impl StreamWriter {
             stream,
             peer_addr,
             logger,
-            sequence_buffer: SequenceBuffer::new(),
+            sequence_buffer: SequenceBuffer::starting_at(first_sequence_number),
             rx_to_write,
             shutting_down: false,
         }
@@ -138,7 +151,7 @@ impl StreamWriter {
                                     packet.last_data,
                                 ));
                             } else if packet.last_data {
-                                debug!(self.logger, "Shutting down stream to server at {} in response to client-drop report", self.peer_addr);
+                                debug!(self.logger, "Shutting down stream to server at {} in response to client-drop report", privacy::socket_addr(self.peer_addr));
                                 self.shutting_down = true;
                                 return self.shutdown();
                             }
@@ -1,5 +1,5 @@
 // Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
-use crate::proxy_server::protocol_pack::from_ibcd;
+use crate::proxy_server::protocol_pack::{from_ibcd, ProtocolPack};
 use crate::sub_lib::cryptde::CryptDE;
 use crate::sub_lib::cryptde::PlainData;
 use crate::sub_lib::dispatcher::InboundClientData;
@@ -24,6 +24,18 @@ impl ClientRequestPayloadFactory {
         logger: &Logger,
     ) -> Option<ClientRequestPayload> {
         let protocol_pack = from_ibcd(&ibcd, logger)?;
+        self.make_for(protocol_pack.as_ref(), ibcd, stream_key, cryptde, logger)
+    }
+
+    // For data whose protocol isn't decided by the port it arrived on, such as data in a tunnel
+    pub fn make_for(
+        &self,
+        protocol_pack: &dyn ProtocolPack,
+        ibcd: &InboundClientData,
+        stream_key: StreamKey,
+        cryptde: &dyn CryptDE,
+        logger: &Logger,
+    ) -> Option<ClientRequestPayload> {
         let sequence_number = match ibcd.sequence_number {
             Some(sequence_number) => sequence_number,
             None => {
@@ -56,6 +68,9 @@ impl ClientRequestPayloadFactory {
             target_port,
             protocol: protocol_pack.proxy_protocol(),
             originator_public_key: cryptde.public_key().clone(),
+            fresh_return_route: false,
+            alpn_protocols: protocol_pack.find_alpn_protocols(&data),
+            trace_id_opt: None,
         })
     }
 }
@@ -102,6 +117,9 @@ mod tests {
                 target_port: 2345,
                 protocol: ProxyProtocol::HTTP,
                 originator_public_key: cryptde.public_key().clone(),
+                fresh_return_route: false,
+                alpn_protocols: vec![],
+                trace_id_opt: None,
             })
         );
     }
@@ -137,6 +155,9 @@ mod tests {
                 target_port: HTTP_PORT,
                 protocol: ProxyProtocol::HTTP,
                 originator_public_key: cryptde.public_key().clone(),
+                fresh_return_route: false,
+                alpn_protocols: vec![],
+                trace_id_opt: None,
             })
         );
     }
@@ -145,9 +166,9 @@ mod tests {
     fn handles_tls_with_hostname() {
         let data = PlainData::new(&[
             0x16, // content_type: Handshake
-            0x00, 0x00, 0x00, 0x00, // version, length: don't care
+            0x03, 0x01, 0x00, 0x3F, // version: TLS 1.0, length: 63
             0x01, // handshake_type: ClientHello
-            0x00, 0x00, 0x00, 0x00, 0x00, // length, version: don't care
+            0x00, 0x00, 0x3B, 0x03, 0x03, // length: 59, version: TLS 1.2
             0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
             0x00, 0x00, // random: don't care
             0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
@@ -192,10 +213,53 @@ mod tests {
                 target_port: 443,
                 protocol: ProxyProtocol::TLS,
                 originator_public_key: cryptde.public_key().clone(),
+                fresh_return_route: false,
+                alpn_protocols: vec![],
+                trace_id_opt: None,
             })
         );
     }
 
+    #[test]
+    fn records_alpn_protocols_offered_in_tls_client_hello() {
+        #[rustfmt::skip]
+        let data = PlainData::new(&[
+            0x16, // content_type: Handshake
+            0x03, 0x01, 0x00, 0x35, // version: TLS 1.0, length: 53
+            0x01, // handshake_type: ClientHello
+            0x00, 0x00, 0x31, 0x03, 0x03, // length: 49, version: TLS 1.2
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, // random: don't care
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, // random: don't care
+            0x00, // session_id_length
+            0x00, 0x00, // cipher_suites_length
+            0x00, // compression_methods_length
+            0x00, 0x09, // extensions_length
+            0x00, 0x10, // extension_type: application_layer_protocol_negotiation
+            0x00, 0x05, // extension_length
+            0x00, 0x03, // protocol_name_list_length
+            0x02, 'h' as u8, '2' as u8, // protocol_name
+        ]);
+        let ibcd = InboundClientData {
+            peer_addr: SocketAddr::from_str("1.2.3.4:5678").unwrap(),
+            reception_port: Some(443),
+            last_data: false,
+            is_clandestine: false,
+            sequence_number: Some(0),
+            data: data.into(),
+        };
+        let cryptde = cryptde();
+        let logger = Logger::new("test");
+        let subject = ClientRequestPayloadFactory::new();
+
+        let result = subject.make(&ibcd, make_meaningless_stream_key(), cryptde, &logger);
+
+        let payload = result.unwrap();
+        assert_eq!(payload.target_hostname, None);
+        assert_eq!(payload.alpn_protocols, vec![String::from("h2")]);
+    }
+
     #[test]
     fn handles_tls_without_hostname() {
         let data = PlainData::new(&[
@@ -240,6 +304,9 @@ mod tests {
                 target_port: 443,
                 protocol: ProxyProtocol::TLS,
                 originator_public_key: cryptde.public_key().clone(),
+                fresh_return_route: false,
+                alpn_protocols: vec![],
+                trace_id_opt: None,
             })
         );
     }
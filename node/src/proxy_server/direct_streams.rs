@@ -0,0 +1,562 @@
+// Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use crate::proxy_client::resolver_wrapper::ResolverWrapper;
+use crate::proxy_client::stream_handler_pool::PENDING_STREAM_PACKET_LIMIT;
+use crate::proxy_client::stream_reader::StreamReader;
+use crate::proxy_client::stream_writer::StreamWriter;
+use crate::proxy_server::protocol_pack::{from_protocol, ServerConnectionFailure};
+use crate::sub_lib::channel_wrappers::FuturesChannelFactory;
+use crate::sub_lib::channel_wrappers::FuturesChannelFactoryReal;
+use crate::sub_lib::channel_wrappers::SenderWrapper;
+use crate::sub_lib::cryptde::PublicKey;
+use crate::sub_lib::logger::Logger;
+use crate::sub_lib::privacy;
+use crate::sub_lib::proxy_client::InboundServerData;
+use crate::sub_lib::proxy_server::ClientRequestPayload;
+use crate::sub_lib::sequence_buffer::SequencedPacket;
+use crate::sub_lib::sequencer::Sequencer;
+use crate::sub_lib::stream_connector::ConnectionInfo;
+use crate::sub_lib::stream_connector::ConnectionInfoFuture;
+use crate::sub_lib::stream_connector::StreamConnector;
+use crate::sub_lib::stream_connector::StreamConnectorReal;
+use crate::sub_lib::stream_key::StreamKey;
+use actix::Recipient;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::net::IpAddr;
+use std::net::SocketAddr;
+use std::str::FromStr;
+use std::sync::mpsc;
+use std::sync::Arc;
+use std::sync::Mutex;
+use tokio::prelude::future;
+use tokio::prelude::Future;
+
+// Streams to destinations on the bypass list. The ProxyServer hands their client data here instead
+// of to the Hopper, and they're connected straight from this Node, using the original DNS servers
+// to find them; whatever the servers send back arrives at the ProxyServer as InboundServerData.
+#[derive(Clone)]
+pub struct DirectStreams {
+    inner: Arc<Mutex<DirectStreamsInner>>,
+}
+
+struct DirectStreamsInner {
+    resolver: Box<dyn ResolverWrapper>,
+    stream_connector: Box<dyn StreamConnector>,
+    channel_factory: Box<dyn FuturesChannelFactory<SequencedPacket>>,
+    server_data_sub: Recipient<InboundServerData>,
+    public_key: PublicKey,
+    writers: HashMap<StreamKey, Box<dyn SenderWrapper<SequencedPacket>>>,
+    // Packets that arrive while their stream is still being looked up and connected, up to
+    // PENDING_STREAM_PACKET_LIMIT of them
+    pending: HashMap<StreamKey, Vec<SequencedPacket>>,
+    // Streams closed for overflowing their queue, whose stragglers are discarded until they retire
+    given_up: HashSet<StreamKey>,
+    logger: Logger,
+}
+
+impl DirectStreams {
+    pub fn new(
+        resolver: Box<dyn ResolverWrapper>,
+        server_data_sub: Recipient<InboundServerData>,
+        public_key: PublicKey,
+    ) -> DirectStreams {
+        DirectStreams {
+            inner: Arc::new(Mutex::new(DirectStreamsInner {
+                resolver,
+                stream_connector: Box::new(StreamConnectorReal {}),
+                channel_factory: Box::new(FuturesChannelFactoryReal {}),
+                server_data_sub,
+                public_key,
+                writers: HashMap::new(),
+                pending: HashMap::new(),
+                given_up: HashSet::new(),
+                logger: Logger::new("DirectStreams"),
+            })),
+        }
+    }
+
+    pub fn contains(&self, stream_key: &StreamKey) -> bool {
+        let inner = self.inner.lock().expect("DirectStreams is poisoned");
+        inner.writers.contains_key(stream_key)
+            || inner.pending.contains_key(stream_key)
+            || inner.given_up.contains(stream_key)
+    }
+
+    pub fn send(&self, payload: ClientRequestPayload) {
+        let mut inner = self.inner.lock().expect("DirectStreams is poisoned");
+        let stream_key = payload.stream_key;
+        let last_data = payload.sequenced_packet.last_data;
+        if let Some(writer) = inner.writers.get(&stream_key) {
+            if writer.unbounded_send(payload.sequenced_packet).is_err() {
+                debug!(
+                    inner.logger,
+                    "Stream {} has already closed; discarding data", stream_key
+                );
+            }
+            if last_data {
+                inner.writers.remove(&stream_key);
+            }
+            return;
+        }
+        if inner.given_up.contains(&stream_key) {
+            debug!(
+                inner.logger,
+                "Discarding data for stream {}, which was closed", stream_key
+            );
+            return;
+        }
+        if let Some(queue) = inner.pending.get_mut(&stream_key) {
+            if queue.len() < PENDING_STREAM_PACKET_LIMIT {
+                queue.push(payload.sequenced_packet);
+                return;
+            }
+            warning!(
+                inner.logger,
+                "More than {} packets arrived for stream {} while it was being connected; closing it",
+                PENDING_STREAM_PACKET_LIMIT,
+                stream_key
+            );
+            inner.given_up.insert(stream_key);
+            drop(inner);
+            return DirectStreams::fail(self.inner.clone(), &payload, vec![]);
+        }
+        if payload.sequenced_packet.data.is_empty() {
+            debug!(
+                inner.logger,
+                "Ignoring empty packet for unconnected stream {}", stream_key
+            );
+            return;
+        }
+        let hostname = match &payload.target_hostname {
+            Some(hostname) => hostname.clone(),
+            None => {
+                error!(
+                    inner.logger,
+                    "Can't connect stream {} directly without a target hostname", stream_key
+                );
+                return;
+            }
+        };
+        inner.pending.insert(stream_key, vec![]);
+        let lookup: Box<dyn Future<Item = Vec<IpAddr>, Error = ()> + Send> =
+            match IpAddr::from_str(hostname.trim_start_matches('[').trim_end_matches(']')) {
+                Ok(ip_addr) => Box::new(future::ok(vec![ip_addr])),
+                Err(_) => {
+                    let logger = inner.logger.clone();
+                    let failed_hostname = hostname.clone();
+                    Box::new(
+                        inner
+                            .resolver
+                            .lookup_ip(&format!("{}.", hostname))
+                            .map(|lookup_ip| lookup_ip.iter().collect::<Vec<IpAddr>>())
+                            .map_err(move |e| {
+                                warning!(
+                                    logger,
+                                    "Couldn't look up bypassed host {}: {}",
+                                    privacy::host(&failed_hostname),
+                                    privacy::data(&e)
+                                );
+                            }),
+                    )
+                }
+            };
+        let inner_arc = self.inner.clone();
+        actix::spawn(
+            lookup.then(move |result| -> Box<dyn Future<Item = (), Error = ()>> {
+                match result {
+                    Ok(ref ip_addrs) if !ip_addrs.is_empty() => {
+                        DirectStreams::connect(inner_arc, payload, ip_addrs.clone())
+                    }
+                    _ => {
+                        let data = {
+                            let inner = inner_arc.lock().expect("DirectStreams is poisoned");
+                            from_protocol(payload.protocol)
+                                .server_impersonator()
+                                .dns_resolution_failure_response(
+                                    &inner.public_key,
+                                    payload.target_hostname.clone(),
+                                )
+                        };
+                        DirectStreams::fail(inner_arc, &payload, data);
+                        Box::new(future::ok(()))
+                    }
+                }
+            }),
+        );
+    }
+
+    pub fn retire(&self, stream_key: &StreamKey) {
+        let mut inner = self.inner.lock().expect("DirectStreams is poisoned");
+        inner.writers.remove(stream_key);
+        inner.pending.remove(stream_key);
+        inner.given_up.remove(stream_key);
+    }
+
+    fn connect(
+        inner_arc: Arc<Mutex<DirectStreamsInner>>,
+        payload: ClientRequestPayload,
+        ip_addrs: Vec<IpAddr>,
+    ) -> Box<dyn Future<Item = (), Error = ()>> {
+        let socket_addrs = ip_addrs
+            .into_iter()
+            .map(|ip_addr| SocketAddr::new(ip_addr, payload.target_port))
+            .collect();
+        Box::new(
+            DirectStreams::connect_to_any(inner_arc.clone(), socket_addrs).then(move |result| {
+                match result {
+                    Ok(connection_info) => {
+                        DirectStreams::start(inner_arc, payload, connection_info)
+                    }
+                    Err(e) => {
+                        let data = from_protocol(payload.protocol)
+                            .server_impersonator()
+                            .server_connection_failure_response(
+                                payload.target_hostname.clone(),
+                                ServerConnectionFailure::from(e.kind()),
+                            );
+                        DirectStreams::fail(inner_arc, &payload, data)
+                    }
+                }
+                Ok(())
+            }),
+        )
+    }
+
+    // The addresses are tried one after another, each within the connector's timeout, so that an
+    // unreachable host never holds up the ProxyServer.
+    fn connect_to_any(
+        inner_arc: Arc<Mutex<DirectStreamsInner>>,
+        mut socket_addrs: Vec<SocketAddr>,
+    ) -> ConnectionInfoFuture {
+        let socket_addr = socket_addrs.remove(0);
+        let attempt = {
+            let inner = inner_arc.lock().expect("DirectStreams is poisoned");
+            inner.stream_connector.connect(socket_addr, &inner.logger)
+        };
+        Box::new(attempt.or_else(move |e| -> ConnectionInfoFuture {
+            if socket_addrs.is_empty() {
+                Box::new(future::err(e))
+            } else {
+                DirectStreams::connect_to_any(inner_arc, socket_addrs)
+            }
+        }))
+    }
+
+    fn start(
+        inner_arc: Arc<Mutex<DirectStreamsInner>>,
+        payload: ClientRequestPayload,
+        connection_info: ConnectionInfo,
+    ) {
+        let hostname = payload.target_hostname.clone().unwrap_or_default();
+        let mut inner = inner_arc.lock().expect("DirectStreams is poisoned");
+        let stream_key = payload.stream_key;
+        let queued = match inner.pending.remove(&stream_key) {
+            Some(queued) => queued,
+            None => {
+                debug!(
+                    inner.logger,
+                    "Stream {} was closed while it was being connected; dropping its connection",
+                    stream_key
+                );
+                return;
+            }
+        };
+        let peer_addr = connection_info.peer_addr;
+        debug!(
+            inner.logger,
+            "Connected stream {} directly to {} at {}",
+            stream_key,
+            privacy::host(&hostname),
+            privacy::socket_addr(peer_addr)
+        );
+        let (writer, rx_to_write) = inner.channel_factory.make(peer_addr);
+        actix::spawn(StreamWriter::new(
+            connection_info.writer,
+            peer_addr,
+            rx_to_write,
+            stream_key,
+        ));
+        let server_data_sub = inner.server_data_sub.clone();
+        let (stream_killer, stream_killer_rx) = mpsc::channel();
+        actix::spawn(
+            StreamReader::new(
+                stream_key,
+                server_data_sub.clone(),
+                connection_info.reader,
+                stream_killer,
+                peer_addr,
+                Arc::new(Mutex::new(Sequencer::new())),
+            )
+            .then(move |_| {
+                if let Ok((stream_key, sequencer)) = stream_killer_rx.try_recv() {
+                    let sequence_number = sequencer
+                        .lock()
+                        .expect("Sequencer is poisoned")
+                        .next_sequence_number();
+                    server_data_sub
+                        .try_send(InboundServerData {
+                            stream_key,
+                            last_data: true,
+                            sequence_number,
+                            source: peer_addr,
+                            data: vec![],
+                        })
+                        .expect("ProxyServer is dead");
+                }
+                Ok(())
+            }),
+        );
+        let mut closed = payload.sequenced_packet.last_data;
+        DirectStreams::write(writer.as_ref(), payload.sequenced_packet);
+        queued.into_iter().for_each(|packet| {
+            closed = closed || packet.last_data;
+            DirectStreams::write(writer.as_ref(), packet);
+        });
+        if !closed {
+            inner.writers.insert(stream_key, writer);
+        }
+    }
+
+    fn write(writer: &dyn SenderWrapper<SequencedPacket>, packet: SequencedPacket) {
+        // The StreamWriter only goes away when its connection does, and the StreamReader reports that
+        let _ = writer.unbounded_send(packet);
+    }
+
+    fn fail(
+        inner_arc: Arc<Mutex<DirectStreamsInner>>,
+        payload: &ClientRequestPayload,
+        data: Vec<u8>,
+    ) {
+        let mut inner = inner_arc.lock().expect("DirectStreams is poisoned");
+        // A stream that's no longer waiting has already been closed one way or another
+        if inner.pending.remove(&payload.stream_key).is_none() {
+            return;
+        }
+        inner
+            .server_data_sub
+            .try_send(InboundServerData {
+                stream_key: payload.stream_key,
+                last_data: true,
+                sequence_number: 0,
+                source: SocketAddr::new(
+                    IpAddr::from_str("0.0.0.0").expect("Bad IP address"),
+                    payload.target_port,
+                ),
+                data,
+            })
+            .expect("ProxyServer is dead");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::proxy_client::local_test_utils::ResolverWrapperMock;
+    use crate::sub_lib::proxy_server::ProxyProtocol;
+    use crate::test_utils::make_meaningless_stream_key;
+    use crate::test_utils::recorder::make_recorder;
+    use crate::test_utils::recorder::peer_actors_builder;
+    use crate::test_utils::recorder::RecordAwaiter;
+    use crate::test_utils::recorder::Recording;
+    use crate::test_utils::stream_connector_mock::StreamConnectorMock;
+    use actix::System;
+    use actix::SystemRunner;
+    use std::io;
+    use std::thread;
+    use trust_dns_resolver::error::ResolveErrorKind;
+
+    fn make_payload(target_hostname: &str, data: &[u8]) -> ClientRequestPayload {
+        ClientRequestPayload {
+            version: ClientRequestPayload::version(),
+            stream_key: make_meaningless_stream_key(),
+            sequenced_packet: SequencedPacket::new(data.to_vec(), 0, false),
+            target_hostname: Some(target_hostname.to_string()),
+            target_port: 80,
+            protocol: ProxyProtocol::HTTP,
+            originator_public_key: PublicKey::new(b"originator"),
+            fresh_return_route: false,
+            alpn_protocols: vec![],
+            trace_id_opt: None,
+        }
+    }
+
+    fn make_subject(
+        resolver: ResolverWrapperMock,
+        stream_connector: StreamConnectorMock,
+    ) -> (DirectStreams, RecordAwaiter, Arc<Mutex<Recording>>) {
+        let (proxy_server, proxy_server_awaiter, proxy_server_recording_arc) = make_recorder();
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            let system = System::new("direct_streams");
+            let peer_actors = peer_actors_builder().proxy_server(proxy_server).build();
+            tx.send(peer_actors.proxy_server.direct_server_data)
+                .unwrap();
+            system.run();
+        });
+        let subject = DirectStreams {
+            inner: Arc::new(Mutex::new(DirectStreamsInner {
+                resolver: Box::new(resolver),
+                stream_connector: Box::new(stream_connector),
+                channel_factory: Box::new(FuturesChannelFactoryReal {}),
+                server_data_sub: rx.recv().unwrap(),
+                public_key: PublicKey::new(b"self"),
+                writers: HashMap::new(),
+                pending: HashMap::new(),
+                given_up: HashSet::new(),
+                logger: Logger::new("DirectStreams"),
+            })),
+        };
+        (subject, proxy_server_awaiter, proxy_server_recording_arc)
+    }
+
+    fn run_until_idle(system: SystemRunner) {
+        actix::spawn(future::lazy(|| {
+            System::current().stop();
+            Ok(())
+        }));
+        system.run();
+    }
+
+    #[test]
+    fn empty_packets_for_unknown_streams_are_ignored() {
+        let (subject, _, _) = make_subject(ResolverWrapperMock::new(), StreamConnectorMock::new());
+
+        subject.send(make_payload("intranet.example.com", b""));
+
+        assert_eq!(subject.contains(&make_meaningless_stream_key()), false);
+    }
+
+    #[test]
+    fn dns_failure_produces_an_impersonated_response() {
+        let lookup_ip_parameters = Arc::new(Mutex::new(vec![]));
+        let resolver = ResolverWrapperMock::new()
+            .lookup_ip_failure(ResolveErrorKind::Io.into())
+            .lookup_ip_parameters(&lookup_ip_parameters);
+        let (subject, proxy_server_awaiter, proxy_server_recording_arc) =
+            make_subject(resolver, StreamConnectorMock::new());
+        let system = System::new("dns_failure_produces_an_impersonated_response");
+
+        subject.send(make_payload(
+            "intranet.example.com",
+            b"GET / HTTP/1.1\r\n\r\n",
+        ));
+        assert_eq!(subject.contains(&make_meaningless_stream_key()), true);
+        run_until_idle(system);
+
+        assert_eq!(subject.contains(&make_meaningless_stream_key()), false);
+        assert_eq!(
+            *lookup_ip_parameters.lock().unwrap(),
+            vec!["intranet.example.com.".to_string()]
+        );
+        proxy_server_awaiter.await_message_count(1);
+        let recording = proxy_server_recording_arc.lock().unwrap();
+        let record = recording.get_record::<InboundServerData>(0);
+        assert_eq!(record.last_data, true);
+        assert_eq!(record.sequence_number, 0);
+        assert_eq!(
+            record.data,
+            from_protocol(ProxyProtocol::HTTP)
+                .server_impersonator()
+                .dns_resolution_failure_response(
+                    &PublicKey::new(b"self"),
+                    Some("intranet.example.com".to_string())
+                )
+        );
+    }
+
+    #[test]
+    fn connection_failure_to_an_ip_address_produces_an_impersonated_response() {
+        let stream_connector = StreamConnectorMock::new()
+            .connect_pair_result(Err(io::Error::from(io::ErrorKind::ConnectionRefused)));
+        let (subject, proxy_server_awaiter, proxy_server_recording_arc) =
+            make_subject(ResolverWrapperMock::new(), stream_connector);
+        let system = System::new("connection_failure_produces_an_impersonated_response");
+
+        subject.send(make_payload("192.168.1.1", b"GET / HTTP/1.1\r\n\r\n"));
+        run_until_idle(system);
+
+        assert_eq!(subject.contains(&make_meaningless_stream_key()), false);
+        proxy_server_awaiter.await_message_count(1);
+        let recording = proxy_server_recording_arc.lock().unwrap();
+        let record = recording.get_record::<InboundServerData>(0);
+        assert_eq!(record.last_data, true);
+        assert_eq!(
+            record.data,
+            from_protocol(ProxyProtocol::HTTP)
+                .server_impersonator()
+                .server_connection_failure_response(
+                    Some("192.168.1.1".to_string()),
+                    ServerConnectionFailure::Refused
+                )
+        );
+    }
+
+    #[test]
+    fn each_address_of_a_host_is_tried_in_turn() {
+        let connect_params = Arc::new(Mutex::new(vec![]));
+        let resolver = ResolverWrapperMock::new().lookup_ip_success(vec![
+            IpAddr::from_str("10.0.0.1").unwrap(),
+            IpAddr::from_str("10.0.0.2").unwrap(),
+        ]);
+        let stream_connector = StreamConnectorMock::new()
+            .connect_pair_params(&connect_params)
+            .connect_pair_result(Err(io::Error::from(io::ErrorKind::TimedOut)))
+            .connect_pair_result(Err(io::Error::from(io::ErrorKind::ConnectionRefused)));
+        let (subject, proxy_server_awaiter, proxy_server_recording_arc) =
+            make_subject(resolver, stream_connector);
+        let system = System::new("each_address_of_a_host_is_tried_in_turn");
+
+        subject.send(make_payload(
+            "intranet.example.com",
+            b"GET / HTTP/1.1\r\n\r\n",
+        ));
+        run_until_idle(system);
+
+        assert_eq!(
+            *connect_params.lock().unwrap(),
+            vec![
+                SocketAddr::from_str("10.0.0.1:80").unwrap(),
+                SocketAddr::from_str("10.0.0.2:80").unwrap(),
+            ]
+        );
+        proxy_server_awaiter.await_message_count(1);
+        let recording = proxy_server_recording_arc.lock().unwrap();
+        let record = recording.get_record::<InboundServerData>(0);
+        assert_eq!(
+            record.data,
+            from_protocol(ProxyProtocol::HTTP)
+                .server_impersonator()
+                .server_connection_failure_response(
+                    Some("intranet.example.com".to_string()),
+                    ServerConnectionFailure::Refused
+                )
+        );
+    }
+
+    #[test]
+    fn a_stream_that_queues_too_many_packets_while_connecting_is_closed() {
+        let stream_connector = StreamConnectorMock::new()
+            .connect_pair_result(Err(io::Error::from(io::ErrorKind::ConnectionRefused)));
+        let (subject, proxy_server_awaiter, proxy_server_recording_arc) =
+            make_subject(ResolverWrapperMock::new(), stream_connector);
+        let system =
+            System::new("a_stream_that_queues_too_many_packets_while_connecting_is_closed");
+        let stream_key = make_meaningless_stream_key();
+
+        (0..(PENDING_STREAM_PACKET_LIMIT + 3))
+            .for_each(|_| subject.send(make_payload("192.168.1.1", b"GET / HTTP/1.1\r\n\r\n")));
+
+        assert_eq!(subject.contains(&stream_key), true);
+        run_until_idle(system);
+        proxy_server_awaiter.await_message_count(1);
+        let recording = proxy_server_recording_arc.lock().unwrap();
+        assert_eq!(recording.len(), 1);
+        let record = recording.get_record::<InboundServerData>(0);
+        assert_eq!(record.stream_key, stream_key);
+        assert_eq!(record.last_data, true);
+        assert_eq!(record.data, Vec::<u8>::new());
+        drop(recording);
+        subject.retire(&stream_key);
+        assert_eq!(subject.contains(&stream_key), false);
+    }
+}
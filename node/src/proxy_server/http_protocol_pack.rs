@@ -30,6 +30,10 @@ impl ProtocolPack for HttpProtocolPack {
         }
     }
 
+    fn find_alpn_protocols(&self, _data: &PlainData) -> Vec<String> {
+        vec![]
+    }
+
     fn server_impersonator(&self) -> Box<dyn ServerImpersonator> {
         Box::new(ServerImpersonatorHttp {})
     }
@@ -78,6 +82,108 @@ impl HttpProtocolPack {
     }
 
     pub fn is_connect(data: &[u8]) -> bool {
+        match Self::method(data) {
+            Ok(http::Method::CONNECT) => true,
+            _ => false,
+        }
+    }
+
+    // A request whose method is idempotent can be sent to the server again if the first
+    // attempt fails, without the server doing anything the browser didn't ask for.
+    pub fn is_idempotent(data: &[u8]) -> bool {
+        match Self::method(data) {
+            Ok(method) => method.is_idempotent(),
+            Err(_) => false,
+        }
+    }
+
+    // A plaintext HTTP/1.1 request after which the connection to the server can carry another
+    // request: the browser hasn't asked for it to be closed, and where the request ends doesn't
+    // depend on chunked framing. HEAD is left out because its response's length says nothing
+    // about how many bytes will actually follow.
+    pub fn keeps_connection_alive(data: &[u8]) -> bool {
+        match Self::method(data) {
+            Ok(http::Method::CONNECT) | Ok(http::Method::HEAD) | Err(_) => return false,
+            Ok(_) => (),
+        }
+        match Self::head_lines(data) {
+            Some(lines) => {
+                lines[0].ends_with(" HTTP/1.1") && Self::headers_allow_reuse(&lines[1..])
+            }
+            None => false,
+        }
+    }
+
+    // The same for a response, which must also say how long it is, so that its end can be found
+    // without waiting for the server to close the connection.
+    pub fn response_keeps_connection_alive(data: &[u8]) -> bool {
+        match Self::head_lines(data) {
+            Some(lines) => {
+                lines[0].starts_with("HTTP/1.1 ")
+                    && Self::headers_allow_reuse(&lines[1..])
+                    && lines[1..]
+                        .iter()
+                        .any(|line| line.to_lowercase().starts_with("content-length:"))
+            }
+            None => false,
+        }
+    }
+
+    // A browser using us as its proxy names the whole URL in its request line, as in
+    // "GET http://host:port/path HTTP/1.1", and adds headers meant only for its proxy. This gives
+    // back the request the server would get from the browser directly, with the host it's for; a
+    // request that already names just a path, or whose head isn't all here, gets None.
+    pub fn to_origin_form(data: &[u8]) -> Option<(Vec<u8>, Host)> {
+        let head_end = index_of(data, b"\r\n\r\n")?;
+        let lines = Self::head_lines(data)?;
+        let mut parts = lines[0].splitn(3, ' ');
+        let (method, url, version) = (parts.next()?, parts.next()?, parts.next()?);
+        if !url.to_lowercase().starts_with("http://") {
+            return None;
+        }
+        let rest = &url["http://".len()..];
+        let authority_end = rest.find(|c| c == '/' || c == '?').unwrap_or(rest.len());
+        let authority = &rest[..authority_end];
+        let host = match Self::host_from_host_name_and_port(authority) {
+            Some(ref host) if host.name.is_empty() => return None,
+            host_opt => host_opt?,
+        };
+        let path = match &rest[authority_end..] {
+            "" => "/".to_string(),
+            path if path.starts_with('/') => path.to_string(),
+            query => format!("/{}", query),
+        };
+        let mut head = vec![format!("{} {} {}", method, path, version)];
+        head.extend(lines[1..].iter().cloned().filter(|line| {
+            let line = line.to_lowercase();
+            !line.starts_with("proxy-connection:") && !line.starts_with("proxy-authorization:")
+        }));
+        if !head[1..]
+            .iter()
+            .any(|line| line.to_lowercase().starts_with("host:"))
+        {
+            head.insert(1, format!("Host: {}", authority));
+        }
+        let mut request = head.join("\r\n").into_bytes();
+        request.extend_from_slice(&data[head_end..]);
+        Some((request, host))
+    }
+
+    fn head_lines(data: &[u8]) -> Option<Vec<String>> {
+        let head_end = index_of(data, b"\r\n\r\n")?;
+        let head = String::from_utf8(data[..head_end].to_vec()).ok()?;
+        Some(head.split("\r\n").map(|line| line.to_string()).collect())
+    }
+
+    fn headers_allow_reuse(header_lines: &[String]) -> bool {
+        !header_lines.iter().any(|line| {
+            let line = line.to_lowercase();
+            (line.starts_with("connection:") && line.contains("close"))
+                || line.starts_with("transfer-encoding:")
+        })
+    }
+
+    fn method(data: &[u8]) -> Result<http::Method, http::method::InvalidMethod> {
         let method_bytes: Vec<u8> = data
             .iter()
             .take(8)
@@ -85,10 +191,7 @@ impl HttpProtocolPack {
             .cloned()
             .collect();
 
-        match http::Method::from_bytes(method_bytes.as_slice()) {
-            Ok(http::Method::CONNECT) => true,
-            _ => false,
-        }
+        http::Method::from_bytes(method_bytes.as_slice())
     }
 
     fn port_from_string(port_str: String) -> Option<u16> {
@@ -117,6 +220,15 @@ mod tests {
         assert_eq!(HTTP_PORT, result);
     }
 
+    #[test]
+    fn finds_no_alpn_protocols() {
+        let data = PlainData::new(b"GET /index.html HTTP/1.1\r\nHost: nowhere.com\r\n\r\n");
+
+        let result = HttpProtocolPack {}.find_alpn_protocols(&data);
+
+        assert_eq!(result, Vec::<String>::new());
+    }
+
     #[test]
     fn returns_none_if_no_double_crlf() {
         let data = PlainData::new(b"no\r\ndouble\r\ncrlf\r\n");
@@ -345,4 +457,139 @@ mod tests {
         let data = b"CONNECTX";
         assert!(!HttpProtocolPack::is_connect(data));
     }
+
+    #[test]
+    fn is_idempotent_true_for_idempotent_methods() {
+        vec!["GET", "HEAD", "OPTIONS", "TRACE", "PUT", "DELETE"]
+            .into_iter()
+            .for_each(|method| {
+                let data = format!(
+                    "{} /index.html HTTP/1.1\r\nHost: server.com\r\n\r\n",
+                    method
+                );
+
+                assert!(
+                    HttpProtocolPack::is_idempotent(data.as_bytes()),
+                    "{}",
+                    method
+                );
+            });
+    }
+
+    #[test]
+    fn is_idempotent_false_for_other_methods_and_non_requests() {
+        vec![
+            &b"POST /form HTTP/1.1\r\nHost: server.com\r\n\r\n"[..],
+            &b"PATCH /form HTTP/1.1\r\nHost: server.com\r\n\r\n"[..],
+            &b"CONNECT server.com:443 HTTP/1.1\r\n\r\n"[..],
+            &b"\x16\x03\x01 not HTTP at all"[..],
+        ]
+        .into_iter()
+        .for_each(|data| assert!(!HttpProtocolPack::is_idempotent(data), "{:?}", data));
+    }
+
+    #[test]
+    fn keeps_connection_alive_true_for_plain_http_1_1_requests() {
+        vec![
+            &b"GET /index.html HTTP/1.1\r\nHost: server.com\r\n\r\n"[..],
+            &b"POST /form HTTP/1.1\r\nHost: server.com\r\nContent-Length: 3\r\n\r\na=b"[..],
+            &b"GET / HTTP/1.1\r\nHost: server.com\r\nConnection: keep-alive\r\n\r\n"[..],
+        ]
+        .into_iter()
+        .for_each(|data| assert!(HttpProtocolPack::keeps_connection_alive(data), "{:?}", data));
+    }
+
+    #[test]
+    fn keeps_connection_alive_false_for_requests_whose_connection_cannot_be_reused() {
+        vec![
+            &b"GET / HTTP/1.0\r\nHost: server.com\r\n\r\n"[..],
+            &b"GET / HTTP/1.1\r\nHost: server.com\r\nConnection: close\r\n\r\n"[..],
+            &b"GET / HTTP/1.1\r\nHost: server.com\r\nconnection: Close\r\n\r\n"[..],
+            &b"POST / HTTP/1.1\r\nHost: server.com\r\nTransfer-Encoding: chunked\r\n\r\n"[..],
+            &b"HEAD / HTTP/1.1\r\nHost: server.com\r\n\r\n"[..],
+            &b"CONNECT server.com:443 HTTP/1.1\r\n\r\n"[..],
+            &b"GET / HTTP/1.1\r\nHost: server.com\r\n"[..],
+            &b"\x16\x03\x01 not HTTP at all"[..],
+        ]
+        .into_iter()
+        .for_each(|data| {
+            assert!(
+                !HttpProtocolPack::keeps_connection_alive(data),
+                "{:?}",
+                data
+            )
+        });
+    }
+
+    #[test]
+    fn response_keeps_connection_alive_only_for_http_1_1_responses_of_known_length() {
+        assert!(HttpProtocolPack::response_keeps_connection_alive(
+            b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nbooga"
+        ));
+        vec![
+            &b"HTTP/1.0 200 OK\r\nContent-Length: 5\r\n\r\nbooga"[..],
+            &b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\nConnection: close\r\n\r\nbooga"[..],
+            &b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n5\r\nbooga\r\n"[..],
+            &b"HTTP/1.1 200 OK\r\n\r\nbooga"[..],
+        ]
+        .into_iter()
+        .for_each(|data| {
+            assert!(
+                !HttpProtocolPack::response_keeps_connection_alive(data),
+                "{:?}",
+                data
+            )
+        });
+    }
+
+    #[test]
+    fn to_origin_form_leaves_the_browser_s_proxy_out_of_the_request() {
+        let request = b"POST http://nowhere.com:8080/index.html?q=1 HTTP/1.1\r\nHost: nowhere.com:8080\r\nProxy-Connection: keep-alive\r\nproxy-authorization: Basic Ym9vZ2E=\r\nContent-Length: 4\r\n\r\nbody";
+
+        let (result, host) = HttpProtocolPack::to_origin_form(request).unwrap();
+
+        assert_eq!(
+            String::from_utf8(result).unwrap(),
+            "POST /index.html?q=1 HTTP/1.1\r\nHost: nowhere.com:8080\r\nContent-Length: 4\r\n\r\nbody"
+        );
+        assert_eq!(
+            host,
+            Host {
+                name: "nowhere.com".to_string(),
+                port: Some(8080)
+            }
+        );
+    }
+
+    #[test]
+    fn to_origin_form_supplies_a_missing_path_and_host_header() {
+        let (result, host) =
+            HttpProtocolPack::to_origin_form(b"GET HTTP://nowhere.com HTTP/1.0\r\n\r\n").unwrap();
+
+        assert_eq!(
+            String::from_utf8(result).unwrap(),
+            "GET / HTTP/1.0\r\nHost: nowhere.com\r\n\r\n"
+        );
+        assert_eq!(host.name, "nowhere.com".to_string());
+        let (result, _) = HttpProtocolPack::to_origin_form(
+            b"GET http://nowhere.com?q=1 HTTP/1.1\r\nHost: nowhere.com\r\n\r\n",
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(result).unwrap(),
+            "GET /?q=1 HTTP/1.1\r\nHost: nowhere.com\r\n\r\n"
+        );
+    }
+
+    #[test]
+    fn to_origin_form_ignores_requests_already_in_origin_form_or_incomplete() {
+        vec![
+            &b"GET /index.html HTTP/1.1\r\nHost: nowhere.com\r\n\r\n"[..],
+            &b"CONNECT nowhere.com:443 HTTP/1.1\r\nHost: nowhere.com:443\r\n\r\n"[..],
+            &b"GET http://nowhere.com/index.html HTTP/1.1\r\nHost: nowh"[..],
+            &b"GET http:///index.html HTTP/1.1\r\n\r\n"[..],
+        ]
+        .into_iter()
+        .for_each(|data| assert_eq!(HttpProtocolPack::to_origin_form(data), None, "{:?}", data));
+    }
 }
@@ -1,40 +1,59 @@
 // Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
 
 pub mod client_request_payload_factory;
+pub mod direct_streams;
 pub mod http_protocol_pack;
 pub mod protocol_pack;
+pub mod raw_protocol_pack;
 pub mod server_impersonator_http;
+pub mod server_impersonator_raw;
 pub mod server_impersonator_tls;
 pub mod tls_protocol_pack;
 
+use crate::persistent_configuration::{HTTP_PORT, TLS_PORT};
+use crate::proxy_client::resolver_wrapper::ResolverWrapperFactory;
+use crate::proxy_client::resolver_wrapper::ResolverWrapperFactoryReal;
 use crate::proxy_server::client_request_payload_factory::ClientRequestPayloadFactory;
+use crate::proxy_server::direct_streams::DirectStreams;
 use crate::proxy_server::http_protocol_pack::HttpProtocolPack;
-use crate::proxy_server::protocol_pack::{from_ibcd, from_protocol, ProtocolPack};
+use crate::proxy_server::protocol_pack::{from_ibcd, from_protocol, Host, ProtocolPack};
+use crate::proxy_server::tls_protocol_pack::TlsProtocolPack;
 use crate::stream_messages::NonClandestineAttributes;
 use crate::stream_messages::RemovedStreamType;
 use crate::sub_lib::accountant::ReportExitServiceConsumedMessage;
 use crate::sub_lib::accountant::ReportRoutingServiceConsumedMessage;
 use crate::sub_lib::bidi_hashmap::BidiHashMap;
+use crate::sub_lib::bypass::BypassList;
 use crate::sub_lib::cryptde::CryptDE;
 use crate::sub_lib::cryptde::PublicKey;
 use crate::sub_lib::dispatcher::InboundClientData;
 use crate::sub_lib::dispatcher::{Endpoint, StreamShutdownMsg};
 use crate::sub_lib::hopper::{ExpiredCoresPackage, IncipientCoresPackage};
 use crate::sub_lib::logger::Logger;
+use crate::sub_lib::metrics;
+use crate::sub_lib::metrics::{PROXY_SERVER_STREAMS_FAILED, PROXY_SERVER_STREAMS_OPENED};
+use crate::sub_lib::neighborhood::DisjointRouteQueryMessage;
 use crate::sub_lib::neighborhood::RatePack;
 use crate::sub_lib::neighborhood::RouteQueryMessage;
 use crate::sub_lib::neighborhood::RouteQueryResponse;
 use crate::sub_lib::neighborhood::{ExpectedService, NodeRecordMetadataMessage};
 use crate::sub_lib::neighborhood::{ExpectedServices, DEFAULT_RATE_PACK};
 use crate::sub_lib::peer_actors::BindMessage;
-use crate::sub_lib::proxy_client::{ClientResponsePayload, DnsResolveFailure};
+use crate::sub_lib::privacy;
+use crate::sub_lib::proxy_client::DnsAnswerPayload;
+use crate::sub_lib::proxy_client::InboundServerData;
+use crate::sub_lib::proxy_client::{ClientResponsePayload, DnsResolveFailure, ExitPolicyRefusal};
 use crate::sub_lib::proxy_server::ClientRequestPayload;
+use crate::sub_lib::proxy_server::InvalidateRoutesMessage;
+use crate::sub_lib::proxy_server::ProxyProtocol;
 use crate::sub_lib::proxy_server::ProxyServerSubs;
 use crate::sub_lib::proxy_server::{AddReturnRouteMessage, AddRouteMessage};
+use crate::sub_lib::proxy_server::{DnsQueryPayload, TunneledDnsAnswer, TunneledDnsQuery};
 use crate::sub_lib::route::Route;
 use crate::sub_lib::set_consuming_wallet_message::SetConsumingWalletMessage;
 use crate::sub_lib::stream_handler_pool::TransmitDataMsg;
 use crate::sub_lib::stream_key::StreamKey;
+use crate::sub_lib::stream_trace::{self, SpanKind, TraceId};
 use crate::sub_lib::ttl_hashmap::TtlHashMap;
 use crate::sub_lib::utils::NODE_MAILBOX_CAPACITY;
 use crate::sub_lib::wallet::Wallet;
@@ -43,15 +62,25 @@ use actix::Addr;
 use actix::Context;
 use actix::Handler;
 use actix::Recipient;
+use futures::sync::mpsc::UnboundedSender;
 use pretty_hex::PrettyHex;
 use std::collections::HashMap;
 use std::net::SocketAddr;
 use std::rc::Rc;
 use std::time::Duration;
+use std::time::Instant;
 use tokio;
 use tokio::prelude::Future;
+use trust_dns_resolver::config::NameServerConfig;
+use trust_dns_resolver::config::Protocol;
+use trust_dns_resolver::config::ResolverConfig;
+use trust_dns_resolver::config::ResolverOpts;
 
 pub const RETURN_ROUTE_TTL: Duration = Duration::from_secs(120);
+// The DNS RCODE for SERVFAIL, sent to the entry DNS server when a query can't be tunneled
+const SERVER_FAILURE_RESPONSE_CODE: u16 = 2;
+// No browser needs more records than this for its ClientHello; past it, we stop waiting
+const MAX_CLIENT_HELLO_RECORDS: usize = 8;
 
 struct ProxyServerOutSubs {
     dispatcher: Recipient<TransmitDataMsg>,
@@ -59,6 +88,7 @@ struct ProxyServerOutSubs {
     accountant_exit: Recipient<ReportExitServiceConsumedMessage>,
     accountant_routing: Recipient<ReportRoutingServiceConsumedMessage>,
     route_source: Recipient<RouteQueryMessage>,
+    disjoint_route_source: Recipient<DisjointRouteQueryMessage>,
     update_node_record_metadata: Recipient<NodeRecordMetadataMessage>,
     add_return_route: Recipient<AddReturnRouteMessage>,
     add_route: Recipient<AddRouteMessage>,
@@ -70,14 +100,26 @@ pub struct ProxyServer {
     client_request_payload_factory: ClientRequestPayloadFactory,
     stream_key_factory: Box<dyn StreamKeyFactory>,
     keys_and_addrs: BidiHashMap<StreamKey, SocketAddr>,
-    tunneled_hosts: HashMap<StreamKey, String>,
+    tunneled_hosts: HashMap<StreamKey, Tunnel>,
+    client_hello_records: HashMap<StreamKey, Vec<InboundClientData>>,
     stream_key_routes: HashMap<StreamKey, RouteQueryResponse>,
+    stream_key_paths: HashMap<StreamKey, StreamPaths>,
+    route_path_count: usize,
+    proxy_port_opt: Option<u16>,
+    proxy_port_streams: HashMap<StreamKey, ProxyPortStream>,
+    repaired_stream_exits: HashMap<StreamKey, Option<PublicKey>>,
     is_decentralized: bool,
     consuming_wallet_balance: Option<i64>,
     cryptde: &'static dyn CryptDE,
     logger: Logger,
     route_ids_to_return_routes: TtlHashMap<u32, AddReturnRouteMessage>,
+    dns_query_responders: HashMap<StreamKey, DnsQueryResponder>,
     browser_proxy_sequence_offset: bool,
+    bypass_list: BypassList,
+    bypass_dns_servers: Vec<SocketAddr>,
+    resolver_wrapper_factory: Box<dyn ResolverWrapperFactory>,
+    direct_streams_opt: Option<DirectStreams>,
+    trace_ids: HashMap<StreamKey, TraceId>,
 }
 
 impl Actor for ProxyServer {
@@ -95,12 +137,34 @@ impl Handler<BindMessage> for ProxyServer {
             accountant_exit: msg.peer_actors.accountant.report_exit_service_consumed,
             accountant_routing: msg.peer_actors.accountant.report_routing_service_consumed,
             route_source: msg.peer_actors.neighborhood.route_query,
+            disjoint_route_source: msg.peer_actors.neighborhood.disjoint_route_query,
             update_node_record_metadata: msg.peer_actors.neighborhood.update_node_record_metadata,
             add_return_route: msg.peer_actors.proxy_server.add_return_route,
             add_route: msg.peer_actors.proxy_server.add_route,
             stream_shutdown_sub: msg.peer_actors.proxy_server.stream_shutdown_sub,
         };
         self.subs = Some(subs);
+        if !self.bypass_list.is_empty() {
+            let mut config = ResolverConfig::new();
+            for dns_server_ref in &self.bypass_dns_servers {
+                info!(
+                    self.logger,
+                    "Adding DNS server for bypassed destinations: {}",
+                    privacy::ip(dns_server_ref.ip())
+                );
+                config.add_name_server(NameServerConfig {
+                    socket_addr: *dns_server_ref,
+                    protocol: Protocol::Udp,
+                    tls_dns_name: None,
+                })
+            }
+            self.direct_streams_opt = Some(DirectStreams::new(
+                self.resolver_wrapper_factory
+                    .make(config, ResolverOpts::default()),
+                msg.peer_actors.proxy_server.direct_server_data,
+                self.cryptde.public_key().clone(),
+            ));
+        }
     }
 }
 
@@ -120,11 +184,14 @@ impl Handler<InboundClientData> for ProxyServer {
     type Result = ();
 
     fn handle(&mut self, msg: InboundClientData, _ctx: &mut Self::Context) -> Self::Result {
+        let msg = self.normalize_proxy_port(msg);
         if msg.is_connect() {
-            self.tls_connect(&msg);
+            self.connect_tunnel(&msg);
             self.browser_proxy_sequence_offset = true;
         } else {
-            self.handle_normal_client_data(msg, false);
+            self.assemble_client_hello(msg)
+                .into_iter()
+                .for_each(|msg| self.handle_normal_client_data(msg, false));
         }
     }
 }
@@ -133,6 +200,11 @@ impl Handler<AddReturnRouteMessage> for ProxyServer {
     type Result = ();
 
     fn handle(&mut self, msg: AddReturnRouteMessage, _ctx: &mut Self::Context) -> Self::Result {
+        if let Some(stream_key) = msg.dns_query_opt {
+            if let Some(responder) = self.dns_query_responders.get_mut(&stream_key) {
+                responder.return_route_id_opt = Some(msg.return_route_id);
+            }
+        }
         self.route_ids_to_return_routes
             .insert(msg.return_route_id, msg);
     }
@@ -157,11 +229,37 @@ impl Handler<AddRouteMessage> for ProxyServer {
     type Result = ();
 
     fn handle(&mut self, msg: AddRouteMessage, _ctx: &mut Self::Context) -> Self::Result {
-        debug!(self.logger, "Establishing stream key {}", msg.stream_key);
+        debug!(
+            self.logger,
+            {stream_key: msg.stream_key},
+            "Establishing stream key {}", msg.stream_key
+        );
+        if !msg.alternate_routes.is_empty() {
+            let mut routes = vec![msg.route.clone()];
+            routes.extend(msg.alternate_routes);
+            debug!(
+                self.logger,
+                "Spreading stream key {} across {} routes",
+                msg.stream_key,
+                routes.len()
+            );
+            self.stream_key_paths
+                .insert(msg.stream_key, StreamPaths::new(routes));
+        } else {
+            let _ = self.stream_key_paths.remove(&msg.stream_key);
+        }
         self.stream_key_routes.insert(msg.stream_key, msg.route);
     }
 }
 
+impl Handler<InvalidateRoutesMessage> for ProxyServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: InvalidateRoutesMessage, _ctx: &mut Self::Context) -> Self::Result {
+        self.handle_invalidate_routes(&msg.public_key)
+    }
+}
+
 impl Handler<ExpiredCoresPackage<DnsResolveFailure>> for ProxyServer {
     type Result = ();
 
@@ -174,6 +272,18 @@ impl Handler<ExpiredCoresPackage<DnsResolveFailure>> for ProxyServer {
     }
 }
 
+impl Handler<ExpiredCoresPackage<ExitPolicyRefusal>> for ProxyServer {
+    type Result = ();
+
+    fn handle(
+        &mut self,
+        msg: ExpiredCoresPackage<ExitPolicyRefusal>,
+        _ctx: &mut Self::Context,
+    ) -> Self::Result {
+        self.handle_exit_policy_refusal(&msg)
+    }
+}
+
 impl Handler<ExpiredCoresPackage<ClientResponsePayload>> for ProxyServer {
     type Result = ();
 
@@ -186,6 +296,34 @@ impl Handler<ExpiredCoresPackage<ClientResponsePayload>> for ProxyServer {
     }
 }
 
+impl Handler<TunneledDnsQuery> for ProxyServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: TunneledDnsQuery, _ctx: &mut Self::Context) -> Self::Result {
+        self.handle_tunneled_dns_query(msg)
+    }
+}
+
+impl Handler<ExpiredCoresPackage<DnsAnswerPayload>> for ProxyServer {
+    type Result = ();
+
+    fn handle(
+        &mut self,
+        msg: ExpiredCoresPackage<DnsAnswerPayload>,
+        _ctx: &mut Self::Context,
+    ) -> Self::Result {
+        self.handle_dns_answer(msg)
+    }
+}
+
+impl Handler<InboundServerData> for ProxyServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: InboundServerData, _ctx: &mut Self::Context) -> Self::Result {
+        self.handle_direct_server_data(msg)
+    }
+}
+
 impl Handler<StreamShutdownMsg> for ProxyServer {
     type Result = ();
 
@@ -206,16 +344,41 @@ impl ProxyServer {
             stream_key_factory: Box::new(StreamKeyFactoryReal {}),
             keys_and_addrs: BidiHashMap::new(),
             tunneled_hosts: HashMap::new(),
+            client_hello_records: HashMap::new(),
             stream_key_routes: HashMap::new(),
+            stream_key_paths: HashMap::new(),
+            route_path_count: 1,
+            proxy_port_opt: None,
+            proxy_port_streams: HashMap::new(),
+            repaired_stream_exits: HashMap::new(),
             is_decentralized,
             consuming_wallet_balance,
             cryptde,
             logger: Logger::new("ProxyServer"),
             route_ids_to_return_routes: TtlHashMap::new(RETURN_ROUTE_TTL),
+            dns_query_responders: HashMap::new(),
             browser_proxy_sequence_offset: false,
+            bypass_list: BypassList::default(),
+            bypass_dns_servers: vec![],
+            resolver_wrapper_factory: Box::new(ResolverWrapperFactoryReal {}),
+            direct_streams_opt: None,
+            trace_ids: HashMap::new(),
         }
     }
 
+    pub fn set_route_path_count(&mut self, route_path_count: usize) {
+        self.route_path_count = std::cmp::max(route_path_count, 1);
+    }
+
+    pub fn set_proxy_port(&mut self, proxy_port_opt: Option<u16>) {
+        self.proxy_port_opt = proxy_port_opt;
+    }
+
+    pub fn set_bypass(&mut self, bypass_list: BypassList, bypass_dns_servers: Vec<SocketAddr>) {
+        self.bypass_list = bypass_list;
+        self.bypass_dns_servers = bypass_dns_servers;
+    }
+
     pub fn make_subs_from(addr: &Addr<ProxyServer>) -> ProxyServerSubs {
         ProxyServerSubs {
             bind: addr.clone().recipient::<BindMessage>(),
@@ -226,10 +389,19 @@ impl ProxyServer {
             dns_failure_from_hopper: addr
                 .clone()
                 .recipient::<ExpiredCoresPackage<DnsResolveFailure>>(),
+            exit_policy_refusal_from_hopper: addr
+                .clone()
+                .recipient::<ExpiredCoresPackage<ExitPolicyRefusal>>(),
+            dns_answer_from_hopper: addr
+                .clone()
+                .recipient::<ExpiredCoresPackage<DnsAnswerPayload>>(),
+            dns_query_from_entry_dns: addr.clone().recipient::<TunneledDnsQuery>(),
             add_return_route: addr.clone().recipient::<AddReturnRouteMessage>(),
             add_route: addr.clone().recipient::<AddRouteMessage>(),
             stream_shutdown_sub: addr.clone().recipient::<StreamShutdownMsg>(),
             set_consuming_wallet_sub: addr.clone().recipient::<SetConsumingWalletMessage>(),
+            invalidate_routes: addr.clone().recipient::<InvalidateRoutesMessage>(),
+            direct_server_data: addr.clone().recipient::<InboundServerData>(),
         }
     }
 
@@ -270,6 +442,8 @@ impl ProxyServer {
 
                 self.report_response_services_consumed(&return_route_info, 0, msg.payload_len);
 
+                // DNS resolution errors always happen on the first request
+                let sequence_number = self.response_sequence_number(&response.stream_key, 0);
                 self.subs
                     .as_ref()
                     .expect("Dispatcher unbound in ProxyServer")
@@ -277,7 +451,7 @@ impl ProxyServer {
                     .try_send(TransmitDataMsg {
                         endpoint: Endpoint::Socket(socket_addr),
                         last_data: true,
-                        sequence_number: Some(0), // DNS resolution errors always happen on the first request
+                        sequence_number: Some(sequence_number),
                         data: from_protocol(return_route_info.protocol)
                             .server_impersonator()
                             .dns_resolution_failure_response(
@@ -288,13 +462,15 @@ impl ProxyServer {
                     .expect("Dispatcher is dead");
                 debug!(
                     self.logger,
+                    {stream_key: response.stream_key},
                     "Retiring stream key {}: DnsResolveFailure", &response.stream_key
                 );
+                count_stream_failure("dns_resolve_failure");
                 self.purge_stream_key(&response.stream_key);
             }
             None => {
                 let server_name = match &return_route_info.server_name {
-                    Some(name) => format!("\"{}\"", name),
+                    Some(name) => format!("\"{}\"", privacy::host(name)),
                     None => "<unspecified server>".to_string(),
                 };
                 error!(self.logger,
@@ -306,12 +482,261 @@ impl ProxyServer {
         }
     }
 
+    // Unlike a DNS failure, this isn't the exit Node's fault: another exit Node with the same
+    // policy would refuse just the same, so there's no point in deprioritizing this one.
+    fn handle_exit_policy_refusal(&mut self, msg: &ExpiredCoresPackage<ExitPolicyRefusal>) {
+        let return_route_info = match self.get_return_route_info(&msg.remaining_route) {
+            Some(rri) => rri,
+            None => return,
+        };
+        let refusal = &msg.payload;
+        match self.keys_and_addrs.a_to_b(&refusal.stream_key) {
+            Some(socket_addr) => {
+                self.report_response_services_consumed(&return_route_info, 0, msg.payload_len);
+                // Exit policy refusals always happen on the first request
+                let sequence_number = self.response_sequence_number(&refusal.stream_key, 0);
+                self.subs
+                    .as_ref()
+                    .expect("Dispatcher unbound in ProxyServer")
+                    .dispatcher
+                    .try_send(TransmitDataMsg {
+                        endpoint: Endpoint::Socket(socket_addr),
+                        last_data: true,
+                        sequence_number: Some(sequence_number),
+                        data: from_protocol(return_route_info.protocol)
+                            .server_impersonator()
+                            .exit_policy_refusal_response(
+                                return_route_info.server_name.clone(),
+                                &refusal.reason,
+                            ),
+                    })
+                    .expect("Dispatcher is dead");
+                debug!(
+                    self.logger,
+                    {stream_key: refusal.stream_key},
+                    "Retiring stream key {}: ExitPolicyRefusal ({})",
+                    &refusal.stream_key,
+                    privacy::data(&refusal.reason)
+                );
+                count_stream_failure("exit_policy_refusal");
+                self.purge_stream_key(&refusal.stream_key);
+            }
+            None => error!(
+                self.logger,
+                "Discarding ExitPolicyRefusal message from an unrecognized stream key {:?}",
+                &refusal.stream_key
+            ),
+        }
+    }
+
+    // The entry DNS server hands us the look-ups it doesn't answer with localhost. They travel to
+    // an exit Node like a request, under a random stream key of their own; an answer is only
+    // taken if it carries that key and comes back on the return route the query was sent with.
+    fn handle_tunneled_dns_query(&mut self, msg: TunneledDnsQuery) {
+        let query_id = msg.query_id;
+        let respond_to = msg.respond_to;
+        if self.consuming_wallet_balance.is_none() && self.is_decentralized {
+            error!(
+                self.logger,
+                "DNS query for {} rejected due to missing consuming wallet",
+                privacy::host(&msg.name)
+            );
+            ProxyServer::send_dns_failure(&respond_to, query_id);
+            return;
+        }
+        self.dns_query_responders
+            .retain(|_, responder| responder.asked.elapsed() < RETURN_ROUTE_TTL);
+        let stream_key = StreamKey::random();
+        self.dns_query_responders.insert(
+            stream_key,
+            DnsQueryResponder {
+                query_id,
+                respond_to: respond_to.clone(),
+                return_route_id_opt: None,
+                asked: Instant::now(),
+            },
+        );
+        let payload = DnsQueryPayload {
+            version: DnsQueryPayload::version(),
+            stream_key,
+            name: msg.name,
+            record_type: msg.record_type,
+            originator_public_key: self.cryptde.public_key().clone(),
+        };
+        let route_source = self.out_subs("Neighborhood").route_source.clone();
+        let hopper = self.out_subs("Hopper").hopper.clone();
+        let accountant_routing_sub = self.out_subs("Accountant").accountant_routing.clone();
+        let add_return_route_sub = self.out_subs("ProxyServer").add_return_route.clone();
+        let minimum_hop_count = if self.is_decentralized { 3 } else { 0 };
+        let cryptde = self.cryptde.dup();
+        let logger = self.logger.clone();
+        debug!(
+            logger,
+            "Getting route to look up {}: query {}",
+            privacy::host(&payload.name),
+            query_id
+        );
+        tokio::spawn(
+            route_source
+                .send(RouteQueryMessage::data_indefinite_route_request(
+                    minimum_hop_count,
+                ))
+                .then(move |route_result| {
+                    match route_result {
+                        Ok(Some(route_query_response)) => {
+                            if ProxyServer::transmit_dns_query(
+                                cryptde,
+                                &hopper,
+                                &accountant_routing_sub,
+                                &add_return_route_sub,
+                                route_query_response,
+                                payload,
+                                &logger,
+                            )
+                            .is_err()
+                            {
+                                ProxyServer::send_dns_failure(&respond_to, query_id);
+                            }
+                        }
+                        Ok(None) => {
+                            error!(
+                                logger,
+                                "Failed to find route to look up {}",
+                                privacy::host(&payload.name)
+                            );
+                            ProxyServer::send_dns_failure(&respond_to, query_id);
+                        }
+                        Err(e) => {
+                            error!(
+                                logger,
+                                "Neighborhood refused to answer route request: {}", e
+                            );
+                            ProxyServer::send_dns_failure(&respond_to, query_id);
+                        }
+                    };
+                    Ok(())
+                }),
+        );
+    }
+
+    fn transmit_dns_query(
+        cryptde: Box<dyn CryptDE>,
+        hopper: &Recipient<IncipientCoresPackage>,
+        accountant_routing_sub: &Recipient<ReportRoutingServiceConsumedMessage>,
+        add_return_route_sub: &Recipient<AddReturnRouteMessage>,
+        route_query_response: RouteQueryResponse,
+        payload: DnsQueryPayload,
+        logger: &Logger,
+    ) -> Result<(), ()> {
+        let (over, back, return_route_id) = match route_query_response.expected_services {
+            ExpectedServices::RoundTrip(over, back, return_route_id) => {
+                (over, back, return_route_id)
+            }
+            _ => panic!("Expected RoundTrip ExpectedServices but got OneWay"),
+        };
+        let payload_destination_key =
+            match ProxyServer::payload_destination_key(&over, &payload.originator_public_key) {
+                Some(key) => key,
+                None => {
+                    error!(
+                        logger,
+                        "Failed to find exit Node to look up {}",
+                        privacy::host(&payload.name)
+                    );
+                    return Err(());
+                }
+            };
+        add_return_route_sub
+            .try_send(AddReturnRouteMessage {
+                return_route_id,
+                expected_services: back,
+                // Never consulted: DNS answers aren't impersonated to a browser
+                protocol: ProxyProtocol::HTTP,
+                server_name: Some(payload.name.clone()),
+                dns_query_opt: Some(payload.stream_key),
+            })
+            .expect("ProxyServer is dead");
+        let pkg = IncipientCoresPackage::new(
+            cryptde.as_ref(),
+            route_query_response.route,
+            payload.into(),
+            &payload_destination_key,
+        )
+        .expect("Key magically disappeared");
+        ProxyServer::report_routing_service(
+            accountant_routing_sub,
+            over,
+            pkg.payload.len(),
+            logger,
+        );
+        hopper.try_send(pkg).expect("Hopper is dead");
+        Ok(())
+    }
+
+    fn send_dns_failure(respond_to: &UnboundedSender<TunneledDnsAnswer>, query_id: u32) {
+        // If the entry DNS server has gone away, there's nobody left to tell
+        let _ = respond_to.unbounded_send(TunneledDnsAnswer::new(
+            query_id,
+            SERVER_FAILURE_RESPONSE_CODE,
+            vec![],
+        ));
+    }
+
+    fn handle_dns_answer(&mut self, msg: ExpiredCoresPackage<DnsAnswerPayload>) {
+        let return_route_id_opt = match self.get_return_route_info(&msg.remaining_route) {
+            Some(return_route_info) => {
+                self.report_response_services_consumed(
+                    &return_route_info,
+                    msg.payload_len,
+                    msg.payload_len,
+                );
+                Some(return_route_info.return_route_id)
+            }
+            None => None,
+        };
+        let answer = msg.payload;
+        match self.dns_query_responders.remove(&answer.stream_key) {
+            Some(responder)
+                if return_route_id_opt.is_some()
+                    && responder.return_route_id_opt == return_route_id_opt =>
+            {
+                debug!(
+                    self.logger,
+                    "Passing {} DNS records for query {} to entry DNS server",
+                    answer.records.len(),
+                    responder.query_id
+                );
+                let _ = responder.respond_to.unbounded_send(TunneledDnsAnswer::new(
+                    responder.query_id,
+                    answer.response_code,
+                    answer.records,
+                ));
+            }
+            // The real answer may yet come back on the right route
+            Some(responder) => {
+                error!(
+                    self.logger,
+                    "Discarding DNS answer for query {} that came back on the wrong route",
+                    responder.query_id
+                );
+                self.dns_query_responders
+                    .insert(answer.stream_key, responder);
+            }
+            None => error!(
+                self.logger,
+                "Discarding DNS answer to unrecognized query {}", answer.stream_key
+            ),
+        }
+    }
+
     fn handle_client_response_payload(&mut self, msg: &ExpiredCoresPackage<ClientResponsePayload>) {
         debug!(
             self.logger,
             "ExpiredCoresPackage remaining_route: {}",
-            msg.remaining_route
-                .to_string(vec![self.cryptde, self.cryptde])
+            privacy::key(
+                msg.remaining_route
+                    .to_string(vec![self.cryptde, self.cryptde])
+            )
         );
         let payload_data_len = msg.payload_len;
         let response = &msg.payload;
@@ -326,6 +751,9 @@ impl ProxyServer {
         };
         match self.keys_and_addrs.a_to_b(&response.stream_key) {
             Some(socket_addr) => {
+                if let Some(trace_id) = self.trace_ids.get(&response.stream_key) {
+                    stream_trace::close_span(*trace_id, SpanKind::FirstResponseByte);
+                }
                 self.report_response_services_consumed(
                     &return_route_info,
                     response.sequenced_packet.data.len(),
@@ -333,10 +761,10 @@ impl ProxyServer {
                 );
 
                 let last_data = response.sequenced_packet.last_data;
-                let sequence_number = Some(
-                    response.sequenced_packet.sequence_number
-                        + self.browser_proxy_sequence_offset as u64,
-                );
+                let sequence_number = Some(self.response_sequence_number(
+                    &response.stream_key,
+                    response.sequenced_packet.sequence_number,
+                ));
                 self
                     .subs
                     .as_ref()
@@ -350,7 +778,7 @@ impl ProxyServer {
                     })
                     .expect("Dispatcher is dead");
                 if last_data {
-                    debug!(self.logger, "Retiring stream key {}: no more data", &response.stream_key);
+                    debug!(self.logger, {stream_key: response.stream_key}, "Retiring stream key {}: no more data", &response.stream_key);
                     self.purge_stream_key(&response.stream_key);
                 }
             }
@@ -364,12 +792,236 @@ impl ProxyServer {
         }
     }
 
-    fn tls_connect(&mut self, msg: &InboundClientData) {
+    fn handle_direct_server_data(&mut self, msg: InboundServerData) {
+        match self.keys_and_addrs.a_to_b(&msg.stream_key) {
+            Some(socket_addr) => {
+                debug!(
+                    self.logger,
+                    "Relaying {}-byte response (stream key {}, sequence {}) from bypassed server {} to client",
+                    msg.data.len(), msg.stream_key, msg.sequence_number, privacy::socket_addr(msg.source)
+                );
+                let sequence_number =
+                    self.response_sequence_number(&msg.stream_key, msg.sequence_number);
+                self.out_subs("Dispatcher")
+                    .dispatcher
+                    .try_send(TransmitDataMsg {
+                        endpoint: Endpoint::Socket(socket_addr),
+                        last_data: msg.last_data,
+                        sequence_number: Some(sequence_number),
+                        data: msg.data,
+                    })
+                    .expect("Dispatcher is dead");
+                if msg.last_data {
+                    debug!(
+                        self.logger,
+                        {stream_key: msg.stream_key},
+                        "Retiring stream key {}: bypassed server closed", &msg.stream_key
+                    );
+                    self.purge_stream_key(&msg.stream_key);
+                }
+            }
+            None => debug!(
+                self.logger,
+                "Discarding {}-byte packet from bypassed server {} for retired stream key {}",
+                msg.data.len(),
+                privacy::socket_addr(msg.source),
+                msg.stream_key
+            ),
+        }
+    }
+
+    // Data for streams already connected directly, or for destinations on the bypass list, never
+    // touches the Substratum Network, so it doesn't need a consuming wallet or a route.
+    fn try_bypass(&mut self, msg: &InboundClientData) -> bool {
+        let direct_streams = match &self.direct_streams_opt {
+            Some(direct_streams) => direct_streams.clone(),
+            None => return false,
+        };
+        let bypass = match self.keys_and_addrs.b_to_a(&msg.peer_addr) {
+            Some(stream_key) if direct_streams.contains(&stream_key) => true,
+            Some(stream_key) if self.tunneled_hosts.contains_key(&stream_key) => self
+                .bypass_list
+                .bypasses(&self.tunneled_hosts[&stream_key].name),
+            Some(_) => false,
+            None => match from_ibcd(msg, &self.logger)
+                .and_then(|protocol_pack| protocol_pack.find_host(&msg.data.clone().into()))
+            {
+                Some(host) => self.bypass_list.bypasses(&host.name),
+                None => false,
+            },
+        };
+        if !bypass {
+            return false;
+        }
+        let stream_key = self.make_stream_key(msg);
+        if let Ok(payload) = self.make_payload(msg.clone(), &stream_key) {
+            debug!(
+                self.logger,
+                "Bypassing the Substratum Network for stream {}: sequence {}, length {}",
+                stream_key,
+                payload.sequenced_packet.sequence_number,
+                payload.sequenced_packet.data.len()
+            );
+            direct_streams.send(payload);
+        }
+        true
+    }
+
+    // Browsers configured to use us as their proxy send absolute-URI requests to the proxy port.
+    // Those go out as the requests the servers would get from the browsers directly, and as far as
+    // the rest of the ProxyServer is concerned, they're plain HTTP.
+    fn normalize_proxy_port(&mut self, msg: InboundClientData) -> InboundClientData {
+        match (self.proxy_port_opt, msg.reception_port) {
+            (Some(proxy_port), Some(reception_port)) if proxy_port == reception_port => (),
+            _ => return msg,
+        }
+        let mut msg = InboundClientData {
+            reception_port: Some(HTTP_PORT),
+            ..msg
+        };
+        if let Some((data, host)) = HttpProtocolPack::to_origin_form(&msg.data) {
+            msg.data = data;
+            self.follow_proxy_port_host(&msg, host);
+        }
+        let request_offset = self
+            .keys_and_addrs
+            .b_to_a(&msg.peer_addr)
+            .and_then(|stream_key| self.proxy_port_streams.get(&stream_key))
+            .map(|stream| stream.request_offset)
+            .unwrap_or(0);
+        msg.sequence_number = msg
+            .sequence_number
+            .map(|sequence_number| sequence_number.saturating_sub(request_offset));
+        msg
+    }
+
+    // A browser keeping its connection to the proxy port alive may send its next request there
+    // for another host. The stream to the first host is closed, and the request opens a new one,
+    // numbered from where the first left off on the browser connection.
+    fn follow_proxy_port_host(&mut self, msg: &InboundClientData, host: Host) {
+        let old_stream_key_opt = self.keys_and_addrs.b_to_a(&msg.peer_addr);
+        let old_stream_opt =
+            old_stream_key_opt.and_then(|stream_key| self.proxy_port_streams.get(&stream_key));
+        let (request_offset, response_offset) = match old_stream_opt {
+            None if old_stream_key_opt.is_some() => return,
+            None => (0, 0),
+            Some(old_stream) if old_stream.host == host => return,
+            Some(old_stream) => (
+                msg.sequence_number.unwrap_or(0),
+                old_stream.response_offset + old_stream.responses_relayed,
+            ),
+        };
+        let stream_key = match old_stream_key_opt {
+            None => self.make_stream_key(msg),
+            Some(old_stream_key) => {
+                self.close_proxy_port_stream(&old_stream_key, msg);
+                let stream_key = self.stream_key_factory.make_fresh();
+                self.open_stream_key(stream_key, msg.peer_addr);
+                stream_key
+            }
+        };
+        self.proxy_port_streams.insert(
+            stream_key,
+            ProxyPortStream {
+                host,
+                request_offset,
+                response_offset,
+                responses_relayed: 0,
+            },
+        );
+    }
+
+    fn close_proxy_port_stream(&mut self, stream_key: &StreamKey, msg: &InboundClientData) {
+        debug!(
+            self.logger,
+            {stream_key: stream_key},
+            "Retiring stream key {}: the browser moved on to another host", stream_key
+        );
+        let request_offset = self
+            .proxy_port_streams
+            .get(stream_key)
+            .map(|stream| stream.request_offset)
+            .unwrap_or(0);
+        self.handle_normal_client_data(
+            InboundClientData {
+                peer_addr: msg.peer_addr,
+                reception_port: Some(HTTP_PORT),
+                sequence_number: msg
+                    .sequence_number
+                    .map(|sequence_number| sequence_number.saturating_sub(request_offset)),
+                last_data: true,
+                is_clandestine: false,
+                data: vec![],
+            },
+            false,
+        );
+        self.purge_stream_key(stream_key);
+    }
+
+    // Where a response goes in the sequence of its browser connection, which may have carried
+    // other streams before this one
+    fn response_sequence_number(&mut self, stream_key: &StreamKey, sequence_number: u64) -> u64 {
+        let response_offset = match self.proxy_port_streams.get_mut(stream_key) {
+            Some(stream) => {
+                stream.responses_relayed = stream.responses_relayed.max(sequence_number + 1);
+                stream.response_offset
+            }
+            None => 0,
+        };
+        sequence_number + response_offset + self.browser_proxy_sequence_offset as u64
+    }
+
+    // The TlsFramer hands us one record at a time, so a ClientHello too big for one record arrives
+    // in several messages. Those are held until the ClientHello is whole; then its server name is
+    // kept as the stream's tunnel, so that every record goes out to the same host.
+    fn assemble_client_hello(&mut self, msg: InboundClientData) -> Vec<InboundClientData> {
+        if msg.reception_port != Some(TLS_PORT) {
+            return vec![msg];
+        }
+        let stream_key_opt = self.keys_and_addrs.b_to_a(&msg.peer_addr);
+        let held_opt = stream_key_opt.and_then(|key| self.client_hello_records.remove(&key));
+        let mut records = match held_opt {
+            Some(records) => records,
+            None if stream_key_opt.is_none()
+                && !msg.last_data
+                && TlsProtocolPack::client_hello_is_incomplete(&msg.data) =>
+            {
+                vec![]
+            }
+            None => return vec![msg],
+        };
+        let stream_key = self.make_stream_key(&msg);
+        let last_data = msg.last_data;
+        records.push(msg);
+        let data = records
+            .iter()
+            .flat_map(|record| record.data.iter().cloned())
+            .collect::<Vec<u8>>();
+        if TlsProtocolPack::client_hello_is_incomplete(&data)
+            && !last_data
+            && records.len() < MAX_CLIENT_HELLO_RECORDS
+        {
+            self.client_hello_records.insert(stream_key, records);
+            return vec![];
+        }
+        let client_hello_opt = TlsProtocolPack::client_hello(&data);
+        if let Some(name) = client_hello_opt.and_then(|hello| hello.server_name_opt) {
+            self.tunneled_hosts
+                .insert(stream_key, Tunnel::new(name, TLS_PORT));
+        }
+        records
+    }
+
+    fn connect_tunnel(&mut self, msg: &InboundClientData) {
         let http_data = HttpProtocolPack {}.find_host(&msg.data.clone().into());
         match http_data {
-            Some(ref host) if host.port == Some(443) => {
+            Some(Host {
+                name,
+                port: Some(port),
+            }) => {
                 let stream_key = self.make_stream_key(&msg);
-                self.tunneled_hosts.insert(stream_key, host.name.clone());
+                self.tunneled_hosts
+                    .insert(stream_key, Tunnel::new(name, port));
                 self.subs
                     .as_ref()
                     .expect("Dispatcher unbound in ProxyServer")
@@ -406,6 +1058,7 @@ impl ProxyServer {
 
     fn handle_normal_client_data(&mut self, msg: InboundClientData, retire_stream_key: bool) {
         let route_source = self.out_subs("Neighborhood").route_source.clone();
+        let disjoint_route_source = self.out_subs("Neighborhood").disjoint_route_source.clone();
         let hopper = self.out_subs("Hopper").hopper.clone();
         let accountant_exit_sub = self.out_subs("Accountant").accountant_exit.clone();
         let accountant_routing_sub = self.out_subs("Accountant").accountant_routing.clone();
@@ -414,6 +1067,9 @@ impl ProxyServer {
         let add_route_sub = self.out_subs("ProxyServer").add_route.clone();
         let stream_shutdown_sub = self.out_subs("ProxyServer").stream_shutdown_sub.clone();
         let source_addr = msg.peer_addr;
+        if self.try_bypass(&msg) {
+            return;
+        }
         if self.consuming_wallet_balance.is_none() && self.is_decentralized {
             let protocol_pack = match from_ibcd(&msg, &self.logger) {
                 None => return,
@@ -433,19 +1089,27 @@ impl ProxyServer {
                 self.logger,
                 "Browser request rejected due to missing consuming wallet"
             );
+            count_stream_failure("no_consuming_wallet");
             return;
         }
         let stream_key = self.make_stream_key(&msg);
-        let payload = match self.make_payload(msg, &stream_key) {
+        let mut payload = match self.make_payload(msg, &stream_key) {
             Ok(payload) => payload,
             Err(_e) => {
                 return;
             }
         };
+        // A repaired stream's new route must lead to the exit Node that holds its server connection.
+        let repaired_exit_opt = self.repaired_stream_exits.remove(&stream_key);
+        payload.fresh_return_route = repaired_exit_opt.is_some();
+        let exit_key_opt = repaired_exit_opt.and_then(|exit_key_opt| exit_key_opt);
+        let trace_id_opt = self.trace_ids.get(&stream_key).cloned();
+        payload.trace_id_opt = trace_id_opt;
         let logger = self.logger.clone();
         let minimum_hop_count = if self.is_decentralized { 3 } else { 0 };
         let cryptde = self.cryptde.dup();
-        match self.stream_key_routes.get(&stream_key) {
+        let route_path_count = self.route_path_count;
+        match self.next_route_for(&stream_key) {
             Some(route_query_response) => {
                 debug!(
                     logger,
@@ -457,7 +1121,7 @@ impl ProxyServer {
                 ProxyServer::try_transmit_to_hopper(
                     cryptde,
                     &hopper,
-                    route_query_response.clone(),
+                    route_query_response,
                     payload,
                     logger,
                     source_addr,
@@ -470,26 +1134,51 @@ impl ProxyServer {
                     } else {
                         None
                     },
+                    trace_id_opt,
                 )
                 .expect("Could not transmit to hopper");
             }
-            None => {
+            None if route_path_count > 1 && minimum_hop_count > 0 => {
                 debug!(logger,
-                    "Getting route and opening new stream with key {} to transmit: sequence {}, length {}",
-                    stream_key, payload.sequenced_packet.sequence_number, payload.sequenced_packet.data.len()
+                    "Getting {} disjoint routes and opening new stream with key {} to transmit: sequence {}, length {}",
+                    route_path_count, stream_key, payload.sequenced_packet.sequence_number, payload.sequenced_packet.data.len()
                 );
+                if let Some(trace_id) = trace_id_opt {
+                    stream_trace::open_span(trace_id, SpanKind::RouteQuery);
+                }
                 tokio::spawn(
-                    route_source
-                        .send(RouteQueryMessage::data_indefinite_route_request(
-                            minimum_hop_count,
-                        ))
+                    disjoint_route_source
+                        .send(DisjointRouteQueryMessage {
+                            target_key_opt: exit_key_opt,
+                            ..DisjointRouteQueryMessage::data_indefinite_route_request_for(
+                                minimum_hop_count,
+                                route_path_count,
+                                payload.target_hostname.clone(),
+                                payload.target_port,
+                            )
+                        })
                         .then(move |route_result| {
+                            if let Some(trace_id) = trace_id_opt {
+                                stream_trace::close_span(trace_id, SpanKind::RouteQuery);
+                            }
                             match route_result {
-                                Ok(Some(route_query_response)) => {
+                                Ok(ref route_query_responses)
+                                    if route_query_responses.is_empty() =>
+                                {
+                                    ProxyServer::handle_route_failure(
+                                        payload,
+                                        &logger,
+                                        source_addr,
+                                        &dispatcher,
+                                    );
+                                }
+                                Ok(mut route_query_responses) => {
+                                    let route_query_response = route_query_responses.remove(0);
                                     add_route_sub
                                         .try_send(AddRouteMessage {
                                             stream_key,
                                             route: route_query_response.clone(),
+                                            alternate_routes: route_query_responses,
                                         })
                                         .expect("ProxyServer is dead");
                                     ProxyServer::try_transmit_to_hopper(
@@ -508,21 +1197,15 @@ impl ProxyServer {
                                         } else {
                                             None
                                         },
+                                        trace_id_opt,
                                     )
                                     .expect("Could not transmit to hopper");
                                 }
-                                Ok(None) => {
-                                    ProxyServer::handle_route_failure(
-                                        payload,
-                                        &logger,
-                                        source_addr,
-                                        &dispatcher,
-                                    );
-                                }
                                 Err(e) => {
                                     error!(
                                         logger,
-                                        "Neighborhood refused to answer route request: {}", e
+                                        "Neighborhood refused to answer disjoint route request: {}",
+                                        e
                                     );
                                 }
                             };
@@ -530,9 +1213,79 @@ impl ProxyServer {
                         }),
                 );
             }
-        }
-    }
-
+            None => {
+                debug!(logger,
+                    "Getting route and opening new stream with key {} to transmit: sequence {}, length {}",
+                    stream_key, payload.sequenced_packet.sequence_number, payload.sequenced_packet.data.len()
+                );
+                if let Some(trace_id) = trace_id_opt {
+                    stream_trace::open_span(trace_id, SpanKind::RouteQuery);
+                }
+                tokio::spawn(
+                    route_source
+                        .send(RouteQueryMessage {
+                            target_key_opt: exit_key_opt,
+                            ..RouteQueryMessage::data_indefinite_route_request_for(
+                                minimum_hop_count,
+                                payload.target_hostname.clone(),
+                                payload.target_port,
+                            )
+                        })
+                        .then(move |route_result| {
+                            if let Some(trace_id) = trace_id_opt {
+                                stream_trace::close_span(trace_id, SpanKind::RouteQuery);
+                            }
+                            match route_result {
+                                Ok(Some(route_query_response)) => {
+                                    add_route_sub
+                                        .try_send(AddRouteMessage {
+                                            stream_key,
+                                            route: route_query_response.clone(),
+                                            alternate_routes: vec![],
+                                        })
+                                        .expect("ProxyServer is dead");
+                                    ProxyServer::try_transmit_to_hopper(
+                                        cryptde,
+                                        &hopper,
+                                        route_query_response,
+                                        payload,
+                                        logger,
+                                        source_addr,
+                                        &dispatcher,
+                                        &accountant_exit_sub,
+                                        &accountant_routing_sub,
+                                        &add_return_route_sub,
+                                        if retire_stream_key {
+                                            Some(&stream_shutdown_sub)
+                                        } else {
+                                            None
+                                        },
+                                        trace_id_opt,
+                                    )
+                                    .expect("Could not transmit to hopper");
+                                }
+                                Ok(None) => {
+                                    ProxyServer::handle_route_failure(
+                                        payload,
+                                        &logger,
+                                        source_addr,
+                                        &dispatcher,
+                                    );
+                                }
+                                Err(e) => {
+                                    error!(
+                                        logger,
+                                        "Neighborhood refused to answer route request: {}", e
+                                    );
+                                }
+                            };
+                            Ok(())
+                        }),
+                );
+            }
+        }
+    }
+
     fn handle_stream_shutdown_msg(&mut self, msg: StreamShutdownMsg) {
         let nca = match msg.stream_type {
             RemovedStreamType::Clandestine => {
@@ -546,7 +1299,7 @@ impl ProxyServer {
                 warning!(
                     self.logger,
                     "Received instruction to shut down nonexistent stream to peer {} - ignoring",
-                    msg_peer_addr
+                    privacy::socket_addr(msg_peer_addr)
                 );
                 return;
             }
@@ -557,19 +1310,21 @@ impl ProxyServer {
                 self.logger,
                 "Reporting shutdown of {} to counterpart", &stream_key
             );
-            let ibcd = InboundClientData {
+            let ibcd = self.normalize_proxy_port(InboundClientData {
                 peer_addr: msg.peer_addr,
                 reception_port: Some(nca.reception_port),
                 last_data: true,
                 is_clandestine: false,
                 sequence_number: Some(nca.sequence_number),
                 data: vec![],
-            };
+            });
             self.handle_normal_client_data(ibcd, true);
         } else {
             debug!(
                 self.logger,
-                "Retiring stream key {}: StreamShutdownMsg for peer {}", &stream_key, msg_peer_addr
+                "Retiring stream key {}: StreamShutdownMsg for peer {}",
+                &stream_key,
+                privacy::socket_addr(msg_peer_addr)
             );
             self.purge_stream_key(&stream_key);
         }
@@ -582,7 +1337,7 @@ impl ProxyServer {
                     self.logger,
                     "make_stream_key() retrieved existing key {} for {}",
                     &stream_key,
-                    ibcd.peer_addr
+                    privacy::socket_addr(ibcd.peer_addr)
                 );
                 stream_key
             }
@@ -590,20 +1345,134 @@ impl ProxyServer {
                 let stream_key = self
                     .stream_key_factory
                     .make(&self.cryptde.public_key(), ibcd.peer_addr);
-                self.keys_and_addrs.insert(stream_key, ibcd.peer_addr);
-                debug!(
-                    self.logger,
-                    "make_stream_key() inserted new key {} for {}", &stream_key, ibcd.peer_addr
-                );
+                self.open_stream_key(stream_key, ibcd.peer_addr);
                 stream_key
             }
         }
     }
 
+    fn open_stream_key(&mut self, stream_key: StreamKey, peer_addr: SocketAddr) {
+        self.keys_and_addrs.insert(stream_key, peer_addr);
+        if let Some(trace_id) = stream_trace::begin(&stream_key) {
+            self.trace_ids.insert(stream_key, trace_id);
+        }
+        metrics::increment(&PROXY_SERVER_STREAMS_OPENED, &[], 1);
+        debug!(
+            self.logger,
+            "make_stream_key() inserted new key {} for {}",
+            &stream_key,
+            privacy::socket_addr(peer_addr)
+        );
+    }
+
     fn purge_stream_key(&mut self, stream_key: &StreamKey) {
         let _ = self.keys_and_addrs.remove_a(stream_key);
         let _ = self.stream_key_routes.remove(stream_key);
+        let _ = self.stream_key_paths.remove(stream_key);
+        let _ = self.repaired_stream_exits.remove(stream_key);
         let _ = self.tunneled_hosts.remove(stream_key);
+        let _ = self.client_hello_records.remove(stream_key);
+        let _ = self.proxy_port_streams.remove(stream_key);
+        if let Some(direct_streams) = &self.direct_streams_opt {
+            direct_streams.retire(stream_key);
+        }
+        if let Some(trace_id) = self.trace_ids.remove(stream_key) {
+            stream_trace::finish(trace_id);
+        }
+    }
+
+    // The stream keys and the browser connections behind them survive; the next request on each
+    // broken stream will find itself without a route and ask the Neighborhood for a new one to the
+    // same exit Node. A stream whose exit Node was lost has lost its server connection with it.
+    fn handle_invalidate_routes(&mut self, public_key: &PublicKey) {
+        let broken_stream_keys = self
+            .stream_key_routes
+            .iter()
+            .filter(|(stream_key, route)| {
+                ProxyServer::route_passes_through(route, public_key)
+                    || match self.stream_key_paths.get(*stream_key) {
+                        Some(stream_paths) => stream_paths
+                            .routes
+                            .iter()
+                            .any(|route| ProxyServer::route_passes_through(route, public_key)),
+                        None => false,
+                    }
+            })
+            .map(|(stream_key, _)| *stream_key)
+            .collect::<Vec<StreamKey>>();
+        broken_stream_keys.into_iter().for_each(|stream_key| {
+            let exit_key_opt = self
+                .stream_key_routes
+                .get(&stream_key)
+                .and_then(ProxyServer::exit_key);
+            if exit_key_opt.as_ref() == Some(public_key) {
+                self.retire_stream_with_lost_exit(&stream_key, public_key);
+                return;
+            }
+            info!(
+                self.logger,
+                "Route for stream {} passed through lost Node {}; rerouting",
+                stream_key,
+                privacy::key(public_key)
+            );
+            let _ = self.stream_key_routes.remove(&stream_key);
+            let _ = self.stream_key_paths.remove(&stream_key);
+            self.repaired_stream_exits.insert(stream_key, exit_key_opt);
+        });
+    }
+
+    fn retire_stream_with_lost_exit(&mut self, stream_key: &StreamKey, exit_key: &PublicKey) {
+        info!(
+            self.logger,
+            "Exit Node {} for stream {} was lost; closing the stream",
+            privacy::key(exit_key),
+            stream_key
+        );
+        if let Some(socket_addr) = self.keys_and_addrs.a_to_b(stream_key) {
+            self.out_subs("Dispatcher")
+                .dispatcher
+                .try_send(TransmitDataMsg {
+                    endpoint: Endpoint::Socket(socket_addr),
+                    last_data: true,
+                    sequence_number: None,
+                    data: vec![],
+                })
+                .expect("Dispatcher is dead");
+        }
+        count_stream_failure("exit_lost");
+        self.purge_stream_key(stream_key);
+    }
+
+    fn exit_key(route: &RouteQueryResponse) -> Option<PublicKey> {
+        match &route.expected_services {
+            ExpectedServices::RoundTrip(over, _, _) => {
+                over.iter().find_map(|service| match service {
+                    ExpectedService::Exit(key, _, _) => Some(key.clone()),
+                    _ => None,
+                })
+            }
+            ExpectedServices::OneWay(_) => None,
+        }
+    }
+
+    fn route_passes_through(route: &RouteQueryResponse, public_key: &PublicKey) -> bool {
+        let services = match &route.expected_services {
+            ExpectedServices::OneWay(services) => services.iter().collect::<Vec<_>>(),
+            ExpectedServices::RoundTrip(over, back, _) => over.iter().chain(back.iter()).collect(),
+        };
+        services.into_iter().any(|service| match service {
+            ExpectedService::Routing(key, _, _) | ExpectedService::Exit(key, _, _) => {
+                key == public_key
+            }
+            ExpectedService::Nothing => false,
+        })
+    }
+
+    fn next_route_for(&mut self, stream_key: &StreamKey) -> Option<RouteQueryResponse> {
+        match self.stream_key_paths.get_mut(stream_key) {
+            Some(stream_paths) => Some(stream_paths.next_route()),
+            None => self.stream_key_routes.get(stream_key).cloned(),
+        }
     }
 
     fn make_payload(
@@ -611,28 +1480,38 @@ impl ProxyServer {
         ibcd: InboundClientData,
         stream_key: &StreamKey,
     ) -> Result<ClientRequestPayload, ()> {
-        let tunnelled_host = self.tunneled_hosts.get(stream_key);
-        let new_ibcd = match tunnelled_host {
-            Some(_) => InboundClientData {
-                reception_port: Some(443),
-                ..ibcd
-            },
-            None => ibcd.clone(),
+        let tunnel_opt = match self.tunneled_hosts.get_mut(stream_key) {
+            Some(tunnel) => {
+                let protocol = tunnel.protocol(&ibcd.data);
+                Some((tunnel.name.clone(), tunnel.port, protocol))
+            }
+            None => None,
         };
-        match self.client_request_payload_factory.make(
-            &new_ibcd,
-            stream_key.clone(),
-            self.cryptde,
-            &self.logger,
-        ) {
+        let payload_opt = match &tunnel_opt {
+            Some((_, _, protocol)) => self.client_request_payload_factory.make_for(
+                from_protocol(*protocol).as_ref(),
+                &ibcd,
+                stream_key.clone(),
+                self.cryptde,
+                &self.logger,
+            ),
+            None => self.client_request_payload_factory.make(
+                &ibcd,
+                stream_key.clone(),
+                self.cryptde,
+                &self.logger,
+            ),
+        };
+        match payload_opt {
             None => {
                 error!(self.logger, "Couldn't create ClientRequestPayload");
                 Err(())
             }
-            Some(payload) => match tunnelled_host {
-                Some(hostname) => Ok(ClientRequestPayload {
+            Some(payload) => match tunnel_opt {
+                Some((hostname, port, _)) => Ok(ClientRequestPayload {
                     version: ClientRequestPayload::version(),
-                    target_hostname: Some(hostname.clone()),
+                    target_hostname: Some(hostname),
+                    target_port: port,
                     ..payload
                 }),
                 None => Ok(payload),
@@ -652,6 +1531,7 @@ impl ProxyServer {
         accountant_routing_sub: &Recipient<ReportRoutingServiceConsumedMessage>,
         add_return_route_sub: &Recipient<AddReturnRouteMessage>,
         retire_stream_key_via: Option<&Recipient<StreamShutdownMsg>>,
+        trace_id_opt: Option<TraceId>,
     ) -> Result<(), ()> {
         match route_query_response.expected_services {
             ExpectedServices::RoundTrip(over, back, return_route_id) => {
@@ -660,11 +1540,19 @@ impl ProxyServer {
                     expected_services: back.clone(),
                     protocol: payload.protocol,
                     server_name: payload.target_hostname.clone(),
+                    dns_query_opt: None,
                 };
-                debug!(
-                    logger,
-                    "Adding expectant return route info: {:?}", return_route_info
-                );
+                if privacy::privacy_logging() {
+                    debug!(
+                        logger,
+                        "Adding expectant return route info for return route {}", return_route_id
+                    );
+                } else {
+                    debug!(
+                        logger,
+                        "Adding expectant return route info: {:?}", return_route_info
+                    );
+                }
                 add_return_route_sub
                     .try_send(return_route_info)
                     .expect("ProxyServer is dead");
@@ -685,6 +1573,7 @@ impl ProxyServer {
                     dispatcher,
                     accountant_routing_sub,
                     retire_stream_key_via,
+                    trace_id_opt,
                 );
             }
             _ => panic!("Expected RoundTrip ExpectedServices but got OneWay"),
@@ -766,43 +1655,39 @@ impl ProxyServer {
         dispatcher: &Recipient<TransmitDataMsg>,
         accountant_routing_sub: &Recipient<ReportRoutingServiceConsumedMessage>,
         retire_stream_key_via: Option<&Recipient<StreamShutdownMsg>>,
+        trace_id_opt: Option<TraceId>,
     ) {
-        let destination_key_opt = if !expected_services.is_empty()
-            && expected_services
-                .iter()
-                .all(|expected_service| match expected_service {
-                    ExpectedService::Nothing => true,
-                    _ => false,
-                }) {
-            Some(payload.originator_public_key.clone())
-        } else {
-            expected_services.iter().find_map(|service| match service {
-                ExpectedService::Exit(public_key, _, _) => Some(public_key.clone()),
-                _ => None,
-            })
-        };
+        let destination_key_opt = ProxyServer::payload_destination_key(
+            &expected_services,
+            &payload.originator_public_key,
+        );
 
         match destination_key_opt {
             None => ProxyServer::handle_route_failure(payload, &logger, source_addr, dispatcher),
             Some(payload_destination_key) => {
                 debug!(
                     logger,
-                    "transmit to hopper with destination key {:?}", payload_destination_key
+                    "transmit to hopper with destination key {:?}",
+                    privacy::key(&payload_destination_key)
                 );
                 let stream_key = payload.stream_key;
-                let pkg = IncipientCoresPackage::new(
+                let mut pkg = IncipientCoresPackage::new(
                     cryptde.as_ref(),
                     route.clone(),
                     payload.into(),
                     &payload_destination_key,
                 )
                 .expect("Key magically disappeared");
+                pkg.trace_id_opt = trace_id_opt;
                 ProxyServer::report_routing_service(
                     accountant_routing_sub,
                     expected_services,
                     pkg.payload.len(),
                     &logger,
                 );
+                if let Some(trace_id) = trace_id_opt {
+                    stream_trace::open_span(trace_id, SpanKind::HopperTransmit);
+                }
                 hopper.try_send(pkg).expect("Hopper is dead");
                 if let Some(shutdown_sub) = retire_stream_key_via {
                     debug!(
@@ -827,6 +1712,28 @@ impl ProxyServer {
         }
     }
 
+    // In zero-hop mode the payload is for this Node itself; otherwise it's for the exit Node.
+    fn payload_destination_key(
+        expected_services: &[ExpectedService],
+        originator_public_key: &PublicKey,
+    ) -> Option<PublicKey> {
+        if !expected_services.is_empty()
+            && expected_services
+                .iter()
+                .all(|expected_service| match expected_service {
+                    ExpectedService::Nothing => true,
+                    _ => false,
+                })
+        {
+            Some(originator_public_key.clone())
+        } else {
+            expected_services.iter().find_map(|service| match service {
+                ExpectedService::Exit(public_key, _, _) => Some(public_key.clone()),
+                _ => None,
+            })
+        }
+    }
+
     fn handle_route_failure(
         payload: ClientRequestPayload,
         logger: &Logger,
@@ -835,7 +1742,12 @@ impl ProxyServer {
     ) {
         let target_hostname = ProxyServer::hostname(&payload);
         ProxyServer::send_route_failure(payload, source_addr, dispatcher);
-        error!(logger, "Failed to find route to {}", target_hostname);
+        error!(
+            logger,
+            "Failed to find route to {}",
+            privacy::host(target_hostname)
+        );
+        count_stream_failure("no_route");
     }
 
     fn send_route_failure(
@@ -922,8 +1834,80 @@ impl ProxyServer {
     }
 }
 
+// A stream for a browser using us as its proxy: the host it goes to, and where its packets start
+// in the sequences of its browser connection, which may have carried other streams before it
+struct ProxyPortStream {
+    host: Host,
+    request_offset: u64,
+    response_offset: u64,
+    responses_relayed: u64,
+}
+
+// The routes over which a multipath stream's requests are dealt out in turn. They all end at the
+// same exit Node, which reassembles the stream by sequence number.
+struct StreamPaths {
+    routes: Vec<RouteQueryResponse>,
+    next_index: usize,
+}
+
+impl StreamPaths {
+    fn new(routes: Vec<RouteQueryResponse>) -> StreamPaths {
+        StreamPaths {
+            routes,
+            next_index: 0,
+        }
+    }
+
+    fn next_route(&mut self) -> RouteQueryResponse {
+        let route = self.routes[self.next_index].clone();
+        self.next_index = (self.next_index + 1) % self.routes.len();
+        route
+    }
+}
+
+// Where the answer to a tunneled DNS query goes, once the query has been given a return route
+struct DnsQueryResponder {
+    query_id: u32,
+    respond_to: UnboundedSender<TunneledDnsAnswer>,
+    return_route_id_opt: Option<u32>,
+    asked: Instant,
+}
+
+// A stream the browser opened with CONNECT, or whose ClientHello came in pieces. What travels
+// through it is decided by the first data the browser sends: a TLS handshake, or anything else,
+// which goes out raw. Nothing says it's HTTP, so it mustn't be answered with HTTP error pages.
+struct Tunnel {
+    name: String,
+    port: u16,
+    protocol_opt: Option<ProxyProtocol>,
+}
+
+impl Tunnel {
+    fn new(name: String, port: u16) -> Tunnel {
+        Tunnel {
+            name,
+            port,
+            protocol_opt: None,
+        }
+    }
+
+    fn protocol(&mut self, data: &[u8]) -> ProxyProtocol {
+        let port = self.port;
+        *self.protocol_opt.get_or_insert_with(|| {
+            if port == TLS_PORT || data.starts_with(&[0x16, 0x03]) {
+                ProxyProtocol::TLS
+            } else {
+                ProxyProtocol::Raw
+            }
+        })
+    }
+}
+
 trait StreamKeyFactory: Send {
     fn make(&self, public_key: &PublicKey, peer_addr: SocketAddr) -> StreamKey;
+    // For a stream that takes over a browser connection from another, whose key is already
+    // derived from the connection
+    fn make_fresh(&self) -> StreamKey;
 }
 
 struct StreamKeyFactoryReal {}
@@ -933,6 +1917,14 @@ impl StreamKeyFactory for StreamKeyFactoryReal {
         // TODO: Replace this implementation
         StreamKey::new(public_key.clone(), peer_addr)
     }
+
+    fn make_fresh(&self) -> StreamKey {
+        StreamKey::random()
+    }
+}
+
+fn count_stream_failure(reason: &str) {
+    metrics::increment(&PROXY_SERVER_STREAMS_FAILED, &[("reason", reason)], 1);
 }
 
 #[cfg(test)]
@@ -940,6 +1932,7 @@ mod tests {
     use super::*;
     use crate::blockchain::blockchain_interface::contract_address;
     use crate::persistent_configuration::{HTTP_PORT, TLS_PORT};
+    use crate::proxy_client::local_test_utils::{ResolverWrapperFactoryMock, ResolverWrapperMock};
     use crate::proxy_server::protocol_pack::ServerImpersonator;
     use crate::proxy_server::server_impersonator_http::ServerImpersonatorHttp;
     use crate::proxy_server::server_impersonator_tls::ServerImpersonatorTls;
@@ -954,7 +1947,10 @@ mod tests {
     use crate::sub_lib::hopper::MessageType;
     use crate::sub_lib::neighborhood::ExpectedServices;
     use crate::sub_lib::neighborhood::{ExpectedService, DEFAULT_RATE_PACK};
-    use crate::sub_lib::proxy_client::{ClientResponsePayload, DnsResolveFailure};
+    use crate::sub_lib::proxy_client::{
+        ClientResponsePayload, DnsResolveFailure, ExitPolicyRefusal,
+    };
+    use crate::sub_lib::proxy_client::{DnsRecord, DnsRecordData};
     use crate::sub_lib::proxy_server::ClientRequestPayload;
     use crate::sub_lib::proxy_server::ProxyProtocol;
     use crate::sub_lib::route::Route;
@@ -974,6 +1970,7 @@ mod tests {
     use crate::test_utils::{make_meaningless_route, make_paying_wallet};
     use crate::test_utils::{make_meaningless_stream_key, DEFAULT_CHAIN_ID};
     use actix::System;
+    use futures::Stream;
     use std::cell::RefCell;
     use std::net::SocketAddr;
     use std::str::FromStr;
@@ -982,6 +1979,7 @@ mod tests {
     use std::sync::Mutex;
     use std::sync::MutexGuard;
     use std::thread;
+    use trust_dns_resolver::error::ResolveErrorKind;
 
     const STANDARD_CONSUMING_WALLET_BALANCE: i64 = 0;
 
@@ -997,6 +1995,7 @@ mod tests {
                     .clone()
                     .recipient::<ReportRoutingServiceConsumedMessage>(),
                 route_source: addr.clone().recipient::<RouteQueryMessage>(),
+                disjoint_route_source: addr.clone().recipient::<DisjointRouteQueryMessage>(),
                 update_node_record_metadata: addr.clone().recipient::<NodeRecordMetadataMessage>(),
                 add_return_route: addr.clone().recipient::<AddReturnRouteMessage>(),
                 add_route: addr.clone().recipient::<AddRouteMessage>(),
@@ -1018,6 +2017,10 @@ mod tests {
                 .push((key.clone(), peer_addr));
             self.make_results.borrow_mut().remove(0)
         }
+
+        fn make_fresh(&self) -> StreamKey {
+            self.make_results.borrow_mut().remove(0)
+        }
     }
 
     impl StreamKeyFactoryMock {
@@ -1059,11 +2062,7 @@ mod tests {
         encodex(
             cryptde,
             &cryptde.public_key(),
-            &LiveHop {
-                public_key: cryptde.public_key().clone(),
-                payer: None,
-                component: Component::ProxyServer,
-            },
+            &LiveHop::new(cryptde.public_key(), None, Component::ProxyServer),
         )
         .unwrap()
     }
@@ -1139,6 +2138,9 @@ mod tests {
             target_port: HTTP_PORT,
             protocol: ProxyProtocol::HTTP,
             originator_public_key: key.clone(),
+            fresh_return_route: false,
+            alpn_protocols: vec![],
+            trace_id_opt: None,
         };
         let expected_pkg =
             IncipientCoresPackage::new(cryptde, route.clone(), expected_payload.into(), &key)
@@ -1178,7 +2180,14 @@ mod tests {
         );
         let recording = neighborhood_recording_arc.lock().unwrap();
         let record = recording.get_record::<RouteQueryMessage>(0);
-        assert_eq!(record, &RouteQueryMessage::data_indefinite_route_request(0));
+        assert_eq!(
+            record,
+            &RouteQueryMessage::data_indefinite_route_request_for(
+                0,
+                Some("nowhere.com".to_string()),
+                HTTP_PORT
+            )
+        );
         let recording = proxy_server_recording_arc.lock().unwrap();
         assert_eq!(recording.len(), 0);
     }
@@ -1236,6 +2245,9 @@ mod tests {
             target_port: 443,
             protocol: ProxyProtocol::TLS,
             originator_public_key: key.clone(),
+            fresh_return_route: false,
+            alpn_protocols: vec![],
+            trace_id_opt: None,
         };
         let expected_pkg =
             IncipientCoresPackage::new(cryptde, route.clone(), expected_payload.into(), &key)
@@ -1287,7 +2299,11 @@ mod tests {
         let neighborhood_record = neighborhood_recording.get_record::<RouteQueryMessage>(0);
         assert_eq!(
             neighborhood_record,
-            &RouteQueryMessage::data_indefinite_route_request(0)
+            &RouteQueryMessage::data_indefinite_route_request_for(
+                0,
+                Some("realdomain.nu".to_string()),
+                443
+            )
         );
     }
 
@@ -1311,6 +2327,7 @@ mod tests {
                 expected_services: vec![ExpectedService::Nothing],
                 protocol: ProxyProtocol::TLS,
                 server_name: None,
+                dns_query_opt: None,
             },
         );
         let subject_addr: Addr<ProxyServer> = subject.start();
@@ -1363,42 +2380,63 @@ mod tests {
     }
 
     #[test]
-    fn proxy_server_sends_route_failure_for_connect_requests_to_ports_other_than_443() {
+    fn proxy_server_tunnels_connect_requests_to_ports_other_than_443() {
         let cryptde = cryptde();
-        let http_request = b"CONNECT https://realdomain.nu:8443 HTTP/1.1\r\nHost: https://bunkjunk.wrong:443\r\n\r\n";
-
-        let (hopper_mock, _hopper_awaiter, _hopper_recording_arc) = make_recorder();
-        let (neighborhood_mock, _, _neighborhood_recording_arc) = make_recorder();
-        let (dispatcher_mock, _dispatcher_awaiter, dispatcher_recording_arc) = make_recorder();
-
+        let key = cryptde.public_key();
+        let http_request =
+            b"CONNECT realdomain.nu:8080 HTTP/1.1\r\nHost: realdomain.nu:8080\r\n\r\n";
+        let (hopper_mock, hopper_awaiter, hopper_recording_arc) = make_recorder();
+        let (neighborhood_mock, _, neighborhood_recording_arc) = make_recorder();
         let neighborhood_mock = neighborhood_mock.route_query_response(Some(
             zero_hop_route_response(&cryptde.public_key(), cryptde),
         ));
-
+        let route = zero_hop_route_response(&key, cryptde).route;
+        let (dispatcher_mock, _, dispatcher_recording_arc) = make_recorder();
         let socket_addr = SocketAddr::from_str("1.2.3.4:5678").unwrap();
         let stream_key = make_meaningless_stream_key();
-        let request_data = http_request.to_vec();
         let msg_from_dispatcher = InboundClientData {
             peer_addr: socket_addr.clone(),
-            reception_port: Some(8443),
+            reception_port: Some(8888),
             sequence_number: Some(0),
             last_data: false,
             is_clandestine: false,
-            data: request_data.clone(),
+            data: http_request.to_vec(),
         };
-
-        let stream_key_parameters_arc = Arc::new(Mutex::new(vec![]));
-        let stream_key_parameters_arc_thread = stream_key_parameters_arc.clone();
+        let tunnelled_msg = InboundClientData {
+            peer_addr: socket_addr.clone(),
+            reception_port: Some(8888),
+            sequence_number: Some(0),
+            last_data: false,
+            is_clandestine: false,
+            data: b"GET /index.html HTTP/1.1\r\nHost: realdomain.nu:8080\r\n\r\n".to_vec(),
+        };
+        let expected_payload = ClientRequestPayload {
+            version: ClientRequestPayload::version(),
+            stream_key: stream_key.clone(),
+            sequenced_packet: SequencedPacket {
+                data: b"GET /index.html HTTP/1.1\r\nHost: realdomain.nu:8080\r\n\r\n".to_vec(),
+                sequence_number: 0,
+                last_data: false,
+            },
+            target_hostname: Some(String::from("realdomain.nu")),
+            target_port: 8080,
+            protocol: ProxyProtocol::Raw,
+            originator_public_key: key.clone(),
+            fresh_return_route: false,
+            alpn_protocols: vec![],
+            trace_id_opt: None,
+        };
+        let expected_pkg =
+            IncipientCoresPackage::new(cryptde, route.clone(), expected_payload.into(), &key)
+                .unwrap();
 
         thread::spawn(move || {
-            let stream_key_factory = StreamKeyFactoryMock::new()
-                .make_parameters(&stream_key_parameters_arc_thread)
-                .make_result(stream_key);
-            let system = System::new(
-                "proxy_server_receives_connect_responds_with_ok_and_stores_stream_key_and_hostname",
-            );
+            let stream_key_factory = StreamKeyFactoryMock::new().make_result(stream_key);
+            let system =
+                System::new("proxy_server_tunnels_connect_requests_to_ports_other_than_443");
             let mut subject =
                 ProxyServer::new(cryptde, false, Some(STANDARD_CONSUMING_WALLET_BALANCE));
+            subject.set_proxy_port(Some(8888));
             subject.stream_key_factory = Box::new(stream_key_factory);
             let subject_addr: Addr<ProxyServer> = subject.start();
             let mut peer_actors = peer_actors_builder()
@@ -1410,38 +2448,327 @@ mod tests {
             subject_addr.try_send(BindMessage { peer_actors }).unwrap();
 
             subject_addr.try_send(msg_from_dispatcher).unwrap();
+            subject_addr.try_send(tunnelled_msg).unwrap();
             system.run();
         });
 
-        thread::sleep(Duration::from_millis(500));
-
-        let expected_transmit_data_msg = TransmitDataMsg {
-            endpoint: Endpoint::Socket(socket_addr),
-            last_data: true,
-            sequence_number: Some(0),
-            data: b"HTTP/1.1 400 Bad Request\r\nContent-Length: 0\r\n\r\n".to_vec(),
-        };
-
+        hopper_awaiter.await_message_count(1);
         let dispatcher_recording = dispatcher_recording_arc.lock().unwrap();
-        let record = dispatcher_recording.get_record::<TransmitDataMsg>(0);
-
-        assert_eq!(record, &expected_transmit_data_msg);
-    }
-
-    #[test]
-    fn proxy_server_sends_error_and_shuts_down_stream_when_connect_host_unparseable() {
-        let cryptde = cryptde();
-        let http_request = "CONNECT λ:🥓:λ HTTP/1.1\r\nHost: 🥓:🥔:🥔\r\n\r\n".as_bytes();
-
-        let (hopper_mock, _hopper_awaiter, _hopper_recording_arc) = make_recorder();
-        let (neighborhood_mock, _, _neighborhood_recording_arc) = make_recorder();
-        let (dispatcher_mock, _dispatcher_awaiter, dispatcher_recording_arc) = make_recorder();
-
-        let neighborhood_mock = neighborhood_mock.route_query_response(Some(
-            zero_hop_route_response(&cryptde.public_key(), cryptde),
-        ));
-
-        let socket_addr = SocketAddr::from_str("1.2.3.4:5678").unwrap();
+        let dispatcher_record = dispatcher_recording.get_record::<TransmitDataMsg>(0);
+        assert_eq!(
+            dispatcher_record,
+            &TransmitDataMsg {
+                endpoint: Endpoint::Socket(socket_addr),
+                last_data: false,
+                sequence_number: Some(0),
+                data: b"HTTP/1.1 200 OK\r\n\r\n".to_vec(),
+            }
+        );
+        let hopper_recording = hopper_recording_arc.lock().unwrap();
+        let hopper_record = hopper_recording.get_record::<IncipientCoresPackage>(0);
+        assert_eq!(hopper_record, &expected_pkg);
+        let neighborhood_recording = neighborhood_recording_arc.lock().unwrap();
+        let neighborhood_record = neighborhood_recording.get_record::<RouteQueryMessage>(0);
+        assert_eq!(
+            neighborhood_record,
+            &RouteQueryMessage::data_indefinite_route_request_for(
+                0,
+                Some("realdomain.nu".to_string()),
+                8080
+            )
+        );
+    }
+
+    #[test]
+    fn proxy_server_closes_a_raw_tunnel_without_an_error_page_when_there_is_no_route() {
+        let cryptde = cryptde();
+        let neighborhood_mock = Recorder::new().route_query_response(None);
+        let dispatcher_mock = Recorder::new();
+        let dispatcher_awaiter = dispatcher_mock.get_awaiter();
+        let dispatcher_recording_arc = dispatcher_mock.get_recording();
+        let socket_addr = SocketAddr::from_str("1.2.3.4:5678").unwrap();
+        let connect_msg = InboundClientData {
+            peer_addr: socket_addr.clone(),
+            reception_port: Some(8888),
+            sequence_number: Some(0),
+            last_data: false,
+            is_clandestine: false,
+            data: b"CONNECT realdomain.nu:6667 HTTP/1.1\r\nHost: realdomain.nu:6667\r\n\r\n"
+                .to_vec(),
+        };
+        let tunnelled_msg = InboundClientData {
+            peer_addr: socket_addr.clone(),
+            reception_port: Some(8888),
+            sequence_number: Some(0),
+            last_data: false,
+            is_clandestine: false,
+            data: b"NICK booga\r\n".to_vec(),
+        };
+
+        thread::spawn(move || {
+            let stream_key_factory =
+                StreamKeyFactoryMock::new().make_result(make_meaningless_stream_key());
+            let system = System::new(
+                "proxy_server_closes_a_raw_tunnel_without_an_error_page_when_there_is_no_route",
+            );
+            let mut subject =
+                ProxyServer::new(cryptde, false, Some(STANDARD_CONSUMING_WALLET_BALANCE));
+            subject.set_proxy_port(Some(8888));
+            subject.stream_key_factory = Box::new(stream_key_factory);
+            let subject_addr: Addr<ProxyServer> = subject.start();
+            let mut peer_actors = peer_actors_builder()
+                .dispatcher(dispatcher_mock)
+                .neighborhood(neighborhood_mock)
+                .build();
+            peer_actors.proxy_server = ProxyServer::make_subs_from(&subject_addr);
+            subject_addr.try_send(BindMessage { peer_actors }).unwrap();
+
+            subject_addr.try_send(connect_msg).unwrap();
+            subject_addr.try_send(tunnelled_msg).unwrap();
+            system.run();
+        });
+
+        dispatcher_awaiter.await_message_count(2);
+        let dispatcher_recording = dispatcher_recording_arc.lock().unwrap();
+        assert_eq!(
+            dispatcher_recording.get_record::<TransmitDataMsg>(1),
+            &TransmitDataMsg {
+                endpoint: Endpoint::Socket(socket_addr),
+                last_data: true,
+                sequence_number: Some(0),
+                data: vec![],
+            }
+        );
+    }
+
+    #[test]
+    fn proxy_server_handles_absolute_uri_requests_arriving_on_the_proxy_port() {
+        let cryptde = cryptde();
+        let key = cryptde.public_key();
+        let http_request = b"GET http://nowhere.com:8080/index.html HTTP/1.1\r\nHost: nowhere.com:8080\r\nProxy-Connection: keep-alive\r\n\r\n";
+        let origin_form_request = b"GET /index.html HTTP/1.1\r\nHost: nowhere.com:8080\r\n\r\n";
+        let (hopper_mock, hopper_awaiter, hopper_recording_arc) = make_recorder();
+        let (neighborhood_mock, _, _) = make_recorder();
+        let neighborhood_mock = neighborhood_mock.route_query_response(Some(
+            zero_hop_route_response(&cryptde.public_key(), cryptde),
+        ));
+        let route = zero_hop_route_response(&key, cryptde).route;
+        let socket_addr = SocketAddr::from_str("1.2.3.4:5678").unwrap();
+        let stream_key = make_meaningless_stream_key();
+        let msg_from_dispatcher = InboundClientData {
+            peer_addr: socket_addr.clone(),
+            reception_port: Some(8888),
+            sequence_number: Some(0),
+            last_data: false,
+            is_clandestine: false,
+            data: http_request.to_vec(),
+        };
+        let expected_payload = ClientRequestPayload {
+            version: ClientRequestPayload::version(),
+            stream_key: stream_key.clone(),
+            sequenced_packet: SequencedPacket {
+                data: origin_form_request.to_vec(),
+                sequence_number: 0,
+                last_data: false,
+            },
+            target_hostname: Some(String::from("nowhere.com")),
+            target_port: 8080,
+            protocol: ProxyProtocol::HTTP,
+            originator_public_key: key.clone(),
+            fresh_return_route: false,
+            alpn_protocols: vec![],
+            trace_id_opt: None,
+        };
+        let expected_pkg =
+            IncipientCoresPackage::new(cryptde, route.clone(), expected_payload.into(), &key)
+                .unwrap();
+
+        thread::spawn(move || {
+            let stream_key_factory = StreamKeyFactoryMock::new().make_result(stream_key);
+            let system = System::new(
+                "proxy_server_handles_absolute_uri_requests_arriving_on_the_proxy_port",
+            );
+            let mut subject =
+                ProxyServer::new(cryptde, false, Some(STANDARD_CONSUMING_WALLET_BALANCE));
+            subject.set_proxy_port(Some(8888));
+            subject.stream_key_factory = Box::new(stream_key_factory);
+            let subject_addr: Addr<ProxyServer> = subject.start();
+            let mut peer_actors = peer_actors_builder()
+                .hopper(hopper_mock)
+                .neighborhood(neighborhood_mock)
+                .build();
+            peer_actors.proxy_server = ProxyServer::make_subs_from(&subject_addr);
+            subject_addr.try_send(BindMessage { peer_actors }).unwrap();
+
+            subject_addr.try_send(msg_from_dispatcher).unwrap();
+            system.run();
+        });
+
+        hopper_awaiter.await_message_count(1);
+        let hopper_recording = hopper_recording_arc.lock().unwrap();
+        let hopper_record = hopper_recording.get_record::<IncipientCoresPackage>(0);
+        assert_eq!(hopper_record, &expected_pkg);
+    }
+
+    #[test]
+    fn proxy_server_opens_a_new_stream_when_a_proxy_port_connection_moves_to_another_host() {
+        let cryptde = cryptde();
+        let (hopper_mock, hopper_awaiter, hopper_recording_arc) = make_recorder();
+        let (neighborhood_mock, _, _) = make_recorder();
+        let neighborhood_mock = neighborhood_mock.route_query_response(Some(
+            zero_hop_route_response(&cryptde.public_key(), cryptde),
+        ));
+        let socket_addr = SocketAddr::from_str("1.2.3.4:5678").unwrap();
+        let first_stream_key = StreamKey::new(cryptde.public_key().clone(), socket_addr);
+        let second_stream_key = StreamKey::random();
+        let request_to = |host: &str, sequence_number: u64| InboundClientData {
+            peer_addr: socket_addr,
+            reception_port: Some(8888),
+            sequence_number: Some(sequence_number),
+            last_data: false,
+            is_clandestine: false,
+            data: format!("GET http://{}/ HTTP/1.1\r\nHost: {}\r\n\r\n", host, host).into_bytes(),
+        };
+        let first_request = request_to("one.com", 0);
+        let second_request = request_to("two.com", 1);
+        let stream_key_factory = StreamKeyFactoryMock::new()
+            .make_result(first_stream_key.clone())
+            .make_result(second_stream_key.clone());
+
+        thread::spawn(move || {
+            let system = System::new(
+                "proxy_server_opens_a_new_stream_when_a_proxy_port_connection_moves_to_another_host",
+            );
+            let mut subject =
+                ProxyServer::new(cryptde, false, Some(STANDARD_CONSUMING_WALLET_BALANCE));
+            subject.set_proxy_port(Some(8888));
+            subject.stream_key_factory = Box::new(stream_key_factory);
+            let subject_addr: Addr<ProxyServer> = subject.start();
+            let mut peer_actors = peer_actors_builder()
+                .hopper(hopper_mock)
+                .neighborhood(neighborhood_mock)
+                .build();
+            peer_actors.proxy_server = ProxyServer::make_subs_from(&subject_addr);
+            subject_addr.try_send(BindMessage { peer_actors }).unwrap();
+            subject_addr.try_send(first_request).unwrap();
+            subject_addr.try_send(second_request).unwrap();
+            system.run();
+        });
+
+        hopper_awaiter.await_message_count(3);
+        let hopper_recording = hopper_recording_arc.lock().unwrap();
+        let requests = (0..3)
+            .map(|index| {
+                let package = hopper_recording.get_record::<IncipientCoresPackage>(index);
+                match decodex::<MessageType>(cryptde, &package.payload).unwrap() {
+                    MessageType::ClientRequest(payload) => (
+                        payload.stream_key,
+                        payload.target_hostname,
+                        payload.sequenced_packet,
+                    ),
+                    other => panic!("Expected ClientRequest, got {:?}", other),
+                }
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            requests,
+            vec![
+                (
+                    first_stream_key.clone(),
+                    Some("one.com".to_string()),
+                    SequencedPacket::new(
+                        b"GET / HTTP/1.1\r\nHost: one.com\r\n\r\n".to_vec(),
+                        0,
+                        false
+                    ),
+                ),
+                (
+                    first_stream_key,
+                    Some("one.com".to_string()),
+                    SequencedPacket::new(vec![], 1, true),
+                ),
+                (
+                    second_stream_key,
+                    Some("two.com".to_string()),
+                    SequencedPacket::new(
+                        b"GET / HTTP/1.1\r\nHost: two.com\r\n\r\n".to_vec(),
+                        0,
+                        false
+                    ),
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn responses_on_a_proxy_port_stream_follow_those_of_the_streams_before_it() {
+        let mut subject =
+            ProxyServer::new(cryptde(), false, Some(STANDARD_CONSUMING_WALLET_BALANCE));
+        let stream_key = make_meaningless_stream_key();
+        subject.proxy_port_streams.insert(
+            stream_key.clone(),
+            ProxyPortStream {
+                host: Host {
+                    name: "two.com".to_string(),
+                    port: None,
+                },
+                request_offset: 3,
+                response_offset: 5,
+                responses_relayed: 0,
+            },
+        );
+
+        let first = subject.response_sequence_number(&stream_key, 0);
+        let second = subject.response_sequence_number(&stream_key, 1);
+        let unknown = subject.response_sequence_number(&StreamKey::random(), 2);
+
+        assert_eq!((first, second, unknown), (5, 6, 2));
+        assert_eq!(
+            subject
+                .proxy_port_streams
+                .get(&stream_key)
+                .unwrap()
+                .responses_relayed,
+            2
+        );
+    }
+
+    #[test]
+    fn tunnel_decides_its_protocol_from_its_port_and_first_data() {
+        let mut tls_port = Tunnel::new("tunneled.com".to_string(), TLS_PORT);
+        let mut tls_handshake = Tunnel::new("tunneled.com".to_string(), 8443);
+        let mut plain = Tunnel::new("tunneled.com".to_string(), 8080);
+
+        assert_eq!(tls_port.protocol(b"client hello"), ProxyProtocol::TLS);
+        assert_eq!(
+            tls_handshake.protocol(&[0x16, 0x03, 0x01, 0x00, 0x05]),
+            ProxyProtocol::TLS
+        );
+        assert_eq!(
+            tls_handshake.protocol(b"GET / HTTP/1.1"),
+            ProxyProtocol::TLS
+        );
+        assert_eq!(plain.protocol(b"GET / HTTP/1.1"), ProxyProtocol::Raw);
+        assert_eq!(
+            plain.protocol(&[0x16, 0x03, 0x01, 0x00, 0x05]),
+            ProxyProtocol::Raw
+        );
+    }
+
+    #[test]
+    fn proxy_server_sends_error_and_shuts_down_stream_when_connect_host_unparseable() {
+        let cryptde = cryptde();
+        let http_request = "CONNECT λ:🥓:λ HTTP/1.1\r\nHost: 🥓:🥔:🥔\r\n\r\n".as_bytes();
+
+        let (hopper_mock, _hopper_awaiter, _hopper_recording_arc) = make_recorder();
+        let (neighborhood_mock, _, _neighborhood_recording_arc) = make_recorder();
+        let (dispatcher_mock, _dispatcher_awaiter, dispatcher_recording_arc) = make_recorder();
+
+        let neighborhood_mock = neighborhood_mock.route_query_response(Some(
+            zero_hop_route_response(&cryptde.public_key(), cryptde),
+        ));
+
+        let socket_addr = SocketAddr::from_str("1.2.3.4:5678").unwrap();
         let stream_key = make_meaningless_stream_key();
         let request_data = http_request.to_vec();
         let msg_from_dispatcher = InboundClientData {
@@ -1527,6 +2854,14 @@ mod tests {
             .build();
         peer_actors.proxy_server = ProxyServer::make_subs_from(&subject_addr);
         subject_addr.try_send(BindMessage { peer_actors }).unwrap();
+        let failures = || {
+            metrics::value(
+                &PROXY_SERVER_STREAMS_FAILED,
+                &[("reason", "no_consuming_wallet")],
+            )
+            .unwrap_or(0)
+        };
+        let failures_before = failures();
 
         subject_addr.try_send(msg_from_dispatcher).unwrap();
 
@@ -1539,6 +2874,7 @@ mod tests {
         let dispatcher_recording = dispatcher_log_arc.lock().unwrap();
         let record = dispatcher_recording.get_record::<TransmitDataMsg>(0);
         let server_impersonator = ServerImpersonatorHttp {};
+        assert!(failures() > failures_before);
         assert_eq!(
             record,
             &TransmitDataMsg {
@@ -1553,6 +2889,137 @@ mod tests {
         );
     }
 
+    #[test]
+    fn proxy_server_sends_bypassed_requests_directly_without_a_consuming_wallet() {
+        let cryptde = cryptde();
+        let (hopper, _, hopper_log_arc) = make_recorder();
+        let (neighborhood, _, neighborhood_log_arc) = make_recorder();
+        let (dispatcher, _, dispatcher_log_arc) = make_recorder();
+        let (proxy_server, proxy_server_awaiter, proxy_server_log_arc) = make_recorder();
+        let socket_addr = SocketAddr::from_str("1.2.3.4:5678").unwrap();
+        let msg_from_dispatcher = InboundClientData {
+            peer_addr: socket_addr,
+            reception_port: Some(HTTP_PORT),
+            sequence_number: Some(0),
+            last_data: false,
+            is_clandestine: false,
+            data: b"GET /index.html HTTP/1.1\r\nHost: wiki.intranet.example.com\r\n\r\n".to_vec(),
+        };
+        let lookup_ip_parameters = Arc::new(Mutex::new(vec![]));
+        let resolver = ResolverWrapperMock::new()
+            .lookup_ip_failure(ResolveErrorKind::Io.into())
+            .lookup_ip_parameters(&lookup_ip_parameters);
+        let mut resolver_wrapper_new_parameters_arc = Arc::new(Mutex::new(vec![]));
+        let resolver_wrapper_factory = ResolverWrapperFactoryMock::new()
+            .new_parameters(&mut resolver_wrapper_new_parameters_arc)
+            .new_result(Box::new(resolver));
+        thread::spawn(move || {
+            let system = System::new(
+                "proxy_server_sends_bypassed_requests_directly_without_a_consuming_wallet",
+            );
+            let mut subject = ProxyServer::new(cryptde, true, None);
+            subject.set_bypass(
+                BypassList::new(vec![BypassList::parse_rule("intranet.example.com").unwrap()]),
+                vec![SocketAddr::from_str("192.168.1.1:53").unwrap()],
+            );
+            subject.resolver_wrapper_factory = Box::new(resolver_wrapper_factory);
+            let subject_addr: Addr<ProxyServer> = subject.start();
+            let peer_actors = peer_actors_builder()
+                .dispatcher(dispatcher)
+                .hopper(hopper)
+                .neighborhood(neighborhood)
+                .proxy_server(proxy_server)
+                .build();
+            subject_addr.try_send(BindMessage { peer_actors }).unwrap();
+
+            subject_addr.try_send(msg_from_dispatcher).unwrap();
+            system.run();
+        });
+
+        proxy_server_awaiter.await_message_count(1);
+        let (config, _) = resolver_wrapper_new_parameters_arc
+            .lock()
+            .unwrap()
+            .remove(0);
+        assert_eq!(
+            config.name_servers(),
+            &[NameServerConfig {
+                socket_addr: SocketAddr::from_str("192.168.1.1:53").unwrap(),
+                protocol: Protocol::Udp,
+                tls_dns_name: None,
+            }]
+        );
+        assert_eq!(
+            *lookup_ip_parameters.lock().unwrap(),
+            vec!["wiki.intranet.example.com.".to_string()]
+        );
+        let proxy_server_recording = proxy_server_log_arc.lock().unwrap();
+        let record = proxy_server_recording.get_record::<InboundServerData>(0);
+        assert_eq!(record.last_data, true);
+        assert_eq!(
+            record.data,
+            ServerImpersonatorHttp {}.dns_resolution_failure_response(
+                cryptde.public_key(),
+                Some("wiki.intranet.example.com".to_string())
+            )
+        );
+        assert!(neighborhood_log_arc.lock().unwrap().is_empty());
+        assert!(hopper_log_arc.lock().unwrap().is_empty());
+        assert!(dispatcher_log_arc.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn proxy_server_relays_direct_server_data_to_the_client() {
+        let cryptde = cryptde();
+        let (dispatcher, _, dispatcher_log_arc) = make_recorder();
+        let socket_addr = SocketAddr::from_str("1.2.3.4:5678").unwrap();
+        let stream_key = make_meaningless_stream_key();
+        let system = System::new("proxy_server_relays_direct_server_data_to_the_client");
+        let mut subject = ProxyServer::new(cryptde, true, None);
+        subject.keys_and_addrs.insert(stream_key, socket_addr);
+        let subject_addr: Addr<ProxyServer> = subject.start();
+        let peer_actors = peer_actors_builder().dispatcher(dispatcher).build();
+        subject_addr.try_send(BindMessage { peer_actors }).unwrap();
+        let server_data = |data: &[u8], sequence_number: u64, last_data: bool| InboundServerData {
+            stream_key,
+            last_data,
+            sequence_number,
+            source: SocketAddr::from_str("192.168.1.2:80").unwrap(),
+            data: data.to_vec(),
+        };
+
+        subject_addr
+            .try_send(server_data(b"HTTP/1.1 200 OK\r\n\r\n", 0, false))
+            .unwrap();
+        subject_addr.try_send(server_data(b"", 1, true)).unwrap();
+        subject_addr
+            .try_send(server_data(b"too late", 2, false))
+            .unwrap();
+
+        System::current().stop();
+        system.run();
+        let dispatcher_recording = dispatcher_log_arc.lock().unwrap();
+        assert_eq!(dispatcher_recording.len(), 2);
+        assert_eq!(
+            dispatcher_recording.get_record::<TransmitDataMsg>(0),
+            &TransmitDataMsg {
+                endpoint: Endpoint::Socket(socket_addr),
+                last_data: false,
+                sequence_number: Some(0),
+                data: b"HTTP/1.1 200 OK\r\n\r\n".to_vec(),
+            }
+        );
+        assert_eq!(
+            dispatcher_recording.get_record::<TransmitDataMsg>(1),
+            &TransmitDataMsg {
+                endpoint: Endpoint::Socket(socket_addr),
+                last_data: true,
+                sequence_number: Some(1),
+                data: vec![],
+            }
+        );
+    }
+
     #[test]
     fn proxy_server_receives_tls_request_with_no_consuming_wallet_and_sends_impersonated_response()
     {
@@ -1661,7 +3128,9 @@ mod tests {
                 target_key_opt: None,
                 target_component: Component::ProxyClient,
                 minimum_hop_count: 0,
-                return_component_opt: Some(Component::ProxyServer)
+                return_component_opt: Some(Component::ProxyServer),
+                target_hostname_opt: Some("nowhere.com".to_string()),
+                target_port_opt: Some(80),
             }
         );
         let dispatcher_recording = dispatcher_log_arc.lock().unwrap();
@@ -1680,6 +3149,9 @@ mod tests {
                     target_port: 80,
                     protocol: ProxyProtocol::HTTP,
                     originator_public_key: cryptde.public_key().clone(),
+                    fresh_return_route: false,
+                    alpn_protocols: vec![],
+                    trace_id_opt: None,
                 }),
                 cryptde.public_key()
             )
@@ -1736,7 +3208,9 @@ mod tests {
                 target_key_opt: None,
                 target_component: Component::ProxyClient,
                 minimum_hop_count: 0,
-                return_component_opt: Some(Component::ProxyServer)
+                return_component_opt: Some(Component::ProxyServer),
+                target_hostname_opt: None,
+                target_port_opt: Some(TLS_PORT),
             }
         );
         let dispatcher_recording = dispatcher_log_arc.lock().unwrap();
@@ -1755,6 +3229,9 @@ mod tests {
                     target_port: 443,
                     protocol: ProxyProtocol::TLS,
                     originator_public_key: cryptde.public_key().clone(),
+                    fresh_return_route: false,
+                    alpn_protocols: vec![],
+                    trace_id_opt: None,
                 }),
                 cryptde.public_key()
             )
@@ -1762,6 +3239,163 @@ mod tests {
         );
     }
 
+    #[test]
+    fn proxy_server_holds_records_of_a_split_client_hello_until_it_is_whole() {
+        let cryptde = cryptde();
+        let (first_record, second_record) = client_hello_in_two_records("split.example.com");
+        let expected_route = zero_hop_route_response(cryptde.public_key(), cryptde);
+        let stream_key = make_meaningless_stream_key();
+        let (hopper, hopper_awaiter, hopper_log_arc) = make_recorder();
+        let neighborhood = Recorder::new().route_query_response(Some(expected_route.clone()));
+        let socket_addr = SocketAddr::from_str("1.2.3.4:5678").unwrap();
+        let record_msg = |data: &Vec<u8>, sequence_number: u64| InboundClientData {
+            peer_addr: socket_addr,
+            reception_port: Some(TLS_PORT),
+            sequence_number: Some(sequence_number),
+            last_data: false,
+            is_clandestine: false,
+            data: data.clone(),
+        };
+        let first_msg = record_msg(&first_record, 0);
+        let second_msg = record_msg(&second_record, 1);
+        thread::spawn(move || {
+            let stream_key_factory = StreamKeyFactoryMock::new().make_result(stream_key);
+            let system =
+                System::new("proxy_server_holds_records_of_a_split_client_hello_until_it_is_whole");
+            let mut subject = ProxyServer::new(cryptde, false, Some(0));
+            subject.stream_key_factory = Box::new(stream_key_factory);
+            let subject_addr: Addr<ProxyServer> = subject.start();
+            let mut peer_actors = peer_actors_builder()
+                .hopper(hopper)
+                .neighborhood(neighborhood)
+                .build();
+            peer_actors.proxy_server = ProxyServer::make_subs_from(&subject_addr);
+            subject_addr.try_send(BindMessage { peer_actors }).unwrap();
+
+            subject_addr.try_send(first_msg).unwrap();
+            subject_addr.try_send(second_msg).unwrap();
+
+            system.run();
+        });
+        hopper_awaiter.await_message_count(2);
+        let hopper_recording = hopper_log_arc.lock().unwrap();
+        let expected_package = |data: Vec<u8>, sequence_number: u64| {
+            IncipientCoresPackage::new(
+                cryptde,
+                expected_route.route.clone(),
+                MessageType::ClientRequest(ClientRequestPayload {
+                    version: ClientRequestPayload::version(),
+                    stream_key,
+                    sequenced_packet: SequencedPacket::new(data, sequence_number, false),
+                    target_hostname: Some(String::from("split.example.com")),
+                    target_port: TLS_PORT,
+                    protocol: ProxyProtocol::TLS,
+                    originator_public_key: cryptde.public_key().clone(),
+                    fresh_return_route: false,
+                    alpn_protocols: vec![],
+                    trace_id_opt: None,
+                }),
+                cryptde.public_key(),
+            )
+            .unwrap()
+        };
+        assert_eq!(
+            hopper_recording.get_record::<IncipientCoresPackage>(0),
+            &expected_package(first_record, 0)
+        );
+        assert_eq!(
+            hopper_recording.get_record::<IncipientCoresPackage>(1),
+            &expected_package(second_record, 1)
+        );
+    }
+
+    #[test]
+    fn client_hello_records_are_released_when_the_browser_closes_the_stream() {
+        let (first_record, _) = client_hello_in_two_records("split.example.com");
+        let socket_addr = SocketAddr::from_str("1.2.3.4:5678").unwrap();
+        let stream_key = make_meaningless_stream_key();
+        let mut subject = ProxyServer::new(cryptde(), false, Some(0));
+        subject.stream_key_factory = Box::new(StreamKeyFactoryMock::new().make_result(stream_key));
+        let msg = InboundClientData {
+            peer_addr: socket_addr,
+            reception_port: Some(TLS_PORT),
+            sequence_number: Some(0),
+            last_data: false,
+            is_clandestine: false,
+            data: first_record.clone(),
+        };
+        let closing_msg = InboundClientData {
+            sequence_number: Some(1),
+            last_data: true,
+            data: vec![],
+            ..msg.clone()
+        };
+
+        let held = subject.assemble_client_hello(msg.clone());
+        let released = subject.assemble_client_hello(closing_msg.clone());
+
+        assert_eq!(held, vec![]);
+        assert_eq!(released, vec![msg, closing_msg]);
+        assert!(subject.client_hello_records.is_empty());
+        assert!(!subject.tunneled_hosts.contains_key(&stream_key));
+    }
+
+    #[test]
+    fn whole_client_hello_and_non_tls_data_pass_straight_through() {
+        let (first_record, second_record) = client_hello_in_two_records("whole.example.com");
+        let handshake_length = (first_record.len() - 5 + second_record.len() - 5) as u8;
+        let whole_record = vec![
+            vec![0x16, 0x03, 0x01, 0x00, handshake_length],
+            first_record[5..].to_vec(),
+            second_record[5..].to_vec(),
+        ]
+        .concat();
+        let mut subject = ProxyServer::new(cryptde(), false, Some(0));
+        let tls_msg = InboundClientData {
+            peer_addr: SocketAddr::from_str("1.2.3.4:5678").unwrap(),
+            reception_port: Some(TLS_PORT),
+            sequence_number: Some(0),
+            last_data: false,
+            is_clandestine: false,
+            data: vec![],
+        };
+        let whole_msg = InboundClientData {
+            data: whole_record,
+            ..tls_msg.clone()
+        };
+        let http_msg = InboundClientData {
+            reception_port: Some(HTTP_PORT),
+            data: first_record,
+            ..tls_msg
+        };
+
+        let whole_result = subject.assemble_client_hello(whole_msg.clone());
+        let http_result = subject.assemble_client_hello(http_msg.clone());
+
+        assert_eq!(whole_result, vec![whole_msg]);
+        assert_eq!(http_result, vec![http_msg]);
+        assert!(subject.keys_and_addrs.is_empty());
+    }
+
+    // A minimal ClientHello naming server_name, split after the first ten bytes of its handshake
+    fn client_hello_in_two_records(server_name: &str) -> (Vec<u8>, Vec<u8>) {
+        let name_length = server_name.len() as u8;
+        let mut message = vec![0x03, 0x03]; // legacy_version: TLS 1.2
+        message.extend_from_slice(&[0xAB; 32]); // random
+        message.extend_from_slice(&[0x00, 0x00, 0x02, 0x13, 0x01, 0x01, 0x00]);
+        message.extend_from_slice(&[0x00, name_length + 9, 0x00, 0x00, 0x00, name_length + 5]);
+        message.extend_from_slice(&[0x00, name_length + 3, 0x00, 0x00, name_length]);
+        message.extend_from_slice(server_name.as_bytes());
+        let mut handshake = vec![0x01, 0x00, 0x00, message.len() as u8];
+        handshake.extend(message);
+        let record = |fragment: &[u8]| {
+            let mut record = vec![0x16, 0x03, 0x01, 0x00, fragment.len() as u8];
+            record.extend_from_slice(fragment);
+            record
+        };
+        (record(&handshake[..10]), record(&handshake[10..]))
+    }
+
     #[test]
     fn proxy_server_receives_http_request_with_existing_stream_key_from_dispatcher_then_sends_cores_package_to_hopper(
     ) {
@@ -1799,6 +3433,9 @@ mod tests {
             target_port: HTTP_PORT,
             protocol: ProxyProtocol::HTTP,
             originator_public_key: key.clone(),
+            fresh_return_route: false,
+            alpn_protocols: vec![],
+            trace_id_opt: None,
         };
         let expected_pkg =
             IncipientCoresPackage::new(cryptde, route.clone(), expected_payload.into(), &key)
@@ -1865,6 +3502,9 @@ mod tests {
             target_port: HTTP_PORT,
             protocol: ProxyProtocol::HTTP,
             originator_public_key: key.clone(),
+            fresh_return_route: false,
+            alpn_protocols: vec![],
+            trace_id_opt: None,
         };
         let expected_pkg =
             IncipientCoresPackage::new(cryptde, route.clone(), expected_payload.into(), &key)
@@ -1979,6 +3619,9 @@ mod tests {
             target_port: HTTP_PORT,
             protocol: ProxyProtocol::HTTP,
             originator_public_key: key.clone(),
+            fresh_return_route: false,
+            alpn_protocols: vec![],
+            trace_id_opt: None,
         };
         let expected_pkg = IncipientCoresPackage::new(
             cryptde,
@@ -2012,7 +3655,14 @@ mod tests {
         assert_eq!(record, &expected_pkg);
         let recording = neighborhood_recording_arc.lock().unwrap();
         let record = recording.get_record::<RouteQueryMessage>(0);
-        assert_eq!(record, &RouteQueryMessage::data_indefinite_route_request(3));
+        assert_eq!(
+            record,
+            &RouteQueryMessage::data_indefinite_route_request_for(
+                3,
+                Some("nowhere.com".to_string()),
+                HTTP_PORT
+            )
+        );
     }
 
     #[test]
@@ -2064,6 +3714,7 @@ mod tests {
         let expected_add_route_message = AddRouteMessage {
             stream_key,
             route: route_query_response.unwrap(),
+            alternate_routes: vec![],
         };
 
         proxy_server_awaiter.await_message_count(1);
@@ -2072,6 +3723,325 @@ mod tests {
         assert_eq!(record, &expected_add_route_message);
     }
 
+    #[test]
+    fn proxy_server_adds_alternate_routes_for_multipath_stream_key() {
+        let cryptde = cryptde();
+        let http_request = b"GET /index.html HTTP/1.1\r\nHost: nowhere.com\r\n\r\n";
+        let (proxy_server_mock, proxy_server_awaiter, proxy_server_recording_arc) = make_recorder();
+        let first_route = RouteQueryResponse {
+            route: Route { hops: vec![] },
+            expected_services: ExpectedServices::RoundTrip(vec![], vec![], 1234),
+        };
+        let second_route = RouteQueryResponse {
+            route: Route { hops: vec![] },
+            expected_services: ExpectedServices::RoundTrip(vec![], vec![], 2345),
+        };
+        let (neighborhood_mock, _, neighborhood_recording_arc) = make_recorder();
+        let neighborhood_mock = neighborhood_mock
+            .disjoint_route_query_response(vec![first_route.clone(), second_route.clone()]);
+        let socket_addr = SocketAddr::from_str("1.2.3.4:5678").unwrap();
+        let stream_key = make_meaningless_stream_key();
+        let msg_from_dispatcher = InboundClientData {
+            peer_addr: socket_addr.clone(),
+            reception_port: Some(HTTP_PORT),
+            sequence_number: Some(0),
+            last_data: true,
+            is_clandestine: false,
+            data: http_request.to_vec(),
+        };
+
+        thread::spawn(move || {
+            let stream_key_factory = StreamKeyFactoryMock::new().make_result(stream_key);
+            let system = System::new("proxy_server_adds_alternate_routes_for_multipath_stream_key");
+            let mut subject =
+                ProxyServer::new(cryptde, true, Some(STANDARD_CONSUMING_WALLET_BALANCE));
+            subject.stream_key_factory = Box::new(stream_key_factory);
+            subject.set_route_path_count(2);
+            let subject_addr: Addr<ProxyServer> = subject.start();
+            let mut peer_actors = peer_actors_builder()
+                .proxy_server(proxy_server_mock)
+                .neighborhood(neighborhood_mock)
+                .build();
+            let add_route_recipient = peer_actors.proxy_server.add_route;
+            peer_actors.proxy_server = ProxyServer::make_subs_from(&subject_addr);
+            peer_actors.proxy_server.add_route = add_route_recipient;
+            subject_addr.try_send(BindMessage { peer_actors }).unwrap();
+
+            subject_addr.try_send(msg_from_dispatcher).unwrap();
+
+            system.run();
+        });
+
+        proxy_server_awaiter.await_message_count(1);
+        let recording = proxy_server_recording_arc.lock().unwrap();
+        let record = recording.get_record::<AddRouteMessage>(0);
+        assert_eq!(
+            record,
+            &AddRouteMessage {
+                stream_key,
+                route: first_route,
+                alternate_routes: vec![second_route],
+            }
+        );
+        let recording = neighborhood_recording_arc.lock().unwrap();
+        let record = recording.get_record::<DisjointRouteQueryMessage>(0);
+        assert_eq!(
+            record,
+            &DisjointRouteQueryMessage::data_indefinite_route_request_for(
+                3,
+                2,
+                Some("nowhere.com".to_string()),
+                HTTP_PORT
+            )
+        );
+    }
+
+    #[test]
+    fn invalidate_routes_drops_only_routes_through_the_lost_node() {
+        let _system = System::new("invalidate_routes_drops_only_routes_through_the_lost_node");
+        let mut subject = ProxyServer::new(cryptde(), true, None);
+        subject.subs = Some(ProxyServerOutSubs::default());
+        let lost_key = PublicKey::new(&[1, 1, 1, 1]);
+        let survivor_key = PublicKey::new(&[2, 2, 2, 2]);
+        let exit_key = PublicKey::new(&[9, 9, 9, 9]);
+        let make_route = |relay_key: &PublicKey, return_route_id| RouteQueryResponse {
+            route: Route { hops: vec![] },
+            expected_services: ExpectedServices::RoundTrip(
+                vec![
+                    ExpectedService::Nothing,
+                    ExpectedService::Routing(
+                        relay_key.clone(),
+                        make_wallet("relay"),
+                        rate_pack(10),
+                    ),
+                    ExpectedService::Exit(exit_key.clone(), make_wallet("exit"), rate_pack(10)),
+                ],
+                vec![ExpectedService::Nothing],
+                return_route_id,
+            ),
+        };
+        let broken_stream_key = StreamKey::new(
+            cryptde().public_key().clone(),
+            SocketAddr::from_str("1.2.3.4:5678").unwrap(),
+        );
+        let multipath_stream_key = StreamKey::new(
+            cryptde().public_key().clone(),
+            SocketAddr::from_str("2.3.4.5:6789").unwrap(),
+        );
+        let intact_stream_key = StreamKey::new(
+            cryptde().public_key().clone(),
+            SocketAddr::from_str("3.4.5.6:7890").unwrap(),
+        );
+        subject
+            .stream_key_routes
+            .insert(broken_stream_key, make_route(&lost_key, 1));
+        subject
+            .stream_key_routes
+            .insert(multipath_stream_key, make_route(&survivor_key, 2));
+        subject.stream_key_paths.insert(
+            multipath_stream_key,
+            StreamPaths::new(vec![make_route(&survivor_key, 2), make_route(&lost_key, 3)]),
+        );
+        subject
+            .stream_key_routes
+            .insert(intact_stream_key, make_route(&survivor_key, 4));
+
+        subject.handle_invalidate_routes(&lost_key);
+
+        assert_eq!(
+            subject.stream_key_routes.contains_key(&broken_stream_key),
+            false
+        );
+        assert_eq!(
+            subject
+                .stream_key_routes
+                .contains_key(&multipath_stream_key),
+            false
+        );
+        assert_eq!(
+            subject.stream_key_paths.contains_key(&multipath_stream_key),
+            false
+        );
+        assert_eq!(
+            subject.stream_key_routes.contains_key(&intact_stream_key),
+            true
+        );
+        assert_eq!(
+            subject.repaired_stream_exits,
+            vec![
+                (broken_stream_key, Some(exit_key.clone())),
+                (multipath_stream_key, Some(exit_key.clone()))
+            ]
+            .into_iter()
+            .collect::<HashMap<StreamKey, Option<PublicKey>>>()
+        );
+    }
+
+    #[test]
+    fn invalidate_routes_closes_streams_whose_exit_node_was_lost() {
+        let system = System::new("invalidate_routes_closes_streams_whose_exit_node_was_lost");
+        let (dispatcher, _, dispatcher_recording_arc) = make_recorder();
+        let mut subject = ProxyServer::new(cryptde(), true, None);
+        subject.subs = Some(ProxyServerOutSubs {
+            dispatcher: dispatcher.start().recipient(),
+            ..ProxyServerOutSubs::default()
+        });
+        let lost_exit_key = PublicKey::new(&[1, 1, 1, 1]);
+        let stream_key = make_meaningless_stream_key();
+        let socket_addr = SocketAddr::from_str("1.2.3.4:5678").unwrap();
+        subject.keys_and_addrs.insert(stream_key, socket_addr);
+        subject.stream_key_routes.insert(
+            stream_key,
+            RouteQueryResponse {
+                route: Route { hops: vec![] },
+                expected_services: ExpectedServices::RoundTrip(
+                    vec![
+                        ExpectedService::Nothing,
+                        ExpectedService::Exit(
+                            lost_exit_key.clone(),
+                            make_wallet("exit"),
+                            rate_pack(10),
+                        ),
+                    ],
+                    vec![ExpectedService::Nothing],
+                    1,
+                ),
+            },
+        );
+
+        subject.handle_invalidate_routes(&lost_exit_key);
+
+        System::current().stop();
+        system.run();
+        assert_eq!(subject.keys_and_addrs.a_to_b(&stream_key), None);
+        assert_eq!(subject.stream_key_routes.contains_key(&stream_key), false);
+        assert_eq!(subject.repaired_stream_exits.is_empty(), true);
+        let recording = dispatcher_recording_arc.lock().unwrap();
+        assert_eq!(
+            recording.get_record::<TransmitDataMsg>(0),
+            &TransmitDataMsg {
+                endpoint: Endpoint::Socket(socket_addr),
+                last_data: true,
+                sequence_number: None,
+                data: vec![],
+            }
+        );
+    }
+
+    #[test]
+    fn repaired_stream_asks_for_a_fresh_return_route_to_the_same_exit() {
+        let cryptde = cryptde();
+        let exit_key = PublicKey::new(&[9, 9, 9, 9]);
+        let http_request = b"GET /index.html HTTP/1.1\r\nHost: nowhere.com\r\n\r\n";
+        let (hopper_mock, hopper_awaiter, hopper_recording_arc) = make_recorder();
+        let route_query_response = RouteQueryResponse {
+            route: Route { hops: vec![] },
+            expected_services: ExpectedServices::RoundTrip(
+                vec![ExpectedService::Nothing],
+                vec![],
+                1234,
+            ),
+        };
+        let (neighborhood_mock, _, neighborhood_recording_arc) = make_recorder();
+        let neighborhood_mock =
+            neighborhood_mock.route_query_response(Some(route_query_response.clone()));
+        let socket_addr = SocketAddr::from_str("1.2.3.4:5678").unwrap();
+        let stream_key = make_meaningless_stream_key();
+        let msg_from_dispatcher = InboundClientData {
+            peer_addr: socket_addr.clone(),
+            reception_port: Some(HTTP_PORT),
+            sequence_number: Some(0),
+            last_data: false,
+            is_clandestine: false,
+            data: http_request.to_vec(),
+        };
+        let expected_payload = ClientRequestPayload {
+            version: ClientRequestPayload::version(),
+            stream_key: stream_key.clone(),
+            sequenced_packet: SequencedPacket {
+                data: PlainData::new(http_request).into(),
+                sequence_number: 0,
+                last_data: false,
+            },
+            target_hostname: Some(String::from("nowhere.com")),
+            target_port: HTTP_PORT,
+            protocol: ProxyProtocol::HTTP,
+            originator_public_key: cryptde.public_key().clone(),
+            fresh_return_route: true,
+            alpn_protocols: vec![],
+            trace_id_opt: None,
+        };
+        let expected_pkg = IncipientCoresPackage::new(
+            cryptde,
+            Route { hops: vec![] },
+            expected_payload.into(),
+            &cryptde.public_key(),
+        )
+        .unwrap();
+
+        thread::spawn(move || {
+            let stream_key_factory = StreamKeyFactoryMock::new().make_result(stream_key);
+            let system =
+                System::new("repaired_stream_asks_for_a_fresh_return_route_to_the_same_exit");
+            let mut subject =
+                ProxyServer::new(cryptde, false, Some(STANDARD_CONSUMING_WALLET_BALANCE));
+            subject.stream_key_factory = Box::new(stream_key_factory);
+            subject
+                .repaired_stream_exits
+                .insert(stream_key, Some(exit_key.clone()));
+            let subject_addr: Addr<ProxyServer> = subject.start();
+            let peer_actors = peer_actors_builder()
+                .hopper(hopper_mock)
+                .neighborhood(neighborhood_mock)
+                .build();
+            subject_addr.try_send(BindMessage { peer_actors }).unwrap();
+
+            subject_addr.try_send(msg_from_dispatcher).unwrap();
+
+            system.run();
+        });
+
+        hopper_awaiter.await_message_count(1);
+        let recording = hopper_recording_arc.lock().unwrap();
+        let record = recording.get_record::<IncipientCoresPackage>(0);
+        assert_eq!(record, &expected_pkg);
+        let recording = neighborhood_recording_arc.lock().unwrap();
+        assert_eq!(
+            recording.get_record::<RouteQueryMessage>(0),
+            &RouteQueryMessage {
+                target_key_opt: Some(exit_key),
+                ..RouteQueryMessage::data_indefinite_route_request_for(
+                    0,
+                    Some(String::from("nowhere.com")),
+                    HTTP_PORT
+                )
+            }
+        );
+    }
+
+    #[test]
+    fn multipath_stream_key_deals_requests_out_across_its_routes_in_turn() {
+        let mut subject = ProxyServer::new(cryptde(), true, None);
+        let stream_key = make_meaningless_stream_key();
+        let make_route = |return_route_id| RouteQueryResponse {
+            route: Route { hops: vec![] },
+            expected_services: ExpectedServices::RoundTrip(vec![], vec![], return_route_id),
+        };
+        subject.stream_key_paths.insert(
+            stream_key,
+            StreamPaths::new(vec![make_route(1), make_route(2)]),
+        );
+        subject.stream_key_routes.insert(stream_key, make_route(1));
+
+        let results = (0..3)
+            .map(|_| subject.next_route_for(&stream_key).unwrap())
+            .collect::<Vec<RouteQueryResponse>>();
+        subject.purge_stream_key(&stream_key);
+
+        assert_eq!(results, vec![make_route(1), make_route(2), make_route(1)]);
+        assert_eq!(subject.next_route_for(&stream_key), None);
+    }
+
     #[test]
     fn proxy_server_uses_existing_route() {
         let cryptde = cryptde();
@@ -2108,6 +4078,9 @@ mod tests {
             target_port: HTTP_PORT,
             protocol: ProxyProtocol::HTTP,
             originator_public_key: cryptde.public_key().clone(),
+            fresh_return_route: false,
+            alpn_protocols: vec![],
+            trace_id_opt: None,
         };
         let expected_pkg = IncipientCoresPackage::new(
             cryptde,
@@ -2131,6 +4104,7 @@ mod tests {
                 .try_send(AddRouteMessage {
                     stream_key,
                     route: route_query_response.unwrap(),
+                    alternate_routes: vec![],
                 })
                 .unwrap();
             subject_addr.try_send(msg_from_dispatcher).unwrap();
@@ -2214,6 +4188,9 @@ mod tests {
             target_port: HTTP_PORT,
             protocol: ProxyProtocol::HTTP,
             originator_public_key: PublicKey::new(b"originator_public_key"),
+            fresh_return_route: false,
+            alpn_protocols: vec![],
+            trace_id_opt: None,
         };
         let logger = Logger::new("test");
 
@@ -2229,6 +4206,7 @@ mod tests {
             &peer_actors.accountant.report_routing_service_consumed,
             &peer_actors.proxy_server.add_return_route,
             None,
+            None,
         )
         .unwrap();
 
@@ -2292,6 +4270,9 @@ mod tests {
             target_port: HTTP_PORT,
             protocol: ProxyProtocol::HTTP,
             originator_public_key: PublicKey::new(b"originator_public_key"),
+            fresh_return_route: false,
+            alpn_protocols: vec![],
+            trace_id_opt: None,
         };
         let logger = Logger::new("test");
 
@@ -2307,6 +4288,7 @@ mod tests {
             &peer_actors.accountant.report_routing_service_consumed,
             &peer_actors.proxy_server.add_return_route,
             Some(&peer_actors.proxy_server.stream_shutdown_sub),
+            None,
         )
         .unwrap();
 
@@ -2320,7 +4302,8 @@ mod tests {
                 return_route_id: 0,
                 expected_services: vec![ExpectedService::Nothing],
                 protocol: ProxyProtocol::HTTP,
-                server_name: Some("nowhere.com".to_string())
+                server_name: Some("nowhere.com".to_string()),
+                dns_query_opt: None,
             }
         );
         let record = recording.get_record::<StreamShutdownMsg>(1);
@@ -2379,7 +4362,70 @@ mod tests {
         TestLogHandler::new()
             .await_log_containing("DEBUG: ProxyServer: No routing services requested.", 1000);
 
-        assert_eq!(accountant_log_arc.lock().unwrap().len(), 0);
+        assert_eq!(accountant_log_arc.lock().unwrap().len(), 0);
+    }
+
+    #[test]
+    fn proxy_server_traces_new_streams_through_the_hopper_when_stream_tracing_is_on() {
+        let cryptde = cryptde();
+        let http_request = b"GET /index.html HTTP/1.1\r\nHost: nowhere.com\r\n\r\n";
+        let (hopper_mock, hopper_awaiter, hopper_recording_arc) = make_recorder();
+        let (neighborhood_mock, _, _) = make_recorder();
+        let neighborhood_mock = neighborhood_mock.route_query_response(Some(
+            zero_hop_route_response(&cryptde.public_key(), cryptde),
+        ));
+        let stream_key = make_meaningless_stream_key();
+        let msg_from_dispatcher = InboundClientData {
+            peer_addr: SocketAddr::from_str("1.2.3.4:5678").unwrap(),
+            reception_port: Some(HTTP_PORT),
+            sequence_number: Some(0),
+            last_data: false,
+            is_clandestine: false,
+            data: http_request.to_vec(),
+        };
+        thread::spawn(move || {
+            let stream_key_factory = StreamKeyFactoryMock::new().make_result(stream_key);
+            let system = System::new(
+                "proxy_server_traces_new_streams_through_the_hopper_when_stream_tracing_is_on",
+            );
+            stream_trace::enable_without_export();
+            let mut subject =
+                ProxyServer::new(cryptde, true, Some(STANDARD_CONSUMING_WALLET_BALANCE));
+            subject.stream_key_factory = Box::new(stream_key_factory);
+            let subject_addr: Addr<ProxyServer> = subject.start();
+            let mut peer_actors = peer_actors_builder()
+                .hopper(hopper_mock)
+                .neighborhood(neighborhood_mock)
+                .build();
+            peer_actors.proxy_server = ProxyServer::make_subs_from(&subject_addr);
+            subject_addr.try_send(BindMessage { peer_actors }).unwrap();
+            subject_addr.try_send(msg_from_dispatcher).unwrap();
+            system.run();
+        });
+
+        hopper_awaiter.await_message_count(1);
+        let views = stream_trace::views();
+        let hopper_recording = hopper_recording_arc.lock().unwrap();
+        let package = hopper_recording.get_record::<IncipientCoresPackage>(0);
+        let trace_id = package.trace_id_opt.unwrap();
+        match decodex::<MessageType>(cryptde, &package.payload).unwrap() {
+            MessageType::ClientRequest(payload) => {
+                assert_eq!(payload.trace_id_opt, Some(trace_id))
+            }
+            other => panic!("Expected ClientRequest, got {:?}", other),
+        }
+        let view = views
+            .iter()
+            .find(|view| view.trace_id == trace_id.to_string())
+            .unwrap();
+        assert_eq!(view.stream_key, stream_key.to_string());
+        assert_eq!(view.closed, false);
+        let span_names = view
+            .spans
+            .iter()
+            .map(|span| span.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(span_names, vec!["route_query"]);
     }
 
     #[test]
@@ -2547,11 +4593,114 @@ mod tests {
         assert_eq!(record, &expected_msg);
         let recording = neighborhood_recording_arc.lock().unwrap();
         let record = recording.get_record::<RouteQueryMessage>(0);
-        assert_eq!(record, &RouteQueryMessage::data_indefinite_route_request(3));
+        assert_eq!(
+            record,
+            &RouteQueryMessage::data_indefinite_route_request_for(
+                3,
+                Some("nowhere.com".to_string()),
+                HTTP_PORT
+            )
+        );
         TestLogHandler::new()
             .exists_log_containing("ERROR: ProxyServer: Failed to find route to nowhere.com");
     }
 
+    #[test]
+    fn proxy_server_logs_no_hostnames_or_ips_in_privacy_logging_mode() {
+        init_test_logging();
+        let cryptde = cryptde();
+        let http_request = b"GET /index.html HTTP/1.1\r\nHost: secret.example\r\n\r\n";
+        let (neighborhood_mock, _, _) = make_recorder();
+        let neighborhood_mock = neighborhood_mock.route_query_response(None);
+        let dispatcher = Recorder::new();
+        let dispatcher_awaiter = dispatcher.get_awaiter();
+        let msg_from_dispatcher = InboundClientData {
+            peer_addr: SocketAddr::from_str("6.2.8.3:1853").unwrap(),
+            reception_port: Some(HTTP_PORT),
+            sequence_number: Some(0),
+            last_data: true,
+            data: http_request.to_vec(),
+            is_clandestine: false,
+        };
+        thread::spawn(move || {
+            privacy::set_privacy_logging(true);
+            let system =
+                System::new("proxy_server_logs_no_hostnames_or_ips_in_privacy_logging_mode");
+            let subject = ProxyServer::new(cryptde, true, Some(STANDARD_CONSUMING_WALLET_BALANCE));
+            let subject_addr: Addr<ProxyServer> = subject.start();
+            let mut peer_actors = peer_actors_builder()
+                .dispatcher(dispatcher)
+                .neighborhood(neighborhood_mock)
+                .build();
+            peer_actors.proxy_server = ProxyServer::make_subs_from(&subject_addr);
+            subject_addr.try_send(BindMessage { peer_actors }).unwrap();
+
+            subject_addr.try_send(msg_from_dispatcher).unwrap();
+
+            system.run();
+        });
+
+        dispatcher_awaiter.await_message_count(1);
+        let tlh = TestLogHandler::new();
+        tlh.exists_log_containing("ERROR: ProxyServer: Failed to find route to host#");
+        tlh.exists_no_log_containing("secret.example");
+        tlh.exists_no_log_containing("6.2.8.3");
+    }
+
+    #[test]
+    fn proxy_server_logs_no_refused_hostnames_in_privacy_logging_mode() {
+        init_test_logging();
+        privacy::set_privacy_logging(true);
+        let system = System::new("proxy_server_logs_no_refused_hostnames_in_privacy_logging_mode");
+        let (dispatcher_mock, dispatcher_awaiter, _) = make_recorder();
+        let cryptde = cryptde();
+        let mut subject = ProxyServer::new(cryptde, false, Some(STANDARD_CONSUMING_WALLET_BALANCE));
+        let stream_key = make_meaningless_stream_key();
+        subject.keys_and_addrs.insert(
+            stream_key.clone(),
+            SocketAddr::from_str("6.2.8.4:1853").unwrap(),
+        );
+        let subject_addr: Addr<ProxyServer> = subject.start();
+        let mut peer_actors = peer_actors_builder().dispatcher(dispatcher_mock).build();
+        peer_actors.proxy_server = ProxyServer::make_subs_from(&subject_addr);
+        subject_addr.try_send(BindMessage { peer_actors }).unwrap();
+        subject_addr
+            .try_send(AddReturnRouteMessage {
+                return_route_id: 1234,
+                expected_services: vec![ExpectedService::Exit(
+                    PublicKey::from(&b"exit_key"[..]),
+                    make_wallet("exit wallet"),
+                    rate_pack(10),
+                )],
+                protocol: ProxyProtocol::HTTP,
+                server_name: Some("refused.example".to_string()),
+                dns_query_opt: None,
+            })
+            .unwrap();
+
+        subject_addr
+            .try_send(ExpiredCoresPackage::new(
+                SocketAddr::from_str("1.2.3.4:1234").unwrap(),
+                Some(make_wallet("irrelevant")),
+                return_route_with_id(cryptde, 1234),
+                ExitPolicyRefusal::new(
+                    stream_key,
+                    "refused.example port 80 is refused by the exit Node's exit policy".to_string(),
+                ),
+                0,
+            ))
+            .unwrap();
+
+        System::current().stop_with_code(0);
+        system.run();
+        privacy::set_privacy_logging(false);
+        dispatcher_awaiter.await_message_count(1);
+        let tlh = TestLogHandler::new();
+        tlh.exists_log_containing("ExitPolicyRefusal (<redacted data>)");
+        tlh.exists_no_log_containing("refused.example");
+        tlh.exists_no_log_containing("6.2.8.4");
+    }
+
     #[test]
     #[should_panic(expected = "Expected RoundTrip ExpectedServices but got OneWay")]
     fn proxy_server_panics_if_it_receives_a_one_way_route_from_a_request_for_a_round_trip_route() {
@@ -2592,6 +4741,9 @@ mod tests {
             target_port: 0,
             protocol: ProxyProtocol::TLS,
             originator_public_key: cryptde.public_key().clone(),
+            fresh_return_route: false,
+            alpn_protocols: vec![],
+            trace_id_opt: None,
         };
         let logger = Logger::new("ProxyServer");
         let source_addr = SocketAddr::from_str("1.2.3.4:5678").unwrap();
@@ -2608,6 +4760,7 @@ mod tests {
             &peer_actors.accountant.report_routing_service_consumed,
             &peer_actors.proxy_server.add_return_route,
             None,
+            None,
         )
         .unwrap();
     }
@@ -2674,7 +4827,14 @@ mod tests {
         assert_eq!(record, &expected_msg);
         let recording = neighborhood_recording_arc.lock().unwrap();
         let record = recording.get_record::<RouteQueryMessage>(0);
-        assert_eq!(record, &RouteQueryMessage::data_indefinite_route_request(3));
+        assert_eq!(
+            record,
+            &RouteQueryMessage::data_indefinite_route_request_for(
+                3,
+                Some("nowhere.com".to_string()),
+                HTTP_PORT
+            )
+        );
         TestLogHandler::new()
             .exists_log_containing("ERROR: ProxyServer: Failed to find route to nowhere.com");
     }
@@ -2683,9 +4843,9 @@ mod tests {
     fn proxy_server_receives_tls_client_hello_from_dispatcher_then_sends_cores_package_to_hopper() {
         let tls_request = &[
             0x16, // content_type: Handshake
-            0x00, 0x00, 0x00, 0x00, // version, length: don't care
+            0x03, 0x01, 0x00, 0x3F, // version: TLS 1.0, length: 63
             0x01, // handshake_type: ClientHello
-            0x00, 0x00, 0x00, 0x00, 0x00, // length, version: don't care
+            0x00, 0x00, 0x3B, 0x03, 0x03, // length: 59, version: TLS 1.2
             0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
             0x00, 0x00, // random: don't care
             0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
@@ -2735,6 +4895,9 @@ mod tests {
             target_port: TLS_PORT,
             protocol: ProxyProtocol::TLS,
             originator_public_key: key.clone(),
+            fresh_return_route: false,
+            alpn_protocols: vec![],
+            trace_id_opt: None,
         };
         let expected_pkg =
             IncipientCoresPackage::new(cryptde, route.clone(), expected_payload.into(), &key)
@@ -2806,6 +4969,9 @@ mod tests {
             target_port: TLS_PORT,
             protocol: ProxyProtocol::TLS,
             originator_public_key: key.clone(),
+            fresh_return_route: false,
+            alpn_protocols: vec![],
+            trace_id_opt: None,
         };
         let expected_pkg =
             IncipientCoresPackage::new(cryptde, route.clone(), expected_payload.into(), &key)
@@ -2875,6 +5041,9 @@ mod tests {
             target_port: TLS_PORT,
             protocol: ProxyProtocol::TLS,
             originator_public_key: key.clone(),
+            fresh_return_route: false,
+            alpn_protocols: vec![],
+            trace_id_opt: None,
         };
         let expected_pkg =
             IncipientCoresPackage::new(cryptde, route.clone(), expected_payload.into(), &key)
@@ -2910,9 +5079,9 @@ mod tests {
         let cryptde = cryptde();
         let tls_request = [
             0x16, // content_type: Handshake
-            0x00, 0x00, 0x00, 0x00, // version, length: don't care
+            0x03, 0x01, 0x00, 0x3F, // version: TLS 1.0, length: 63
             0x01, // handshake_type: ClientHello
-            0x00, 0x00, 0x00, 0x00, 0x00, // length, version: don't care
+            0x00, 0x00, 0x3B, 0x03, 0x03, // length: 59, version: TLS 1.2
             0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
             0x00, 0x00, // random: don't care
             0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
@@ -2992,6 +5161,7 @@ mod tests {
                 expected_services: vec![ExpectedService::Nothing],
                 protocol: ProxyProtocol::TLS,
                 server_name: None,
+                dns_query_opt: None,
             },
         );
         let subject_addr: Addr<ProxyServer> = subject.start();
@@ -3049,9 +5219,10 @@ mod tests {
                 expected_services: ExpectedServices::RoundTrip(vec![], vec![], 1234),
             },
         );
-        subject
-            .tunneled_hosts
-            .insert(stream_key.clone(), "hostname".to_string());
+        subject.tunneled_hosts.insert(
+            stream_key.clone(),
+            Tunnel::new("hostname".to_string(), TLS_PORT),
+        );
         subject.route_ids_to_return_routes.insert(
             1234,
             AddReturnRouteMessage {
@@ -3059,6 +5230,7 @@ mod tests {
                 expected_services: vec![],
                 protocol: ProxyProtocol::HTTP,
                 server_name: None,
+                dns_query_opt: None,
             },
         );
 
@@ -3130,6 +5302,7 @@ mod tests {
                 ],
                 protocol: ProxyProtocol::TLS,
                 server_name: None,
+                dns_query_opt: None,
             },
         );
         let incoming_route_g_wallet = make_wallet("G Earning");
@@ -3159,6 +5332,7 @@ mod tests {
                 ],
                 protocol: ProxyProtocol::TLS,
                 server_name: None,
+                dns_query_opt: None,
             },
         );
         let subject_addr: Addr<ProxyServer> = subject.start();
@@ -3314,6 +5488,7 @@ mod tests {
                 )],
                 protocol: ProxyProtocol::HTTP,
                 server_name: Some("server.com".to_string()),
+                dns_query_opt: None,
             })
             .unwrap();
 
@@ -3338,6 +5513,240 @@ mod tests {
         );
     }
 
+    #[test]
+    fn handle_exit_policy_refusal_sends_refusal_page_to_dispatcher() {
+        let system = System::new("handle_exit_policy_refusal_sends_refusal_page_to_dispatcher");
+        let (dispatcher_mock, _, dispatcher_log_arc) = make_recorder();
+        let (neighborhood_mock, _, neighborhood_log_arc) = make_recorder();
+        let cryptde = cryptde();
+        let mut subject = ProxyServer::new(cryptde, false, Some(STANDARD_CONSUMING_WALLET_BALANCE));
+        let stream_key = make_meaningless_stream_key();
+        let socket_addr = SocketAddr::from_str("1.2.3.4:5678").unwrap();
+        subject
+            .keys_and_addrs
+            .insert(stream_key.clone(), socket_addr.clone());
+        let subject_addr: Addr<ProxyServer> = subject.start();
+        let reason = "server.com port 80 is refused by the exit Node's exit policy";
+        let expired_cores_package: ExpiredCoresPackage<ExitPolicyRefusal> =
+            ExpiredCoresPackage::new(
+                SocketAddr::from_str("1.2.3.4:1234").unwrap(),
+                Some(make_wallet("irrelevant")),
+                return_route_with_id(cryptde, 1234),
+                ExitPolicyRefusal::new(stream_key, reason.to_string()),
+                0,
+            );
+        let mut peer_actors = peer_actors_builder()
+            .dispatcher(dispatcher_mock)
+            .neighborhood(neighborhood_mock)
+            .build();
+        peer_actors.proxy_server = ProxyServer::make_subs_from(&subject_addr);
+        subject_addr.try_send(BindMessage { peer_actors }).unwrap();
+        subject_addr
+            .try_send(AddReturnRouteMessage {
+                return_route_id: 1234,
+                expected_services: vec![ExpectedService::Exit(
+                    PublicKey::from(&b"exit_key"[..]),
+                    make_wallet("exit wallet"),
+                    rate_pack(10),
+                )],
+                protocol: ProxyProtocol::HTTP,
+                server_name: Some("server.com".to_string()),
+                dns_query_opt: None,
+            })
+            .unwrap();
+
+        subject_addr.try_send(expired_cores_package).unwrap();
+
+        System::current().stop_with_code(0);
+        system.run();
+        let dispatcher_recording = dispatcher_log_arc.lock().unwrap();
+        assert_eq!(
+            dispatcher_recording.get_record::<TransmitDataMsg>(0),
+            &TransmitDataMsg {
+                endpoint: Endpoint::Socket(socket_addr),
+                last_data: true,
+                sequence_number: Some(0),
+                data: ServerImpersonatorHttp {}
+                    .exit_policy_refusal_response(Some("server.com".to_string()), reason),
+            }
+        );
+        assert_eq!(neighborhood_log_arc.lock().unwrap().len(), 0);
+    }
+
+    #[test]
+    fn tunneled_dns_query_goes_to_hopper_and_its_answer_comes_back_to_entry_dns() {
+        let cryptde = cryptde();
+        let key = cryptde.public_key().clone();
+        let (hopper_mock, hopper_awaiter, hopper_recording_arc) = make_recorder();
+        let (neighborhood_mock, _, neighborhood_recording_arc) = make_recorder();
+        let neighborhood_mock =
+            neighborhood_mock.route_query_response(Some(zero_hop_route_response(&key, cryptde)));
+        let route = zero_hop_route_response(&key, cryptde).route;
+        let (respond_to, answers) = futures::sync::mpsc::unbounded();
+        let records = vec![DnsRecord {
+            name: "example.com".to_string(),
+            ttl: 300,
+            data: DnsRecordData::Txt(vec![b"v=spf1 -all".to_vec()]),
+        }];
+        let (addr_tx, addr_rx) = mpsc::channel();
+        thread::spawn(move || {
+            let system = System::new(
+                "tunneled_dns_query_goes_to_hopper_and_its_answer_comes_back_to_entry_dns",
+            );
+            let subject = ProxyServer::new(cryptde, false, Some(STANDARD_CONSUMING_WALLET_BALANCE));
+            let subject_addr: Addr<ProxyServer> = subject.start();
+            let mut peer_actors = peer_actors_builder()
+                .hopper(hopper_mock)
+                .neighborhood(neighborhood_mock)
+                .build();
+            peer_actors.proxy_server = ProxyServer::make_subs_from(&subject_addr);
+            subject_addr.try_send(BindMessage { peer_actors }).unwrap();
+
+            subject_addr
+                .try_send(TunneledDnsQuery {
+                    query_id: 1234,
+                    name: "example.com".to_string(),
+                    record_type: 16,
+                    respond_to,
+                })
+                .unwrap();
+            addr_tx.send(subject_addr).unwrap();
+
+            system.run();
+        });
+
+        hopper_awaiter.await_message_count(1);
+        let package = hopper_recording_arc
+            .lock()
+            .unwrap()
+            .get_record::<IncipientCoresPackage>(0)
+            .clone();
+        let stream_key = match decodex::<MessageType>(cryptde, &package.payload).unwrap() {
+            MessageType::DnsQuery(query) => query.stream_key,
+            other => panic!("Expected DnsQuery, got {:?}", other),
+        };
+        let expected_payload = DnsQueryPayload {
+            version: DnsQueryPayload::version(),
+            stream_key,
+            name: "example.com".to_string(),
+            record_type: 16,
+            originator_public_key: key.clone(),
+        };
+        assert_eq!(
+            package,
+            IncipientCoresPackage::new(cryptde, route, expected_payload.into(), &key).unwrap()
+        );
+        assert_eq!(
+            neighborhood_recording_arc
+                .lock()
+                .unwrap()
+                .get_record::<RouteQueryMessage>(0),
+            &RouteQueryMessage::data_indefinite_route_request(0)
+        );
+        let subject_addr: Addr<ProxyServer> = addr_rx.recv().unwrap();
+        subject_addr
+            .try_send(ExpiredCoresPackage::new(
+                SocketAddr::from_str("1.2.3.4:1234").unwrap(),
+                None,
+                return_route_with_id(cryptde, 0),
+                DnsAnswerPayload::new(stream_key, 0, records.clone()),
+                0,
+            ))
+            .unwrap();
+        assert_eq!(
+            answers.wait().next().unwrap().unwrap(),
+            TunneledDnsAnswer::new(1234, 0, records)
+        );
+    }
+
+    #[test]
+    fn dns_answer_is_discarded_unless_it_comes_back_on_the_route_of_its_query() {
+        init_test_logging();
+        let cryptde = cryptde();
+        let mut subject = ProxyServer::new(cryptde, false, Some(STANDARD_CONSUMING_WALLET_BALANCE));
+        let (respond_to, answers) = futures::sync::mpsc::unbounded();
+        let stream_key = make_meaningless_stream_key();
+        subject.dns_query_responders.insert(
+            stream_key,
+            DnsQueryResponder {
+                query_id: 1234,
+                respond_to,
+                return_route_id_opt: Some(4321),
+                asked: Instant::now(),
+            },
+        );
+        vec![4321, 5432].into_iter().for_each(|return_route_id| {
+            subject.route_ids_to_return_routes.insert(
+                return_route_id,
+                AddReturnRouteMessage {
+                    return_route_id,
+                    expected_services: vec![ExpectedService::Nothing],
+                    protocol: ProxyProtocol::HTTP,
+                    server_name: None,
+                    dns_query_opt: None,
+                },
+            )
+        });
+        let answer_on = |return_route_id: u32, stream_key: StreamKey| {
+            ExpiredCoresPackage::new(
+                SocketAddr::from_str("1.2.3.4:1234").unwrap(),
+                None,
+                return_route_with_id(cryptde, return_route_id),
+                DnsAnswerPayload::new(stream_key, 0, vec![]),
+                0,
+            )
+        };
+
+        subject.handle_dns_answer(answer_on(5432, stream_key));
+        subject.handle_dns_answer(answer_on(4321, StreamKey::random()));
+        subject.handle_dns_answer(answer_on(4321, stream_key));
+
+        drop(subject);
+        assert_eq!(
+            answers
+                .wait()
+                .map(|answer| answer.unwrap())
+                .collect::<Vec<TunneledDnsAnswer>>(),
+            vec![TunneledDnsAnswer::new(1234, 0, vec![])]
+        );
+        TestLogHandler::new().exists_log_containing(
+            "ERROR: ProxyServer: Discarding DNS answer for query 1234 that came back on the wrong route",
+        );
+    }
+
+    #[test]
+    fn tunneled_dns_query_fails_when_no_route_can_be_found() {
+        let cryptde = cryptde();
+        let (neighborhood_mock, _, _) = make_recorder();
+        let neighborhood_mock = neighborhood_mock.route_query_response(None);
+        let (respond_to, answers) = futures::sync::mpsc::unbounded();
+        thread::spawn(move || {
+            let system = System::new("tunneled_dns_query_fails_when_no_route_can_be_found");
+            let subject = ProxyServer::new(cryptde, true, Some(STANDARD_CONSUMING_WALLET_BALANCE));
+            let subject_addr: Addr<ProxyServer> = subject.start();
+            let peer_actors = peer_actors_builder()
+                .neighborhood(neighborhood_mock)
+                .build();
+            subject_addr.try_send(BindMessage { peer_actors }).unwrap();
+
+            subject_addr
+                .try_send(TunneledDnsQuery {
+                    query_id: 1234,
+                    name: "example.com".to_string(),
+                    record_type: 15,
+                    respond_to,
+                })
+                .unwrap();
+
+            system.run();
+        });
+
+        assert_eq!(
+            answers.wait().next().unwrap().unwrap(),
+            TunneledDnsAnswer::new(1234, SERVER_FAILURE_RESPONSE_CODE, vec![])
+        );
+    }
+
     #[test]
     fn handle_dns_resolve_failure_reports_services_consumed() {
         let system = System::new("proxy_server_records_accounting");
@@ -3377,6 +5786,7 @@ mod tests {
                 ],
                 protocol: ProxyProtocol::TLS,
                 server_name: Some("server.com".to_string()),
+                dns_query_opt: None,
             },
         );
 
@@ -3450,6 +5860,7 @@ mod tests {
                 )],
                 protocol: ProxyProtocol::HTTP,
                 server_name: Some("server.com".to_string()),
+                dns_query_opt: None,
             },
         );
 
@@ -3517,6 +5928,7 @@ mod tests {
                 )],
                 protocol: ProxyProtocol::HTTP,
                 server_name: Some("server.com".to_string()),
+                dns_query_opt: None,
             },
         );
 
@@ -3586,6 +5998,7 @@ mod tests {
                 )],
                 protocol: ProxyProtocol::HTTP,
                 server_name: None,
+                dns_query_opt: None,
             },
         );
 
@@ -3646,9 +6059,10 @@ mod tests {
         subject
             .keys_and_addrs
             .insert(stream_key.clone(), socket_addr.clone());
-        subject
-            .tunneled_hosts
-            .insert(stream_key.clone(), "tunneled host".to_string());
+        subject.tunneled_hosts.insert(
+            stream_key.clone(),
+            Tunnel::new("tunneled host".to_string(), TLS_PORT),
+        );
         subject.stream_key_routes.insert(
             stream_key.clone(),
             RouteQueryResponse {
@@ -3663,6 +6077,7 @@ mod tests {
                 expected_services: vec![ExpectedService::Nothing, ExpectedService::Nothing],
                 protocol: ProxyProtocol::HTTP,
                 server_name: None,
+                dns_query_opt: None,
             },
         );
         let dns_resolve_failure = DnsResolveFailure::new(stream_key);
@@ -3702,6 +6117,7 @@ mod tests {
                 expected_services: vec![ExpectedService::Nothing],
                 protocol: ProxyProtocol::HTTP,
                 server_name: None,
+                dns_query_opt: None,
             },
         );
         let subject_addr: Addr<ProxyServer> = subject.start();
@@ -3871,6 +6287,7 @@ mod tests {
                     expected_services: vec![],
                     protocol: ProxyProtocol::TLS,
                     server_name: None,
+                    dns_query_opt: None,
                 },
             );
             let subject_addr: Addr<ProxyServer> = subject.start();
@@ -3923,9 +6340,10 @@ mod tests {
                 expected_services: ExpectedServices::RoundTrip(vec![], vec![], 1234),
             },
         );
-        subject
-            .tunneled_hosts
-            .insert(unaffected_stream_key, "blah".to_string());
+        subject.tunneled_hosts.insert(
+            unaffected_stream_key,
+            Tunnel::new("blah".to_string(), TLS_PORT),
+        );
 
         subject.handle_stream_shutdown_msg(StreamShutdownMsg {
             peer_addr: SocketAddr::from_str("1.2.3.4:5678").unwrap(),
@@ -4003,12 +6421,14 @@ mod tests {
                 ),
             },
         );
-        subject
-            .tunneled_hosts
-            .insert(unaffected_stream_key, "blah".to_string());
-        subject
-            .tunneled_hosts
-            .insert(affected_stream_key, "tunneled.com".to_string());
+        subject.tunneled_hosts.insert(
+            unaffected_stream_key,
+            Tunnel::new("blah".to_string(), TLS_PORT),
+        );
+        subject.tunneled_hosts.insert(
+            affected_stream_key,
+            Tunnel::new("tunneled.com".to_string(), TLS_PORT),
+        );
         let subject_addr = subject.start();
         let (hopper, _, hopper_recording_arc) = make_recorder();
         let (proxy_server, _, proxy_server_recording_arc) = make_recorder();
@@ -4046,6 +6466,9 @@ mod tests {
                     target_port: 443,
                     protocol: ProxyProtocol::TLS,
                     originator_public_key: cryptde().public_key().clone(),
+                    fresh_return_route: false,
+                    alpn_protocols: vec![],
+                    trace_id_opt: None,
                 }
             ),
             other => panic!("Wrong payload type: {:?}", other),
@@ -4158,6 +6581,9 @@ mod tests {
                     target_port: HTTP_PORT,
                     protocol: ProxyProtocol::HTTP,
                     originator_public_key: cryptde().public_key().clone(),
+                    fresh_return_route: false,
+                    alpn_protocols: vec![],
+                    trace_id_opt: None,
                 }
             ),
             other => panic!("Wrong payload type: {:?}", other),
@@ -4206,12 +6632,14 @@ mod tests {
                 expected_services: ExpectedServices::RoundTrip(vec![], vec![], 1234),
             },
         );
-        subject
-            .tunneled_hosts
-            .insert(unaffected_stream_key, "blah".to_string());
-        subject
-            .tunneled_hosts
-            .insert(affected_stream_key, "blah".to_string());
+        subject.tunneled_hosts.insert(
+            unaffected_stream_key,
+            Tunnel::new("blah".to_string(), TLS_PORT),
+        );
+        subject.tunneled_hosts.insert(
+            affected_stream_key,
+            Tunnel::new("blah".to_string(), TLS_PORT),
+        );
 
         subject.handle_stream_shutdown_msg(StreamShutdownMsg {
             peer_addr: affected_socket_addr,
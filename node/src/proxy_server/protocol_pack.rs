@@ -1,11 +1,15 @@
 // Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
 use crate::persistent_configuration::{HTTP_PORT, TLS_PORT};
 use crate::proxy_server::http_protocol_pack::HttpProtocolPack;
+use crate::proxy_server::raw_protocol_pack::RawProtocolPack;
 use crate::proxy_server::tls_protocol_pack::TlsProtocolPack;
 use crate::sub_lib::cryptde::{PlainData, PublicKey};
 use crate::sub_lib::dispatcher::InboundClientData;
 use crate::sub_lib::logger::Logger;
+use crate::sub_lib::privacy;
 use crate::sub_lib::proxy_server::ProxyProtocol;
+use std::fmt;
+use std::io;
 
 #[derive(Clone, Debug, PartialEq)]
 pub struct Host {
@@ -17,6 +21,7 @@ pub trait ProtocolPack: Send + Sync {
     fn proxy_protocol(&self) -> ProxyProtocol;
     fn standard_port(&self) -> u16;
     fn find_host(&self, data: &PlainData) -> Option<Host>;
+    fn find_alpn_protocols(&self, data: &PlainData) -> Vec<String>;
     fn server_impersonator(&self) -> Box<dyn ServerImpersonator>;
 }
 
@@ -24,6 +29,7 @@ pub fn from_protocol(protocol: ProxyProtocol) -> Box<dyn ProtocolPack> {
     match protocol {
         ProxyProtocol::HTTP => Box::new(HttpProtocolPack {}),
         ProxyProtocol::TLS => Box::new(TlsProtocolPack {}),
+        ProxyProtocol::Raw => Box::new(RawProtocolPack {}),
     }
 }
 
@@ -42,7 +48,7 @@ pub fn from_ibcd(ibcd: &InboundClientData, logger: &Logger) -> Option<Box<dyn Pr
                 logger,
                 "No origin port specified with {}-byte non-clandestine packet: {:?}",
                 ibcd.data.len(),
-                ibcd.data
+                privacy::data(&ibcd.data)
             );
             return None;
         }
@@ -56,13 +62,49 @@ pub fn from_ibcd(ibcd: &InboundClientData, logger: &Logger) -> Option<Box<dyn Pr
                 "No protocol associated with origin port {} for {}-byte non-clandestine packet: {:?}",
                 origin_port,
                 ibcd.data.len(),
-                &ibcd.data
+                privacy::data(&ibcd.data)
             );
             None
         }
     }
 }
 
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ServerConnectionFailure {
+    Refused,
+    TimedOut,
+    Reset,
+    Other,
+}
+
+impl fmt::Display for ServerConnectionFailure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let description = match self {
+            ServerConnectionFailure::Refused => "connection refused",
+            ServerConnectionFailure::TimedOut => "connection timed out",
+            ServerConnectionFailure::Reset => "connection reset",
+            ServerConnectionFailure::Other => "connection failed",
+        };
+        write!(f, "{}", description)
+    }
+}
+
+impl From<io::ErrorKind> for ServerConnectionFailure {
+    fn from(kind: io::ErrorKind) -> Self {
+        match kind {
+            io::ErrorKind::ConnectionRefused => ServerConnectionFailure::Refused,
+            io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock => {
+                ServerConnectionFailure::TimedOut
+            }
+            io::ErrorKind::ConnectionReset
+            | io::ErrorKind::ConnectionAborted
+            | io::ErrorKind::BrokenPipe
+            | io::ErrorKind::UnexpectedEof => ServerConnectionFailure::Reset,
+            _ => ServerConnectionFailure::Other,
+        }
+    }
+}
+
 pub trait ServerImpersonator {
     fn route_query_failure_response(&self, server_name: &str) -> Vec<u8>;
     fn dns_resolution_failure_response(
@@ -71,4 +113,14 @@ pub trait ServerImpersonator {
         server_name_opt: Option<String>,
     ) -> Vec<u8>;
     fn consuming_wallet_absent(&self) -> Vec<u8>;
+    fn server_connection_failure_response(
+        &self,
+        server_name_opt: Option<String>,
+        failure: ServerConnectionFailure,
+    ) -> Vec<u8>;
+    fn exit_policy_refusal_response(
+        &self,
+        server_name_opt: Option<String>,
+        reason: &str,
+    ) -> Vec<u8>;
 }
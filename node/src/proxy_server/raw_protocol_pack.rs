@@ -0,0 +1,56 @@
+// Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use crate::proxy_server::protocol_pack::{Host, ProtocolPack, ServerImpersonator};
+use crate::proxy_server::server_impersonator_raw::ServerImpersonatorRaw;
+use crate::sub_lib::cryptde::PlainData;
+use crate::sub_lib::proxy_server::ProxyProtocol;
+
+// Whatever a browser sends through a CONNECT tunnel that isn't TLS. Nothing can be assumed about
+// it, so nothing is looked for in it.
+pub struct RawProtocolPack {}
+
+impl ProtocolPack for RawProtocolPack {
+    fn proxy_protocol(&self) -> ProxyProtocol {
+        ProxyProtocol::Raw
+    }
+
+    // Raw streams have no port of their own: their target port always comes from the CONNECT
+    // request that opened the tunnel.
+    fn standard_port(&self) -> u16 {
+        0
+    }
+
+    fn find_host(&self, _data: &PlainData) -> Option<Host> {
+        None
+    }
+
+    fn find_alpn_protocols(&self, _data: &PlainData) -> Vec<String> {
+        vec![]
+    }
+
+    fn server_impersonator(&self) -> Box<dyn ServerImpersonator> {
+        Box::new(ServerImpersonatorRaw {})
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn knows_its_protocol() {
+        let result = RawProtocolPack {}.proxy_protocol();
+
+        assert_eq!(result, ProxyProtocol::Raw);
+    }
+
+    #[test]
+    fn finds_nothing_even_in_data_that_looks_like_http() {
+        let data = PlainData::new(b"GET /index.html HTTP/1.1\r\nHost: nowhere.com\r\n\r\n");
+
+        assert_eq!(RawProtocolPack {}.find_host(&data), None);
+        assert_eq!(
+            RawProtocolPack {}.find_alpn_protocols(&data),
+            Vec::<String>::new()
+        );
+    }
+}
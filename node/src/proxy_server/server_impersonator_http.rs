@@ -1,5 +1,5 @@
 // Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
-use crate::proxy_server::protocol_pack::ServerImpersonator;
+use crate::proxy_server::protocol_pack::{ServerConnectionFailure, ServerImpersonator};
 use crate::sub_lib::cryptde::PublicKey;
 
 pub struct ServerImpersonatorHttp {}
@@ -51,6 +51,62 @@ impl ServerImpersonator for ServerImpersonatorHttp {
             Set up a funded consuming wallet and try again.",
         )
     }
+
+    fn server_connection_failure_response(
+        &self,
+        server_name_opt: Option<String>,
+        failure: ServerConnectionFailure,
+    ) -> Vec<u8> {
+        let server_name = server_name_opt.unwrap_or_else(|| "<unspecified>".to_string());
+        let (status, title, explanation) = match failure {
+            ServerConnectionFailure::Refused => (
+                502,
+                "Connection Refused",
+                "refused the connection. It may be down, or it may not accept connections on that port",
+            ),
+            ServerConnectionFailure::TimedOut => (
+                504,
+                "Connection Timed Out",
+                "didn't answer in time. It may be overloaded or unreachable from there",
+            ),
+            ServerConnectionFailure::Reset => (
+                502,
+                "Connection Reset",
+                "dropped the connection before it could answer",
+            ),
+            ServerConnectionFailure::Other => {
+                (502, "Connection Failed", "couldn't be reached")
+            }
+        };
+        ServerImpersonatorHttp::make_error_response(
+            status,
+            title,
+            &format!("Exit Node couldn't connect to {}", server_name),
+            &format!(
+                "The exit Node handling your request tried to contact {}, but the server {}. \
+                 Reload the page to try again.",
+                server_name, explanation
+            ),
+        )
+    }
+
+    fn exit_policy_refusal_response(
+        &self,
+        server_name_opt: Option<String>,
+        reason: &str,
+    ) -> Vec<u8> {
+        let server_name = server_name_opt.unwrap_or_else(|| "<unspecified>".to_string());
+        ServerImpersonatorHttp::make_error_response(
+            403,
+            "Exit Policy Refusal",
+            &format!("Exit Node won't connect to {}", server_name),
+            &format!(
+                "The operator of the exit Node handling your request has restricted the servers \
+                 it will contact on behalf of other Nodes, and {} isn't one of them: {}.",
+                server_name, reason
+            ),
+        )
+    }
 }
 
 impl ServerImpersonatorHttp {
@@ -215,6 +271,74 @@ mod tests {
         assert_eq!(expected, result);
     }
 
+    #[test]
+    fn server_connection_failure_response_describes_the_failure() {
+        let subject = ServerImpersonatorHttp {};
+
+        let refused = subject.server_connection_failure_response(
+            Some("server.com".to_string()),
+            ServerConnectionFailure::Refused,
+        );
+        let timed_out = subject.server_connection_failure_response(
+            Some("server.com".to_string()),
+            ServerConnectionFailure::TimedOut,
+        );
+        let reset =
+            subject.server_connection_failure_response(None, ServerConnectionFailure::Reset);
+
+        let refused = String::from_utf8(refused).unwrap();
+        let timed_out = String::from_utf8(timed_out).unwrap();
+        let reset = String::from_utf8(reset).unwrap();
+        assert!(refused.starts_with("HTTP/1.1 502 "), "{}", refused);
+        assert!(
+            refused.contains("<h2>Title: Connection Refused</h2>"),
+            "{}",
+            refused
+        );
+        assert!(
+            refused.contains("<h3>Subtitle: Exit Node couldn't connect to server.com</h3>"),
+            "{}",
+            refused
+        );
+        assert!(timed_out.starts_with("HTTP/1.1 504 "), "{}", timed_out);
+        assert!(
+            timed_out.contains("<h2>Title: Connection Timed Out</h2>"),
+            "{}",
+            timed_out
+        );
+        assert!(reset.starts_with("HTTP/1.1 502 "), "{}", reset);
+        assert!(
+            reset.contains("<h2>Title: Connection Reset</h2>"),
+            "{}",
+            reset
+        );
+        assert!(
+            reset.contains("<h3>Subtitle: Exit Node couldn't connect to <unspecified></h3>"),
+            "{}",
+            reset
+        );
+    }
+
+    #[test]
+    fn exit_policy_refusal_response_produces_expected_error_page() {
+        let subject = ServerImpersonatorHttp {};
+
+        let result = subject.exit_policy_refusal_response(
+            Some("localhost".to_string()),
+            "localhost port 80 is refused by the exit Node's exit policy",
+        );
+
+        let expected = ServerImpersonatorHttp::make_error_response(
+            403,
+            "Exit Policy Refusal",
+            "Exit Node won't connect to localhost",
+            "The operator of the exit Node handling your request has restricted the servers \
+             it will contact on behalf of other Nodes, and localhost isn't one of them: \
+             localhost port 80 is refused by the exit Node's exit policy.",
+        );
+        assert_eq!(expected, result);
+    }
+
     #[test]
     fn consuming_wallet_absent_response_produces_expected_error_page() {
         let subject = ServerImpersonatorHttp {};
@@ -0,0 +1,75 @@
+// Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use crate::proxy_server::protocol_pack::{ServerConnectionFailure, ServerImpersonator};
+use crate::sub_lib::cryptde::PublicKey;
+
+// There's no way to explain a failure to whatever is on the other end of a raw tunnel, so every
+// failure is reported by closing it with nothing said.
+pub struct ServerImpersonatorRaw {}
+
+impl ServerImpersonator for ServerImpersonatorRaw {
+    fn route_query_failure_response(&self, _server_name: &str) -> Vec<u8> {
+        vec![]
+    }
+
+    fn dns_resolution_failure_response(
+        &self,
+        _exit_key: &PublicKey,
+        _server_name_opt: Option<String>,
+    ) -> Vec<u8> {
+        vec![]
+    }
+
+    fn consuming_wallet_absent(&self) -> Vec<u8> {
+        vec![]
+    }
+
+    fn server_connection_failure_response(
+        &self,
+        _server_name_opt: Option<String>,
+        _failure: ServerConnectionFailure,
+    ) -> Vec<u8> {
+        vec![]
+    }
+
+    fn exit_policy_refusal_response(
+        &self,
+        _server_name_opt: Option<String>,
+        _reason: &str,
+    ) -> Vec<u8> {
+        vec![]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_failure_produces_nothing_at_all() {
+        let subject = ServerImpersonatorRaw {};
+
+        assert_eq!(
+            subject.route_query_failure_response("server.com"),
+            Vec::<u8>::new()
+        );
+        assert_eq!(
+            subject.dns_resolution_failure_response(
+                &PublicKey::new(&b"exit"[..]),
+                Some("server.com".to_string())
+            ),
+            Vec::<u8>::new()
+        );
+        assert_eq!(subject.consuming_wallet_absent(), Vec::<u8>::new());
+        assert_eq!(
+            subject.server_connection_failure_response(
+                Some("server.com".to_string()),
+                ServerConnectionFailure::Refused
+            ),
+            Vec::<u8>::new()
+        );
+        assert_eq!(
+            subject.exit_policy_refusal_response(Some("server.com".to_string()), "denied"),
+            Vec::<u8>::new()
+        );
+    }
+}
@@ -1,5 +1,5 @@
 // Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
-use crate::proxy_server::protocol_pack::ServerImpersonator;
+use crate::proxy_server::protocol_pack::{ServerConnectionFailure, ServerImpersonator};
 use crate::sub_lib::cryptde::PublicKey;
 
 pub struct ServerImpersonatorTls {}
@@ -20,6 +20,27 @@ impl ServerImpersonator for ServerImpersonatorTls {
     fn consuming_wallet_absent(&self) -> Vec<u8> {
         Vec::from(&TLS_INTERNAL_ERROR_ALERT[..])
     }
+
+    // TLS has no alert for a server that can't be reached; the closest thing to a reset is
+    // a failed handshake, and everything else is an internal error as far as the browser knows.
+    fn server_connection_failure_response(
+        &self,
+        _server_name_opt: Option<String>,
+        failure: ServerConnectionFailure,
+    ) -> Vec<u8> {
+        match failure {
+            ServerConnectionFailure::Reset => Vec::from(&TLS_HANDSHAKE_FAILURE_ALERT[..]),
+            _ => Vec::from(&TLS_INTERNAL_ERROR_ALERT[..]),
+        }
+    }
+
+    fn exit_policy_refusal_response(
+        &self,
+        _server_name_opt: Option<String>,
+        _reason: &str,
+    ) -> Vec<u8> {
+        Vec::from(&TLS_ACCESS_DENIED_ALERT[..])
+    }
 }
 
 const TLS_INTERNAL_ERROR_ALERT: [u8; 7] = [
@@ -30,6 +51,22 @@ const TLS_INTERNAL_ERROR_ALERT: [u8; 7] = [
     0x50, // internal_error alert
 ];
 
+const TLS_HANDSHAKE_FAILURE_ALERT: [u8; 7] = [
+    0x15, // alert
+    0x03, 0x03, // TLS 1.2
+    0x00, 0x02, // packet length
+    0x02, // fatal alert
+    0x28, // handshake_failure alert
+];
+
+const TLS_ACCESS_DENIED_ALERT: [u8; 7] = [
+    0x15, // alert
+    0x03, 0x03, // TLS 1.2
+    0x00, 0x02, // packet length
+    0x02, // fatal alert
+    0x31, // access_denied alert
+];
+
 const TLS_UNRECOGNIZED_NAME_ALERT: [u8; 7] = [
     0x15, // alert
     0x03, 0x03, // TLS 1.2
@@ -69,4 +106,38 @@ mod tests {
 
         assert_eq!(Vec::from(&TLS_INTERNAL_ERROR_ALERT[..]), result);
     }
+
+    #[test]
+    fn server_connection_reset_produces_handshake_failure_alert() {
+        let subject = ServerImpersonatorTls {};
+
+        let result = subject.server_connection_failure_response(
+            Some("server.com".to_string()),
+            ServerConnectionFailure::Reset,
+        );
+
+        assert_eq!(Vec::from(&TLS_HANDSHAKE_FAILURE_ALERT[..]), result);
+    }
+
+    #[test]
+    fn other_server_connection_failures_produce_internal_error_alert() {
+        let subject = ServerImpersonatorTls {};
+
+        let refused =
+            subject.server_connection_failure_response(None, ServerConnectionFailure::Refused);
+        let timed_out =
+            subject.server_connection_failure_response(None, ServerConnectionFailure::TimedOut);
+
+        assert_eq!(Vec::from(&TLS_INTERNAL_ERROR_ALERT[..]), refused);
+        assert_eq!(Vec::from(&TLS_INTERNAL_ERROR_ALERT[..]), timed_out);
+    }
+
+    #[test]
+    fn exit_policy_refusal_response_produces_access_denied_alert() {
+        let subject = ServerImpersonatorTls {};
+
+        let result = subject.exit_policy_refusal_response(None, "ignored");
+
+        assert_eq!(Vec::from(&TLS_ACCESS_DENIED_ALERT[..]), result);
+    }
 }
@@ -4,6 +4,24 @@ use crate::proxy_server::server_impersonator_tls::ServerImpersonatorTls;
 use crate::sub_lib::binary_traverser::BinaryTraverser;
 use crate::sub_lib::cryptde::PlainData;
 use crate::sub_lib::proxy_server::ProxyProtocol;
+use std::cmp::min;
+
+const RECORD_HEADER_LENGTH: usize = 5;
+const MAX_RECORD_LENGTH: usize = 16384;
+const HANDSHAKE_CONTENT_TYPE: u8 = 0x16;
+const CLIENT_HELLO_HANDSHAKE_TYPE: u8 = 0x01;
+const CLIENT_VERSION_AND_RANDOM_LENGTH: usize = 34;
+const MAX_SESSION_ID_LENGTH: usize = 32;
+const SERVER_NAME_EXTENSION_TYPE: u16 = 0x0000;
+const ALPN_EXTENSION_TYPE: u16 = 0x0010;
+const HOST_NAME_TYPE: u8 = 0x00;
+
+// What a browser tells us about the connection it wants in its ClientHello
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ClientHello {
+    pub server_name_opt: Option<String>,
+    pub alpn_protocols: Vec<String>,
+}
 
 pub struct TlsProtocolPack {}
 
@@ -17,16 +35,14 @@ impl ProtocolPack for TlsProtocolPack {
     }
 
     fn find_host(&self, data: &PlainData) -> Option<Host> {
-        let mut xvsr = BinaryTraverser::new(data);
-        if !TlsProtocolPack::is_handshake(&mut xvsr) {
-            return None;
-        }
-        if !TlsProtocolPack::is_client_hello(&mut xvsr) {
-            return None;
-        }
-        match Self::host_name_from_client_hello(&mut xvsr) {
-            Ok(name) => Some(Host { name, port: None }),
-            Err(()) => None,
+        let name = TlsProtocolPack::client_hello(data.as_slice())?.server_name_opt?;
+        Some(Host { name, port: None })
+    }
+
+    fn find_alpn_protocols(&self, data: &PlainData) -> Vec<String> {
+        match TlsProtocolPack::client_hello(data.as_slice()) {
+            Some(client_hello) => client_hello.alpn_protocols,
+            None => vec![],
         }
     }
 
@@ -36,49 +52,117 @@ impl ProtocolPack for TlsProtocolPack {
 }
 
 impl TlsProtocolPack {
-    fn is_handshake(xvsr: &mut BinaryTraverser) -> bool {
-        let handshake_content_type = 22u8;
-        xvsr.get_u8() == Ok(handshake_content_type)
+    pub fn client_hello(data: &[u8]) -> Option<ClientHello> {
+        let handshake = PlainData::from(TlsProtocolPack::handshake_from_records(data));
+        let mut xvsr = BinaryTraverser::new(&handshake);
+        if xvsr.get_u8() != Ok(CLIENT_HELLO_HANDSHAKE_TYPE) {
+            return None;
+        }
+        let message_length = xvsr.get_u24().ok()? as usize;
+        let message_end = min(xvsr.offset() + message_length, handshake.len());
+        let message = PlainData::new(&handshake.as_slice()[xvsr.offset()..message_end]);
+        TlsProtocolPack::client_hello_from_message(&mut BinaryTraverser::new(&message)).ok()
+    }
+
+    // True while the records so far hold the beginning of a ClientHello but not its end
+    pub fn client_hello_is_incomplete(data: &[u8]) -> bool {
+        let handshake = PlainData::from(TlsProtocolPack::handshake_from_records(data));
+        let mut xvsr = BinaryTraverser::new(&handshake);
+        match (xvsr.get_u8(), xvsr.get_u24()) {
+            (Ok(CLIENT_HELLO_HANDSHAKE_TYPE), Ok(message_length)) => {
+                xvsr.offset() + message_length as usize > handshake.len()
+            }
+            (Ok(CLIENT_HELLO_HANDSHAKE_TYPE), Err(_)) => true,
+            _ => false,
+        }
+    }
+
+    // A ClientHello may be split across several handshake records, and the last of them may have
+    // been cut short; this glues together as much of the handshake as the data holds.
+    fn handshake_from_records(data: &[u8]) -> Vec<u8> {
+        let mut handshake = vec![];
+        let mut remaining = data;
+        while let Some(record_length) = TlsProtocolPack::handshake_record_length(remaining) {
+            let record_end = min(RECORD_HEADER_LENGTH + record_length, remaining.len());
+            handshake.extend_from_slice(&remaining[RECORD_HEADER_LENGTH..record_end]);
+            remaining = &remaining[record_end..];
+        }
+        handshake
     }
 
-    fn is_client_hello(xvsr: &mut BinaryTraverser) -> bool {
-        let handshake_message_type_position = 5;
-        let client_hello_message_type = 1u8;
-        xvsr.advance(handshake_message_type_position - xvsr.offset())
-            .expect("Internal Error");
-        xvsr.get_u8() == Ok(client_hello_message_type)
+    // Record versions from SSL 3.0 to TLS 1.2 are acceptable: TLS 1.3 poses as 1.0 or 1.2 here.
+    fn handshake_record_length(data: &[u8]) -> Option<usize> {
+        if data.len() <= RECORD_HEADER_LENGTH
+            || data[0] != HANDSHAKE_CONTENT_TYPE
+            || data[1] != 0x03
+            || data[2] > 0x03
+        {
+            return None;
+        }
+        match ((data[3] as usize) << 8) | (data[4] as usize) {
+            0 => None,
+            length if length > MAX_RECORD_LENGTH => None,
+            length => Some(length),
+        }
     }
 
-    fn host_name_from_client_hello(xvsr: &mut BinaryTraverser) -> Result<String, ()> {
-        let session_id_length_position = 43;
-        let server_name_extension_type = 0u16;
-        xvsr.advance(session_id_length_position - xvsr.offset())?;
-        let session_id_length = xvsr.get_u8()?;
-        xvsr.advance(session_id_length as usize)?;
+    fn client_hello_from_message(xvsr: &mut BinaryTraverser) -> Result<ClientHello, ()> {
+        xvsr.advance(CLIENT_VERSION_AND_RANDOM_LENGTH)?;
+        let session_id_length = xvsr.get_u8()? as usize;
+        if session_id_length > MAX_SESSION_ID_LENGTH {
+            return Err(());
+        }
+        xvsr.advance(session_id_length)?;
         let cipher_suites_length = xvsr.get_u16()?;
         xvsr.advance(cipher_suites_length as usize)?;
         let compression_methods_length = xvsr.get_u8()?;
         xvsr.advance(compression_methods_length as usize)?;
+        let mut client_hello = ClientHello::default();
+        if xvsr.is_valid() {
+            // Whatever we find before the extensions turn out to be malformed is still worth having
+            let _ = TlsProtocolPack::read_extensions(xvsr, &mut client_hello);
+        }
+        Ok(client_hello)
+    }
+
+    // Unknown extensions--including GREASE values and encrypted ClientHellos--are skipped. If an
+    // extension appears more than once, the first one counts.
+    fn read_extensions(
+        xvsr: &mut BinaryTraverser,
+        client_hello: &mut ClientHello,
+    ) -> Result<(), ()> {
         let extensions_length = xvsr.get_u16()? as usize;
-        let extensions_offset = xvsr.offset();
-        while xvsr.offset() < (extensions_offset + extensions_length) {
+        let extensions_end = xvsr.offset() + extensions_length;
+        while xvsr.offset() < extensions_end {
             let extension_type = xvsr.get_u16()?;
-            if extension_type == server_name_extension_type {
-                return TlsProtocolPack::host_name_from_extension(xvsr);
+            let extension_length = xvsr.get_u16()? as usize;
+            if xvsr.offset() + extension_length > extensions_end {
+                return Err(());
+            }
+            let extension = PlainData::new(xvsr.next_bytes(extension_length)?);
+            let mut extension_xvsr = BinaryTraverser::new(&extension);
+            match extension_type {
+                SERVER_NAME_EXTENSION_TYPE if client_hello.server_name_opt.is_none() => {
+                    client_hello.server_name_opt =
+                        TlsProtocolPack::host_name_from_extension(&mut extension_xvsr).ok()
+                }
+                ALPN_EXTENSION_TYPE if client_hello.alpn_protocols.is_empty() => {
+                    client_hello.alpn_protocols =
+                        TlsProtocolPack::protocols_from_alpn_extension(&mut extension_xvsr)
+                            .unwrap_or_default()
+                }
+                _ => (),
             }
-            let extension_length = xvsr.get_u16()?;
-            xvsr.advance(extension_length as usize)?;
         }
-        Err(())
+        Ok(())
     }
 
     fn host_name_from_extension(xvsr: &mut BinaryTraverser) -> Result<String, ()> {
-        xvsr.advance(2)?;
         let server_name_list_length = xvsr.get_u16()? as usize;
         let server_name_list_end = xvsr.offset() + server_name_list_length;
         while xvsr.offset() < server_name_list_end {
             let server_name_type = xvsr.get_u8()?;
-            if server_name_type == 0x00 {
+            if server_name_type == HOST_NAME_TYPE {
                 return Self::host_name_from_list_entry(xvsr);
             }
             let server_name_length = xvsr.get_u16()?;
@@ -91,16 +175,255 @@ impl TlsProtocolPack {
         let server_name_length = xvsr.get_u16()?;
         let server_name_bytes = xvsr.next_bytes(server_name_length as usize)?;
         match String::from_utf8(Vec::from(server_name_bytes)) {
+            Ok(ref hostname) if hostname.is_empty() => Err(()),
             Ok(hostname) => Ok(hostname),
             Err(_) => Err(()),
         }
     }
+
+    fn protocols_from_alpn_extension(xvsr: &mut BinaryTraverser) -> Result<Vec<String>, ()> {
+        let protocol_list_length = xvsr.get_u16()? as usize;
+        let protocol_list_end = xvsr.offset() + protocol_list_length;
+        let mut protocols = vec![];
+        while xvsr.offset() < protocol_list_end {
+            let protocol_length = xvsr.get_u8()?;
+            let protocol_bytes = xvsr.next_bytes(protocol_length as usize)?;
+            if let Ok(protocol) = String::from_utf8(Vec::from(protocol_bytes)) {
+                protocols.push(protocol);
+            }
+        }
+        Ok(protocols)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    // Captured from curl 7.88.1 (OpenSSL 3.0) connecting to www.example.com with HTTP/2 enabled
+    #[rustfmt::skip]
+    const CURL_TLS_1_3_CLIENT_HELLO: &[u8] = &[
+        0x16, 0x03, 0x01, 0x02, 0x00, 0x01, 0x00, 0x01, 0xFC, 0x03, 0x03, 0x76,
+        0x45, 0xBA, 0x05, 0x59, 0x9B, 0x6C, 0xC2, 0x17, 0x4B, 0x68, 0x8C, 0x78,
+        0xA1, 0x47, 0x4E, 0x44, 0xAF, 0x01, 0xAE, 0xAD, 0x39, 0x0C, 0x9F, 0x0B,
+        0x33, 0x9A, 0x07, 0x95, 0xC2, 0xFE, 0xB6, 0x20, 0x87, 0xA7, 0xE4, 0x82,
+        0xB1, 0x0A, 0x2C, 0xD2, 0x86, 0x15, 0xB4, 0x9D, 0xFF, 0x25, 0x36, 0xF1,
+        0x68, 0x78, 0x7C, 0x3C, 0x2D, 0x5C, 0x95, 0x67, 0xA8, 0x2B, 0x2F, 0x78,
+        0x36, 0x89, 0x87, 0x42, 0x00, 0x3E, 0x13, 0x02, 0x13, 0x03, 0x13, 0x01,
+        0xC0, 0x2C, 0xC0, 0x30, 0x00, 0x9F, 0xCC, 0xA9, 0xCC, 0xA8, 0xCC, 0xAA,
+        0xC0, 0x2B, 0xC0, 0x2F, 0x00, 0x9E, 0xC0, 0x24, 0xC0, 0x28, 0x00, 0x6B,
+        0xC0, 0x23, 0xC0, 0x27, 0x00, 0x67, 0xC0, 0x0A, 0xC0, 0x14, 0x00, 0x39,
+        0xC0, 0x09, 0xC0, 0x13, 0x00, 0x33, 0x00, 0x9D, 0x00, 0x9C, 0x00, 0x3D,
+        0x00, 0x3C, 0x00, 0x35, 0x00, 0x2F, 0x00, 0xFF, 0x01, 0x00, 0x01, 0x75,
+        0x00, 0x00, 0x00, 0x14, 0x00, 0x12, 0x00, 0x00, 0x0F, 0x77, 0x77, 0x77,
+        0x2E, 0x65, 0x78, 0x61, 0x6D, 0x70, 0x6C, 0x65, 0x2E, 0x63, 0x6F, 0x6D,
+        0x00, 0x0B, 0x00, 0x04, 0x03, 0x00, 0x01, 0x02, 0x00, 0x0A, 0x00, 0x16,
+        0x00, 0x14, 0x00, 0x1D, 0x00, 0x17, 0x00, 0x1E, 0x00, 0x19, 0x00, 0x18,
+        0x01, 0x00, 0x01, 0x01, 0x01, 0x02, 0x01, 0x03, 0x01, 0x04, 0x00, 0x10,
+        0x00, 0x0E, 0x00, 0x0C, 0x02, 0x68, 0x32, 0x08, 0x68, 0x74, 0x74, 0x70,
+        0x2F, 0x31, 0x2E, 0x31, 0x00, 0x16, 0x00, 0x00, 0x00, 0x17, 0x00, 0x00,
+        0x00, 0x31, 0x00, 0x00, 0x00, 0x0D, 0x00, 0x2A, 0x00, 0x28, 0x04, 0x03,
+        0x05, 0x03, 0x06, 0x03, 0x08, 0x07, 0x08, 0x08, 0x08, 0x09, 0x08, 0x0A,
+        0x08, 0x0B, 0x08, 0x04, 0x08, 0x05, 0x08, 0x06, 0x04, 0x01, 0x05, 0x01,
+        0x06, 0x01, 0x03, 0x03, 0x03, 0x01, 0x03, 0x02, 0x04, 0x02, 0x05, 0x02,
+        0x06, 0x02, 0x00, 0x2B, 0x00, 0x09, 0x08, 0x03, 0x04, 0x03, 0x03, 0x03,
+        0x02, 0x03, 0x01, 0x00, 0x2D, 0x00, 0x02, 0x01, 0x01, 0x00, 0x33, 0x00,
+        0x26, 0x00, 0x24, 0x00, 0x1D, 0x00, 0x20, 0xD3, 0x65, 0x40, 0x27, 0x1E,
+        0xE1, 0xD7, 0x06, 0x14, 0x22, 0x9E, 0x2B, 0x3D, 0x99, 0x28, 0x5C, 0xF8,
+        0x90, 0xAA, 0x3A, 0x0C, 0x91, 0xAD, 0x17, 0x70, 0xC1, 0x68, 0x0A, 0x70,
+        0x51, 0x8E, 0x52, 0x00, 0x15, 0x00, 0xAE, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00,
+    ];
+
+    // Captured from Node.js 20 connecting to node.example.net, offering h2 and http/1.1
+    #[rustfmt::skip]
+    const NODE_TLS_1_3_CLIENT_HELLO: &[u8] = &[
+        0x16, 0x03, 0x01, 0x01, 0x83, 0x01, 0x00, 0x01, 0x7F, 0x03, 0x03, 0x97,
+        0xBE, 0x7B, 0x0E, 0x14, 0xBC, 0x4D, 0x9A, 0xEB, 0x68, 0x07, 0xBF, 0xA8,
+        0x17, 0xB9, 0x73, 0xF8, 0x4A, 0xBC, 0x7A, 0x92, 0x08, 0xE6, 0x30, 0xD2,
+        0x85, 0x6F, 0xD7, 0xEB, 0x11, 0xA5, 0xA5, 0x20, 0xC7, 0xB8, 0xB2, 0xD1,
+        0x76, 0xE6, 0x84, 0x35, 0x40, 0xF6, 0x65, 0xB8, 0x80, 0xF5, 0xE2, 0xD3,
+        0xC0, 0x19, 0x25, 0xE7, 0x33, 0x2C, 0x8C, 0xBE, 0x34, 0x0D, 0xED, 0xBD,
+        0xD2, 0x52, 0x3F, 0x2E, 0x00, 0x76, 0x13, 0x02, 0x13, 0x03, 0x13, 0x01,
+        0xC0, 0x2F, 0xC0, 0x2B, 0xC0, 0x30, 0xC0, 0x2C, 0x00, 0x9E, 0xC0, 0x27,
+        0x00, 0x67, 0xC0, 0x28, 0x00, 0x6B, 0x00, 0xA3, 0x00, 0x9F, 0xCC, 0xA9,
+        0xCC, 0xA8, 0xCC, 0xAA, 0xC0, 0xAF, 0xC0, 0xAD, 0xC0, 0xA3, 0xC0, 0x9F,
+        0xC0, 0x5D, 0xC0, 0x61, 0xC0, 0x57, 0xC0, 0x53, 0x00, 0xA2, 0xC0, 0xAE,
+        0xC0, 0xAC, 0xC0, 0xA2, 0xC0, 0x9E, 0xC0, 0x5C, 0xC0, 0x60, 0xC0, 0x56,
+        0xC0, 0x52, 0xC0, 0x24, 0x00, 0x6A, 0xC0, 0x23, 0x00, 0x40, 0xC0, 0x0A,
+        0xC0, 0x14, 0x00, 0x39, 0x00, 0x38, 0xC0, 0x09, 0xC0, 0x13, 0x00, 0x33,
+        0x00, 0x32, 0x00, 0x9D, 0xC0, 0xA1, 0xC0, 0x9D, 0xC0, 0x51, 0x00, 0x9C,
+        0xC0, 0xA0, 0xC0, 0x9C, 0xC0, 0x50, 0x00, 0x3D, 0x00, 0x3C, 0x00, 0x35,
+        0x00, 0x2F, 0x00, 0xFF, 0x01, 0x00, 0x00, 0xC0, 0x00, 0x00, 0x00, 0x15,
+        0x00, 0x13, 0x00, 0x00, 0x10, 0x6E, 0x6F, 0x64, 0x65, 0x2E, 0x65, 0x78,
+        0x61, 0x6D, 0x70, 0x6C, 0x65, 0x2E, 0x6E, 0x65, 0x74, 0x00, 0x0B, 0x00,
+        0x04, 0x03, 0x00, 0x01, 0x02, 0x00, 0x0A, 0x00, 0x16, 0x00, 0x14, 0x00,
+        0x1D, 0x00, 0x17, 0x00, 0x1E, 0x00, 0x19, 0x00, 0x18, 0x01, 0x00, 0x01,
+        0x01, 0x01, 0x02, 0x01, 0x03, 0x01, 0x04, 0x00, 0x23, 0x00, 0x00, 0x00,
+        0x10, 0x00, 0x0E, 0x00, 0x0C, 0x02, 0x68, 0x32, 0x08, 0x68, 0x74, 0x74,
+        0x70, 0x2F, 0x31, 0x2E, 0x31, 0x00, 0x16, 0x00, 0x00, 0x00, 0x17, 0x00,
+        0x00, 0x00, 0x0D, 0x00, 0x2A, 0x00, 0x28, 0x04, 0x03, 0x05, 0x03, 0x06,
+        0x03, 0x08, 0x07, 0x08, 0x08, 0x08, 0x09, 0x08, 0x0A, 0x08, 0x0B, 0x08,
+        0x04, 0x08, 0x05, 0x08, 0x06, 0x04, 0x01, 0x05, 0x01, 0x06, 0x01, 0x03,
+        0x03, 0x03, 0x01, 0x03, 0x02, 0x04, 0x02, 0x05, 0x02, 0x06, 0x02, 0x00,
+        0x2B, 0x00, 0x05, 0x04, 0x03, 0x04, 0x03, 0x03, 0x00, 0x2D, 0x00, 0x02,
+        0x01, 0x01, 0x00, 0x33, 0x00, 0x26, 0x00, 0x24, 0x00, 0x1D, 0x00, 0x20,
+        0x22, 0x86, 0x06, 0x84, 0xA4, 0x76, 0xEC, 0x9D, 0xFB, 0xC5, 0x66, 0xF0,
+        0xAF, 0x9E, 0x5B, 0x88, 0x03, 0x45, 0x85, 0x24, 0xBB, 0xB1, 0x73, 0x9E,
+        0x2E, 0x4E, 0x3F, 0x22, 0xFD, 0xB9, 0xB3, 0x51,
+    ];
+
+    // Captured from openssl s_client -tls1_2 -alpn http/1.1 connecting to tls12.example.org
+    #[rustfmt::skip]
+    const OPENSSL_TLS_1_2_CLIENT_HELLO: &[u8] = &[
+        0x16, 0x03, 0x01, 0x00, 0xE3, 0x01, 0x00, 0x00, 0xDF, 0x03, 0x03, 0xBA,
+        0x39, 0x9D, 0x22, 0x5D, 0x3B, 0x86, 0x76, 0x00, 0x32, 0x45, 0xAB, 0x10,
+        0x62, 0xBF, 0x88, 0x33, 0x2D, 0x34, 0xB9, 0xA5, 0xDC, 0xC9, 0x99, 0x0A,
+        0x5D, 0x19, 0x1E, 0x3F, 0x2F, 0x4F, 0xDE, 0x00, 0x00, 0x36, 0xC0, 0x2C,
+        0xC0, 0x30, 0x00, 0x9F, 0xCC, 0xA9, 0xCC, 0xA8, 0xCC, 0xAA, 0xC0, 0x2B,
+        0xC0, 0x2F, 0x00, 0x9E, 0xC0, 0x24, 0xC0, 0x28, 0x00, 0x6B, 0xC0, 0x23,
+        0xC0, 0x27, 0x00, 0x67, 0xC0, 0x0A, 0xC0, 0x14, 0x00, 0x39, 0xC0, 0x09,
+        0xC0, 0x13, 0x00, 0x33, 0x00, 0x9D, 0x00, 0x9C, 0x00, 0x3D, 0x00, 0x3C,
+        0x00, 0x35, 0x00, 0x2F, 0x01, 0x00, 0x00, 0x80, 0xFF, 0x01, 0x00, 0x01,
+        0x00, 0x00, 0x00, 0x00, 0x16, 0x00, 0x14, 0x00, 0x00, 0x11, 0x74, 0x6C,
+        0x73, 0x31, 0x32, 0x2E, 0x65, 0x78, 0x61, 0x6D, 0x70, 0x6C, 0x65, 0x2E,
+        0x6F, 0x72, 0x67, 0x00, 0x0B, 0x00, 0x04, 0x03, 0x00, 0x01, 0x02, 0x00,
+        0x0A, 0x00, 0x0C, 0x00, 0x0A, 0x00, 0x1D, 0x00, 0x17, 0x00, 0x1E, 0x00,
+        0x18, 0x00, 0x19, 0x00, 0x23, 0x00, 0x00, 0x00, 0x10, 0x00, 0x0B, 0x00,
+        0x09, 0x08, 0x68, 0x74, 0x74, 0x70, 0x2F, 0x31, 0x2E, 0x31, 0x00, 0x16,
+        0x00, 0x00, 0x00, 0x17, 0x00, 0x00, 0x00, 0x0D, 0x00, 0x2A, 0x00, 0x28,
+        0x04, 0x03, 0x05, 0x03, 0x06, 0x03, 0x08, 0x07, 0x08, 0x08, 0x08, 0x09,
+        0x08, 0x0A, 0x08, 0x0B, 0x08, 0x04, 0x08, 0x05, 0x08, 0x06, 0x04, 0x01,
+        0x05, 0x01, 0x06, 0x01, 0x03, 0x03, 0x03, 0x01, 0x03, 0x02, 0x04, 0x02,
+        0x05, 0x02, 0x06, 0x02,
+    ];
+
+    // Laid out as Chrome 119 sends it to www.google.com, with its own random, session ID, key share
+    // and ECH payload: GREASE values lead the cipher suites, supported groups and versions, and
+    // an empty GREASE extension comes first, a one-byte one last; since Chrome has no ECH config
+    // for the server, it sends a GREASE encrypted_client_hello
+    #[rustfmt::skip]
+    const CHROME_TLS_1_3_CLIENT_HELLO: &[u8] = &[
+        0x16, 0x03, 0x01, 0x01, 0xF5, 0x01, 0x00, 0x01, 0xF1, 0x03, 0x03, 0xA7,
+        0xC0, 0x2D, 0xD8, 0x43, 0xEF, 0x10, 0xC8, 0x7C, 0xF6, 0x1F, 0x6E, 0x60,
+        0x90, 0x01, 0xE1, 0x56, 0x95, 0x77, 0x32, 0x1D, 0x2C, 0x1C, 0x8D, 0xD3,
+        0xE3, 0x91, 0x25, 0x44, 0x0C, 0xD4, 0x6F, 0x20, 0x44, 0x3D, 0x48, 0x84,
+        0x04, 0x8F, 0x7E, 0xCE, 0xFD, 0x14, 0x02, 0xE2, 0xB6, 0x55, 0x62, 0xA8,
+        0xA4, 0xD6, 0x8D, 0xE6, 0xD7, 0x42, 0x85, 0x1E, 0xCD, 0xC6, 0x58, 0x11,
+        0xB7, 0xA4, 0xA3, 0x70, 0x00, 0x20, 0x0A, 0x0A, 0x13, 0x01, 0x13, 0x02,
+        0x13, 0x03, 0xC0, 0x2B, 0xC0, 0x2F, 0xC0, 0x2C, 0xC0, 0x30, 0xCC, 0xA9,
+        0xCC, 0xA8, 0xC0, 0x13, 0xC0, 0x14, 0x00, 0x9C, 0x00, 0x9D, 0x00, 0x2F,
+        0x00, 0x35, 0x01, 0x00, 0x01, 0x88, 0x3A, 0x3A, 0x00, 0x00, 0x00, 0x1B,
+        0x00, 0x03, 0x02, 0x00, 0x02, 0x00, 0x33, 0x00, 0x2B, 0x00, 0x29, 0x8A,
+        0x8A, 0x00, 0x01, 0x00, 0x00, 0x1D, 0x00, 0x20, 0xE1, 0x13, 0x2D, 0xB1,
+        0xF7, 0x3F, 0xA1, 0x8A, 0xBB, 0x80, 0x34, 0xD7, 0x0B, 0x8E, 0xBB, 0xF6,
+        0x29, 0x42, 0xDC, 0x39, 0x8C, 0x8A, 0x5D, 0x8E, 0xA9, 0xB6, 0xEE, 0x2B,
+        0x3A, 0xA9, 0x7D, 0x57, 0x00, 0x0B, 0x00, 0x02, 0x01, 0x00, 0x00, 0x2D,
+        0x00, 0x02, 0x01, 0x01, 0x00, 0x17, 0x00, 0x00, 0x00, 0x12, 0x00, 0x00,
+        0x00, 0x0A, 0x00, 0x0A, 0x00, 0x08, 0x8A, 0x8A, 0x00, 0x1D, 0x00, 0x17,
+        0x00, 0x18, 0x00, 0x00, 0x00, 0x13, 0x00, 0x11, 0x00, 0x00, 0x0E, 0x77,
+        0x77, 0x77, 0x2E, 0x67, 0x6F, 0x6F, 0x67, 0x6C, 0x65, 0x2E, 0x63, 0x6F,
+        0x6D, 0x44, 0x69, 0x00, 0x05, 0x00, 0x03, 0x02, 0x68, 0x32, 0xFE, 0x0D,
+        0x00, 0xBA, 0x00, 0x00, 0x01, 0x00, 0x01, 0x49, 0x00, 0x20, 0x08, 0x95,
+        0xBD, 0x87, 0x50, 0x7A, 0x05, 0x5B, 0x43, 0x78, 0xA7, 0xDD, 0xBD, 0xBF,
+        0x7F, 0x67, 0x7A, 0x4E, 0x8D, 0xF6, 0xCE, 0x0F, 0x88, 0xF6, 0xAC, 0x82,
+        0x7C, 0xCE, 0xDC, 0x02, 0x94, 0xC8, 0x00, 0x90, 0x8B, 0x57, 0x07, 0x5F,
+        0x47, 0x9E, 0xBF, 0xC3, 0x94, 0xE0, 0x8D, 0x1E, 0xDA, 0x37, 0xE8, 0x18,
+        0xC9, 0x7C, 0xBC, 0xF0, 0xD4, 0x8E, 0x65, 0x94, 0x6F, 0xCB, 0xB4, 0x90,
+        0x38, 0xEE, 0xBD, 0x65, 0xE5, 0x21, 0xCC, 0xD0, 0x85, 0xB1, 0xA6, 0x1B,
+        0x39, 0x5F, 0xD5, 0x3F, 0x47, 0xDC, 0xF2, 0xDF, 0x4C, 0x6D, 0x86, 0x08,
+        0xEC, 0xA7, 0xED, 0x8E, 0x61, 0x1C, 0x3A, 0xF6, 0xAE, 0x81, 0x72, 0xA7,
+        0xA0, 0xC3, 0x2A, 0x0B, 0x84, 0xBF, 0xE2, 0xA3, 0xDB, 0x18, 0x14, 0x18,
+        0x95, 0x53, 0xF4, 0xFA, 0xFC, 0x60, 0x12, 0x1E, 0x2C, 0x1F, 0x71, 0xF7,
+        0x46, 0xD0, 0xCD, 0x15, 0x3E, 0xDA, 0x99, 0xDB, 0xC2, 0xEB, 0xB2, 0xAD,
+        0x60, 0x94, 0xF5, 0x07, 0x8E, 0x63, 0x8E, 0x71, 0xD5, 0xA1, 0x81, 0xCE,
+        0x4D, 0x41, 0x84, 0x6E, 0x13, 0x06, 0xB2, 0x01, 0xA4, 0x64, 0xEE, 0xCE,
+        0xEC, 0x91, 0x1A, 0x46, 0x3A, 0x60, 0x4F, 0x6C, 0xCC, 0x18, 0xEF, 0x69,
+        0xF2, 0xA8, 0x78, 0xE1, 0x1A, 0xFE, 0xE3, 0x8A, 0x00, 0x2B, 0x00, 0x07,
+        0x06, 0xDA, 0xDA, 0x03, 0x04, 0x03, 0x03, 0x00, 0x23, 0x00, 0x00, 0x00,
+        0x0D, 0x00, 0x12, 0x00, 0x10, 0x04, 0x03, 0x08, 0x04, 0x04, 0x01, 0x05,
+        0x03, 0x08, 0x05, 0x05, 0x01, 0x08, 0x06, 0x06, 0x01, 0xFF, 0x01, 0x00,
+        0x01, 0x00, 0x00, 0x05, 0x00, 0x05, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x10, 0x00, 0x0E, 0x00, 0x0C, 0x02, 0x68, 0x32, 0x08, 0x68, 0x74, 0x74,
+        0x70, 0x2F, 0x31, 0x2E, 0x31, 0x2A, 0x2A, 0x00, 0x01, 0x00, 0x00, 0x15,
+        0x00, 0x00,
+    ];
+    // Laid out as Firefox 120 sends it to www.mozilla.org, with its own random, session ID, key
+    // shares and ECH payload: no GREASE, but a GREASE encrypted_client_hello after record_size_limit
+    #[rustfmt::skip]
+    const FIREFOX_TLS_1_3_CLIENT_HELLO: &[u8] = &[
+        0x16, 0x03, 0x01, 0x02, 0x92, 0x01, 0x00, 0x02, 0x8E, 0x03, 0x03, 0x93,
+        0x59, 0xB8, 0x85, 0x6A, 0x4E, 0x82, 0xE8, 0x6B, 0x03, 0x74, 0x88, 0x9D,
+        0x44, 0xB7, 0x16, 0xA5, 0x8E, 0x65, 0x08, 0xE8, 0x46, 0x7D, 0xF4, 0x96,
+        0xEA, 0x13, 0x2A, 0xD5, 0xA4, 0xF7, 0xC8, 0x20, 0x38, 0x92, 0xC6, 0x2F,
+        0xE8, 0x8D, 0xC2, 0xFE, 0x35, 0x51, 0x14, 0x3C, 0x2E, 0x54, 0xFE, 0x77,
+        0xE8, 0x15, 0xCA, 0xFB, 0x58, 0xEC, 0x38, 0x78, 0x62, 0xD0, 0x62, 0x33,
+        0x6B, 0x8F, 0xA0, 0xF2, 0x00, 0x22, 0x13, 0x01, 0x13, 0x03, 0x13, 0x02,
+        0xC0, 0x2B, 0xC0, 0x2F, 0xCC, 0xA9, 0xCC, 0xA8, 0xC0, 0x2C, 0xC0, 0x30,
+        0xC0, 0x0A, 0xC0, 0x09, 0xC0, 0x13, 0xC0, 0x14, 0x00, 0x9C, 0x00, 0x9D,
+        0x00, 0x2F, 0x00, 0x35, 0x01, 0x00, 0x02, 0x23, 0x00, 0x00, 0x00, 0x14,
+        0x00, 0x12, 0x00, 0x00, 0x0F, 0x77, 0x77, 0x77, 0x2E, 0x6D, 0x6F, 0x7A,
+        0x69, 0x6C, 0x6C, 0x61, 0x2E, 0x6F, 0x72, 0x67, 0x00, 0x17, 0x00, 0x00,
+        0xFF, 0x01, 0x00, 0x01, 0x00, 0x00, 0x0A, 0x00, 0x0E, 0x00, 0x0C, 0x00,
+        0x1D, 0x00, 0x17, 0x00, 0x18, 0x00, 0x19, 0x01, 0x00, 0x01, 0x01, 0x00,
+        0x0B, 0x00, 0x02, 0x01, 0x00, 0x00, 0x23, 0x00, 0x00, 0x00, 0x10, 0x00,
+        0x0E, 0x00, 0x0C, 0x02, 0x68, 0x32, 0x08, 0x68, 0x74, 0x74, 0x70, 0x2F,
+        0x31, 0x2E, 0x31, 0x00, 0x05, 0x00, 0x05, 0x01, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x22, 0x00, 0x0A, 0x00, 0x08, 0x04, 0x03, 0x05, 0x03, 0x06, 0x03,
+        0x02, 0x03, 0x00, 0x33, 0x00, 0x6B, 0x00, 0x69, 0x00, 0x1D, 0x00, 0x20,
+        0x7D, 0xFF, 0x40, 0x6B, 0x69, 0xF0, 0xA7, 0x4D, 0xF7, 0xEF, 0xF4, 0xA7,
+        0x7F, 0x7E, 0xD4, 0xEA, 0x83, 0x38, 0xFD, 0x96, 0x6F, 0xD2, 0xA7, 0x7B,
+        0xEC, 0x46, 0xE1, 0xF4, 0x46, 0xF1, 0x66, 0xC6, 0x00, 0x17, 0x00, 0x41,
+        0x04, 0x09, 0x2C, 0x17, 0x17, 0xA0, 0xA9, 0x8C, 0xDD, 0x93, 0x1E, 0xC8,
+        0x5A, 0xCC, 0x80, 0x03, 0xA0, 0x00, 0x1E, 0xB1, 0x2F, 0x6E, 0x76, 0x9A,
+        0x3B, 0xA0, 0x8F, 0x28, 0x2D, 0x06, 0x88, 0x25, 0x71, 0x3C, 0xA1, 0x18,
+        0x6A, 0x36, 0xBD, 0x50, 0x17, 0xE0, 0x0F, 0xEE, 0x69, 0x75, 0xDB, 0x41,
+        0x55, 0x4D, 0x3E, 0xE1, 0x88, 0x4C, 0x32, 0xBD, 0xF6, 0x56, 0x2A, 0x73,
+        0xDB, 0xE2, 0x38, 0x7A, 0x3F, 0x00, 0x2B, 0x00, 0x05, 0x04, 0x03, 0x04,
+        0x03, 0x03, 0x00, 0x0D, 0x00, 0x18, 0x00, 0x16, 0x04, 0x03, 0x05, 0x03,
+        0x06, 0x03, 0x08, 0x04, 0x08, 0x05, 0x08, 0x06, 0x04, 0x01, 0x05, 0x01,
+        0x06, 0x01, 0x02, 0x03, 0x02, 0x01, 0x00, 0x2D, 0x00, 0x02, 0x01, 0x01,
+        0x00, 0x1C, 0x00, 0x02, 0x40, 0x01, 0xFE, 0x0D, 0x01, 0x19, 0x00, 0x00,
+        0x01, 0x00, 0x01, 0x4B, 0x00, 0x20, 0x6D, 0x67, 0xD6, 0x3F, 0xCC, 0xD3,
+        0x7F, 0xA2, 0xD8, 0x91, 0xC7, 0x20, 0xB6, 0xBC, 0x5C, 0x68, 0xB1, 0x8F,
+        0x95, 0xAD, 0xB2, 0x64, 0x79, 0x94, 0xB1, 0x96, 0x16, 0x80, 0x53, 0x92,
+        0xBC, 0xE5, 0x00, 0xEF, 0x35, 0x3A, 0xF0, 0xE6, 0x48, 0x58, 0x2F, 0x7A,
+        0xA2, 0x23, 0x2D, 0xA6, 0x7E, 0xDF, 0x9B, 0x56, 0xB6, 0xB6, 0x5E, 0x86,
+        0x57, 0xF6, 0x08, 0xDD, 0xA0, 0x3D, 0xC3, 0x8C, 0x64, 0x15, 0xFA, 0x9E,
+        0xF8, 0xE7, 0xEC, 0x95, 0xB8, 0x94, 0xE2, 0xCC, 0xEE, 0x76, 0xFD, 0x89,
+        0x9B, 0x5D, 0xE0, 0x79, 0xE2, 0x69, 0xCE, 0xAA, 0x2D, 0xBC, 0x78, 0x22,
+        0x3F, 0xA6, 0x35, 0x45, 0xD8, 0xA4, 0x9C, 0xB7, 0xE9, 0xAC, 0x17, 0x3A,
+        0x4E, 0x92, 0x1C, 0x2B, 0x1F, 0xEA, 0x11, 0xA7, 0x35, 0x7F, 0xDE, 0xCC,
+        0xBF, 0x10, 0x13, 0x87, 0x03, 0xC9, 0xBE, 0xF6, 0x3C, 0xA9, 0xCF, 0xB2,
+        0x6F, 0x40, 0x2B, 0x51, 0x37, 0xDE, 0x79, 0xC9, 0xAF, 0x7C, 0x5A, 0xEB,
+        0xFA, 0xA0, 0x37, 0x97, 0x04, 0x0B, 0x96, 0x57, 0x49, 0x3F, 0x1A, 0xC0,
+        0x66, 0x5A, 0xF7, 0xC5, 0x34, 0x4B, 0x43, 0xDD, 0xD5, 0xCA, 0xC2, 0xF1,
+        0xAE, 0xF5, 0x2F, 0xAD, 0x45, 0x79, 0x5D, 0x7C, 0x42, 0x3E, 0x62, 0x4B,
+        0x3B, 0x1B, 0xFF, 0x7B, 0x52, 0x24, 0xAB, 0x23, 0x9A, 0x96, 0xEF, 0xD7,
+        0xE2, 0x98, 0x00, 0x94, 0xA8, 0xC2, 0x32, 0x04, 0x04, 0x51, 0x27, 0xE0,
+        0xF9, 0x6E, 0xB5, 0x5D, 0x86, 0xD4, 0x61, 0x50, 0xF1, 0xCD, 0x48, 0x83,
+        0x13, 0xF1, 0xFB, 0xED, 0xF6, 0xA3, 0x0F, 0x9B, 0x99, 0x65, 0xBB, 0x2B,
+        0xD3, 0xB8, 0x60, 0x4B, 0x86, 0x7D, 0x7B, 0xB1, 0xAB, 0x7B, 0x7E, 0x47,
+        0x39, 0x49, 0x9E, 0xBA, 0x04, 0x89, 0x74, 0x5B, 0xE8, 0xC4, 0x18, 0x69,
+        0x92, 0x50, 0xDB, 0x78, 0x57, 0x56, 0x56, 0xF5, 0x37, 0x11, 0xA7, 0xF9,
+        0xC2, 0x01, 0xA5, 0x23, 0x76, 0xCC, 0x10, 0x52, 0x6C, 0xE0, 0x13, 0xF7,
+        0xD6, 0xCF, 0x05,
+    ];
+
     #[test]
     fn knows_its_protocol() {
         let result = TlsProtocolPack {}.proxy_protocol();
@@ -391,9 +714,9 @@ mod tests {
         #[rustfmt::skip]
         let data = PlainData::new(&[
             0x16, // content_type: Handshake
-            0x00, 0x00, 0x00, 0x00, // version, length: don't care
+            0x03, 0x01, 0x00, 0x3F, // version: TLS 1.0, length: 63
             0x01, // handshake_type: ClientHello
-            0x00, 0x00, 0x00, 0x00, 0x00, // length, version: don't care
+            0x00, 0x00, 0x3B, 0x03, 0x03, // length: 59, version: TLS 1.2
             0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
             0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
             0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
@@ -681,9 +1004,9 @@ mod tests {
         #[rustfmt::skip]
         let data = PlainData::new(&[
             0x16, // content_type: Handshake
-            0x00, 0x00, 0x00, 0x00, // version, length: don't care
+            0x03, 0x01, 0x00, 0x42, // version: TLS 1.0, length: 66
             0x01, // handshake_type: ClientHello
-            0x00, 0x00, 0x00, 0x00, 0x00, // length, version: don't care
+            0x00, 0x00, 0x3E, 0x03, 0x03, // length: 62, version: TLS 1.2
             0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
             0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
             0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
@@ -720,9 +1043,9 @@ mod tests {
         #[rustfmt::skip]
         let data = PlainData::new(&[
             0x16, // content_type: Handshake
-            0x00, 0x00, 0x00, 0x00, // version, length: don't care
+            0x03, 0x01, 0x00, 0x4F, // version: TLS 1.0, length: 79
             0x01, // handshake_type: ClientHello
-            0x00, 0x00, 0x00, 0x00, 0x00, // length, version: don't care
+            0x00, 0x00, 0x4B, 0x03, 0x03, // length: 75, version: TLS 1.2
             0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
             0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
             0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
@@ -765,9 +1088,9 @@ mod tests {
         #[rustfmt::skip]
         let data = PlainData::new(&[
             0x16, // content_type: Handshake
-            0x00, 0x00, 0x00, 0x00, // version, length: don't care
+            0x03, 0x01, 0x00, 0x46, // version: TLS 1.0, length: 70
             0x01, // handshake_type: ClientHello
-            0x00, 0x00, 0x00, 0x00, 0x00, // length, version: don't care
+            0x00, 0x00, 0x42, 0x03, 0x03, // length: 66, version: TLS 1.2
             0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
             0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
             0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
@@ -792,4 +1115,295 @@ mod tests {
 
         assert_eq!(None, result);
     }
+
+    #[test]
+    fn extracts_host_and_alpn_from_curl_tls_1_3_client_hello() {
+        let result = TlsProtocolPack::client_hello(CURL_TLS_1_3_CLIENT_HELLO);
+
+        assert_eq!(
+            result,
+            Some(ClientHello {
+                server_name_opt: Some(String::from("www.example.com")),
+                alpn_protocols: vec![String::from("h2"), String::from("http/1.1")],
+            })
+        );
+    }
+
+    #[test]
+    fn extracts_host_and_alpn_from_node_tls_1_3_client_hello() {
+        let result = TlsProtocolPack::client_hello(NODE_TLS_1_3_CLIENT_HELLO);
+
+        assert_eq!(
+            result,
+            Some(ClientHello {
+                server_name_opt: Some(String::from("node.example.net")),
+                alpn_protocols: vec![String::from("h2"), String::from("http/1.1")],
+            })
+        );
+    }
+
+    #[test]
+    fn extracts_host_and_alpn_from_openssl_tls_1_2_client_hello() {
+        let result = TlsProtocolPack::client_hello(OPENSSL_TLS_1_2_CLIENT_HELLO);
+
+        assert_eq!(
+            result,
+            Some(ClientHello {
+                server_name_opt: Some(String::from("tls12.example.org")),
+                alpn_protocols: vec![String::from("http/1.1")],
+            })
+        );
+    }
+
+    #[test]
+    fn extracts_host_and_alpn_from_chrome_client_hello_with_grease_and_ech() {
+        let result = TlsProtocolPack::client_hello(CHROME_TLS_1_3_CLIENT_HELLO);
+
+        assert_eq!(
+            result,
+            Some(ClientHello {
+                server_name_opt: Some(String::from("www.google.com")),
+                alpn_protocols: vec![String::from("h2"), String::from("http/1.1")],
+            })
+        );
+    }
+
+    #[test]
+    fn extracts_host_and_alpn_from_firefox_client_hello_with_ech() {
+        let result = TlsProtocolPack::client_hello(FIREFOX_TLS_1_3_CLIENT_HELLO);
+
+        assert_eq!(
+            result,
+            Some(ClientHello {
+                server_name_opt: Some(String::from("www.mozilla.org")),
+                alpn_protocols: vec![String::from("h2"), String::from("http/1.1")],
+            })
+        );
+    }
+
+    #[test]
+    fn reassembles_chrome_client_hello_fragmented_across_records() {
+        let data = refragment(CHROME_TLS_1_3_CLIENT_HELLO, 128);
+
+        let result = TlsProtocolPack::client_hello(&data);
+
+        assert_eq!(
+            result,
+            TlsProtocolPack::client_hello(CHROME_TLS_1_3_CLIENT_HELLO)
+        );
+    }
+
+    #[test]
+    fn client_hello_is_incomplete_until_its_last_record_arrives() {
+        let data = refragment(FIREFOX_TLS_1_3_CLIENT_HELLO, 256);
+        let record_ends = [5 + 256, 2 * (5 + 256), data.len()];
+
+        let result = record_ends
+            .iter()
+            .map(|end| TlsProtocolPack::client_hello_is_incomplete(&data[..*end]))
+            .collect::<Vec<bool>>();
+
+        assert_eq!(result, vec![true, true, false]);
+        assert_eq!(
+            TlsProtocolPack::client_hello_is_incomplete(CHROME_TLS_1_3_CLIENT_HELLO),
+            false
+        );
+    }
+
+    #[test]
+    fn data_that_is_not_a_client_hello_is_never_incomplete() {
+        let mut server_hello = CURL_TLS_1_3_CLIENT_HELLO[..100].to_vec();
+        server_hello[5] = 0x02;
+
+        assert_eq!(
+            TlsProtocolPack::client_hello_is_incomplete(b"GET / HTTP/1.1\r\n"),
+            false
+        );
+        assert_eq!(
+            TlsProtocolPack::client_hello_is_incomplete(&server_hello),
+            false
+        );
+    }
+
+    #[test]
+    fn reassembles_client_hello_fragmented_across_records() {
+        let data = refragment(CURL_TLS_1_3_CLIENT_HELLO, 100);
+
+        let result = TlsProtocolPack::client_hello(&data);
+
+        assert_eq!(
+            result,
+            TlsProtocolPack::client_hello(CURL_TLS_1_3_CLIENT_HELLO)
+        );
+        assert_eq!(data.len(), CURL_TLS_1_3_CLIENT_HELLO.len() + 5 * 5);
+    }
+
+    #[test]
+    fn finds_host_in_client_hello_whose_last_record_is_missing() {
+        let data = refragment(CURL_TLS_1_3_CLIENT_HELLO, 200);
+
+        let result = TlsProtocolPack {}.find_host(&PlainData::new(&data[..205]));
+
+        assert_eq!(
+            result,
+            Some(Host {
+                name: String::from("www.example.com"),
+                port: None,
+            })
+        );
+    }
+
+    #[test]
+    fn skips_grease_and_encrypted_client_hello_extensions() {
+        #[rustfmt::skip]
+        let extensions = [
+            &[
+                0x2A, 0x2A, 0x00, 0x00, // extension_type: GREASE, empty
+                0xFE, 0x0D, 0x00, 0x04, // extension_type: encrypted_client_hello
+                0x00, 0x01, 0x02, 0x03, // opaque to us
+                0x00, 0x00, 0x00, 0x17, // extension_type: server_name
+                0x00, 0x15, 0x00, 0x00, 0x12, // server_name_list_length, server_name_type, length
+            ][..],
+            b"public.example.com",
+            &[
+                0x00, 0x10, 0x00, 0x0E, // extension_type: application_layer_protocol_negotiation
+                0x00, 0x0C, 0x02, // protocol_name_list_length, protocol_name_length
+            ][..],
+            b"h2",
+            &[0x08][..], // protocol_name_length
+            b"http/1.1",
+            &[
+                0x4A, 0x4A, 0x00, 0x01, 0x00, // extension_type: GREASE, one byte
+            ][..],
+        ]
+        .concat();
+        let data = client_hello_with_extensions(&extensions);
+
+        let result = TlsProtocolPack::client_hello(&data);
+
+        assert_eq!(
+            result,
+            Some(ClientHello {
+                server_name_opt: Some(String::from("public.example.com")),
+                alpn_protocols: vec![String::from("h2"), String::from("http/1.1")],
+            })
+        );
+    }
+
+    #[test]
+    fn first_server_name_extension_wins() {
+        #[rustfmt::skip]
+        let extensions = [
+            &[0x00, 0x00, 0x00, 0x0A, 0x00, 0x08, 0x00, 0x00, 0x05][..],
+            b"first",
+            &[0x00, 0x00, 0x00, 0x0B, 0x00, 0x09, 0x00, 0x00, 0x06][..],
+            b"second",
+        ]
+        .concat();
+        let data = client_hello_with_extensions(&extensions);
+
+        let result = TlsProtocolPack::client_hello(&data);
+
+        assert_eq!(result.unwrap().server_name_opt, Some(String::from("first")));
+    }
+
+    #[test]
+    fn accepts_client_hello_without_extensions() {
+        let mut data = client_hello_with_extensions(&[]);
+        // An SSL 3.0 or TLS 1.0 client may leave out the extensions section altogether
+        data.truncate(data.len() - 2);
+        data[4] -= 2;
+        data[8] -= 2;
+
+        let result = TlsProtocolPack::client_hello(&data);
+
+        assert_eq!(result, Some(ClientHello::default()));
+    }
+
+    #[test]
+    fn rejects_records_with_unknown_versions() {
+        vec![[0x02, 0x00], [0x03, 0x04], [0x00, 0x00]]
+            .into_iter()
+            .for_each(|version| {
+                let mut data = CURL_TLS_1_3_CLIENT_HELLO.to_vec();
+                data[1] = version[0];
+                data[2] = version[1];
+
+                let result = TlsProtocolPack::client_hello(&data);
+
+                assert_eq!(result, None, "version: {:?}", version);
+            });
+    }
+
+    #[test]
+    fn rejects_records_longer_than_tls_allows() {
+        let mut data = CURL_TLS_1_3_CLIENT_HELLO.to_vec();
+        data[3] = 0x40;
+        data[4] = 0x01;
+
+        let result = TlsProtocolPack::client_hello(&data);
+
+        assert_eq!(result, None);
+    }
+
+    #[test]
+    fn rejects_session_id_longer_than_32_bytes() {
+        let mut data = CURL_TLS_1_3_CLIENT_HELLO.to_vec();
+        data[43] = 33;
+
+        let result = TlsProtocolPack::client_hello(&data);
+
+        assert_eq!(result, None);
+    }
+
+    #[test]
+    fn finds_alpn_protocols_through_protocol_pack() {
+        let result =
+            TlsProtocolPack {}.find_alpn_protocols(&PlainData::new(OPENSSL_TLS_1_2_CLIENT_HELLO));
+
+        assert_eq!(result, vec![String::from("http/1.1")]);
+    }
+
+    #[test]
+    fn finds_no_alpn_protocols_in_data_that_is_not_a_client_hello() {
+        let result = TlsProtocolPack {}.find_alpn_protocols(&PlainData::new(b"GET / HTTP/1.1"));
+
+        assert_eq!(result, Vec::<String>::new());
+    }
+
+    fn refragment(client_hello: &[u8], fragment_length: usize) -> Vec<u8> {
+        client_hello[RECORD_HEADER_LENGTH..]
+            .chunks(fragment_length)
+            .flat_map(|fragment| {
+                let mut record = vec![0x16, 0x03, 0x01];
+                record.extend_from_slice(&u16_bytes(fragment.len()));
+                record.extend_from_slice(fragment);
+                record
+            })
+            .collect()
+    }
+
+    // Shaped like a modern browser's: a full session_id for TLS 1.3 middlebox compatibility, and a
+    // GREASE value leading the cipher suites
+    fn client_hello_with_extensions(extensions: &[u8]) -> Vec<u8> {
+        let mut message = vec![0x03, 0x03]; // legacy_version: TLS 1.2
+        message.extend_from_slice(&[0xAB; 32]); // random
+        message.push(32); // session_id_length
+        message.extend_from_slice(&[0xCD; 32]); // session_id
+        message.extend_from_slice(&[0x00, 0x06, 0x3A, 0x3A, 0x13, 0x01, 0x13, 0x02]);
+        message.extend_from_slice(&[0x01, 0x00]); // compression_methods: null
+        message.extend_from_slice(&u16_bytes(extensions.len()));
+        message.extend_from_slice(extensions);
+        let mut handshake = vec![0x01, 0x00]; // handshake_type: ClientHello, length
+        handshake.extend_from_slice(&u16_bytes(message.len()));
+        handshake.extend(message);
+        let mut record = vec![0x16, 0x03, 0x01]; // content_type: Handshake, version: TLS 1.0
+        record.extend_from_slice(&u16_bytes(handshake.len()));
+        record.extend(handshake);
+        record
+    }
+
+    fn u16_bytes(value: usize) -> [u8; 2] {
+        [(value >> 8) as u8, (value & 0xFF) as u8]
+    }
 }
@@ -4,9 +4,12 @@ use super::privilege_drop::PrivilegeDropper;
 use super::privilege_drop::PrivilegeDropperReal;
 use crate::bootstrapper::{BootstrapperConfig, RealUser};
 use crate::entry_dns::dns_socket_server::DnsSocketServer;
-use crate::node_configurator::node_configurator_standard::NodeConfiguratorStandardPrivileged;
+use crate::node_configurator::node_configurator_standard::{
+    NodeConfiguratorStandardPrivileged, LOG_LEVELS,
+};
 use crate::node_configurator::NodeConfigurator;
 use crate::sub_lib;
+use crate::sub_lib::entry_dns::EntryDnsTunnel;
 use crate::sub_lib::main_tools::Command;
 use crate::sub_lib::main_tools::StdStreams;
 use crate::sub_lib::socket_server::SocketServer;
@@ -14,16 +17,28 @@ use backtrace::Backtrace;
 use chrono::{DateTime, Local};
 use flexi_logger::LogSpecBuilder;
 use flexi_logger::Logger;
-use flexi_logger::{Cleanup, Criterion, LevelFilter, Naming};
-use flexi_logger::{DeferredNow, Duplicate, Record};
+use flexi_logger::{Age, Cleanup, Criterion, LevelFilter, Naming};
+use flexi_logger::{DeferredNow, Duplicate, FormatFunction, Record};
+use flexi_logger::{LogSpecification, ReconfigurationHandle};
 use futures::try_ready;
+use lazy_static::lazy_static;
+use log::kv;
+use serde_json::{Map, Value};
 use std::any::Any;
+use std::fmt;
 use std::panic::{Location, PanicInfo};
 use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Mutex;
 use std::{io, thread};
 use tokio::prelude::Async;
 use tokio::prelude::Future;
 
+lazy_static! {
+    // Kept so that the log level can be changed after the logging subsystem has started
+    static ref LOG_RECONFIGURATION_HANDLE: Mutex<Option<ReconfigurationHandle>> = Mutex::new(None);
+}
+
 pub struct ServerInitializer {
     dns_socket_server: Box<dyn SocketServer<(), Item = (), Error = ()>>,
     bootstrapper: Box<dyn SocketServer<BootstrapperConfig, Item = (), Error = ()>>,
@@ -78,9 +93,13 @@ impl Future for ServerInitializer {
 
 impl ServerInitializer {
     pub fn new() -> ServerInitializer {
+        let entry_dns_tunnel = EntryDnsTunnel::new();
         ServerInitializer {
-            dns_socket_server: Box::new(DnsSocketServer::new()),
-            bootstrapper: Box::new(Bootstrapper::new(Box::new(LoggerInitializerWrapperReal {}))),
+            dns_socket_server: Box::new(DnsSocketServer::new(entry_dns_tunnel.clone())),
+            bootstrapper: Box::new(Bootstrapper::new(
+                Box::new(LoggerInitializerWrapperReal {}),
+                entry_dns_tunnel,
+            )),
             privilege_dropper: Box::new(PrivilegeDropperReal::new()),
         }
     }
@@ -92,34 +111,172 @@ impl Default for ServerInitializer {
     }
 }
 
+// A default level, optionally followed by levels for particular loggers, named as they are in
+// Logger::new(): for example "warn,Neighborhood=trace,Hopper=debug". flexi_logger matches the
+// names as prefixes, so a logger name also covers every logger whose name starts with it, unless
+// that one is given a level of its own: the longest match wins.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LogLevelSpec {
+    pub default: LevelFilter,
+    pub loggers: Vec<(String, LevelFilter)>,
+}
+
+impl LogLevelSpec {
+    pub fn new(default: LevelFilter) -> LogLevelSpec {
+        LogLevelSpec {
+            default,
+            loggers: vec![],
+        }
+    }
+
+    fn parse_level(level: &str, item: &str) -> Result<LevelFilter, String> {
+        if LOG_LEVELS.contains(&level.to_lowercase().as_str()) {
+            Ok(LevelFilter::from_str(level).expect("LOG_LEVELS and LevelFilter disagree"))
+        } else {
+            Err(format!(
+                "Invalid log level '{}': choose from {}",
+                item,
+                LOG_LEVELS.join(", ")
+            ))
+        }
+    }
+}
+
+impl FromStr for LogLevelSpec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut default_opt = None;
+        let mut loggers = vec![];
+        for item in s.split(',').map(|item| item.trim()) {
+            match item.find('=') {
+                None if default_opt.is_some() => {
+                    return Err(format!("More than one default log level in '{}'", s))
+                }
+                None => default_opt = Some(Self::parse_level(item, item)?),
+                Some(idx) => {
+                    let name = item[..idx].trim();
+                    if name.is_empty() || name.contains(char::is_whitespace) {
+                        return Err(format!("Invalid logger name in log level '{}'", item));
+                    }
+                    let level = Self::parse_level(item[idx + 1..].trim(), item)?;
+                    loggers.push((name.to_string(), level));
+                }
+            }
+        }
+        Ok(LogLevelSpec {
+            default: default_opt.unwrap_or(LevelFilter::Warn),
+            loggers,
+        })
+    }
+}
+
+impl fmt::Display for LogLevelSpec {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.default.to_string().to_lowercase())?;
+        self.loggers.iter().try_for_each(|(name, level)| {
+            write!(f, ",{}={}", name, level.to_string().to_lowercase())
+        })
+    }
+}
+
+pub const DEFAULT_LOG_ROTATION_MEGABYTES: u64 = 100;
+pub const DEFAULT_LOG_RETENTION: usize = 50;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LogFormat {
+    Text,
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(format!("Unrecognized log format: '{}'", s)),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LogRotationTrigger {
+    Size(u64),
+    Hourly,
+    Daily,
+}
+
+impl LogRotationTrigger {
+    fn criterion(self) -> Criterion {
+        match self {
+            LogRotationTrigger::Size(bytes) => Criterion::Size(bytes),
+            LogRotationTrigger::Hourly => Criterion::Age(Age::Hour),
+            LogRotationTrigger::Daily => Criterion::Age(Age::Day),
+        }
+    }
+}
+
+// Rotated logfiles are zipped, and only the newest `keep` of them are kept
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LogRotation {
+    pub trigger: LogRotationTrigger,
+    pub keep: usize,
+}
+
+impl Default for LogRotation {
+    fn default() -> Self {
+        LogRotation {
+            trigger: LogRotationTrigger::Size(DEFAULT_LOG_ROTATION_MEGABYTES * 1_000_000),
+            keep: DEFAULT_LOG_RETENTION,
+        }
+    }
+}
+
 pub trait LoggerInitializerWrapper: Send {
-    fn init(&mut self, file_path: PathBuf, real_user: &RealUser, log_level: LevelFilter);
+    fn init(
+        &mut self,
+        file_path: PathBuf,
+        real_user: &RealUser,
+        log_level: &LogLevelSpec,
+        log_format: LogFormat,
+        log_rotation: LogRotation,
+    );
 }
 
 pub struct LoggerInitializerWrapperReal {}
 
 impl LoggerInitializerWrapper for LoggerInitializerWrapperReal {
-    fn init(&mut self, file_path: PathBuf, real_user: &RealUser, log_level: LevelFilter) {
-        Logger::with(
-            LogSpecBuilder::new()
-                .default(log_level)
-                .module("tokio", LevelFilter::Off)
-                .module("mio", LevelFilter::Off)
-                .build(),
-        )
-        .log_to_file()
-        .directory(file_path.clone())
-        .print_message()
-        .duplicate_to_stderr(Duplicate::Info)
-        .suppress_timestamp()
-        .format(format_function)
-        .rotate(
-            Criterion::Size(100_000_000),
-            Naming::Numbers,
-            Cleanup::KeepZipFiles(50),
-        )
-        .start()
-        .expect("Logging subsystem failed to start");
+    fn init(
+        &mut self,
+        file_path: PathBuf,
+        real_user: &RealUser,
+        log_level: &LogLevelSpec,
+        log_format: LogFormat,
+        log_rotation: LogRotation,
+    ) {
+        let format: FormatFunction = match log_format {
+            LogFormat::Text => format_function,
+            LogFormat::Json => json_format_function,
+        };
+        let handle = Logger::with(log_spec(log_level))
+            .log_to_file()
+            .directory(file_path.clone())
+            .print_message()
+            .duplicate_to_stderr(Duplicate::Info)
+            .suppress_timestamp()
+            .format(format)
+            .rotate(
+                log_rotation.trigger.criterion(),
+                Naming::Numbers,
+                Cleanup::KeepZipFiles(log_rotation.keep),
+            )
+            .start()
+            .expect("Logging subsystem failed to start");
+        *LOG_RECONFIGURATION_HANDLE
+            .lock()
+            .expect("Log reconfiguration handle is poisoned") = Some(handle);
         let privilege_dropper = PrivilegeDropperReal::new();
         let logfile_name = file_path.join("SubstratumNode_rCURRENT.log");
         privilege_dropper.chown(&logfile_name, real_user);
@@ -129,6 +286,32 @@ impl LoggerInitializerWrapper for LoggerInitializerWrapperReal {
     }
 }
 
+pub fn set_log_level(log_level: &LogLevelSpec) -> Result<(), String> {
+    match LOG_RECONFIGURATION_HANDLE
+        .lock()
+        .expect("Log reconfiguration handle is poisoned")
+        .as_mut()
+    {
+        Some(handle) => {
+            handle.set_new_spec(log_spec(log_level));
+            Ok(())
+        }
+        None => Err("The logging subsystem hasn't started".to_string()),
+    }
+}
+
+fn log_spec(log_level: &LogLevelSpec) -> LogSpecification {
+    let mut builder = LogSpecBuilder::new();
+    builder
+        .default(log_level.default)
+        .module("tokio", LevelFilter::Off)
+        .module("mio", LevelFilter::Off);
+    log_level.loggers.iter().for_each(|(name, level)| {
+        builder.module(name, *level);
+    });
+    builder.build()
+}
+
 struct AltLocation {
     file: String,
     line: u32,
@@ -191,31 +374,91 @@ fn format_function(
     real_format_function(write, now.now(), record)
 }
 
-// ...but this one isn't.
+// ...and neither is this one...
+fn json_format_function(
+    write: &mut dyn io::Write,
+    now: &mut DeferredNow,
+    record: &Record,
+) -> Result<(), io::Error> {
+    real_json_format_function(write, now.now(), record)
+}
+
+// ...but these are.
 pub fn real_format_function(
     write: &mut dyn io::Write,
     timestamp: &DateTime<Local>,
     record: &Record,
 ) -> Result<(), io::Error> {
     let timestamp = timestamp.naive_local().format("%Y-%m-%dT%H:%M:%S%.3f");
-    let thread_id_str = format!("{:?}", thread::current().id());
-    let thread_id = &thread_id_str[9..(thread_id_str.len() - 1)];
     let level = record.level();
     let name = record.module_path().unwrap_or("<unnamed>");
     write.write_fmt(format_args!(
         "{} Thd{}: {}: {}: ",
-        timestamp, thread_id, level, name
+        timestamp,
+        current_thread_id(),
+        level,
+        name
     ))?;
     write.write_fmt(*record.args())
 }
 
+// One JSON object per line. Structured fields sit beside the standard ones, which they can't replace.
+pub fn real_json_format_function(
+    write: &mut dyn io::Write,
+    timestamp: &DateTime<Local>,
+    record: &Record,
+) -> Result<(), io::Error> {
+    let mut object = Map::new();
+    record
+        .key_values()
+        .visit(&mut FieldCollector {
+            object: &mut object,
+        })
+        .map_err(|e| io::Error::new(io::ErrorKind::Other, e.to_string()))?;
+    let standard_fields = vec![
+        (
+            "timestamp",
+            timestamp.format("%Y-%m-%dT%H:%M:%S%.3f%:z").to_string(),
+        ),
+        ("thread", current_thread_id()),
+        (
+            "logger",
+            record.module_path().unwrap_or("<unnamed>").to_string(),
+        ),
+        ("level", record.level().to_string()),
+        ("message", record.args().to_string()),
+    ];
+    standard_fields.into_iter().for_each(|(key, value)| {
+        object.insert(key.to_string(), Value::String(value));
+    });
+    serde_json::to_writer(write, &object).map_err(io::Error::from)
+}
+
+fn current_thread_id() -> String {
+    let thread_id_str = format!("{:?}", thread::current().id());
+    thread_id_str[9..(thread_id_str.len() - 1)].to_string()
+}
+
+struct FieldCollector<'a> {
+    object: &'a mut Map<String, Value>,
+}
+
+impl<'a, 'kvs> kv::Visitor<'kvs> for FieldCollector<'a> {
+    fn visit_pair(&mut self, key: kv::Key<'kvs>, value: kv::Value<'kvs>) -> Result<(), kv::Error> {
+        self.object
+            .insert(key.as_str().to_string(), Value::String(value.to_string()));
+        Ok(())
+    }
+}
+
 #[cfg(test)]
 pub mod test_utils {
     use crate::bootstrapper::RealUser;
     use crate::privilege_drop::PrivilegeDropper;
-    use crate::server_initializer::LoggerInitializerWrapper;
+    use crate::server_initializer::{
+        LogFormat, LogLevelSpec, LogRotation, LoggerInitializerWrapper,
+    };
     use crate::test_utils::logging::init_test_logging;
-    use log::LevelFilter;
     use std::path::PathBuf;
     use std::sync::{Arc, Mutex};
 
@@ -259,16 +502,28 @@ pub mod test_utils {
         }
     }
 
+    pub type LoggerInitParameters = (PathBuf, RealUser, LogLevelSpec, LogFormat, LogRotation);
+
     pub struct LoggerInitializerWrapperMock {
-        init_parameters: Arc<Mutex<Vec<(PathBuf, RealUser, LevelFilter)>>>,
+        init_parameters: Arc<Mutex<Vec<LoggerInitParameters>>>,
     }
 
     impl LoggerInitializerWrapper for LoggerInitializerWrapperMock {
-        fn init(&mut self, file_path: PathBuf, real_user: &RealUser, log_level: LevelFilter) {
-            self.init_parameters
-                .lock()
-                .unwrap()
-                .push((file_path, real_user.clone(), log_level));
+        fn init(
+            &mut self,
+            file_path: PathBuf,
+            real_user: &RealUser,
+            log_level: &LogLevelSpec,
+            log_format: LogFormat,
+            log_rotation: LogRotation,
+        ) {
+            self.init_parameters.lock().unwrap().push((
+                file_path,
+                real_user.clone(),
+                log_level.clone(),
+                log_format,
+                log_rotation,
+            ));
             assert!(init_test_logging());
         }
     }
@@ -282,7 +537,7 @@ pub mod test_utils {
 
         pub fn init_parameters(
             mut self,
-            parameters: &Arc<Mutex<Vec<(PathBuf, RealUser, LevelFilter)>>>,
+            parameters: &Arc<Mutex<Vec<LoggerInitParameters>>>,
         ) -> Self {
             self.init_parameters = parameters.clone();
             self
@@ -299,6 +554,7 @@ pub mod tests {
     use crate::test_utils::logging::{init_test_logging, TestLogHandler};
     use crate::test_utils::ByteArrayWriter;
     use crate::test_utils::{ByteArrayReader, FakeStreamHolder};
+    use chrono::TimeZone;
     use std::sync::Arc;
     use std::sync::Mutex;
 
@@ -443,6 +699,193 @@ pub mod tests {
         tlh.exists_log_containing("ERROR: PanicHandler: file.txt:24:42 - I'm just a string slice");
     }
 
+    #[test]
+    fn set_log_level_is_refused_before_logging_starts() {
+        let result = set_log_level(&LogLevelSpec::new(LevelFilter::Debug));
+
+        assert_eq!(
+            result,
+            Err("The logging subsystem hasn't started".to_string())
+        );
+    }
+
+    #[test]
+    fn log_spec_silences_tokio_and_mio() {
+        let result = log_spec(&LogLevelSpec::new(LevelFilter::Warn));
+
+        assert!(result.enabled(log::Level::Warn, "node_lib::neighborhood"));
+        assert!(!result.enabled(log::Level::Info, "node_lib::neighborhood"));
+        assert!(!result.enabled(log::Level::Error, "tokio::reactor"));
+        assert!(!result.enabled(log::Level::Error, "mio::poll"));
+    }
+
+    #[test]
+    fn log_spec_applies_levels_for_particular_loggers() {
+        let result =
+            log_spec(&LogLevelSpec::from_str("warn,Neighborhood=trace,Hopper=off").unwrap());
+
+        assert!(result.enabled(log::Level::Trace, "Neighborhood"));
+        assert!(!result.enabled(log::Level::Error, "Hopper"));
+        assert!(result.enabled(log::Level::Warn, "Dispatcher"));
+        assert!(!result.enabled(log::Level::Info, "Dispatcher"));
+        assert!(!result.enabled(log::Level::Error, "tokio::reactor"));
+    }
+
+    #[test]
+    fn log_level_spec_parses_default_and_logger_levels() {
+        let result = LogLevelSpec::from_str(" INFO , Neighborhood=trace,Hopper = Debug").unwrap();
+
+        assert_eq!(
+            result,
+            LogLevelSpec {
+                default: LevelFilter::Info,
+                loggers: vec![
+                    ("Neighborhood".to_string(), LevelFilter::Trace),
+                    ("Hopper".to_string(), LevelFilter::Debug),
+                ],
+            }
+        );
+        assert_eq!(result.to_string(), "info,Neighborhood=trace,Hopper=debug");
+    }
+
+    #[test]
+    fn log_level_spec_defaults_to_warn_when_only_loggers_are_given() {
+        let result = LogLevelSpec::from_str("ProxyServer=debug").unwrap();
+
+        assert_eq!(result.to_string(), "warn,ProxyServer=debug");
+    }
+
+    #[test]
+    fn log_level_spec_complains_about_bad_specs() {
+        assert_eq!(
+            LogLevelSpec::from_str("loud"),
+            Err(
+                "Invalid log level 'loud': choose from off, error, warn, info, debug, trace"
+                    .to_string()
+            )
+        );
+        assert_eq!(
+            LogLevelSpec::from_str("warn,Hopper=loud"),
+            Err(
+                "Invalid log level 'Hopper=loud': choose from off, error, warn, info, debug, trace"
+                    .to_string()
+            )
+        );
+        assert_eq!(
+            LogLevelSpec::from_str("warn,=debug"),
+            Err("Invalid logger name in log level '=debug'".to_string())
+        );
+        assert_eq!(
+            LogLevelSpec::from_str("warn,info"),
+            Err("More than one default log level in 'warn,info'".to_string())
+        );
+    }
+
+    #[test]
+    fn log_format_parses_text_and_json() {
+        assert_eq!(LogFormat::from_str("text"), Ok(LogFormat::Text));
+        assert_eq!(LogFormat::from_str("json"), Ok(LogFormat::Json));
+        assert_eq!(
+            LogFormat::from_str("xml"),
+            Err("Unrecognized log format: 'xml'".to_string())
+        );
+    }
+
+    #[test]
+    fn log_rotation_defaults_to_size_based() {
+        let result = LogRotation::default();
+
+        assert_eq!(
+            result,
+            LogRotation {
+                trigger: LogRotationTrigger::Size(100_000_000),
+                keep: 50,
+            }
+        );
+    }
+
+    #[test]
+    fn log_rotation_triggers_become_flexi_logger_criteria() {
+        match LogRotationTrigger::Size(1234).criterion() {
+            Criterion::Size(1234) => (),
+            _ => panic!("Expected Criterion::Size(1234)"),
+        }
+        match LogRotationTrigger::Hourly.criterion() {
+            Criterion::Age(Age::Hour) => (),
+            _ => panic!("Expected Criterion::Age(Age::Hour)"),
+        }
+        match LogRotationTrigger::Daily.criterion() {
+            Criterion::Age(Age::Day) => (),
+            _ => panic!("Expected Criterion::Age(Age::Day)"),
+        }
+    }
+
+    #[test]
+    fn json_format_includes_standard_and_structured_fields() {
+        let timestamp = Local.ymd(2019, 10, 8).and_hms_milli(12, 34, 56, 789);
+        let fields: &[(&str, kv::Value)] = &[
+            ("stream_key", kv::Value::from_display(&"abcd")),
+            ("message", kv::Value::from_display(&"not the message")),
+        ];
+        let mut writer = ByteArrayWriter::new();
+
+        real_json_format_function(
+            &mut writer,
+            &timestamp,
+            &Record::builder()
+                .args(format_args!("Something {} happened", "notable"))
+                .module_path(Some("ProxyServer"))
+                .level(log::Level::Info)
+                .key_values(&fields)
+                .build(),
+        )
+        .unwrap();
+
+        let output = writer.get_string();
+        let json: Value = serde_json::from_str(&output).unwrap();
+        assert!(json["timestamp"]
+            .as_str()
+            .unwrap()
+            .starts_with("2019-10-08T12:34:56.789"));
+        assert_eq!(json["thread"], Value::String(current_thread_id()));
+        assert_eq!(json["logger"], Value::String("ProxyServer".to_string()));
+        assert_eq!(json["level"], Value::String("INFO".to_string()));
+        assert_eq!(
+            json["message"],
+            Value::String("Something notable happened".to_string())
+        );
+        assert_eq!(json["stream_key"], Value::String("abcd".to_string()));
+        assert_eq!(json.as_object().unwrap().len(), 6);
+        assert!(!output.contains('\n'));
+    }
+
+    #[test]
+    fn text_format_ignores_structured_fields() {
+        let timestamp = Local.ymd(2019, 10, 8).and_hms_milli(12, 34, 56, 789);
+        let fields: &[(&str, kv::Value)] = &[("stream_key", kv::Value::from_display(&"abcd"))];
+        let mut writer = ByteArrayWriter::new();
+
+        real_format_function(
+            &mut writer,
+            &timestamp,
+            &Record::builder()
+                .args(format_args!("Something happened"))
+                .module_path(Some("ProxyServer"))
+                .level(log::Level::Info)
+                .key_values(&fields)
+                .build(),
+        )
+        .unwrap();
+
+        assert_eq!(
+            writer.get_string(),
+            format!(
+                "2019-10-08T12:34:56.789 Thd{}: INFO: ProxyServer: Something happened",
+                current_thread_id()
+            )
+        );
+    }
+
     #[test]
     fn exits_after_all_socket_servers_exit() {
         let dns_socket_server = CrashTestDummy::new(CrashPoint::Error, ());
@@ -21,6 +21,7 @@ use crate::sub_lib::neighborhood::NodeQueryResponseMetadata;
 use crate::sub_lib::neighborhood::RemoveNeighborMessage;
 use crate::sub_lib::neighborhood::ZERO_RATE_PACK;
 use crate::sub_lib::node_addr::NodeAddr;
+use crate::sub_lib::privacy;
 use crate::sub_lib::sequence_buffer::SequencedPacket;
 use crate::sub_lib::stream_connector::StreamConnector;
 use crate::sub_lib::stream_connector::StreamConnectorReal;
@@ -90,7 +91,7 @@ impl Display for StreamWriterKey {
         if self.socket_addr.ip().is_loopback() {
             write!(f, "localhost:{}", self.socket_addr.port())
         } else {
-            write!(f, "{}:*", self.socket_addr.ip())
+            write!(f, "{}:*", privacy::ip(self.socket_addr.ip()))
         }
     }
 }
@@ -229,8 +230,8 @@ impl StreamHandlerPool {
                 "non-"
             },
             origin_port,
-            local_addr,
-            peer_addr
+            privacy::socket_addr(local_addr),
+            privacy::socket_addr(peer_addr)
         );
         tokio::spawn(stream_reader);
     }
@@ -258,7 +259,7 @@ impl StreamHandlerPool {
             self.logger,
             "Handling order to transmit {} bytes to {:?}",
             msg.data.len(),
-            msg.endpoint
+            privacy::data(&msg.endpoint)
         );
         let node_query_response_recipient = self
             .self_subs
@@ -275,7 +276,8 @@ impl StreamHandlerPool {
                 };
                 debug!(
                     self.logger,
-                    "Sending node query about {} to Neighborhood", key
+                    "Sending node query about {} to Neighborhood",
+                    privacy::key(&key)
                 );
                 self.ask_neighborhood
                     .as_ref()
@@ -286,7 +288,8 @@ impl StreamHandlerPool {
             Endpoint::Socket(socket_addr) => {
                 debug!(
                     self.logger,
-                    "Translating TransmitDataMsg to node query response about {}", socket_addr
+                    "Translating TransmitDataMsg to node query response about {}",
+                    privacy::socket_addr(socket_addr)
                 );
                 node_query_response_recipient
                     .try_send(DispatcherNodeQueryResponse {
@@ -326,8 +329,8 @@ impl StreamHandlerPool {
         debug!(
             self.logger,
             "Stream from local {} to peer {} has closed; removing writer with key {}",
-            msg.local_addr,
-            msg.peer_addr,
+            privacy::socket_addr(msg.local_addr),
+            privacy::socket_addr(msg.peer_addr),
             stream_writer_key
         );
         let report_to_counterpart = match self.stream_writers.remove(&stream_writer_key) {
@@ -339,7 +342,7 @@ impl StreamHandlerPool {
             stream_type: msg.stream_type,
             report_to_counterpart,
         };
-        debug!(self.logger, "Signaling StreamShutdownMsg to Dispatcher for stream from {} with stream type {:?}, {}report to counterpart", stream_shutdown_msg.peer_addr, stream_shutdown_msg.stream_type, if stream_shutdown_msg.report_to_counterpart {""} else {"don't "});
+        debug!(self.logger, "Signaling StreamShutdownMsg to Dispatcher for stream from {} with stream type {:?}, {}report to counterpart", privacy::socket_addr(stream_shutdown_msg.peer_addr), stream_shutdown_msg.stream_type, if stream_shutdown_msg.report_to_counterpart {""} else {"don't "});
         msg.sub
             .try_send(stream_shutdown_msg)
             .expect("StreamShutdownMsg target is dead");
@@ -350,7 +353,8 @@ impl StreamHandlerPool {
         // TODO Can be recombined with TransmitDataMsg after SC-358/GH-96
         debug!(
             self.logger,
-            "Handling node query response containing {:?}", msg.result
+            "Handling node query response containing {:?}",
+            privacy::data(&msg.result)
         );
         let node_addr = match msg.result.clone() {
             Some(node_descriptor) => match node_descriptor.node_addr_opt {
@@ -359,7 +363,7 @@ impl StreamHandlerPool {
                     error!(
                         self.logger,
                         "No known IP for neighbor in route with key: {}",
-                        node_descriptor.public_key
+                        privacy::key(&node_descriptor.public_key)
                     );
                     return;
                 }
@@ -367,7 +371,8 @@ impl StreamHandlerPool {
             None => {
                 error!(
                     self.logger,
-                    "No neighbor found at endpoint {:?}", msg.context.endpoint
+                    "No neighbor found at endpoint {:?}",
+                    privacy::data(&msg.context.endpoint)
                 );
                 return;
             }
@@ -387,7 +392,7 @@ impl StreamHandlerPool {
                 debug!(
                     self.logger,
                     "Found already-open stream to {} keyed by {}: using",
-                    tx_box.peer_addr(),
+                    privacy::socket_addr(tx_box.peer_addr()),
                     sw_key
                 );
                 debug!(self.logger, "Masking {} bytes", msg.context.data.len());
@@ -399,7 +404,7 @@ impl StreamHandlerPool {
                             error!(
                                 self.logger,
                                 "Masking failed for {}: {}. Discarding {} bytes.",
-                                peer_addr,
+                                privacy::socket_addr(peer_addr),
                                 e,
                                 msg.context.data.len()
                             );
@@ -417,7 +422,7 @@ impl StreamHandlerPool {
                             self.logger,
                             "Removing channel to disabled StreamWriter {} to {}: {}",
                             sw_key,
-                            peer_addr,
+                            privacy::socket_addr(peer_addr),
                             e
                         );
                         self.stream_writers
@@ -429,19 +434,19 @@ impl StreamHandlerPool {
                 };
                 if msg.context.last_data {
                     debug!(self.logger,
-                        "Removing channel to StreamWriter {} to {} in response to server-drop report", sw_key, peer_addr
+                        "Removing channel to StreamWriter {} to {} in response to server-drop report", sw_key, privacy::socket_addr(peer_addr)
                     );
                     self.stream_writers
                         .remove(&StreamWriterKey::from(peer_addr));
                 }
             }
             Some(None) => {
-                debug!(self.logger, "Found in-the-process-of-being-opened stream to {} keyed by {}: preparing to use", peer_addr, sw_key);
+                debug!(self.logger, "Found in-the-process-of-being-opened stream to {} keyed by {}: preparing to use", privacy::socket_addr(peer_addr), sw_key);
                 // a connection is already in progress. resubmit this message, to give the connection time to complete
                 info!(
                     self.logger,
                     "connection for {} in progress, resubmitting {} bytes",
-                    peer_addr,
+                    privacy::socket_addr(peer_addr),
                     msg.context.data.len()
                 );
                 let recipient = self
@@ -462,7 +467,7 @@ impl StreamHandlerPool {
                     error!(
                         self.logger,
                         "Local connection {:?} not found. Discarding {} bytes.",
-                        peer_addr,
+                        privacy::socket_addr(peer_addr),
                         msg.context.data.len()
                     );
                     return;
@@ -470,7 +475,9 @@ impl StreamHandlerPool {
 
                 debug!(
                     self.logger,
-                    "No existing stream keyed by {}: creating one to {}", sw_key, peer_addr
+                    "No existing stream keyed by {}: creating one to {}",
+                    sw_key,
+                    privacy::socket_addr(peer_addr)
                 );
 
                 let subs = self.self_subs.clone().expect("Internal error");
@@ -501,7 +508,7 @@ impl StreamHandlerPool {
 
                 let connect_future = self.stream_connector.connect(peer_addr, &self.logger)
                     .map(move |connection_info| {
-                        debug!(logger_m, "Connection attempt to {} succeeded", peer_addr);
+                        debug!(logger_m, "Connection attempt to {} succeeded", privacy::socket_addr(peer_addr));
                         let origin_port = connection_info.local_addr.port();
                         add_stream_sub.try_send(AddStreamMsg {
                             connection_info,
@@ -511,7 +518,7 @@ impl StreamHandlerPool {
                         node_query_response_sub.try_send(msg).expect("StreamHandlerPool is dead");
                     })
                     .map_err(move |err| { // connection was unsuccessful
-                        error!(logger_me, "Stream to {} does not exist and could not be connected; discarding {} bytes: {}", peer_addr, msg_data_len, err);
+                        error!(logger_me, "Stream to {} does not exist and could not be connected; discarding {} bytes: {}", privacy::socket_addr(peer_addr), msg_data_len, err);
                         remove_sub.try_send(RemoveStreamMsg {
                             peer_addr: peer_addr_e,
                             local_addr: SocketAddr::new (localhost(), 0), // irrelevant; stream was never opened
@@ -523,7 +530,11 @@ impl StreamHandlerPool {
                         tell_neighborhood.try_send(remove_node_message).expect("Neighborhood is Dead");
                     });
 
-                debug!(self.logger, "Beginning connection attempt to {}", peer_addr);
+                debug!(
+                    self.logger,
+                    "Beginning connection attempt to {}",
+                    privacy::socket_addr(peer_addr)
+                );
                 tokio::spawn(connect_future);
             }
         }
@@ -6,6 +6,7 @@ use crate::stream_messages::*;
 use crate::sub_lib::dispatcher;
 use crate::sub_lib::dispatcher::StreamShutdownMsg;
 use crate::sub_lib::logger::Logger;
+use crate::sub_lib::privacy;
 use crate::sub_lib::sequencer::Sequencer;
 use crate::sub_lib::tokio_wrappers::ReadHalfWrapper;
 use crate::sub_lib::utils::indicates_dead_stream;
@@ -166,7 +167,7 @@ impl StreamReaderReal {
                         data: unmasked_chunk.chunk.clone(),
                     };
                     debug!(self.logger, "Discriminator framed and unmasked {} bytes for {}; transmitting via Hopper",
-                                              unmasked_chunk.chunk.len(), msg.peer_addr);
+                                              unmasked_chunk.chunk.len(), privacy::socket_addr(msg.peer_addr));
                     self.ibcd_sub.try_send(msg).expect("Dispatcher is dead");
                 }
                 None => {
@@ -178,7 +179,7 @@ impl StreamReaderReal {
     }
 
     fn shutdown(&mut self) {
-        debug!(self.logger, "Directing removal of {}clandestine StreamReader with reception_port {:?} on {} listening to {}", if self.is_clandestine {""} else {"non-"}, self.reception_port, self.local_addr, self.peer_addr);
+        debug!(self.logger, "Directing removal of {}clandestine StreamReader with reception_port {:?} on {} listening to {}", if self.is_clandestine {""} else {"non-"}, self.reception_port, privacy::socket_addr(self.local_addr), privacy::socket_addr(self.peer_addr));
         self.remove_sub
             .try_send(RemoveStreamMsg {
                 peer_addr: self.peer_addr,
@@ -199,7 +200,11 @@ impl StreamReaderReal {
     }
 
     fn stringify(local_addr: SocketAddr, peer_addr: SocketAddr) -> String {
-        format!("between local {} and peer {}", local_addr, peer_addr)
+        format!(
+            "between local {} and peer {}",
+            privacy::socket_addr(local_addr),
+            privacy::socket_addr(peer_addr)
+        )
     }
 }
 
@@ -1,6 +1,7 @@
 // Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
 use crate::sub_lib::channel_wrappers::ReceiverWrapper;
 use crate::sub_lib::logger::Logger;
+use crate::sub_lib::privacy;
 use crate::sub_lib::sequence_buffer::SequenceBuffer;
 use crate::sub_lib::sequence_buffer::SequencedPacket;
 use crate::sub_lib::tokio_wrappers::WriteHalfWrapper;
@@ -95,7 +96,7 @@ impl StreamWriterSorted {
                                     self.logger,
                                     "Error writing {} bytes to {}: {}",
                                     packet.data.len(),
-                                    self.peer_addr,
+                                    privacy::socket_addr(self.peer_addr),
                                     e
                                 );
                                 return WriteBufferStatus::StreamInError;
@@ -129,7 +130,7 @@ impl StreamWriterSorted {
                                     packet.last_data,
                                 ));
                             } else if packet.last_data {
-                                debug!(self.logger, "Shutting down stream to client at {} in response to server-drop report", self.peer_addr);
+                                debug!(self.logger, "Shutting down stream to client at {} in response to server-drop report", privacy::socket_addr(self.peer_addr));
                                 self.shutting_down = true;
                                 return match self.stream.shutdown() {
                                     Ok(Async::NotReady) => WriteBufferStatus::BufferNotEmpty,
@@ -34,6 +34,7 @@ pub struct AccountantSubs {
     pub report_new_payments: Recipient<ReceivedPayments>,
     pub report_sent_payments: Recipient<SentPayments>,
     pub get_financial_statistics_sub: Recipient<GetFinancialStatisticsMessage>,
+    pub set_earning_wallet_sub: Recipient<SetEarningWalletMsg>,
 }
 
 impl Debug for AccountantSubs {
@@ -77,6 +78,14 @@ pub struct ReportExitServiceConsumedMessage {
 #[derive(Clone, PartialEq, Debug, Message)]
 pub struct GetFinancialStatisticsMessage {
     pub client_id: u64,
+    pub context_id: u64,
+}
+
+#[derive(Clone, PartialEq, Debug, Message)]
+pub struct SetEarningWalletMsg {
+    pub client_id: u64,
+    pub context_id: u64,
+    pub wallet: Wallet,
 }
 
 #[derive(Clone, PartialEq, Debug, Message, Serialize, Deserialize)]
@@ -112,6 +121,7 @@ mod tests {
             report_new_payments: recipient!(recorder, ReceivedPayments),
             report_sent_payments: recipient!(recorder, SentPayments),
             get_financial_statistics_sub: recipient!(recorder, GetFinancialStatisticsMessage),
+            set_earning_wallet_sub: recipient!(recorder, SetEarningWalletMsg),
         };
 
         assert_eq!(format!("{:?}", subject), "AccountantSubs");
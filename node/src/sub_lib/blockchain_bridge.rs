@@ -39,12 +39,14 @@ pub struct ReportAccountsPayable {
 #[derive(Clone, PartialEq, Debug, Message)]
 pub struct SetWalletPasswordMsg {
     pub client_id: u64,
+    pub context_id: u64,
     pub password: String,
 }
 
 #[derive(Clone, PartialEq, Debug, Message)]
 pub struct SetGasPriceMsg {
     pub client_id: u64,
+    pub context_id: u64,
     pub gas_price: String,
 }
 
@@ -0,0 +1,114 @@
+// Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use crate::sub_lib::exit_policy::ExitRule;
+use std::str::FromStr;
+
+// Destinations on the bypass list don't go through the Substratum Network at all: the entry DNS
+// server resolves their names with the system's original DNS servers, and the ProxyServer
+// connects to them directly. A domain rule covers the domain and all of its subdomains.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct BypassList {
+    rules: Vec<ExitRule>,
+}
+
+impl BypassList {
+    pub fn new(rules: Vec<ExitRule>) -> BypassList {
+        BypassList { rules }
+    }
+
+    // Bypass rules are exit rules without the port rules, since DNS knows nothing of ports.
+    pub fn parse_rule(rule: &str) -> Result<ExitRule, String> {
+        match ExitRule::from_str(rule) {
+            Ok(ExitRule::Host(pattern)) if pattern.starts_with("*.") => Ok(ExitRule::Host(pattern)),
+            Ok(ExitRule::Host(pattern)) => Ok(ExitRule::Host(format!("*.{}", pattern))),
+            Ok(ExitRule::Ports(_, _)) | Err(_) => {
+                Err(format!("Invalid bypass rule: {}", rule.trim()))
+            }
+            Ok(rule) => Ok(rule),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    pub fn rules(&self) -> &Vec<ExitRule> {
+        &self.rules
+    }
+
+    // The host can be a name or an IP address, as it would appear in a request.
+    pub fn bypasses(&self, host: &str) -> bool {
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        self.rules
+            .iter()
+            .any(|rule| rule.may_match(Some(host), None) == Some(true))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::IpAddr;
+
+    #[test]
+    fn parse_rule_makes_domains_cover_their_subdomains() {
+        assert_eq!(
+            BypassList::parse_rule("Example.com."),
+            Ok(ExitRule::Host("*.example.com".to_string()))
+        );
+        assert_eq!(
+            BypassList::parse_rule("*.example.com"),
+            Ok(ExitRule::Host("*.example.com".to_string()))
+        );
+        assert_eq!(
+            BypassList::parse_rule("10.0.0.0/8"),
+            Ok(ExitRule::Network(IpAddr::from_str("10.0.0.0").unwrap(), 8))
+        );
+        assert_eq!(
+            BypassList::parse_rule("private"),
+            Ok(ExitRule::PrivateNetworks)
+        );
+    }
+
+    #[test]
+    fn parse_rule_rejects_port_rules_and_nonsense() {
+        assert_eq!(
+            BypassList::parse_rule("port:22"),
+            Err("Invalid bypass rule: port:22".to_string())
+        );
+        assert_eq!(
+            BypassList::parse_rule("10.0.0.0/33"),
+            Err("Invalid bypass rule: 10.0.0.0/33".to_string())
+        );
+        assert_eq!(
+            BypassList::parse_rule("bad..name"),
+            Err("Invalid bypass rule: bad..name".to_string())
+        );
+    }
+
+    #[test]
+    fn bypasses_matches_names_and_addresses() {
+        let subject = BypassList::new(vec![
+            BypassList::parse_rule("intranet.example.com").unwrap(),
+            BypassList::parse_rule("192.168.0.0/16").unwrap(),
+            BypassList::parse_rule("fd00::/8").unwrap(),
+        ]);
+
+        assert_eq!(subject.bypasses("intranet.example.com"), true);
+        assert_eq!(subject.bypasses("Wiki.Intranet.Example.com."), true);
+        assert_eq!(subject.bypasses("example.com"), false);
+        assert_eq!(subject.bypasses("notintranet.example.com"), false);
+        assert_eq!(subject.bypasses("192.168.1.1"), true);
+        assert_eq!(subject.bypasses("192.169.1.1"), false);
+        assert_eq!(subject.bypasses("[fd00::1]"), true);
+        assert_eq!(subject.bypasses("fe80::1"), false);
+    }
+
+    #[test]
+    fn empty_list_bypasses_nothing() {
+        let subject = BypassList::default();
+
+        assert_eq!(subject.is_empty(), true);
+        assert_eq!(subject.bypasses("localhost"), false);
+        assert_eq!(subject.bypasses("127.0.0.1"), false);
+    }
+}
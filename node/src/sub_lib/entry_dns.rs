@@ -0,0 +1,145 @@
+// Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use crate::sub_lib::bypass::BypassList;
+use crate::sub_lib::proxy_server::TunneledDnsQuery;
+use actix::Recipient;
+use std::fmt;
+use std::fmt::Debug;
+use std::fmt::Formatter;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EntryDnsMode {
+    // Every A and AAAA query is answered with localhost; everything else is refused as not implemented.
+    Localhost,
+    // A and AAAA queries are still answered with localhost, but queries for other record types are
+    // looked up by an exit Node so that the answers are real.
+    Tunnel,
+    // Everything an exit Node can look up is looked up, A and AAAA included, so that software that
+    // doesn't go through the proxy port gets real addresses. Browsers have to use the proxy port.
+    Resolve,
+}
+
+impl Default for EntryDnsMode {
+    fn default() -> Self {
+        EntryDnsMode::Localhost
+    }
+}
+
+// The entry DNS server is created before the configuration is read and long before the actors
+// are, so the bypass list and the ProxyServer's recipient for tunneled queries have to be handed
+// over after the fact.
+#[derive(Clone, Default)]
+pub struct EntryDnsTunnel {
+    recipient_opt: Arc<Mutex<Option<Recipient<TunneledDnsQuery>>>>,
+    mode: Arc<Mutex<EntryDnsMode>>,
+    bypass: Arc<Mutex<(BypassList, Vec<SocketAddr>)>>,
+}
+
+impl Debug for EntryDnsTunnel {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(
+            f,
+            "EntryDnsTunnel {{ connected: {} }}",
+            self.recipient_opt().is_some()
+        )
+    }
+}
+
+impl EntryDnsTunnel {
+    pub fn new() -> EntryDnsTunnel {
+        EntryDnsTunnel::default()
+    }
+
+    pub fn connect(&self, recipient: Recipient<TunneledDnsQuery>, mode: EntryDnsMode) {
+        *self.mode.lock().expect("EntryDnsTunnel is poisoned") = mode;
+        *self
+            .recipient_opt
+            .lock()
+            .expect("EntryDnsTunnel is poisoned") = Some(recipient);
+    }
+
+    pub fn resolves_addresses(&self) -> bool {
+        *self.mode.lock().expect("EntryDnsTunnel is poisoned") == EntryDnsMode::Resolve
+    }
+
+    pub fn recipient_opt(&self) -> Option<Recipient<TunneledDnsQuery>> {
+        self.recipient_opt
+            .lock()
+            .expect("EntryDnsTunnel is poisoned")
+            .clone()
+    }
+
+    // Names on the bypass list are looked up by the bypass DNS servers instead of being answered
+    // with localhost.
+    pub fn set_bypass(&self, bypass_list: BypassList, bypass_dns_servers: Vec<SocketAddr>) {
+        *self.bypass.lock().expect("EntryDnsTunnel is poisoned") =
+            (bypass_list, bypass_dns_servers);
+    }
+
+    pub fn bypass(&self) -> (BypassList, Vec<SocketAddr>) {
+        self.bypass
+            .lock()
+            .expect("EntryDnsTunnel is poisoned")
+            .clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::recorder::make_recorder;
+    use actix::{Actor, System};
+    use futures::sync::mpsc::unbounded;
+    use std::str::FromStr;
+
+    #[test]
+    fn clones_of_a_tunnel_see_its_connection() {
+        let system = System::new("clones_of_a_tunnel_see_its_connection");
+        let (proxy_server, _, proxy_server_recording_arc) = make_recorder();
+        let recipient = proxy_server.start().recipient::<TunneledDnsQuery>();
+        let subject = EntryDnsTunnel::new();
+        let clone = subject.clone();
+        assert_eq!(
+            format!("{:?}", clone),
+            "EntryDnsTunnel { connected: false }"
+        );
+
+        assert_eq!(clone.resolves_addresses(), false);
+
+        subject.connect(recipient, EntryDnsMode::Resolve);
+
+        assert_eq!(format!("{:?}", clone), "EntryDnsTunnel { connected: true }");
+        assert_eq!(clone.resolves_addresses(), true);
+        let (respond_to, _) = unbounded();
+        clone
+            .recipient_opt()
+            .unwrap()
+            .try_send(TunneledDnsQuery {
+                query_id: 1,
+                name: "example.com".to_string(),
+                record_type: 15,
+                respond_to,
+            })
+            .unwrap();
+        System::current().stop();
+        system.run();
+        let proxy_server_recording = proxy_server_recording_arc.lock().unwrap();
+        let query = proxy_server_recording.get_record::<TunneledDnsQuery>(0);
+        assert_eq!(query.name, "example.com".to_string());
+    }
+
+    #[test]
+    fn clones_of_a_tunnel_see_its_bypass_list() {
+        let subject = EntryDnsTunnel::new();
+        let clone = subject.clone();
+        assert_eq!(clone.bypass(), (BypassList::default(), vec![]));
+        let bypass_list =
+            BypassList::new(vec![BypassList::parse_rule("intranet.example.com").unwrap()]);
+        let bypass_dns_servers = vec![SocketAddr::from_str("192.168.1.1:53").unwrap()];
+
+        subject.set_bypass(bypass_list.clone(), bypass_dns_servers.clone());
+
+        assert_eq!(clone.bypass(), (bypass_list, bypass_dns_servers));
+    }
+}
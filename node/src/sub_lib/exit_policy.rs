@@ -0,0 +1,716 @@
+// Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use serde::de::IgnoredAny;
+use serde::Deserialize;
+use serde::Deserializer;
+use serde_derive::{Deserialize, Serialize};
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::str::FromStr;
+
+pub const PRIVATE_NETWORKS_RULE: &str = "private";
+// Stands for an empty list of rules on the command line, where an empty value isn't allowed.
+pub const NO_EXIT_RULES: &str = "none";
+// Caps on what a Node says about its exit policy in Gossip, so that a policy can't make a record
+// arbitrarily large. A summary that has to leave something out errs on the permissive side.
+pub const MAX_GOSSIPED_PORT_RANGES: usize = 16;
+pub const MAX_GOSSIPED_HOSTS: usize = 16;
+const MAX_HOST_LEN: usize = 253;
+
+// Addresses nobody on the Substratum Network has any business reaching through someone else's
+// exit Node: loopback, the operator's own LAN, carrier-grade NAT, link-local (which is where
+// cloud providers put their instance-metadata endpoints), multicast and broadcast, and the NAT64
+// prefix, through which a translating network would reach any of the IPv4 ones.
+const PRIVATE_NETWORKS: &[(IpAddr, u8)] = &[
+    (IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)), 8),
+    (IpAddr::V4(Ipv4Addr::new(10, 0, 0, 0)), 8),
+    (IpAddr::V4(Ipv4Addr::new(100, 64, 0, 0)), 10),
+    (IpAddr::V4(Ipv4Addr::new(127, 0, 0, 0)), 8),
+    (IpAddr::V4(Ipv4Addr::new(169, 254, 0, 0)), 16),
+    (IpAddr::V4(Ipv4Addr::new(172, 16, 0, 0)), 12),
+    (IpAddr::V4(Ipv4Addr::new(192, 168, 0, 0)), 16),
+    (IpAddr::V4(Ipv4Addr::new(224, 0, 0, 0)), 4),
+    (IpAddr::V4(Ipv4Addr::new(255, 255, 255, 255)), 32),
+    (IpAddr::V6(Ipv6Addr::new(0, 0, 0, 0, 0, 0, 0, 0)), 128),
+    (IpAddr::V6(Ipv6Addr::new(0, 0, 0, 0, 0, 0, 0, 1)), 128),
+    (
+        IpAddr::V6(Ipv6Addr::new(0x64, 0xff9b, 0, 0, 0, 0, 0, 0)),
+        96,
+    ),
+    (IpAddr::V6(Ipv6Addr::new(0xfc00, 0, 0, 0, 0, 0, 0, 0)), 7),
+    (IpAddr::V6(Ipv6Addr::new(0xfe80, 0, 0, 0, 0, 0, 0, 0)), 10),
+    (IpAddr::V6(Ipv6Addr::new(0xff00, 0, 0, 0, 0, 0, 0, 0)), 8),
+];
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ExitRule {
+    Network(IpAddr, u8),
+    Ports(u16, u16),
+    // Lowercase, without a trailing dot. A leading "*." matches the domain and all its subdomains.
+    Host(String),
+    PrivateNetworks,
+}
+
+impl FromStr for ExitRule {
+    type Err = String;
+
+    fn from_str(rule: &str) -> Result<Self, Self::Err> {
+        let rule = rule.trim();
+        if rule.eq_ignore_ascii_case(PRIVATE_NETWORKS_RULE) {
+            Ok(ExitRule::PrivateNetworks)
+        } else if rule.starts_with("port:") {
+            Self::parse_ports(&rule["port:".len()..])
+        } else if rule.contains('/') {
+            Self::parse_network(rule)
+        } else if let Ok(ip_addr) = IpAddr::from_str(rule) {
+            Ok(ExitRule::Network(ip_addr, Self::max_prefix_len(&ip_addr)))
+        } else {
+            Self::parse_host(rule)
+        }
+    }
+}
+
+impl fmt::Display for ExitRule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ExitRule::Network(ip_addr, prefix_len) => write!(f, "{}/{}", ip_addr, prefix_len),
+            ExitRule::Ports(low, high) if low == high => write!(f, "port:{}", low),
+            ExitRule::Ports(low, high) => write!(f, "port:{}-{}", low, high),
+            ExitRule::Host(pattern) => write!(f, "{}", pattern),
+            ExitRule::PrivateNetworks => write!(f, "{}", PRIVATE_NETWORKS_RULE),
+        }
+    }
+}
+
+impl ExitRule {
+    pub fn matches(&self, hostname: &str, ip_addr: IpAddr, port: u16) -> bool {
+        match self {
+            ExitRule::Network(network, prefix_len) => {
+                Self::network_contains(*network, *prefix_len, ip_addr)
+            }
+            ExitRule::Ports(low, high) => port >= *low && port <= *high,
+            ExitRule::Host(pattern) => Self::host_matches(pattern, hostname),
+            ExitRule::PrivateNetworks => PRIVATE_NETWORKS.iter().any(|(network, prefix_len)| {
+                Self::network_contains(*network, *prefix_len, ip_addr)
+            }),
+        }
+    }
+
+    // For a Node that knows only what the client asked for, not where the exit Node's DNS will
+    // send it. None means the rule can't be decided without more information.
+    pub fn may_match(&self, hostname_opt: Option<&str>, port_opt: Option<u16>) -> Option<bool> {
+        match self {
+            ExitRule::Ports(low, high) => port_opt.map(|port| port >= *low && port <= *high),
+            ExitRule::Host(pattern) => {
+                hostname_opt.map(|hostname| Self::host_matches(pattern, hostname))
+            }
+            ExitRule::Network(_, _) | ExitRule::PrivateNetworks => hostname_opt
+                .and_then(|hostname| IpAddr::from_str(hostname).ok())
+                .map(|ip_addr| self.matches("", ip_addr, 0)),
+        }
+    }
+
+    fn parse_ports(ports: &str) -> Result<ExitRule, String> {
+        let bad_rule = || format!("Invalid port rule: port:{}", ports);
+        let mut bounds = ports.splitn(2, '-');
+        let low = bounds
+            .next()
+            .and_then(|low| low.parse::<u16>().ok())
+            .ok_or_else(bad_rule)?;
+        let high = match bounds.next() {
+            Some(high) => high.parse::<u16>().map_err(|_| bad_rule())?,
+            None => low,
+        };
+        if low > high {
+            return Err(bad_rule());
+        }
+        Ok(ExitRule::Ports(low, high))
+    }
+
+    fn parse_network(network: &str) -> Result<ExitRule, String> {
+        let bad_rule = || format!("Invalid network rule: {}", network);
+        let mut parts = network.splitn(2, '/');
+        let ip_addr = parts
+            .next()
+            .and_then(|ip| IpAddr::from_str(ip).ok())
+            .ok_or_else(bad_rule)?;
+        let prefix_len = parts
+            .next()
+            .and_then(|len| len.parse::<u8>().ok())
+            .ok_or_else(bad_rule)?;
+        if prefix_len > Self::max_prefix_len(&ip_addr) {
+            return Err(bad_rule());
+        }
+        Ok(ExitRule::Network(ip_addr, prefix_len))
+    }
+
+    fn parse_host(host: &str) -> Result<ExitRule, String> {
+        let pattern = host.trim_end_matches('.').to_lowercase();
+        let name = if pattern.starts_with("*.") {
+            &pattern[2..]
+        } else {
+            &pattern[..]
+        };
+        let valid = !name.is_empty()
+            && name.split('.').all(|label| {
+                !label.is_empty()
+                    && label
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+            });
+        if valid {
+            Ok(ExitRule::Host(pattern))
+        } else {
+            Err(format!("Invalid host rule: {}", host))
+        }
+    }
+
+    fn max_prefix_len(ip_addr: &IpAddr) -> u8 {
+        match ip_addr {
+            IpAddr::V4(_) => 32,
+            IpAddr::V6(_) => 128,
+        }
+    }
+
+    fn network_contains(network: IpAddr, prefix_len: u8, ip_addr: IpAddr) -> bool {
+        match (network, Self::unmap(ip_addr)) {
+            (IpAddr::V4(network), IpAddr::V4(ip_addr)) => {
+                let mask = u32::max_value()
+                    .checked_shl(32 - u32::from(prefix_len))
+                    .unwrap_or(0);
+                u32::from(network) & mask == u32::from(ip_addr) & mask
+            }
+            (IpAddr::V6(network), IpAddr::V6(ip_addr)) => {
+                let mask = u128::max_value()
+                    .checked_shl(128 - u32::from(prefix_len))
+                    .unwrap_or(0);
+                u128::from(network) & mask == u128::from(ip_addr) & mask
+            }
+            _ => false,
+        }
+    }
+
+    // An IPv4-mapped IPv6 address (::ffff:a.b.c.d) reaches the same host as a.b.c.d, so it must
+    // be judged by the IPv4 rules.
+    fn unmap(ip_addr: IpAddr) -> IpAddr {
+        match ip_addr {
+            IpAddr::V6(v6) => match v6.segments() {
+                [0, 0, 0, 0, 0, 0xffff, _, _] => IpAddr::V4(v6.to_ipv4().expect("Internal error")),
+                _ => ip_addr,
+            },
+            ip_addr => ip_addr,
+        }
+    }
+
+    fn host_matches(pattern: &str, hostname: &str) -> bool {
+        let hostname = hostname.trim_end_matches('.').to_lowercase();
+        if pattern.starts_with("*.") {
+            let domain = &pattern[2..];
+            hostname == domain || hostname.ends_with(&pattern[1..])
+        } else {
+            hostname == pattern
+        }
+    }
+}
+
+// Decides which destinations an exit Node is willing to connect to on behalf of other Nodes.
+// A destination is refused if it matches any deny rule, or if there are allow rules and it
+// matches none of them. Every Node advertises a summary of its policy in Gossip, so that
+// originating Nodes can avoid choosing exits that will refuse their requests.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ExitPolicy {
+    pub allow: Vec<ExitRule>,
+    pub deny: Vec<ExitRule>,
+}
+
+impl Default for ExitPolicy {
+    fn default() -> Self {
+        ExitPolicy::new(vec![], vec![ExitRule::PrivateNetworks])
+    }
+}
+
+impl ExitPolicy {
+    pub fn new(allow: Vec<ExitRule>, deny: Vec<ExitRule>) -> ExitPolicy {
+        ExitPolicy { allow, deny }
+    }
+
+    pub fn check(&self, hostname: &str, ip_addr: IpAddr, port: u16) -> Result<(), String> {
+        if let Some(rule) = self
+            .deny
+            .iter()
+            .find(|rule| rule.matches(hostname, ip_addr, port))
+        {
+            return Err(format!(
+                "{} ({}) port {} is denied by exit policy rule {}",
+                hostname, ip_addr, port, rule
+            ));
+        }
+        if !self.allow.is_empty()
+            && !self
+                .allow
+                .iter()
+                .any(|rule| rule.matches(hostname, ip_addr, port))
+        {
+            return Err(format!(
+                "{} ({}) port {} is not allowed by exit policy",
+                hostname, ip_addr, port
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn summary(&self) -> ExitPolicySummary {
+        let port_ranges = |rules: &[ExitRule]| -> Vec<(u16, u16)> {
+            rules
+                .iter()
+                .filter_map(|rule| match rule {
+                    ExitRule::Ports(low, high) => Some((*low, *high)),
+                    _ => None,
+                })
+                .collect()
+        };
+        // An allow rule that isn't about ports can let any port through.
+        let allowed_ports = if self.allow.iter().all(|rule| match rule {
+            ExitRule::Ports(_, _) => true,
+            _ => false,
+        }) {
+            let ranges = ExitPolicySummary::merge(port_ranges(&self.allow));
+            if ranges.len() > MAX_GOSSIPED_PORT_RANGES {
+                vec![(ranges[0].0, ranges[ranges.len() - 1].1)]
+            } else {
+                ranges
+            }
+        } else {
+            vec![]
+        };
+        let mut denied_ports = ExitPolicySummary::merge(port_ranges(&self.deny));
+        denied_ports.truncate(MAX_GOSSIPED_PORT_RANGES);
+        let denied_hosts = self
+            .deny
+            .iter()
+            .filter_map(|rule| match rule {
+                ExitRule::Host(pattern) if pattern.len() <= MAX_HOST_LEN => Some(pattern.clone()),
+                _ => None,
+            })
+            .take(MAX_GOSSIPED_HOSTS)
+            .collect();
+        ExitPolicySummary {
+            allowed_ports,
+            denied_ports,
+            denied_hosts,
+            denies_private_networks: self.deny.contains(&ExitRule::PrivateNetworks),
+        }
+    }
+}
+
+// What a Node tells the rest of the Network about its exit policy: the port ranges it allows and
+// denies, the hosts it denies, and whether it refuses private networks. Rules that can't be
+// summarized are left out, so the exit Node may still refuse something its summary accepts.
+// Entries a Node doesn't understand, perhaps because they come from a newer version, are skipped
+// rather than making the whole record unreadable.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExitPolicySummary {
+    // Empty means any port.
+    #[serde(default, deserialize_with = "deserialize_port_ranges")]
+    pub allowed_ports: Vec<(u16, u16)>,
+    #[serde(default, deserialize_with = "deserialize_port_ranges")]
+    pub denied_ports: Vec<(u16, u16)>,
+    #[serde(default, deserialize_with = "deserialize_hosts")]
+    pub denied_hosts: Vec<String>,
+    #[serde(default)]
+    pub denies_private_networks: bool,
+}
+
+impl Default for ExitPolicySummary {
+    fn default() -> Self {
+        ExitPolicy::default().summary()
+    }
+}
+
+impl ExitPolicySummary {
+    // The originating Node's view: false only if the exit Node is certain to refuse the
+    // destination. Anything that depends on the IP address the exit Node will resolve is given
+    // the benefit of the doubt; the exit Node makes the final decision.
+    pub fn may_accept(&self, hostname_opt: Option<&str>, port_opt: Option<u16>) -> bool {
+        if let Some(port) = port_opt {
+            let in_ranges = |ranges: &[(u16, u16)]| {
+                ranges
+                    .iter()
+                    .any(|(low, high)| port >= *low && port <= *high)
+            };
+            if in_ranges(&self.denied_ports)
+                || (!self.allowed_ports.is_empty() && !in_ranges(&self.allowed_ports))
+            {
+                return false;
+            }
+        }
+        if let Some(hostname) = hostname_opt {
+            if self
+                .denied_hosts
+                .iter()
+                .any(|pattern| ExitRule::host_matches(pattern, hostname))
+            {
+                return false;
+            }
+            if self.denies_private_networks
+                && ExitRule::PrivateNetworks.may_match(Some(hostname), None) == Some(true)
+            {
+                return false;
+            }
+        }
+        true
+    }
+
+    fn merge(mut ranges: Vec<(u16, u16)>) -> Vec<(u16, u16)> {
+        ranges.sort();
+        let mut merged: Vec<(u16, u16)> = vec![];
+        ranges.into_iter().for_each(|(low, high)| {
+            let adjoins_last = match merged.last() {
+                Some((_, last_high)) => u32::from(low) <= u32::from(*last_high) + 1,
+                None => false,
+            };
+            if adjoins_last {
+                let last = merged.last_mut().expect("Internal error");
+                last.1 = last.1.max(high);
+            } else {
+                merged.push((low, high));
+            }
+        });
+        merged
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Lenient<T> {
+    Known(T),
+    Unknown(IgnoredAny),
+}
+
+fn deserialize_leniently<'de, D, T>(deserializer: D) -> Result<Vec<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    let entries: Vec<Lenient<T>> = Vec::deserialize(deserializer)?;
+    Ok(entries
+        .into_iter()
+        .filter_map(|entry| match entry {
+            Lenient::Known(value) => Some(value),
+            Lenient::Unknown(_) => None,
+        })
+        .collect())
+}
+
+fn deserialize_port_ranges<'de, D>(deserializer: D) -> Result<Vec<(u16, u16)>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let mut ranges: Vec<(u16, u16)> = deserialize_leniently(deserializer)?;
+    ranges.retain(|(low, high)| low <= high);
+    ranges.truncate(MAX_GOSSIPED_PORT_RANGES);
+    Ok(ranges)
+}
+
+fn deserialize_hosts<'de, D>(deserializer: D) -> Result<Vec<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let mut hosts: Vec<String> = deserialize_leniently(deserializer)?;
+    hosts.retain(|host| {
+        host.len() <= MAX_HOST_LEN && ExitRule::parse_host(host) == Ok(ExitRule::Host(host.clone()))
+    });
+    hosts.truncate(MAX_GOSSIPED_HOSTS);
+    Ok(hosts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(ip_addr: &str) -> IpAddr {
+        IpAddr::from_str(ip_addr).unwrap()
+    }
+
+    #[test]
+    fn rules_parse_and_display() {
+        vec![
+            (
+                "10.0.0.0/8",
+                ExitRule::Network(ip("10.0.0.0"), 8),
+                "10.0.0.0/8",
+            ),
+            (
+                "1.2.3.4",
+                ExitRule::Network(ip("1.2.3.4"), 32),
+                "1.2.3.4/32",
+            ),
+            (
+                "fe80::/10",
+                ExitRule::Network(ip("fe80::"), 10),
+                "fe80::/10",
+            ),
+            ("port:25", ExitRule::Ports(25, 25), "port:25"),
+            (
+                "port:6660-6669",
+                ExitRule::Ports(6660, 6669),
+                "port:6660-6669",
+            ),
+            (
+                "*.Example.COM.",
+                ExitRule::Host("*.example.com".to_string()),
+                "*.example.com",
+            ),
+            ("PRIVATE", ExitRule::PrivateNetworks, "private"),
+        ]
+        .into_iter()
+        .for_each(|(input, expected_rule, expected_display)| {
+            let rule = ExitRule::from_str(input).unwrap();
+
+            assert_eq!(rule, expected_rule, "{}", input);
+            assert_eq!(rule.to_string(), expected_display.to_string(), "{}", input);
+        });
+    }
+
+    #[test]
+    fn malformed_rules_are_rejected() {
+        vec![
+            "10.0.0.0/33",
+            "10.0.0/8",
+            "port:",
+            "port:70000",
+            "port:30-20",
+            "bad host.com",
+            "*.",
+            "",
+        ]
+        .into_iter()
+        .for_each(|input| assert!(ExitRule::from_str(input).is_err(), "{}", input));
+    }
+
+    #[test]
+    fn network_rules_match_addresses_inside_the_network() {
+        let subject = ExitRule::from_str("172.16.0.0/12").unwrap();
+
+        assert!(subject.matches("", ip("172.31.255.255"), 80));
+        assert!(!subject.matches("", ip("172.32.0.0"), 80));
+        assert!(!subject.matches("", ip("fe80::1"), 80));
+        assert!(ExitRule::from_str("0.0.0.0/0")
+            .unwrap()
+            .matches("", ip("8.8.8.8"), 80));
+    }
+
+    #[test]
+    fn host_rules_match_names_case_insensitively() {
+        let exact = ExitRule::from_str("metadata.google.internal").unwrap();
+        let wildcard = ExitRule::from_str("*.example.com").unwrap();
+        let address = ip("1.2.3.4");
+
+        assert!(exact.matches("Metadata.Google.Internal.", address, 80));
+        assert!(!exact.matches("google.internal", address, 80));
+        assert!(wildcard.matches("example.com", address, 80));
+        assert!(wildcard.matches("www.EXAMPLE.com", address, 80));
+        assert!(!wildcard.matches("badexample.com", address, 80));
+    }
+
+    #[test]
+    fn private_networks_rule_covers_loopback_lan_metadata_multicast_and_nat64_addresses() {
+        let subject = ExitRule::PrivateNetworks;
+
+        vec![
+            "127.0.0.1",
+            "10.1.2.3",
+            "192.168.0.1",
+            "172.16.5.4",
+            "169.254.169.254",
+            "100.64.0.1",
+            "0.0.0.0",
+            "::1",
+            "fd00::1",
+            "fe80::1",
+            "::ffff:127.0.0.1",
+            "224.0.0.251",
+            "239.255.255.250",
+            "255.255.255.255",
+            "ff02::1",
+            "64:ff9b::a00:1",
+        ]
+        .into_iter()
+        .for_each(|address| assert!(subject.matches("", ip(address), 80), "{}", address));
+        vec![
+            "8.8.8.8",
+            "172.32.0.1",
+            "2001:4860:4860::8888",
+            "::ffff:8.8.8.8",
+            "223.255.255.255",
+            "240.0.0.1",
+            "64:ff9b:1::a00:1",
+        ]
+        .into_iter()
+        .for_each(|address| assert!(!subject.matches("", ip(address), 80), "{}", address));
+    }
+
+    #[test]
+    fn default_policy_denies_only_private_networks() {
+        let subject = ExitPolicy::default();
+
+        assert_eq!(subject.check("server.com", ip("1.2.3.4"), 25), Ok(()));
+        assert_eq!(
+            subject.check("localhost", ip("127.0.0.1"), 80),
+            Err("localhost (127.0.0.1) port 80 is denied by exit policy rule private".to_string())
+        );
+    }
+
+    #[test]
+    fn deny_rules_win_over_allow_rules() {
+        let subject = ExitPolicy::new(
+            vec![ExitRule::Ports(80, 80), ExitRule::Ports(443, 443)],
+            vec![ExitRule::Host("*.blocked.com".to_string())],
+        );
+
+        assert_eq!(subject.check("server.com", ip("1.2.3.4"), 443), Ok(()));
+        assert_eq!(
+            subject.check("www.blocked.com", ip("1.2.3.4"), 443),
+            Err(
+                "www.blocked.com (1.2.3.4) port 443 is denied by exit policy rule *.blocked.com"
+                    .to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn allow_rules_refuse_everything_they_do_not_cover() {
+        let subject = ExitPolicy::new(vec![ExitRule::Ports(80, 80)], vec![]);
+
+        assert_eq!(
+            subject.check("server.com", ip("1.2.3.4"), 25),
+            Err("server.com (1.2.3.4) port 25 is not allowed by exit policy".to_string())
+        );
+    }
+
+    #[test]
+    fn summary_keeps_port_ranges_denied_hosts_and_private_networks() {
+        let subject = ExitPolicy::new(
+            vec![
+                ExitRule::Ports(6660, 6669),
+                ExitRule::Ports(80, 80),
+                ExitRule::Ports(6670, 6670),
+            ],
+            vec![
+                ExitRule::PrivateNetworks,
+                ExitRule::Network(ip("fe80::"), 10),
+                ExitRule::Host("*.example.com".to_string()),
+            ],
+        );
+
+        let result = subject.summary();
+
+        assert_eq!(
+            result,
+            ExitPolicySummary {
+                allowed_ports: vec![(80, 80), (6660, 6670)],
+                denied_ports: vec![],
+                denied_hosts: vec!["*.example.com".to_string()],
+                denies_private_networks: true,
+            }
+        );
+        assert_eq!(
+            serde_json::to_string(&result).unwrap(),
+            r#"{"allowed_ports":[[80,80],[6660,6670]],"denied_ports":[],"denied_hosts":["*.example.com"],"denies_private_networks":true}"#
+        );
+        let round_trip: ExitPolicySummary =
+            serde_cbor::de::from_slice(&serde_cbor::ser::to_vec(&result).unwrap()).unwrap();
+        assert_eq!(round_trip, result);
+    }
+
+    #[test]
+    fn summary_errs_on_the_permissive_side_when_it_must_leave_something_out() {
+        let many_ports = (0..(MAX_GOSSIPED_PORT_RANGES as u16 + 4))
+            .map(|n| ExitRule::Ports(n * 10, n * 10))
+            .collect::<Vec<ExitRule>>();
+        let many_hosts = (0..(MAX_GOSSIPED_HOSTS + 4))
+            .map(|n| ExitRule::Host(format!("host{}.com", n)))
+            .collect::<Vec<ExitRule>>();
+
+        let by_ports = ExitPolicy::new(many_ports.clone(), many_ports.clone()).summary();
+        let by_host = ExitPolicy::new(
+            vec![
+                ExitRule::Host("server.com".to_string()),
+                ExitRule::Ports(80, 80),
+            ],
+            many_hosts,
+        )
+        .summary();
+
+        assert_eq!(
+            by_ports.allowed_ports,
+            vec![(0, (MAX_GOSSIPED_PORT_RANGES as u16 + 3) * 10)]
+        );
+        assert_eq!(by_ports.denied_ports.len(), MAX_GOSSIPED_PORT_RANGES);
+        assert_eq!(by_host.allowed_ports, vec![]);
+        assert_eq!(by_host.denied_hosts.len(), MAX_GOSSIPED_HOSTS);
+        assert!(by_host.may_accept(Some("server.com"), Some(25)));
+    }
+
+    #[test]
+    fn summary_may_accept_refuses_only_what_is_certain_to_be_refused() {
+        let subject = ExitPolicy::new(
+            vec![ExitRule::Ports(80, 80), ExitRule::Ports(443, 443)],
+            vec![
+                ExitRule::PrivateNetworks,
+                ExitRule::Ports(8080, 8080),
+                ExitRule::Host("*.blocked.com".to_string()),
+            ],
+        )
+        .summary();
+
+        assert!(subject.may_accept(Some("server.com"), Some(443)));
+        assert!(subject.may_accept(None, Some(80)));
+        assert!(subject.may_accept(Some("server.com"), None));
+        assert!(subject.may_accept(Some("localhost"), Some(80)));
+        assert!(!subject.may_accept(Some("server.com"), Some(25)));
+        assert!(!subject.may_accept(None, Some(8080)));
+        assert!(!subject.may_accept(Some("www.blocked.com"), Some(443)));
+        assert!(!subject.may_accept(Some("127.0.0.1"), Some(80)));
+    }
+
+    #[test]
+    fn summary_skips_entries_it_does_not_understand() {
+        let result: ExitPolicySummary = serde_json::from_str(
+            r#"{"allowed_ports":[[80,80],"quic",[443,443],[9,1]],"denied_hosts":["ok.com",{"regex":".*"},"bad host"],"denies_private_networks":true,"denied_countries":["XX"]}"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            result,
+            ExitPolicySummary {
+                allowed_ports: vec![(80, 80), (443, 443)],
+                denied_ports: vec![],
+                denied_hosts: vec!["ok.com".to_string()],
+                denies_private_networks: true,
+            }
+        );
+    }
+
+    #[test]
+    fn summary_caps_what_it_accepts_from_gossip() {
+        let ranges = (0..100)
+            .map(|n| format!("[{},{}]", n, n))
+            .collect::<Vec<String>>();
+        let hosts = (0..100)
+            .map(|n| format!("\"host{}.com\"", n))
+            .collect::<Vec<String>>();
+        let json = format!(
+            r#"{{"allowed_ports":[{}],"denied_hosts":["{}",{}]}}"#,
+            ranges.join(","),
+            "a".repeat(MAX_HOST_LEN + 1),
+            hosts.join(",")
+        );
+
+        let result: ExitPolicySummary = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(result.allowed_ports.len(), MAX_GOSSIPED_PORT_RANGES);
+        assert_eq!(result.denied_hosts.len(), MAX_GOSSIPED_HOSTS);
+        assert_eq!(result.denied_hosts[0], "host0.com".to_string());
+        assert_eq!(result.denies_private_networks, false);
+    }
+}
@@ -7,15 +7,46 @@ use crate::sub_lib::cryptde::CryptData;
 use crate::sub_lib::cryptde::PublicKey;
 use crate::sub_lib::dispatcher::Component;
 use serde_derive::{Deserialize, Serialize};
+use std::fmt;
+use std::fmt::{Debug, Formatter};
+use std::time::SystemTime;
 
 // This structure is the one that will travel from Node to Node in a CORES package.
 // There may soon be another version that always stays on the Node and is used to
 // remember Routes while they're in use.
-#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+//
+// The nonce and timestamp are minted when the hop is created and let the Node that decrypts
+// the hop recognize a CORES package that has been captured and re-injected. Hops from Nodes
+// that predate them deserialize with zeroes, which means "unstamped."
+#[derive(Clone, Deserialize, Serialize)]
 pub struct LiveHop {
     pub public_key: PublicKey,
     pub payer: Option<Payer>,
     pub component: Component,
+    #[serde(default)]
+    pub nonce: u64,
+    #[serde(default)]
+    pub timestamp: u64,
+}
+
+// The replay stamp is left out of both of these on purpose: a hop is the same hop no matter
+// when it was minted.
+impl Debug for LiveHop {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        f.debug_struct("LiveHop")
+            .field("public_key", &self.public_key)
+            .field("payer", &self.payer)
+            .field("component", &self.component)
+            .finish()
+    }
+}
+
+impl PartialEq for LiveHop {
+    fn eq(&self, other: &LiveHop) -> bool {
+        self.public_key == other.public_key
+            && self.payer == other.payer
+            && self.component == other.component
+    }
 }
 
 impl LiveHop {
@@ -24,9 +55,15 @@ impl LiveHop {
             public_key: key.clone(),
             payer,
             component,
+            nonce: rand::random::<u64>(),
+            timestamp: Self::now_secs(),
         }
     }
 
+    pub fn is_stamped(&self) -> bool {
+        self.nonce != 0 || self.timestamp != 0
+    }
+
     pub fn decode(cryptde: &dyn CryptDE, crypt_data: &CryptData) -> Result<Self, String> {
         decodex::<LiveHop>(cryptde, crypt_data)
     }
@@ -45,6 +82,13 @@ impl LiveHop {
             None => false,
         }
     }
+
+    fn now_secs() -> u64 {
+        match SystemTime::now().duration_since(SystemTime::UNIX_EPOCH) {
+            Ok(d) => d.as_secs(),
+            Err(_) => 0,
+        }
+    }
 }
 
 #[cfg(test)]
@@ -66,6 +110,46 @@ mod tests {
 
         assert_eq!(subject.public_key, key);
         assert_eq!(subject.component, Component::Neighborhood);
+        assert_eq!(subject.is_stamped(), true);
+    }
+
+    #[test]
+    fn hops_are_minted_with_distinct_nonces_that_survive_encoding_but_do_not_affect_equality() {
+        let cryptde = cryptde();
+        let key = PublicKey::new(b"key");
+        let one = LiveHop::new(&key, None, Component::Hopper);
+        let another = LiveHop::new(&key, None, Component::Hopper);
+
+        let decoded = LiveHop::decode(cryptde, &one.encode(&key, cryptde).unwrap()).unwrap();
+
+        assert_ne!(one.nonce, another.nonce);
+        assert_eq!(one, another);
+        assert_eq!(decoded.nonce, one.nonce);
+        assert_eq!(decoded.timestamp, one.timestamp);
+    }
+
+    #[test]
+    fn hops_without_a_stamp_deserialize_as_unstamped() {
+        #[derive(Serialize)]
+        struct OldLiveHop {
+            public_key: PublicKey,
+            payer: Option<Payer>,
+            component: Component,
+        }
+        let old_hop = OldLiveHop {
+            public_key: PublicKey::new(b"key"),
+            payer: None,
+            component: Component::ProxyClient,
+        };
+        let serialized = serde_cbor::ser::to_vec(&old_hop).unwrap();
+
+        let result: LiveHop = serde_cbor::de::from_slice(&serialized[..]).unwrap();
+
+        assert_eq!(
+            result,
+            LiveHop::new(&PublicKey::new(b"key"), None, Component::ProxyClient)
+        );
+        assert_eq!(result.is_stamped(), false);
     }
 
     #[test]
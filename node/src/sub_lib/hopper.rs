@@ -7,9 +7,12 @@ use crate::sub_lib::cryptde::PublicKey;
 use crate::sub_lib::dispatcher::InboundClientData;
 use crate::sub_lib::node_addr::NodeAddr;
 use crate::sub_lib::peer_actors::BindMessage;
-use crate::sub_lib::proxy_client::{ClientResponsePayload, DnsResolveFailure};
-use crate::sub_lib::proxy_server::ClientRequestPayload;
+use crate::sub_lib::proxy_client::{
+    ClientResponsePayload, DnsAnswerPayload, DnsResolveFailure, ExitPolicyRefusal,
+};
+use crate::sub_lib::proxy_server::{ClientRequestPayload, DnsQueryPayload};
 use crate::sub_lib::route::Route;
+use crate::sub_lib::stream_trace::TraceId;
 use crate::sub_lib::wallet::Wallet;
 use actix::Message;
 use actix::Recipient;
@@ -59,6 +62,8 @@ impl NoLookupIncipientCoresPackage {
 pub struct IncipientCoresPackage {
     pub route: Route,
     pub payload: CryptData,
+    // Set by the ProxyServer when it's tracing the stream the package belongs to
+    pub trace_id_opt: Option<TraceId>,
 }
 
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
@@ -67,6 +72,9 @@ pub enum MessageType {
     ClientResponse(ClientResponsePayload),
     Gossip(Gossip),
     DnsResolveFailed(DnsResolveFailure),
+    ExitPolicyRefused(ExitPolicyRefusal),
+    DnsQuery(DnsQueryPayload),
+    DnsAnswer(DnsAnswerPayload),
 }
 
 impl IncipientCoresPackage {
@@ -84,6 +92,7 @@ impl IncipientCoresPackage {
         Ok(IncipientCoresPackage {
             route,
             payload: encrypted_payload,
+            trace_id_opt: None,
         })
     }
 }
@@ -1,4 +1,5 @@
 // Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use log::kv::Value;
 use log::logger;
 use log::Level;
 #[cfg(not(test))]
@@ -13,30 +14,55 @@ pub struct Logger {
 }
 
 macro_rules! trace {
+    ($logger: expr, {$($key: ident: $value: expr),+ $(,)*}, $($arg:tt)*) => {
+        $logger.structured(log::Level::Trace, || {
+            (format!($($arg)*), vec![$((stringify!($key), $value.to_string())),+])
+        })
+    };
     ($logger: expr, $($arg:tt)*) => {
         $logger.trace(|| format!($($arg)*))
     };
 }
 
 macro_rules! debug {
+    ($logger: expr, {$($key: ident: $value: expr),+ $(,)*}, $($arg:tt)*) => {
+        $logger.structured(log::Level::Debug, || {
+            (format!($($arg)*), vec![$((stringify!($key), $value.to_string())),+])
+        })
+    };
     ($logger: expr, $($arg:tt)*) => {
         $logger.debug(|| format!($($arg)*))
     };
 }
 
 macro_rules! info {
+    ($logger: expr, {$($key: ident: $value: expr),+ $(,)*}, $($arg:tt)*) => {
+        $logger.structured(log::Level::Info, || {
+            (format!($($arg)*), vec![$((stringify!($key), $value.to_string())),+])
+        })
+    };
     ($logger: expr, $($arg:tt)*) => {
         $logger.info(|| format!($($arg)*))
     };
 }
 
 macro_rules! warning {
+    ($logger: expr, {$($key: ident: $value: expr),+ $(,)*}, $($arg:tt)*) => {
+        $logger.structured(log::Level::Warn, || {
+            (format!($($arg)*), vec![$((stringify!($key), $value.to_string())),+])
+        })
+    };
     ($logger: expr, $($arg:tt)*) => {
         $logger.warning(|| format!($($arg)*))
     };
 }
 
 macro_rules! error {
+    ($logger: expr, {$($key: ident: $value: expr),+ $(,)*}, $($arg:tt)*) => {
+        $logger.structured(log::Level::Error, || {
+            (format!($($arg)*), vec![$((stringify!($key), $value.to_string())),+])
+        })
+    };
     ($logger: expr, $($arg:tt)*) => {
         $logger.error(|| format!($($arg)*))
     };
@@ -142,12 +168,34 @@ impl Logger {
         self.log(level, string)
     }
 
+    // Like the leveled methods, but the message carries named fields that the JSON log format
+    // writes out separately from the message text
+    pub fn structured<F>(&self, level: Level, log_function: F)
+    where
+        F: FnOnce() -> (String, Vec<(&'static str, String)>),
+    {
+        if !self.level_enabled(level) {
+            return;
+        }
+        let (msg, fields) = log_function();
+        let pairs = fields
+            .iter()
+            .map(|(key, value)| (*key, Value::from_display(value)))
+            .collect::<Vec<(&str, Value)>>();
+        self.log_with_fields(level, msg, &pairs)
+    }
+
     pub fn log(&self, level: Level, msg: String) {
+        self.log_with_fields(level, msg, &[])
+    }
+
+    fn log_with_fields(&self, level: Level, msg: String, fields: &[(&str, Value)]) {
         logger().log(
             &Record::builder()
                 .args(format_args!("{}", msg))
                 .module_path(Some(&self.name))
                 .level(level)
+                .key_values(&fields)
                 .build(),
         );
     }
@@ -392,6 +440,45 @@ mod tests {
         tlh.exists_log_containing("error! 42");
     }
 
+    #[test]
+    fn macros_with_fields_log_only_the_message_in_text_format() {
+        init_test_logging();
+        let logger = Logger::new("test");
+
+        trace!(logger, {stream_key: "abc"}, "trace! {}", 43);
+        debug!(logger, {stream_key: "abc"}, "debug! {}", 43);
+        info!(logger, {stream_key: "abc", wallet: 44}, "info! {}", 43);
+        warning!(logger, {public_key: "def"}, "warning! {}", 43);
+        error!(logger, {public_key: "def",}, "error! {}", 43);
+
+        let tlh = TestLogHandler::new();
+        tlh.exists_log_matching("TRACE: test: trace! 43$");
+        tlh.exists_log_matching("DEBUG: test: debug! 43$");
+        tlh.exists_log_matching("INFO: test: info! 43$");
+        tlh.exists_log_matching("WARN: test: warning! 43$");
+        tlh.exists_log_matching("ERROR: test: error! 43$");
+    }
+
+    #[test]
+    fn structured_is_not_computed_when_log_level_is_too_low() {
+        let logger = Logger {
+            name: "test".to_string(),
+            level_limit: Level::Info,
+        };
+        let signal = Arc::new(Mutex::new(Some(false)));
+        let signal_c = signal.clone();
+
+        let log_function = move || {
+            let mut locked_signal = signal_c.lock().unwrap();
+            locked_signal.replace(true);
+            ("blah".to_string(), vec![("key", "value".to_string())])
+        };
+
+        logger.structured(Level::Debug, log_function);
+
+        assert_eq!(signal.lock().unwrap().as_ref(), Some(&false));
+    }
+
     fn timestamp_as_string(timestamp: &SystemTime) -> String {
         let date_time: DateTime<Local> = DateTime::from(timestamp.clone());
         let fmt = StrftimeItems::new("%Y-%m-%dT%H:%M:%S%.3f");
@@ -0,0 +1,325 @@
+// Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use lazy_static::lazy_static;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::Mutex;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MetricKind {
+    Counter,
+    Gauge,
+}
+
+impl MetricKind {
+    fn name(self) -> &'static str {
+        match self {
+            MetricKind::Counter => "counter",
+            MetricKind::Gauge => "gauge",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Metric {
+    pub name: &'static str,
+    pub help: &'static str,
+    pub kind: MetricKind,
+}
+
+pub const DISPATCHER_BYTES_RECEIVED: Metric = Metric {
+    name: "substratum_dispatcher_bytes_received_total",
+    help: "Bytes the Dispatcher has received, by the local port they arrived on",
+    kind: MetricKind::Counter,
+};
+pub const DISPATCHER_BYTES_SENT: Metric = Metric {
+    name: "substratum_dispatcher_bytes_sent_total",
+    help: "Bytes the Dispatcher has sent, by the local port the stream arrived on",
+    kind: MetricKind::Counter,
+};
+pub const HOPPER_PACKAGES: Metric = Metric {
+    name: "substratum_hopper_packages_total",
+    help: "CORES packages the Hopper has routed onward, consumed here, or dropped",
+    kind: MetricKind::Counter,
+};
+pub const PROXY_SERVER_STREAMS_OPENED: Metric = Metric {
+    name: "substratum_proxy_server_streams_opened_total",
+    help: "Browser streams the ProxyServer has opened",
+    kind: MetricKind::Counter,
+};
+pub const PROXY_SERVER_STREAMS_FAILED: Metric = Metric {
+    name: "substratum_proxy_server_streams_failed_total",
+    help: "Browser streams the ProxyServer has given up on, by reason",
+    kind: MetricKind::Counter,
+};
+pub const PROXY_CLIENT_DNS_LOOKUPS: Metric = Metric {
+    name: "substratum_proxy_client_dns_lookups_total",
+    help: "DNS look-ups the ProxyClient has made, by result",
+    kind: MetricKind::Counter,
+};
+pub const PROXY_CLIENT_DNS_CACHE_ENTRIES: Metric = Metric {
+    name: "substratum_proxy_client_dns_cache_entries",
+    help: "Answers the ProxyClient's DNS cache is holding",
+    kind: MetricKind::Gauge,
+};
+pub const PROXY_CLIENT_STREAMS_ESTABLISHED: Metric = Metric {
+    name: "substratum_proxy_client_streams_established_total",
+    help: "Server connections the ProxyClient has set up for streams, by protocol and by the first application protocol the browser offered",
+    kind: MetricKind::Counter,
+};
+pub const PROXY_CLIENT_POOLED_CONNECTIONS: Metric = Metric {
+    name: "substratum_proxy_client_pooled_connections_total",
+    help: "Idle server connections the ProxyClient's connection pool has parked, reused, or discarded, and look-ups it couldn't answer, by outcome",
+    kind: MetricKind::Counter,
+};
+pub const PROXY_CLIENT_POOL_IDLE_CONNECTIONS: Metric = Metric {
+    name: "substratum_proxy_client_pool_idle_connections",
+    help: "Idle server connections the ProxyClient's connection pool is holding",
+    kind: MetricKind::Gauge,
+};
+pub const NEIGHBORHOOD_NODES: Metric = Metric {
+    name: "substratum_neighborhood_nodes",
+    help: "Nodes in the Neighborhood database, including this one",
+    kind: MetricKind::Gauge,
+};
+pub const NEIGHBORHOOD_GOSSIP_RECEIVED: Metric = Metric {
+    name: "substratum_neighborhood_gossip_received_total",
+    help: "Gossip messages the Neighborhood has received",
+    kind: MetricKind::Counter,
+};
+pub const NEIGHBORHOOD_GOSSIP_SENT: Metric = Metric {
+    name: "substratum_neighborhood_gossip_sent_total",
+    help: "Gossip messages the Neighborhood has sent",
+    kind: MetricKind::Counter,
+};
+pub const ACCOUNTANT_RECEIVABLE_BALANCE: Metric = Metric {
+    name: "substratum_accountant_receivable_balance_gwub",
+    help: "What other Nodes owe this one, in gwub",
+    kind: MetricKind::Gauge,
+};
+pub const ACCOUNTANT_PAYABLE_BALANCE: Metric = Metric {
+    name: "substratum_accountant_payable_balance_gwub",
+    help: "What this Node owes other Nodes and hasn't started paying, in gwub",
+    kind: MetricKind::Gauge,
+};
+
+lazy_static! {
+    static ref REGISTRY: Mutex<MetricsRegistry> = Mutex::new(MetricsRegistry::new());
+}
+
+pub fn increment(metric: &Metric, labels: &[(&str, &str)], amount: u64) {
+    registry().increment(metric, labels, amount)
+}
+
+pub fn set(metric: &Metric, labels: &[(&str, &str)], value: i64) {
+    registry().set(metric, labels, value)
+}
+
+pub fn value(metric: &Metric, labels: &[(&str, &str)]) -> Option<i64> {
+    registry().value(metric, labels)
+}
+
+pub fn render() -> String {
+    registry().render()
+}
+
+fn registry() -> std::sync::MutexGuard<'static, MetricsRegistry> {
+    REGISTRY.lock().expect("Metrics registry is poisoned")
+}
+
+type Labels = Vec<(String, String)>;
+
+struct Family {
+    metric: Metric,
+    samples: BTreeMap<Labels, i64>,
+}
+
+// Families and samples are kept sorted so that every scrape lists them in the same order
+#[derive(Default)]
+pub struct MetricsRegistry {
+    families: BTreeMap<&'static str, Family>,
+}
+
+impl MetricsRegistry {
+    pub fn new() -> MetricsRegistry {
+        MetricsRegistry::default()
+    }
+
+    pub fn increment(&mut self, metric: &Metric, labels: &[(&str, &str)], amount: u64) {
+        *self.sample(metric, labels) += amount as i64;
+    }
+
+    pub fn set(&mut self, metric: &Metric, labels: &[(&str, &str)], value: i64) {
+        *self.sample(metric, labels) = value;
+    }
+
+    pub fn value(&self, metric: &Metric, labels: &[(&str, &str)]) -> Option<i64> {
+        self.families
+            .get(metric.name)
+            .and_then(|family| family.samples.get(&Self::labels(labels)))
+            .cloned()
+    }
+
+    pub fn render(&self) -> String {
+        let mut text = String::new();
+        self.families.values().for_each(|family| {
+            writeln!(text, "# HELP {} {}", family.metric.name, family.metric.help)
+                .expect("Internal Error");
+            writeln!(
+                text,
+                "# TYPE {} {}",
+                family.metric.name,
+                family.metric.kind.name()
+            )
+            .expect("Internal Error");
+            family.samples.iter().for_each(|(labels, value)| {
+                writeln!(
+                    text,
+                    "{}{} {}",
+                    family.metric.name,
+                    Self::render_labels(labels),
+                    value
+                )
+                .expect("Internal Error")
+            });
+        });
+        text
+    }
+
+    fn sample(&mut self, metric: &Metric, labels: &[(&str, &str)]) -> &mut i64 {
+        self.families
+            .entry(metric.name)
+            .or_insert_with(|| Family {
+                metric: *metric,
+                samples: BTreeMap::new(),
+            })
+            .samples
+            .entry(Self::labels(labels))
+            .or_insert(0)
+    }
+
+    fn labels(labels: &[(&str, &str)]) -> Labels {
+        let mut labels = labels
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect::<Labels>();
+        labels.sort();
+        labels
+    }
+
+    fn render_labels(labels: &Labels) -> String {
+        if labels.is_empty() {
+            return String::new();
+        }
+        let pairs = labels
+            .iter()
+            .map(|(name, value)| format!("{}=\"{}\"", name, Self::escape(value)))
+            .collect::<Vec<String>>();
+        format!("{{{}}}", pairs.join(","))
+    }
+
+    fn escape(value: &str) -> String {
+        value
+            .replace('\\', "\\\\")
+            .replace('"', "\\\"")
+            .replace('\n', "\\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counters_accumulate_separately_for_each_set_of_labels() {
+        let mut subject = MetricsRegistry::new();
+
+        subject.increment(&DISPATCHER_BYTES_RECEIVED, &[("port", "80")], 100);
+        subject.increment(&DISPATCHER_BYTES_RECEIVED, &[("port", "443")], 20);
+        subject.increment(&DISPATCHER_BYTES_RECEIVED, &[("port", "80")], 3);
+
+        assert_eq!(
+            subject.value(&DISPATCHER_BYTES_RECEIVED, &[("port", "80")]),
+            Some(103)
+        );
+        assert_eq!(
+            subject.value(&DISPATCHER_BYTES_RECEIVED, &[("port", "443")]),
+            Some(20)
+        );
+        assert_eq!(
+            subject.value(&DISPATCHER_BYTES_RECEIVED, &[("port", "8080")]),
+            None
+        );
+    }
+
+    #[test]
+    fn gauges_are_replaced_rather_than_accumulated() {
+        let mut subject = MetricsRegistry::new();
+
+        subject.set(&NEIGHBORHOOD_NODES, &[], 5);
+        subject.set(&NEIGHBORHOOD_NODES, &[], 3);
+
+        assert_eq!(subject.value(&NEIGHBORHOOD_NODES, &[]), Some(3));
+    }
+
+    #[test]
+    fn label_order_does_not_matter() {
+        let mut subject = MetricsRegistry::new();
+
+        subject.increment(&HOPPER_PACKAGES, &[("a", "1"), ("b", "2")], 1);
+        subject.increment(&HOPPER_PACKAGES, &[("b", "2"), ("a", "1")], 1);
+
+        assert_eq!(
+            subject.value(&HOPPER_PACKAGES, &[("a", "1"), ("b", "2")]),
+            Some(2)
+        );
+    }
+
+    #[test]
+    fn render_produces_prometheus_text_format() {
+        let mut subject = MetricsRegistry::new();
+        subject.increment(&HOPPER_PACKAGES, &[("disposition", "routed")], 4);
+        subject.increment(&HOPPER_PACKAGES, &[("disposition", "dropped")], 1);
+        subject.set(&NEIGHBORHOOD_NODES, &[], 7);
+        subject.set(&ACCOUNTANT_PAYABLE_BALANCE, &[], -12);
+
+        let result = subject.render();
+
+        assert_eq!(
+            result,
+            "# HELP substratum_accountant_payable_balance_gwub What this Node owes other Nodes and hasn't started paying, in gwub\n\
+             # TYPE substratum_accountant_payable_balance_gwub gauge\n\
+             substratum_accountant_payable_balance_gwub -12\n\
+             # HELP substratum_hopper_packages_total CORES packages the Hopper has routed onward, consumed here, or dropped\n\
+             # TYPE substratum_hopper_packages_total counter\n\
+             substratum_hopper_packages_total{disposition=\"dropped\"} 1\n\
+             substratum_hopper_packages_total{disposition=\"routed\"} 4\n\
+             # HELP substratum_neighborhood_nodes Nodes in the Neighborhood database, including this one\n\
+             # TYPE substratum_neighborhood_nodes gauge\n\
+             substratum_neighborhood_nodes 7\n"
+        );
+    }
+
+    #[test]
+    fn render_escapes_label_values() {
+        let mut subject = MetricsRegistry::new();
+        subject.increment(
+            &PROXY_SERVER_STREAMS_FAILED,
+            &[("reason", "back\\slash \"quoted\"\nnewline")],
+            1,
+        );
+
+        let result = subject.render();
+
+        assert_eq!(
+            result.lines().last().unwrap(),
+            "substratum_proxy_server_streams_failed_total{reason=\"back\\\\slash \\\"quoted\\\"\\nnewline\"} 1"
+        );
+    }
+
+    #[test]
+    fn render_of_an_empty_registry_is_empty() {
+        let subject = MetricsRegistry::new();
+
+        assert_eq!(subject.render(), String::new());
+    }
+}
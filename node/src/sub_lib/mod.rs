@@ -12,6 +12,7 @@ pub mod accountant;
 pub mod bidi_hashmap;
 pub mod binary_traverser;
 pub mod blockchain_bridge;
+pub mod bypass;
 pub mod channel_wrappers;
 pub mod crash_point;
 pub mod cryptde;
@@ -19,6 +20,8 @@ pub mod cryptde_null;
 pub mod cryptde_real;
 pub mod data_version;
 pub mod dispatcher;
+pub mod entry_dns;
+pub mod exit_policy;
 pub mod framer;
 pub mod framer_utils;
 pub mod hop;
@@ -27,19 +30,23 @@ pub mod http_packet_framer;
 pub mod http_response_start_finder;
 pub mod limiter;
 pub mod main_tools;
+pub mod metrics;
 pub mod neighborhood;
 pub mod node_addr;
 pub mod peer_actors;
+pub mod privacy;
 pub mod proxy_client;
 pub mod proxy_server;
 pub mod route;
 pub mod sequence_buffer;
 pub mod sequencer;
 pub mod set_consuming_wallet_message;
+pub mod set_earning_wallet_message;
 pub mod socket_server;
 pub mod stream_connector;
 pub mod stream_handler_pool;
 pub mod stream_key;
+pub mod stream_trace;
 pub mod tcp_wrappers;
 pub mod tls_framer;
 pub mod tokio_wrappers;
@@ -8,6 +8,7 @@ use crate::sub_lib::node_addr::NodeAddr;
 use crate::sub_lib::peer_actors::{BindMessage, StartMessage};
 use crate::sub_lib::route::Route;
 use crate::sub_lib::set_consuming_wallet_message::SetConsumingWalletMessage;
+use crate::sub_lib::set_earning_wallet_message::SetEarningWalletMessage;
 use crate::sub_lib::stream_handler_pool::DispatcherNodeQueryResponse;
 use crate::sub_lib::stream_handler_pool::TransmitDataMsg;
 use crate::sub_lib::utils::node_descriptor_delimiter;
@@ -16,6 +17,7 @@ use actix::Message;
 use actix::Recipient;
 use lazy_static::lazy_static;
 use serde_derive::{Deserialize, Serialize};
+use std::fmt;
 use std::fmt::{Debug, Formatter};
 use std::net::IpAddr;
 use std::str::FromStr;
@@ -213,6 +215,7 @@ pub struct NeighborhoodSubs {
     pub start: Recipient<StartMessage>,
     pub node_query: Recipient<NodeQueryMessage>,
     pub route_query: Recipient<RouteQueryMessage>,
+    pub disjoint_route_query: Recipient<DisjointRouteQueryMessage>,
     pub update_node_record_metadata: Recipient<NodeRecordMetadataMessage>,
     pub from_hopper: Recipient<ExpiredCoresPackage<Gossip>>,
     pub dispatcher_node_query: Recipient<DispatcherNodeQueryMessage>,
@@ -220,6 +223,10 @@ pub struct NeighborhoodSubs {
     pub stream_shutdown_sub: Recipient<StreamShutdownMsg>,
     pub set_consuming_wallet_sub: Recipient<SetConsumingWalletMessage>,
     pub from_ui_gateway: Recipient<NeighborhoodDotGraphRequest>,
+    pub neighborhood_view_sub: Recipient<NeighborhoodViewRequest>,
+    pub set_rate_pack_sub: Recipient<SetRatePackMsg>,
+    pub set_neighborhood_mode_sub: Recipient<SetNeighborhoodModeMsg>,
+    pub set_earning_wallet_sub: Recipient<SetEarningWalletMessage>,
 }
 
 impl Debug for NeighborhoodSubs {
@@ -255,6 +262,40 @@ pub struct BootstrapNeighborhoodNowMessage {}
 #[derive(Clone, Debug, Message, PartialEq)]
 pub struct NeighborhoodDotGraphRequest {
     pub client_id: u64,
+    pub context_id: u64,
+}
+
+#[derive(Clone, Debug, Message, PartialEq)]
+pub struct NeighborhoodViewRequest {
+    pub client_id: u64,
+    pub context_id: u64,
+}
+
+// The Neighborhood database as UIs see it, without the Graphviz
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NeighborhoodView {
+    pub root_key: String,
+    pub earning_wallet: String,
+    pub consuming_wallet: Option<String>,
+    pub nodes: Vec<NodeRecordView>,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NodeRecordView {
+    pub public_key: String,
+    pub version: u32,
+    pub routing_byte_rate: u64,
+    pub routing_service_rate: u64,
+    pub exit_byte_rate: u64,
+    pub exit_service_rate: u64,
+    pub accepts_connections: bool,
+    pub routes_data: bool,
+    pub desirable: bool,
+    pub node_addr: Option<String>,
+    pub half_neighbors: Vec<String>,
+    pub full_neighbors: Vec<String>,
 }
 
 #[derive(Clone, Debug, PartialEq)]
@@ -280,6 +321,10 @@ pub struct RouteQueryMessage {
     pub target_component: Component,
     pub minimum_hop_count: usize,
     pub return_component_opt: Option<Component>,
+    // Where the exit Node will be asked to connect, if known: exits whose advertised exit
+    // policy would refuse it aren't chosen.
+    pub target_hostname_opt: Option<String>,
+    pub target_port_opt: Option<u16>,
 }
 
 impl Message for RouteQueryMessage {
@@ -293,6 +338,68 @@ impl RouteQueryMessage {
             target_component: Component::ProxyClient,
             minimum_hop_count,
             return_component_opt: Some(Component::ProxyServer),
+            target_hostname_opt: None,
+            target_port_opt: None,
+        }
+    }
+
+    pub fn data_indefinite_route_request_for(
+        minimum_hop_count: usize,
+        target_hostname_opt: Option<String>,
+        target_port: u16,
+    ) -> RouteQueryMessage {
+        RouteQueryMessage {
+            target_hostname_opt,
+            target_port_opt: Some(target_port),
+            ..RouteQueryMessage::data_indefinite_route_request(minimum_hop_count)
+        }
+    }
+}
+
+// Asks for up to path_count round-trip routes to a single exit Node, no two of which share a
+// relay in either direction. Fewer routes than requested (but never zero) come back if the
+// database can't supply that many; an empty response means there is no route at all. If
+// target_key_opt is set, that's the exit Node.
+#[derive(Debug, PartialEq)]
+pub struct DisjointRouteQueryMessage {
+    pub target_key_opt: Option<PublicKey>,
+    pub minimum_hop_count: usize,
+    pub path_count: usize,
+    pub target_hostname_opt: Option<String>,
+    pub target_port_opt: Option<u16>,
+}
+
+impl Message for DisjointRouteQueryMessage {
+    type Result = Vec<RouteQueryResponse>;
+}
+
+impl DisjointRouteQueryMessage {
+    pub fn data_indefinite_route_request(
+        minimum_hop_count: usize,
+        path_count: usize,
+    ) -> DisjointRouteQueryMessage {
+        DisjointRouteQueryMessage {
+            target_key_opt: None,
+            minimum_hop_count,
+            path_count,
+            target_hostname_opt: None,
+            target_port_opt: None,
+        }
+    }
+
+    pub fn data_indefinite_route_request_for(
+        minimum_hop_count: usize,
+        path_count: usize,
+        target_hostname_opt: Option<String>,
+        target_port: u16,
+    ) -> DisjointRouteQueryMessage {
+        DisjointRouteQueryMessage {
+            target_hostname_opt,
+            target_port_opt: Some(target_port),
+            ..DisjointRouteQueryMessage::data_indefinite_route_request(
+                minimum_hop_count,
+                path_count,
+            )
         }
     }
 }
@@ -334,6 +441,58 @@ pub struct RatePack {
     pub exit_service_rate: u64,
 }
 
+// Written the way UIs send it: routing byte rate, routing service rate, exit byte rate and exit
+// service rate, separated by pipes
+impl FromStr for RatePack {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let rates = input
+            .split('|')
+            .map(|piece| piece.trim().parse::<u64>())
+            .collect::<Result<Vec<u64>, _>>();
+        match rates {
+            Ok(ref rates) if rates.len() == 4 => Ok(RatePack {
+                routing_byte_rate: rates[0],
+                routing_service_rate: rates[1],
+                exit_byte_rate: rates[2],
+                exit_service_rate: rates[3],
+            }),
+            _ => Err(format!(
+                "Rate pack should be four whole numbers separated by '|', not '{}'",
+                input
+            )),
+        }
+    }
+}
+
+impl fmt::Display for RatePack {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}|{}|{}|{}",
+            self.routing_byte_rate,
+            self.routing_service_rate,
+            self.exit_byte_rate,
+            self.exit_service_rate
+        )
+    }
+}
+
+#[derive(Clone, Debug, Message, PartialEq)]
+pub struct SetRatePackMsg {
+    pub client_id: u64,
+    pub context_id: u64,
+    pub rate_pack: RatePack,
+}
+
+#[derive(Clone, Debug, Message, PartialEq)]
+pub struct SetNeighborhoodModeMsg {
+    pub client_id: u64,
+    pub context_id: u64,
+    pub mode: String,
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -362,6 +521,7 @@ mod tests {
             start: recipient!(recorder, StartMessage),
             node_query: recipient!(recorder, NodeQueryMessage),
             route_query: recipient!(recorder, RouteQueryMessage),
+            disjoint_route_query: recipient!(recorder, DisjointRouteQueryMessage),
             update_node_record_metadata: recipient!(recorder, NodeRecordMetadataMessage),
             from_hopper: recipient!(recorder, ExpiredCoresPackage<Gossip>),
             dispatcher_node_query: recipient!(recorder, DispatcherNodeQueryMessage),
@@ -369,11 +529,46 @@ mod tests {
             stream_shutdown_sub: recipient!(recorder, StreamShutdownMsg),
             set_consuming_wallet_sub: recipient!(recorder, SetConsumingWalletMessage),
             from_ui_gateway: recipient!(recorder, NeighborhoodDotGraphRequest),
+            neighborhood_view_sub: recipient!(recorder, NeighborhoodViewRequest),
+            set_rate_pack_sub: recipient!(recorder, SetRatePackMsg),
+            set_neighborhood_mode_sub: recipient!(recorder, SetNeighborhoodModeMsg),
+            set_earning_wallet_sub: recipient!(recorder, SetEarningWalletMessage),
         };
 
         assert_eq!(format!("{:?}", subject), "NeighborhoodSubs");
     }
 
+    #[test]
+    fn rate_pack_from_str_round_trips_through_display() {
+        let subject = RatePack::from_str("1|2| 3 |4").unwrap();
+
+        assert_eq!(
+            subject,
+            RatePack {
+                routing_byte_rate: 1,
+                routing_service_rate: 2,
+                exit_byte_rate: 3,
+                exit_service_rate: 4,
+            }
+        );
+        assert_eq!(subject.to_string(), "1|2|3|4".to_string());
+    }
+
+    #[test]
+    fn rate_pack_from_str_complains_about_bad_input() {
+        vec!["1|2|3", "1|2|3|4|5", "1|2|-3|4", "one|2|3|4", ""]
+            .into_iter()
+            .for_each(|input| {
+                assert_eq!(
+                    RatePack::from_str(input),
+                    Err(format!(
+                        "Rate pack should be four whole numbers separated by '|', not '{}'",
+                        input
+                    ))
+                )
+            });
+    }
+
     #[test]
     fn node_descriptor_from_str_requires_two_pieces_to_a_configuration() {
         let result = NodeDescriptor::from_str(cryptde(), "only_one_piece", DEFAULT_CHAIN_ID);
@@ -503,6 +698,29 @@ mod tests {
                 target_component: Component::ProxyClient,
                 minimum_hop_count: 2,
                 return_component_opt: Some(Component::ProxyServer),
+                target_hostname_opt: None,
+                target_port_opt: None,
+            }
+        );
+    }
+
+    #[test]
+    fn data_indefinite_route_request_for() {
+        let result = RouteQueryMessage::data_indefinite_route_request_for(
+            2,
+            Some("server.com".to_string()),
+            443,
+        );
+
+        assert_eq!(
+            result,
+            RouteQueryMessage {
+                target_key_opt: None,
+                target_component: Component::ProxyClient,
+                minimum_hop_count: 2,
+                return_component_opt: Some(Component::ProxyServer),
+                target_hostname_opt: Some("server.com".to_string()),
+                target_port_opt: Some(443),
             }
         );
     }
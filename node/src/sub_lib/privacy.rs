@@ -0,0 +1,245 @@
+// Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use lazy_static::lazy_static;
+#[cfg(test)]
+use std::cell::Cell;
+use std::fmt;
+use std::net::SocketAddr;
+#[cfg(not(test))]
+use std::sync::atomic::{AtomicBool, Ordering};
+
+// With --privacy-logging on, hostnames, IP addresses, wallet addresses and public keys are logged
+// as tokens like host#1a2b3c4d. The salt is new every run, so a token can be followed from line to
+// line of one logfile, but it can't be checked against a guess or matched up across runs.
+lazy_static! {
+    static ref SALT: [u8; 16] = rand::random();
+}
+
+#[cfg(not(test))]
+static PRIVACY_LOGGING: AtomicBool = AtomicBool::new(false);
+
+#[cfg(test)]
+thread_local! {
+    // Per thread in tests, so that one test turning privacy on can't redact another test's logs
+    static PRIVACY_LOGGING: Cell<bool> = Cell::new(false);
+}
+
+#[cfg(not(test))]
+pub fn set_privacy_logging(enabled: bool) {
+    PRIVACY_LOGGING.store(enabled, Ordering::Relaxed)
+}
+
+#[cfg(not(test))]
+pub fn privacy_logging() -> bool {
+    PRIVACY_LOGGING.load(Ordering::Relaxed)
+}
+
+#[cfg(test)]
+pub fn set_privacy_logging(enabled: bool) {
+    PRIVACY_LOGGING.with(|privacy_logging| privacy_logging.set(enabled))
+}
+
+#[cfg(test)]
+pub fn privacy_logging() -> bool {
+    PRIVACY_LOGGING.with(|privacy_logging| privacy_logging.get())
+}
+
+pub struct Private<T> {
+    kind: &'static str,
+    value: T,
+}
+
+pub fn host<T: fmt::Display>(value: T) -> Private<T> {
+    Private {
+        kind: "host",
+        value,
+    }
+}
+
+pub fn ip<T: fmt::Display>(value: T) -> Private<T> {
+    Private { kind: "ip", value }
+}
+
+pub fn wallet<T: fmt::Display>(value: T) -> Private<T> {
+    Private {
+        kind: "wallet",
+        value,
+    }
+}
+
+pub fn key<T: fmt::Display>(value: T) -> Private<T> {
+    Private { kind: "key", value }
+}
+
+impl<T: fmt::Display> fmt::Display for Private<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if privacy_logging() {
+            f.write_str(&redact(self.kind, &self.value.to_string()))
+        } else {
+            fmt::Display::fmt(&self.value, f)
+        }
+    }
+}
+
+// Redacted from the Display form, so that {} and {:?} give the same token for the same value
+impl<T: fmt::Display + fmt::Debug> fmt::Debug for Private<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if privacy_logging() {
+            f.write_str(&redact(self.kind, &self.value.to_string()))
+        } else {
+            fmt::Debug::fmt(&self.value, f)
+        }
+    }
+}
+
+// Keeps the port, which says what sort of traffic it was but not whose
+pub struct PrivateSocketAddr(SocketAddr);
+
+pub fn socket_addr(addr: SocketAddr) -> PrivateSocketAddr {
+    PrivateSocketAddr(addr)
+}
+
+impl fmt::Display for PrivateSocketAddr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if privacy_logging() {
+            write!(
+                f,
+                "{}:{}",
+                redact("ip", &self.0.ip().to_string()),
+                self.0.port()
+            )
+        } else {
+            fmt::Display::fmt(&self.0, f)
+        }
+    }
+}
+
+impl fmt::Debug for PrivateSocketAddr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if privacy_logging() {
+            fmt::Display::fmt(self, f)
+        } else {
+            fmt::Debug::fmt(&self.0, f)
+        }
+    }
+}
+
+// Packet contents can carry anything, such as the Host header of an HTTP request, so they're left
+// out entirely rather than hashed
+pub struct PrivateData<T>(T);
+
+pub fn data<T>(value: T) -> PrivateData<T> {
+    PrivateData(value)
+}
+
+impl<T: fmt::Display> fmt::Display for PrivateData<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if privacy_logging() {
+            f.write_str("<redacted data>")
+        } else {
+            fmt::Display::fmt(&self.0, f)
+        }
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for PrivateData<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if privacy_logging() {
+            f.write_str("<redacted data>")
+        } else {
+            fmt::Debug::fmt(&self.0, f)
+        }
+    }
+}
+
+fn redact(kind: &str, text: &str) -> String {
+    let mut hash = sha1::Sha1::new();
+    hash.update(&SALT[..]);
+    hash.update(text.as_bytes());
+    let token = hash.digest().bytes()[..4]
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect::<String>();
+    format!("{}#{}", kind, token)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use regex::Regex;
+    use std::net::IpAddr;
+    use std::str::FromStr;
+
+    #[test]
+    fn values_are_logged_as_they_are_when_privacy_logging_is_off() {
+        set_privacy_logging(false);
+
+        assert_eq!(host("example.com").to_string(), "example.com");
+        assert_eq!(
+            ip(IpAddr::from_str("1.2.3.4").unwrap()).to_string(),
+            "1.2.3.4"
+        );
+        assert_eq!(format!("{:?}", host("example.com")), "\"example.com\"");
+        assert_eq!(
+            socket_addr(SocketAddr::from_str("1.2.3.4:5678").unwrap()).to_string(),
+            "1.2.3.4:5678"
+        );
+    }
+
+    #[test]
+    fn values_are_replaced_by_tokens_when_privacy_logging_is_on() {
+        set_privacy_logging(true);
+
+        let token_format = Regex::new("^(host|ip|wallet|key)#[0-9a-f]{8}$").unwrap();
+        [
+            host("example.com").to_string(),
+            ip("1.2.3.4").to_string(),
+            wallet("0x0123456789012345678901234567890123456789").to_string(),
+            key("AQIDBA").to_string(),
+        ]
+        .iter()
+        .for_each(|token| assert!(token_format.is_match(token), "{}", token));
+        assert!(host("example.com").to_string().starts_with("host#"));
+        assert!(wallet("0x01").to_string().starts_with("wallet#"));
+
+        set_privacy_logging(false);
+    }
+
+    #[test]
+    fn the_same_value_gets_the_same_token_however_it_is_formatted() {
+        set_privacy_logging(true);
+
+        let display = host("example.com").to_string();
+        let debug = format!("{:?}", host(String::from("example.com")));
+
+        assert_eq!(display, debug);
+        assert_ne!(display, host("example.org").to_string());
+
+        set_privacy_logging(false);
+    }
+
+    #[test]
+    fn socket_addresses_keep_their_ports() {
+        set_privacy_logging(true);
+        let addr = SocketAddr::from_str("1.2.3.4:5678").unwrap();
+
+        let result = socket_addr(addr).to_string();
+
+        assert_eq!(result, format!("{}:5678", ip(addr.ip())));
+        assert_eq!(format!("{:?}", socket_addr(addr)), result);
+
+        set_privacy_logging(false);
+    }
+
+    #[test]
+    fn data_is_left_out_entirely() {
+        let bytes = b"GET / HTTP/1.1\r\nHost: example.com\r\n\r\n".to_vec();
+
+        set_privacy_logging(false);
+        assert_eq!(format!("{:?}", data(&bytes)), format!("{:?}", bytes));
+        set_privacy_logging(true);
+        assert_eq!(format!("{:?}", data(&bytes)), "<redacted data>");
+        assert_eq!(format!("{}", data("Host: example.com")), "<redacted data>");
+
+        set_privacy_logging(false);
+    }
+}
@@ -1,29 +1,109 @@
 // Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
 use crate::sub_lib::cryptde::CryptDE;
 use crate::sub_lib::data_version::DataVersion;
+use crate::sub_lib::exit_policy::ExitPolicy;
 use crate::sub_lib::hopper::{ExpiredCoresPackage, MessageType};
 use crate::sub_lib::peer_actors::BindMessage;
-use crate::sub_lib::proxy_server::ClientRequestPayload;
+use crate::sub_lib::proxy_server::{ClientRequestPayload, DnsQueryPayload};
 use crate::sub_lib::sequence_buffer::SequencedPacket;
 use crate::sub_lib::stream_key::StreamKey;
 use actix::Message;
 use actix::Recipient;
 use serde_derive::{Deserialize, Serialize};
+use std::fmt;
 use std::fmt::{Debug, Formatter};
+use std::net::IpAddr;
 use std::net::Ipv4Addr;
+use std::net::Ipv6Addr;
 use std::net::SocketAddr;
 use std::net::SocketAddrV4;
+use std::time::Duration;
 
 pub fn error_socket_addr() -> SocketAddr {
     SocketAddr::from(SocketAddrV4::new(Ipv4Addr::new(0, 0, 0, 0), 0))
 }
 
+// How an exit Node talks to its upstream DNS servers. The encrypted protocols carry the name the
+// servers' certificates must present, since the servers themselves are given by IP address.
+#[derive(Clone, Debug, PartialEq)]
+pub enum DnsProtocol {
+    Udp,
+    Tls(String),
+    Https(String),
+}
+
+impl Default for DnsProtocol {
+    fn default() -> Self {
+        DnsProtocol::Udp
+    }
+}
+
+impl DnsProtocol {
+    pub fn default_port(&self) -> u16 {
+        match self {
+            DnsProtocol::Udp => 53,
+            DnsProtocol::Tls(_) => 853,
+            DnsProtocol::Https(_) => 443,
+        }
+    }
+
+    pub fn tls_dns_name(&self) -> Option<String> {
+        match self {
+            DnsProtocol::Udp => None,
+            DnsProtocol::Tls(name) | DnsProtocol::Https(name) => Some(name.clone()),
+        }
+    }
+}
+
+// How an exit Node keeps idle connections to HTTP servers open for later streams to reuse
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ConnectionPoolConfig {
+    pub max_idle_per_server: usize,
+    pub idle_timeout: Duration,
+}
+
+#[derive(Message, Clone, Debug, PartialEq)]
+pub struct SetDnsServersMsg {
+    pub client_id: u64,
+    pub context_id: u64,
+    pub dns_servers: Vec<IpAddr>,
+}
+
+#[derive(Message, Clone, Debug, PartialEq)]
+pub struct GetDnsCacheStatsMsg {
+    pub client_id: u64,
+    pub context_id: u64,
+}
+
+// How well the exit Node's DNS cache is sparing its upstream DNS servers
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DnsCacheStats {
+    pub hits: u64,
+    pub negative_hits: u64,
+    pub misses: u64,
+    pub entries: usize,
+}
+
+impl fmt::Display for DnsCacheStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} hits ({} negative), {} misses, {} entries",
+            self.hits, self.negative_hits, self.misses, self.entries
+        )
+    }
+}
+
 #[derive(Clone)]
 pub struct ProxyClientConfig {
     pub cryptde: &'static dyn CryptDE,
     pub dns_servers: Vec<SocketAddr>,
+    pub dns_protocol: DnsProtocol,
     pub exit_service_rate: u64,
     pub exit_byte_rate: u64,
+    pub exit_policy: ExitPolicy,
+    pub exit_connection_pool: Option<ConnectionPoolConfig>,
 }
 
 #[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
@@ -52,6 +132,72 @@ impl DnsResolveFailure {
     }
 }
 
+// Sent back to the originating Node when this exit Node's exit policy forbids it to connect to
+// the requested server.
+#[derive(Message, Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct ExitPolicyRefusal {
+    pub version: DataVersion,
+    pub stream_key: StreamKey,
+    pub reason: String,
+}
+
+impl ExitPolicyRefusal {
+    pub fn version() -> DataVersion {
+        DataVersion::new(0, 0).expect("Internal Error")
+    }
+
+    pub fn new(stream_key: StreamKey, reason: String) -> Self {
+        Self {
+            version: Self::version(),
+            stream_key,
+            reason,
+        }
+    }
+}
+
+// An exit Node's answer to a DnsQueryPayload. response_code is the numeric DNS RCODE; records
+// carry only the record types the entry DNS server knows how to pass on.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct DnsAnswerPayload {
+    pub version: DataVersion,
+    pub stream_key: StreamKey,
+    pub response_code: u16,
+    pub records: Vec<DnsRecord>,
+}
+
+impl DnsAnswerPayload {
+    pub fn version() -> DataVersion {
+        DataVersion::new(0, 0).expect("Internal Error")
+    }
+
+    pub fn new(stream_key: StreamKey, response_code: u16, records: Vec<DnsRecord>) -> Self {
+        Self {
+            version: Self::version(),
+            stream_key,
+            response_code,
+            records,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct DnsRecord {
+    pub name: String,
+    pub ttl: u32,
+    pub data: DnsRecordData,
+}
+
+// Names inside records travel as plain strings rather than wire format, so that no compression
+// pointers into a packet the entry Node never saw can sneak through.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub enum DnsRecordData {
+    A(Ipv4Addr),
+    Aaaa(Ipv6Addr),
+    Cname(String),
+    Mx(u16, String),
+    Txt(Vec<Vec<u8>>),
+}
+
 impl Into<MessageType> for ClientResponsePayload {
     fn into(self) -> MessageType {
         MessageType::ClientResponse(self)
@@ -64,12 +210,28 @@ impl Into<MessageType> for DnsResolveFailure {
     }
 }
 
+impl Into<MessageType> for ExitPolicyRefusal {
+    fn into(self) -> MessageType {
+        MessageType::ExitPolicyRefused(self)
+    }
+}
+
+impl Into<MessageType> for DnsAnswerPayload {
+    fn into(self) -> MessageType {
+        MessageType::DnsAnswer(self)
+    }
+}
+
 #[derive(Clone)]
 pub struct ProxyClientSubs {
     pub bind: Recipient<BindMessage>,
     pub from_hopper: Recipient<ExpiredCoresPackage<ClientRequestPayload>>,
     pub inbound_server_data: Recipient<InboundServerData>,
     pub dns_resolve_failed: Recipient<DnsResolveFailure>,
+    pub exit_policy_refused: Recipient<ExitPolicyRefusal>,
+    pub dns_query_from_hopper: Recipient<ExpiredCoresPackage<DnsQueryPayload>>,
+    pub set_dns_servers_sub: Recipient<SetDnsServersMsg>,
+    pub dns_cache_stats_sub: Recipient<GetDnsCacheStatsMsg>,
 }
 
 impl Debug for ProxyClientSubs {
@@ -133,6 +295,21 @@ mod tests {
         )
     }
 
+    #[test]
+    fn dns_protocol_knows_its_port_and_certificate_name() {
+        let udp = DnsProtocol::Udp;
+        let tls = DnsProtocol::Tls("dns.example.com".to_string());
+        let https = DnsProtocol::Https("doh.example.com".to_string());
+
+        assert_eq!(DnsProtocol::default(), udp);
+        assert_eq!(udp.default_port(), 53);
+        assert_eq!(udp.tls_dns_name(), None);
+        assert_eq!(tls.default_port(), 853);
+        assert_eq!(tls.tls_dns_name(), Some("dns.example.com".to_string()));
+        assert_eq!(https.default_port(), 443);
+        assert_eq!(https.tls_dns_name(), Some("doh.example.com".to_string()));
+    }
+
     #[test]
     fn proxy_client_subs_debug() {
         let recorder = Recorder::new().start();
@@ -142,8 +319,27 @@ mod tests {
             from_hopper: recipient!(recorder, ExpiredCoresPackage<ClientRequestPayload>),
             inbound_server_data: recipient!(recorder, InboundServerData),
             dns_resolve_failed: recipient!(recorder, DnsResolveFailure),
+            exit_policy_refused: recipient!(recorder, ExitPolicyRefusal),
+            dns_query_from_hopper: recipient!(recorder, ExpiredCoresPackage<DnsQueryPayload>),
+            set_dns_servers_sub: recipient!(recorder, SetDnsServersMsg),
+            dns_cache_stats_sub: recipient!(recorder, GetDnsCacheStatsMsg),
         };
 
         assert_eq!(format!("{:?}", subject), "ProxyClientSubs");
     }
+
+    #[test]
+    fn dns_cache_stats_display_properly() {
+        let subject = DnsCacheStats {
+            hits: 12,
+            negative_hits: 3,
+            misses: 4,
+            entries: 5,
+        };
+
+        assert_eq!(
+            subject.to_string(),
+            "12 hits (3 negative), 4 misses, 5 entries"
+        );
+    }
 }
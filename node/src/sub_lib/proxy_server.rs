@@ -6,19 +6,28 @@ use crate::sub_lib::dispatcher::StreamShutdownMsg;
 use crate::sub_lib::hopper::{ExpiredCoresPackage, MessageType};
 use crate::sub_lib::neighborhood::{ExpectedService, RouteQueryResponse};
 use crate::sub_lib::peer_actors::BindMessage;
-use crate::sub_lib::proxy_client::{ClientResponsePayload, DnsResolveFailure};
+use crate::sub_lib::proxy_client::{
+    ClientResponsePayload, DnsAnswerPayload, DnsRecord, DnsResolveFailure, ExitPolicyRefusal,
+    InboundServerData,
+};
 use crate::sub_lib::sequence_buffer::SequencedPacket;
 use crate::sub_lib::set_consuming_wallet_message::SetConsumingWalletMessage;
 use crate::sub_lib::stream_key::StreamKey;
+use crate::sub_lib::stream_trace::TraceId;
 use actix::Message;
 use actix::Recipient;
+use futures::sync::mpsc::UnboundedSender;
 use serde_derive::{Deserialize, Serialize};
 use std::fmt::Debug;
 
+pub const MAX_ROUTE_PATH_COUNT: usize = 8;
+
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
 pub enum ProxyProtocol {
     HTTP,
     TLS,
+    // Anything else a browser sends through a CONNECT tunnel
+    Raw,
 }
 
 // TODO: Based on the way it's used, this struct should comprise two elements: one, a nested
@@ -33,6 +42,18 @@ pub struct ClientRequestPayload {
     pub target_port: u16,
     pub protocol: ProxyProtocol,
     pub originator_public_key: PublicKey,
+    // Set on the first request after the originating Node replaces a broken route: the exit Node
+    // should forget every return route it has for this stream except the one this request came on.
+    #[serde(default)]
+    pub fresh_return_route: bool,
+    // The application protocols the browser offered in its TLS ClientHello, for the exit Node's
+    // policies and metrics. Empty for plain HTTP.
+    #[serde(default)]
+    pub alpn_protocols: Vec<String>,
+    // Set by the ProxyServer when it's tracing the stream, so that the ProxyClient can time its
+    // side too when it's on the same Node. Any other Node doesn't know the trace and ignores it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trace_id_opt: Option<TraceId>,
 }
 
 impl Into<MessageType> for ClientRequestPayload {
@@ -47,18 +68,82 @@ impl ClientRequestPayload {
     }
 }
 
+// Asks an exit Node to look up a name for the entry DNS server. record_type is the numeric DNS
+// RR type; the exit Node answers with a DnsAnswerPayload carrying the same stream_key, which is
+// made up fresh and at random for every query.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct DnsQueryPayload {
+    pub version: DataVersion,
+    pub stream_key: StreamKey,
+    pub name: String,
+    pub record_type: u16,
+    pub originator_public_key: PublicKey,
+}
+
+impl Into<MessageType> for DnsQueryPayload {
+    fn into(self) -> MessageType {
+        MessageType::DnsQuery(self)
+    }
+}
+
+impl DnsQueryPayload {
+    pub fn version() -> DataVersion {
+        DataVersion::new(0, 0).expect("Internal Error")
+    }
+}
+
+// Sent by the entry DNS server when it wants a query resolved through the network rather than
+// answered locally. The answer comes back over respond_to.
+#[derive(Message, Debug)]
+pub struct TunneledDnsQuery {
+    pub query_id: u32,
+    pub name: String,
+    pub record_type: u16,
+    pub respond_to: UnboundedSender<TunneledDnsAnswer>,
+}
+
+// What an exit Node found for a TunneledDnsQuery, or SERVFAIL if the query couldn't get to one.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TunneledDnsAnswer {
+    pub query_id: u32,
+    pub response_code: u16,
+    pub records: Vec<DnsRecord>,
+}
+
+impl TunneledDnsAnswer {
+    pub fn new(query_id: u32, response_code: u16, records: Vec<DnsRecord>) -> Self {
+        Self {
+            query_id,
+            response_code,
+            records,
+        }
+    }
+}
+
 #[derive(Message, Debug, PartialEq, Eq)]
 pub struct AddReturnRouteMessage {
     pub return_route_id: u32,
     pub expected_services: Vec<ExpectedService>,
     pub protocol: ProxyProtocol,
     pub server_name: Option<String>,
+    // The tunneled DNS query whose answer is to come back this way, if that's what it's for
+    pub dns_query_opt: Option<StreamKey>,
 }
 
+// alternate_routes is empty unless the stream is being spread across several routes, in
+// which case it holds the routes other than the primary one.
 #[derive(Message, Debug, PartialEq)]
 pub struct AddRouteMessage {
     pub stream_key: StreamKey,
     pub route: RouteQueryResponse,
+    pub alternate_routes: Vec<RouteQueryResponse>,
+}
+
+// Sent by the Neighborhood when this Node loses its neighborship with another Node. Every route
+// that passes through that Node is broken, and streams using one need to find another.
+#[derive(Message, Debug, PartialEq)]
+pub struct InvalidateRoutesMessage {
+    pub public_key: PublicKey,
 }
 
 #[derive(Clone)]
@@ -68,10 +153,15 @@ pub struct ProxyServerSubs {
     pub from_dispatcher: Recipient<InboundClientData>,
     pub from_hopper: Recipient<ExpiredCoresPackage<ClientResponsePayload>>,
     pub dns_failure_from_hopper: Recipient<ExpiredCoresPackage<DnsResolveFailure>>,
+    pub exit_policy_refusal_from_hopper: Recipient<ExpiredCoresPackage<ExitPolicyRefusal>>,
+    pub dns_answer_from_hopper: Recipient<ExpiredCoresPackage<DnsAnswerPayload>>,
+    pub dns_query_from_entry_dns: Recipient<TunneledDnsQuery>,
     pub add_return_route: Recipient<AddReturnRouteMessage>,
     pub add_route: Recipient<AddRouteMessage>,
     pub stream_shutdown_sub: Recipient<StreamShutdownMsg>,
     pub set_consuming_wallet_sub: Recipient<SetConsumingWalletMessage>,
+    pub invalidate_routes: Recipient<InvalidateRoutesMessage>,
+    pub direct_server_data: Recipient<InboundServerData>,
 }
 
 impl Debug for ProxyServerSubs {
@@ -96,10 +186,18 @@ mod tests {
             from_dispatcher: recipient!(recorder, InboundClientData),
             from_hopper: recipient!(recorder, ExpiredCoresPackage<ClientResponsePayload>),
             dns_failure_from_hopper: recipient!(recorder, ExpiredCoresPackage<DnsResolveFailure>),
+            exit_policy_refusal_from_hopper: recipient!(
+                recorder,
+                ExpiredCoresPackage<ExitPolicyRefusal>
+            ),
+            dns_answer_from_hopper: recipient!(recorder, ExpiredCoresPackage<DnsAnswerPayload>),
+            dns_query_from_entry_dns: recipient!(recorder, TunneledDnsQuery),
             add_return_route: recipient!(recorder, AddReturnRouteMessage),
             add_route: recipient!(recorder, AddRouteMessage),
             stream_shutdown_sub: recipient!(recorder, StreamShutdownMsg),
             set_consuming_wallet_sub: recipient!(recorder, SetConsumingWalletMessage),
+            invalidate_routes: recipient!(recorder, InvalidateRoutesMessage),
+            direct_server_data: recipient!(recorder, InboundServerData),
         };
 
         assert_eq!(format!("{:?}", subject), "ProxyServerSubs");
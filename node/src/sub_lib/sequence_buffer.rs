@@ -163,9 +163,14 @@ impl Default for SequenceBuffer {
 
 impl SequenceBuffer {
     pub fn new() -> SequenceBuffer {
+        SequenceBuffer::starting_at(0)
+    }
+
+    // For a stream that has already written the packets before this one somewhere else
+    pub fn starting_at(next_expected_sequence_number: u64) -> SequenceBuffer {
         SequenceBuffer {
             buffer: BinaryHeap::new(),
-            next_expected_sequence_number: 0,
+            next_expected_sequence_number,
             seen_sequence_numbers: vec![],
             logger: Logger::new("SequenceBuffer"),
         }
@@ -314,6 +319,23 @@ mod tests {
         assert_eq!(subject.poll(), None);
     }
 
+    #[test]
+    fn sequence_buffer_can_start_partway_through() {
+        let earlier = SequencedPacket::new(vec![1, 2], 2, false);
+        let first = SequencedPacket::new(vec![3, 4], 3, false);
+        let second = SequencedPacket::new(vec![5, 6], 4, true);
+
+        let mut subject = SequenceBuffer::starting_at(3);
+
+        subject.push(second.clone());
+        subject.push(earlier);
+        subject.push(first.clone());
+
+        assert_eq!(subject.poll(), Some(first));
+        assert_eq!(subject.poll(), Some(second));
+        assert_eq!(subject.poll(), None);
+    }
+
     #[test]
     fn sequence_buffer_returns_none_while_waiting_for_next_ordered_sequenced_packet() {
         let a = SequencedPacket::new(vec![1, 23, 6, 5], 0, false);
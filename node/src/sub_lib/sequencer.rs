@@ -1,6 +1,6 @@
 // Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
 
-#[derive(Default)]
+#[derive(Clone, Default)]
 pub struct Sequencer {
     current_sequence_number: u64,
 }
@@ -10,6 +10,12 @@ impl Sequencer {
         Self::default()
     }
 
+    pub fn starting_at(sequence_number: u64) -> Self {
+        Sequencer {
+            current_sequence_number: sequence_number,
+        }
+    }
+
     pub fn next_sequence_number(&mut self) -> u64 {
         let sn = self.current_sequence_number;
         self.current_sequence_number += 1;
@@ -29,4 +35,12 @@ mod tests {
             assert_eq!(subject.next_sequence_number(), i);
         }
     }
+
+    #[test]
+    fn sequencer_can_start_partway_through() {
+        let mut subject = Sequencer::starting_at(47);
+
+        assert_eq!(subject.next_sequence_number(), 47);
+        assert_eq!(subject.next_sequence_number(), 48);
+    }
 }
@@ -1,5 +1,6 @@
 // Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
 use crate::sub_lib::logger::Logger;
+use crate::sub_lib::privacy;
 use crate::sub_lib::tokio_wrappers::ReadHalfWrapper;
 use crate::sub_lib::tokio_wrappers::ReadHalfWrapperReal;
 use crate::sub_lib::tokio_wrappers::WriteHalfWrapper;
@@ -60,7 +61,8 @@ impl StreamConnector for StreamConnectorReal {
                             Err(e) => {
                                 error!(
                                     future_logger,
-                                    "Newly-connected stream to {} has no peer_addr", socket_addr
+                                    "Newly-connected stream to {} has no peer_addr",
+                                    privacy::socket_addr(socket_addr)
                                 );
                                 return Err(e);
                             }
@@ -76,7 +78,8 @@ impl StreamConnector for StreamConnectorReal {
                     Err(e) => {
                         error!(
                             future_logger,
-                            "Could not connect TCP stream to {}", socket_addr
+                            "Could not connect TCP stream to {}",
+                            privacy::socket_addr(socket_addr)
                         );
                         Err(e)
                     }
@@ -105,7 +108,11 @@ impl StreamConnector for StreamConnectorReal {
 
             match StdTcpStream::connect(&socket_addr) {
                 Ok(stream) => {
-                    debug!(logger, "Connected new stream to {}", socket_addr);
+                    debug!(
+                        logger,
+                        "Connected new stream to {}",
+                        privacy::socket_addr(socket_addr)
+                    );
                     let tokio_stream = TcpStream::from_std(stream, &Handle::default())
                         .expect("Tokio could not create a TcpStream");
                     return Ok(self.split_stream(tokio_stream, logger).unwrap_or_else(|| {
@@ -123,8 +130,8 @@ impl StreamConnector for StreamConnectorReal {
         error!(
             logger,
             "Could not connect to any of the IP addresses supplied for {}: {:?}",
-            target_hostname,
-            socket_addrs_tried
+            privacy::host(target_hostname),
+            privacy::data(&socket_addrs_tried)
         );
         Err(last_error)
     }
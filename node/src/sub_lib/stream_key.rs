@@ -92,6 +92,13 @@ impl StreamKey {
             hash: hash.digest().bytes(),
         }
     }
+
+    // For exchanges outside any browser stream, whose keys mustn't be guessable
+    pub fn random() -> StreamKey {
+        StreamKey {
+            hash: rand::random(),
+        }
+    }
 }
 
 type HashType = [u8; sha1::DIGEST_LENGTH];
@@ -101,6 +108,14 @@ mod tests {
     use super::*;
     use std::str::FromStr;
 
+    #[test]
+    fn random_stream_keys_differ() {
+        let one = StreamKey::random();
+        let another = StreamKey::random();
+
+        assert_ne!(one, another);
+    }
+
     #[test]
     fn matching_keys_and_matching_addrs_make_matching_stream_keys() {
         let key = PublicKey::new(&b"These are the times"[..]);
@@ -0,0 +1,502 @@
+// Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use crate::sub_lib::stream_key::StreamKey;
+use lazy_static::lazy_static;
+use serde_derive::{Deserialize, Serialize};
+use serde_json::json;
+#[cfg(test)]
+use std::cell::Cell;
+use std::collections::{BTreeSet, HashMap, VecDeque};
+use std::fmt;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::Path;
+#[cfg(not(test))]
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+pub const STREAM_TRACES_FILE: &str = "stream-traces.json";
+// Finished traces kept for UIs to ask about; older ones are only in the file
+pub const RETAINED_TRACES: usize = 100;
+// Unfinished traces kept at once. A stream the ProxyServer never retires would otherwise be
+// remembered forever; past this, the oldest unfinished trace is dropped.
+pub const MAX_UNFINISHED_TRACES: usize = 1000;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct TraceId(u64);
+
+impl TraceId {
+    pub fn random() -> TraceId {
+        TraceId(rand::random())
+    }
+}
+
+impl fmt::Display for TraceId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:016x}", self.0)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum SpanKind {
+    // From the ProxyServer asking the Neighborhood for a route until it has one
+    RouteQuery,
+    // From the ProxyServer handing a request to the Hopper until the Hopper hands it on
+    HopperTransmit,
+    // From the stream's first request until the first response for it arrives
+    FirstResponseByte,
+    // From the stream's first request until the stream is retired
+    StreamClose,
+    // From the ProxyClient taking the stream's first request until it has connected to the
+    // server. Only recorded when this Node is the stream's exit Node too.
+    ServerConnect,
+}
+
+impl SpanKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            SpanKind::RouteQuery => "route_query",
+            SpanKind::HopperTransmit => "hopper_transmit",
+            SpanKind::FirstResponseByte => "first_response_byte",
+            SpanKind::StreamClose => "stream_close",
+            SpanKind::ServerConnect => "server_connect",
+        }
+    }
+}
+
+// Span starts are measured from the start of their trace
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SpanView {
+    pub name: String,
+    pub start_micros: u64,
+    pub duration_micros: u64,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StreamTraceView {
+    pub trace_id: String,
+    pub stream_key: String,
+    pub started_at_millis: u64,
+    pub closed: bool,
+    pub spans: Vec<SpanView>,
+}
+
+lazy_static! {
+    static ref TRACER: Mutex<StreamTracer> = Mutex::new(StreamTracer::new(None));
+}
+
+#[cfg(not(test))]
+static STREAM_TRACING: AtomicBool = AtomicBool::new(false);
+
+#[cfg(test)]
+thread_local! {
+    // Per thread in tests, so that one test's traces can't turn up in another test's messages
+    static STREAM_TRACING: Cell<bool> = Cell::new(false);
+}
+
+#[cfg(not(test))]
+fn set_stream_tracing(enabled: bool) {
+    STREAM_TRACING.store(enabled, Ordering::Relaxed)
+}
+
+#[cfg(not(test))]
+pub fn stream_tracing() -> bool {
+    STREAM_TRACING.load(Ordering::Relaxed)
+}
+
+#[cfg(test)]
+fn set_stream_tracing(enabled: bool) {
+    STREAM_TRACING.with(|stream_tracing| stream_tracing.set(enabled))
+}
+
+#[cfg(test)]
+pub fn stream_tracing() -> bool {
+    STREAM_TRACING.with(|stream_tracing| stream_tracing.get())
+}
+
+// Finished traces are appended to the file in Chrome's Trace Event Format, which chrome://tracing
+// and Perfetto can open even though the array is never closed.
+pub fn enable(export_path: &Path) -> Result<(), String> {
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(export_path)
+        .map_err(|e| format!("Could not open {}: {}", export_path.display(), e))?;
+    let is_empty = file.metadata().map(|m| m.len() == 0).unwrap_or(false);
+    if is_empty {
+        writeln!(file, "[").map_err(|e| e.to_string())?;
+    }
+    tracer().export_opt = Some(Box::new(file));
+    set_stream_tracing(true);
+    Ok(())
+}
+
+#[cfg(test)]
+pub fn enable_without_export() {
+    set_stream_tracing(true);
+}
+
+#[cfg(test)]
+pub fn disable() {
+    set_stream_tracing(false);
+}
+
+pub fn begin(stream_key: &StreamKey) -> Option<TraceId> {
+    if stream_tracing() {
+        Some(tracer().begin(&stream_key.to_string()))
+    } else {
+        None
+    }
+}
+
+pub fn open_span(trace_id: TraceId, kind: SpanKind) {
+    tracer().open_span(trace_id, kind)
+}
+
+pub fn close_span(trace_id: TraceId, kind: SpanKind) {
+    tracer().close_span(trace_id, kind)
+}
+
+pub fn finish(trace_id: TraceId) {
+    tracer().finish(trace_id)
+}
+
+pub fn views() -> Vec<StreamTraceView> {
+    tracer().views()
+}
+
+fn tracer() -> std::sync::MutexGuard<'static, StreamTracer> {
+    TRACER.lock().expect("Stream tracer is poisoned")
+}
+
+struct Trace {
+    stream_key: String,
+    started_at: SystemTime,
+    started: Instant,
+    open: HashMap<SpanKind, Instant>,
+    spans: Vec<SpanView>,
+}
+
+impl Trace {
+    fn view(&self, trace_id: TraceId, closed: bool) -> StreamTraceView {
+        StreamTraceView {
+            trace_id: trace_id.to_string(),
+            stream_key: self.stream_key.clone(),
+            started_at_millis: micros_since_epoch(self.started_at) / 1000,
+            closed,
+            spans: self.spans.clone(),
+        }
+    }
+}
+
+pub struct StreamTracer {
+    traces: HashMap<TraceId, Trace>,
+    // The same traces, oldest first, so that dropping one never means searching
+    unfinished: BTreeSet<(Instant, TraceId)>,
+    finished: VecDeque<StreamTraceView>,
+    export_opt: Option<Box<dyn Write + Send>>,
+}
+
+impl StreamTracer {
+    pub fn new(export_opt: Option<Box<dyn Write + Send>>) -> StreamTracer {
+        StreamTracer {
+            traces: HashMap::new(),
+            unfinished: BTreeSet::new(),
+            finished: VecDeque::new(),
+            export_opt,
+        }
+    }
+
+    pub fn begin(&mut self, stream_key: &str) -> TraceId {
+        while self.traces.len() >= MAX_UNFINISHED_TRACES {
+            let oldest = match self.unfinished.iter().next() {
+                Some(oldest) => *oldest,
+                None => break,
+            };
+            self.unfinished.remove(&oldest);
+            self.traces.remove(&oldest.1);
+        }
+        let trace_id = TraceId::random();
+        let now = Instant::now();
+        self.unfinished.insert((now, trace_id));
+        let mut open = HashMap::new();
+        open.insert(SpanKind::FirstResponseByte, now);
+        open.insert(SpanKind::StreamClose, now);
+        self.traces.insert(
+            trace_id,
+            Trace {
+                stream_key: stream_key.to_string(),
+                started_at: SystemTime::now(),
+                started: now,
+                open,
+                spans: vec![],
+            },
+        );
+        trace_id
+    }
+
+    pub fn open_span(&mut self, trace_id: TraceId, kind: SpanKind) {
+        if let Some(trace) = self.traces.get_mut(&trace_id) {
+            trace.open.insert(kind, Instant::now());
+        }
+    }
+
+    // A span that isn't open, such as a first response byte that has already come, is left alone
+    pub fn close_span(&mut self, trace_id: TraceId, kind: SpanKind) {
+        if let Some(trace) = self.traces.get_mut(&trace_id) {
+            if let Some(opened) = trace.open.remove(&kind) {
+                trace.spans.push(SpanView {
+                    name: kind.name().to_string(),
+                    start_micros: micros(opened.duration_since(trace.started)),
+                    duration_micros: micros(opened.elapsed()),
+                });
+            }
+        }
+    }
+
+    pub fn finish(&mut self, trace_id: TraceId) {
+        self.close_span(trace_id, SpanKind::StreamClose);
+        let trace = match self.traces.remove(&trace_id) {
+            Some(trace) => trace,
+            None => return,
+        };
+        self.unfinished.remove(&(trace.started, trace_id));
+        let view = trace.view(trace_id, true);
+        if let Some(export) = self.export_opt.as_mut() {
+            // A trace that can't be written is still kept for UIs
+            let _ = Self::export(export, &view);
+        }
+        self.finished.push_back(view);
+        while self.finished.len() > RETAINED_TRACES {
+            self.finished.pop_front();
+        }
+    }
+
+    pub fn views(&self) -> Vec<StreamTraceView> {
+        let mut views = self.finished.iter().cloned().collect::<Vec<_>>();
+        let mut unfinished = self
+            .traces
+            .iter()
+            .map(|(trace_id, trace)| trace.view(*trace_id, false))
+            .collect::<Vec<_>>();
+        unfinished.sort_by_key(|view| view.started_at_millis);
+        views.extend(unfinished);
+        views
+    }
+
+    fn export(export: &mut Box<dyn Write + Send>, view: &StreamTraceView) -> std::io::Result<()> {
+        let started_at_micros = view.started_at_millis * 1000;
+        for span in &view.spans {
+            let event = json!({
+                "name": span.name,
+                "cat": "stream",
+                "ph": "X",
+                "ts": started_at_micros + span.start_micros,
+                "dur": span.duration_micros,
+                "pid": std::process::id(),
+                "tid": u64::from_str_radix(&view.trace_id, 16).unwrap_or(0) & 0xFFFF_FFFF,
+                "args": {"traceId": view.trace_id, "streamKey": view.stream_key},
+            });
+            writeln!(export, "{},", event)?;
+        }
+        export.flush()
+    }
+}
+
+fn micros(duration: std::time::Duration) -> u64 {
+    duration.as_secs() * 1_000_000 + u64::from(duration.subsec_micros())
+}
+
+fn micros_since_epoch(time: SystemTime) -> u64 {
+    micros(time.duration_since(UNIX_EPOCH).unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::ensure_node_home_directory_exists;
+    use serde_json::Value;
+    use std::fs;
+    use std::fs::File;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn spans_are_recorded_from_open_to_close() {
+        let mut subject = StreamTracer::new(None);
+        let trace_id = subject.begin("stream key");
+
+        subject.open_span(trace_id, SpanKind::RouteQuery);
+        thread::sleep(Duration::from_millis(10));
+        subject.close_span(trace_id, SpanKind::RouteQuery);
+        subject.close_span(trace_id, SpanKind::FirstResponseByte);
+        subject.close_span(trace_id, SpanKind::FirstResponseByte);
+
+        let views = subject.views();
+        assert_eq!(views.len(), 1);
+        let view = &views[0];
+        assert_eq!(view.trace_id, trace_id.to_string());
+        assert_eq!(view.stream_key, "stream key".to_string());
+        assert_eq!(view.closed, false);
+        let names = view
+            .spans
+            .iter()
+            .map(|span| span.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["route_query", "first_response_byte"]);
+        assert!(view.spans[0].duration_micros >= 10_000);
+        assert!(view.spans[1].duration_micros >= view.spans[0].duration_micros);
+    }
+
+    #[test]
+    fn finishing_a_trace_closes_the_stream_and_forgets_spans_that_never_closed() {
+        let mut subject = StreamTracer::new(None);
+        let trace_id = subject.begin("stream key");
+        subject.open_span(trace_id, SpanKind::HopperTransmit);
+
+        subject.finish(trace_id);
+        subject.close_span(trace_id, SpanKind::HopperTransmit);
+
+        let views = subject.views();
+        assert_eq!(views.len(), 1);
+        assert_eq!(views[0].closed, true);
+        let names = views[0]
+            .spans
+            .iter()
+            .map(|span| span.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["stream_close"]);
+    }
+
+    #[test]
+    fn spans_for_unknown_traces_are_ignored() {
+        let mut subject = StreamTracer::new(None);
+
+        subject.open_span(TraceId(1), SpanKind::RouteQuery);
+        subject.close_span(TraceId(1), SpanKind::RouteQuery);
+        subject.finish(TraceId(1));
+
+        assert_eq!(subject.views(), vec![]);
+    }
+
+    #[test]
+    fn only_the_most_recent_finished_traces_are_kept() {
+        let mut subject = StreamTracer::new(None);
+        let trace_ids = (0..(RETAINED_TRACES + 2))
+            .map(|_| subject.begin("stream key"))
+            .collect::<Vec<_>>();
+
+        trace_ids
+            .iter()
+            .for_each(|trace_id| subject.finish(*trace_id));
+
+        let views = subject.views();
+        assert_eq!(views.len(), RETAINED_TRACES);
+        assert_eq!(views[0].trace_id, trace_ids[2].to_string());
+    }
+
+    #[test]
+    fn the_oldest_unfinished_trace_is_dropped_to_make_room() {
+        let mut subject = StreamTracer::new(None);
+        let trace_ids = (0..(MAX_UNFINISHED_TRACES + 1))
+            .map(|_| subject.begin("stream key"))
+            .collect::<Vec<_>>();
+
+        let views = subject.views();
+        assert_eq!(views.len(), MAX_UNFINISHED_TRACES);
+        assert!(!views
+            .iter()
+            .any(|view| view.trace_id == trace_ids[0].to_string()));
+        assert_eq!(subject.unfinished.len(), MAX_UNFINISHED_TRACES);
+        subject.finish(trace_ids[1]);
+        assert_eq!(subject.unfinished.len(), MAX_UNFINISHED_TRACES - 1);
+        assert_eq!(subject.finished[0].trace_id, trace_ids[1].to_string());
+    }
+
+    #[test]
+    fn finished_traces_are_exported_as_trace_events() {
+        let home_dir = ensure_node_home_directory_exists(
+            "stream_trace",
+            "finished_traces_are_exported_as_trace_events",
+        );
+        let path = home_dir.join(STREAM_TRACES_FILE);
+        let mut subject = StreamTracer::new(Some(Box::new(File::create(&path).unwrap())));
+        let trace_id = subject.begin("stream key");
+        subject.open_span(trace_id, SpanKind::RouteQuery);
+        subject.close_span(trace_id, SpanKind::RouteQuery);
+
+        subject.finish(trace_id);
+
+        let contents = fs::read_to_string(&path).unwrap();
+        let events = contents
+            .lines()
+            .map(|line| serde_json::from_str::<Value>(line.trim_end_matches(',')).unwrap())
+            .collect::<Vec<_>>();
+        let view = &subject.views()[0];
+        assert_eq!(events.len(), 2);
+        events
+            .iter()
+            .zip(view.spans.iter())
+            .for_each(|(event, span)| {
+                assert_eq!(event["name"], json!(span.name));
+                assert_eq!(event["ph"], json!("X"));
+                assert_eq!(
+                    event["ts"],
+                    json!(view.started_at_millis * 1000 + span.start_micros)
+                );
+                assert_eq!(event["dur"], json!(span.duration_micros));
+                assert_eq!(event["args"]["traceId"], json!(trace_id.to_string()));
+                assert_eq!(event["args"]["streamKey"], json!("stream key"));
+            });
+    }
+
+    #[test]
+    fn enable_starts_the_file_as_a_json_array_only_once() {
+        let home_dir = ensure_node_home_directory_exists(
+            "stream_trace",
+            "enable_starts_the_file_as_a_json_array_only_once",
+        );
+        let path = home_dir.join(STREAM_TRACES_FILE);
+        let _ = fs::remove_file(&path);
+
+        enable(&path).unwrap();
+        enable(&path).unwrap();
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "[\n");
+        assert!(stream_tracing());
+        disable();
+    }
+
+    #[test]
+    fn enable_complains_about_a_file_it_cannot_open() {
+        let home_dir = ensure_node_home_directory_exists(
+            "stream_trace",
+            "enable_complains_about_a_file_it_cannot_open",
+        );
+
+        let result = enable(&home_dir);
+
+        assert!(result.unwrap_err().starts_with("Could not open"));
+        assert!(!stream_tracing());
+    }
+
+    #[test]
+    fn begin_makes_no_trace_unless_tracing_is_enabled() {
+        let stream_key = StreamKey::new(
+            crate::sub_lib::cryptde::PublicKey::new(&[1, 2, 3]),
+            "1.2.3.4:5678".parse().unwrap(),
+        );
+
+        assert_eq!(begin(&stream_key), None);
+
+        enable_without_export();
+        let trace_id = begin(&stream_key).unwrap();
+        disable();
+        assert!(views()
+            .iter()
+            .any(|view| view.trace_id == trace_id.to_string()
+                && view.stream_key == stream_key.to_string()));
+    }
+}
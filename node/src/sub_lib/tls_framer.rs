@@ -4,6 +4,7 @@ use crate::sub_lib::framer::Framer;
 use crate::sub_lib::utils::index_of;
 
 const PRESERVE_HEADER_LEN: usize = 4;
+const MAX_RECORD_LEN: usize = 16384 + 2048;
 
 #[derive(Default)]
 pub struct TlsFramer {
@@ -63,7 +64,8 @@ impl TlsFramer {
         }
     }
 
-    // TODO: This is a very weak set of criteria that will result in many real-life false positives.  See SC-227/GH-165.
+    // A record header is recognized by its content type, its protocol version, and a length no
+    // greater than TLS allows for a record's ciphertext.
     fn search_for_frame_offset(data: &[u8]) -> Result<usize, usize> {
         match index_of(data, &[0x03]) {
             None => Err(0),    // Err (0) means don't bother trying again
@@ -75,6 +77,7 @@ impl TlsFramer {
                 } // Err (0) means don't bother trying again
                 if TlsFramer::is_valid_content_type(data[offset])
                     && TlsFramer::is_valid_protocol_version(data[offset + 1], data[offset + 2])
+                    && TlsFramer::is_valid_length(data[offset + 3], data[offset + 4])
                 {
                     Ok(offset)
                 } else {
@@ -88,9 +91,13 @@ impl TlsFramer {
         (candidate >= 0x14) && (candidate <= 0x17)
     }
 
-    // TODO: This should accept 0x0300, 0x0301, 0x0302, and 0x0303. See SC-227/GH-165.
+    // SSL 3.0 through TLS 1.2; TLS 1.3 records claim to be TLS 1.2 (or 1.0, in a ClientHello).
     fn is_valid_protocol_version(byte1: u8, byte2: u8) -> bool {
-        (byte1 == 0x03) && ((byte2 == 0x01) || (byte2 == 0x03))
+        (byte1 == 0x03) && (byte2 <= 0x03)
+    }
+
+    fn is_valid_length(hi_byte: u8, lo_byte: u8) -> bool {
+        TlsFramer::to_usize(hi_byte, lo_byte) <= MAX_RECORD_LEN
     }
 
     fn to_usize(hi_byte: u8, lo_byte: u8) -> usize {
@@ -105,6 +112,7 @@ mod tests {
     #[test]
     fn constant_values() {
         assert_eq!(PRESERVE_HEADER_LEN, 4);
+        assert_eq!(MAX_RECORD_LEN, 18432);
     }
 
     #[test]
@@ -144,7 +152,7 @@ mod tests {
     fn tls_framer_rejects_unrecognized_second_tls_version_byte() {
         let mut subject = TlsFramer::new();
 
-        subject.add_data(&vec![0x15, 0x03, 0x02, 0x00, 0x03, 0x05, 0x06, 0x07][..]);
+        subject.add_data(&vec![0x15, 0x03, 0x04, 0x00, 0x03, 0x05, 0x06, 0x07][..]);
         let result = subject.take_frame();
 
         assert_eq!(result, None);
@@ -152,9 +160,20 @@ mod tests {
     }
 
     #[test]
-    fn tls_framer_does_not_reject_data_on_basis_of_illegal_length() {
+    fn tls_framer_rejects_data_on_basis_of_illegal_length() {
+        let mut subject = TlsFramer::new();
+        let data = vec![0x16, 0x03, 0x03, 0x48, 0x01, 0x05, 0x06, 0x07];
+        subject.add_data(&data[..]);
+        let result = subject.take_frame();
+
+        assert_eq!(result, None);
+        assert_eq!(subject.data_so_far, vec!(0x01, 0x05, 0x06, 0x07));
+    }
+
+    #[test]
+    fn tls_framer_waits_for_the_rest_of_a_legal_length_record() {
         let mut subject = TlsFramer::new();
-        let data = vec![0x16, 0x03, 0x03, 0xFF, 0xFF, 0x05, 0x06, 0x07];
+        let data = vec![0x16, 0x03, 0x03, 0x48, 0x00, 0x05, 0x06, 0x07];
         subject.add_data(&data[..]);
         let result = subject.take_frame();
 
@@ -185,24 +204,26 @@ mod tests {
     }
 
     #[test]
-    fn tls_framer_recognizes_two_tls_versions() {
-        vec![0x0301, 0x0303].iter().for_each(|version| {
-            let mut subject = TlsFramer::new();
-            let byte1 = (version >> 8) as u8;
-            let byte2 = (version & 0xFF) as u8;
-
-            subject.add_data(&vec![0x17, byte1, byte2, 0x00, 0x03, 0x01, 0x02, 0x03][..]);
-            let result = subject.take_frame();
-
-            assert_eq!(
-                result,
-                Some(FramedChunk {
-                    chunk: vec!(0x17, byte1, byte2, 0x00, 0x03, 0x01, 0x02, 0x03),
-                    last_chunk: false,
-                })
-            );
-            assert_eq!(subject.data_so_far, Vec::<u8>::new());
-        });
+    fn tls_framer_recognizes_four_tls_versions() {
+        vec![0x0300, 0x0301, 0x0302, 0x0303]
+            .iter()
+            .for_each(|version| {
+                let mut subject = TlsFramer::new();
+                let byte1 = (version >> 8) as u8;
+                let byte2 = (version & 0xFF) as u8;
+
+                subject.add_data(&vec![0x17, byte1, byte2, 0x00, 0x03, 0x01, 0x02, 0x03][..]);
+                let result = subject.take_frame();
+
+                assert_eq!(
+                    result,
+                    Some(FramedChunk {
+                        chunk: vec!(0x17, byte1, byte2, 0x00, 0x03, 0x01, 0x02, 0x03),
+                        last_chunk: false,
+                    })
+                );
+                assert_eq!(subject.data_so_far, Vec::<u8>::new());
+            });
     }
 
     #[test]
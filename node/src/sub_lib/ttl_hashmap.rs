@@ -54,6 +54,20 @@ where
         }
     }
 
+    pub fn remove(&mut self, key: &K) -> Option<Rc<V>> {
+        self.data.borrow_mut().remove(key).map(|(value, _)| value)
+    }
+
+    pub fn len(&self) -> usize {
+        self.remove_expired_entries();
+
+        self.data.borrow().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
     fn remove_expired_entries(&self) {
         let now = Instant::now();
 
@@ -139,6 +153,34 @@ mod tests {
         );
     }
 
+    #[test]
+    fn ttl_hashmap_remove_returns_and_forgets_entry() {
+        let mut subject = TtlHashMap::new(Duration::from_millis(1000));
+        subject.insert(42u32, "Hello");
+
+        let removed = subject.remove(&42u32);
+        let removed_again = subject.remove(&42u32);
+
+        assert_eq!(removed.unwrap().as_ref(), &"Hello");
+        assert_eq!(removed_again, None);
+        assert_eq!(subject.get(&42u32), None);
+    }
+
+    #[test]
+    fn ttl_hashmap_len_does_not_count_expired_entries() {
+        let mut subject = TtlHashMap::new(Duration::from_millis(10));
+        assert_eq!(subject.is_empty(), true);
+
+        subject.insert(42u32, "Hello");
+        assert_eq!(subject.len(), 1);
+        assert_eq!(subject.is_empty(), false);
+
+        thread::sleep(Duration::from_millis(20));
+
+        assert_eq!(subject.len(), 0);
+        assert_eq!(subject.is_empty(), true);
+    }
+
     #[test]
     fn ttl_hashmap_get_preserves_otherwise_expired_entry() {
         // Note: You may think that these delays are far too long for unit tests, and that you can
@@ -1,17 +1,25 @@
 // Copyright (c) 2017-2018, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
 use crate::sub_lib::accountant::FinancialStatisticsMessage;
+use crate::sub_lib::neighborhood::{NeighborhoodView, NodeRecordView};
 use crate::sub_lib::peer_actors::BindMessage;
+use crate::sub_lib::proxy_client::DnsCacheStats;
+use crate::sub_lib::stream_trace::StreamTraceView;
+use crate::ui_gateway::ui_traffic_converter::BROADCAST;
 use actix::Message;
 use actix::Recipient;
 use serde_derive::{Deserialize, Serialize};
 use std::fmt::{Debug, Formatter};
 
 pub const DEFAULT_UI_PORT: u16 = 5333;
+pub const DEFAULT_METRICS_PORT: u16 = 5334;
 
 #[derive(Clone, Debug)]
 pub struct UiGatewayConfig {
     pub ui_port: u16,
+    // Metrics are served only when the user asks for them
+    pub metrics_port_opt: Option<u16>,
     pub node_descriptor: String,
+    pub session_token: String,
 }
 
 #[derive(Clone)]
@@ -19,6 +27,7 @@ pub struct UiGatewaySubs {
     pub bind: Recipient<BindMessage>,
     pub ui_message_sub: Recipient<UiCarrierMessage>,
     pub from_ui_message_sub: Recipient<FromUiMessage>,
+    pub ui_disconnect_sub: Recipient<UiDisconnectMessage>,
 }
 
 impl Debug for UiGatewaySubs {
@@ -30,9 +39,22 @@ impl Debug for UiGatewaySubs {
 #[derive(Message, Debug, Serialize, Deserialize, PartialEq)]
 pub struct UiCarrierMessage {
     pub client_id: u64,
+    // Assigned by the UiGateway to each request, and handed back by the actor that answers it
+    pub context_id: Option<u64>,
     pub data: UiMessage,
 }
 
+impl UiCarrierMessage {
+    // Goes to every UI that has subscribed to the event's topic
+    pub fn event(event: UiEvent) -> UiCarrierMessage {
+        UiCarrierMessage {
+            client_id: BROADCAST,
+            context_id: None,
+            data: UiMessage::Event(event),
+        }
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, PartialEq)]
 pub enum UiMessage {
     GetFinancialStatisticsMessage,
@@ -45,7 +67,110 @@ pub enum UiMessage {
     NodeDescriptor(String),
     NeighborhoodDotGraphRequest,
     NeighborhoodDotGraphResponse(String),
+    NeighborhoodRequest,
+    NeighborhoodResponse(NeighborhoodView),
+    StreamTracesRequest,
+    StreamTracesResponse(Vec<StreamTraceView>),
+    DnsCacheStatsRequest,
+    DnsCacheStatsResponse(DnsCacheStats),
     ShutdownMessage,
+    Subscribe(Vec<String>),
+    SubscribeResponse(Vec<String>),
+    Event(UiEvent),
+    SetConfiguration(Setting, String),
+    SetConfigurationResponse(Setting, Result<(), String>),
+}
+
+// Settings that UIs can change while the Node runs, rather than only on the command line
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq)]
+pub enum Setting {
+    RatePack,
+    NeighborhoodMode,
+    LogLevel,
+    DnsServers,
+    EarningWallet,
+}
+
+// Things that happen in the Node that UIs would otherwise have to poll for
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub enum UiEvent {
+    NeighborAdded(String),
+    NeighborRemoved(String),
+    RouteFailed(String),
+    WalletBanned(String),
+    PaymentSent {
+        to: String,
+        amount: u64,
+        transaction: String,
+    },
+    PaymentConfirmed {
+        from: String,
+        amount: u64,
+        block_number: u64,
+    },
+    WalletUnlocked(String),
+    NeighborhoodChanged {
+        updated: Vec<NodeRecordView>,
+        removed: Vec<String>,
+    },
+}
+
+impl UiMessage {
+    // Requests come from UIs; everything else is on its way to one
+    pub fn is_request(&self) -> bool {
+        match self {
+            UiMessage::GetFinancialStatisticsMessage
+            | UiMessage::SetGasPrice(_)
+            | UiMessage::SetWalletPassword(_)
+            | UiMessage::GetNodeDescriptor
+            | UiMessage::NeighborhoodDotGraphRequest
+            | UiMessage::NeighborhoodRequest
+            | UiMessage::StreamTracesRequest
+            | UiMessage::DnsCacheStatsRequest
+            | UiMessage::ShutdownMessage
+            | UiMessage::Subscribe(_)
+            | UiMessage::SetConfiguration(_, _) => true,
+            UiMessage::FinancialStatisticsResponse(_)
+            | UiMessage::SetGasPriceResponse(_)
+            | UiMessage::SetWalletPasswordResponse(_)
+            | UiMessage::NodeDescriptor(_)
+            | UiMessage::NeighborhoodDotGraphResponse(_)
+            | UiMessage::NeighborhoodResponse(_)
+            | UiMessage::StreamTracesResponse(_)
+            | UiMessage::DnsCacheStatsResponse(_)
+            | UiMessage::SubscribeResponse(_)
+            | UiMessage::SetConfigurationResponse(_, _)
+            | UiMessage::Event(_) => false,
+        }
+    }
+
+    // UIs that haven't presented the session token may only ask questions
+    pub fn requires_authentication(&self) -> bool {
+        match self {
+            UiMessage::GetFinancialStatisticsMessage
+            | UiMessage::GetNodeDescriptor
+            | UiMessage::NeighborhoodDotGraphRequest
+            | UiMessage::NeighborhoodRequest
+            | UiMessage::StreamTracesRequest
+            | UiMessage::DnsCacheStatsRequest
+            | UiMessage::Subscribe(_) => false,
+            UiMessage::SetGasPrice(_)
+            | UiMessage::SetWalletPassword(_)
+            | UiMessage::SetConfiguration(_, _)
+            | UiMessage::ShutdownMessage
+            | UiMessage::FinancialStatisticsResponse(_)
+            | UiMessage::SetGasPriceResponse(_)
+            | UiMessage::SetWalletPasswordResponse(_)
+            | UiMessage::NodeDescriptor(_)
+            | UiMessage::NeighborhoodDotGraphResponse(_)
+            | UiMessage::NeighborhoodResponse(_)
+            | UiMessage::StreamTracesResponse(_)
+            | UiMessage::DnsCacheStatsResponse(_)
+            | UiMessage::SubscribeResponse(_)
+            | UiMessage::SetConfigurationResponse(_, _)
+            | UiMessage::Event(_) => true,
+        }
+    }
 }
 
 #[derive(Message, PartialEq, Debug)]
@@ -54,11 +179,20 @@ pub struct FromUiMessage {
     pub json: String,
 }
 
+#[derive(Message, PartialEq, Debug)]
+pub struct UiDisconnectMessage {
+    pub client_id: u64,
+}
+
 #[cfg(test)]
 mod tests {
     use crate::sub_lib::peer_actors::BindMessage;
-    use crate::sub_lib::ui_gateway::{FromUiMessage, UiCarrierMessage, UiGatewaySubs};
+    use crate::sub_lib::ui_gateway::{
+        FromUiMessage, Setting, UiCarrierMessage, UiDisconnectMessage, UiEvent, UiGatewaySubs,
+        UiMessage,
+    };
     use crate::test_utils::recorder::Recorder;
+    use crate::ui_gateway::ui_traffic_converter::BROADCAST;
     use actix::Actor;
 
     #[test]
@@ -69,8 +203,61 @@ mod tests {
             bind: recipient!(recorder, BindMessage),
             ui_message_sub: recipient!(recorder, UiCarrierMessage),
             from_ui_message_sub: recipient!(recorder, FromUiMessage),
+            ui_disconnect_sub: recipient!(recorder, UiDisconnectMessage),
         };
 
         assert_eq!(format!("{:?}", subject), "UiGatewaySubs");
     }
+
+    #[test]
+    fn only_questions_can_be_asked_without_authentication() {
+        let read_only = vec![
+            UiMessage::GetFinancialStatisticsMessage,
+            UiMessage::GetNodeDescriptor,
+            UiMessage::NeighborhoodDotGraphRequest,
+            UiMessage::NeighborhoodRequest,
+            UiMessage::StreamTracesRequest,
+            UiMessage::DnsCacheStatsRequest,
+            UiMessage::Subscribe(vec!["paymentSent".to_string()]),
+        ];
+        let privileged = vec![
+            UiMessage::SetGasPrice("1".to_string()),
+            UiMessage::SetWalletPassword("password".to_string()),
+            UiMessage::SetConfiguration(Setting::LogLevel, "debug".to_string()),
+            UiMessage::ShutdownMessage,
+            UiMessage::SetGasPriceResponse(true),
+            UiMessage::NodeDescriptor("descriptor".to_string()),
+        ];
+
+        read_only
+            .iter()
+            .for_each(|msg| assert_eq!(msg.requires_authentication(), false, "{:?}", msg));
+        privileged
+            .iter()
+            .for_each(|msg| assert_eq!(msg.requires_authentication(), true, "{:?}", msg));
+    }
+
+    #[test]
+    fn requests_are_told_apart_from_what_goes_back_to_uis() {
+        assert!(UiMessage::GetNodeDescriptor.is_request());
+        assert!(UiMessage::DnsCacheStatsRequest.is_request());
+        assert!(UiMessage::SetConfiguration(Setting::LogLevel, "debug".to_string()).is_request());
+        assert!(!UiMessage::NodeDescriptor("descriptor".to_string()).is_request());
+        assert!(!UiMessage::SetConfigurationResponse(Setting::LogLevel, Ok(())).is_request());
+        assert!(!UiMessage::Event(UiEvent::WalletBanned("0x1234".to_string())).is_request());
+    }
+
+    #[test]
+    fn events_are_broadcast() {
+        let result = UiCarrierMessage::event(UiEvent::WalletBanned("0x1234".to_string()));
+
+        assert_eq!(
+            result,
+            UiCarrierMessage {
+                client_id: BROADCAST,
+                context_id: None,
+                data: UiMessage::Event(UiEvent::WalletBanned("0x1234".to_string())),
+            }
+        );
+    }
 }
@@ -372,6 +372,9 @@ pub fn make_request_payload(bytes: usize, cryptde: &dyn CryptDE) -> ClientReques
         target_port: HTTP_PORT,
         protocol: ProxyProtocol::HTTP,
         originator_public_key: cryptde.public_key().clone(),
+        fresh_return_route: false,
+        alpn_protocols: vec![],
+        trace_id_opt: None,
     }
 }
 
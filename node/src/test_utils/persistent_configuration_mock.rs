@@ -25,6 +25,7 @@ pub struct PersistentConfigurationMock {
     earning_wallet_from_address_results: RefCell<Vec<Option<Wallet>>>,
     earning_wallet_address_results: RefCell<Vec<Option<String>>>,
     set_earning_wallet_address_params: Arc<Mutex<Vec<String>>>,
+    change_earning_wallet_address_params: Arc<Mutex<Vec<String>>>,
     start_block_results: RefCell<Vec<u64>>,
     set_start_block_transactionally_results: RefCell<Vec<Result<(), String>>>,
     set_gas_price_params: Arc<Mutex<Vec<u64>>>,
@@ -108,6 +109,13 @@ impl PersistentConfiguration for PersistentConfigurationMock {
             .push(address.to_string());
     }
 
+    fn change_earning_wallet_address(&self, address: &str) {
+        self.change_earning_wallet_address_params
+            .lock()
+            .unwrap()
+            .push(address.to_string());
+    }
+
     fn start_block(&self) -> u64 {
         if self.start_block_results.borrow().is_empty() {
             return 0;
@@ -268,6 +276,14 @@ impl PersistentConfigurationMock {
         self
     }
 
+    pub fn change_earning_wallet_address_params(
+        mut self,
+        params: &Arc<Mutex<Vec<String>>>,
+    ) -> PersistentConfigurationMock {
+        self.change_earning_wallet_address_params = params.clone();
+        self
+    }
+
     pub fn start_block_result(self, start_block: u64) -> Self {
         self.start_block_results.borrow_mut().push(start_block);
         self
@@ -9,6 +9,7 @@ use crate::sub_lib::accountant::ReportExitServiceConsumedMessage;
 use crate::sub_lib::accountant::ReportExitServiceProvidedMessage;
 use crate::sub_lib::accountant::ReportRoutingServiceConsumedMessage;
 use crate::sub_lib::accountant::ReportRoutingServiceProvidedMessage;
+use crate::sub_lib::accountant::SetEarningWalletMsg;
 use crate::sub_lib::accountant::{AccountantSubs, GetFinancialStatisticsMessage};
 use crate::sub_lib::blockchain_bridge::{BlockchainBridgeSubs, SetWalletPasswordMsg};
 use crate::sub_lib::blockchain_bridge::{ReportAccountsPayable, SetGasPriceMsg};
@@ -17,26 +18,35 @@ use crate::sub_lib::dispatcher::{DispatcherSubs, StreamShutdownMsg};
 use crate::sub_lib::hopper::IncipientCoresPackage;
 use crate::sub_lib::hopper::{ExpiredCoresPackage, NoLookupIncipientCoresPackage};
 use crate::sub_lib::hopper::{HopperSubs, MessageType};
+use crate::sub_lib::neighborhood::DisjointRouteQueryMessage;
 use crate::sub_lib::neighborhood::DispatcherNodeQueryMessage;
 use crate::sub_lib::neighborhood::NeighborhoodDotGraphRequest;
 use crate::sub_lib::neighborhood::NeighborhoodSubs;
+use crate::sub_lib::neighborhood::NeighborhoodViewRequest;
 use crate::sub_lib::neighborhood::NodeQueryMessage;
 use crate::sub_lib::neighborhood::NodeQueryResponseMetadata;
 use crate::sub_lib::neighborhood::NodeRecordMetadataMessage;
 use crate::sub_lib::neighborhood::RemoveNeighborMessage;
 use crate::sub_lib::neighborhood::RouteQueryMessage;
 use crate::sub_lib::neighborhood::RouteQueryResponse;
+use crate::sub_lib::neighborhood::SetNeighborhoodModeMsg;
+use crate::sub_lib::neighborhood::SetRatePackMsg;
 use crate::sub_lib::peer_actors::PeerActors;
 use crate::sub_lib::peer_actors::{BindMessage, StartMessage};
-use crate::sub_lib::proxy_client::{ClientResponsePayload, InboundServerData};
-use crate::sub_lib::proxy_client::{DnsResolveFailure, ProxyClientSubs};
+use crate::sub_lib::proxy_client::GetDnsCacheStatsMsg;
+use crate::sub_lib::proxy_client::SetDnsServersMsg;
+use crate::sub_lib::proxy_client::{ClientResponsePayload, DnsAnswerPayload, InboundServerData};
+use crate::sub_lib::proxy_client::{DnsResolveFailure, ExitPolicyRefusal, ProxyClientSubs};
+use crate::sub_lib::proxy_server::InvalidateRoutesMessage;
 use crate::sub_lib::proxy_server::ProxyServerSubs;
 use crate::sub_lib::proxy_server::{AddReturnRouteMessage, AddRouteMessage, ClientRequestPayload};
+use crate::sub_lib::proxy_server::{DnsQueryPayload, TunneledDnsQuery};
 use crate::sub_lib::set_consuming_wallet_message::SetConsumingWalletMessage;
+use crate::sub_lib::set_earning_wallet_message::SetEarningWalletMessage;
 use crate::sub_lib::stream_handler_pool::DispatcherNodeQueryResponse;
 use crate::sub_lib::stream_handler_pool::TransmitDataMsg;
 use crate::sub_lib::ui_gateway::UiGatewaySubs;
-use crate::sub_lib::ui_gateway::{FromUiMessage, UiCarrierMessage};
+use crate::sub_lib::ui_gateway::{FromUiMessage, UiCarrierMessage, UiDisconnectMessage};
 use crate::test_utils::to_millis;
 use actix::Actor;
 use actix::Addr;
@@ -55,6 +65,7 @@ pub struct Recorder {
     recording: Arc<Mutex<Recording>>,
     node_query_responses: Vec<Option<NodeQueryResponseMetadata>>,
     route_query_responses: Vec<Option<RouteQueryResponse>>,
+    disjoint_route_query_responses: Vec<Vec<RouteQueryResponse>>,
     retrieve_transactions_responses: Vec<Result<Vec<Transaction>, BlockchainError>>,
     report_accounts_payable_responses: Vec<Result<Vec<BlockchainResult<Payment>>, String>>,
 }
@@ -88,6 +99,9 @@ recorder_message_handler!(ExpiredCoresPackage<MessageType>);
 recorder_message_handler!(ExpiredCoresPackage<ClientRequestPayload>);
 recorder_message_handler!(ExpiredCoresPackage<ClientResponsePayload>);
 recorder_message_handler!(ExpiredCoresPackage<DnsResolveFailure>);
+recorder_message_handler!(ExpiredCoresPackage<ExitPolicyRefusal>);
+recorder_message_handler!(ExpiredCoresPackage<DnsQueryPayload>);
+recorder_message_handler!(ExpiredCoresPackage<DnsAnswerPayload>);
 recorder_message_handler!(ExpiredCoresPackage<Gossip>);
 recorder_message_handler!(AddReturnRouteMessage);
 recorder_message_handler!(TransmitDataMsg);
@@ -101,6 +115,7 @@ recorder_message_handler!(DispatcherNodeQueryResponse);
 recorder_message_handler!(DispatcherNodeQueryMessage);
 recorder_message_handler!(UiCarrierMessage);
 recorder_message_handler!(FromUiMessage);
+recorder_message_handler!(UiDisconnectMessage);
 recorder_message_handler!(GetFinancialStatisticsMessage);
 recorder_message_handler!(ReportRoutingServiceProvidedMessage);
 recorder_message_handler!(ReportExitServiceProvidedMessage);
@@ -110,15 +125,25 @@ recorder_message_handler!(SetWalletPasswordMsg);
 recorder_message_handler!(SetGasPriceMsg);
 recorder_message_handler!(SetConsumingWalletMessage);
 recorder_message_handler!(DnsResolveFailure);
+recorder_message_handler!(ExitPolicyRefusal);
 recorder_message_handler!(NodeRecordMetadataMessage);
 recorder_message_handler!(ReceivedPayments);
 recorder_message_handler!(SentPayments);
 recorder_message_handler!(AddRouteMessage);
+recorder_message_handler!(InvalidateRoutesMessage);
+recorder_message_handler!(TunneledDnsQuery);
 recorder_message_handler!(AddStreamMsg);
 recorder_message_handler!(PoolBindMessage);
 recorder_message_handler!(RemoveStreamMsg);
 recorder_message_handler!(StreamShutdownMsg);
 recorder_message_handler!(NeighborhoodDotGraphRequest);
+recorder_message_handler!(NeighborhoodViewRequest);
+recorder_message_handler!(SetRatePackMsg);
+recorder_message_handler!(SetNeighborhoodModeMsg);
+recorder_message_handler!(SetEarningWalletMessage);
+recorder_message_handler!(SetEarningWalletMsg);
+recorder_message_handler!(SetDnsServersMsg);
+recorder_message_handler!(GetDnsCacheStatsMsg);
 recorder_message_handler!(StartMessage);
 
 impl Handler<NodeQueryMessage> for Recorder {
@@ -153,6 +178,22 @@ impl Handler<RouteQueryMessage> for Recorder {
     }
 }
 
+impl Handler<DisjointRouteQueryMessage> for Recorder {
+    type Result = MessageResult<DisjointRouteQueryMessage>;
+
+    fn handle(
+        &mut self,
+        msg: DisjointRouteQueryMessage,
+        _ctx: &mut Self::Context,
+    ) -> <Self as Handler<DisjointRouteQueryMessage>>::Result {
+        self.record(msg);
+        MessageResult(extract_response(
+            &mut self.disjoint_route_query_responses,
+            "No RouteQueryResponses prepared for DisjointRouteQueryMessage",
+        ))
+    }
+}
+
 impl Handler<RetrieveTransactions> for Recorder {
     type Result = MessageResult<RetrieveTransactions>;
 
@@ -231,6 +272,11 @@ impl Recorder {
         self
     }
 
+    pub fn disjoint_route_query_response(mut self, response: Vec<RouteQueryResponse>) -> Recorder {
+        self.disjoint_route_query_responses.push(response);
+        self
+    }
+
     pub fn retrieve_transactions_response(
         mut self,
         response: Result<Vec<Transaction>, BlockchainError>,
@@ -330,10 +376,19 @@ pub fn make_proxy_server_subs_from(addr: &Addr<Recorder>) -> ProxyServerSubs {
         dns_failure_from_hopper: addr
             .clone()
             .recipient::<ExpiredCoresPackage<DnsResolveFailure>>(),
+        exit_policy_refusal_from_hopper: addr
+            .clone()
+            .recipient::<ExpiredCoresPackage<ExitPolicyRefusal>>(),
+        dns_answer_from_hopper: addr
+            .clone()
+            .recipient::<ExpiredCoresPackage<DnsAnswerPayload>>(),
+        dns_query_from_entry_dns: recipient!(addr, TunneledDnsQuery),
         add_return_route: recipient!(addr, AddReturnRouteMessage),
         add_route: recipient!(addr, AddRouteMessage),
         stream_shutdown_sub: recipient!(addr, StreamShutdownMsg),
         set_consuming_wallet_sub: recipient!(addr, SetConsumingWalletMessage),
+        invalidate_routes: recipient!(addr, InvalidateRoutesMessage),
+        direct_server_data: recipient!(addr, InboundServerData),
     }
 }
 
@@ -363,6 +418,12 @@ pub fn make_proxy_client_subs_from(addr: &Addr<Recorder>) -> ProxyClientSubs {
             .recipient::<ExpiredCoresPackage<ClientRequestPayload>>(),
         inbound_server_data: recipient!(addr, InboundServerData),
         dns_resolve_failed: recipient!(addr, DnsResolveFailure),
+        exit_policy_refused: recipient!(addr, ExitPolicyRefusal),
+        dns_query_from_hopper: addr
+            .clone()
+            .recipient::<ExpiredCoresPackage<DnsQueryPayload>>(),
+        set_dns_servers_sub: recipient!(addr, SetDnsServersMsg),
+        dns_cache_stats_sub: recipient!(addr, GetDnsCacheStatsMsg),
     }
 }
 
@@ -372,6 +433,7 @@ pub fn make_neighborhood_subs_from(addr: &Addr<Recorder>) -> NeighborhoodSubs {
         start: recipient!(addr, StartMessage),
         node_query: recipient!(addr, NodeQueryMessage),
         route_query: recipient!(addr, RouteQueryMessage),
+        disjoint_route_query: recipient!(addr, DisjointRouteQueryMessage),
         update_node_record_metadata: recipient!(addr, NodeRecordMetadataMessage),
         from_hopper: addr.clone().recipient::<ExpiredCoresPackage<Gossip>>(),
         dispatcher_node_query: recipient!(addr, DispatcherNodeQueryMessage),
@@ -379,6 +441,10 @@ pub fn make_neighborhood_subs_from(addr: &Addr<Recorder>) -> NeighborhoodSubs {
         stream_shutdown_sub: recipient!(addr, StreamShutdownMsg),
         set_consuming_wallet_sub: recipient!(addr, SetConsumingWalletMessage),
         from_ui_gateway: addr.clone().recipient::<NeighborhoodDotGraphRequest>(),
+        neighborhood_view_sub: recipient!(addr, NeighborhoodViewRequest),
+        set_rate_pack_sub: recipient!(addr, SetRatePackMsg),
+        set_neighborhood_mode_sub: recipient!(addr, SetNeighborhoodModeMsg),
+        set_earning_wallet_sub: recipient!(addr, SetEarningWalletMessage),
     }
 }
 
@@ -397,6 +463,7 @@ pub fn make_accountant_subs_from(addr: &Addr<Recorder>) -> AccountantSubs {
         report_new_payments: recipient!(addr, ReceivedPayments),
         report_sent_payments: recipient!(addr, SentPayments),
         get_financial_statistics_sub: recipient!(addr, GetFinancialStatisticsMessage),
+        set_earning_wallet_sub: recipient!(addr, SetEarningWalletMsg),
     }
 }
 
@@ -405,6 +472,7 @@ pub fn make_ui_gateway_subs_from(addr: &Addr<Recorder>) -> UiGatewaySubs {
         bind: recipient!(addr, BindMessage),
         ui_message_sub: recipient!(addr, UiCarrierMessage),
         from_ui_message_sub: recipient!(addr, FromUiMessage),
+        ui_disconnect_sub: recipient!(addr, UiDisconnectMessage),
     }
 }
 
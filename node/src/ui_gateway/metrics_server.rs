@@ -0,0 +1,154 @@
+// Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use crate::sub_lib::logger::Logger;
+use crate::sub_lib::metrics;
+use crate::sub_lib::utils::localhost;
+use futures::Future;
+use futures::Stream;
+use std::net::SocketAddr;
+use tokio::io::{read, write_all};
+use tokio::net::TcpListener;
+
+const REQUEST_BUFFER_SIZE: usize = 1024;
+
+// Answers Prometheus scrapes with whatever the actors have recorded so far. Like the UI port, it
+// listens only on localhost. Metrics aren't worth stopping the Node for, so a port that can't be
+// had is only logged.
+pub fn serve_metrics(port: u16) {
+    let logger = Logger::new("MetricsServer");
+    let server_address = SocketAddr::new(localhost(), port);
+    let listener = match TcpListener::bind(&server_address) {
+        Ok(listener) => listener,
+        Err(e) => {
+            error!(
+                logger,
+                "Could not start metrics server at {}: {}", server_address, e
+            );
+            return;
+        }
+    };
+    let accept_logger = logger.clone();
+    let request_logger = logger.clone();
+    tokio::spawn(
+        listener
+            .incoming()
+            .map_err(move |e| {
+                error!(
+                    accept_logger,
+                    "Metrics server stopped accepting connections: {}", e
+                )
+            })
+            .for_each(move |stream| {
+                let logger = request_logger.clone();
+                tokio::spawn(
+                    read(stream, vec![0u8; REQUEST_BUFFER_SIZE])
+                        .and_then(|(stream, buffer, length)| {
+                            write_all(stream, response_to(&buffer[..length]))
+                        })
+                        .map(|_| ())
+                        .map_err(move |e| {
+                            warning!(logger, "Couldn't answer metrics request: {}", e)
+                        }),
+                );
+                Ok(())
+            }),
+    );
+    info!(
+        logger,
+        "Serving metrics at http://{}/metrics", server_address
+    );
+}
+
+fn response_to(request: &[u8]) -> Vec<u8> {
+    let request = String::from_utf8_lossy(request);
+    let mut words = request.split_whitespace();
+    match (words.next(), words.next()) {
+        (Some("GET"), Some("/metrics")) => http_response("200 OK", &metrics::render()),
+        _ => http_response("404 Not Found", ""),
+    }
+}
+
+fn http_response(status: &str, body: &str) -> Vec<u8> {
+    format!(
+        "HTTP/1.1 {}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    )
+    .into_bytes()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sub_lib::metrics::DISPATCHER_BYTES_RECEIVED;
+    use crate::test_utils::logging::{init_test_logging, TestLogHandler};
+    use crate::test_utils::{find_free_port, wait_for};
+    use actix::System;
+    use futures::future::lazy;
+    use std::io::{Read, Write};
+    use std::net::TcpStream;
+    use std::thread;
+
+    fn start_subject(port: u16) {
+        thread::spawn(move || {
+            let system = System::new("metrics_server");
+            actix::spawn(lazy(move || {
+                serve_metrics(port);
+                Ok(())
+            }));
+            system.run();
+        });
+    }
+
+    fn scrape(port: u16, request: &str) -> String {
+        let mut stream_opt = None;
+        wait_for(None, None, || {
+            stream_opt = TcpStream::connect(SocketAddr::new(localhost(), port)).ok();
+            stream_opt.is_some()
+        });
+        let mut stream = stream_opt.unwrap();
+        stream.write_all(request.as_bytes()).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    #[test]
+    fn serves_the_registry_in_prometheus_text_format() {
+        let port = find_free_port();
+        metrics::increment(&DISPATCHER_BYTES_RECEIVED, &[("port", "metrics-server")], 1);
+        start_subject(port);
+
+        let response = scrape(port, "GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n");
+
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{}", response);
+        assert!(response.contains("Content-Type: text/plain; version=0.0.4\r\n"));
+        assert!(response.contains("# TYPE substratum_dispatcher_bytes_received_total counter\n"));
+        assert!(response
+            .contains("substratum_dispatcher_bytes_received_total{port=\"metrics-server\"} 1\n"));
+    }
+
+    #[test]
+    fn a_port_that_is_taken_is_logged_instead_of_stopping_the_node() {
+        init_test_logging();
+        let port = find_free_port();
+        let _taken = std::net::TcpListener::bind(SocketAddr::new(localhost(), port)).unwrap();
+
+        serve_metrics(port);
+
+        TestLogHandler::new().exists_log_containing(&format!(
+            "ERROR: MetricsServer: Could not start metrics server at 127.0.0.1:{}",
+            port
+        ));
+    }
+
+    #[test]
+    fn anything_but_a_scrape_is_not_found() {
+        let result = response_to(b"POST /metrics HTTP/1.1\r\n\r\n");
+
+        assert_eq!(
+            String::from_utf8(result).unwrap(),
+            "HTTP/1.1 404 Not Found\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+        );
+    }
+}
@@ -1,21 +1,33 @@
 // Copyright (c) 2017-2018, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
 
+mod metrics_server;
+pub mod session_token;
 mod shutdown_supervisor;
 pub mod ui_traffic_converter;
 mod websocket_supervisor;
 
-use crate::sub_lib::accountant::GetFinancialStatisticsMessage;
+use crate::node_configurator::node_configurator_standard::reconfiguration;
+use crate::server_initializer::set_log_level;
+use crate::sub_lib::accountant::{GetFinancialStatisticsMessage, SetEarningWalletMsg};
 use crate::sub_lib::blockchain_bridge::{SetGasPriceMsg, SetWalletPasswordMsg};
 use crate::sub_lib::logger::Logger;
 use crate::sub_lib::neighborhood::NeighborhoodDotGraphRequest;
+use crate::sub_lib::neighborhood::NeighborhoodViewRequest;
+use crate::sub_lib::neighborhood::{SetNeighborhoodModeMsg, SetRatePackMsg};
 use crate::sub_lib::peer_actors::BindMessage;
+use crate::sub_lib::proxy_client::GetDnsCacheStatsMsg;
+use crate::sub_lib::proxy_client::SetDnsServersMsg;
+use crate::sub_lib::stream_trace;
 use crate::sub_lib::ui_gateway::UiGatewaySubs;
-use crate::sub_lib::ui_gateway::{FromUiMessage, UiCarrierMessage};
-use crate::sub_lib::ui_gateway::{UiGatewayConfig, UiMessage};
+use crate::sub_lib::ui_gateway::{FromUiMessage, UiCarrierMessage, UiDisconnectMessage};
+use crate::sub_lib::ui_gateway::{Setting, UiEvent, UiGatewayConfig, UiMessage};
+use crate::ui_gateway::metrics_server::serve_metrics;
 use crate::ui_gateway::shutdown_supervisor::ShutdownSupervisor;
 use crate::ui_gateway::shutdown_supervisor::ShutdownSupervisorReal;
 use crate::ui_gateway::ui_traffic_converter::UiTrafficConverter;
 use crate::ui_gateway::ui_traffic_converter::UiTrafficConverterReal;
+use crate::ui_gateway::ui_traffic_converter::SETTING_OPCODES;
+use crate::ui_gateway::ui_traffic_converter::{opcode_for, topic_for, UiRequest, BROADCAST};
 use crate::ui_gateway::websocket_supervisor::WebSocketSupervisor;
 use crate::ui_gateway::websocket_supervisor::WebSocketSupervisorReal;
 use actix::Actor;
@@ -23,6 +35,7 @@ use actix::Addr;
 use actix::Context;
 use actix::Handler;
 use actix::Recipient;
+use std::collections::HashMap;
 
 struct UiGatewayOutSubs {
     ui_message_sub: Recipient<UiCarrierMessage>,
@@ -30,15 +43,37 @@ struct UiGatewayOutSubs {
     blockchain_bridge_set_gas_price_sub: Recipient<SetGasPriceMsg>,
     accountant_get_financial_statistics_sub: Recipient<GetFinancialStatisticsMessage>,
     neighborhood: Recipient<NeighborhoodDotGraphRequest>,
+    neighborhood_view: Recipient<NeighborhoodViewRequest>,
+    neighborhood_set_rate_pack: Recipient<SetRatePackMsg>,
+    neighborhood_set_mode: Recipient<SetNeighborhoodModeMsg>,
+    accountant_set_earning_wallet: Recipient<SetEarningWalletMsg>,
+    proxy_client_set_dns_servers: Recipient<SetDnsServersMsg>,
+    proxy_client_dns_cache_stats: Recipient<GetDnsCacheStatsMsg>,
 }
 
+// How a UI asked its question, and therefore how it wants the answer
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum ResponseFormat {
+    Legacy,
+    Versioned(Option<u64>),
+}
+
+// Passes a request from a UI on to whoever can answer it, along with the client and context IDs
+type RequestHandler = fn(&mut UiGateway, u64, u64, UiMessage);
+
 pub struct UiGateway {
     port: u16,
+    metrics_port_opt: Option<u16>,
     node_descriptor: String,
+    session_token: String,
     converter: Box<dyn UiTrafficConverter>,
     subs: Option<UiGatewayOutSubs>,
     websocket_supervisor: Option<Box<dyn WebSocketSupervisor>>,
     shutdown_supervisor: Box<dyn ShutdownSupervisor>,
+    request_handlers: HashMap<&'static str, RequestHandler>,
+    next_context_id: u64,
+    // The client that asked each outstanding request, and how it wants the answer
+    pending_requests: HashMap<u64, (u64, ResponseFormat)>,
     logger: Logger,
 }
 
@@ -46,11 +81,16 @@ impl UiGateway {
     pub fn new(config: &UiGatewayConfig) -> UiGateway {
         UiGateway {
             port: config.ui_port,
+            metrics_port_opt: config.metrics_port_opt,
             node_descriptor: config.node_descriptor.clone(),
+            session_token: config.session_token.clone(),
             converter: Box::new(UiTrafficConverterReal::new()),
             subs: None,
             websocket_supervisor: None,
             shutdown_supervisor: Box::new(ShutdownSupervisorReal::new()),
+            request_handlers: Self::request_handlers(),
+            next_context_id: 0,
+            pending_requests: HashMap::new(),
             logger: Logger::new("UiGateway"),
         }
     }
@@ -60,8 +100,300 @@ impl UiGateway {
             bind: recipient!(addr, BindMessage),
             ui_message_sub: recipient!(addr, UiCarrierMessage),
             from_ui_message_sub: recipient!(addr, FromUiMessage),
+            ui_disconnect_sub: recipient!(addr, UiDisconnectMessage),
+        }
+    }
+
+    fn request_handlers() -> HashMap<&'static str, RequestHandler> {
+        let mut handlers: HashMap<&'static str, RequestHandler> = HashMap::new();
+        handlers.insert("financialStatistics", Self::get_financial_statistics);
+        handlers.insert("setGasPrice", Self::set_gas_price);
+        handlers.insert("setWalletPassword", Self::set_wallet_password);
+        handlers.insert("nodeDescriptor", Self::get_node_descriptor);
+        handlers.insert("neighborhoodDotGraph", Self::get_neighborhood_dot_graph);
+        handlers.insert("neighborhood", Self::get_neighborhood);
+        handlers.insert("streamTraces", Self::get_stream_traces);
+        handlers.insert("dnsCache", Self::get_dns_cache_stats);
+        handlers.insert("shutdown", Self::shutdown);
+        handlers.insert("subscribe", Self::subscribe);
+        SETTING_OPCODES.iter().for_each(|(_, opcode, _)| {
+            handlers.insert(*opcode, Self::set_configuration);
+        });
+        handlers
+    }
+
+    // The context ID goes out with the request and comes back with its answer, so answers can
+    // come back in any order
+    fn remember_request(&mut self, client_id: u64, format: ResponseFormat) -> u64 {
+        let context_id = self.next_context_id;
+        self.next_context_id = self.next_context_id.wrapping_add(1);
+        self.pending_requests
+            .insert(context_id, (client_id, format));
+        context_id
+    }
+
+    // None if the client has disconnected since it asked, or the request has already been answered
+    fn take_response_format(
+        &mut self,
+        client_id: u64,
+        context_id_opt: Option<u64>,
+    ) -> Option<ResponseFormat> {
+        let context_id = match context_id_opt {
+            None => return Some(ResponseFormat::Legacy),
+            Some(context_id) => context_id,
+        };
+        match self.pending_requests.remove(&context_id) {
+            Some((asker, format)) if asker == client_id => Some(format),
+            Some(entry) => {
+                self.pending_requests.insert(context_id, entry);
+                None
+            }
+            None => None,
+        }
+    }
+
+    fn dispatch_request(&mut self, client_id: u64, context_id_opt: Option<u64>, data: UiMessage) {
+        let context_id = match context_id_opt {
+            Some(context_id) => context_id,
+            None => self.remember_request(client_id, ResponseFormat::Legacy),
+        };
+        match self.request_handlers.get(opcode_for(&data)).cloned() {
+            Some(handler) => handler(self, client_id, context_id, data),
+            None => {
+                self.pending_requests.remove(&context_id);
+                error!(
+                    self.logger,
+                    "No handler for {} request from UI {}",
+                    opcode_for(&data),
+                    client_id
+                );
+            }
+        }
+    }
+
+    fn send_response(&mut self, client_id: u64, context_id_opt: Option<u64>, data: UiMessage) {
+        let marshalled = match self.take_response_format(client_id, context_id_opt) {
+            Some(ResponseFormat::Legacy) => self.converter.marshal(data),
+            Some(ResponseFormat::Versioned(ui_context_id)) => {
+                self.converter.marshal_response(ui_context_id, data)
+            }
+            None => {
+                debug!(
+                    self.logger,
+                    "Discarding {} response for UI {}, which is no longer waiting for it",
+                    opcode_for(&data),
+                    client_id
+                );
+                return;
+            }
+        }
+        .expect("Internal error: failed to marshal UiMessage");
+        self.send_to_ui(client_id, &marshalled);
+    }
+
+    fn send_event(&self, client_id: u64, event: UiEvent) {
+        let topic = topic_for(&event);
+        let marshalled = self
+            .converter
+            .marshal_event(event)
+            .expect("Internal error: failed to marshal UiEvent");
+        if client_id == BROADCAST {
+            self.websocket_supervisor().broadcast(topic, &marshalled);
+        } else {
+            self.send_to_ui(client_id, &marshalled);
+        }
+    }
+
+    fn answer(&self, client_id: u64, context_id: u64, data: UiMessage) {
+        self.subs()
+            .ui_message_sub
+            .try_send(UiCarrierMessage {
+                client_id,
+                context_id: Some(context_id),
+                data,
+            })
+            .expect("UiGateway is dead")
+    }
+
+    fn get_financial_statistics(&mut self, client_id: u64, context_id: u64, _: UiMessage) {
+        self.subs()
+            .accountant_get_financial_statistics_sub
+            .try_send(GetFinancialStatisticsMessage {
+                client_id,
+                context_id,
+            })
+            .expect("Accountant is dead")
+    }
+
+    fn set_gas_price(&mut self, client_id: u64, context_id: u64, data: UiMessage) {
+        if let UiMessage::SetGasPrice(gas_price) = data {
+            self.subs()
+                .blockchain_bridge_set_gas_price_sub
+                .try_send(SetGasPriceMsg {
+                    client_id,
+                    context_id,
+                    gas_price,
+                })
+                .expect("Blockchain Bridge is dead");
+        }
+    }
+
+    fn set_wallet_password(&mut self, client_id: u64, context_id: u64, data: UiMessage) {
+        if let UiMessage::SetWalletPassword(password) = data {
+            self.subs()
+                .blockchain_bridge_set_consuming_wallet_password_sub
+                .try_send(SetWalletPasswordMsg {
+                    client_id,
+                    context_id,
+                    password,
+                })
+                .expect("Blockchain Bridge is dead");
+        }
+    }
+
+    fn get_node_descriptor(&mut self, client_id: u64, context_id: u64, _: UiMessage) {
+        self.answer(
+            client_id,
+            context_id,
+            UiMessage::NodeDescriptor(self.node_descriptor.clone()),
+        )
+    }
+
+    fn get_neighborhood_dot_graph(&mut self, client_id: u64, context_id: u64, _: UiMessage) {
+        debug!(self.logger, "in UiMessage::NeighborhoodDotGraphRequest");
+        self.subs()
+            .neighborhood
+            .try_send(NeighborhoodDotGraphRequest {
+                client_id,
+                context_id,
+            })
+            .expect("Neighborhood is dead");
+    }
+
+    fn get_neighborhood(&mut self, client_id: u64, context_id: u64, _: UiMessage) {
+        debug!(self.logger, "in UiMessage::NeighborhoodRequest");
+        self.subs()
+            .neighborhood_view
+            .try_send(NeighborhoodViewRequest {
+                client_id,
+                context_id,
+            })
+            .expect("Neighborhood is dead");
+    }
+
+    fn get_stream_traces(&mut self, client_id: u64, context_id: u64, _: UiMessage) {
+        self.answer(
+            client_id,
+            context_id,
+            UiMessage::StreamTracesResponse(stream_trace::views()),
+        )
+    }
+
+    fn get_dns_cache_stats(&mut self, client_id: u64, context_id: u64, _: UiMessage) {
+        self.subs()
+            .proxy_client_dns_cache_stats
+            .try_send(GetDnsCacheStatsMsg {
+                client_id,
+                context_id,
+            })
+            .expect("ProxyClient is dead");
+    }
+
+    // Shutdown orders aren't answered
+    fn shutdown(&mut self, _client_id: u64, context_id: u64, _: UiMessage) {
+        self.pending_requests.remove(&context_id);
+        info!(self.logger, "Received shutdown order");
+        self.shutdown_supervisor.shutdown();
+    }
+
+    fn subscribe(&mut self, client_id: u64, context_id: u64, data: UiMessage) {
+        if let UiMessage::Subscribe(topics) = data {
+            self.websocket_supervisor()
+                .subscribe(client_id, topics.clone());
+            self.answer(client_id, context_id, UiMessage::SubscribeResponse(topics))
         }
     }
+
+    // Values are checked here just as the command line would check them; only acceptable ones go on
+    // to the actors that own the settings, which answer the UI themselves.
+    fn set_configuration(&mut self, client_id: u64, context_id: u64, data: UiMessage) {
+        let (setting, value) = match data {
+            UiMessage::SetConfiguration(setting, value) => (setting, value),
+            _ => return,
+        };
+        let subs = self.subs();
+        let result = match setting {
+            Setting::RatePack => reconfiguration::rate_pack(&value).map(|rate_pack| {
+                subs.neighborhood_set_rate_pack
+                    .try_send(SetRatePackMsg {
+                        client_id,
+                        context_id,
+                        rate_pack,
+                    })
+                    .expect("Neighborhood is dead")
+            }),
+            Setting::NeighborhoodMode => reconfiguration::neighborhood_mode(&value).map(|mode| {
+                subs.neighborhood_set_mode
+                    .try_send(SetNeighborhoodModeMsg {
+                        client_id,
+                        context_id,
+                        mode,
+                    })
+                    .expect("Neighborhood is dead")
+            }),
+            Setting::LogLevel => reconfiguration::log_level(&value).and_then(|log_level| {
+                set_log_level(&log_level)?;
+                info!(self.logger, "Log level changed to {}", log_level);
+                self.answer(
+                    client_id,
+                    context_id,
+                    UiMessage::SetConfigurationResponse(setting, Ok(())),
+                );
+                Ok(())
+            }),
+            Setting::DnsServers => reconfiguration::dns_servers(&value).map(|dns_servers| {
+                subs.proxy_client_set_dns_servers
+                    .try_send(SetDnsServersMsg {
+                        client_id,
+                        context_id,
+                        dns_servers,
+                    })
+                    .expect("ProxyClient is dead")
+            }),
+            Setting::EarningWallet => reconfiguration::earning_wallet(&value).map(|wallet| {
+                subs.accountant_set_earning_wallet
+                    .try_send(SetEarningWalletMsg {
+                        client_id,
+                        context_id,
+                        wallet,
+                    })
+                    .expect("Accountant is dead")
+            }),
+        };
+        if let Err(e) = result {
+            warning!(self.logger, "Refused to change {:?}: {}", setting, e);
+            self.answer(
+                client_id,
+                context_id,
+                UiMessage::SetConfigurationResponse(setting, Err(e)),
+            );
+        }
+    }
+
+    fn send_to_ui(&self, client_id: u64, json: &str) {
+        self.websocket_supervisor().send(client_id, json);
+    }
+
+    fn subs(&self) -> &UiGatewayOutSubs {
+        self.subs.as_ref().expect("UiGateway is unbound")
+    }
+
+    fn websocket_supervisor(&self) -> &dyn WebSocketSupervisor {
+        self.websocket_supervisor
+            .as_ref()
+            .expect("WebsocketSupervisor is unbound")
+            .as_ref()
+    }
 }
 
 impl Actor for UiGateway {
@@ -91,12 +423,31 @@ impl Handler<BindMessage> for UiGateway {
                 .get_financial_statistics_sub
                 .clone(),
             neighborhood: msg.peer_actors.neighborhood.from_ui_gateway.clone(),
+            neighborhood_view: msg.peer_actors.neighborhood.neighborhood_view_sub.clone(),
+            neighborhood_set_rate_pack: msg.peer_actors.neighborhood.set_rate_pack_sub.clone(),
+            neighborhood_set_mode: msg
+                .peer_actors
+                .neighborhood
+                .set_neighborhood_mode_sub
+                .clone(),
+            accountant_set_earning_wallet: msg
+                .peer_actors
+                .accountant
+                .set_earning_wallet_sub
+                .clone(),
+            proxy_client_set_dns_servers: msg.peer_actors.proxy_client.set_dns_servers_sub.clone(),
+            proxy_client_dns_cache_stats: msg.peer_actors.proxy_client.dns_cache_stats_sub.clone(),
         };
         self.subs = Some(subs);
         self.websocket_supervisor = Some(Box::new(WebSocketSupervisorReal::new(
             self.port,
             msg.peer_actors.ui_gateway.from_ui_message_sub.clone(),
+            msg.peer_actors.ui_gateway.ui_disconnect_sub.clone(),
+            self.session_token.clone(),
         )));
+        if let Some(metrics_port) = self.metrics_port_opt {
+            serve_metrics(metrics_port);
+        }
         info!(self.logger, "UIGateway bound");
     }
 }
@@ -106,123 +457,95 @@ impl Handler<UiCarrierMessage> for UiGateway {
 
     // All UI messages, both inbound and outbound, come through here
     fn handle(&mut self, msg: UiCarrierMessage, _ctx: &mut Self::Context) -> Self::Result {
-        match msg.data {
-            UiMessage::SetWalletPassword(password) => {
-                self.subs
-                    .as_ref()
-                    .expect("UiGateway is unbound")
-                    .blockchain_bridge_set_consuming_wallet_password_sub
-                    .try_send(SetWalletPasswordMsg {
-                        client_id: msg.client_id,
-                        password,
-                    })
-                    .expect("Blockchain Bridge is dead");
-            }
-            UiMessage::GetFinancialStatisticsMessage => self
-                .subs
-                .as_ref()
-                .expect("UiGateway is unbound")
-                .accountant_get_financial_statistics_sub
-                .try_send(GetFinancialStatisticsMessage {
-                    client_id: msg.client_id,
-                })
-                .expect("Accountant is dead"),
-            UiMessage::ShutdownMessage => {
-                info!(self.logger, "Received shutdown order");
-                self.shutdown_supervisor.shutdown();
-            }
-            UiMessage::GetNodeDescriptor => self
-                .subs
-                .as_ref()
-                .expect("UiGateway is unbound")
-                .ui_message_sub
-                .try_send(UiCarrierMessage {
-                    client_id: msg.client_id,
-                    data: UiMessage::NodeDescriptor(self.node_descriptor.clone()),
-                })
-                .expect("UiGateway is dead"),
-            UiMessage::SetGasPrice(gas_price) => set_gas_price(self, msg.client_id, &gas_price),
-            UiMessage::NodeDescriptor(_)
-            | UiMessage::SetWalletPasswordResponse(_)
-            | UiMessage::FinancialStatisticsResponse(_)
-            | UiMessage::SetGasPriceResponse(_)
-            | UiMessage::NeighborhoodDotGraphResponse(_) => {
-                let marshalled = self
-                    .converter
-                    .marshal(msg.data)
-                    .expect("Internal error: failed to marshal UiMessage");
-                self.websocket_supervisor
-                    .as_ref()
-                    .expect("WebsocketSupervisor is unbound")
-                    .send(msg.client_id, &marshalled);
-            }
-            UiMessage::NeighborhoodDotGraphRequest => {
-                debug!(self.logger, "in UiMessage::NeighborhoodDotGraphRequest");
-                self.subs
-                    .as_ref()
-                    .expect("UiGateway is unbound")
-                    .neighborhood
-                    .try_send(NeighborhoodDotGraphRequest {
-                        client_id: msg.client_id,
-                    })
-                    .expect("UiGateway is dead");
+        let UiCarrierMessage {
+            client_id,
+            context_id,
+            data,
+        } = msg;
+        match data {
+            UiMessage::Event(event) => self.send_event(client_id, event),
+            data => {
+                if data.is_request() {
+                    self.dispatch_request(client_id, context_id, data)
+                } else {
+                    self.send_response(client_id, context_id, data)
+                }
             }
         }
     }
 }
 
-fn set_gas_price(ui_gateway: &UiGateway, client_id: u64, gas_price: &str) {
-    ui_gateway
-        .subs
-        .as_ref()
-        .expect("UiGateway is unbound")
-        .blockchain_bridge_set_gas_price_sub
-        .try_send(SetGasPriceMsg {
-            client_id,
-            gas_price: gas_price.to_string(),
-        })
-        .expect("Blockchain Bridge is dead");
-}
-
 impl Handler<FromUiMessage> for UiGateway {
     type Result = ();
 
     // JSON messages from external UIs come in here, are translated to UiMessages, and sent to the handler above
     fn handle(&mut self, msg: FromUiMessage, _ctx: &mut Self::Context) -> Self::Result {
         match self.converter.unmarshal(&msg.json) {
-            Err(e) => warning!(
-                self.logger,
-                "Error unmarshalling message from UI - ignoring: '{}'",
-                e
-            ),
-            Ok(ui_message) => self
-                .subs
-                .as_ref()
-                .expect("UiGateway is unbound")
-                .ui_message_sub
-                .try_send(UiCarrierMessage {
-                    client_id: msg.client_id,
-                    data: ui_message,
-                })
-                .expect("UiGateway is dead"),
+            Err(e) => {
+                warning!(
+                    self.logger,
+                    "Error unmarshalling message from UI - rejecting: '{}'",
+                    e.error.message
+                );
+                let marshalled = self
+                    .converter
+                    .marshal_error(e)
+                    .expect("Internal error: failed to marshal UI error");
+                self.send_to_ui(msg.client_id, &marshalled);
+            }
+            Ok(request) => {
+                let (format, ui_message) = match request {
+                    UiRequest::Legacy(ui_message) => (ResponseFormat::Legacy, ui_message),
+                    UiRequest::Versioned {
+                        context_id,
+                        message,
+                    } => (ResponseFormat::Versioned(context_id), message),
+                };
+                let context_id = self.remember_request(msg.client_id, format);
+                self.subs()
+                    .ui_message_sub
+                    .try_send(UiCarrierMessage {
+                        client_id: msg.client_id,
+                        context_id: Some(context_id),
+                        data: ui_message,
+                    })
+                    .expect("UiGateway is dead")
+            }
         };
     }
 }
 
+impl Handler<UiDisconnectMessage> for UiGateway {
+    type Result = ();
+
+    // Whatever a departed UI was waiting for, it won't hear now
+    fn handle(&mut self, msg: UiDisconnectMessage, _ctx: &mut Self::Context) -> Self::Result {
+        self.pending_requests
+            .retain(|_, (client_id, _)| *client_id != msg.client_id);
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::sub_lib::accountant::{FinancialStatisticsMessage, GetFinancialStatisticsMessage};
     use crate::sub_lib::blockchain_bridge::SetWalletPasswordMsg;
-    use crate::sub_lib::ui_gateway::UiMessage;
+    use crate::sub_lib::cryptde::PublicKey;
+    use crate::sub_lib::neighborhood::RatePack;
+    use crate::sub_lib::stream_key::StreamKey;
+    use crate::sub_lib::ui_gateway::{UiEvent, UiMessage};
+    use crate::sub_lib::wallet::Wallet;
     use crate::test_utils::find_free_port;
     use crate::test_utils::logging::init_test_logging;
     use crate::test_utils::logging::TestLogHandler;
     use crate::test_utils::recorder::peer_actors_builder;
     use crate::test_utils::recorder::{make_recorder, Recorder};
     use crate::test_utils::wait_for;
+    use crate::ui_gateway::ui_traffic_converter::{UiError, UiRequestError, UNKNOWN_OPCODE_ERROR};
     use actix::System;
     use std::cell::RefCell;
+    use std::net::{IpAddr, SocketAddr};
+    use std::str::FromStr;
     use std::sync::Arc;
     use std::sync::Mutex;
     use std::thread;
@@ -242,6 +565,12 @@ mod tests {
                     .clone()
                     .recipient::<GetFinancialStatisticsMessage>(),
                 neighborhood: addr.clone().recipient::<NeighborhoodDotGraphRequest>(),
+                neighborhood_view: addr.clone().recipient::<NeighborhoodViewRequest>(),
+                neighborhood_set_rate_pack: addr.clone().recipient::<SetRatePackMsg>(),
+                neighborhood_set_mode: addr.clone().recipient::<SetNeighborhoodModeMsg>(),
+                accountant_set_earning_wallet: addr.clone().recipient::<SetEarningWalletMsg>(),
+                proxy_client_set_dns_servers: addr.clone().recipient::<SetDnsServersMsg>(),
+                proxy_client_dns_cache_stats: addr.clone().recipient::<GetDnsCacheStatsMsg>(),
             }
         }
     }
@@ -249,8 +578,14 @@ mod tests {
     pub struct UiTrafficConverterMock {
         marshal_parameters: Arc<Mutex<Vec<UiMessage>>>,
         marshal_results: RefCell<Vec<Result<String, String>>>,
+        marshal_response_parameters: Arc<Mutex<Vec<(Option<u64>, UiMessage)>>>,
+        marshal_response_results: RefCell<Vec<Result<String, String>>>,
+        marshal_error_parameters: Arc<Mutex<Vec<UiRequestError>>>,
+        marshal_error_results: RefCell<Vec<Result<String, String>>>,
+        marshal_event_parameters: Arc<Mutex<Vec<UiEvent>>>,
+        marshal_event_results: RefCell<Vec<Result<String, String>>>,
         unmarshal_parameters: Arc<Mutex<Vec<String>>>,
-        unmarshal_results: RefCell<Vec<Result<UiMessage, String>>>,
+        unmarshal_results: RefCell<Vec<Result<UiRequest, UiRequestError>>>,
     }
 
     impl UiTrafficConverter for UiTrafficConverterMock {
@@ -259,7 +594,32 @@ mod tests {
             self.marshal_results.borrow_mut().remove(0)
         }
 
-        fn unmarshal(&self, json: &str) -> Result<UiMessage, String> {
+        fn marshal_response(
+            &self,
+            context_id: Option<u64>,
+            ui_message: UiMessage,
+        ) -> Result<String, String> {
+            self.marshal_response_parameters
+                .lock()
+                .unwrap()
+                .push((context_id, ui_message));
+            self.marshal_response_results.borrow_mut().remove(0)
+        }
+
+        fn marshal_error(&self, request_error: UiRequestError) -> Result<String, String> {
+            self.marshal_error_parameters
+                .lock()
+                .unwrap()
+                .push(request_error);
+            self.marshal_error_results.borrow_mut().remove(0)
+        }
+
+        fn marshal_event(&self, event: UiEvent) -> Result<String, String> {
+            self.marshal_event_parameters.lock().unwrap().push(event);
+            self.marshal_event_results.borrow_mut().remove(0)
+        }
+
+        fn unmarshal(&self, json: &str) -> Result<UiRequest, UiRequestError> {
             self.unmarshal_parameters
                 .lock()
                 .unwrap()
@@ -273,6 +633,12 @@ mod tests {
             UiTrafficConverterMock {
                 marshal_parameters: Arc::new(Mutex::new(vec![])),
                 marshal_results: RefCell::new(vec![]),
+                marshal_response_parameters: Arc::new(Mutex::new(vec![])),
+                marshal_response_results: RefCell::new(vec![]),
+                marshal_error_parameters: Arc::new(Mutex::new(vec![])),
+                marshal_error_results: RefCell::new(vec![]),
+                marshal_event_parameters: Arc::new(Mutex::new(vec![])),
+                marshal_event_results: RefCell::new(vec![]),
                 unmarshal_parameters: Arc::new(Mutex::new(vec![])),
                 unmarshal_results: RefCell::new(vec![]),
             }
@@ -293,6 +659,32 @@ mod tests {
             self
         }
 
+        fn marshal_error_parameters(
+            mut self,
+            parameters: &Arc<Mutex<Vec<UiRequestError>>>,
+        ) -> UiTrafficConverterMock {
+            self.marshal_error_parameters = parameters.clone();
+            self
+        }
+
+        fn marshal_error_result(self, result: Result<String, String>) -> UiTrafficConverterMock {
+            self.marshal_error_results.borrow_mut().push(result);
+            self
+        }
+
+        fn marshal_event_parameters(
+            mut self,
+            parameters: &Arc<Mutex<Vec<UiEvent>>>,
+        ) -> UiTrafficConverterMock {
+            self.marshal_event_parameters = parameters.clone();
+            self
+        }
+
+        fn marshal_event_result(self, result: Result<String, String>) -> UiTrafficConverterMock {
+            self.marshal_event_results.borrow_mut().push(result);
+            self
+        }
+
         fn unmarshal_parameters(
             mut self,
             parameters: &Arc<Mutex<Vec<String>>>,
@@ -301,7 +693,10 @@ mod tests {
             self
         }
 
-        fn unmarshal_result(self, result: Result<UiMessage, String>) -> UiTrafficConverterMock {
+        fn unmarshal_result(
+            self,
+            result: Result<UiRequest, UiRequestError>,
+        ) -> UiTrafficConverterMock {
             self.unmarshal_results.borrow_mut().push(result);
             self
         }
@@ -310,6 +705,8 @@ mod tests {
     #[derive(Default)]
     struct WebSocketSupervisorMock {
         send_parameters: Arc<Mutex<Vec<(u64, String)>>>,
+        subscribe_parameters: Arc<Mutex<Vec<(u64, Vec<String>)>>>,
+        broadcast_parameters: Arc<Mutex<Vec<(String, String)>>>,
     }
 
     impl WebSocketSupervisor for WebSocketSupervisorMock {
@@ -319,15 +716,47 @@ mod tests {
                 .unwrap()
                 .push((client_id, String::from(message_json)));
         }
+
+        fn subscribe(&self, client_id: u64, topics: Vec<String>) {
+            self.subscribe_parameters
+                .lock()
+                .unwrap()
+                .push((client_id, topics));
+        }
+
+        fn broadcast(&self, topic: &str, message_json: &str) {
+            self.broadcast_parameters
+                .lock()
+                .unwrap()
+                .push((String::from(topic), String::from(message_json)));
+        }
     }
 
     impl WebSocketSupervisorMock {
         fn new() -> WebSocketSupervisorMock {
             WebSocketSupervisorMock {
                 send_parameters: Arc::new(Mutex::new(vec![])),
+                subscribe_parameters: Arc::new(Mutex::new(vec![])),
+                broadcast_parameters: Arc::new(Mutex::new(vec![])),
             }
         }
 
+        fn subscribe_parameters(
+            mut self,
+            parameters: &Arc<Mutex<Vec<(u64, Vec<String>)>>>,
+        ) -> WebSocketSupervisorMock {
+            self.subscribe_parameters = parameters.clone();
+            self
+        }
+
+        fn broadcast_parameters(
+            mut self,
+            parameters: &Arc<Mutex<Vec<(String, String)>>>,
+        ) -> WebSocketSupervisorMock {
+            self.broadcast_parameters = parameters.clone();
+            self
+        }
+
         fn send_parameters(
             mut self,
             parameters: &Arc<Mutex<Vec<(u64, String)>>>,
@@ -368,7 +797,9 @@ mod tests {
         let (accountant, _, accountant_recording_arc) = make_recorder();
         let subject = UiGateway::new(&UiGatewayConfig {
             ui_port: find_free_port(),
+            metrics_port_opt: None,
             node_descriptor: String::from(""),
+            session_token: String::new(),
         });
         let system = System::new(
             "receiving_a_get_financial_statistics_message_sends_traffic_to_the_accountant",
@@ -380,6 +811,7 @@ mod tests {
 
         addr.try_send(UiCarrierMessage {
             client_id: 3,
+            context_id: None,
             data: UiMessage::GetFinancialStatisticsMessage,
         })
         .unwrap();
@@ -390,7 +822,10 @@ mod tests {
         let accountant_recorder = accountant_recording_arc.lock().unwrap();
         assert_eq!(
             accountant_recorder.get_record::<GetFinancialStatisticsMessage>(0),
-            &GetFinancialStatisticsMessage { client_id: 3 }
+            &GetFinancialStatisticsMessage {
+                client_id: 3,
+                context_id: 0
+            }
         )
     }
 
@@ -399,7 +834,9 @@ mod tests {
         let (blockchain_bridge, _, blockchain_bridge_recorder_arc) = make_recorder();
         let subject = UiGateway::new(&UiGatewayConfig {
             ui_port: find_free_port(),
+            metrics_port_opt: None,
             node_descriptor: String::from(""),
+            session_token: String::new(),
         });
         let system = System::new(
             "receiving_a_set_consuming_wallet_password_message_sends_traffic_to_blockchain_bridge",
@@ -413,6 +850,7 @@ mod tests {
 
         addr.try_send(UiCarrierMessage {
             client_id: 0,
+            context_id: None,
             data: UiMessage::SetWalletPassword("booga".to_string()),
         })
         .unwrap();
@@ -424,6 +862,7 @@ mod tests {
             blockchain_bridge_recorder.get_record::<SetWalletPasswordMsg>(0),
             &SetWalletPasswordMsg {
                 client_id: 0,
+                context_id: 0,
                 password: "booga".to_string(),
             }
         )
@@ -439,7 +878,9 @@ mod tests {
                 ShutdownSupervisorMock::new().shutdown_parameters(&shutdown_parameters_inside);
             let mut subject = UiGateway::new(&UiGatewayConfig {
                 ui_port: find_free_port(),
+                metrics_port_opt: None,
                 node_descriptor: String::from(""),
+                session_token: String::new(),
             });
             subject.shutdown_supervisor = Box::new(supervisor);
             let system =
@@ -451,6 +892,7 @@ mod tests {
 
             addr.try_send(UiCarrierMessage {
                 client_id: 0,
+                context_id: None,
                 data: UiMessage::ShutdownMessage,
             })
             .unwrap();
@@ -470,7 +912,9 @@ mod tests {
             );
             let mut subject = UiGateway::new(&UiGatewayConfig {
                 ui_port: find_free_port(),
+                metrics_port_opt: None,
                 node_descriptor: String::from("NODE-DESCRIPTOR"),
+                session_token: String::new(),
             });
             let ui_gateway_recorder_addr = ui_gateway_recorder.start();
             subject.subs = Some(UiGatewayOutSubs {
@@ -493,6 +937,7 @@ mod tests {
                 .ui_message_sub
                 .try_send(UiCarrierMessage {
                     client_id: 1234,
+                    context_id: None,
                     data: UiMessage::GetNodeDescriptor,
                 })
                 .unwrap();
@@ -508,11 +953,69 @@ mod tests {
             ui_gateway_recording.get_record::<UiCarrierMessage>(1),
             &UiCarrierMessage {
                 client_id: 1234,
+                context_id: Some(1),
                 data: UiMessage::NodeDescriptor("NODE-DESCRIPTOR".to_string())
             }
         );
     }
 
+    #[test]
+    fn receiving_a_stream_traces_request_triggers_a_stream_traces_response() {
+        let (ui_gateway_recorder, ui_gateway_awaiter, ui_gateway_recording_arc) = make_recorder();
+        let stream_key = StreamKey::new(
+            PublicKey::new(&b"receiving_a_stream_traces_request"[..]),
+            SocketAddr::from_str("1.2.3.4:5678").unwrap(),
+        );
+
+        thread::spawn(move || {
+            let system =
+                System::new("receiving_a_stream_traces_request_triggers_a_stream_traces_response");
+            stream_trace::enable_without_export();
+            stream_trace::begin(&stream_key).unwrap();
+            stream_trace::disable();
+            let mut subject = UiGateway::new(&UiGatewayConfig {
+                ui_port: find_free_port(),
+                metrics_port_opt: None,
+                node_descriptor: String::new(),
+                session_token: String::new(),
+            });
+            let ui_gateway_recorder_addr = ui_gateway_recorder.start();
+            subject.subs = Some(UiGatewayOutSubs {
+                ui_message_sub: ui_gateway_recorder_addr.recipient::<UiCarrierMessage>(),
+                ..Default::default()
+            });
+            let subject_addr = subject.start();
+            let subject_subs = UiGateway::make_subs_from(&subject_addr);
+
+            subject_subs
+                .ui_message_sub
+                .try_send(UiCarrierMessage {
+                    client_id: 1234,
+                    context_id: None,
+                    data: UiMessage::StreamTracesRequest,
+                })
+                .unwrap();
+
+            system.run();
+        });
+
+        ui_gateway_awaiter.await_message_count(1);
+
+        let ui_gateway_recording = ui_gateway_recording_arc.lock().unwrap();
+        let response = ui_gateway_recording.get_record::<UiCarrierMessage>(0);
+        assert_eq!(response.client_id, 1234);
+        match &response.data {
+            UiMessage::StreamTracesResponse(traces) => assert!(
+                traces
+                    .iter()
+                    .any(|trace| trace.stream_key == stream_key.to_string()),
+                "{:?}",
+                traces
+            ),
+            other => panic!("Expected StreamTracesResponse, got {:?}", other),
+        }
+    }
+
     #[test]
     fn node_descriptor_message_is_directed_to_websocket_supervisor() {
         let (ui_gateway_recorder, _, _) = make_recorder();
@@ -521,7 +1024,9 @@ mod tests {
         let system = System::new("node_descriptor_message_is_directed_to_websocket_supervisor");
         let mut subject = UiGateway::new(&UiGatewayConfig {
             ui_port: find_free_port(),
+            metrics_port_opt: None,
             node_descriptor: String::from(""),
+            session_token: String::new(),
         });
         subject.websocket_supervisor = Some(Box::new(
             WebSocketSupervisorMock::new().send_parameters(&receive_parameters_arc),
@@ -538,6 +1043,7 @@ mod tests {
             .ui_message_sub
             .try_send(UiCarrierMessage {
                 client_id: 1234,
+                context_id: None,
                 data: UiMessage::NodeDescriptor("NODE-DESCRIPTOR".to_string()),
             })
             .unwrap();
@@ -573,7 +1079,9 @@ mod tests {
         );
         let mut subject = UiGateway::new(&UiGatewayConfig {
             ui_port: find_free_port(),
+            metrics_port_opt: None,
             node_descriptor: String::from(""),
+            session_token: String::new(),
         });
         subject.websocket_supervisor = Some(Box::new(
             WebSocketSupervisorMock::new().send_parameters(&receive_parameters_arc),
@@ -590,6 +1098,7 @@ mod tests {
             .ui_message_sub
             .try_send(UiCarrierMessage {
                 client_id: 1234,
+                context_id: None,
                 data: UiMessage::SetWalletPasswordResponse(true),
             })
             .unwrap();
@@ -619,7 +1128,9 @@ mod tests {
         let (blockchain_bridge, _, blockchain_bridge_recorder_arc) = make_recorder();
         let subject = UiGateway::new(&UiGatewayConfig {
             ui_port: find_free_port(),
+            metrics_port_opt: None,
             node_descriptor: String::from(""),
+            session_token: String::new(),
         });
         let system =
             System::new("receiving_a_set_gas_price_message_sends_traffic_to_blockchain_bridge");
@@ -632,6 +1143,7 @@ mod tests {
 
         addr.try_send(UiCarrierMessage {
             client_id: 0,
+            context_id: None,
             data: UiMessage::SetGasPrice("11".to_string()),
         })
         .unwrap();
@@ -643,6 +1155,7 @@ mod tests {
             blockchain_bridge_recorder.get_record::<SetGasPriceMsg>(0),
             &SetGasPriceMsg {
                 client_id: 0,
+                context_id: 0,
                 gas_price: "11".to_string(),
             }
         )
@@ -657,7 +1170,9 @@ mod tests {
             System::new("set_gas_price_response_message_is_directed_to_websocket_supervisor");
         let mut subject = UiGateway::new(&UiGatewayConfig {
             ui_port: find_free_port(),
+            metrics_port_opt: None,
             node_descriptor: "".to_string(),
+            session_token: String::new(),
         });
         subject.websocket_supervisor = Some(Box::new(
             WebSocketSupervisorMock::new().send_parameters(&receive_parameters_arc),
@@ -674,6 +1189,7 @@ mod tests {
             .ui_message_sub
             .try_send(UiCarrierMessage {
                 client_id: 1234,
+                context_id: None,
                 data: UiMessage::SetGasPriceResponse(true),
             })
             .unwrap();
@@ -708,7 +1224,9 @@ mod tests {
         );
         let mut subject = UiGateway::new(&UiGatewayConfig {
             ui_port: find_free_port(),
+            metrics_port_opt: None,
             node_descriptor: String::from(""),
+            session_token: String::new(),
         });
         subject.websocket_supervisor = Some(Box::new(
             WebSocketSupervisorMock::new().send_parameters(&receive_parameters_arc),
@@ -725,6 +1243,7 @@ mod tests {
             .ui_message_sub
             .try_send(UiCarrierMessage {
                 client_id: 1234,
+                context_id: None,
                 data: UiMessage::FinancialStatisticsResponse(FinancialStatisticsMessage {
                     pending_credit: 1_000_000_001,
                     pending_debt: 2_000_000_001,
@@ -763,13 +1282,15 @@ mod tests {
         let unmarshal_parameters = Arc::new(Mutex::new(vec![]));
         let handler = UiTrafficConverterMock::new()
             .unmarshal_parameters(&unmarshal_parameters)
-            .unmarshal_result(Ok(UiMessage::ShutdownMessage));
+            .unmarshal_result(Ok(UiRequest::Legacy(UiMessage::ShutdownMessage)));
         let (ui_gateway, ui_gateway_awaiter, ui_gateway_recording_arc) = make_recorder();
 
         thread::spawn(move || {
             let mut subject = UiGateway::new(&UiGatewayConfig {
                 ui_port: find_free_port(),
+                metrics_port_opt: None,
                 node_descriptor: String::from(""),
+                session_token: String::new(),
             });
             subject.converter = Box::new(handler);
             let system = System::new("good_from_ui_message_is_unmarshalled_and_resent");
@@ -797,43 +1318,204 @@ mod tests {
             ui_gateway_recording.get_record::<UiCarrierMessage>(0),
             &UiCarrierMessage {
                 client_id: 42,
+                context_id: Some(0),
                 data: UiMessage::ShutdownMessage
             }
         );
     }
 
     #[test]
-    fn bad_from_ui_message_is_logged_and_ignored() {
+    fn bad_from_ui_message_is_logged_and_answered_with_an_error() {
         init_test_logging();
-        let handler =
-            UiTrafficConverterMock::new().unmarshal_result(Err(String::from("I have a tummyache")));
+        let request_error = UiRequestError {
+            opcode: "booga".to_string(),
+            context_id: Some(1),
+            error: UiError::new(UNKNOWN_OPCODE_ERROR, "I have a tummyache"),
+        };
+        let marshal_error_parameters_arc = Arc::new(Mutex::new(vec![]));
+        let handler = UiTrafficConverterMock::new()
+            .unmarshal_result(Err(request_error))
+            .marshal_error_parameters(&marshal_error_parameters_arc)
+            .marshal_error_result(Ok("error JSON".to_string()));
+        let send_parameters_arc = Arc::new(Mutex::new(vec![]));
         let (ui_gateway, _, ui_gateway_recording_arc) = make_recorder();
+        let system = System::new("bad_from_ui_message_is_logged_and_answered_with_an_error");
+        let mut subject = UiGateway::new(&UiGatewayConfig {
+            ui_port: find_free_port(),
+            metrics_port_opt: None,
+            node_descriptor: String::from(""),
+            session_token: String::new(),
+        });
+        subject.converter = Box::new(handler);
+        subject.websocket_supervisor = Some(Box::new(
+            WebSocketSupervisorMock::new().send_parameters(&send_parameters_arc),
+        ));
+        subject.subs = Some(UiGatewayOutSubs {
+            ui_message_sub: ui_gateway.start().recipient::<UiCarrierMessage>(),
+            ..Default::default()
+        });
+        let addr: Addr<UiGateway> = subject.start();
 
-        thread::spawn(move || {
-            let mut subject = UiGateway::new(&UiGatewayConfig {
-                ui_port: find_free_port(),
-                node_descriptor: String::from(""),
-            });
-            subject.converter = Box::new(handler);
-            let system = System::new("bad_from_ui_message_is_logged_and_ignored");
-            let addr: Addr<UiGateway> = subject.start();
-            let peer_actors = peer_actors_builder().ui_gateway(ui_gateway).build();
-            addr.try_send(BindMessage { peer_actors }).unwrap();
+        addr.try_send(FromUiMessage {
+            client_id: 7,
+            json: String::from("pretend I'm JSON"),
+        })
+        .unwrap();
 
+        System::current().stop();
+        system.run();
+        TestLogHandler::new().exists_log_containing(
+            "Error unmarshalling message from UI - rejecting: 'I have a tummyache'",
+        );
+        assert_eq!(
+            *marshal_error_parameters_arc.lock().unwrap(),
+            vec![UiRequestError {
+                opcode: "booga".to_string(),
+                context_id: Some(1),
+                error: UiError::new(UNKNOWN_OPCODE_ERROR, "I have a tummyache"),
+            }]
+        );
+        assert_eq!(
+            *send_parameters_arc.lock().unwrap(),
+            vec![(7, "error JSON".to_string())]
+        );
+        let ui_gateway_recording = ui_gateway_recording_arc.lock().unwrap();
+        assert_eq!(ui_gateway_recording.len(), 0);
+    }
+
+    #[test]
+    fn versioned_requests_get_versioned_responses_with_their_context_ids() {
+        let send_parameters_arc = Arc::new(Mutex::new(vec![]));
+        let (ui_gateway, _, ui_gateway_recording_arc) = make_recorder();
+        let system =
+            System::new("versioned_requests_get_versioned_responses_with_their_context_ids");
+        let mut subject = UiGateway::new(&UiGatewayConfig {
+            ui_port: find_free_port(),
+            metrics_port_opt: None,
+            node_descriptor: String::from(""),
+            session_token: String::new(),
+        });
+        subject.websocket_supervisor = Some(Box::new(
+            WebSocketSupervisorMock::new().send_parameters(&send_parameters_arc),
+        ));
+        subject.subs = Some(UiGatewayOutSubs {
+            ui_message_sub: ui_gateway.start().recipient::<UiCarrierMessage>(),
+            ..Default::default()
+        });
+        let addr: Addr<UiGateway> = subject.start();
+        let requests = vec![
+            (
+                1,
+                r#"{"version":1,"opcode":"setGasPrice","contextId":11,"payload":{"gasPrice":"booga"}}"#,
+            ),
+            (
+                1,
+                r#"{"version":1,"opcode":"setGasPrice","contextId":12,"payload":{"gasPrice":"12"}}"#,
+            ),
+            (1, r#""GetNodeDescriptor""#),
+            (
+                2,
+                r#"{"version":1,"opcode":"nodeDescriptor","contextId":13}"#,
+            ),
+        ];
+        // Answers to the same kind of question from the same UI may come back in any order
+        let responses = vec![
+            (1, 1, UiMessage::SetGasPriceResponse(true)),
+            (2, 3, UiMessage::NodeDescriptor("descriptor".to_string())),
+            (1, 0, UiMessage::SetGasPriceResponse(false)),
+            (1, 2, UiMessage::NodeDescriptor("descriptor".to_string())),
+            (1, 0, UiMessage::SetGasPriceResponse(true)),
+            (1, 3, UiMessage::NodeDescriptor("descriptor".to_string())),
+        ];
+
+        requests.into_iter().for_each(|(client_id, json)| {
             addr.try_send(FromUiMessage {
-                client_id: 0,
-                json: String::from("pretend I'm JSON"),
+                client_id,
+                json: json.to_string(),
             })
-            .unwrap();
+            .unwrap()
+        });
+        responses
+            .into_iter()
+            .for_each(|(client_id, context_id, data)| {
+                addr.try_send(UiCarrierMessage {
+                    client_id,
+                    context_id: Some(context_id),
+                    data,
+                })
+                .unwrap()
+            });
 
-            system.run();
+        System::current().stop();
+        system.run();
+        let ui_gateway_recording = ui_gateway_recording_arc.lock().unwrap();
+        assert_eq!(
+            (0..4)
+                .map(|index| {
+                    let request = ui_gateway_recording.get_record::<UiCarrierMessage>(index);
+                    (request.client_id, request.context_id)
+                })
+                .collect::<Vec<(u64, Option<u64>)>>(),
+            vec![(1, Some(0)), (1, Some(1)), (1, Some(2)), (2, Some(3))]
+        );
+        assert_eq!(
+            *send_parameters_arc.lock().unwrap(),
+            vec![
+                (1, r#"{"version":1,"opcode":"setGasPrice","contextId":12,"payload":{}}"#.to_string()),
+                (2, r#"{"version":1,"opcode":"nodeDescriptor","contextId":13,"payload":{"nodeDescriptor":"descriptor"}}"#.to_string()),
+                (1, r#"{"version":1,"opcode":"setGasPrice","contextId":11,"error":{"code":6,"message":"The gas price was not accepted"}}"#.to_string()),
+                (1, r#"{"NodeDescriptor":"descriptor"}"#.to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn answers_for_a_ui_that_has_disconnected_are_discarded() {
+        let send_parameters_arc = Arc::new(Mutex::new(vec![]));
+        let (ui_gateway, _, _) = make_recorder();
+        let system = System::new("answers_for_a_ui_that_has_disconnected_are_discarded");
+        let mut subject = UiGateway::new(&UiGatewayConfig {
+            ui_port: find_free_port(),
+            metrics_port_opt: None,
+            node_descriptor: String::from(""),
+            session_token: String::new(),
+        });
+        subject.websocket_supervisor = Some(Box::new(
+            WebSocketSupervisorMock::new().send_parameters(&send_parameters_arc),
+        ));
+        subject.subs = Some(UiGatewayOutSubs {
+            ui_message_sub: ui_gateway.start().recipient::<UiCarrierMessage>(),
+            ..Default::default()
+        });
+        let addr: Addr<UiGateway> = subject.start();
+        vec![1, 2].into_iter().for_each(|client_id| {
+            addr.try_send(FromUiMessage {
+                client_id,
+                json: r#"{"version":1,"opcode":"nodeDescriptor","contextId":5}"#.to_string(),
+            })
+            .unwrap()
         });
-        TestLogHandler::new().await_log_containing(
-            "Error unmarshalling message from UI - ignoring: 'I have a tummyache'",
-            1000,
+
+        addr.try_send(UiDisconnectMessage { client_id: 1 }).unwrap();
+        vec![(1, 0), (2, 1)]
+            .into_iter()
+            .for_each(|(client_id, context_id)| {
+                addr.try_send(UiCarrierMessage {
+                    client_id,
+                    context_id: Some(context_id),
+                    data: UiMessage::NodeDescriptor("descriptor".to_string()),
+                })
+                .unwrap()
+            });
+
+        System::current().stop();
+        system.run();
+        assert_eq!(
+            *send_parameters_arc.lock().unwrap(),
+            vec![
+                (2, r#"{"version":1,"opcode":"nodeDescriptor","contextId":5,"payload":{"nodeDescriptor":"descriptor"}}"#.to_string()),
+            ]
         );
-        let ui_gateway_recording = ui_gateway_recording_arc.lock().unwrap();
-        assert_eq!(ui_gateway_recording.len(), 0);
     }
 
     #[test]
@@ -841,7 +1523,9 @@ mod tests {
         let (neighborhood, _, neighborhood_recorder_arc) = make_recorder();
         let subject = UiGateway::new(&UiGatewayConfig {
             ui_port: find_free_port(),
+            metrics_port_opt: None,
             node_descriptor: String::from(""),
+            session_token: String::new(),
         });
         let system = System::new("request_for_dot_graph_forwards_request_to_neighbor");
         let addr: Addr<UiGateway> = subject.start();
@@ -860,7 +1544,318 @@ mod tests {
         let actual_request = neighborhood_recorder.get_record::<NeighborhoodDotGraphRequest>(0);
         assert_eq!(
             actual_request,
-            &NeighborhoodDotGraphRequest { client_id: 0 }
+            &NeighborhoodDotGraphRequest {
+                client_id: 0,
+                context_id: 0
+            }
+        );
+    }
+
+    #[test]
+    fn request_for_neighborhood_view_forwards_request_to_neighborhood() {
+        let (neighborhood, _, neighborhood_recorder_arc) = make_recorder();
+        let subject = UiGateway::new(&UiGatewayConfig {
+            ui_port: find_free_port(),
+            metrics_port_opt: None,
+            node_descriptor: String::from(""),
+            session_token: String::new(),
+        });
+        let system = System::new("request_for_neighborhood_view_forwards_request_to_neighborhood");
+        let addr: Addr<UiGateway> = subject.start();
+        let mut peer_actors = peer_actors_builder().neighborhood(neighborhood).build();
+        peer_actors.ui_gateway = UiGateway::make_subs_from(&addr);
+        addr.try_send(BindMessage { peer_actors }).unwrap();
+
+        let json = r#"{"version":1,"opcode":"neighborhood","contextId":3}"#.to_string();
+        addr.try_send(FromUiMessage { client_id: 4, json }).unwrap();
+
+        System::current().stop();
+        system.run();
+        let neighborhood_recorder = neighborhood_recorder_arc.lock().unwrap();
+        assert_eq!(
+            neighborhood_recorder.get_record::<NeighborhoodViewRequest>(0),
+            &NeighborhoodViewRequest {
+                client_id: 4,
+                context_id: 0
+            }
+        );
+    }
+
+    #[test]
+    fn request_for_dns_cache_stats_forwards_request_to_proxy_client() {
+        let (proxy_client, _, proxy_client_recording_arc) = make_recorder();
+        let subject = UiGateway::new(&UiGatewayConfig {
+            ui_port: find_free_port(),
+            metrics_port_opt: None,
+            node_descriptor: String::from(""),
+            session_token: String::new(),
+        });
+        let system = System::new("request_for_dns_cache_stats_forwards_request_to_proxy_client");
+        let addr: Addr<UiGateway> = subject.start();
+        let mut peer_actors = peer_actors_builder().proxy_client(proxy_client).build();
+        peer_actors.ui_gateway = UiGateway::make_subs_from(&addr);
+        addr.try_send(BindMessage { peer_actors }).unwrap();
+
+        let json = r#"{"version":1,"opcode":"dnsCache","contextId":3}"#.to_string();
+        addr.try_send(FromUiMessage { client_id: 4, json }).unwrap();
+
+        System::current().stop();
+        system.run();
+        let proxy_client_recording = proxy_client_recording_arc.lock().unwrap();
+        assert_eq!(
+            proxy_client_recording.get_record::<GetDnsCacheStatsMsg>(0),
+            &GetDnsCacheStatsMsg {
+                client_id: 4,
+                context_id: 0
+            }
+        );
+    }
+
+    #[test]
+    fn acceptable_configuration_changes_go_to_the_actors_that_own_the_settings() {
+        let (neighborhood, _, neighborhood_recording_arc) = make_recorder();
+        let (accountant, _, accountant_recording_arc) = make_recorder();
+        let (proxy_client, _, proxy_client_recording_arc) = make_recorder();
+        let subject = UiGateway::new(&UiGatewayConfig {
+            ui_port: find_free_port(),
+            metrics_port_opt: None,
+            node_descriptor: String::from(""),
+            session_token: String::new(),
+        });
+        let system =
+            System::new("acceptable_configuration_changes_go_to_the_actors_that_own_the_settings");
+        let addr: Addr<UiGateway> = subject.start();
+        let mut peer_actors = peer_actors_builder()
+            .neighborhood(neighborhood)
+            .accountant(accountant)
+            .proxy_client(proxy_client)
+            .build();
+        peer_actors.ui_gateway = UiGateway::make_subs_from(&addr);
+        addr.try_send(BindMessage { peer_actors }).unwrap();
+        let set = |setting: Setting, value: &str| {
+            addr.try_send(UiCarrierMessage {
+                client_id: 4,
+                context_id: None,
+                data: UiMessage::SetConfiguration(setting, value.to_string()),
+            })
+            .unwrap()
+        };
+
+        set(Setting::RatePack, "1|2|3|4");
+        set(Setting::NeighborhoodMode, "Consume-Only");
+        set(Setting::DnsServers, "1.1.1.1, 8.8.8.8");
+        set(
+            Setting::EarningWallet,
+            "0x0123456789012345678901234567890123456789",
+        );
+
+        System::current().stop();
+        system.run();
+        let neighborhood_recording = neighborhood_recording_arc.lock().unwrap();
+        assert_eq!(
+            neighborhood_recording.get_record::<SetRatePackMsg>(0),
+            &SetRatePackMsg {
+                client_id: 4,
+                context_id: 0,
+                rate_pack: RatePack {
+                    routing_byte_rate: 1,
+                    routing_service_rate: 2,
+                    exit_byte_rate: 3,
+                    exit_service_rate: 4,
+                },
+            }
+        );
+        assert_eq!(
+            neighborhood_recording.get_record::<SetNeighborhoodModeMsg>(1),
+            &SetNeighborhoodModeMsg {
+                client_id: 4,
+                context_id: 1,
+                mode: "consume-only".to_string(),
+            }
+        );
+        let proxy_client_recording = proxy_client_recording_arc.lock().unwrap();
+        assert_eq!(
+            proxy_client_recording.get_record::<SetDnsServersMsg>(0),
+            &SetDnsServersMsg {
+                client_id: 4,
+                context_id: 2,
+                dns_servers: vec![
+                    IpAddr::from_str("1.1.1.1").unwrap(),
+                    IpAddr::from_str("8.8.8.8").unwrap(),
+                ],
+            }
+        );
+        let accountant_recording = accountant_recording_arc.lock().unwrap();
+        assert_eq!(
+            accountant_recording.get_record::<SetEarningWalletMsg>(0),
+            &SetEarningWalletMsg {
+                client_id: 4,
+                context_id: 3,
+                wallet: Wallet::from_str("0x0123456789012345678901234567890123456789").unwrap(),
+            }
+        );
+    }
+
+    #[test]
+    fn unacceptable_configuration_changes_are_refused_at_once() {
+        let (ui_gateway, _, ui_gateway_recording_arc) = make_recorder();
+        let (neighborhood, _, neighborhood_recording_arc) = make_recorder();
+        let system = System::new("unacceptable_configuration_changes_are_refused_at_once");
+        let mut subject = UiGateway::new(&UiGatewayConfig {
+            ui_port: find_free_port(),
+            metrics_port_opt: None,
+            node_descriptor: String::from(""),
+            session_token: String::new(),
+        });
+        subject.subs = Some(UiGatewayOutSubs {
+            ui_message_sub: ui_gateway.start().recipient::<UiCarrierMessage>(),
+            neighborhood_set_rate_pack: neighborhood.start().recipient::<SetRatePackMsg>(),
+            ..Default::default()
+        });
+        let addr: Addr<UiGateway> = subject.start();
+
+        addr.try_send(UiCarrierMessage {
+            client_id: 5,
+            context_id: None,
+            data: UiMessage::SetConfiguration(Setting::RatePack, "1|2|3".to_string()),
+        })
+        .unwrap();
+        addr.try_send(UiCarrierMessage {
+            client_id: 5,
+            context_id: None,
+            data: UiMessage::SetConfiguration(Setting::LogLevel, "debug".to_string()),
+        })
+        .unwrap();
+
+        System::current().stop();
+        system.run();
+        let ui_gateway_recording = ui_gateway_recording_arc.lock().unwrap();
+        assert_eq!(
+            ui_gateway_recording.get_record::<UiCarrierMessage>(0),
+            &UiCarrierMessage {
+                client_id: 5,
+                context_id: Some(0),
+                data: UiMessage::SetConfigurationResponse(
+                    Setting::RatePack,
+                    Err(
+                        "Rate pack should be four whole numbers separated by '|', not '1|2|3'"
+                            .to_string()
+                    )
+                ),
+            }
+        );
+        // No logging subsystem is started in tests, so there's nothing to reconfigure
+        assert_eq!(
+            ui_gateway_recording.get_record::<UiCarrierMessage>(1),
+            &UiCarrierMessage {
+                client_id: 5,
+                context_id: Some(1),
+                data: UiMessage::SetConfigurationResponse(
+                    Setting::LogLevel,
+                    Err("The logging subsystem hasn't started".to_string())
+                ),
+            }
+        );
+        assert_eq!(ui_gateway_recording.len(), 2);
+        assert_eq!(neighborhood_recording_arc.lock().unwrap().len(), 0);
+    }
+
+    #[test]
+    fn subscriptions_are_passed_to_the_websocket_supervisor_and_acknowledged() {
+        let subscribe_parameters_arc = Arc::new(Mutex::new(vec![]));
+        let (ui_gateway, _, ui_gateway_recording_arc) = make_recorder();
+        let system =
+            System::new("subscriptions_are_passed_to_the_websocket_supervisor_and_acknowledged");
+        let mut subject = UiGateway::new(&UiGatewayConfig {
+            ui_port: find_free_port(),
+            metrics_port_opt: None,
+            node_descriptor: String::from(""),
+            session_token: String::new(),
+        });
+        subject.websocket_supervisor = Some(Box::new(
+            WebSocketSupervisorMock::new().subscribe_parameters(&subscribe_parameters_arc),
+        ));
+        subject.subs = Some(UiGatewayOutSubs {
+            ui_message_sub: ui_gateway.start().recipient::<UiCarrierMessage>(),
+            ..Default::default()
+        });
+        let addr: Addr<UiGateway> = subject.start();
+
+        addr.try_send(UiCarrierMessage {
+            client_id: 5,
+            context_id: None,
+            data: UiMessage::Subscribe(vec!["paymentSent".to_string()]),
+        })
+        .unwrap();
+
+        System::current().stop();
+        system.run();
+        assert_eq!(
+            *subscribe_parameters_arc.lock().unwrap(),
+            vec![(5, vec!["paymentSent".to_string()])]
+        );
+        let ui_gateway_recording = ui_gateway_recording_arc.lock().unwrap();
+        assert_eq!(
+            ui_gateway_recording.get_record::<UiCarrierMessage>(0),
+            &UiCarrierMessage {
+                client_id: 5,
+                context_id: Some(0),
+                data: UiMessage::SubscribeResponse(vec!["paymentSent".to_string()]),
+            }
+        );
+    }
+
+    #[test]
+    fn broadcast_events_go_to_subscribers_and_others_to_their_clients() {
+        let marshal_event_parameters_arc = Arc::new(Mutex::new(vec![]));
+        let converter = UiTrafficConverterMock::new()
+            .marshal_event_parameters(&marshal_event_parameters_arc)
+            .marshal_event_result(Ok("banned JSON".to_string()))
+            .marshal_event_result(Ok("unlocked JSON".to_string()));
+        let broadcast_parameters_arc = Arc::new(Mutex::new(vec![]));
+        let send_parameters_arc = Arc::new(Mutex::new(vec![]));
+        let system = System::new("broadcast_events_go_to_subscribers_and_others_to_their_clients");
+        let mut subject = UiGateway::new(&UiGatewayConfig {
+            ui_port: find_free_port(),
+            metrics_port_opt: None,
+            node_descriptor: String::from(""),
+            session_token: String::new(),
+        });
+        subject.converter = Box::new(converter);
+        subject.websocket_supervisor = Some(Box::new(
+            WebSocketSupervisorMock::new()
+                .broadcast_parameters(&broadcast_parameters_arc)
+                .send_parameters(&send_parameters_arc),
+        ));
+        let addr: Addr<UiGateway> = subject.start();
+
+        addr.try_send(UiCarrierMessage::event(UiEvent::WalletBanned(
+            "0x1234".to_string(),
+        )))
+        .unwrap();
+        addr.try_send(UiCarrierMessage {
+            client_id: 6,
+            context_id: None,
+            data: UiMessage::Event(UiEvent::WalletUnlocked("0x5678".to_string())),
+        })
+        .unwrap();
+
+        System::current().stop();
+        system.run();
+        assert_eq!(
+            *marshal_event_parameters_arc.lock().unwrap(),
+            vec![
+                UiEvent::WalletBanned("0x1234".to_string()),
+                UiEvent::WalletUnlocked("0x5678".to_string())
+            ]
+        );
+        assert_eq!(
+            *broadcast_parameters_arc.lock().unwrap(),
+            vec![("walletBanned".to_string(), "banned JSON".to_string())]
+        );
+        assert_eq!(
+            *send_parameters_arc.lock().unwrap(),
+            vec![(6, "unlocked JSON".to_string())]
         );
     }
 }
@@ -0,0 +1,122 @@
+// Copyright (c) 2017-2019, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
+use rand::RngCore;
+use std::fs;
+use std::fs::OpenOptions;
+use std::io;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+
+pub const UI_SESSION_TOKEN_FILE: &str = "ui-session-token";
+
+// A fresh token for every run of the Node; a UI proves it may give orders by reading it out of
+// the data directory, which only the Node's user should be able to do.
+pub fn generate_session_token() -> String {
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+pub fn write_session_token(data_directory: &Path, token: &str) -> io::Result<PathBuf> {
+    fs::create_dir_all(data_directory)?;
+    let path = data_directory.join(UI_SESSION_TOKEN_FILE);
+    let mut file = open_private(&path)?;
+    file.write_all(token.as_bytes())?;
+    Ok(path)
+}
+
+pub fn tokens_match(expected: &str, presented: &str) -> bool {
+    if expected.is_empty() || (expected.len() != presented.len()) {
+        return false;
+    }
+    expected
+        .bytes()
+        .zip(presented.bytes())
+        .fold(0u8, |acc, (a, b)| acc | (a ^ b))
+        == 0
+}
+
+#[cfg(unix)]
+fn open_private(path: &Path) -> io::Result<fs::File> {
+    use std::os::unix::fs::OpenOptionsExt;
+    use std::os::unix::fs::PermissionsExt;
+    let file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(path)?;
+    // mode() only applies to files that didn't exist already
+    fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+    Ok(file)
+}
+
+// The data directory on Windows is inside the user's profile, which other users can't read
+#[cfg(not(unix))]
+fn open_private(path: &Path) -> io::Result<fs::File> {
+    OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::ensure_node_home_directory_exists;
+
+    #[test]
+    fn session_tokens_are_long_random_hex_strings() {
+        let one = generate_session_token();
+        let another = generate_session_token();
+
+        assert_eq!(one.len(), 64);
+        assert!(one.chars().all(|c| c.is_ascii_hexdigit()));
+        assert_ne!(one, another);
+    }
+
+    #[test]
+    fn session_token_is_written_to_the_data_directory() {
+        let data_dir = ensure_node_home_directory_exists(
+            "session_token",
+            "session_token_is_written_to_the_data_directory",
+        );
+        fs::write(
+            data_dir.join(UI_SESSION_TOKEN_FILE),
+            "old and much longer token",
+        )
+        .unwrap();
+
+        let result = write_session_token(&data_dir, "new token").unwrap();
+
+        assert_eq!(result, data_dir.join(UI_SESSION_TOKEN_FILE));
+        assert_eq!(fs::read_to_string(&result).unwrap(), "new token");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn session_token_file_is_readable_only_by_its_owner() {
+        use std::os::unix::fs::PermissionsExt;
+        let data_dir = ensure_node_home_directory_exists(
+            "session_token",
+            "session_token_file_is_readable_only_by_its_owner",
+        );
+        let path = data_dir.join(UI_SESSION_TOKEN_FILE);
+        fs::write(&path, "world-readable").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o644)).unwrap();
+
+        write_session_token(&data_dir, "token").unwrap();
+
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+
+    #[test]
+    fn tokens_match_only_when_identical_and_not_empty() {
+        assert_eq!(tokens_match("abcd", "abcd"), true);
+        assert_eq!(tokens_match("abcd", "abce"), false);
+        assert_eq!(tokens_match("abcd", "abc"), false);
+        assert_eq!(tokens_match("", ""), false);
+    }
+}
@@ -1,13 +1,114 @@
 // Copyright (c) 2017-2018, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
 
-use crate::sub_lib::ui_gateway::UiMessage;
+use crate::sub_lib::ui_gateway::{Setting, UiEvent, UiMessage};
+use serde_derive::{Deserialize, Serialize};
+use serde_json::json;
+use serde_json::Value;
 
-#[allow(dead_code)]
 pub const BROADCAST: u64 = 0xFFFF_FFFF_FFFF_FFFF;
 
+pub const UI_PROTOCOL_VERSION: u32 = 1;
+
+pub const MALFORMED_REQUEST_ERROR: u64 = 1;
+pub const UNSUPPORTED_VERSION_ERROR: u64 = 2;
+pub const UNKNOWN_OPCODE_ERROR: u64 = 3;
+pub const BAD_PAYLOAD_ERROR: u64 = 4;
+pub const UNAUTHENTICATED_ERROR: u64 = 5;
+pub const REQUEST_REFUSED_ERROR: u64 = 6;
+
+// Events are sent with their topic as the opcode
+pub const UI_EVENT_TOPICS: [&str; 8] = [
+    "neighborAdded",
+    "neighborRemoved",
+    "routeFailed",
+    "walletBanned",
+    "paymentSent",
+    "paymentConfirmed",
+    "walletUnlocked",
+    "neighborhoodChanged",
+];
+
+// Each setting UIs can change has its own opcode, and takes its new value from one payload field
+pub const SETTING_OPCODES: [(Setting, &str, &str); 5] = [
+    (Setting::RatePack, "setRatePack", "ratePack"),
+    (
+        Setting::NeighborhoodMode,
+        "setNeighborhoodMode",
+        "neighborhoodMode",
+    ),
+    (Setting::LogLevel, "setLogLevel", "logLevel"),
+    (Setting::DnsServers, "setDnsServers", "dnsServers"),
+    (Setting::EarningWallet, "setEarningWallet", "earningWallet"),
+];
+
+// What versioned requests and responses look like on the wire. A response echoes the opcode and
+// context ID of its request, and carries either a payload or an error.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UiEnvelope {
+    pub version: u32,
+    pub opcode: String,
+    #[serde(default)]
+    pub context_id: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub payload: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<UiError>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct UiError {
+    pub code: u64,
+    pub message: String,
+}
+
+impl UiError {
+    pub fn new(code: u64, message: &str) -> UiError {
+        UiError {
+            code,
+            message: message.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum UiRequest {
+    // A bare serialized UiMessage, from a UI that predates the envelope; it gets bare answers
+    Legacy(UiMessage),
+    Versioned {
+        context_id: Option<u64>,
+        message: UiMessage,
+    },
+}
+
+impl UiRequest {
+    pub fn message(&self) -> &UiMessage {
+        match self {
+            UiRequest::Legacy(message) => message,
+            UiRequest::Versioned { message, .. } => message,
+        }
+    }
+}
+
+// Whatever could be salvaged from a request that couldn't be understood, so that the answer can
+// be matched up with it
+#[derive(Debug, PartialEq)]
+pub struct UiRequestError {
+    pub opcode: String,
+    pub context_id: Option<u64>,
+    pub error: UiError,
+}
+
 pub trait UiTrafficConverter: Send {
     fn marshal(&self, ui_message: UiMessage) -> Result<String, String>;
-    fn unmarshal(&self, json: &str) -> Result<UiMessage, String>;
+    fn marshal_response(
+        &self,
+        context_id: Option<u64>,
+        ui_message: UiMessage,
+    ) -> Result<String, String>;
+    fn marshal_error(&self, request_error: UiRequestError) -> Result<String, String>;
+    fn marshal_event(&self, event: UiEvent) -> Result<String, String>;
+    fn unmarshal(&self, json: &str) -> Result<UiRequest, UiRequestError>;
 }
 
 #[derive(Default)]
@@ -18,8 +119,65 @@ impl UiTrafficConverter for UiTrafficConverterReal {
         serde_json::to_string(&ui_message).map_err(|e| e.to_string())
     }
 
-    fn unmarshal(&self, _json: &str) -> Result<UiMessage, String> {
-        serde_json::from_str(_json).map_err(|e| e.to_string())
+    fn marshal_response(
+        &self,
+        context_id: Option<u64>,
+        ui_message: UiMessage,
+    ) -> Result<String, String> {
+        let opcode = opcode_for(&ui_message).to_string();
+        let (payload, error) = match response_payload(ui_message)? {
+            Ok(payload) => (Some(payload), None),
+            Err(error) => (None, Some(error)),
+        };
+        serde_json::to_string(&UiEnvelope {
+            version: UI_PROTOCOL_VERSION,
+            opcode,
+            context_id,
+            payload,
+            error,
+        })
+        .map_err(|e| e.to_string())
+    }
+
+    fn marshal_error(&self, request_error: UiRequestError) -> Result<String, String> {
+        serde_json::to_string(&UiEnvelope {
+            version: UI_PROTOCOL_VERSION,
+            opcode: request_error.opcode,
+            context_id: request_error.context_id,
+            payload: None,
+            error: Some(request_error.error),
+        })
+        .map_err(|e| e.to_string())
+    }
+
+    fn marshal_event(&self, event: UiEvent) -> Result<String, String> {
+        serde_json::to_string(&UiEnvelope {
+            version: UI_PROTOCOL_VERSION,
+            opcode: topic_for(&event).to_string(),
+            context_id: None,
+            payload: Some(event_payload(event)),
+            error: None,
+        })
+        .map_err(|e| e.to_string())
+    }
+
+    fn unmarshal(&self, json: &str) -> Result<UiRequest, UiRequestError> {
+        let value: Value = serde_json::from_str(json).map_err(|e| UiRequestError {
+            opcode: String::new(),
+            context_id: None,
+            error: UiError::new(MALFORMED_REQUEST_ERROR, &e.to_string()),
+        })?;
+        if value.get("opcode").is_some() {
+            Self::unmarshal_versioned(value)
+        } else {
+            serde_json::from_value(value)
+                .map(UiRequest::Legacy)
+                .map_err(|e| UiRequestError {
+                    opcode: String::new(),
+                    context_id: None,
+                    error: UiError::new(MALFORMED_REQUEST_ERROR, &e.to_string()),
+                })
+        }
     }
 }
 
@@ -27,11 +185,222 @@ impl UiTrafficConverterReal {
     pub fn new() -> Self {
         Self {}
     }
+
+    fn unmarshal_versioned(value: Value) -> Result<UiRequest, UiRequestError> {
+        let opcode = value["opcode"].as_str().unwrap_or("").to_string();
+        let context_id = value["contextId"].as_u64();
+        let make_error = |code: u64, message: &str| UiRequestError {
+            opcode: opcode.clone(),
+            context_id,
+            error: UiError::new(code, message),
+        };
+        let envelope: UiEnvelope = serde_json::from_value(value.clone())
+            .map_err(|e| make_error(MALFORMED_REQUEST_ERROR, &e.to_string()))?;
+        if envelope.version != UI_PROTOCOL_VERSION {
+            return Err(make_error(
+                UNSUPPORTED_VERSION_ERROR,
+                &format!(
+                    "UI protocol version {} is not supported; use version {}",
+                    envelope.version, UI_PROTOCOL_VERSION
+                ),
+            ));
+        }
+        let message = request_for_opcode(&envelope.opcode, &envelope.payload)
+            .map_err(|error| make_error(error.code, &error.message))?;
+        Ok(UiRequest::Versioned {
+            context_id: envelope.context_id,
+            message,
+        })
+    }
+}
+
+// Requests and their responses share an opcode
+pub fn opcode_for(ui_message: &UiMessage) -> &'static str {
+    match ui_message {
+        UiMessage::GetFinancialStatisticsMessage | UiMessage::FinancialStatisticsResponse(_) => {
+            "financialStatistics"
+        }
+        UiMessage::SetGasPrice(_) | UiMessage::SetGasPriceResponse(_) => "setGasPrice",
+        UiMessage::SetWalletPassword(_) | UiMessage::SetWalletPasswordResponse(_) => {
+            "setWalletPassword"
+        }
+        UiMessage::GetNodeDescriptor | UiMessage::NodeDescriptor(_) => "nodeDescriptor",
+        UiMessage::NeighborhoodDotGraphRequest | UiMessage::NeighborhoodDotGraphResponse(_) => {
+            "neighborhoodDotGraph"
+        }
+        UiMessage::NeighborhoodRequest | UiMessage::NeighborhoodResponse(_) => "neighborhood",
+        UiMessage::StreamTracesRequest | UiMessage::StreamTracesResponse(_) => "streamTraces",
+        UiMessage::DnsCacheStatsRequest | UiMessage::DnsCacheStatsResponse(_) => "dnsCache",
+        UiMessage::ShutdownMessage => "shutdown",
+        UiMessage::Subscribe(_) | UiMessage::SubscribeResponse(_) => "subscribe",
+        UiMessage::Event(event) => topic_for(event),
+        UiMessage::SetConfiguration(setting, _)
+        | UiMessage::SetConfigurationResponse(setting, _) => {
+            let (_, opcode, _) = SETTING_OPCODES
+                .iter()
+                .find(|(candidate, _, _)| candidate == setting)
+                .expect("Setting has no opcode");
+            opcode
+        }
+    }
+}
+
+pub fn topic_for(event: &UiEvent) -> &'static str {
+    match event {
+        UiEvent::NeighborAdded(_) => UI_EVENT_TOPICS[0],
+        UiEvent::NeighborRemoved(_) => UI_EVENT_TOPICS[1],
+        UiEvent::RouteFailed(_) => UI_EVENT_TOPICS[2],
+        UiEvent::WalletBanned(_) => UI_EVENT_TOPICS[3],
+        UiEvent::PaymentSent { .. } => UI_EVENT_TOPICS[4],
+        UiEvent::PaymentConfirmed { .. } => UI_EVENT_TOPICS[5],
+        UiEvent::WalletUnlocked(_) => UI_EVENT_TOPICS[6],
+        UiEvent::NeighborhoodChanged { .. } => UI_EVENT_TOPICS[7],
+    }
+}
+
+fn request_for_opcode(opcode: &str, payload: &Option<Value>) -> Result<UiMessage, UiError> {
+    match opcode {
+        "financialStatistics" => Ok(UiMessage::GetFinancialStatisticsMessage),
+        "setGasPrice" => {
+            string_from_payload(opcode, payload, "gasPrice").map(UiMessage::SetGasPrice)
+        }
+        "setWalletPassword" => {
+            string_from_payload(opcode, payload, "password").map(UiMessage::SetWalletPassword)
+        }
+        "nodeDescriptor" => Ok(UiMessage::GetNodeDescriptor),
+        "neighborhoodDotGraph" => Ok(UiMessage::NeighborhoodDotGraphRequest),
+        "neighborhood" => Ok(UiMessage::NeighborhoodRequest),
+        "streamTraces" => Ok(UiMessage::StreamTracesRequest),
+        "dnsCache" => Ok(UiMessage::DnsCacheStatsRequest),
+        "shutdown" => Ok(UiMessage::ShutdownMessage),
+        "subscribe" => topics_from_payload(opcode, payload).map(UiMessage::Subscribe),
+        _ => match SETTING_OPCODES
+            .iter()
+            .find(|(_, candidate, _)| *candidate == opcode)
+        {
+            Some((setting, _, field)) => string_from_payload(opcode, payload, field)
+                .map(|value| UiMessage::SetConfiguration(*setting, value)),
+            None => Err(UiError::new(
+                UNKNOWN_OPCODE_ERROR,
+                &format!("Unknown opcode '{}'", opcode),
+            )),
+        },
+    }
+}
+
+fn string_from_payload(
+    opcode: &str,
+    payload: &Option<Value>,
+    field: &str,
+) -> Result<String, UiError> {
+    match payload.as_ref().and_then(|payload| payload[field].as_str()) {
+        Some(value) => Ok(value.to_string()),
+        None => Err(UiError::new(
+            BAD_PAYLOAD_ERROR,
+            &format!(
+                "Opcode '{}' needs a string '{}' in its payload",
+                opcode, field
+            ),
+        )),
+    }
+}
+
+fn topics_from_payload(opcode: &str, payload: &Option<Value>) -> Result<Vec<String>, UiError> {
+    let bad_payload = |message: String| UiError::new(BAD_PAYLOAD_ERROR, &message);
+    let topics = match payload
+        .as_ref()
+        .and_then(|payload| payload["topics"].as_array())
+    {
+        Some(topics) => topics,
+        None => {
+            return Err(bad_payload(format!(
+                "Opcode '{}' needs an array 'topics' in its payload",
+                opcode
+            )))
+        }
+    };
+    topics
+        .iter()
+        .map(|topic| match topic.as_str() {
+            Some(topic) if UI_EVENT_TOPICS.contains(&topic) => Ok(topic.to_string()),
+            _ => Err(bad_payload(format!("Unknown topic {}", topic))),
+        })
+        .collect()
+}
+
+// The outer Err is for messages that aren't responses at all; the inner one is for refusals
+fn response_payload(ui_message: UiMessage) -> Result<Result<Value, UiError>, String> {
+    match ui_message {
+        UiMessage::FinancialStatisticsResponse(statistics) => serde_json::to_value(statistics)
+            .map(Ok)
+            .map_err(|e| e.to_string()),
+        UiMessage::SetGasPriceResponse(true) | UiMessage::SetWalletPasswordResponse(true) => {
+            Ok(Ok(json!({})))
+        }
+        UiMessage::SetGasPriceResponse(false) => Ok(Err(UiError::new(
+            REQUEST_REFUSED_ERROR,
+            "The gas price was not accepted",
+        ))),
+        UiMessage::SetWalletPasswordResponse(false) => Ok(Err(UiError::new(
+            REQUEST_REFUSED_ERROR,
+            "The wallet password was not accepted",
+        ))),
+        UiMessage::NodeDescriptor(node_descriptor) => {
+            Ok(Ok(json!({ "nodeDescriptor": node_descriptor })))
+        }
+        UiMessage::NeighborhoodDotGraphResponse(dot_graph) => {
+            Ok(Ok(json!({ "dotGraph": dot_graph })))
+        }
+        UiMessage::NeighborhoodResponse(view) => serde_json::to_value(view)
+            .map(Ok)
+            .map_err(|e| e.to_string()),
+        UiMessage::StreamTracesResponse(traces) => serde_json::to_value(traces)
+            .map(|traces| Ok(json!({ "traces": traces })))
+            .map_err(|e| e.to_string()),
+        UiMessage::DnsCacheStatsResponse(stats) => serde_json::to_value(stats)
+            .map(Ok)
+            .map_err(|e| e.to_string()),
+        UiMessage::SubscribeResponse(topics) => Ok(Ok(json!({ "topics": topics }))),
+        UiMessage::SetConfigurationResponse(_, Ok(())) => Ok(Ok(json!({}))),
+        UiMessage::SetConfigurationResponse(_, Err(message)) => {
+            Ok(Err(UiError::new(REQUEST_REFUSED_ERROR, &message)))
+        }
+        other => Err(format!("{:?} is not a response", other)),
+    }
+}
+
+fn event_payload(event: UiEvent) -> Value {
+    match event {
+        UiEvent::NeighborAdded(public_key) | UiEvent::NeighborRemoved(public_key) => {
+            json!({ "publicKey": public_key })
+        }
+        UiEvent::RouteFailed(reason) => json!({ "reason": reason }),
+        UiEvent::WalletBanned(wallet) | UiEvent::WalletUnlocked(wallet) => {
+            json!({ "wallet": wallet })
+        }
+        UiEvent::PaymentSent {
+            to,
+            amount,
+            transaction,
+        } => json!({ "to": to, "amount": amount, "transaction": transaction }),
+        UiEvent::PaymentConfirmed {
+            from,
+            amount,
+            block_number,
+        } => json!({ "from": from, "amount": amount, "blockNumber": block_number }),
+        UiEvent::NeighborhoodChanged { updated, removed } => {
+            json!({ "updated": updated, "removed": removed })
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::sub_lib::accountant::FinancialStatisticsMessage;
+    use crate::sub_lib::neighborhood::{NeighborhoodView, NodeRecordView};
+    use crate::sub_lib::proxy_client::DnsCacheStats;
+    use crate::sub_lib::stream_trace::{SpanView, StreamTraceView};
 
     #[test]
     fn a_shutdown_message_is_properly_marshalled_and_unmarshalled() {
@@ -40,7 +409,10 @@ mod tests {
         let marshalled = serde_json::to_string(&UiMessage::ShutdownMessage).unwrap();
         let unmarshalled = subject.unmarshal(&marshalled);
 
-        assert_eq!(unmarshalled, Ok(UiMessage::ShutdownMessage));
+        assert_eq!(
+            unmarshalled,
+            Ok(UiRequest::Legacy(UiMessage::ShutdownMessage))
+        );
     }
 
     #[test]
@@ -50,6 +422,471 @@ mod tests {
         let marshaled = serde_json::to_string(&UiMessage::NeighborhoodDotGraphRequest).unwrap();
         let unmarshaled = subject.unmarshal(&marshaled);
 
-        assert_eq!(unmarshaled, Ok(UiMessage::NeighborhoodDotGraphRequest));
+        assert_eq!(
+            unmarshaled,
+            Ok(UiRequest::Legacy(UiMessage::NeighborhoodDotGraphRequest))
+        );
+    }
+
+    #[test]
+    fn versioned_requests_are_unmarshalled_by_opcode() {
+        let subject = UiTrafficConverterReal::new();
+        let expectations = vec![
+            (
+                r#""opcode":"financialStatistics""#,
+                UiMessage::GetFinancialStatisticsMessage,
+            ),
+            (
+                r#""opcode":"setGasPrice","payload":{"gasPrice":"11"}"#,
+                UiMessage::SetGasPrice("11".to_string()),
+            ),
+            (
+                r#""opcode":"setWalletPassword","payload":{"password":"booga"}"#,
+                UiMessage::SetWalletPassword("booga".to_string()),
+            ),
+            (r#""opcode":"nodeDescriptor""#, UiMessage::GetNodeDescriptor),
+            (
+                r#""opcode":"neighborhoodDotGraph""#,
+                UiMessage::NeighborhoodDotGraphRequest,
+            ),
+            (r#""opcode":"neighborhood""#, UiMessage::NeighborhoodRequest),
+            (r#""opcode":"streamTraces""#, UiMessage::StreamTracesRequest),
+            (r#""opcode":"dnsCache""#, UiMessage::DnsCacheStatsRequest),
+            (r#""opcode":"shutdown""#, UiMessage::ShutdownMessage),
+            (
+                r#""opcode":"subscribe","payload":{"topics":["paymentSent","walletBanned"]}"#,
+                UiMessage::Subscribe(vec!["paymentSent".to_string(), "walletBanned".to_string()]),
+            ),
+            (
+                r#""opcode":"setRatePack","payload":{"ratePack":"1|2|3|4"}"#,
+                UiMessage::SetConfiguration(Setting::RatePack, "1|2|3|4".to_string()),
+            ),
+            (
+                r#""opcode":"setNeighborhoodMode","payload":{"neighborhoodMode":"consume-only"}"#,
+                UiMessage::SetConfiguration(Setting::NeighborhoodMode, "consume-only".to_string()),
+            ),
+            (
+                r#""opcode":"setLogLevel","payload":{"logLevel":"debug"}"#,
+                UiMessage::SetConfiguration(Setting::LogLevel, "debug".to_string()),
+            ),
+            (
+                r#""opcode":"setDnsServers","payload":{"dnsServers":"1.1.1.1,8.8.8.8"}"#,
+                UiMessage::SetConfiguration(Setting::DnsServers, "1.1.1.1,8.8.8.8".to_string()),
+            ),
+            (
+                r#""opcode":"setEarningWallet","payload":{"earningWallet":"0x1234"}"#,
+                UiMessage::SetConfiguration(Setting::EarningWallet, "0x1234".to_string()),
+            ),
+        ];
+
+        expectations
+            .into_iter()
+            .enumerate()
+            .for_each(|(context_id, (fields, message))| {
+                let json = format!(r#"{{"version":1,"contextId":{},{}}}"#, context_id, fields);
+
+                let result = subject.unmarshal(&json);
+
+                assert_eq!(
+                    result,
+                    Ok(UiRequest::Versioned {
+                        context_id: Some(context_id as u64),
+                        message
+                    }),
+                    "{}",
+                    json
+                );
+            });
+    }
+
+    #[test]
+    fn context_id_is_optional() {
+        let subject = UiTrafficConverterReal::new();
+
+        let result = subject.unmarshal(r#"{"version":1,"opcode":"nodeDescriptor"}"#);
+
+        assert_eq!(
+            result,
+            Ok(UiRequest::Versioned {
+                context_id: None,
+                message: UiMessage::GetNodeDescriptor
+            })
+        );
+    }
+
+    #[test]
+    fn unknown_opcodes_are_reported_with_the_context_id() {
+        let subject = UiTrafficConverterReal::new();
+
+        let result = subject.unmarshal(r#"{"version":1,"opcode":"booga","contextId":42}"#);
+
+        assert_eq!(
+            result,
+            Err(UiRequestError {
+                opcode: "booga".to_string(),
+                context_id: Some(42),
+                error: UiError::new(UNKNOWN_OPCODE_ERROR, "Unknown opcode 'booga'"),
+            })
+        );
+    }
+
+    #[test]
+    fn unsupported_versions_are_reported() {
+        let subject = UiTrafficConverterReal::new();
+
+        let result = subject.unmarshal(r#"{"version":2,"opcode":"shutdown","contextId":3}"#);
+
+        assert_eq!(
+            result,
+            Err(UiRequestError {
+                opcode: "shutdown".to_string(),
+                context_id: Some(3),
+                error: UiError::new(
+                    UNSUPPORTED_VERSION_ERROR,
+                    "UI protocol version 2 is not supported; use version 1"
+                ),
+            })
+        );
+    }
+
+    #[test]
+    fn missing_payloads_are_reported() {
+        let subject = UiTrafficConverterReal::new();
+
+        let result = subject.unmarshal(
+            r#"{"version":1,"opcode":"setGasPrice","contextId":4,"payload":{"gasPrice":11}}"#,
+        );
+
+        assert_eq!(
+            result,
+            Err(UiRequestError {
+                opcode: "setGasPrice".to_string(),
+                context_id: Some(4),
+                error: UiError::new(
+                    BAD_PAYLOAD_ERROR,
+                    "Opcode 'setGasPrice' needs a string 'gasPrice' in its payload"
+                ),
+            })
+        );
+    }
+
+    #[test]
+    fn malformed_requests_are_reported() {
+        let subject = UiTrafficConverterReal::new();
+
+        let not_json = subject.unmarshal("booga").err().unwrap();
+        let not_a_message = subject.unmarshal(r#""Booga""#).err().unwrap();
+        let bad_envelope = subject
+            .unmarshal(r#"{"opcode":"shutdown","contextId":5}"#)
+            .err()
+            .unwrap();
+
+        assert_eq!(not_json.error.code, MALFORMED_REQUEST_ERROR);
+        assert_eq!(not_a_message.error.code, MALFORMED_REQUEST_ERROR);
+        assert_eq!(bad_envelope.error.code, MALFORMED_REQUEST_ERROR);
+        assert_eq!(bad_envelope.opcode, "shutdown".to_string());
+        assert_eq!(bad_envelope.context_id, Some(5));
+    }
+
+    #[test]
+    fn responses_are_marshalled_into_envelopes() {
+        let subject = UiTrafficConverterReal::new();
+
+        let statistics = subject
+            .marshal_response(
+                Some(6),
+                UiMessage::FinancialStatisticsResponse(FinancialStatisticsMessage {
+                    pending_credit: 100,
+                    pending_debt: 200,
+                }),
+            )
+            .unwrap();
+        let descriptor = subject
+            .marshal_response(None, UiMessage::NodeDescriptor("descriptor".to_string()))
+            .unwrap();
+        let gas_price = subject
+            .marshal_response(Some(7), UiMessage::SetGasPriceResponse(true))
+            .unwrap();
+
+        assert_eq!(
+            statistics,
+            r#"{"version":1,"opcode":"financialStatistics","contextId":6,"payload":{"pendingCredit":100,"pendingDebt":200}}"#
+        );
+        assert_eq!(
+            descriptor,
+            r#"{"version":1,"opcode":"nodeDescriptor","contextId":null,"payload":{"nodeDescriptor":"descriptor"}}"#
+        );
+        assert_eq!(
+            gas_price,
+            r#"{"version":1,"opcode":"setGasPrice","contextId":7,"payload":{}}"#
+        );
+    }
+
+    #[test]
+    fn stream_traces_are_marshalled_in_camel_case() {
+        let subject = UiTrafficConverterReal::new();
+
+        let result = subject
+            .marshal_response(
+                Some(9),
+                UiMessage::StreamTracesResponse(vec![StreamTraceView {
+                    trace_id: "00000000000004d2".to_string(),
+                    stream_key: "AQIDBA".to_string(),
+                    started_at_millis: 1000,
+                    closed: true,
+                    spans: vec![SpanView {
+                        name: "route_query".to_string(),
+                        start_micros: 10,
+                        duration_micros: 250,
+                    }],
+                }]),
+            )
+            .unwrap();
+
+        assert_eq!(
+            result,
+            r#"{"version":1,"opcode":"streamTraces","contextId":9,"payload":{"traces":[{"closed":true,"spans":[{"durationMicros":250,"name":"route_query","startMicros":10}],"startedAtMillis":1000,"streamKey":"AQIDBA","traceId":"00000000000004d2"}]}}"#
+        );
+    }
+
+    #[test]
+    fn dns_cache_stats_are_marshalled_in_camel_case() {
+        let subject = UiTrafficConverterReal::new();
+
+        let result = subject
+            .marshal_response(
+                Some(4),
+                UiMessage::DnsCacheStatsResponse(DnsCacheStats {
+                    hits: 12,
+                    negative_hits: 3,
+                    misses: 5,
+                    entries: 4,
+                }),
+            )
+            .unwrap();
+
+        assert_eq!(
+            result,
+            r#"{"version":1,"opcode":"dnsCache","contextId":4,"payload":{"entries":4,"hits":12,"misses":5,"negativeHits":3}}"#
+        );
+    }
+
+    #[test]
+    fn refusals_are_marshalled_as_errors() {
+        let subject = UiTrafficConverterReal::new();
+
+        let result = subject
+            .marshal_response(Some(8), UiMessage::SetWalletPasswordResponse(false))
+            .unwrap();
+
+        assert_eq!(
+            result,
+            r#"{"version":1,"opcode":"setWalletPassword","contextId":8,"error":{"code":6,"message":"The wallet password was not accepted"}}"#
+        );
+    }
+
+    #[test]
+    fn configuration_changes_are_acknowledged_or_refused() {
+        let subject = UiTrafficConverterReal::new();
+
+        let accepted = subject
+            .marshal_response(
+                Some(8),
+                UiMessage::SetConfigurationResponse(Setting::LogLevel, Ok(())),
+            )
+            .unwrap();
+        let refused = subject
+            .marshal_response(
+                Some(9),
+                UiMessage::SetConfigurationResponse(
+                    Setting::DnsServers,
+                    Err("Invalid DNS server IP address 'booga'".to_string()),
+                ),
+            )
+            .unwrap();
+
+        assert_eq!(
+            accepted,
+            r#"{"version":1,"opcode":"setLogLevel","contextId":8,"payload":{}}"#
+        );
+        assert_eq!(
+            refused,
+            r#"{"version":1,"opcode":"setDnsServers","contextId":9,"error":{"code":6,"message":"Invalid DNS server IP address 'booga'"}}"#
+        );
+    }
+
+    #[test]
+    fn requests_cannot_be_marshalled_as_responses() {
+        let subject = UiTrafficConverterReal::new();
+
+        let result = subject.marshal_response(Some(9), UiMessage::ShutdownMessage);
+
+        assert_eq!(result, Err("ShutdownMessage is not a response".to_string()));
+    }
+
+    #[test]
+    fn request_errors_are_marshalled_into_envelopes() {
+        let subject = UiTrafficConverterReal::new();
+
+        let result = subject
+            .marshal_error(UiRequestError {
+                opcode: "booga".to_string(),
+                context_id: Some(10),
+                error: UiError::new(UNKNOWN_OPCODE_ERROR, "Unknown opcode 'booga'"),
+            })
+            .unwrap();
+
+        assert_eq!(
+            result,
+            r#"{"version":1,"opcode":"booga","contextId":10,"error":{"code":3,"message":"Unknown opcode 'booga'"}}"#
+        );
+    }
+
+    #[test]
+    fn subscriptions_to_unknown_topics_are_reported() {
+        let subject = UiTrafficConverterReal::new();
+
+        let result = subject.unmarshal(
+            r#"{"version":1,"opcode":"subscribe","contextId":11,"payload":{"topics":["paymentSent","booga"]}}"#,
+        );
+
+        assert_eq!(
+            result,
+            Err(UiRequestError {
+                opcode: "subscribe".to_string(),
+                context_id: Some(11),
+                error: UiError::new(BAD_PAYLOAD_ERROR, r#"Unknown topic "booga""#),
+            })
+        );
+    }
+
+    #[test]
+    fn subscription_responses_list_the_topics() {
+        let subject = UiTrafficConverterReal::new();
+
+        let result = subject
+            .marshal_response(
+                Some(12),
+                UiMessage::SubscribeResponse(vec!["routeFailed".to_string()]),
+            )
+            .unwrap();
+
+        assert_eq!(
+            result,
+            r#"{"version":1,"opcode":"subscribe","contextId":12,"payload":{"topics":["routeFailed"]}}"#
+        );
+    }
+
+    #[test]
+    fn events_are_marshalled_into_envelopes_named_for_their_topics() {
+        let subject = UiTrafficConverterReal::new();
+        let expectations = vec![
+            (
+                UiEvent::NeighborAdded("AQIDBA".to_string()),
+                r#""opcode":"neighborAdded","contextId":null,"payload":{"publicKey":"AQIDBA"}"#,
+            ),
+            (
+                UiEvent::NeighborRemoved("AQIDBA".to_string()),
+                r#""opcode":"neighborRemoved","contextId":null,"payload":{"publicKey":"AQIDBA"}"#,
+            ),
+            (
+                UiEvent::RouteFailed("No route".to_string()),
+                r#""opcode":"routeFailed","contextId":null,"payload":{"reason":"No route"}"#,
+            ),
+            (
+                UiEvent::WalletBanned("0x1234".to_string()),
+                r#""opcode":"walletBanned","contextId":null,"payload":{"wallet":"0x1234"}"#,
+            ),
+            (
+                UiEvent::PaymentSent {
+                    to: "0x1234".to_string(),
+                    amount: 100,
+                    transaction: "0xabcd".to_string(),
+                },
+                r#""opcode":"paymentSent","contextId":null,"payload":{"amount":100,"to":"0x1234","transaction":"0xabcd"}"#,
+            ),
+            (
+                UiEvent::PaymentConfirmed {
+                    from: "0x1234".to_string(),
+                    amount: 200,
+                    block_number: 300,
+                },
+                r#""opcode":"paymentConfirmed","contextId":null,"payload":{"amount":200,"blockNumber":300,"from":"0x1234"}"#,
+            ),
+            (
+                UiEvent::WalletUnlocked("0x5678".to_string()),
+                r#""opcode":"walletUnlocked","contextId":null,"payload":{"wallet":"0x5678"}"#,
+            ),
+        ];
+
+        expectations.into_iter().for_each(|(event, fields)| {
+            let result = subject.marshal_event(event).unwrap();
+
+            assert_eq!(result, format!(r#"{{"version":1,{}}}"#, fields));
+        });
+    }
+
+    fn make_node_record_view() -> NodeRecordView {
+        NodeRecordView {
+            public_key: "AQIDBA".to_string(),
+            version: 2,
+            routing_byte_rate: 3,
+            routing_service_rate: 4,
+            exit_byte_rate: 5,
+            exit_service_rate: 6,
+            accepts_connections: true,
+            routes_data: false,
+            desirable: true,
+            node_addr: Some("1.2.3.4:1234".to_string()),
+            half_neighbors: vec!["BQYHCA".to_string()],
+            full_neighbors: vec![],
+        }
+    }
+
+    const NODE_RECORD_VIEW_JSON: &str = r#"{"acceptsConnections":true,"desirable":true,"exitByteRate":5,"exitServiceRate":6,"fullNeighbors":[],"halfNeighbors":["BQYHCA"],"nodeAddr":"1.2.3.4:1234","publicKey":"AQIDBA","routesData":false,"routingByteRate":3,"routingServiceRate":4,"version":2}"#;
+
+    #[test]
+    fn neighborhood_views_are_marshalled_as_structured_json() {
+        let subject = UiTrafficConverterReal::new();
+
+        let result = subject
+            .marshal_response(
+                Some(13),
+                UiMessage::NeighborhoodResponse(NeighborhoodView {
+                    root_key: "AQIDBA".to_string(),
+                    earning_wallet: "0x1234".to_string(),
+                    consuming_wallet: None,
+                    nodes: vec![make_node_record_view()],
+                }),
+            )
+            .unwrap();
+
+        assert_eq!(
+            result,
+            format!(
+                r#"{{"version":1,"opcode":"neighborhood","contextId":13,"payload":{{"consumingWallet":null,"earningWallet":"0x1234","nodes":[{}],"rootKey":"AQIDBA"}}}}"#,
+                NODE_RECORD_VIEW_JSON
+            )
+        );
+    }
+
+    #[test]
+    fn neighborhood_changes_are_marshalled_as_events() {
+        let subject = UiTrafficConverterReal::new();
+
+        let result = subject
+            .marshal_event(UiEvent::NeighborhoodChanged {
+                updated: vec![make_node_record_view()],
+                removed: vec!["CQoLDA".to_string()],
+            })
+            .unwrap();
+
+        assert_eq!(
+            result,
+            format!(
+                r#"{{"version":1,"opcode":"neighborhoodChanged","contextId":null,"payload":{{"removed":["CQoLDA"],"updated":[{}]}}}}"#,
+                NODE_RECORD_VIEW_JSON
+            )
+        );
     }
 }
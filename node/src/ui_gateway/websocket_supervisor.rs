@@ -1,7 +1,12 @@
 // Copyright (c) 2017-2018, Substratum LLC (https://substratum.net) and/or its affiliates. All rights reserved.
 use crate::sub_lib::logger::Logger;
-use crate::sub_lib::ui_gateway::FromUiMessage;
+use crate::sub_lib::privacy;
+use crate::sub_lib::ui_gateway::{FromUiMessage, UiDisconnectMessage};
 use crate::sub_lib::utils::localhost;
+use crate::ui_gateway::session_token::tokens_match;
+use crate::ui_gateway::ui_traffic_converter::UNAUTHENTICATED_ERROR;
+use crate::ui_gateway::ui_traffic_converter::{opcode_for, UiError, UiRequest, UiRequestError};
+use crate::ui_gateway::ui_traffic_converter::{UiTrafficConverter, UiTrafficConverterReal};
 use actix::Recipient;
 use bytes::BytesMut;
 use futures::future::FutureResult;
@@ -12,7 +17,7 @@ use futures::Future;
 use futures::Sink;
 use futures::Stream;
 use std::any::Any;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::net::SocketAddr;
 use std::sync::Arc;
 use std::sync::Mutex;
@@ -51,18 +56,24 @@ impl ClientWrapper for ClientWrapperReal {
 
 pub trait WebSocketSupervisor: Send {
     fn send(&self, client_id: u64, message_json: &str);
+    fn subscribe(&self, client_id: u64, topics: Vec<String>);
+    fn broadcast(&self, topic: &str, message_json: &str);
 }
 
 pub struct WebSocketSupervisorReal {
-    #[allow(dead_code)]
     inner: Arc<Mutex<WebSocketSupervisorInner>>,
+    logger: Logger,
 }
 
 struct WebSocketSupervisorInner {
     next_client_id: u64,
     from_ui_message: Recipient<FromUiMessage>,
+    ui_disconnect: Recipient<UiDisconnectMessage>,
+    session_token: String,
     client_id_by_socket_addr: HashMap<SocketAddr, u64>,
     client_by_id: HashMap<u64, Box<dyn ClientWrapper>>,
+    authenticated_client_ids: HashSet<u64>,
+    subscriptions: HashMap<u64, HashSet<String>>,
 }
 
 impl WebSocketSupervisor for WebSocketSupervisorReal {
@@ -76,15 +87,59 @@ impl WebSocketSupervisor for WebSocketSupervisorReal {
             None => panic!("Tried to send to a nonexistent client"),
         };
     }
+
+    // A new subscription replaces the old one, so a UI unsubscribes by naming fewer topics
+    fn subscribe(&self, client_id: u64, topics: Vec<String>) {
+        let mut locked_inner = self.inner.lock().expect("WebSocketSupervisor is poisoned");
+        locked_inner
+            .subscriptions
+            .insert(client_id, topics.into_iter().collect());
+    }
+
+    // One UI that can't be reached shouldn't keep an event from the others
+    fn broadcast(&self, topic: &str, message_json: &str) {
+        let mut locked_inner = self.inner.lock().expect("WebSocketSupervisor is poisoned");
+        let subscriber_ids = locked_inner
+            .subscriptions
+            .iter()
+            .filter(|(_, topics)| topics.contains(topic))
+            .map(|(client_id, _)| *client_id)
+            .collect::<Vec<u64>>();
+        subscriber_ids.into_iter().for_each(|client_id| {
+            if let Some(client) = locked_inner.client_by_id.get_mut(&client_id) {
+                if let Err(e) = client
+                    .send(OwnedMessage::Text(message_json.to_string()))
+                    .and_then(|_| client.flush())
+                {
+                    warning!(
+                        self.logger,
+                        "Error sending {} event to UI {}: {:?}",
+                        topic,
+                        client_id,
+                        e
+                    );
+                }
+            }
+        });
+    }
 }
 
 impl WebSocketSupervisorReal {
-    pub fn new(port: u16, from_ui_message: Recipient<FromUiMessage>) -> WebSocketSupervisorReal {
+    pub fn new(
+        port: u16,
+        from_ui_message: Recipient<FromUiMessage>,
+        ui_disconnect: Recipient<UiDisconnectMessage>,
+        session_token: String,
+    ) -> WebSocketSupervisorReal {
         let inner = Arc::new(Mutex::new(WebSocketSupervisorInner {
             next_client_id: 0,
             from_ui_message,
+            ui_disconnect,
+            session_token,
             client_id_by_socket_addr: HashMap::new(),
             client_by_id: HashMap::new(),
+            authenticated_client_ids: HashSet::new(),
+            subscriptions: HashMap::new(),
         }));
         let logger = Logger::new("WebSocketSupervisor");
         let logger_1 = logger.clone();
@@ -93,8 +148,9 @@ impl WebSocketSupervisorReal {
             .unwrap_or_else(|e| panic!("Could not start UI server at {}: {}", server_address, e));
         let upgrade_tuple_stream = Self::remove_failures(server.incoming(), &logger);
         let inner_clone = inner.clone();
+        let logger_2 = logger.clone();
         let foreach_result = upgrade_tuple_stream.for_each(move |(upgrade, socket_addr)| {
-            Self::handle_upgrade_request(upgrade, socket_addr, inner_clone.clone(), &logger);
+            Self::handle_upgrade_request(upgrade, socket_addr, inner_clone.clone(), &logger_2);
             Ok(())
         });
         tokio::spawn(foreach_result.then(move |result| match result {
@@ -107,7 +163,7 @@ impl WebSocketSupervisorReal {
                 Err(())
             }
         }));
-        WebSocketSupervisorReal { inner }
+        WebSocketSupervisorReal { inner, logger }
     }
 
     fn remove_failures<I, E>(
@@ -139,24 +195,76 @@ impl WebSocketSupervisorReal {
         {
             Self::reject_upgrade_request(upgrade, &logger);
         } else {
-            Self::accept_upgrade_request(upgrade, socket_addr, inner, logger);
+            match Self::authenticate(&upgrade.uri(), &inner) {
+                Err(()) => Self::reject_invalid_session_token(upgrade, socket_addr, logger),
+                Ok(authenticated) => {
+                    Self::accept_upgrade_request(upgrade, socket_addr, inner, authenticated, logger)
+                }
+            }
+        }
+    }
+
+    // A UI that presents no token may only ask questions; one that presents the wrong token is
+    // turned away.
+    fn authenticate(uri: &str, inner: &Arc<Mutex<WebSocketSupervisorInner>>) -> Result<bool, ()> {
+        match Self::presented_session_token(uri) {
+            None => Ok(false),
+            Some(token) => {
+                let locked_inner = inner.lock().expect("WebSocketSupervisor is poisoned");
+                if tokens_match(&locked_inner.session_token, &token) {
+                    Ok(true)
+                } else {
+                    Err(())
+                }
+            }
         }
     }
 
+    fn presented_session_token(uri: &str) -> Option<String> {
+        let query = uri.splitn(2, '?').nth(1)?;
+        query.split('&').find_map(|pair| {
+            let mut key_and_value = pair.splitn(2, '=');
+            if key_and_value.next() == Some("token") {
+                Some(key_and_value.next().unwrap_or("").to_string())
+            } else {
+                None
+            }
+        })
+    }
+
     fn accept_upgrade_request(
         upgrade: WsUpgrade<TcpStream, BytesMut>,
         socket_addr: SocketAddr,
         inner: Arc<Mutex<WebSocketSupervisorInner>>,
+        authenticated: bool,
         logger: &Logger,
     ) {
         let logger_clone = logger.clone();
-        info!(logger_clone, "UI connected at {}", socket_addr);
+        if authenticated {
+            info!(
+                logger_clone,
+                "UI connected at {}",
+                privacy::socket_addr(socket_addr)
+            );
+        } else {
+            info!(
+                logger_clone,
+                "UI connected at {} without a session token; it may only ask questions",
+                privacy::socket_addr(socket_addr)
+            );
+        }
         let upgrade_future =
             upgrade
                 .use_protocol("SubstratumNode-UI")
                 .accept()
                 .map(move |(client, _)| {
-                    Self::handle_connection(client, &inner, &logger_clone, socket_addr);
+                    Self::handle_connection(
+                        client,
+                        &inner,
+                        &logger_clone,
+                        socket_addr,
+                        authenticated,
+                    );
                 });
         tokio::spawn(upgrade_future.then(|result| {
             match result {
@@ -175,11 +283,25 @@ impl WebSocketSupervisorReal {
         tokio::spawn(upgrade.reject().then(|_| ok::<(), ()>(())));
     }
 
+    fn reject_invalid_session_token(
+        upgrade: WsUpgrade<TcpStream, BytesMut>,
+        socket_addr: SocketAddr,
+        logger: &Logger,
+    ) {
+        warning!(
+            logger,
+            "UI at {} presented an invalid session token; rejecting",
+            privacy::socket_addr(socket_addr)
+        );
+        tokio::spawn(upgrade.reject().then(|_| ok::<(), ()>(())));
+    }
+
     fn handle_connection(
         client: Framed<TcpStream, MessageCodec<OwnedMessage>>,
         inner: &Arc<Mutex<WebSocketSupervisorInner>>,
         logger: &Logger,
         socket_addr: SocketAddr,
+        authenticated: bool,
     ) {
         let logger_1 = logger.clone();
         let logger_2 = logger.clone();
@@ -199,6 +321,9 @@ impl WebSocketSupervisorReal {
                 delegate: sync_outgoing,
             }),
         );
+        if authenticated {
+            locked_inner.authenticated_client_ids.insert(client_id);
+        }
         let incoming_future = incoming
             .then(move |result| Self::handle_websocket_errors(result, &logger_2, socket_addr))
             .map(move |owned_message| match owned_message {
@@ -225,7 +350,7 @@ impl WebSocketSupervisorReal {
         socket_addr: SocketAddr,
         message: &str,
     ) -> FutureResult<(), ()> {
-        let locked_inner = inner_arc.lock().expect("WebSocketSupervisor is poisoned");
+        let mut locked_inner = inner_arc.lock().expect("WebSocketSupervisor is poisoned");
         match locked_inner.client_id_by_socket_addr.get(&socket_addr) {
             None => {
                 warning!(
@@ -235,10 +360,22 @@ impl WebSocketSupervisorReal {
                 err::<(), ()>(()) // end the stream
             }
             Some(client_id_ref) => {
+                let client_id = *client_id_ref;
+                if !locked_inner.authenticated_client_ids.contains(&client_id)
+                    && Self::refuse_order(
+                        &mut locked_inner,
+                        client_id,
+                        socket_addr,
+                        message,
+                        logger,
+                    )
+                {
+                    return ok::<(), ()>(());
+                }
                 locked_inner
                     .from_ui_message
                     .try_send(FromUiMessage {
-                        client_id: *client_id_ref,
+                        client_id,
                         json: String::from(message),
                     })
                     .expect("UiGateway is dead");
@@ -247,12 +384,70 @@ impl WebSocketSupervisorReal {
         }
     }
 
+    // Anything the UiGateway can't understand is harmless, so only recognized orders are refused.
+    // UIs that speak the versioned protocol are told why; older ones just don't hear back.
+    fn refuse_order(
+        locked_inner: &mut WebSocketSupervisorInner,
+        client_id: u64,
+        socket_addr: SocketAddr,
+        message: &str,
+        logger: &Logger,
+    ) -> bool {
+        let converter = UiTrafficConverterReal::new();
+        let request = match converter.unmarshal(message) {
+            Ok(request) => request,
+            Err(_) => return false,
+        };
+        if !request.message().requires_authentication() {
+            return false;
+        }
+        warning!(
+            logger,
+            "UI at {} has not presented the session token; ignoring its order",
+            privacy::socket_addr(socket_addr)
+        );
+        if let UiRequest::Versioned {
+            context_id,
+            message: ui_message,
+        } = request
+        {
+            let refusal = converter
+                .marshal_error(UiRequestError {
+                    opcode: opcode_for(&ui_message).to_string(),
+                    context_id,
+                    error: UiError::new(
+                        UNAUTHENTICATED_ERROR,
+                        "Only UIs that present the session token may give orders",
+                    ),
+                })
+                .expect("Internal error: failed to marshal UI error");
+            if let Some(client) = locked_inner.client_by_id.get_mut(&client_id) {
+                if let Err(e) = client
+                    .send(OwnedMessage::Text(refusal))
+                    .and_then(|_| client.flush())
+                {
+                    warning!(
+                        logger,
+                        "Error refusing order from UI at {}: {:?}",
+                        privacy::socket_addr(socket_addr),
+                        e
+                    );
+                }
+            }
+        }
+        true
+    }
+
     fn handle_close_message(
         inner_arc: &Arc<Mutex<WebSocketSupervisorInner>>,
         logger: &Logger,
         socket_addr: SocketAddr,
     ) -> FutureResult<(), ()> {
-        info!(logger, "UI at {} disconnected", socket_addr);
+        info!(
+            logger,
+            "UI at {} disconnected",
+            privacy::socket_addr(socket_addr)
+        );
         let mut locked_inner = inner_arc.lock().expect("WebSocketSupervisor is poisoned");
         let client_id = match locked_inner.client_id_by_socket_addr.remove(&socket_addr) {
             None => {
@@ -260,7 +455,13 @@ impl WebSocketSupervisorReal {
             }
             Some(client_id) => client_id,
         };
+        locked_inner.authenticated_client_ids.remove(&client_id);
+        locked_inner.subscriptions.remove(&client_id);
         Self::close_connection(&mut locked_inner, client_id, socket_addr, &logger);
+        locked_inner
+            .ui_disconnect
+            .try_send(UiDisconnectMessage { client_id })
+            .expect("UiGateway is dead");
 
         err::<(), ()>(()) // end the stream
     }
@@ -272,7 +473,9 @@ impl WebSocketSupervisorReal {
     ) -> FutureResult<(), ()> {
         info!(
             logger,
-            "UI at {} sent unexpected {} message; ignoring", socket_addr, message_type
+            "UI at {} sent unexpected {} message; ignoring",
+            privacy::socket_addr(socket_addr),
+            message_type
         );
         ok::<(), ()>(())
     }
@@ -287,7 +490,7 @@ impl WebSocketSupervisorReal {
                 warning!(
                     logger,
                     "UI at {} violated protocol: terminating",
-                    socket_addr
+                    privacy::socket_addr(socket_addr)
                 );
                 err::<I, ()>(())
             }
@@ -309,7 +512,7 @@ impl WebSocketSupervisorReal {
             Err(e) => warning!(
                 logger,
                 "Error acknowledging connection closure from UI at {}: {:?}",
-                socket_addr,
+                privacy::socket_addr(socket_addr),
                 e
             ),
             Ok(_) => client
@@ -429,13 +632,45 @@ mod tests {
         }
     }
 
+    const SESSION_TOKEN: &str = "0123456789abcdef";
+
     fn make_client(port: u16, protocol: &str) -> Result<Client<TcpStream>, WebSocketError> {
-        ClientBuilder::new(format!("ws://127.0.0.1:{}", port).as_str())
+        make_client_at(port, "", protocol)
+    }
+
+    fn make_client_at(
+        port: u16,
+        path: &str,
+        protocol: &str,
+    ) -> Result<Client<TcpStream>, WebSocketError> {
+        ClientBuilder::new(format!("ws://127.0.0.1:{}{}", port, path).as_str())
             .expect("ClientBuilder could not be built")
             .add_protocol(protocol)
             .connect_insecure()
     }
 
+    fn start_subject(port: u16, ui_gateway: Recorder, system_name: &'static str) {
+        thread::spawn(move || {
+            let system = System::new(system_name);
+            let from_ui_message = {
+                let addr: Addr<Recorder> = ui_gateway.start();
+                addr.recipient::<FromUiMessage>()
+            };
+            let ui_disconnect = Recorder::new().start().recipient::<UiDisconnectMessage>();
+            let subject = lazy(move || {
+                let _subject = WebSocketSupervisorReal::new(
+                    port,
+                    from_ui_message,
+                    ui_disconnect,
+                    SESSION_TOKEN.to_string(),
+                );
+                Ok(())
+            });
+            actix::spawn(subject);
+            system.run();
+        });
+    }
+
     fn wait_for_client(port: u16, protocol: &str) -> Client<TcpStream> {
         let mut one_client_opt: Option<Client<TcpStream>> = None;
         wait_for(None, None, || match make_client(port, protocol) {
@@ -475,8 +710,14 @@ mod tests {
                 let addr: Addr<Recorder> = ui_gateway.start();
                 addr.recipient::<FromUiMessage>()
             };
+            let ui_disconnect = Recorder::new().start().recipient::<UiDisconnectMessage>();
             let subject = lazy(move || {
-                let _subject = WebSocketSupervisorReal::new(port, from_ui_message);
+                let _subject = WebSocketSupervisorReal::new(
+                    port,
+                    from_ui_message,
+                    ui_disconnect,
+                    String::new(),
+                );
                 Ok(())
             });
             actix::spawn(subject);
@@ -500,8 +741,14 @@ mod tests {
                 let addr: Addr<Recorder> = ui_gateway.start();
                 addr.recipient::<FromUiMessage>()
             };
+            let ui_disconnect = Recorder::new().start().recipient::<UiDisconnectMessage>();
             let subject = lazy(move || {
-                let _subject = WebSocketSupervisorReal::new(port, from_ui_message);
+                let _subject = WebSocketSupervisorReal::new(
+                    port,
+                    from_ui_message,
+                    ui_disconnect,
+                    String::new(),
+                );
                 Ok(())
             });
             actix::spawn(subject);
@@ -518,6 +765,148 @@ mod tests {
         );
     }
 
+    #[test]
+    fn rejects_connection_attempt_with_invalid_session_token() {
+        init_test_logging();
+        let port = find_free_port();
+        let (ui_gateway, _, _) = make_recorder();
+        start_subject(
+            port,
+            ui_gateway,
+            "rejects_connection_attempt_with_invalid_session_token",
+        );
+        wait_for_server(port);
+
+        make_client_at(port, "/?token=booga", "SubstratumNode-UI")
+            .err()
+            .unwrap();
+        make_client_at(port, "/?token=", "SubstratumNode-UI")
+            .err()
+            .unwrap();
+
+        let tlh = TestLogHandler::new();
+        tlh.await_log_matching(
+            "UI at 127\\.0\\.0\\.1:\\d+ presented an invalid session token; rejecting",
+            1000,
+        );
+    }
+
+    #[test]
+    fn client_without_session_token_may_ask_questions_but_not_give_orders() {
+        init_test_logging();
+        let port = find_free_port();
+        let (ui_gateway, ui_gateway_awaiter, ui_gateway_recording_arc) = make_recorder();
+        start_subject(
+            port,
+            ui_gateway,
+            "client_without_session_token_may_ask_questions_but_not_give_orders",
+        );
+        let mut client = wait_for_client(port, "SubstratumNode-UI");
+        let converter = UiTrafficConverterReal::new();
+
+        vec![
+            UiMessage::ShutdownMessage,
+            UiMessage::SetWalletPassword("password".to_string()),
+            UiMessage::SetGasPrice("1".to_string()),
+            UiMessage::GetNodeDescriptor,
+        ]
+        .into_iter()
+        .for_each(|ui_message| {
+            client
+                .send_message(&Message::text(converter.marshal(ui_message).unwrap()))
+                .unwrap()
+        });
+
+        ui_gateway_awaiter.await_message_count(1);
+        let ui_gateway_recording = ui_gateway_recording_arc.lock().unwrap();
+        assert_eq!(
+            ui_gateway_recording.get_record::<FromUiMessage>(0).json,
+            "\"GetNodeDescriptor\""
+        );
+        assert_eq!(ui_gateway_recording.len(), 1);
+        TestLogHandler::new().exists_log_matching(
+            "UI at 127\\.0\\.0\\.1:\\d+ has not presented the session token; ignoring its order",
+        );
+    }
+
+    #[test]
+    fn versioned_orders_without_session_token_are_refused_with_errors() {
+        let port = find_free_port();
+        let (ui_gateway, _, ui_gateway_recording_arc) = make_recorder();
+        start_subject(
+            port,
+            ui_gateway,
+            "versioned_orders_without_session_token_are_refused_with_errors",
+        );
+        let mut client = wait_for_client(port, "SubstratumNode-UI");
+
+        client
+            .send_message(&Message::text(
+                r#"{"version":1,"opcode":"shutdown","contextId":1234}"#,
+            ))
+            .unwrap();
+
+        let response = client.recv_message().unwrap();
+        assert_eq!(
+            response,
+            OwnedMessage::Text(
+                r#"{"version":1,"opcode":"shutdown","contextId":1234,"error":{"code":5,"message":"Only UIs that present the session token may give orders"}}"#
+                    .to_string()
+            )
+        );
+        assert_eq!(ui_gateway_recording_arc.lock().unwrap().len(), 0);
+    }
+
+    #[test]
+    fn client_with_session_token_may_give_orders() {
+        let port = find_free_port();
+        let (ui_gateway, ui_gateway_awaiter, ui_gateway_recording_arc) = make_recorder();
+        start_subject(
+            port,
+            ui_gateway,
+            "client_with_session_token_may_give_orders",
+        );
+        wait_for_server(port);
+        let mut client = make_client_at(
+            port,
+            &format!("/?token={}", SESSION_TOKEN),
+            "SubstratumNode-UI",
+        )
+        .unwrap();
+
+        client
+            .send_message(&Message::text(
+                UiTrafficConverterReal::new()
+                    .marshal(UiMessage::ShutdownMessage)
+                    .unwrap(),
+            ))
+            .unwrap();
+
+        ui_gateway_awaiter.await_message_count(1);
+        let ui_gateway_recording = ui_gateway_recording_arc.lock().unwrap();
+        assert_eq!(
+            ui_gateway_recording.get_record::<FromUiMessage>(0).json,
+            "\"ShutdownMessage\""
+        );
+    }
+
+    #[test]
+    fn session_token_is_found_among_query_parameters() {
+        assert_eq!(
+            WebSocketSupervisorReal::presented_session_token("/?other=1&token=abc"),
+            Some("abc".to_string())
+        );
+        assert_eq!(
+            WebSocketSupervisorReal::presented_session_token("/?token"),
+            Some("".to_string())
+        );
+        assert_eq!(
+            WebSocketSupervisorReal::presented_session_token("/?tokens=abc"),
+            None
+        );
+        assert_eq!(WebSocketSupervisorReal::presented_session_token("/"), None);
+    }
+
     #[test]
     fn logs_unexpected_binary_ping_pong_websocket_messages() {
         init_test_logging();
@@ -530,8 +919,14 @@ mod tests {
                 let addr: Addr<Recorder> = ui_gateway.start();
                 addr.recipient::<FromUiMessage>()
             };
+            let ui_disconnect = Recorder::new().start().recipient::<UiDisconnectMessage>();
             let subject = lazy(move || {
-                let _subject = WebSocketSupervisorReal::new(port, from_ui_message);
+                let _subject = WebSocketSupervisorReal::new(
+                    port,
+                    from_ui_message,
+                    ui_disconnect,
+                    String::new(),
+                );
                 Ok(())
             });
             actix::spawn(subject);
@@ -577,8 +972,14 @@ mod tests {
                 let addr: Addr<Recorder> = ui_gateway.start();
                 addr.recipient::<FromUiMessage>()
             };
+            let ui_disconnect = Recorder::new().start().recipient::<UiDisconnectMessage>();
             let subject = lazy(move || {
-                let _subject = WebSocketSupervisorReal::new(port, from_ui_message);
+                let _subject = WebSocketSupervisorReal::new(
+                    port,
+                    from_ui_message,
+                    ui_disconnect,
+                    String::new(),
+                );
                 Ok(())
             });
             actix::spawn(subject);
@@ -637,8 +1038,14 @@ mod tests {
                 let addr: Addr<Recorder> = ui_gateway.start();
                 addr.recipient::<FromUiMessage>()
             };
+            let ui_disconnect = Recorder::new().start().recipient::<UiDisconnectMessage>();
             let subject = lazy(move || {
-                let _subject = WebSocketSupervisorReal::new(port, from_ui_message);
+                let _subject = WebSocketSupervisorReal::new(
+                    port,
+                    from_ui_message,
+                    ui_disconnect,
+                    String::new(),
+                );
                 Ok(())
             });
             actix::spawn(subject);
@@ -674,8 +1081,14 @@ mod tests {
                 let addr: Addr<Recorder> = ui_gateway.start();
                 addr.recipient::<FromUiMessage>()
             };
+            let ui_disconnect = Recorder::new().start().recipient::<UiDisconnectMessage>();
             let subject = lazy(move || {
-                let _subject = WebSocketSupervisorReal::new(port, from_ui_message);
+                let _subject = WebSocketSupervisorReal::new(
+                    port,
+                    from_ui_message,
+                    ui_disconnect,
+                    String::new(),
+                );
                 Ok(())
             });
             actix::spawn(subject);
@@ -704,10 +1117,16 @@ mod tests {
         let port = find_free_port();
         let (ui_gateway, _, _) = make_recorder();
         let ui_gateway_recipient = ui_gateway.start().recipient::<FromUiMessage>();
+        let ui_disconnect_recipient = Recorder::new().start().recipient::<UiDisconnectMessage>();
         let system = System::new("send_dot_graph_response_sends_it_to_the_client");
         let mut client_id = 0;
         let lazy_future = lazy(move || {
-            let subject = WebSocketSupervisorReal::new(port, ui_gateway_recipient);
+            let subject = WebSocketSupervisorReal::new(
+                port,
+                ui_gateway_recipient,
+                ui_disconnect_recipient,
+                String::new(),
+            );
             let mut mock_client = ClientWrapperMock::new();
             mock_client.send_results.push(Ok(()));
             mock_client.flush_results.push(Ok(()));
@@ -746,12 +1165,20 @@ mod tests {
 
         thread::spawn(move || {
             let system = System::new("once_a_client_sends_a_close_no_more_data_is_accepted");
-            let from_ui_message = {
+            let (from_ui_message, ui_disconnect) = {
                 let addr: Addr<Recorder> = ui_gateway.start();
-                addr.recipient::<FromUiMessage>()
+                (
+                    addr.clone().recipient::<FromUiMessage>(),
+                    addr.recipient::<UiDisconnectMessage>(),
+                )
             };
             let subject = lazy(move || {
-                let _subject = WebSocketSupervisorReal::new(port, from_ui_message);
+                let _subject = WebSocketSupervisorReal::new(
+                    port,
+                    from_ui_message,
+                    ui_disconnect,
+                    String::new(),
+                );
                 Ok(())
             });
             actix::spawn(subject);
@@ -766,7 +1193,7 @@ mod tests {
 
         client.shutdown().unwrap();
         thread::sleep(Duration::from_secs(1));
-        ui_gateway_awaiter.await_message_count(1);
+        ui_gateway_awaiter.await_message_count(2);
         let ui_gateway_recording = ui_gateway_recording_arc.lock().unwrap();
         assert_eq!(
             ui_gateway_recording.get_record::<FromUiMessage>(0),
@@ -775,7 +1202,11 @@ mod tests {
                 json: String::from("One")
             }
         );
-        assert_eq!(ui_gateway_recording.len(), 1);
+        assert_eq!(
+            ui_gateway_recording.get_record::<UiDisconnectMessage>(1),
+            &UiDisconnectMessage { client_id: 0 }
+        );
+        assert_eq!(ui_gateway_recording.len(), 2);
     }
 
     #[test]
@@ -789,8 +1220,14 @@ mod tests {
                 let addr: Addr<Recorder> = ui_gateway.start();
                 addr.recipient::<FromUiMessage>()
             };
+            let ui_disconnect = Recorder::new().start().recipient::<UiDisconnectMessage>();
             let subject = lazy(move || {
-                let _subject = WebSocketSupervisorReal::new(port, from_ui_message);
+                let _subject = WebSocketSupervisorReal::new(
+                    port,
+                    from_ui_message,
+                    ui_disconnect,
+                    String::new(),
+                );
                 Ok(())
             });
             actix::spawn(subject);
@@ -823,10 +1260,16 @@ mod tests {
         let port = find_free_port();
         let (ui_gateway, _, _) = make_recorder();
         let ui_gateway_recipient = ui_gateway.start().recipient::<FromUiMessage>();
+        let ui_disconnect_recipient = Recorder::new().start().recipient::<UiDisconnectMessage>();
         let system = System::new("send_sends_a_message_to_the_client");
         let mut client_id = 0;
         let lazy_future = lazy(move || {
-            let subject = WebSocketSupervisorReal::new(port, ui_gateway_recipient);
+            let subject = WebSocketSupervisorReal::new(
+                port,
+                ui_gateway_recipient,
+                ui_disconnect_recipient,
+                String::new(),
+            );
             let mut mock_client = ClientWrapperMock::new();
             mock_client.send_results.push(Ok(()));
             mock_client.flush_results.push(Ok(()));
@@ -853,16 +1296,85 @@ mod tests {
         system.run();
     }
 
+    #[test]
+    fn broadcast_sends_events_only_to_subscribers_and_survives_failures() {
+        init_test_logging();
+        let port = find_free_port();
+        let (ui_gateway, _, _) = make_recorder();
+        let ui_gateway_recipient = ui_gateway.start().recipient::<FromUiMessage>();
+        let ui_disconnect_recipient = Recorder::new().start().recipient::<UiDisconnectMessage>();
+        let system =
+            System::new("broadcast_sends_events_only_to_subscribers_and_survives_failures");
+        let lazy_future = lazy(move || {
+            let subject = WebSocketSupervisorReal::new(
+                port,
+                ui_gateway_recipient,
+                ui_disconnect_recipient,
+                String::new(),
+            );
+            let mut subscriber = ClientWrapperMock::new();
+            subscriber.send_results.push(Ok(()));
+            subscriber.flush_results.push(Ok(()));
+            let subscriber_id = subject.inject_mock_client(subscriber);
+            let other_topic_id = subject.inject_mock_client(ClientWrapperMock::new());
+            let mut broken = ClientWrapperMock::new();
+            broken
+                .send_results
+                .push(Err(WebSocketError::NoDataAvailable));
+            let broken_id = subject.inject_mock_client(broken);
+            let unsubscribed_id = subject.inject_mock_client(ClientWrapperMock::new());
+            subject.subscribe(
+                subscriber_id,
+                vec!["routeFailed".to_string(), "walletBanned".to_string()],
+            );
+            subject.subscribe(other_topic_id, vec!["paymentSent".to_string()]);
+            subject.subscribe(broken_id, vec!["walletBanned".to_string()]);
+            subject.subscribe(unsubscribed_id, vec!["walletBanned".to_string()]);
+            subject.subscribe(unsubscribed_id, vec![]);
+
+            subject.broadcast("walletBanned", "event JSON");
+
+            let sent_to = |client_id: u64| {
+                subject
+                    .get_mock_client(client_id)
+                    .send_params
+                    .lock()
+                    .unwrap()
+                    .clone()
+            };
+            assert_eq!(
+                sent_to(subscriber_id),
+                vec![OwnedMessage::Text("event JSON".to_string())]
+            );
+            assert_eq!(sent_to(other_topic_id), vec![]);
+            assert_eq!(sent_to(unsubscribed_id), vec![]);
+            TestLogHandler::new().exists_log_containing(&format!(
+                "Error sending walletBanned event to UI {}: NoDataAvailable",
+                broken_id
+            ));
+            Ok(())
+        });
+        actix::spawn(lazy_future);
+        System::current().stop();
+        system.run();
+    }
+
     #[test]
     #[should_panic(expected = "Flush error: NoDataAvailable")]
     fn send_tries_to_send_message_and_panics_on_flush() {
         let port = find_free_port();
         let (ui_gateway, _, _) = make_recorder();
         let ui_gateway_recipient = ui_gateway.start().recipient::<FromUiMessage>();
+        let ui_disconnect_recipient = Recorder::new().start().recipient::<UiDisconnectMessage>();
         let system = System::new("receive_sends_a_message_and_errors_on_flush");
         let mut client_id = 0;
         let lazy_future = lazy(move || {
-            let subject = WebSocketSupervisorReal::new(port, ui_gateway_recipient);
+            let subject = WebSocketSupervisorReal::new(
+                port,
+                ui_gateway_recipient,
+                ui_disconnect_recipient,
+                String::new(),
+            );
             let mut mock_client = ClientWrapperMock::new();
             mock_client.send_results.push(Ok(()));
             mock_client
@@ -886,10 +1398,16 @@ mod tests {
         let port = find_free_port();
         let (ui_gateway, _, _) = make_recorder();
         let ui_gateway_recipient = ui_gateway.start().recipient::<FromUiMessage>();
+        let ui_disconnect_recipient = Recorder::new().start().recipient::<UiDisconnectMessage>();
         let system = System::new("receive_sends_a_message_and_errors_on_send");
         let mut client_id = 0;
         let lazy_future = lazy(move || {
-            let subject = WebSocketSupervisorReal::new(port, ui_gateway_recipient);
+            let subject = WebSocketSupervisorReal::new(
+                port,
+                ui_gateway_recipient,
+                ui_disconnect_recipient,
+                String::new(),
+            );
             let mut mock_client = ClientWrapperMock::new();
             mock_client
                 .send_results
@@ -912,9 +1430,15 @@ mod tests {
         let port = find_free_port();
         let (ui_gateway, _, _) = make_recorder();
         let ui_gateway_recipient = ui_gateway.start().recipient::<FromUiMessage>();
+        let ui_disconnect_recipient = Recorder::new().start().recipient::<UiDisconnectMessage>();
         let system = System::new("receive_sends_a_message_and_errors_on_send");
         let lazy_future = lazy(move || {
-            let subject = WebSocketSupervisorReal::new(port, ui_gateway_recipient);
+            let subject = WebSocketSupervisorReal::new(
+                port,
+                ui_gateway_recipient,
+                ui_disconnect_recipient,
+                String::new(),
+            );
 
             let json_string = "{totally: 'valid'}";
 
@@ -6,13 +6,21 @@ use futures::future::*;
 use node_lib::sub_lib::ui_gateway::{UiMessage, DEFAULT_UI_PORT};
 use node_lib::sub_lib::utils::localhost;
 use node_lib::test_utils::assert_matches;
+use node_lib::ui_gateway::session_token::UI_SESSION_TOKEN_FILE;
 use node_lib::ui_gateway::ui_traffic_converter::{UiTrafficConverter, UiTrafficConverterReal};
 use std::time::Duration;
 use tokio::prelude::*;
 use tokio::runtime::Runtime;
+use utils::SubstratumNode;
 use websocket::ClientBuilder;
 use websocket::OwnedMessage;
 
+fn authenticated_ui_url() -> String {
+    let token =
+        std::fs::read_to_string(SubstratumNode::data_dir().join(UI_SESSION_TOKEN_FILE)).unwrap();
+    format!("ws://{}:{}/?token={}", localhost(), DEFAULT_UI_PORT, token)
+}
+
 #[test]
 fn ui_gateway_message_integration() {
     fdlimit::raise_fd_limit();
@@ -41,12 +49,11 @@ fn ui_gateway_message_integration() {
         .marshal(UiMessage::ShutdownMessage)
         .expect("Couldn't marshal ShutdownMessage");
 
-    let shutdown_client =
-        ClientBuilder::new(format!("ws://{}:{}", localhost(), DEFAULT_UI_PORT).as_str())
-            .expect("Couldn't create second ClientBuilder")
-            .add_protocol("SubstratumNode-UI")
-            .async_connect_insecure()
-            .and_then(|(s, _)| s.send(OwnedMessage::Text(shutdown_msg)));
+    let shutdown_client = ClientBuilder::new(authenticated_ui_url().as_str())
+        .expect("Couldn't create second ClientBuilder")
+        .add_protocol("SubstratumNode-UI")
+        .async_connect_insecure()
+        .and_then(|(s, _)| s.send(OwnedMessage::Text(shutdown_msg)));
 
     let mut rt = Runtime::new().expect("Couldn't create Runtime");
     rt.block_on(descriptor_client)
@@ -90,12 +97,11 @@ fn ui_gateway_dot_graph_message_integration() {
 
     let shutdown_msg = converter.marshal(UiMessage::ShutdownMessage).unwrap();
 
-    let shutdown_client =
-        ClientBuilder::new(format!("ws://{}:{}", localhost(), DEFAULT_UI_PORT).as_str())
-            .unwrap()
-            .add_protocol("SubstratumNode-UI")
-            .async_connect_insecure()
-            .and_then(|(s, _)| s.send(OwnedMessage::Text(shutdown_msg)));
+    let shutdown_client = ClientBuilder::new(authenticated_ui_url().as_str())
+        .unwrap()
+        .add_protocol("SubstratumNode-UI")
+        .async_connect_insecure()
+        .and_then(|(s, _)| s.send(OwnedMessage::Text(shutdown_msg)));
 
     let mut rt = Runtime::new().unwrap();
     rt.block_on(digraph_client).unwrap();